@@ -0,0 +1,30 @@
+//! Times encoding and decoding a full raft entry of records in each codec,
+//! the apply path's share of the work. Run with `cargo bench --bench codec`.
+
+use std::time::Instant;
+
+use distributed_analytics_system::metrics::{
+    codec::{decode, RaftCodec},
+    Labels, MetricOperation,
+};
+
+const ROUND_TRIPS: u32 = 20;
+
+fn main() {
+    let batch: Vec<MetricOperation> = (0..10_000)
+        .map(|i| {
+            let labels = Labels::from([("region".into(), "eu-west-1".into())]);
+            MetricOperation::record(format!("service_{}_request_latency_seconds", i % 50), i as f64 * 0.25, 1_700_000_000 + i as i64, labels)
+        })
+        .collect();
+
+    for codec in [RaftCodec::Json, RaftCodec::Bincode, RaftCodec::Protobuf] {
+        let start = Instant::now();
+        for _ in 0..ROUND_TRIPS {
+            let data = codec.encode(&batch).unwrap();
+            assert_eq!(decode(&data).unwrap().len(), batch.len());
+        }
+        let size = codec.encode(&batch).unwrap().len();
+        println!("{:<10} {:>9} bytes {:>9.1?} per round trip", format!("{:?}", codec), size, start.elapsed() / ROUND_TRIPS);
+    }
+}
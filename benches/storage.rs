@@ -0,0 +1,70 @@
+//! Compares preparing the hot read and write statements on every call with
+//! reusing them from the connection's statement cache, then times the DuckDB
+//! backend end to end. Run with `cargo bench --bench storage`.
+
+use std::time::{Duration, Instant};
+
+use distributed_analytics_system::metrics::{
+    migrations::run_migrations,
+    storage::{DuckDbBackend, MetricStorageBackend},
+    Labels, MetricAggregate, MetricPoint, Summation,
+};
+use duckdb::{params, Connection, OptionalExt, Statement};
+
+const CALLS: u64 = 20_000;
+
+const INSERT: &str = "INSERT INTO metrics (name, value, timestamp, seq) VALUES (?, ?, ?, ?)";
+const LATEST: &str = "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp > ? \
+                      ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT 1";
+
+fn connection() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    run_migrations(&mut conn).unwrap();
+    conn
+}
+
+fn run(label: &str, cached: bool) -> Duration {
+    let conn = connection();
+    let start = Instant::now();
+    for seq in 1..=CALLS {
+        let name = format!("metric-{}", seq % 100);
+        let write = |stmt: &mut Statement| stmt.execute(params![name, seq as f64, seq as i64, seq]).unwrap();
+        let read = |stmt: &mut Statement| {
+            stmt.query_row(params![name, 0], |row| row.get::<_, f64>(0)).optional().unwrap()
+        };
+        let latest = if cached {
+            write(&mut conn.prepare_cached(INSERT).unwrap());
+            read(&mut conn.prepare_cached(LATEST).unwrap())
+        } else {
+            write(&mut conn.prepare(INSERT).unwrap());
+            read(&mut conn.prepare(LATEST).unwrap())
+        };
+        assert_eq!(latest, Some(seq as f64));
+    }
+    let elapsed = start.elapsed();
+    println!("{:<24} {:>8.1?} ({:.1?} per write and read)", label, elapsed, elapsed / CALLS as u32);
+    elapsed
+}
+
+async fn run_backend() {
+    let backend = DuckDbBackend::open_in_memory().unwrap();
+    let mut aggregate = MetricAggregate::default();
+    let start = Instant::now();
+    for seq in 1..=CALLS {
+        let sample = MetricPoint { value: seq as f64, timestamp: seq as i64 };
+        aggregate.add(sample.value, Summation::Naive);
+        backend.insert_sample("cpu", sample, &Labels::new(), seq, Some(&aggregate)).await.unwrap();
+        backend.latest("cpu", 0).await.unwrap();
+        backend.aggregate("cpu").await.unwrap();
+    }
+    let elapsed = start.elapsed();
+    println!("{:<24} {:>8.1?} ({:.1?} per write and two reads)", "backend", elapsed, elapsed / CALLS as u32);
+}
+
+fn main() {
+    let prepared = run("prepare per call", false);
+    let cached = run("prepare_cached", true);
+    println!("statement cache speedup: {:.2}x", prepared.as_secs_f64() / cached.as_secs_f64());
+
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(run_backend());
+}
@@ -1,5 +1,5 @@
 use std::process::Command;
-use tonic_build;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Tell Cargo to rerun this if the proto file changes
@@ -13,6 +13,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             &["src/proto/metrics.proto"],
             &["src/proto"], // The directory containing your .proto files
         )?;
-    
+
+    embed_build_info();
     Ok(())
 }
+
+/// Sets the commit and build time served at `/info`, each "unknown" when
+/// it cannot be read, e.g. when building outside a git checkout.
+fn embed_build_info() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RAFTMETRICS_GIT_COMMIT={}", commit);
+    // A new commit moves the branch HEAD points at, not HEAD itself
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(branch) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=.git/{}", branch);
+        }
+    }
+
+    // Honour SOURCE_DATE_EPOCH so reproducible builds embed a fixed time
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let build_time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|elapsed| elapsed.as_secs().to_string()))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RAFTMETRICS_BUILD_TIME={}", build_time);
+}
@@ -0,0 +1,172 @@
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request, State},
+    http::{header::AUTHORIZATION, HeaderMap, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{
+    api::{control::ControlHandle, middleware::constant_time_eq, read_only::READ_POSTS},
+    metrics::routing::ACL_DENIED_TOTAL,
+    RaftMetricsError, Result,
+};
+
+/// What an API key may do with the metrics under a prefix. Writing implies
+/// reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    fn as_str(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+/// Metric name prefixes and the API keys allowed to read or write under
+/// each, from the JSON file at `ACL_PATH`:
+///
+/// ```json
+/// {"acme.": {"acme-ingest-key": "write", "dashboard-key": "read"}}
+/// ```
+///
+/// A name is governed by the longest prefix it starts with. Names under no
+/// prefix stay open to every client, with or without a key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Acl {
+    /// Longest prefix first.
+    rules: Vec<(String, BTreeMap<String, Access>)>,
+}
+
+impl Acl {
+    /// Parses an ACL file's contents.
+    pub fn parse(json: &str) -> std::result::Result<Self, String> {
+        let rules: BTreeMap<String, BTreeMap<String, Access>> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if rules.contains_key("") {
+            return Err("an empty prefix would cover every metric; list the prefixes to protect".to_string());
+        }
+        let mut rules: Vec<_> = rules.into_iter().collect();
+        rules.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Ok(Self { rules })
+    }
+
+    /// Reads the ACL file at `path`; no path means no ACLs.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+            .and_then(|json| Self::parse(&json).map_err(|e| format!("{}: {}", path.display(), e)))
+            .map_err(|e| RaftMetricsError::InvalidConfig(vec![e]))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Refuses `access` to `name` for the client presenting `key`:
+    /// [`RaftMetricsError::Unauthorized`] without a key where one is
+    /// needed, [`RaftMetricsError::Forbidden`] for a key not granted it.
+    pub fn check(&self, key: Option<&str>, name: &str, access: Access) -> Result<()> {
+        self.decide(key, name, access).inspect_err(|_| ACL_DENIED_TOTAL.with_label_values(&[access.as_str()]).inc())
+    }
+
+    /// Refuses `access` to any name starting with `prefix`, as
+    /// [`Self::check`] would for each: the rule governing `prefix` itself
+    /// and every rule for a longer prefix under it.
+    pub fn check_prefix(&self, key: Option<&str>, prefix: &str, access: Access) -> Result<()> {
+        let nested = self.rules.iter().map(|(rule, _)| rule.as_str()).filter(|rule| rule.starts_with(prefix));
+        std::iter::once(prefix).chain(nested).try_for_each(|name| self.check(key, name, access))
+    }
+
+    /// Whether [`Self::check`] would allow `access`, without counting a
+    /// denial; for leaving names out of listings.
+    pub fn permits(&self, key: Option<&str>, name: &str, access: Access) -> bool {
+        self.decide(key, name, access).is_ok()
+    }
+
+    /// Whether [`Self::check_prefix`] would allow `access`, without
+    /// counting a denial.
+    pub fn permits_prefix(&self, key: Option<&str>, prefix: &str, access: Access) -> bool {
+        let nested = self.rules.iter().map(|(rule, _)| rule.as_str()).filter(|rule| rule.starts_with(prefix));
+        std::iter::once(prefix).chain(nested).all(|name| self.permits(key, name, access))
+    }
+
+    fn decide(&self, key: Option<&str>, name: &str, access: Access) -> Result<()> {
+        let Some((prefix, grants)) = self.rules.iter().find(|(prefix, _)| name.starts_with(prefix.as_str())) else {
+            return Ok(());
+        };
+        let Some(key) = key else {
+            return Err(RaftMetricsError::Unauthorized(format!("metrics under '{}' need an API key", prefix)));
+        };
+        let granted = grants
+            .iter()
+            .filter(|(granted, _)| constant_time_eq(granted.as_bytes(), key.as_bytes()))
+            .map(|(_, access)| *access)
+            .next();
+        match granted {
+            Some(granted) if granted >= access => Ok(()),
+            _ => Err(RaftMetricsError::Forbidden(format!("this key may not {} metrics under '{}'", access.as_str(), prefix))),
+        }
+    }
+}
+
+/// The API key a request presents as `Authorization: Bearer <key>`.
+pub fn api_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Checks requests to routes naming one metric in their path against the
+/// current ACLs, as a read for `GET` and the read-only `POST`s and a write
+/// otherwise. Routes taking names in their body check them in the handler.
+/// Installed with `route_layer` so the `MatchedPath` is known.
+pub async fn check_metric_acl(State(handle): State<ControlHandle>, params: Option<RawPathParams>, request: Request, next: Next) -> Response {
+    let Some((_, name)) = params.as_ref().and_then(|params| params.iter().find(|(param, _)| *param == "name")) else {
+        return next.run(request).await;
+    };
+    let read = matches!(*request.method(), Method::GET | Method::HEAD)
+        || request.extensions().get::<MatchedPath>().is_some_and(|path| READ_POSTS.contains(&path.as_str()));
+    let access = if read { Access::Read } else { Access::Write };
+    match handle.snapshot().acl.check(api_key(request.headers()), name, access) {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_decides() {
+        let acl = Acl::parse(r#"{"acme.": {"ingest": "write", "dash": "read"}, "acme.billing.": {"billing": "write"}}"#).unwrap();
+        assert!(acl.check(Some("ingest"), "acme.cpu", Access::Write).is_ok());
+        assert!(acl.check(Some("dash"), "acme.cpu", Access::Read).is_ok());
+        assert_eq!(acl.check(Some("dash"), "acme.cpu", Access::Write).unwrap_err().code(), crate::error::ErrorCode::Forbidden);
+        assert_eq!(acl.check(None, "acme.cpu", Access::Read).unwrap_err().code(), crate::error::ErrorCode::Unauthorized);
+        // The longer prefix replaces the shorter one's grants
+        assert!(acl.check(Some("ingest"), "acme.billing.total", Access::Read).is_err());
+        assert!(acl.check(Some("billing"), "acme.billing.total", Access::Write).is_ok());
+        assert!(acl.check(None, "other", Access::Write).is_ok());
+
+        // A prefix answers for every rule it covers, however it is spelled
+        assert!(acl.check_prefix(Some("dash"), "acme", Access::Read).is_err());
+        assert!(acl.check_prefix(Some("billing"), "acme.billing.", Access::Read).is_ok());
+        assert!(acl.check_prefix(None, "ac", Access::Read).is_err());
+        assert!(acl.check_prefix(None, "", Access::Read).is_err());
+        assert!(acl.check_prefix(None, "other", Access::Read).is_ok());
+
+        assert!(Acl::parse(r#"{"": {"root": "write"}}"#).is_err());
+        assert!(Acl::parse(r#"{"acme.": {"ingest": "admin"}}"#).is_err());
+    }
+}
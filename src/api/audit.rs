@@ -0,0 +1,414 @@
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::DefaultHasher, BTreeSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        control::{read_latest, read_routed, send_within, worker_error, ControlHandle, ControlState, LatestRead},
+        deadline::Deadline,
+        reports::RunTrigger,
+        types::{AggregatesPage, MetricValueResponse, MAX_AGGREGATES_PAGE},
+    },
+    error::ErrorCode,
+    metrics::routing::AUDIT_MISMATCHES_TOTAL,
+    RaftMetricsError, Result,
+};
+
+/// Where the control node keeps its audit reports unless
+/// `AUDIT_REPORTS_FILE` says otherwise. The control node has no metrics
+/// store, so reports go to a file rather than a table.
+pub const DEFAULT_AUDIT_REPORTS_FILE: &str = "raftmetrics.audits";
+/// Reports kept, in memory and in the reports file; older ones are dropped.
+const MAX_AUDIT_REPORTS: usize = 50;
+/// Most metrics one run may sample.
+pub const MAX_AUDIT_SAMPLE: usize = 1000;
+/// Most names listed from each worker to sample from.
+const AUDIT_CANDIDATES_PER_WORKER: usize = 10_000;
+/// Budget for listing one worker's names, and for the reads of one metric.
+const AUDIT_READ_BUDGET: Duration = Duration::from_secs(10);
+
+/// `?sample=` on `POST /admin/audit/run`.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub sample: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn sample(&self, default: usize) -> Result<usize> {
+        match self.sample.unwrap_or(default) {
+            0 => Err(RaftMetricsError::InvalidRequest("sample must be positive".to_string())),
+            sample if sample > MAX_AUDIT_SAMPLE => Err(RaftMetricsError::InvalidRequest(format!(
+                "sample must be at most {}", MAX_AUDIT_SAMPLE
+            ))),
+            sample => Ok(sample),
+        }
+    }
+}
+
+/// What one read of a metric answered. Neither a value nor a timestamp
+/// means the metric was not found.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRead {
+    /// For the read routed through the control node, what served it:
+    /// `cache`, `primary`, `replica`, `standby` or `stale-cache`. For a
+    /// direct read, `primary` or `replica`.
+    pub layer: String,
+    /// Worker read directly; absent for the routed read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+impl AuditRead {
+    fn new(layer: &str, url: Option<&str>, metric: Option<&MetricValueResponse>) -> Self {
+        Self {
+            layer: layer.to_string(),
+            url: url.map(str::to_string),
+            value: metric.map(|metric| metric.value),
+            timestamp: metric.map(|metric| metric.timestamp),
+        }
+    }
+
+    fn agrees_with(&self, other: &AuditRead) -> bool {
+        (self.value, self.timestamp) == (other.value, other.timestamp)
+    }
+}
+
+/// A metric some read of which disagreed with its primary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditMismatch {
+    pub metric: String,
+    /// The primary's own state, read directly.
+    pub expected: AuditRead,
+    /// Each read that answered differently.
+    pub disagreeing: Vec<AuditRead>,
+}
+
+/// Outcome of one audit run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditReport {
+    pub id: String,
+    pub trigger: RunTrigger,
+    /// Unix seconds.
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// Names listed from the workers, which the sample was drawn from.
+    pub candidates: usize,
+    /// Metrics whose reads were compared.
+    pub audited: usize,
+    /// Reads that failed, and so were left out of the comparison. A metric
+    /// whose primary could not be read is not audited.
+    pub failed_reads: usize,
+    pub mismatches: Vec<AuditMismatch>,
+}
+
+#[derive(Debug, Default)]
+struct AuditRuns {
+    running: bool,
+    last_started: Option<Instant>,
+    /// Oldest first.
+    reports: VecDeque<AuditReport>,
+}
+
+/// Audit reports, kept in a file of JSON lines when there is one so they
+/// outlive a restart, and whether a run is going.
+#[derive(Debug, Default)]
+pub struct Audits {
+    path: Option<PathBuf>,
+    runs: Mutex<AuditRuns>,
+}
+
+impl Audits {
+    /// Reports saved at `path`, if it holds any. Lines that do not parse are
+    /// skipped.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut reports = VecDeque::new();
+        if let Some(contents) = path.as_ref().and_then(|path| std::fs::read_to_string(path).ok()) {
+            reports.extend(contents.lines().filter_map(|line| serde_json::from_str(line).ok()));
+        }
+        while reports.len() > MAX_AUDIT_REPORTS {
+            reports.pop_front();
+        }
+        Self { path, runs: Mutex::new(AuditRuns { reports, ..AuditRuns::default() }) }
+    }
+
+    /// Kept reports, newest first.
+    pub fn reports(&self) -> Vec<AuditReport> {
+        self.runs.lock().unwrap().reports.iter().rev().cloned().collect()
+    }
+
+    /// Marks a run started at `now`, unless one is going or the last
+    /// started less than `min_gap` ago.
+    fn claim(&self, min_gap: Duration, now: Instant) -> Result<()> {
+        let mut runs = self.runs.lock().unwrap();
+        if runs.running {
+            return Err(RaftMetricsError::Conflict("an audit is already running".to_string()));
+        }
+        if let Some(since) = runs.last_started.map(|started| now.saturating_duration_since(started)).filter(|&since| since < min_gap) {
+            return Err(RaftMetricsError::RateLimited(format!(
+                "the last audit started {}s ago; audits start at least {}s apart",
+                since.as_secs(),
+                min_gap.as_secs()
+            )));
+        }
+        runs.running = true;
+        runs.last_started = Some(now);
+        Ok(())
+    }
+
+    fn finish(&self, report: Option<AuditReport>) {
+        let mut runs = self.runs.lock().unwrap();
+        runs.running = false;
+        let Some(report) = report else { return };
+        runs.reports.push_back(report);
+        while runs.reports.len() > MAX_AUDIT_REPORTS {
+            runs.reports.pop_front();
+        }
+        let Some(path) = &self.path else { return };
+        let contents: String = runs.reports
+            .iter()
+            .filter_map(|report| serde_json::to_string(report).ok())
+            .map(|line| line + "\n")
+            .collect();
+        if let Err(e) = std::fs::write(path, contents) {
+            warn!("Failed to save audit reports to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Audits `sample` metrics drawn at random from those the workers list:
+/// each is read through the control node, as a client would read it, and
+/// directly from its primary and every replica, and any read that answers
+/// differently from the primary is reported. Metrics are audited at most
+/// `AUDIT_METRICS_PER_SEC` a second. Refused while another run is going or
+/// within `AUDIT_MIN_GAP_SECS` of the last one starting.
+pub async fn run_audit(state: &ControlState, sample: usize, trigger: RunTrigger) -> Result<AuditReport> {
+    state.audits.claim(state.config.audit.min_gap, Instant::now())?;
+    // Run apart from the request so a client hanging up does not leave it
+    // half done and claimed
+    let task = {
+        let state = state.clone();
+        tokio::spawn(async move { audit(&state, sample, trigger).await })
+    };
+    let report = task.await.map_err(|e| RaftMetricsError::Internal(format!("Audit task failed: {}", e)));
+    state.audits.finish(report.as_ref().ok().cloned());
+    let report = report?;
+    for mismatch in &report.mismatches {
+        for read in &mismatch.disagreeing {
+            AUDIT_MISMATCHES_TOTAL.with_label_values(&[&read.layer]).inc();
+        }
+    }
+    info!(
+        "Audit {} compared {} of {} metrics: {} mismatched, {} reads failed",
+        report.id, report.audited, report.candidates, report.mismatches.len(), report.failed_reads
+    );
+    Ok(report)
+}
+
+async fn audit(state: &ControlState, sample: usize, trigger: RunTrigger) -> AuditReport {
+    let id = uuid::Uuid::new_v4();
+    let started_at = chrono::Utc::now().timestamp();
+    let candidates = list_names(state).await;
+    let names = draw(&candidates, sample, id.as_u64_pair().0);
+
+    let mut report = AuditReport {
+        id: id.to_string(),
+        trigger,
+        started_at,
+        finished_at: started_at,
+        candidates: candidates.len(),
+        audited: 0,
+        failed_reads: 0,
+        mismatches: Vec::new(),
+    };
+    let mut pace = tokio::time::interval(Duration::from_secs_f64(1.0 / f64::from(state.config.audit.metrics_per_sec)));
+    pace.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    for name in names {
+        pace.tick().await;
+        audit_metric(state, &name, &mut report).await;
+    }
+    report.finished_at = chrono::Utc::now().timestamp();
+    report
+}
+
+/// Names of every metric the workers hold, up to
+/// [`AUDIT_CANDIDATES_PER_WORKER`] from each. A worker that cannot list
+/// its metrics is left out.
+async fn list_names(state: &ControlState) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for worker_url in state.worker_urls.iter() {
+        let deadline = Deadline::after(AUDIT_READ_BUDGET);
+        let mut after: Option<String> = None;
+        let mut listed = 0;
+        while listed < AUDIT_CANDIDATES_PER_WORKER {
+            let mut request = state.read_pool.client()
+                .get(format!("{}/aggregates", worker_url))
+                .query(&[("limit", MAX_AGGREGATES_PAGE)]);
+            if let Some(after) = &after {
+                request = request.query(&[("after", after)]);
+            }
+            let page = match send_within(state, &state.read_pool, request, &deadline, worker_url).await {
+                Ok(response) if response.status().is_success() => response.json::<AggregatesPage>().await
+                    .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e))),
+                Ok(response) => Err(worker_error(response, "Worker failed to list aggregates").await),
+                Err(e) => Err(e),
+            };
+            let page = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!("Audit could not list the metrics on {}: {}", worker_url, e);
+                    break;
+                }
+            };
+            listed += page.aggregates.len();
+            names.extend(page.aggregates.into_iter().map(|aggregate| aggregate.name));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+    }
+    names
+}
+
+/// `sample` of `names`, ordered by a hash mixing each with `seed` so every
+/// run draws a different set.
+fn draw(names: &BTreeSet<String>, sample: usize, seed: u64) -> Vec<String> {
+    let mut drawn: Vec<(u64, &String)> = names
+        .iter()
+        .map(|name| {
+            let mut hasher = DefaultHasher::new();
+            (seed, name).hash(&mut hasher);
+            (hasher.finish(), name)
+        })
+        .collect();
+    drawn.sort_unstable();
+    drawn.into_iter().take(sample).map(|(_, name)| name.clone()).collect()
+}
+
+/// Reads `name` routed and from each copy at once and adds a mismatch to
+/// `report` for every read disagreeing with the primary's.
+async fn audit_metric(state: &ControlState, name: &str, report: &mut AuditReport) {
+    let deadline = Deadline::after(AUDIT_READ_BUDGET);
+    let partition = state.router.route(name);
+    let worker_url = &state.worker_urls[partition];
+    let replicas = state.config.replicas_of(worker_url);
+    let copies: Vec<(&str, &String)> = std::iter::once(("primary", worker_url))
+        .chain(replicas.iter().map(|url| ("replica", url)))
+        .collect();
+    let headers = HeaderMap::new();
+    let direct = copies.iter().map(|(_, url)| read_latest(state, &deadline, url, name, &None, &headers));
+    let (routed, direct) = tokio::join!(read_routed(state, &deadline, name), futures_util::future::join_all(direct));
+
+    let mut reads = Vec::new();
+    for ((layer, url), read) in copies.iter().zip(direct) {
+        match read {
+            Ok(LatestRead::Value(metric, _)) => reads.push(AuditRead::new(layer, Some(url.as_str()), Some(&metric))),
+            Err(e) if e.code() == ErrorCode::MetricNotFound => reads.push(AuditRead::new(layer, Some(url.as_str()), None)),
+            // Nothing to match is sent, so no copy answers 304
+            Ok(LatestRead::NotModified(_)) => {}
+            Err(e) => {
+                warn!("Audit read of '{}' from {} failed: {}", name, url, e);
+                report.failed_reads += 1;
+                // Without the primary there is nothing to compare against
+                if *layer == "primary" {
+                    return;
+                }
+            }
+        }
+    }
+    match routed {
+        Ok((metric, cached)) => {
+            let layer = match (cached, metric.source) {
+                (true, _) => "cache",
+                (false, Some(source)) => source.as_str(),
+                (false, None) => "primary",
+            };
+            reads.push(AuditRead::new(layer, None, Some(&metric)));
+        }
+        Err(e) if e.code() == ErrorCode::MetricNotFound => reads.push(AuditRead::new("primary", None, None)),
+        Err(e) => {
+            warn!("Audit read of '{}' through the control node failed: {}", name, e);
+            report.failed_reads += 1;
+        }
+    }
+
+    report.audited += 1;
+    let expected = reads.remove(0);
+    let disagreeing: Vec<AuditRead> = reads.into_iter().filter(|read| !read.agrees_with(&expected)).collect();
+    if !disagreeing.is_empty() {
+        warn!("Audit found {} read(s) of '{}' disagreeing with its primary", disagreeing.len(), name);
+        report.mismatches.push(AuditMismatch { metric: name.to_string(), expected, disagreeing });
+    }
+}
+
+/// Runs an audit every `AUDIT_INTERVAL_SECS`, unless it is zero.
+pub fn schedule_audits(handle: ControlHandle) {
+    tokio::spawn(async move {
+        loop {
+            let interval = handle.snapshot().config.audit.interval;
+            if interval.is_zero() {
+                return;
+            }
+            tokio::time::sleep(interval).await;
+            let state = handle.snapshot();
+            if let Err(e) = run_audit(&state, state.config.audit.sample, RunTrigger::Schedule).await {
+                warn!("Scheduled audit did not run: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(id: &str) -> AuditReport {
+        AuditReport {
+            id: id.to_string(),
+            trigger: RunTrigger::Manual,
+            started_at: 0,
+            finished_at: 0,
+            candidates: 0,
+            audited: 0,
+            failed_reads: 0,
+            mismatches: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_runs_are_spaced_and_reports_outlive_a_restart() {
+        let path = std::env::temp_dir().join(format!("audits-{}", uuid::Uuid::new_v4()));
+        let audits = Audits::load(Some(path.clone()));
+        let start = Instant::now();
+        let gap = Duration::from_secs(60);
+
+        audits.claim(gap, start).unwrap();
+        assert!(matches!(audits.claim(gap, start), Err(RaftMetricsError::Conflict(_))));
+        audits.finish(Some(report("first")));
+        assert!(matches!(audits.claim(gap, start + Duration::from_secs(30)), Err(RaftMetricsError::RateLimited(_))));
+        audits.claim(gap, start + gap).unwrap();
+        audits.finish(Some(report("second")));
+
+        let restarted = Audits::load(Some(path.clone()));
+        let ids: Vec<String> = restarted.reports().into_iter().map(|report| report.id).collect();
+        assert_eq!(ids, ["second", "first"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_draws_vary_with_the_seed() {
+        let names: BTreeSet<String> = (0..100).map(|i| format!("m{}", i)).collect();
+        assert_eq!(draw(&names, 10, 1), draw(&names, 10, 1));
+        assert_ne!(draw(&names, 10, 1), draw(&names, 10, 2));
+        assert_eq!(draw(&names, 500, 1).len(), 100);
+    }
+}
@@ -0,0 +1,318 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{api::control::ControlState, metrics::{sampling::keeps_nth, Labels}, RaftMetricsError, Result};
+
+/// Largest ingest body a capture will buffer; bigger ones, and bodies of
+/// unknown length, pass through uncaptured.
+pub const CAPTURE_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Headers whose values never reach a capture.
+const SENSITIVE_HEADERS: [&str; 6] = [
+    "authorization", "proxy-authorization", "cookie", "set-cookie", "x-api-key", "x-auth-token",
+];
+
+/// Body of `POST /admin/capture/start`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRequest {
+    /// Metric name to capture, or a prefix ending in `*`; `*` alone matches all.
+    pub metric: String,
+    /// Fraction of matching requests to keep, in `(0, 1]`.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// Most entries kept; the oldest are dropped beyond it.
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// How long the capture runs, capped at the node's `CAPTURE_MAX_SECS`.
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_max_entries() -> usize {
+    100
+}
+
+/// One ingest request as the control node received it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturedRequest {
+    /// Arrival time in Unix milliseconds.
+    pub timestamp_ms: i64,
+    /// `X-Forwarded-For` if present, else the peer address when known.
+    pub source: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+    pub metric_name: String,
+    pub partition: usize,
+}
+
+/// State reported by `GET /admin/capture`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureSnapshot {
+    pub active: bool,
+    pub metric: Option<String>,
+    pub sample_rate: Option<f64>,
+    /// Seconds until the capture stops on its own.
+    pub expires_in_secs: Option<u64>,
+    /// Matching requests seen, captured or not.
+    pub matched: u64,
+    pub entries: Vec<CapturedRequest>,
+}
+
+struct ActiveCapture {
+    request: CaptureRequest,
+    expires: Instant,
+    matched: u64,
+    entries: VecDeque<CapturedRequest>,
+}
+
+impl ActiveCapture {
+    fn matches(&self, name: &str) -> bool {
+        match self.request.metric.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => self.request.metric == name,
+        }
+    }
+
+    /// Keeps `sample_rate` of matches, spread evenly.
+    fn sampled(&mut self) -> bool {
+        self.matched += 1;
+        keeps_nth(self.matched, self.request.sample_rate)
+    }
+}
+
+/// Opt-in, time-limited capture of raw ingest requests on the control node.
+/// While no capture runs, the ingest path pays for one atomic load.
+#[derive(Default)]
+pub struct IngestCapture {
+    enabled: AtomicBool,
+    current: Mutex<Option<ActiveCapture>>,
+    /// Entries of the last capture, kept readable after it stops.
+    finished: Mutex<Option<CaptureSnapshot>>,
+}
+
+impl IngestCapture {
+    /// Starts a capture, replacing any running one.
+    pub fn start(&self, request: CaptureRequest, max_duration: Duration) -> Result<CaptureSnapshot> {
+        if request.metric.is_empty() {
+            return Err(RaftMetricsError::InvalidRequest("capture metric must not be empty".to_string()));
+        }
+        if !(request.sample_rate > 0.0 && request.sample_rate <= 1.0) {
+            return Err(RaftMetricsError::InvalidRequest("sample_rate must be in (0, 1]".to_string()));
+        }
+        if request.max_entries == 0 {
+            return Err(RaftMetricsError::InvalidRequest("max_entries must be positive".to_string()));
+        }
+
+        let duration = request.duration_secs.map_or(max_duration, |secs| Duration::from_secs(secs).min(max_duration));
+        info!(
+            "Capturing ingest requests for '{}' at rate {} for {:?}",
+            request.metric, request.sample_rate, duration
+        );
+        *self.current.lock().unwrap() = Some(ActiveCapture {
+            request,
+            expires: Instant::now() + duration,
+            matched: 0,
+            entries: VecDeque::new(),
+        });
+        self.enabled.store(true, Ordering::Release);
+        Ok(self.snapshot())
+    }
+
+    /// Stops the running capture, keeping its entries readable.
+    pub fn stop(&self) -> CaptureSnapshot {
+        let mut current = self.current.lock().unwrap();
+        self.finish(&mut current);
+        drop(current);
+        self.snapshot()
+    }
+
+    pub fn snapshot(&self) -> CaptureSnapshot {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|capture| Instant::now() >= capture.expires) {
+            self.finish(&mut current);
+        }
+        match current.as_ref() {
+            Some(capture) => CaptureSnapshot {
+                active: true,
+                metric: Some(capture.request.metric.clone()),
+                sample_rate: Some(capture.request.sample_rate),
+                expires_in_secs: Some(capture.expires.saturating_duration_since(Instant::now()).as_secs()),
+                matched: capture.matched,
+                entries: capture.entries.iter().cloned().collect(),
+            },
+            None => self.finished.lock().unwrap().clone().unwrap_or_default(),
+        }
+    }
+
+    fn finish(&self, current: &mut Option<ActiveCapture>) {
+        self.enabled.store(false, Ordering::Release);
+        if let Some(capture) = current.take() {
+            info!("Ingest capture for '{}' stopped with {} entries", capture.request.metric, capture.entries.len());
+            *self.finished.lock().unwrap() = Some(CaptureSnapshot {
+                active: false,
+                metric: Some(capture.request.metric),
+                sample_rate: Some(capture.request.sample_rate),
+                expires_in_secs: None,
+                matched: capture.matched,
+                entries: capture.entries.into(),
+            });
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Whether a request for `name` should be captured right now.
+    fn admit(&self, name: &str) -> bool {
+        let mut current = self.current.lock().unwrap();
+        let Some(capture) = current.as_mut() else { return false };
+        if Instant::now() >= capture.expires {
+            self.finish(&mut current);
+            return false;
+        }
+        capture.matches(name) && capture.sampled()
+    }
+
+    fn record(&self, entry: CapturedRequest) {
+        if let Some(capture) = self.current.lock().unwrap().as_mut() {
+            if capture.entries.len() == capture.request.max_entries {
+                capture.entries.pop_front();
+            }
+            capture.entries.push_back(entry);
+        }
+    }
+}
+
+fn redacted_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                "<redacted>".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct NamedMetric {
+    metric_name: String,
+    #[serde(default)]
+    labels: Labels,
+}
+
+/// Copies matching `POST /metrics` requests into the node's capture before
+/// passing them on unchanged.
+pub async fn capture_ingest(State(state): State<ControlState>, request: Request, next: Next) -> Response {
+    if !state.capture.is_enabled() {
+        return next.run(request).await;
+    }
+    let length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if length.is_none_or(|length| length > CAPTURE_MAX_BODY_BYTES) {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, CAPTURE_MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Not capturing ingest request: {}", e);
+            return RaftMetricsError::InvalidRequest(format!("Failed to read request body: {}", e)).into_response();
+        }
+    };
+
+    if let Ok(metric) = serde_json::from_slice::<NamedMetric>(&bytes) {
+        if state.capture.admit(&metric.metric_name) {
+            let source = parts
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+                .or_else(|| parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|info| info.0.to_string()));
+            state.capture.record(CapturedRequest {
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                source,
+                headers: redacted_headers(&parts.headers),
+                body: String::from_utf8_lossy(&bytes).into_owned(),
+                partition: state.route_write(&metric.metric_name, &metric.labels),
+                metric_name: metric.metric_name,
+            });
+        }
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(metric: &str, sample_rate: f64) -> IngestCapture {
+        let capture = IngestCapture::default();
+        let request = CaptureRequest { metric: metric.into(), sample_rate, max_entries: 3, duration_secs: None };
+        capture.start(request, Duration::from_secs(60)).unwrap();
+        capture
+    }
+
+    #[test]
+    fn test_sampling_and_ring_buffer() {
+        let capture = capture("cpu_*", 0.5);
+        let admitted: Vec<bool> = (0..6).map(|_| capture.admit("cpu_user")).collect();
+        assert_eq!(admitted, [false, true, false, true, false, true]);
+        assert!(!capture.admit("mem_used"));
+
+        let entry = |n: usize| CapturedRequest {
+            timestamp_ms: n as i64,
+            source: None,
+            headers: BTreeMap::new(),
+            body: String::new(),
+            metric_name: "cpu_user".into(),
+            partition: 0,
+        };
+        for n in 0..5 {
+            capture.record(entry(n));
+        }
+        let snapshot = capture.snapshot();
+        assert_eq!(snapshot.matched, 6);
+        assert_eq!(snapshot.entries.iter().map(|e| e.timestamp_ms).collect::<Vec<_>>(), [2, 3, 4]);
+
+        let stopped = capture.stop();
+        assert!(!stopped.active && !capture.is_enabled());
+        assert_eq!(stopped.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_capture_expires() {
+        let capture = IngestCapture::default();
+        let request = CaptureRequest { metric: "*".into(), sample_rate: 1.0, max_entries: 10, duration_secs: Some(0) };
+        capture.start(request, Duration::from_secs(60)).unwrap();
+        assert!(!capture.admit("cpu"));
+        assert!(!capture.is_enabled());
+        assert!(!capture.snapshot().active);
+    }
+}
@@ -0,0 +1,189 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::{error::ErrorCode, RaftMetricsError, Result};
+
+/// Body of `POST /chaos/latency`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyFault {
+    pub delay_ms: u64,
+    pub percent: u32,
+}
+
+/// Body of `POST /chaos/errors`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ErrorFault {
+    /// 500 or 503.
+    #[serde(default = "default_error_status")]
+    pub status: u16,
+    pub percent: u32,
+}
+
+fn default_error_status() -> u16 {
+    500
+}
+
+/// Body of `POST /chaos/drop-raft`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DropRaftFault {
+    pub percent: u32,
+}
+
+fn check_percent(percent: u32) -> Result<()> {
+    match percent {
+        0..=100 => Ok(()),
+        _ => Err(RaftMetricsError::InvalidRequest("percent must be between 0 and 100".to_string())),
+    }
+}
+
+/// Whether the next of the events counted by `seen` is among `percent` of
+/// them. Hits are spread evenly rather than drawn at random, so 20% fails
+/// exactly every fifth request and tests can count on it.
+fn strikes(seen: &AtomicU64, percent: u32) -> bool {
+    let n = seen.fetch_add(1, Ordering::Relaxed);
+    let percent = u64::from(percent);
+    (n + 1) * percent / 100 > n * percent / 100
+}
+
+/// Faults a worker injects on command, for resilience testing. Its
+/// endpoints are only served when `CHAOS_ENABLED=true`.
+#[derive(Debug, Default)]
+pub struct ChaosState {
+    enabled: AtomicBool,
+    latency: Mutex<Option<LatencyFault>>,
+    errors: Mutex<Option<ErrorFault>>,
+    raft_drop_percent: AtomicU64,
+    requests_delayed: AtomicU64,
+    requests_failed: AtomicU64,
+    raft_messages: AtomicU64,
+    raft_dropped: AtomicU64,
+}
+
+impl ChaosState {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// How long to hold the next request, if at all.
+    fn delay(&self) -> Option<Duration> {
+        let fault = (*self.latency.lock().unwrap())?;
+        strikes(&self.requests_delayed, fault.percent).then(|| Duration::from_millis(fault.delay_ms))
+    }
+
+    /// Status to fail the next request with, if any.
+    fn error(&self) -> Option<StatusCode> {
+        let fault = (*self.errors.lock().unwrap())?;
+        strikes(&self.requests_failed, fault.percent).then(|| StatusCode::from_u16(fault.status).ok()).flatten()
+    }
+
+    /// Whether the transport should drop the next outbound raft message.
+    pub fn drops_raft_message(&self) -> bool {
+        let percent = self.raft_drop_percent.load(Ordering::Relaxed) as u32;
+        if percent == 0 || !strikes(&self.raft_messages, percent) {
+            return false;
+        }
+        self.raft_dropped.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Outbound raft messages dropped so far.
+    pub fn raft_dropped(&self) -> u64 {
+        self.raft_dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        *self.latency.lock().unwrap() = None;
+        *self.errors.lock().unwrap() = None;
+        self.raft_drop_percent.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Delays or fails requests as configured, before they reach a handler.
+pub async fn inject_faults(State(chaos): State<Arc<ChaosState>>, request: Request, next: Next) -> Response {
+    if let Some(delay) = chaos.delay() {
+        debug!("Chaos: delaying {} by {:?}", request.uri().path(), delay);
+        tokio::time::sleep(delay).await;
+    }
+    if let Some(status) = chaos.error() {
+        debug!("Chaos: failing {} with {}", request.uri().path(), status);
+        let code = ErrorCode::for_status(status.as_u16());
+        let body = serde_json::json!({ "error": "injected by chaos testing", "code": code, "retriable": code.retriable() });
+        return (status, Json(body)).into_response();
+    }
+    next.run(request).await
+}
+
+async fn put_latency(State(chaos): State<Arc<ChaosState>>, Json(fault): Json<LatencyFault>) -> Result<StatusCode> {
+    check_percent(fault.percent)?;
+    warn!("Chaos: delaying {}% of requests by {}ms", fault.percent, fault.delay_ms);
+    *chaos.latency.lock().unwrap() = Some(fault);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn put_errors(State(chaos): State<Arc<ChaosState>>, Json(fault): Json<ErrorFault>) -> Result<StatusCode> {
+    check_percent(fault.percent)?;
+    if !matches!(fault.status, 500 | 503) {
+        return Err(RaftMetricsError::InvalidRequest("status must be 500 or 503".to_string()));
+    }
+    warn!("Chaos: failing {}% of requests with {}", fault.percent, fault.status);
+    *chaos.errors.lock().unwrap() = Some(fault);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn put_raft_drop(State(chaos): State<Arc<ChaosState>>, Json(fault): Json<DropRaftFault>) -> Result<StatusCode> {
+    check_percent(fault.percent)?;
+    warn!("Chaos: dropping {}% of outbound raft messages", fault.percent);
+    chaos.raft_drop_percent.store(u64::from(fault.percent), Ordering::Relaxed);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn clear(State(chaos): State<Arc<ChaosState>>) -> StatusCode {
+    warn!("Chaos: cleared");
+    chaos.clear();
+    StatusCode::NO_CONTENT
+}
+
+pub fn chaos_routes<S: Clone + Send + Sync + 'static>(chaos: Arc<ChaosState>) -> Router<S> {
+    Router::new()
+        .route("/chaos/latency", post(put_latency))
+        .route("/chaos/errors", post(put_errors))
+        .route("/chaos/drop-raft", post(put_raft_drop))
+        .route("/chaos/clear", post(clear))
+        .with_state(chaos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faults_hit_an_even_share() {
+        let chaos = ChaosState::default();
+        *chaos.errors.lock().unwrap() = Some(ErrorFault { status: 503, percent: 20 });
+        let failed: Vec<bool> = (0..10).map(|_| chaos.error().is_some()).collect();
+        assert_eq!(failed.iter().filter(|&&failed| failed).count(), 2);
+        assert!(failed[4]);
+
+        chaos.raft_drop_percent.store(10, Ordering::Relaxed);
+        let dropped = (0..100).filter(|_| chaos.drops_raft_message()).count();
+        assert_eq!((dropped, chaos.raft_dropped()), (10, 10));
+
+        chaos.clear();
+        assert!(chaos.error().is_none() && !chaos.drops_raft_message());
+    }
+}
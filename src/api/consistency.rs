@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{Result, RaftMetricsError, metrics::MetricsRegistry};
+
+/// Prefix of the current version token format.
+const TOKEN_V1: &str = "v1.";
+
+/// Default longest time a read waits for its worker to reach `min_version`.
+pub const DEFAULT_VERSION_WAIT: Duration = Duration::from_secs(1);
+
+/// Encodes a raft applied index as an opaque version token.
+pub fn encode_version(applied_index: u64) -> String {
+    format!("{}{:x}", TOKEN_V1, applied_index)
+}
+
+/// Decodes a token produced by [`encode_version`].
+pub fn decode_version(token: &str) -> Result<u64> {
+    token
+        .strip_prefix(TOKEN_V1)
+        .and_then(|index| u64::from_str_radix(index, 16).ok())
+        .ok_or_else(|| RaftMetricsError::InvalidRequest(format!("malformed version token '{}'", token)))
+}
+
+/// Optional `?min_version=<token>` on reads, for read-your-writes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConsistencyQuery {
+    pub min_version: Option<String>,
+}
+
+impl ConsistencyQuery {
+    /// Waits up to `within` for `metrics` to apply the requested version.
+    pub async fn wait(&self, metrics: &MetricsRegistry, within: Duration) -> Result<()> {
+        let Some(token) = &self.min_version else { return Ok(()) };
+        let required = decode_version(token)?;
+
+        if tokio::time::timeout(within, metrics.wait_for_applied(required)).await.is_err() {
+            return Err(RaftMetricsError::VersionNotReached(format!(
+                "replica has not applied version {} within {}ms", token, within.as_millis()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How far a write must get before it is acknowledged, from `?ack=` on
+/// ingest. Responses report the level reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AckLevel {
+    /// Acknowledged once the control node has queued the forward.
+    None,
+    /// Acknowledged once the owning worker has applied the write.
+    #[default]
+    Local,
+    /// Acknowledged once a majority of the owning worker's raft group has
+    /// committed the write.
+    Quorum,
+}
+
+/// Optional `?ack=none|local|quorum` on ingest.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AckQuery {
+    #[serde(default)]
+    pub ack: AckLevel,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_round_trip() {
+        assert_eq!(decode_version(&encode_version(0)).unwrap(), 0);
+        assert_eq!(decode_version(&encode_version(u64::MAX)).unwrap(), u64::MAX);
+        assert!(decode_version("42").is_err());
+        assert!(decode_version("v2.2a").is_err());
+    }
+}
@@ -1,19 +1,96 @@
 use axum::{
-    extract::{State, Path},
-    routing::{get, post},
+    body::Body,
+    extract::{FromRef, State, Path, Query, RawQuery, Request},
+    Extension,
+    http::{header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH}, HeaderMap, HeaderName, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
     Json, Router,
 };
-use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tracing::{info, debug};
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, debug, warn, Instrument};
 
 use crate::{
     Result,
     RaftMetricsError,
-    metrics::MetricsRegistry,
-    raft::storage::MemStorage,
-    partitioning::get_partition,
-    api::worker::{WorkerMetricResponse, MetricAggregateResponse},
+    error::{DeadlineStage, ErrorCode},
+    metrics::{hooks::HookConfig, namespace::NamespaceTree, quotas::{TenantUsage, UsageReport}, routing::{self, CONTROL_CACHE_HITS_TOTAL, CONTROL_FORWARD_DURATION, RATE_LIMITED_TOTAL}, histograms::{self, HistogramDefinition, HistogramMergeRequest, HistogramMetric, HistogramReport, MergedHistogram}, states::{StateDefinition, StateMetric, StateReport}, transform::IngestTransform, Labels, MetricsRegistry, StaleMetric, FORWARD_FAILURES_TOTAL},
+    raft::{node::{HealthStatus, RaftHealth}, storage::MemStorage},
+    partitioning::{self, JumpHashRouter, Router as _, WeightedRingRouter},
+    telemetry,
+    config::{ControlConfig, PreflightConfig},
+    api::{
+        acl::{self, check_metric_acl, Access, Acl},
+        audit::{self, AuditQuery, AuditReport, Audits},
+        capture::{capture_ingest, CaptureRequest, CaptureSnapshot, IngestCapture},
+        consistency::{AckLevel, AckQuery},
+        deadline::{attach_deadline, Deadline, TIMEOUT_HEADER},
+        envelope::envelope_responses,
+        forward::ForwardPool,
+        forwarding::{self, refuse_loops, ForwardFrom, Forwarder, ForwardingStatus},
+        freshness::freshness_headers,
+        etag::not_modified,
+        info::{node_id_from_env, NodeInfo, NO_STORAGE},
+        load::{poll_worker_loads, WorkerLoads},
+        membership::{ClusterHealth, DeregisterRequest, HeartbeatRequest, Membership, RegisterRequest, RegisterResponse},
+        middleware::{count_routed, require_admin, trace_requests, track_requests},
+        msgpack::{Encoded, Format, JsonOrMsgpack},
+        preflight::{check_admin_listen, control_preflight, PreflightReport},
+        push::spawn_pusher,
+        rate_limit::RateLimiter,
+        read_cache::{CachedRead, HotReads, ReadCache, DEFAULT_HOT_READS_FILE},
+        read_only::{refuse_writes, ReadOnly, ReadOnlyQuery},
+        reports::{self, ReportRun, ReportSummary, Reports, RunTrigger},
+        routing_preview::{RoutingPreview, RoutingPreviewRequest, MAX_PREVIEW_NAMES},
+        scrape::prometheus_scrape,
+        serve::{admin_listen_addr, ctrl_c, serve_split},
+        shadow::{ShadowStats, ShadowTraffic},
+        slo::{slo_status, SloTracker},
+        types::{
+            AggregatesPage,
+            AggregatesQuery,
+            BatchAggregateRequest,
+            BatchAggregateResponse,
+            BucketsResponse,
+            ClusterTopology,
+            CompareResponse,
+            GroupByResponse,
+            HistogramQuery,
+            IngestSummary,
+            MetricAggregateResponse,
+            MetricDeltaResponse,
+            MetricHistoryResponse,
+            MetricRequest,
+            MetricValueResponse,
+            MultiGetRequest,
+            MultiGetResponse,
+            PrimeSummary,
+            QuarantineResponse,
+            RaftDebugResponse,
+            ReadSource,
+            ResetQuery,
+            SampleRateRequest,
+            SeriesRequest,
+            SeriesResponse,
+            StaleMetricsPage,
+            StaleQuery,
+            TopMetricsResponse,
+            TopQuery,
+            TreeQuery,
+            WorkerHealthResponse,
+            WorkerTopology,
+            WriteResponse,
+            MAX_BATCH_AGGREGATE_NAMES,
+            MAX_MULTI_GET_NAMES,
+            MAX_STALE_PAGE,
+        },
+    },
 };
 
 #[derive(Clone)]
@@ -21,116 +98,1230 @@ pub struct ControlState {
     pub storage: Arc<MemStorage>,
     pub metrics: Arc<MetricsRegistry>,
     pub worker_urls: Arc<Vec<String>>,
-    pub http_client: Arc<reqwest::Client>,
+    pub router: Arc<dyn partitioning::Router>,
+    pub config: Arc<ControlConfig>,
+    pub read_pool: Arc<ForwardPool>,
+    pub write_pool: Arc<ForwardPool>,
+    pub slo: Arc<SloTracker>,
+    pub capture: Arc<IngestCapture>,
+    /// Result of the checks run at startup.
+    pub preflight: Arc<PreflightReport>,
+    /// Copies of writes sent to shadow workers, if any are configured.
+    pub shadow: Arc<ShadowTraffic>,
+    pub read_cache: Arc<ReadCache>,
+    /// Namespace trees by their shape, kept for the read cache's TTL.
+    pub tree_cache: Arc<ReadCache<NamespaceTree>>,
+    /// Reads of each metric, for choosing what to prime the cache with.
+    pub hot_reads: Arc<HotReads>,
+    /// Cleared while the read cache is primed at startup; `/health`
+    /// answers `503` until it is set.
+    pub ready: Arc<AtomicBool>,
+    /// Served at `/info`, with the partition count filled in per request.
+    pub info: Arc<NodeInfo>,
+    /// Run state of the reports in `config`.
+    pub reports: Arc<Reports>,
+    /// Latest load reported by each worker.
+    pub loads: Arc<WorkerLoads>,
+    /// Set while writes are refused cluster-wide; see [`ReadOnly`].
+    pub read_only: Arc<ReadOnly>,
+    /// Holds each tenant to its rate quota; see [`Self::admit_samples`].
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Sends matching writes downstream when `FORWARD_FROM` is `control`.
+    pub forwarder: Arc<Forwarder>,
+    /// API keys allowed to read or write each protected metric prefix.
+    pub acl: Arc<Acl>,
+    /// Consistency audit reports and run state; see [`audit::run_audit`].
+    pub audits: Arc<Audits>,
+    /// Slots for the forwards of `ack=none` writes still running; see
+    /// [`MAX_UNACKED_WRITES`].
+    pub unacked: Arc<tokio::sync::Semaphore>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MetricRequest {
-    pub metric_name: String,
-    pub value: f64,
+impl ControlState {
+    pub fn new(config: ControlConfig) -> Result<Self> {
+        let read_pool = ForwardPool::new("read", &config.read_pool)?;
+        let write_pool = ForwardPool::new("write", &config.write_pool)?;
+
+        Ok(Self {
+            storage: Arc::new(MemStorage::new()),
+            metrics: Arc::new(MetricsRegistry::new()?),
+            worker_urls: Arc::new(config.worker_urls.clone()),
+            router: build_router(&config),
+            slo: Arc::new(SloTracker::new(&config.slo)),
+            capture: Arc::new(IngestCapture::default()),
+            preflight: Arc::new(PreflightReport::default()),
+            shadow: Arc::new(ShadowTraffic::new(config.shadow.clone())?),
+            read_cache: Arc::new(ReadCache::new(config.read_cache)),
+            tree_cache: Arc::new(ReadCache::new(config.read_cache)),
+            hot_reads: Arc::new(HotReads::default()),
+            ready: Arc::new(AtomicBool::new(true)),
+            info: Arc::new(NodeInfo::new("control", 0, NO_STORAGE, None)),
+            reports: Arc::new(Reports::new()),
+            loads: Arc::new(WorkerLoads::default()),
+            read_only: Arc::new(ReadOnly::default()),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            forwarder: Arc::new(Forwarder::new(config.forwarding.clone(), ForwardFrom::Control, "control")),
+            acl: Arc::new(Acl::load(config.acl_path.as_deref())?),
+            audits: Arc::new(Audits::default()),
+            unacked: Arc::new(tokio::sync::Semaphore::new(MAX_UNACKED_WRITES)),
+            config: Arc::new(config),
+            read_pool: Arc::new(read_pool),
+            write_pool: Arc::new(write_pool),
+        })
+    }
+
+    /// Partition a write of `name` carrying `labels` goes to; see
+    /// [`partitioning::PartitionKey`].
+    pub fn route_write(&self, name: &str, labels: &Labels) -> usize {
+        let span = tracing::info_span!(
+            "partition.select",
+            metric.name = telemetry::metric_attr(name),
+            partition = tracing::field::Empty,
+        );
+        let _entered = span.enter();
+        let partition = self.router.route(&self.config.partition_key.key(name, labels));
+        span.record("partition", partition);
+        partition
+    }
+
+    /// A copy of this state routing by `config`, with fresh pools. The
+    /// registry, storage, SLO tracker, ingest capture, forwarder, ACLs and
+    /// `ack=none` slots carry over, and so does shadowing with its stats
+    /// unless its settings changed.
+    fn reconfigured(&self, config: ControlConfig) -> Result<Self> {
+        let read_pool = ForwardPool::new("read", &config.read_pool)?;
+        let write_pool = ForwardPool::new("write", &config.write_pool)?;
+
+        Ok(Self {
+            storage: self.storage.clone(),
+            metrics: self.metrics.clone(),
+            worker_urls: Arc::new(config.worker_urls.clone()),
+            router: build_router(&config),
+            slo: self.slo.clone(),
+            capture: self.capture.clone(),
+            preflight: self.preflight.clone(),
+            shadow: match config.shadow == *self.shadow.config() {
+                true => self.shadow.clone(),
+                false => Arc::new(ShadowTraffic::new(config.shadow.clone())?),
+            },
+            // Routing may have moved metrics, so cached answers are dropped
+            read_cache: Arc::new(ReadCache::new(config.read_cache)),
+            tree_cache: Arc::new(ReadCache::new(config.read_cache)),
+            hot_reads: self.hot_reads.clone(),
+            ready: self.ready.clone(),
+            info: self.info.clone(),
+            reports: self.reports.clone(),
+            loads: self.loads.clone(),
+            read_only: self.read_only.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            forwarder: self.forwarder.clone(),
+            acl: self.acl.clone(),
+            audits: self.audits.clone(),
+            unacked: self.unacked.clone(),
+            config: Arc::new(config),
+            read_pool: Arc::new(read_pool),
+            write_pool: Arc::new(write_pool),
+        })
+    }
+
+    /// Refuses a write of one sample of each of `names` with
+    /// [`RaftMetricsError::RateLimited`] when a tenant among them is over
+    /// its rate quota. Only tenants with a quota are tracked.
+    pub fn admit_samples<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let quotas = &self.config.quotas;
+        let mut by_tenant = BTreeMap::<&str, u64>::new();
+        for name in names {
+            if let Some(tenant) = quotas.tenant_of(name).filter(|tenant| quotas.quota(tenant).is_some()) {
+                *by_tenant.entry(tenant).or_default() += 1;
+            }
+        }
+        let now = Instant::now();
+        for (tenant, samples) in by_tenant {
+            let rate = quotas.quota(tenant).and_then(|quota| quota.max_samples_per_sec);
+            if !self.rate_limiter.admit(tenant, rate, samples, now) {
+                RATE_LIMITED_TOTAL.with_label_values(&[tenant]).inc_by(samples);
+                return Err(RaftMetricsError::RateLimited(format!(
+                    "tenant '{}' is over its quota of {} samples a second", tenant, rate.unwrap_or_default()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuses `access` to any of `names` the ACLs do not grant the API key
+    /// in `headers`; see [`Acl::check`].
+    pub fn authorize<'a>(&self, headers: &HeaderMap, names: impl IntoIterator<Item = &'a str>, access: Access) -> Result<()> {
+        let key = acl::api_key(headers);
+        names.into_iter().try_for_each(|name| self.acl.check(key, name, access))
+    }
+
+    /// Like [`Self::authorize`] for every name starting with `prefix`; see
+    /// [`Acl::check_prefix`].
+    pub fn authorize_prefix(&self, headers: &HeaderMap, prefix: &str, access: Access) -> Result<()> {
+        self.acl.check_prefix(acl::api_key(headers), prefix, access)
+    }
+
+    /// Whether the API key in `headers` may read `name`, for leaving names
+    /// out of listings.
+    pub fn readable(&self, headers: &HeaderMap, name: &str) -> bool {
+        self.acl.permits(acl::api_key(headers), name, Access::Read)
+    }
+
+    /// Whether the API key in `headers` may read every name starting with
+    /// `prefix`, for leaving groups of names out of listings.
+    pub fn readable_prefix(&self, headers: &HeaderMap, prefix: &str) -> bool {
+        self.acl.permits_prefix(acl::api_key(headers), prefix, Access::Read)
+    }
+
+    /// The worker reporting the least load, for work any worker can take.
+    pub fn least_loaded_worker(&self) -> Option<&str> {
+        let router = self.loads.router(&self.worker_urls);
+        self.worker_urls.get(router.route("")).map(String::as_str)
+    }
+}
+
+/// Produces the config a reload should switch to.
+pub type ConfigLoader = Arc<dyn Fn() -> Result<ControlConfig> + Send + Sync>;
+
+/// The control node's current [`ControlState`], swapped whole on reload
+/// and whenever a worker registers or lapses. Each request takes a snapshot
+/// when it starts, so neither changes the workers or pools under a request
+/// already in flight.
+#[derive(Clone)]
+pub struct ControlHandle {
+    current: Arc<RwLock<ControlState>>,
+    /// Locked before `current` whenever both are held.
+    members: Arc<Mutex<Membership>>,
+    loader: ConfigLoader,
+}
+
+impl ControlHandle {
+    pub fn new(state: ControlState, loader: ConfigLoader) -> Self {
+        Self {
+            members: Arc::new(Mutex::new(Membership::new((*state.config).clone()))),
+            current: Arc::new(RwLock::new(state)),
+            loader,
+        }
+    }
+
+    pub fn snapshot(&self) -> ControlState {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Loads and validates a new config and switches to it, reading the
+    /// ACL file again. Only worker URLs, routing weights, pool timeouts,
+    /// deadlines, tenant quotas and ACLs change; the admin token and port,
+    /// SLOs, the tenant separator and forwarding need a restart. On any
+    /// error the current config stays.
+    pub fn reload(&self) -> Result<Arc<ControlConfig>> {
+        let mut config = (self.loader)()?;
+        config.validate().map_err(RaftMetricsError::InvalidConfig)?;
+        let acl = Acl::load(config.acl_path.as_deref())?;
+
+        let mut members = self.members.lock().unwrap();
+        let mut current = self.current.write().unwrap();
+        if config.admin_token != current.config.admin_token {
+            warn!("ADMIN_TOKEN changes take effect after a restart");
+            config.admin_token = current.config.admin_token.clone();
+        }
+        if config.slo != current.config.slo {
+            warn!("SLO changes take effect after a restart");
+            config.slo = current.config.slo.clone();
+        }
+        if config.admin_port != current.config.admin_port {
+            warn!("ADMIN_PORT changes take effect after a restart");
+            config.admin_port = current.config.admin_port;
+        }
+        if config.quotas.separator != current.config.quotas.separator {
+            warn!("{} changes take effect after a restart", crate::metrics::quotas::SEPARATOR_KEY);
+            config.quotas.separator = current.config.quotas.separator.clone();
+        }
+        if config.forwarding != current.config.forwarding {
+            warn!("Forwarding changes take effect after a restart");
+            config.forwarding = current.config.forwarding.clone();
+        }
+
+        let mut next = members.clone();
+        next.set_base(config);
+        let config = next.effective();
+        if config.worker_urls.len() != current.worker_urls.len() {
+            warn!(
+                "Worker count changed from {} to {}; metrics will move between partitions and earlier writes stay on their old worker",
+                current.worker_urls.len(),
+                config.worker_urls.len()
+            );
+        }
+
+        *current = current.reconfigured(config)?;
+        current.acl = Arc::new(acl);
+        *members = next;
+        info!("Reloaded configuration; workers: {:?}", current.worker_urls);
+        Ok(current.config.clone())
+    }
+
+    /// Adds a self-registered worker, or renews its registration, and
+    /// reroutes when the set of workers or their capacities changed.
+    pub fn register_worker(&self, request: &RegisterRequest) -> Result<RegisterResponse> {
+        let mut members = self.members.lock().unwrap();
+        let mut next = members.clone();
+        if next.register(request, Instant::now())? {
+            self.reroute(&next)?;
+            info!("Worker {} registered with capacity {}", request.url, request.capacity);
+        }
+        *members = next;
+
+        let current = self.current.read().unwrap();
+        Ok(RegisterResponse {
+            workers: members.routed(),
+            ttl_secs: current.config.registration_ttl.as_secs(),
+        })
+    }
+
+    /// Stops routing to a self-registered worker; `false` if it was not one.
+    pub fn deregister_worker(&self, url: &str) -> Result<bool> {
+        let mut members = self.members.lock().unwrap();
+        let mut next = members.clone();
+        if !next.deregister(url) {
+            return Ok(false);
+        }
+        self.reroute(&next)?;
+        *members = next;
+        info!("Worker {} deregistered", url);
+        Ok(true)
+    }
+
+    /// Stops routing to registered workers whose last heartbeat is older
+    /// than `REGISTRATION_TTL_SECS` at `now`, returning their URLs.
+    pub fn expire_workers(&self, now: Instant) -> Result<Vec<String>> {
+        let mut members = self.members.lock().unwrap();
+        let mut next = members.clone();
+        let ttl = self.current.read().unwrap().config.registration_ttl;
+        let lapsed = next.expire(now, ttl);
+        if !lapsed.is_empty() {
+            self.reroute(&next)?;
+            warn!("Worker registrations lapsed: {:?}", lapsed);
+        }
+        *members = next;
+        Ok(lapsed)
+    }
+
+    /// Records a heartbeat, routing to the worker again if it was down.
+    pub fn heartbeat_worker(&self, request: &HeartbeatRequest, now: Instant) -> Result<()> {
+        let mut members = self.members.lock().unwrap();
+        let mut next = members.clone();
+        if next.heartbeat(request, now)? {
+            self.reroute(&next)?;
+            info!("Worker {} is sending heartbeats again", request.url);
+        }
+        *members = next;
+        Ok(())
+    }
+
+    /// Stops routing to workers that have sent no heartbeat for
+    /// `HEARTBEAT_TIMEOUT_SECS` at `now`, returning the URLs newly down.
+    pub fn mark_down_workers(&self, now: Instant) -> Result<Vec<String>> {
+        let mut members = self.members.lock().unwrap();
+        let mut next = members.clone();
+        let timeout = self.current.read().unwrap().config.heartbeat_timeout;
+        let down = next.mark_down(now, timeout);
+        if !down.is_empty() {
+            self.reroute(&next)?;
+            warn!("Workers missed heartbeats and are down: {:?}", down);
+        }
+        *members = next;
+        Ok(down)
+    }
+
+    pub fn health(&self, now: Instant) -> ClusterHealth {
+        let members = self.members.lock().unwrap();
+        let current = self.current.read().unwrap();
+        ClusterHealth {
+            workers: members.health(now),
+            heartbeat_timeout_secs: current.config.heartbeat_timeout.as_secs(),
+            read_only: current.read_only.enabled(),
+        }
+    }
+
+    fn reroute(&self, members: &Membership) -> Result<()> {
+        let mut current = self.current.write().unwrap();
+        *current = current.reconfigured(members.effective())?;
+        Ok(())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MetricResponse {
-    pub success: bool,
-    pub message: String,
+impl FromRef<ControlHandle> for ControlState {
+    fn from_ref(handle: &ControlHandle) -> Self {
+        handle.snapshot()
+    }
 }
 
+/// Serves `state` with a fixed config; `/admin/reload` keeps it as is.
 pub fn control_router(state: ControlState) -> Router {
+    let config = state.config.clone();
+    reloadable_control_router(ControlHandle::new(state, Arc::new(move || Ok((*config).clone()))))
+}
+
+/// Serves `handle` on one listener, data and admin routes together.
+pub fn reloadable_control_router(handle: ControlHandle) -> Router {
+    data_router(handle.clone()).merge(admin_router(handle))
+}
+
+/// The public API: ingest, reads, cluster views, reports, health and info.
+pub fn data_router(handle: ControlHandle) -> Router {
+    let state = handle.snapshot();
+    let node_id = state.info.node_id;
+    let admin_token: Option<Arc<str>> = state.config.admin_token.as_deref().map(Arc::from);
     Router::new()
         .route("/health", get(health_check))
-        .route("/metrics", post(record_metric))
-        .route("/metrics/:name", get(get_metric))
-        .route("/metrics/:name/aggregate", get(get_metric_aggregate))
-        .with_state(state)
+        .route("/info", get(get_info))
+        .merge(Router::new().route("/slo", get(slo_status)).with_state(state.slo.clone()))
+        .route(
+            "/metrics",
+            post(record_metric)
+                .route_layer(middleware::from_fn_with_state(handle.clone(), capture_ingest))
+                .route_layer(middleware::from_fn(count_routed)),
+        )
+        .route("/metrics/stream", post(stream_metrics))
+        .route("/metrics/transaction", post(record_transaction))
+        .route("/metrics/query", post(get_metrics))
+        .route("/metrics/get", post(get_series))
+        .route("/metrics/stale", get(get_stale_metrics))
+        .route("/metrics/tree", get(get_metric_tree))
+        .route("/metrics/:name", get(get_metric).route_layer(middleware::from_fn(count_routed)).delete(delete_metric))
+        .route("/metrics/:name/aggregate", get(get_metric_aggregate).route_layer(middleware::from_fn(count_routed)))
+        .route("/aggregate/batch", post(get_aggregate_batch))
+        .route("/aggregates", get(get_aggregates))
+        .route("/metrics/:name/delta", get(get_metric_delta))
+        .route("/metrics/:name/compare", get(compare_metric))
+        .route("/metrics/:name/history", get(get_metric_history))
+        .route("/metrics/:name/range", get(get_metric_range))
+        .route("/metrics/:name/groupby", get(get_metric_groupby))
+        .route("/metrics/:name/buckets", get(get_metric_buckets))
+        .route("/metrics/:name/sample_rate", put(put_sample_rate).route_layer(middleware::from_fn_with_state(admin_token.clone(), require_admin)))
+        .route("/metrics/:name/transform", put(put_transform))
+        .route("/metrics/:name/states", get(get_states).put(put_states))
+        .route("/metrics/:name/histogram", get(get_histogram).put(put_histogram))
+        .route("/histograms/merge", post(merge_histograms))
+        .route("/metrics/:name/quarantine", get(get_quarantine))
+        .route("/metrics/:name/quarantine/apply", post(apply_quarantine))
+        .route("/metrics/:name/reset", post(reset_metric))
+        .route("/hooks/:id", put(put_hook).route_layer(middleware::from_fn_with_state(admin_token, require_admin)))
+        .route("/cluster/topology", get(cluster_topology))
+        .route("/debug/top", get(get_top_metrics))
+        .route("/usage", get(get_usage))
+        .route("/cluster/health", get(cluster_health))
+        .route("/reports", get(list_reports))
+        .route("/forwarding/status", get(forwarding_status))
+        .route_layer(middleware::from_fn_with_state(handle.clone(), check_metric_acl))
+        .route_layer(middleware::from_fn_with_state(state.config.forwarding.max_hops, refuse_loops))
+        .route_layer(middleware::from_fn_with_state(state.read_only.clone(), refuse_writes))
+        .route_layer(middleware::from_fn_with_state(state.slo.clone(), track_requests))
+        .route_layer(middleware::from_fn(trace_requests))
+        .layer(middleware::from_fn_with_state(handle.clone(), attach_current_deadline))
+        .layer(middleware::from_fn_with_state(node_id, envelope_responses))
+        .with_state(handle)
+}
+
+/// Admin, worker membership and Prometheus routes, on `ADMIN_PORT` when
+/// one is set. All but `/prometheus` need `ADMIN_TOKEN`. Of these only
+/// `/admin/reset` is refused while read-only.
+pub fn admin_router(handle: ControlHandle) -> Router {
+    let state = handle.snapshot();
+    let node_id = state.info.node_id;
+    let admin_token: Option<Arc<str>> = state.config.admin_token.as_deref().map(Arc::from);
+    Router::new()
+        .route("/admin/reset", post(reset_all).route_layer(middleware::from_fn_with_state(state.read_only.clone(), refuse_writes)))
+        .route("/admin/readonly", post(set_read_only))
+        .route("/admin/reload", post(reload_config))
+        .route("/admin/config", get(get_config))
+        .route("/admin/capture", get(get_capture))
+        .route("/admin/capture/start", post(start_capture))
+        .route("/admin/capture/stop", post(stop_capture))
+        .route("/admin/preflight", get(get_preflight))
+        .route("/admin/shadow/stats", get(get_shadow_stats))
+        .route("/admin/routing_preview", post(routing_preview))
+        .route("/admin/cache/prime", post(prime_cache))
+        .route("/admin/audit/run", post(run_audit))
+        .route("/admin/audit/reports", get(list_audits))
+        .route("/reports/:id/run", post(run_report))
+        .route("/cluster/register", post(register_worker))
+        .route("/cluster/deregister", post(deregister_worker))
+        .route("/cluster/heartbeat", post(worker_heartbeat))
+        .route_layer(middleware::from_fn_with_state(admin_token, require_admin))
+        .route("/prometheus", get(prometheus_scrape))
+        .route_layer(middleware::from_fn_with_state(state.slo.clone(), track_requests))
+        .route_layer(middleware::from_fn(trace_requests))
+        .layer(middleware::from_fn_with_state(handle.clone(), attach_current_deadline))
+        .layer(middleware::from_fn_with_state(node_id, envelope_responses))
+        .with_state(handle)
+}
+
+/// [`attach_deadline`] with the default budget of the config in force when
+/// the request arrives, so a reloaded `DEFAULT_DEADLINE_MS` applies at once.
+async fn attach_current_deadline(State(state): State<ControlState>, request: Request, next: middleware::Next) -> Response {
+    attach_deadline(State(state.config.deadline.default_budget), request, next).await
+}
+
+/// Counts a failed control→worker call against the worker it was sent to.
+/// Transport errors and 5xx responses count; 4xx are the client's problem.
+fn record_forward_failure(worker_url: &str) {
+    FORWARD_FAILURES_TOTAL.with_label_values(&[worker_url]).inc();
+}
+
+/// Sends `request` through `pool` within what is left of `deadline`, less
+/// the configured safety margin, and forwards that budget to the worker so
+/// it can give up at the same time. Running out of budget before or during
+/// the call is reported as [`RaftMetricsError::DeadlineExceeded`], and a
+/// worker that cannot be reached as [`RaftMetricsError::Unavailable`].
+pub(crate) async fn send_within(
+    state: &ControlState,
+    pool: &ForwardPool,
+    request: reqwest::RequestBuilder,
+    deadline: &Deadline,
+    worker_url: &str,
+) -> Result<reqwest::Response> {
+    let margin = state.config.deadline.safety_margin;
+    let budget = deadline.remaining().saturating_sub(margin);
+    if budget.is_zero() {
+        return Err(deadline.exceeded(DeadlineStage::ControlQueue));
+    }
+
+    let span = tracing::info_span!(
+        "forward",
+        otel.kind = "client",
+        worker.url = worker_url,
+        pool = pool.name(),
+        http.status_code = tracing::field::Empty,
+    );
+    let request = request
+        .timeout(budget.min(pool.request_timeout()))
+        .header(TIMEOUT_HEADER, budget.as_millis().to_string());
+    let request = telemetry::inject(request, &span);
+    let started = Instant::now();
+    let response = pool.send(request).instrument(span.clone()).await;
+    if let Ok(response) = &response {
+        span.record("http.status_code", response.status().as_u16());
+    }
+    CONTROL_FORWARD_DURATION.with_label_values(&[worker_url]).observe(started.elapsed().as_secs_f64());
+    let response = response
+        .map_err(|e| {
+            if e.is_timeout() && deadline.remaining() <= margin {
+                return deadline.exceeded(DeadlineStage::Worker);
+            }
+            record_forward_failure(worker_url);
+            RaftMetricsError::Unavailable(format!("Failed to forward request to worker: {}", e))
+        })?;
+
+    if response.status() == reqwest::StatusCode::GATEWAY_TIMEOUT {
+        return Err(deadline.exceeded(DeadlineStage::Worker));
+    }
+    if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        let error_text = body["error"].as_str().unwrap_or("Version not reached").to_string();
+        return Err(RaftMetricsError::VersionNotReached(error_text));
+    }
+    if response.status().is_server_error() {
+        record_forward_failure(worker_url);
+    }
+    Ok(response)
+}
+
+async fn get_info(State(state): State<ControlState>) -> Json<NodeInfo> {
+    Json(NodeInfo {
+        partitions: Some(state.worker_urls.len()),
+        read_only: state.read_only.enabled(),
+        ..state.info.as_ref().clone()
+    })
 }
 
-async fn health_check() -> impl axum::response::IntoResponse {
-    Json(serde_json::json!({
+async fn health_check(State(state): State<ControlState>) -> impl axum::response::IntoResponse {
+    let read_only = state.read_only.enabled();
+    if !state.ready.load(Ordering::Acquire) {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({
+            "status": "starting",
+            "message": "Control node is priming its read cache",
+            "ready": false,
+            "read_only": read_only
+        })));
+    }
+    if state.slo.degraded() {
+        return (StatusCode::OK, Json(serde_json::json!({
+            "status": "degraded",
+            "message": "Control node is burning an SLO error budget",
+            "ready": true,
+            "read_only": read_only
+        })));
+    }
+    (StatusCode::OK, Json(serde_json::json!({
         "status": "healthy",
-        "message": "Control node is operational"
-    }))
+        "message": "Control node is operational",
+        "ready": true,
+        "read_only": read_only
+    })))
 }
 
+/// Most `ack=none` writes forwarded after their response at once.
+pub const MAX_UNACKED_WRITES: usize = 1024;
+
+/// Records one metric at the `?ack=` level. With `none` the forward runs
+/// after the response, unless [`MAX_UNACKED_WRITES`] are already running,
+/// in which case it is forwarded before answering as `local` would be; a
+/// write that fell short of its level answers `202 Accepted` and is
+/// marked `degraded`.
 async fn record_metric(
     State(state): State<ControlState>,
-    Json(request): Json<MetricRequest>,
-) -> Result<Json<MetricResponse>> {
+    Extension(deadline): Extension<Deadline>,
+    Query(ack): Query<AckQuery>,
+    headers: HeaderMap,
+    format: Format,
+    JsonOrMsgpack(request): JsonOrMsgpack<MetricRequest>,
+) -> Result<(StatusCode, Encoded<WriteResponse>)> {
     info!("Recording metric: {} = {}", request.metric_name, request.value);
     
-    let worker_count = state.worker_urls.len();
-    info!("Total workers: {}", worker_count);
-    
-    let partition = get_partition(&request.metric_name, worker_count);
-    let worker_url = &state.worker_urls[partition];
-    debug!("Metric '{}' hashed to partition {} ({})", request.metric_name, partition, worker_url);
+    info!("Total workers: {}", state.router.worker_count());
     
-    let response = state.http_client.post(&format!("{}/process", worker_url))
-        .json(&request)
-        .send()
+    let hops = forwarding::hops(&headers);
+    state.authorize(&headers, [request.metric_name.as_str()], Access::Write)?;
+    state.admit_samples([request.metric_name.as_str()])?;
+    let partition = state.route_write(&request.metric_name, &request.labels);
+    routing::record_assignment(partition);
+    debug!("Metric '{}' hashed to partition {} ({})", request.metric_name, partition, state.worker_urls[partition]);
+
+    let unacked = match ack.ack {
+        AckLevel::None => state.unacked.clone().try_acquire_owned().ok(),
+        _ => None,
+    };
+    if let Some(slot) = unacked {
+        tokio::spawn(async move {
+            let _slot = slot;
+            let name = request.metric_name.clone();
+            if let Err(e) = forward_metric(&state, partition, request, AckLevel::Local, &deadline, hops).await {
+                warn!("Unacknowledged write to partition {} failed: {}", partition, e);
+            }
+            state.read_cache.invalidate(&name);
+        });
+        return Ok((StatusCode::ACCEPTED, format.respond(WriteResponse {
+            success: true,
+            message: format!("Metric queued for worker {}", partition + 1),
+            version: None,
+            ack: Some(AckLevel::None),
+            degraded: false,
+        })));
+    }
+
+    if ack.ack == AckLevel::None {
+        debug!("{} unacknowledged writes in flight; forwarding '{}' before answering", MAX_UNACKED_WRITES, request.metric_name);
+    }
+    let name = request.metric_name.clone();
+    let written = forward_metric(&state, partition, request, ack.ack.max(AckLevel::Local), &deadline, hops).await;
+    state.read_cache.invalidate(&name);
+    let written = written?;
+    // Workers that predate ack levels always wait for the local apply
+    let reached = written.ack.unwrap_or(AckLevel::Local);
+    let degraded = reached < ack.ack;
+    Ok((if degraded { StatusCode::ACCEPTED } else { StatusCode::OK }, format.respond(WriteResponse {
+        success: true,
+        message: format!("Metric recorded on worker {}", partition + 1),
+        version: written.version,
+        ack: Some(reached),
+        degraded,
+    })))
+}
+
+/// Sends `request` to `partition`'s worker, asking it to acknowledge at
+/// `ack`, and mirrors it to the shadow and offers it for forwarding once
+/// the worker took it. `hops` is the times the write has been forwarded.
+async fn forward_metric(
+    state: &ControlState,
+    partition: usize,
+    request: MetricRequest,
+    ack: AckLevel,
+    deadline: &Deadline,
+    hops: u32,
+) -> Result<MetricValueResponse> {
+    // A write that never reached the owning worker goes to the partition's
+    // promoted standbys, which share its raft group
+    let standbys = state.config.standbys_of(&state.worker_urls[partition]);
+    let mut failure = None;
+    let mut response = None;
+    for worker_url in std::iter::once(&state.worker_urls[partition]).chain(standbys) {
+        if let Some(failure) = &failure {
+            warn!("Write of '{}' failed ({}); trying standby {}", request.metric_name, failure, worker_url);
+        }
+        let sent = send_within(
+                state,
+                &state.write_pool,
+                forwarding::with_hops(state.write_pool.client().post(format!("{}/process", worker_url)), hops).query(&AckQuery { ack }).json(&request),
+                deadline,
+                worker_url,
+            )
+            .await;
+        match sent {
+            Ok(sent) if sent.status() == reqwest::StatusCode::MISDIRECTED_REQUEST => {
+                failure = Some(worker_error(sent, "Standby refused metric").await);
+            }
+            Ok(sent) => {
+                response = Some(sent);
+                break;
+            }
+            Err(e @ RaftMetricsError::Unavailable(_)) => failure = Some(e),
+            Err(e) => return Err(e),
+        }
+    }
+    let Some(response) = response else {
+        return Err(failure.unwrap_or_else(|| RaftMetricsError::Unavailable("no worker answered".to_string())));
+    };
+
+    // A worker that lost leadership mid-write answers 503 with RAFT_NO_LEADER
+    // and the new leader's id when it knows it; both reach the client as sent
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to process metric").await);
+    }
+    state.forwarder.offer_request(&request, hops);
+    state.shadow.mirror(partition, move |client, url| client.post(format!("{}/process", url)).json(&request));
+    response
+        .json()
         .await
-        .map_err(|e| RaftMetricsError::Internal(format!("Failed to forward request to worker: {}", e)))?;
-        
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+}
+
+/// Records every metric in the body or none of them. The metrics must all
+/// hash to one partition, so the worker there commits them as a single
+/// raft operation.
+async fn record_transaction(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    headers: HeaderMap,
+    Json(requests): Json<Vec<MetricRequest>>,
+) -> Result<Json<WriteResponse>> {
+    info!("Recording transaction of {} metrics", requests.len());
+
+    let Some(first) = requests.first() else {
+        return Err(RaftMetricsError::InvalidRequest("a transaction needs at least one operation".to_string()));
+    };
+    let partition = state.route_write(&first.metric_name, &first.labels);
+    if let Some(stray) = requests.iter().find(|request| state.route_write(&request.metric_name, &request.labels) != partition) {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "'{}' and '{}' are on different partitions; a transaction must stay on one",
+            first.metric_name, stray.metric_name
+        )));
+    }
+    state.authorize(&headers, requests.iter().map(|request| request.metric_name.as_str()), Access::Write)?;
+    state.admit_samples(requests.iter().map(|request| request.metric_name.as_str()))?;
+    routing::record_assignment(partition);
+
+    let hops = forwarding::hops(&headers);
+    let worker_url = &state.worker_urls[partition];
+    let response = send_within(
+            &state,
+            &state.write_pool,
+            forwarding::with_hops(state.write_pool.client().post(format!("{}/process/transaction", worker_url)), hops).json(&requests),
+            &deadline,
+            worker_url,
+        )
+        .await;
+    for request in &requests {
+        state.read_cache.invalidate(&request.metric_name);
+    }
+    let response = response?;
+
     if !response.status().is_success() {
-        let error_text = response.text().await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(RaftMetricsError::Internal(format!("Worker failed to process metric: {}", error_text)));
+        return Err(worker_error(response, "Worker failed to process transaction").await);
+    }
+    let count = requests.len();
+    for request in &requests {
+        state.forwarder.offer_request(request, hops);
     }
+    state.shadow.mirror(partition, move |client, url| client.post(format!("{}/process/transaction", url)).json(&requests));
 
-    Ok(Json(MetricResponse {
+    Ok(Json(WriteResponse {
         success: true,
-        message: format!("Metric recorded on worker {}", partition + 1),
+        message: format!("{} metrics recorded on worker {}", count, partition + 1),
+        version: None,
+        ack: None,
+        degraded: false,
     }))
 }
 
+/// The error a worker answered with, passed on under the worker's own
+/// code and message so clients see why it failed.
+pub(crate) async fn worker_error(response: reqwest::Response, action: &str) -> RaftMetricsError {
+    let status = response.status();
+    let body = response.text().await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+    let error = RaftMetricsError::from_response(status.as_u16(), &body);
+    debug!("{}: worker answered {} ({})", action, status, error);
+    error
+}
+
+/// Records buffered from an NDJSON stream before they are sent to workers.
+pub const STREAM_FLUSH_RECORDS: usize = 500;
+
+/// Ingests an `application/x-ndjson` body of [`MetricRequest`] lines as it
+/// arrives, forwarding to workers every [`STREAM_FLUSH_RECORDS`] records.
+/// Malformed lines are counted as rejected and skipped. Each flush gets its
+/// own default budget, since a backfill may outlast any single request's.
+async fn stream_metrics(State(state): State<ControlState>, headers: HeaderMap, body: Body) -> Result<Json<IngestSummary>> {
+    info!("Starting NDJSON ingest stream");
+
+    let hops = forwarding::hops(&headers);
+    let key = acl::api_key(&headers);
+    let mut summary = IngestSummary::default();
+    let mut batches: Vec<Vec<MetricRequest>> = vec![Vec::new(); state.worker_urls.len()];
+    let mut buffered = 0;
+    let mut line = Vec::new();
+    let mut chunks = body.into_data_stream();
+
+    loop {
+        let chunk = chunks.next().await.transpose()
+            .map_err(|e| RaftMetricsError::InvalidRequest(format!("Failed to read ingest stream: {}", e)))?;
+        let done = chunk.is_none();
+        let chunk = chunk.unwrap_or_default();
+
+        let mut rest = &chunk[..];
+        while !rest.is_empty() || (done && !line.is_empty()) {
+            match rest.iter().position(|&b| b == b'\n') {
+                Some(end) => {
+                    line.extend_from_slice(&rest[..end]);
+                    rest = &rest[end + 1..];
+                }
+                None if done => {}
+                None => {
+                    line.extend_from_slice(rest);
+                    break;
+                }
+            }
+
+            let text = String::from_utf8_lossy(&line);
+            if !text.trim().is_empty() {
+                match serde_json::from_str::<MetricRequest>(text.trim()) {
+                    Ok(request) if state.acl.check(key, &request.metric_name, Access::Write).is_err() => {
+                        summary.rejected += 1;
+                    }
+                    Ok(request) if state.admit_samples([request.metric_name.as_str()]).is_err() => {
+                        summary.rejected += 1;
+                        summary.rate_limited += 1;
+                    }
+                    Ok(request) => {
+                        batches[state.route_write(&request.metric_name, &request.labels)].push(request);
+                        buffered += 1;
+                    }
+                    Err(e) => {
+                        debug!("Rejecting NDJSON line: {}", e);
+                        summary.rejected += 1;
+                    }
+                }
+            }
+            line.clear();
+        }
+
+        if buffered >= STREAM_FLUSH_RECORDS || done {
+            flush_batches(&state, &mut batches, &mut summary, hops).await;
+            buffered = 0;
+        }
+        if done {
+            break;
+        }
+    }
+
+    info!(
+        "NDJSON ingest finished: {} accepted, {} rejected ({} rate limited)",
+        summary.accepted, summary.rejected, summary.rate_limited
+    );
+    Ok(Json(summary))
+}
+
+/// Sends each partition's buffered records to its worker, counting records
+/// a worker could not take as rejected, and offers the rest for forwarding.
+async fn flush_batches(state: &ControlState, batches: &mut [Vec<MetricRequest>], summary: &mut IngestSummary, hops: u32) {
+    for (partition, batch) in batches.iter_mut().enumerate() {
+        if batch.is_empty() {
+            continue;
+        }
+        let records = std::mem::take(batch);
+        let worker_url = &state.worker_urls[partition];
+        let deadline = Deadline::after(state.config.deadline.default_budget);
+        let request = forwarding::with_hops(state.write_pool.client().post(format!("{}/process/batch", worker_url)), hops).json(&records);
+
+        let flushed = match send_within(state, &state.write_pool, request, &deadline, worker_url).await {
+            Ok(response) if response.status().is_success() => response.json::<IngestSummary>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e))),
+            Ok(response) => Err(RaftMetricsError::Internal(format!("worker answered {}", response.status()))),
+            Err(e) => Err(e),
+        };
+        for record in &records {
+            state.read_cache.invalidate(&record.metric_name);
+        }
+        match flushed {
+            Ok(flushed) => {
+                summary.accepted += flushed.accepted;
+                summary.rejected += flushed.rejected;
+                for (index, record) in records.iter().enumerate() {
+                    if !flushed.errors.iter().any(|error| error.index == index) {
+                        state.forwarder.offer_request(record, hops);
+                    }
+                }
+                state.shadow.mirror(partition, move |client, url| client.post(format!("{}/process/batch", url)).json(&records));
+            }
+            Err(e) => {
+                warn!("Failed to flush {} records to {}: {}", records.len(), worker_url, e);
+                summary.rejected += records.len() as u64;
+            }
+        }
+    }
+}
+
+/// Forwards a read of a metric, passing `If-None-Match` through so the
+/// worker's `304 Not Modified` and ETag reach the client. Reads without a
+/// query string go through the read cache when it is on.
+///
+/// When the owning worker cannot answer, the partition's replicas are
+/// tried in turn, then its promoted standbys, then the cached value however
+/// old unless the client sent `?allow_stale=false`. The response's `source` says which answered.
+/// Any other error a worker answers with, such as `404` or a `500` from its
+/// store, is passed on under the worker's code.
+/// `?consistency=quorum` reads every copy instead; see [`quorum_read`].
 async fn get_metric(
     State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
     Path(name): Path<String>,
-) -> Result<Json<WorkerMetricResponse>> {
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<Response> {
     info!("Retrieving metric: {}", name);
+    let (query, options) = take_read_options(query)?;
+    state.hot_reads.record(&name);
+    serve_read(&state, &deadline, &name, query, options, &headers).await
+}
+
+/// Reads `name` as `GET /metrics/:name` serves it, through the read cache
+/// and failover, without counting it as a client read. Answers with the
+/// value and whether the read cache served it.
+pub(crate) async fn read_routed(state: &ControlState, deadline: &Deadline, name: &str) -> Result<(MetricValueResponse, bool)> {
+    let cached = state.read_cache.enabled() && state.read_cache.get(name, Instant::now()).is_some();
+    let options = ReadOptions { allow_stale: true, quorum: false };
+    let response = serve_read(state, deadline, name, None, options, &HeaderMap::new()).await?;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to read response: {}", e)))?;
+    let metric = serde_json::from_slice(&body)
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))?;
+    Ok((metric, cached))
+}
+
+async fn serve_read(
+    state: &ControlState,
+    deadline: &Deadline,
+    name: &str,
+    query: Option<String>,
+    ReadOptions { allow_stale, quorum }: ReadOptions,
+    headers: &HeaderMap,
+) -> Result<Response> {
+    let cacheable = query.is_none() && !quorum && state.read_cache.enabled();
+    if cacheable {
+        if let Some(cached) = state.read_cache.get(name, Instant::now()) {
+            CONTROL_CACHE_HITS_TOTAL.inc();
+            return Ok(cached_response(cached, headers));
+        }
+    }
+    // Misses arriving while another is read wait for it and take its answer
+    let _flight = match cacheable {
+        true => {
+            let flight = state.read_cache.join_flight(name).await;
+            if let Some(cached) = state.read_cache.get(name, Instant::now()) {
+                CONTROL_CACHE_HITS_TOTAL.inc();
+                return Ok(cached_response(cached, headers));
+            }
+            Some(flight)
+        }
+        false => None,
+    };
+    let ticket = cacheable.then(|| state.read_cache.begin(name));
     
-    let worker_count = state.worker_urls.len();
-    let partition = get_partition(&name, worker_count);
+    let partition = state.router.route(name);
+    routing::record_assignment(partition);
     let worker_url = &state.worker_urls[partition];
     debug!("Metric '{}' hashed to partition {} ({})", name, partition, worker_url);
-    
-    let response = state.http_client.get(&format!("{}/metrics/{}", worker_url, name))
-        .send()
-        .await
-        .map_err(|e| RaftMetricsError::Internal(format!("Failed to forward request to worker: {}", e)))?;
-        
-    if !response.status().is_success() {
-        let error_text = response.text().await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(RaftMetricsError::Internal(format!("Worker failed to retrieve metric: {}", error_text)));
+
+    if quorum {
+        let metric = quorum_read(state, deadline, partition, name, &query).await?;
+        return Ok((freshness(&metric), Json(metric)).into_response());
     }
-    
-    let metric_response: WorkerMetricResponse = response.json().await
+
+    let replicas = state.config.replicas_of(worker_url);
+    let standbys = state.config.standbys_of(worker_url);
+    let workers = std::iter::once((ReadSource::Primary, worker_url))
+        .chain(replicas.iter().map(|url| (ReadSource::Replica, url)))
+        .chain(standbys.iter().map(|url| (ReadSource::Standby, url)));
+    let mut failure = None;
+    for (source, url) in workers {
+        if let Some(failure) = &failure {
+            warn!("Read of '{}' failed ({}); trying {} {}", name, failure, source.as_str(), url);
+        }
+        let (metric, etag) = match read_latest(state, deadline, url, name, &query, headers).await {
+            Ok(LatestRead::NotModified(etag)) => return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response()),
+            Ok(LatestRead::Value(metric, etag)) => (metric, etag),
+            // A standby answers 421 until it is promoted
+            Err(e) if matches!(e.code(), ErrorCode::WorkerUnavailable | ErrorCode::RaftNoLeader | ErrorCode::NotServing) => {
+                if source != ReadSource::Primary {
+                    routing::record_read_fallback(source.as_str(), "failed");
+                }
+                failure = Some(e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let metric = MetricValueResponse { source: Some(source), ..metric };
+        match source {
+            ReadSource::Primary => state.shadow.compare_read(partition, name, metric.value),
+            _ => routing::record_read_fallback(source.as_str(), "served"),
+        }
+        if let Some(ticket) = ticket {
+            let cached = CachedRead { metric: metric.clone(), etag: etag.clone() };
+            state.read_cache.insert(name, ticket, cached, Instant::now());
+        }
+        return Ok((freshness(&metric), etag.map(|etag| [(ETAG, etag)]), Json(metric)).into_response());
+    }
+    if replicas.is_empty() {
+        routing::record_read_fallback(ReadSource::Replica.as_str(), "missing");
+    }
+
+    let stale = ReadSource::StaleCache.as_str();
+    let cached = (allow_stale && query.is_none()).then(|| state.read_cache.get_stale(name)).flatten();
+    match cached {
+        _ if !allow_stale => routing::record_read_fallback(stale, "disallowed"),
+        Some(cached) => {
+            routing::record_read_fallback(stale, "served");
+            warn!("No worker could serve '{}'; answering with the value from {}", name, cached.metric.timestamp);
+            let freshness = freshness(&cached.metric);
+            return Ok((freshness, Json(MetricValueResponse { source: Some(ReadSource::StaleCache), ..cached.metric })).into_response());
+        }
+        None => routing::record_read_fallback(stale, "missing"),
+    }
+    let failure = failure.map_or_else(|| "no worker answered".to_string(), |e| e.to_string());
+    Err(RaftMetricsError::Unavailable(format!("No worker could serve '{}': {}", name, failure)))
+}
+
+/// A cached read as served, or `304` when the client holds its ETag.
+fn cached_response(cached: CachedRead, headers: &HeaderMap) -> Response {
+    let freshness = freshness(&cached.metric);
+    match cached.etag {
+        Some(etag) if not_modified(headers, &etag) => (StatusCode::NOT_MODIFIED, freshness, [(ETAG, etag)]).into_response(),
+        etag => (freshness, etag.map(|etag| [(ETAG, etag)]), Json(cached.metric)).into_response(),
+    }
+}
+
+/// Reads `name` from the partition's primary and every replica at once and
+/// answers with the copy holding the newest sample, once a majority of
+/// them have answered. Copies that are older, or lack the metric, are
+/// repaired in the background by writing the newest sample back to them.
+/// Only reads without a query string repair, since a unit or precision
+/// changes the value served from the one stored.
+async fn quorum_read(
+    state: &ControlState,
+    deadline: &Deadline,
+    partition: usize,
+    name: &str,
+    query: &Option<String>,
+) -> Result<MetricValueResponse> {
+    let worker_url = &state.worker_urls[partition];
+    let replicas = state.config.replicas_of(worker_url);
+    let copies: Vec<(ReadSource, &String)> = std::iter::once((ReadSource::Primary, worker_url))
+        .chain(replicas.iter().map(|url| (ReadSource::Replica, url)))
+        .collect();
+    let headers = HeaderMap::new();
+    let reads = copies.iter().map(|(_, url)| read_latest(state, deadline, url, name, query, &headers));
+
+    let mut answered = Vec::new();
+    let mut failure = None;
+    for (copy, read) in copies.iter().zip(futures_util::future::join_all(reads).await) {
+        match read {
+            Ok(LatestRead::Value(metric, _)) => answered.push((copy, Some(metric))),
+            Err(e) if e.code() == ErrorCode::MetricNotFound => answered.push((copy, None)),
+            // Nothing to match is sent, so no copy answers 304
+            Ok(LatestRead::NotModified(_)) => {}
+            Err(e) => {
+                warn!("Quorum read of '{}' from {} failed: {}", name, copy.1, e);
+                failure = Some(e);
+            }
+        }
+    }
+    let needed = copies.len() / 2 + 1;
+    if answered.len() < needed {
+        let failure = failure.map_or_else(|| "no worker answered".to_string(), |e| e.to_string());
+        return Err(RaftMetricsError::Unavailable(format!(
+            "{} of {} copies of '{}' answered, {} needed: {}", answered.len(), copies.len(), name, needed, failure
+        )));
+    }
+
+    // The first copy wins a tie, so the primary is preferred
+    let Some((source, newest)) = answered
+        .iter()
+        .filter_map(|((source, _), metric)| metric.as_ref().map(|metric| (*source, metric)))
+        .min_by_key(|(_, metric)| std::cmp::Reverse(metric.timestamp))
+    else {
+        return Err(RaftMetricsError::NotFound);
+    };
+    let stale: Vec<&String> = answered
+        .iter()
+        .filter(|(_, metric)| metric.as_ref().is_none_or(|metric| metric.timestamp < newest.timestamp))
+        .map(|((_, url), _)| *url)
+        .collect();
+    if query.is_none() && !stale.is_empty() {
+        state.read_cache.invalidate(name);
+        for url in stale {
+            repair_copy(state, url, newest);
+        }
+    }
+    Ok(MetricValueResponse { source: Some(source), ..newest.clone() })
+}
+
+/// Writes `newest` to the copy at `url`, which answered a quorum read with
+/// an older sample or none. The write is sent in the background and its
+/// outcome logged and counted.
+fn repair_copy(state: &ControlState, url: &str, newest: &MetricValueResponse) {
+    let request = MetricRequest {
+        metric_name: newest.name.clone(),
+        value: newest.value,
+        state: None,
+        timestamp: Some(newest.timestamp),
+        labels: Default::default(),
+    };
+    let send = state.write_pool.client()
+        .post(format!("{}/process", url))
+        .timeout(state.write_pool.request_timeout())
+        .json(&request)
+        .send();
+    let url = url.to_string();
+    tokio::spawn(async move {
+        match send.await {
+            Ok(response) if response.status().is_success() => {
+                info!("Read repair wrote '{}' at {} to {}", request.metric_name, request.timestamp.unwrap_or_default(), url);
+                routing::record_read_repair("repaired");
+            }
+            Ok(response) => {
+                warn!("Read repair of '{}' on {} answered {}", request.metric_name, url, response.status());
+                routing::record_read_repair("failed");
+            }
+            Err(e) => {
+                warn!("Read repair of '{}' on {} failed: {}", request.metric_name, url, e);
+                routing::record_read_repair("failed");
+            }
+        }
+    });
+}
+
+/// Caching headers for `metric` from the expiry its worker reported,
+/// counted down to now so a cached read does not overstate its freshness.
+fn freshness(metric: &MetricValueResponse) -> Option<[(HeaderName, String); 2]> {
+    metric.expires_at.map(|expires_at| freshness_headers(expires_at, chrono::Utc::now().timestamp()))
+}
+
+/// A worker's answer to a read of a metric's latest value.
+pub(crate) enum LatestRead {
+    NotModified(String),
+    Value(MetricValueResponse, Option<String>),
+}
+
+/// Reads a metric's latest value from `worker_url`. A worker that cannot be
+/// reached or answers with a server error is `Unavailable`, so the read can
+/// be tried elsewhere; any other failure is final.
+pub(crate) async fn read_latest(
+    state: &ControlState,
+    deadline: &Deadline,
+    worker_url: &str,
+    name: &str,
+    query: &Option<String>,
+    headers: &HeaderMap,
+) -> Result<LatestRead> {
+    let mut request = state.read_pool.client().get(format!("{}/metrics/{}{}", worker_url, name, query_suffix(query.clone())));
+    for tag in headers.get_all(IF_NONE_MATCH).iter().filter_map(|tag| tag.to_str().ok()) {
+        request = request.header(reqwest::header::IF_NONE_MATCH, tag);
+    }
+    let response = send_within(state, &state.read_pool, request, deadline, worker_url).await?;
+
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_string);
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(LatestRead::NotModified(etag.unwrap_or_default()));
+    }
+    if !status.is_success() {
+        return Err(worker_error(response, "Worker failed to retrieve metric").await);
+    }
+
+    let metric = response.json().await
         .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))?;
-    
-    Ok(Json(metric_response))
+    Ok(LatestRead::Value(metric, etag))
+}
+
+/// How the control node serves a read of a metric's latest value.
+struct ReadOptions {
+    /// Whether the cached value may answer once every worker has failed.
+    allow_stale: bool,
+    /// Whether to read every copy and answer with the newest.
+    quorum: bool,
+}
+
+/// Splits `allow_stale` and `consistency` out of a read's query string; the
+/// rest goes to the worker untouched. Stale reads are allowed unless
+/// `allow_stale` is `false`, and `consistency` is `one` (the default) or
+/// `quorum`.
+fn take_read_options(query: Option<String>) -> Result<(Option<String>, ReadOptions)> {
+    let mut options = ReadOptions { allow_stale: true, quorum: false };
+    let Some(query) = query else {
+        return Ok((None, options));
+    };
+    let mut rest = Vec::new();
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("allow_stale", value)) => {
+                options.allow_stale = value.parse().map_err(|_| RaftMetricsError::InvalidRequest(format!(
+                    "allow_stale must be true or false, not '{}'", value
+                )))?;
+            }
+            Some(("consistency", value)) => {
+                options.quorum = match value {
+                    "one" => false,
+                    "quorum" => true,
+                    _ => return Err(RaftMetricsError::InvalidRequest(format!(
+                        "consistency must be one or quorum, not '{}'", value
+                    ))),
+                };
+            }
+            _ => rest.push(pair),
+        }
+    }
+    Ok(((!rest.is_empty()).then(|| rest.join("&")), options))
 }
 
 async fn get_metric_aggregate(
     State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
     Path(name): Path<String>,
+    RawQuery(query): RawQuery,
 ) -> Result<Json<MetricAggregateResponse>> {
     info!("Calculating aggregate for metric: {}", name);
     
-    let worker_count = state.worker_urls.len();
-    let partition = get_partition(&name, worker_count);
+    let partition = state.router.route(&name);
+    routing::record_assignment(partition);
     let worker_url = &state.worker_urls[partition];
     debug!("Metric '{}' hashed to partition {} ({})", name, partition, worker_url);
     
-    let response = state.http_client.get(&format!("{}/metrics/{}/aggregate", worker_url, name))
-        .send()
-        .await
-        .map_err(|e| RaftMetricsError::Internal(format!("Failed to forward request to worker: {}", e)))?;
-        
+    let response = send_within(
+            &state,
+            &state.read_pool,
+            state.read_pool.client().get(format!("{}/metrics/{}/aggregate{}", worker_url, name, query_suffix(query))),
+            &deadline,
+            worker_url,
+        )
+        .await?;
+
     if !response.status().is_success() {
-        let error_text = response.text().await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(RaftMetricsError::Internal(format!("Worker failed to retrieve aggregate: {}", error_text)));
+        return Err(worker_error(response, "Worker failed to retrieve aggregate").await);
     }
     
     let aggregate_response: MetricAggregateResponse = response.json().await
@@ -139,38 +1330,4212 @@ async fn get_metric_aggregate(
     Ok(Json(aggregate_response))
 }
 
-pub async fn start_control_node() {
-    let storage = Arc::new(MemStorage::new());
-    let metrics = Arc::new(MetricsRegistry::new());
+/// Re-encodes a client query string for forwarding to a worker.
+fn query_suffix(query: Option<String>) -> String {
+    query.map(|q| format!("?{}", q)).unwrap_or_default()
+}
 
-    // Parse worker URLs from environment variable
-    let worker_urls: Vec<String> = std::env::var("WORKER_HOSTS")
-        .unwrap_or_else(|_| "http://localhost:8081".to_string())
-        .split(',')
-        .map(|host| {
-            if host.starts_with("http://") {
-                host.to_string()
-            } else {
-                format!("http://{}", host)
+/// Forwards a read to the worker owning `name` and decodes its JSON body.
+/// `path` is appended to the worker URL and should include any query string.
+async fn forward_read<T: serde::de::DeserializeOwned>(
+    state: &ControlState,
+    deadline: &Deadline,
+    name: &str,
+    path: &str,
+) -> Result<T> {
+    let partition = state.router.route(name);
+    let worker_url = &state.worker_urls[partition];
+    debug!("Metric '{}' hashed to partition {} ({})", name, partition, worker_url);
+
+    let response = send_within(
+            state,
+            &state.read_pool,
+            state.read_pool.client().get(format!("{}{}", worker_url, path)),
+            deadline,
+            worker_url,
+        )
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(worker_error(response, &format!("Worker failed to serve {}", path)).await);
+    }
+
+    response.json().await
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+}
+
+async fn get_metric_delta(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<MetricDeltaResponse>> {
+    info!("Calculating delta for metric: {}", name);
+
+    let path = format!("/metrics/{}/delta?{}", name, query.unwrap_or_default());
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+async fn compare_metric(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<CompareResponse>> {
+    info!("Comparing metric: {}", name);
+
+    let path = format!("/metrics/{}/compare{}", name, query_suffix(query));
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+async fn get_metric_history(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<MetricHistoryResponse>> {
+    info!("Reading history of metric: {}", name);
+
+    let path = format!("/metrics/{}/history{}", name, query_suffix(query));
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+/// Fetches aggregates for many metrics at once: names are grouped by
+/// partition and each owning worker is asked once, concurrently.
+async fn get_aggregate_batch(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    Json(request): Json<BatchAggregateRequest>,
+) -> Result<Json<BatchAggregateResponse>> {
+    info!("Calculating aggregates for {} metrics", request.names.len());
+    if request.names.len() > MAX_BATCH_AGGREGATE_NAMES {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "at most {} names per batch", MAX_BATCH_AGGREGATE_NAMES
+        )));
+    }
+    state.authorize(&headers, request.names.iter().map(String::as_str), Access::Read)?;
+
+    let path = format!("/aggregate/batch{}", query_suffix(query));
+    let replies = fan_out_by_partition::<_, BatchAggregateResponse>(
+        &state, deadline, request.names, &path, |names| BatchAggregateRequest { names }, "Worker failed to serve batch aggregate",
+    )
+    .await?;
+    let mut aggregates = BatchAggregateResponse::new();
+    for (_, reply) in replies {
+        aggregates.extend(reply?);
+    }
+    Ok(Json(aggregates))
+}
+
+/// Forwards a read of the series with exactly the labels asked for to the
+/// worker its writes are routed to, so a name split over partitions by
+/// `PARTITION_LABEL_KEYS` is read from the right one.
+async fn get_series(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    Json(request): Json<SeriesRequest>,
+) -> Result<Json<SeriesResponse>> {
+    info!("Retrieving series of {} with {} labels", request.name, request.labels.len());
+    state.authorize(&headers, [request.name.as_str()], Access::Read)?;
+
+    let partition = state.route_write(&request.name, &request.labels);
+    routing::record_assignment(partition);
+    let worker_url = &state.worker_urls[partition];
+    let http = state.read_pool.client()
+        .post(format!("{}/metrics/get{}", worker_url, query_suffix(query)))
+        .json(&request);
+    let response = send_within(&state, &state.read_pool, http, &deadline, worker_url).await?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to retrieve series").await);
+    }
+    let series = response.json().await
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))?;
+    Ok(Json(series))
+}
+
+/// Latest values of many metrics, one worker call per partition. A failing
+/// worker only marks its own names as errored.
+async fn get_metrics(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    Json(request): Json<MultiGetRequest>,
+) -> Result<Json<MultiGetResponse>> {
+    info!("Retrieving {} metrics", request.names.len());
+    if request.names.len() > MAX_MULTI_GET_NAMES {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "at most {} names per request", MAX_MULTI_GET_NAMES
+        )));
+    }
+    state.authorize(&headers, request.names.iter().map(String::as_str), Access::Read)?;
+
+    let path = format!("/metrics/multi_get{}", query_suffix(query));
+    let replies = fan_out_by_partition::<_, MultiGetResponse>(
+        &state, deadline, request.names, &path, |names| MultiGetRequest { names }, "Worker failed to serve metrics",
+    )
+    .await?;
+    let mut merged = MultiGetResponse::default();
+    for (names, result) in replies {
+        match result {
+            Ok(response) => {
+                merged.values.extend(response.values);
+                merged.missing.extend(response.missing);
             }
-        })
-        .collect();
+            Err(e) => {
+                warn!("Failed to read {} metrics: {}", names.len(), e);
+                let reason = e.to_string();
+                merged.errors.extend(names.into_iter().map(|name| (name, reason.clone())));
+            }
+        }
+    }
+    merged.missing.sort();
+    Ok(Json(merged))
+}
 
-    info!("Configured worker URLs: {:?}", worker_urls);
+/// Groups `names` by the partition they route to, dropping repeats, and
+/// POSTs each group as `body(names)` to `path` on its worker, all at once.
+/// Every group comes back with its worker's reply, or the error reading it
+/// met, so each caller decides whether one failing worker fails the whole.
+async fn fan_out_by_partition<B, R>(
+    state: &ControlState,
+    deadline: Deadline,
+    names: Vec<String>,
+    path: &str,
+    body: fn(Vec<String>) -> B,
+    action: &'static str,
+) -> Result<Vec<(Vec<String>, Result<R>)>>
+where
+    B: Serialize,
+    R: serde::de::DeserializeOwned + Send + 'static,
+{
+    let mut partitions: Vec<Vec<String>> = vec![Vec::new(); state.worker_urls.len()];
+    for name in names {
+        let names = &mut partitions[state.router.route(&name)];
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
 
-    let state = ControlState {
-        storage: storage.clone(),
-        metrics: metrics.clone(),
-        worker_urls: Arc::new(worker_urls),
-        http_client: Arc::new(reqwest::Client::new()),
-    };
+    let mut calls = tokio::task::JoinSet::new();
+    for (partition, names) in partitions.into_iter().enumerate().filter(|(_, names)| !names.is_empty()) {
+        let state = state.clone();
+        let request = state.read_pool.client()
+            .post(format!("{}{}", state.worker_urls[partition], path))
+            .json(&body(names.clone()));
+        calls.spawn(async move {
+            let worker_url = &state.worker_urls[partition];
+            let result = async {
+                let response = send_within(&state, &state.read_pool, request, &deadline, worker_url).await?;
+                if !response.status().is_success() {
+                    return Err(worker_error(response, action).await);
+                }
+                response.json::<R>().await
+                    .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+            };
+            (names, result.await)
+        });
+    }
 
-    let app = control_router(state);
+    let mut results = Vec::with_capacity(calls.len());
+    while let Some(call) = calls.join_next().await {
+        results.push(call.map_err(|e| RaftMetricsError::Internal(format!("{} task failed: {}", path, e)))?);
+    }
+    Ok(results)
+}
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    info!("Starting control node on {}", addr);
+/// Idle metrics across every worker, merged into one page in name order.
+/// Fails whole when a worker cannot be read, so a cleanup job never mistakes
+/// a partial listing for a complete one.
+async fn get_stale_metrics(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<StaleQuery>,
+    headers: HeaderMap,
+) -> Result<Json<StaleMetricsPage>> {
+    info!("Listing metrics idle for {}s", query.idle_seconds);
+    let limit = query.limit()?;
+    let (state, idle_seconds) = (&state, query.idle_seconds);
+    let name: fn(&StaleMetric) -> &str = |metric| &metric.name;
+    let (metrics, next) = readable_page(state, &headers, query.after, limit, name, move |after, limit| async move {
+        let page = fetch_stale_page(state, deadline, StaleQuery { idle_seconds, after, limit: Some(limit) }).await?;
+        Ok((page.metrics, page.next))
+    })
+    .await?;
+    Ok(Json(StaleMetricsPage { metrics, next }))
+}
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// Pages through a name-ordered listing from the cursor `after` until it
+/// holds `limit` entries the API key in `headers` may read. Names under
+/// prefixes the key cannot read are left out of both the entries and the
+/// cursor. `fetch` reads one merged page of at most the given size after
+/// a cursor.
+async fn readable_page<T, F, Fut>(
+    state: &ControlState,
+    headers: &HeaderMap,
+    mut after: Option<String>,
+    limit: usize,
+    name: fn(&T) -> &str,
+    fetch: F,
+) -> Result<(Vec<T>, Option<String>)>
+where
+    F: Fn(Option<String>, usize) -> Fut,
+    Fut: std::future::Future<Output = Result<(Vec<T>, Option<String>)>>,
+{
+    let mut readable = Vec::new();
+    loop {
+        let (page, next) = fetch(after, limit - readable.len()).await?;
+        readable.extend(page.into_iter().filter(|entry| state.readable(headers, name(entry))));
+        match next {
+            // Everything up to the last readable name has been seen, so it
+            // serves as the cursor in place of one the key cannot read
+            Some(next) if readable.len() == limit => {
+                let next = match state.readable(headers, &next) {
+                    true => next,
+                    false => readable.last().map(|entry| name(entry).to_string()).unwrap_or(next),
+                };
+                return Ok((readable, Some(next)));
+            }
+            Some(next) => after = Some(next),
+            None => return Ok((readable, None)),
+        }
+    }
+}
+
+/// One page of idle metrics across every worker, merged in name order.
+pub(crate) async fn fetch_stale_page(state: &ControlState, deadline: Deadline, query: StaleQuery) -> Result<StaleMetricsPage> {
+    let limit = query.limit()?;
+    let query = Arc::new(query);
+
+    let mut calls = tokio::task::JoinSet::new();
+    for worker_url in state.worker_urls.iter() {
+        let state = state.clone();
+        let worker_url = worker_url.clone();
+        let query = query.clone();
+        calls.spawn(async move {
+            let request = state.read_pool.client().get(format!("{}/metrics/stale", worker_url)).query(&*query);
+            let response = send_within(&state, &state.read_pool, request, &deadline, &worker_url).await?;
+            if !response.status().is_success() {
+                return Err(worker_error(response, "Worker failed to list stale metrics").await);
+            }
+            response.json::<StaleMetricsPage>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+        });
+    }
+
+    let mut merged = StaleMetricsPage::default();
+    let mut more = false;
+    while let Some(call) = calls.join_next().await {
+        let page = call.map_err(|e| RaftMetricsError::Internal(format!("Stale listing task failed: {}", e)))??;
+        more |= page.next.is_some();
+        merged.metrics.extend(page.metrics);
+    }
+    merged.metrics.sort_by(|a, b| a.name.cmp(&b.name));
+    more |= merged.metrics.len() > limit;
+    merged.metrics.truncate(limit);
+    merged.next = more.then(|| merged.metrics.last().map(|metric| metric.name.clone())).flatten();
+    Ok(merged)
+}
+
+/// The hottest metrics across every worker. Each worker ranks its own top
+/// `n` and the merge keeps the first `n` of those, as every metric lives
+/// on one worker. A worker that cannot be read fails the request.
+async fn get_top_metrics(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<TopQuery>,
+    headers: HeaderMap,
+) -> Result<Json<TopMetricsResponse>> {
+    let n = query.n()?;
+    let query = Arc::new(TopQuery { n: Some(n), ..query });
+
+    let mut calls = tokio::task::JoinSet::new();
+    for worker_url in state.worker_urls.iter() {
+        let state = state.clone();
+        let worker_url = worker_url.clone();
+        let query = query.clone();
+        calls.spawn(async move {
+            let request = state.read_pool.client().get(format!("{}/debug/top", worker_url)).query(&*query);
+            let response = send_within(&state, &state.read_pool, request, &deadline, &worker_url).await?;
+            if !response.status().is_success() {
+                return Err(worker_error(response, "Worker failed to rank metrics").await);
+            }
+            response.json::<TopMetricsResponse>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+        });
+    }
+
+    let mut merged = TopMetricsResponse { by: query.by, metrics: Vec::new() };
+    while let Some(call) = calls.join_next().await {
+        let top = call.map_err(|e| RaftMetricsError::Internal(format!("Top metrics task failed: {}", e)))??;
+        merged.metrics.extend(top.metrics.into_iter().filter(|metric| state.readable(&headers, &metric.name)));
+    }
+    merged.metrics.sort_by(|a, b| query.by.rank(a, b));
+    merged.metrics.truncate(n);
+    Ok(Json(merged))
+}
+
+/// Each tenant's quota and consumption across the cluster: series and
+/// storage summed over workers, with each worker's share, as quotas on
+/// those are enforced per worker, and the rate forwarded here. Tenants
+/// with a metric the API key may not read are left out. A worker that
+/// cannot be read fails the request.
+async fn get_usage(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<UsageReport>>> {
+    let mut calls = tokio::task::JoinSet::new();
+    for worker_url in state.worker_urls.iter() {
+        let state = state.clone();
+        let worker_url = worker_url.clone();
+        calls.spawn(async move {
+            let request = state.read_pool.client().get(format!("{}/usage", worker_url));
+            let response = send_within(&state, &state.read_pool, request, &deadline, &worker_url).await?;
+            if !response.status().is_success() {
+                return Err(worker_error(response, "Worker failed to report usage").await);
+            }
+            let usage = response.json::<Vec<UsageReport>>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))?;
+            Ok((worker_url, usage))
+        });
+    }
+
+    let quotas = &state.config.quotas;
+    let mut merged: BTreeMap<String, UsageReport> = quotas.tenants.iter()
+        .map(|(tenant, quota)| (tenant.clone(), UsageReport {
+            tenant: tenant.clone(),
+            quota: *quota,
+            series: 0,
+            storage_bytes: 0,
+            samples_per_sec: None,
+            workers: BTreeMap::new(),
+        }))
+        .collect();
+    while let Some(call) = calls.join_next().await {
+        let (worker_url, usage) = call.map_err(|e| RaftMetricsError::Internal(format!("Usage task failed: {}", e)))??;
+        for report in usage {
+            let merged = merged.entry(report.tenant.clone()).or_insert_with(|| UsageReport {
+                tenant: report.tenant.clone(),
+                quota: quotas.quota(&report.tenant).copied().unwrap_or_default(),
+                series: 0,
+                storage_bytes: 0,
+                samples_per_sec: None,
+                workers: BTreeMap::new(),
+            });
+            merged.series += report.series;
+            merged.storage_bytes += report.storage_bytes;
+            merged.workers.insert(worker_url.clone(), TenantUsage { series: report.series, storage_bytes: report.storage_bytes });
+        }
+    }
+    merged.retain(|tenant, _| state.readable_prefix(&headers, &format!("{}{}", tenant, quotas.separator)));
+    let now = Instant::now();
+    for report in merged.values_mut() {
+        report.samples_per_sec = state.rate_limiter.rate(&report.tenant, now);
+    }
+    Ok(Json(merged.into_values().collect()))
+}
+
+/// One page of aggregates across every worker, in name order. Each worker
+/// is asked for a full page after the same cursor, so the first `limit`
+/// names of the merge are complete. A worker that cannot be read fails the
+/// request rather than leaving a gap the cursor would skip over.
+async fn get_aggregates(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<AggregatesQuery>,
+    headers: HeaderMap,
+) -> Result<Json<AggregatesPage>> {
+    info!("Listing aggregates under '{}'", query.prefix);
+    let limit = query.limit()?;
+    let (state, prefix) = (&state, &query.prefix);
+    let name: fn(&MetricAggregateResponse) -> &str = |aggregate| &aggregate.name;
+    let (aggregates, next) = readable_page(state, &headers, query.after.clone(), limit, name, move |after, limit| {
+        let query = AggregatesQuery { prefix: prefix.clone(), after, limit: Some(limit) };
+        async move {
+            let page = fetch_aggregates_page(state, deadline, query).await?;
+            Ok((page.aggregates, page.next))
+        }
+    })
+    .await?;
+    Ok(Json(AggregatesPage { aggregates, next }))
+}
+
+/// One page of aggregates across every worker, merged in name order.
+async fn fetch_aggregates_page(state: &ControlState, deadline: Deadline, query: AggregatesQuery) -> Result<AggregatesPage> {
+    let limit = query.limit()?;
+    let query = Arc::new(query);
+    let mut calls = tokio::task::JoinSet::new();
+    for worker_url in state.worker_urls.iter() {
+        let state = state.clone();
+        let worker_url = worker_url.clone();
+        let query = query.clone();
+        calls.spawn(async move {
+            let request = state.read_pool.client().get(format!("{}/aggregates", worker_url)).query(&*query);
+            let response = send_within(&state, &state.read_pool, request, &deadline, &worker_url).await?;
+            if !response.status().is_success() {
+                return Err(worker_error(response, "Worker failed to list aggregates").await);
+            }
+            response.json::<AggregatesPage>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+        });
+    }
+
+    // Everything up to the earliest cursor has been seen from every worker
+    let mut merged = AggregatesPage::default();
+    let earliest = |next: Option<String>, name: String| match next {
+        Some(next) => Some(next.min(name)),
+        None => Some(name),
+    };
+    while let Some(call) = calls.join_next().await {
+        let page = call.map_err(|e| RaftMetricsError::Internal(format!("Aggregate listing task failed: {}", e)))??;
+        if let Some(next) = page.next {
+            merged.next = earliest(merged.next, next);
+        }
+        merged.aggregates.extend(page.aggregates);
+    }
+    merged.aggregates.sort_by(|a, b| a.name.cmp(&b.name));
+    if merged.aggregates.len() > limit {
+        merged.aggregates.truncate(limit);
+        merged.next = earliest(merged.next, merged.aggregates[limit - 1].name.clone());
+    }
+    if let Some(next) = &merged.next {
+        merged.aggregates.retain(|aggregate| &aggregate.name <= next);
+    }
+    Ok(merged)
+}
+
+/// Namespace tree under a prefix, merged from every worker's. A worker that
+/// cannot be read fails the request, since its metrics would be missing
+/// from every total.
+async fn get_metric_tree(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<TreeQuery>,
+    headers: HeaderMap,
+) -> Result<Json<NamespaceTree>> {
+    info!("Rolling up metrics under '{}'", query.prefix);
+    let mut tree = fetch_metric_tree(&state, deadline, query).await?;
+    // A namespace is hidden along with the metrics directly under it
+    let separator = tree.separator.clone();
+    tree.retain(|path| state.readable(&headers, path) && state.readable(&headers, &format!("{}{}", path, separator)));
+    Ok(Json(tree))
+}
+
+/// The merged tree behind `GET /metrics/tree`, also read by reports.
+pub(crate) async fn fetch_metric_tree(state: &ControlState, deadline: Deadline, query: TreeQuery) -> Result<NamespaceTree> {
+    let shape = query.shape()?;
+    let key = shape.key();
+    if state.tree_cache.enabled() {
+        if let Some(tree) = state.tree_cache.get(&key, Instant::now()) {
+            CONTROL_CACHE_HITS_TOTAL.inc();
+            return Ok(tree);
+        }
+    }
+    let ticket = state.tree_cache.enabled().then(|| state.tree_cache.begin(&key));
+
+    let query = Arc::new(query);
+    let mut calls = tokio::task::JoinSet::new();
+    for worker_url in state.worker_urls.iter() {
+        let state = state.clone();
+        let worker_url = worker_url.clone();
+        let query = query.clone();
+        calls.spawn(async move {
+            let request = state.read_pool.client().get(format!("{}/metrics/tree", worker_url)).query(&*query);
+            let response = send_within(&state, &state.read_pool, request, &deadline, &worker_url).await?;
+            if !response.status().is_success() {
+                return Err(worker_error(response, "Worker failed to roll up metrics").await);
+            }
+            response.json::<NamespaceTree>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))
+        });
+    }
+
+    let mut trees = Vec::with_capacity(state.worker_urls.len());
+    while let Some(call) = calls.join_next().await {
+        trees.push(call.map_err(|e| RaftMetricsError::Internal(format!("Namespace tree task failed: {}", e)))??);
+    }
+    let tree = NamespaceTree::merge(&shape, trees);
+    if let Some(ticket) = ticket {
+        state.tree_cache.insert(&key, ticket, tree.clone(), Instant::now());
+    }
+    Ok(tree)
+}
+
+async fn get_metric_groupby(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<GroupByResponse>> {
+    info!("Grouping metric: {}", name);
+
+    let path = format!("/metrics/{}/groupby?{}", name, query.unwrap_or_default());
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+async fn get_metric_buckets(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<BucketsResponse>> {
+    info!("Bucketing metric: {}", name);
+
+    let path = format!("/metrics/{}/buckets?{}", name, query.unwrap_or_default());
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+/// Proxies the owning worker's NDJSON range stream chunk by chunk, so the
+/// range is never held in memory here. The stream ends early if it outlasts
+/// the read pool's request timeout or the request deadline.
+async fn get_metric_range(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Response> {
+    info!("Streaming range for metric: {}", name);
+
+    let partition = state.router.route(&name);
+    let worker_url = &state.worker_urls[partition];
+    debug!("Metric '{}' hashed to partition {} ({})", name, partition, worker_url);
+
+    let response = send_within(
+            &state,
+            &state.read_pool,
+            state.read_pool.client().get(format!("{}/metrics/{}/range{}", worker_url, name, query_suffix(query))),
+            &deadline,
+            worker_url,
+        )
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to stream range").await);
+    }
+
+    let chunks = stream::unfold(Some(response), |response| async move {
+        let mut response = response?;
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+            Ok(None) => None,
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(chunks)).into_response())
+}
+
+async fn delete_metric(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    info!("Deleting metric: {}", name);
+
+    let partition = state.router.route(&name);
+    let worker_url = &state.worker_urls[partition];
+    debug!("Metric '{}' hashed to partition {} ({})", name, partition, worker_url);
+
+    let response = send_within(
+            &state,
+            &state.write_pool,
+            state.write_pool.client().delete(format!("{}/metrics/{}", worker_url, name)),
+            &deadline,
+            worker_url,
+        )
+        .await;
+    state.read_cache.invalidate(&name);
+    let response = response?;
+
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to delete metric").await);
+    }
+    state.shadow.mirror(partition, move |client, url| client.delete(format!("{}/metrics/{}", url, name)));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Installs a hook on the worker owning its metric, or on every worker for a
+/// prefix hook since a prefix can span partitions. Needs `ADMIN_TOKEN`,
+/// since a hook makes the workers post to an address of the caller's
+/// choosing, and that key must be able to read every metric it can match.
+async fn put_hook(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(hook): Json<HookConfig>,
+) -> Result<StatusCode> {
+    info!("Installing hook '{}' on {}", id, hook.metric);
+    hook.validate()?;
+    // The webhook is sent the values it watches, so it needs their readers' key
+    match hook.prefix() {
+        Some(prefix) => state.authorize_prefix(&headers, prefix, Access::Read)?,
+        None => state.authorize(&headers, [hook.metric.as_str()], Access::Read)?,
+    }
+
+    let targets: Vec<&String> = match hook.prefix() {
+        Some(_) => state.worker_urls.iter().collect(),
+        None => vec![&state.worker_urls[state.router.route(&hook.metric)]],
+    };
+
+    for worker_url in targets {
+        let mut request = state.write_pool.client().put(format!("{}/hooks/{}", worker_url, id)).json(&hook);
+        if let Some(token) = &state.config.admin_token {
+            request = request.bearer_auth(token);
+        }
+        let response = send_within(&state, &state.write_pool, request, &deadline, worker_url).await?;
+        if !response.status().is_success() {
+            return Err(worker_error(response, "Worker failed to install hook").await);
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets the sample rate on the worker owning `name`. Needs `ADMIN_TOKEN`,
+/// since a rate thins out what every later writer stores.
+async fn put_sample_rate(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Json(request): Json<SampleRateRequest>,
+) -> Result<StatusCode> {
+    info!("Sampling '{}' at {}", name, request.sample_rate);
+    if !(0.0..=1.0).contains(&request.sample_rate) {
+        return Err(RaftMetricsError::InvalidRequest("sample_rate must be between 0 and 1".to_string()));
+    }
+
+    let worker_url = &state.worker_urls[state.router.route(&name)];
+    let mut request = state.write_pool.client()
+        .put(format!("{}/metrics/{}/sample_rate", worker_url, name))
+        .json(&request);
+    if let Some(token) = &state.config.admin_token {
+        request = request.bearer_auth(token);
+    }
+    let response = send_within(&state, &state.write_pool, request, &deadline, worker_url).await?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to set sample rate").await);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets the ingest transform on the worker owning `name`.
+async fn put_transform(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Json(request): Json<IngestTransform>,
+) -> Result<StatusCode> {
+    info!("Recording '{}' as value * {} + {}", name, request.scale, request.offset);
+    let transform = IngestTransform::new(request.scale, request.offset)?;
+
+    let partition = state.router.route(&name);
+    let worker_url = &state.worker_urls[partition];
+    let request = state.write_pool.client().put(format!("{}/metrics/{}/transform", worker_url, name)).json(&transform);
+    let response = send_within(&state, &state.write_pool, request, &deadline, worker_url).await?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to set transform").await);
+    }
+    state.shadow.mirror(partition, move |client, url| client.put(format!("{}/metrics/{}/transform", url, name)).json(&transform));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes `name` a state metric on the worker owning it.
+async fn put_states(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Json(request): Json<StateDefinition>,
+) -> Result<StatusCode> {
+    info!("Tracking '{}' as one of {:?}", name, request.states);
+    StateMetric::new(request.states.clone(), request.stale_after_secs)?;
+
+    let partition = state.router.route(&name);
+    let worker_url = &state.worker_urls[partition];
+    let forwarded = state.write_pool.client().put(format!("{}/metrics/{}/states", worker_url, name)).json(&request);
+    let response = send_within(&state, &state.write_pool, forwarded, &deadline, worker_url).await?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to define states").await);
+    }
+    state.read_cache.invalidate(&name);
+    state.shadow.mirror(partition, move |client, url| client.put(format!("{}/metrics/{}/states", url, name)).json(&request));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_states(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<Json<StateReport>> {
+    info!("Getting states of metric: {}", name);
+
+    let path = format!("/metrics/{}/states", name);
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+/// Counts `name`'s samples into buckets on the worker owning it.
+async fn put_histogram(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<HistogramQuery>,
+    Json(request): Json<HistogramDefinition>,
+) -> Result<StatusCode> {
+    info!("Counting '{}' into buckets", name);
+    HistogramMetric::new(request.buckets.clone().unwrap_or_else(histograms::default_bounds))?;
+
+    let partition = state.router.route(&name);
+    let worker_url = &state.worker_urls[partition];
+    let forwarded = state.write_pool.client().put(format!("{}/metrics/{}/histogram", worker_url, name)).query(&query).json(&request);
+    let response = send_within(&state, &state.write_pool, forwarded, &deadline, worker_url).await?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to define histogram").await);
+    }
+    state.shadow.mirror(partition, move |client, url| {
+        client.put(format!("{}/metrics/{}/histogram", url, name)).query(&query).json(&request)
+    });
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_histogram(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<Json<HistogramReport>> {
+    info!("Getting histogram of metric: {}", name);
+
+    let path = format!("/metrics/{}/histogram", name);
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+/// Sums the buckets of several histogram metrics, wherever they live. Every
+/// one must exist and share the first one's layout, or the merge fails.
+async fn merge_histograms(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    headers: HeaderMap,
+    Json(request): Json<HistogramMergeRequest>,
+) -> Result<Json<MergedHistogram>> {
+    info!("Merging {} histograms", request.names.len());
+    if request.names.len() > MAX_MULTI_GET_NAMES {
+        return Err(RaftMetricsError::InvalidRequest(format!("at most {} names per request", MAX_MULTI_GET_NAMES)));
+    }
+    state.authorize(&headers, request.names.iter().map(String::as_str), Access::Read)?;
+    let mut names: Vec<String> = Vec::with_capacity(request.names.len());
+    for name in request.names {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let reads = names.iter().map(|name| {
+        let path = format!("/metrics/{}/histogram", name);
+        let (state, deadline) = (&state, &deadline);
+        async move { forward_read::<HistogramReport>(state, deadline, name, &path).await }
+    });
+    let mut reports = names.iter().zip(futures_util::future::join_all(reads).await);
+    let Some((_, first)) = reports.next() else {
+        return Err(RaftMetricsError::InvalidRequest("name at least one histogram".to_string()));
+    };
+    let mut merged = first?.histogram;
+    for (name, report) in reports {
+        merged.merge(&report?.histogram).map_err(|_| {
+            RaftMetricsError::Conflict(format!("'{}' has a different bucket layout from '{}'", name, names[0]))
+        })?;
+    }
+
+    Ok(Json(MergedHistogram { names, histogram: merged }))
+}
+
+async fn get_quarantine(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<Json<QuarantineResponse>> {
+    info!("Listing quarantined samples of metric: {}", name);
+
+    let path = format!("/metrics/{}/quarantine", name);
+    Ok(Json(forward_read(&state, &deadline, &name, &path).await?))
+}
+
+/// Applies `name`'s quarantined samples on the worker owning it.
+async fn apply_quarantine(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    info!("Applying quarantined samples of metric: {}", name);
+
+    let worker_url = &state.worker_urls[state.router.route(&name)];
+    let request = state.write_pool.client().post(format!("{}/metrics/{}/quarantine/apply", worker_url, name));
+    let response = send_within(&state, &state.write_pool, request, &deadline, worker_url).await;
+    state.read_cache.invalidate(&name);
+    let response = response?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to apply quarantined samples").await);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reset_metric(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<ResetQuery>,
+) -> Result<StatusCode> {
+    info!("Resetting metric: {}", name);
+
+    let partition = state.router.route(&name);
+    let worker_url = &state.worker_urls[partition];
+    let request = state.write_pool.client().post(format!("{}/metrics/{}/reset", worker_url, name)).query(&query);
+    let response = send_within(&state, &state.write_pool, request, &deadline, worker_url).await;
+    state.read_cache.invalidate(&name);
+    let response = response?;
+    if !response.status().is_success() {
+        return Err(worker_error(response, "Worker failed to reset metric").await);
+    }
+    state.shadow.mirror(partition, move |client, url| client.post(format!("{}/metrics/{}/reset", url, name)).query(&query));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Probes every worker's health and raft state in parallel and lists them
+/// in partition order.
+async fn cluster_topology(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+) -> Json<ClusterTopology> {
+    let mut probes = tokio::task::JoinSet::new();
+    for (partition, url) in state.worker_urls.iter().enumerate() {
+        let state = state.clone();
+        let url = url.clone();
+        probes.spawn(async move { probe_worker(&state, &deadline, partition, url).await });
+    }
+
+    let mut workers = Vec::with_capacity(state.worker_urls.len());
+    while let Some(probe) = probes.join_next().await {
+        match probe {
+            Ok(worker) => workers.push(worker),
+            Err(e) => warn!("Worker probe failed: {}", e),
+        }
+    }
+    workers.sort_by_key(|worker| worker.partition);
+    Json(ClusterTopology { workers })
+}
+
+async fn probe_worker(state: &ControlState, deadline: &Deadline, partition: usize, url: String) -> WorkerTopology {
+    let get = |path: &str| state.read_pool.client().get(format!("{}{}", url, path));
+
+    // An unhealthy worker answers 503 with its status in the body
+    let status = match send_within(state, &state.read_pool, get("/health"), deadline, &url).await {
+        Ok(response)
+            if response.status().is_success() || response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE =>
+        {
+            match response.json::<WorkerHealthResponse>().await {
+                Ok(body) => Some(body.status),
+                Err(e) => {
+                    warn!("Worker {} sent an unreadable health body: {}", url, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let raft = match send_within(state, &state.read_pool, get("/debug/raft"), deadline, &url).await {
+        Ok(response) if response.status().is_success() => match response.json::<RaftDebugResponse>().await {
+            Ok(raft) => Some(raft),
+            Err(e) => {
+                warn!("Worker {} sent an unreadable raft status: {}", url, e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    WorkerTopology {
+        partition,
+        status: status.unwrap_or_else(|| "unknown".to_string()),
+        url,
+        raft,
+    }
+}
+
+/// Clears every worker. Each worker applies the reset through its own raft
+/// group, so all of its replicas clear consistently.
+async fn reset_all(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+) -> Result<Json<WriteResponse>> {
+    info!("Resetting all {} workers", state.worker_urls.len());
+
+    let mut failed = Vec::new();
+    for worker_url in state.worker_urls.iter() {
+        let mut request = state.write_pool.client().post(format!("{}/admin/reset", worker_url));
+        if let Some(token) = &state.config.admin_token {
+            request = request.bearer_auth(token);
+        }
+        match send_within(&state, &state.write_pool, request, &deadline, worker_url).await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => failed.push(format!("{} ({})", worker_url, response.status())),
+            Err(e) => failed.push(format!("{} ({})", worker_url, e)),
+        }
+    }
+    state.read_cache.clear();
+    state.tree_cache.clear();
+
+    if !failed.is_empty() {
+        return Err(RaftMetricsError::Internal(format!(
+            "Reset failed on {} of {} workers: {}",
+            failed.len(),
+            state.worker_urls.len(),
+            failed.join(", ")
+        )));
+    }
+
+    Ok(Json(WriteResponse {
+        success: true,
+        message: format!("Reset {} workers", state.worker_urls.len()),
+        version: None,
+        ack: None,
+        degraded: false,
+    }))
+}
+
+/// How often read counts are saved for the next start to prime with.
+const HOT_READS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The metrics to prime: those in `CACHE_PRIME_METRICS`, then the
+/// `CACHE_PRIME_TOP` most read.
+fn prime_targets(state: &ControlState) -> Vec<String> {
+    let config = &state.config.cache_prime;
+    let mut names = config.metrics.clone();
+    let hottest = state.hot_reads.hottest(config.top + names.len());
+    let hot: Vec<String> = hottest.into_iter().filter(|name| !names.contains(name)).take(config.top).collect();
+    names.extend(hot);
+    names
+}
+
+/// Reads the latest value of each metric to prime from its owning worker
+/// into the read cache, `CACHE_PRIME_CONCURRENCY` at a time.
+pub(crate) async fn prime_read_cache(state: &ControlState, deadline: &Deadline) -> PrimeSummary {
+    let mut summary = PrimeSummary::default();
+    if !state.read_cache.enabled() {
+        return summary;
+    }
+    let headers = &HeaderMap::new();
+    let mut reads = stream::iter(prime_targets(state))
+        .map(|name| async move {
+            let worker_url = &state.worker_urls[state.router.route(&name)];
+            let ticket = state.read_cache.begin(&name);
+            let read = read_latest(state, deadline, worker_url, &name, &None, headers).await;
+            (name, ticket, read)
+        })
+        .buffer_unordered(state.config.cache_prime.concurrency);
+    while let Some((name, ticket, read)) = reads.next().await {
+        match read {
+            Ok(LatestRead::Value(metric, etag)) => {
+                let metric = MetricValueResponse { source: Some(ReadSource::Primary), ..metric };
+                state.read_cache.insert(&name, ticket, CachedRead { metric, etag }, Instant::now());
+                summary.primed += 1;
+            }
+            Err(e) if e.code() == ErrorCode::MetricNotFound => summary.missing += 1,
+            // Nothing to match is sent, so no worker answers 304
+            Ok(LatestRead::NotModified(_)) => {}
+            Err(e) => {
+                warn!("Could not prime '{}': {}", name, e);
+                summary.failed.push(name);
+            }
+        }
+    }
+    summary
+}
+
+/// Primes the read cache now, as at startup.
+async fn prime_cache(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+) -> Json<PrimeSummary> {
+    let summary = prime_read_cache(&state, &deadline).await;
+    info!("Primed {} metrics into the read cache ({} missing, {} failed)", summary.primed, summary.missing, summary.failed.len());
+    Json(summary)
+}
+
+/// Primes the read cache in the background, with `/health` answering `503`
+/// until priming finishes or `CACHE_PRIME_TIMEOUT_MS` passes, whichever is
+/// first.
+fn prime_on_startup(state: ControlState) {
+    if !state.read_cache.enabled() {
+        return;
+    }
+    state.ready.store(false, Ordering::Release);
+    tokio::spawn(async move {
+        let timeout = state.config.cache_prime.timeout;
+        let deadline = Deadline::after(timeout);
+        match tokio::time::timeout(timeout, prime_read_cache(&state, &deadline)).await {
+            Ok(summary) => info!(
+                "Primed {} metrics into the read cache ({} missing, {} failed)",
+                summary.primed, summary.missing, summary.failed.len()
+            ),
+            Err(_) => warn!("Priming the read cache took longer than {:?}; reporting ready without it", timeout),
+        }
+        state.ready.store(true, Ordering::Release);
+    });
+}
+
+/// Saves read counts every [`HOT_READS_SAVE_INTERVAL`].
+fn save_hot_reads(hot_reads: Arc<HotReads>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(HOT_READS_SAVE_INTERVAL).await;
+            hot_reads.save();
+        }
+    });
+}
+
+/// Turns cluster-wide read-only mode on or off: on the control node first,
+/// so its own write routes change at once, then on every worker so writes
+/// sent to them directly change too. Safe to repeat when a worker failed.
+async fn set_read_only(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<ReadOnlyQuery>,
+) -> Result<Json<serde_json::Value>> {
+    state.read_only.set(query.enabled)?;
+    let failed = propagate_read_only(&state, query.enabled, &deadline).await;
+    if !failed.is_empty() {
+        return Err(RaftMetricsError::Internal(format!(
+            "Read-only mode set on the control node but not on {} of {} workers: {}",
+            failed.len(),
+            state.worker_urls.len(),
+            failed.join(", ")
+        )));
+    }
+    Ok(Json(serde_json::json!({ "read_only": query.enabled, "workers": state.worker_urls.len() })))
+}
+
+/// Sets read-only mode on every worker, returning those that failed.
+async fn propagate_read_only(state: &ControlState, enabled: bool, deadline: &Deadline) -> Vec<String> {
+    let mut failed = Vec::new();
+    for worker_url in state.worker_urls.iter() {
+        let mut request = state.write_pool.client().post(format!("{}/admin/readonly?enabled={}", worker_url, enabled));
+        if let Some(token) = &state.config.admin_token {
+            request = request.bearer_auth(token);
+        }
+        match send_within(state, &state.write_pool, request, deadline, worker_url).await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => failed.push(format!("{} ({})", worker_url, response.status())),
+            Err(e) => failed.push(format!("{} ({})", worker_url, e)),
+        }
+    }
+    failed
+}
+
+/// Re-reads the config and switches to it, answering with the effective
+/// config, or with every validation error while the old config stays.
+async fn reload_config(State(handle): State<ControlHandle>) -> Result<Json<serde_json::Value>> {
+    info!("Reloading configuration on request");
+    let config = handle.reload()?;
+    push_quotas(&handle.snapshot()).await;
+    Ok(Json(effective_config(&config)))
+}
+
+/// Sends the tenant quotas to every worker, replica and standby, which
+/// enforce them on series and storage. A worker that cannot take them is
+/// logged and keeps its old quotas.
+async fn push_quotas(state: &ControlState) {
+    let deadline = Deadline::after(state.config.deadline.default_budget);
+    let replicas = state.config.replica_urls.values().chain(state.config.standby_urls.values()).flatten();
+    let worker_urls: Vec<String> = state.worker_urls.iter().chain(replicas).cloned().collect();
+    for worker_url in &worker_urls {
+        let mut request = state.write_pool.client().put(format!("{}/admin/quotas", worker_url)).json(&state.config.quotas);
+        if let Some(token) = &state.config.admin_token {
+            request = request.bearer_auth(token);
+        }
+        match send_within(state, &state.write_pool, request, &deadline, worker_url).await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => warn!("Failed to push tenant quotas to {}: {}", worker_url, response.status()),
+            Err(e) => warn!("Failed to push tenant quotas to {}: {}", worker_url, e),
+        }
+    }
+}
+
+async fn register_worker(
+    State(handle): State<ControlHandle>,
+    Json(request): Json<RegisterRequest>,
+) -> Result<Json<RegisterResponse>> {
+    Ok(Json(handle.register_worker(&request)?))
+}
+
+async fn deregister_worker(
+    State(handle): State<ControlHandle>,
+    Json(request): Json<DeregisterRequest>,
+) -> Result<StatusCode> {
+    match handle.deregister_worker(&request.url)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(RaftMetricsError::NoData(format!("{} is not a registered worker", request.url))),
+    }
+}
+
+async fn worker_heartbeat(
+    State(handle): State<ControlHandle>,
+    Json(request): Json<HeartbeatRequest>,
+) -> Result<StatusCode> {
+    handle.heartbeat_worker(&request, Instant::now())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Worker liveness, with the raft health of every worker: from its last
+/// heartbeat, or asked of its `/health` when it sends none, as configured
+/// workers started without `CONTROL_URL` do not.
+async fn cluster_health(
+    State(handle): State<ControlHandle>,
+    Extension(deadline): Extension<Deadline>,
+) -> Json<ClusterHealth> {
+    let state = handle.snapshot();
+    let mut health = handle.health(Instant::now());
+    let mut calls = tokio::task::JoinSet::new();
+    for (index, worker) in health.workers.iter().enumerate().filter(|(_, worker)| worker.raft.is_none()) {
+        let (state, worker_url) = (state.clone(), worker.url.clone());
+        calls.spawn(async move {
+            let request = state.read_pool.client().get(format!("{}/health", worker_url));
+            // An unhealthy worker answers 503 with the same body
+            let body = match send_within(&state, &state.read_pool, request, &deadline, &worker_url).await {
+                Ok(response) => response.json::<WorkerHealthResponse>().await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            let raft = body.map_or_else(
+                |e| RaftHealth { status: HealthStatus::Unhealthy, reason: Some(format!("health check failed: {}", e)) },
+                |body| body.raft.health,
+            );
+            (index, raft)
+        });
+    }
+    while let Some(joined) = calls.join_next().await {
+        if let Ok((index, raft)) = joined {
+            health.workers[index].raft = Some(raft);
+        }
+    }
+    Json(health)
+}
+
+async fn get_config(State(state): State<ControlState>) -> Json<serde_json::Value> {
+    Json(effective_config(&state.config))
+}
+
+/// Starts recording matching `POST /metrics` requests; see [`IngestCapture`].
+async fn start_capture(
+    State(state): State<ControlState>,
+    Json(request): Json<CaptureRequest>,
+) -> Result<Json<CaptureSnapshot>> {
+    Ok(Json(state.capture.start(request, state.config.capture_max_duration)?))
+}
+
+async fn stop_capture(State(state): State<ControlState>) -> Json<CaptureSnapshot> {
+    Json(state.capture.stop())
+}
+
+async fn get_capture(State(state): State<ControlState>) -> Json<CaptureSnapshot> {
+    Json(state.capture.snapshot())
+}
+
+async fn get_preflight(State(state): State<ControlState>) -> Json<PreflightReport> {
+    Json(state.preflight.as_ref().clone())
+}
+
+async fn list_reports(State(state): State<ControlState>) -> Json<Vec<ReportSummary>> {
+    Json(state.reports.summaries(&state.config.reports, chrono::Utc::now()))
+}
+
+/// Runs a report now and answers with its outcome; `409` while a run of
+/// it is already going.
+async fn run_report(State(state): State<ControlState>, Path(id): Path<String>) -> Result<Json<ReportRun>> {
+    let report = state.config.reports.iter().find(|report| report.id == id).ok_or(RaftMetricsError::NotFound)?;
+    info!("Running report '{}' on demand", id);
+    // Spawned so the run still finishes and is recorded if the client leaves
+    let report = report.clone();
+    let run = tokio::spawn(async move { reports::run_report(&state, &report, RunTrigger::Manual, chrono::Utc::now()).await });
+    let run = run.await.map_err(|e| RaftMetricsError::Internal(format!("Report task failed: {}", e)))?;
+    Ok(Json(run?))
+}
+
+/// Audits `?sample=` metrics now (`AUDIT_SAMPLE` by default) and answers
+/// with the report; `409` while another audit runs and `429` within
+/// `AUDIT_MIN_GAP_SECS` of the last one starting.
+async fn run_audit(State(state): State<ControlState>, Query(query): Query<AuditQuery>) -> Result<Json<AuditReport>> {
+    let sample = query.sample(state.config.audit.sample)?;
+    info!("Auditing {} metrics on demand", sample);
+    Ok(Json(audit::run_audit(&state, sample, RunTrigger::Manual).await?))
+}
+
+/// Kept audit reports, newest first.
+async fn list_audits(State(state): State<ControlState>) -> Json<Vec<AuditReport>> {
+    Json(state.audits.reports())
+}
+
+async fn get_shadow_stats(State(state): State<ControlState>) -> Json<ShadowStats> {
+    Json(state.shadow.stats())
+}
+
+async fn forwarding_status(State(state): State<ControlState>) -> Json<ForwardingStatus> {
+    Json(state.forwarder.status())
+}
+
+/// Where the given names, or every name the workers hold, would route
+/// under a proposed worker set, against where they route now.
+async fn routing_preview(
+    State(state): State<ControlState>,
+    Extension(deadline): Extension<Deadline>,
+    Json(request): Json<RoutingPreviewRequest>,
+) -> Result<Json<RoutingPreview>> {
+    let proposed = request.proposed_config(&state.config)?;
+    let (names, truncated) = match request.names {
+        Some(names) => (names, false),
+        None => known_metric_names(&state, &deadline).await?,
+    };
+    info!("Previewing routing of {} metrics over {} workers", names.len(), proposed.worker_urls.len());
+    Ok(Json(RoutingPreview::compare(names, state.router.as_ref(), build_router(&proposed).as_ref(), truncated)))
+}
+
+/// Every metric name the workers hold, up to [`MAX_PREVIEW_NAMES`], and
+/// whether any were left out. Names come from each worker's stale listing,
+/// so those written within the last second are missed.
+async fn known_metric_names(state: &ControlState, deadline: &Deadline) -> Result<(Vec<String>, bool)> {
+    let mut names = Vec::new();
+    for worker_url in state.worker_urls.iter() {
+        let mut after: Option<String> = None;
+        loop {
+            let query = StaleQuery { idle_seconds: 0, after: after.take(), limit: Some(MAX_STALE_PAGE) };
+            let request = state.read_pool.client().get(format!("{}/metrics/stale", worker_url)).query(&query);
+            let response = send_within(state, &state.read_pool, request, deadline, worker_url).await?;
+            if !response.status().is_success() {
+                return Err(worker_error(response, "Worker failed to list metrics").await);
+            }
+            let page = response.json::<StaleMetricsPage>().await
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e)))?;
+            names.extend(page.metrics.into_iter().map(|metric| metric.name));
+            if names.len() > MAX_PREVIEW_NAMES {
+                names.truncate(MAX_PREVIEW_NAMES);
+                return Ok((names, true));
+            }
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+    }
+    Ok((names, false))
+}
+
+/// Renders `config` for `/admin/config`, with secrets redacted.
+fn effective_config(config: &ControlConfig) -> serde_json::Value {
+    let pool = |pool: &crate::config::HttpPoolConfig| serde_json::json!({
+        "pool_max_idle_per_host": pool.pool_max_idle_per_host,
+        "pool_idle_timeout_ms": pool.pool_idle_timeout.as_millis() as u64,
+        "connect_timeout_ms": pool.connect_timeout.as_millis() as u64,
+        "request_timeout_ms": pool.request_timeout.as_millis() as u64,
+        "tcp_keepalive_secs": pool.tcp_keepalive.as_secs(),
+    });
+    let slos: Vec<_> = config.slo.slos.iter()
+        .map(|slo| serde_json::json!({
+            "name": slo.name,
+            "endpoint": slo.endpoint,
+            "percentile": slo.percentile,
+            "threshold_ms": slo.threshold.as_millis() as u64,
+            "window_secs": slo.window.as_secs(),
+        }))
+        .collect();
+
+    serde_json::json!({
+        "worker_urls": config.worker_urls,
+        "worker_weights": config.worker_weights,
+        "down_workers": config.down_workers,
+        "partition_seed": config.partition_seed,
+        "partition_label_keys": config.partition_key.label_keys(),
+        "read_pool": pool(&config.read_pool),
+        "write_pool": pool(&config.write_pool),
+        "deadline": {
+            "default_budget_ms": config.deadline.default_budget.as_millis() as u64,
+            "safety_margin_ms": config.deadline.safety_margin.as_millis() as u64,
+        },
+        "admin_token": config.admin_token.as_ref().map(|_| "<redacted>"),
+        "admin_port": config.admin_port,
+        "capture_max_secs": config.capture_max_duration.as_secs(),
+        "registration_ttl_secs": config.registration_ttl.as_secs(),
+        "heartbeat_timeout_secs": config.heartbeat_timeout.as_secs(),
+        "load_poll_interval_secs": config.load_poll_interval.as_secs(),
+        "shadow": {
+            "url": config.shadow.url,
+            "partition_urls": config.shadow.partition_urls,
+            "percent": config.shadow.percent,
+            "compare_reads": config.shadow.compare_reads,
+            "max_in_flight": config.shadow.max_in_flight,
+            "timeout_ms": config.shadow.timeout.as_millis() as u64,
+        },
+        "slo": {
+            "slos": slos,
+            "degraded_burn_rate": config.slo.degraded_burn_rate,
+        },
+        "replica_urls": config.replica_urls,
+        "standby_urls": config.standby_urls,
+        "tenant_quotas": config.quotas,
+        "acl_path": config.acl_path,
+        "forwarding": {
+            "from": config.forwarding.from,
+            "rules": config.forwarding.rules.iter().map(|rule| serde_json::json!({
+                "metrics": rule.metrics,
+                "destination": rule.destination.to_string(),
+                "interval_ms": rule.interval.as_millis() as u64,
+                "mode": rule.mode,
+            })).collect::<Vec<_>>(),
+            "buffer_samples": config.forwarding.buffer_samples,
+            "batch_samples": config.forwarding.batch_samples,
+            "max_retries": config.forwarding.max_retries,
+            "max_hops": config.forwarding.max_hops,
+            "timeout_ms": config.forwarding.timeout.as_millis() as u64,
+        },
+        "read_cache": {
+            "size": config.read_cache.capacity,
+            "ttl_ms": config.read_cache.ttl.as_millis() as u64,
+        },
+        "cache_prime": {
+            "metrics": config.cache_prime.metrics,
+            "top": config.cache_prime.top,
+            "concurrency": config.cache_prime.concurrency,
+            "timeout_ms": config.cache_prime.timeout.as_millis() as u64,
+        },
+        "audit": {
+            "interval_secs": config.audit.interval.as_secs(),
+            "sample": config.audit.sample,
+            "metrics_per_sec": config.audit.metrics_per_sec,
+            "min_gap_secs": config.audit.min_gap.as_secs(),
+        },
+    })
+}
+
+/// Uses the weighted ring when `worker_weights` is set, jump hashing around
+/// `down_workers` otherwise, hashing with `partition_seed` when one is set.
+fn build_router(config: &ControlConfig) -> Arc<dyn partitioning::Router> {
+    match (&config.worker_weights, config.partition_seed) {
+        (Some(weights), seed) => {
+            info!("Using weighted ring routing with weights {:?}", weights);
+            let workers: Vec<(String, u32)> = config.worker_urls.iter().cloned().zip(weights.iter().copied()).collect();
+            match seed {
+                Some(seed) => Arc::new(WeightedRingRouter::seeded(&workers, seed)),
+                None => Arc::new(WeightedRingRouter::new(&workers)),
+            }
+        }
+        (None, seed) => {
+            let router = match seed {
+                Some(seed) => JumpHashRouter::seeded(config.worker_urls.len(), seed),
+                None => JumpHashRouter::new(config.worker_urls.len()),
+            };
+            let down: Vec<usize> = config
+                .worker_urls
+                .iter()
+                .enumerate()
+                .filter(|(_, url)| config.down_workers.contains(url))
+                .map(|(index, _)| index)
+                .collect();
+            Arc::new(router.skipping(&down))
+        }
+    }
+}
+
+/// Reloads `handle` on every SIGHUP, keeping the old config when it fails.
+#[cfg(unix)]
+fn reload_on_sighup(handle: ControlHandle) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                warn!("Cannot listen for SIGHUP, config reload is only available via /admin/reload: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            if let Err(e) = handle.reload() {
+                warn!("Keeping the current configuration: {}", e);
+            }
+        }
+    });
+}
+
+/// Sweeps lapsed worker registrations and missed heartbeats twice per
+/// TTL or heartbeat timeout, whichever is shorter.
+fn sweep_workers(handle: ControlHandle) {
+    tokio::spawn(async move {
+        loop {
+            let config = handle.snapshot().config;
+            tokio::time::sleep(config.registration_ttl.min(config.heartbeat_timeout) / 2).await;
+            if let Err(e) = handle.expire_workers(Instant::now()) {
+                warn!("Failed to expire worker registrations: {}", e);
+            }
+            if let Err(e) = handle.mark_down_workers(Instant::now()) {
+                warn!("Failed to mark down silent workers: {}", e);
+            }
+        }
+    });
+}
+
+/// Where the control node keeps its read-only mode unless `READ_ONLY_FILE`
+/// says otherwise.
+pub const DEFAULT_READ_ONLY_FILE: &str = "raftmetrics.read_only";
+
+fn control_listen_addr() -> String {
+    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    format!("0.0.0.0:{}", port)
+}
+
+/// Runs the control node's startup checks without starting it, for `--check`.
+pub async fn preflight_control_node() -> Result<PreflightReport> {
+    let config_file = std::env::var("CONFIG_FILE").ok().map(PathBuf::from);
+    let config = ControlConfig::load(config_file.as_deref())?;
+    let (mut report, _) = control_preflight(&config, &control_listen_addr(), &PreflightConfig::from_env()).await;
+    check_admin_listen(&mut report, admin_listen_addr(config.admin_port).as_deref()).await;
+    Ok(report)
+}
+
+pub async fn start_control_node() -> Result<()> {
+    let config_file = std::env::var("CONFIG_FILE").ok().map(PathBuf::from);
+    let config = ControlConfig::load(config_file.as_deref())?;
+    let addr = control_listen_addr();
+    let (mut report, listener) = control_preflight(&config, &addr, &PreflightConfig::from_env()).await;
+    let admin_addr = admin_listen_addr(config.admin_port);
+    let admin_listener = check_admin_listen(&mut report, admin_addr.as_deref()).await;
+    report.log();
+    let listener = match listener {
+        Some(listener) if report.passed() => listener,
+        _ => return Err(RaftMetricsError::InvalidConfig(report.failures())),
+    };
+    info!("Configured worker URLs: {:?}", config.worker_urls);
+    info!("Read pool: {:?}, write pool: {:?}", config.read_pool, config.write_pool);
+
+    let mut state = ControlState::new(config)?;
+    state.preflight = Arc::new(report);
+    state.read_only = Arc::new(ReadOnly::from_env(Some(PathBuf::from(DEFAULT_READ_ONLY_FILE))));
+    if state.read_only.enabled() {
+        // Workers that lost their own flag are told again
+        let state = state.clone();
+        tokio::spawn(async move {
+            let deadline = Deadline::after(state.config.deadline.default_budget);
+            let failed = propagate_read_only(&state, true, &deadline).await;
+            if !failed.is_empty() {
+                warn!("Could not make workers read-only at startup: {}", failed.join(", "));
+            }
+        });
+    }
+    state.info = Arc::new(NodeInfo::new("control", node_id_from_env(), NO_STORAGE, Some(state.worker_urls.len())));
+    state.info.log();
+    state.hot_reads = Arc::new(HotReads::from_env(Some(PathBuf::from(DEFAULT_HOT_READS_FILE))));
+    state.audits = Arc::new(Audits::load(Some(state.config.audit.reports_file.clone())));
+    let hot_reads = state.hot_reads.clone();
+    save_hot_reads(hot_reads.clone());
+    prime_on_startup(state.clone());
+    spawn_pusher(&format!("control-{}", state.info.node_id));
+    state.forwarder = Arc::new(Forwarder::new(state.config.forwarding.clone(), ForwardFrom::Control, &format!("control-{}", state.info.node_id)));
+    state.forwarder.spawn();
+    let forwarder = state.forwarder.clone();
+    let handle = ControlHandle::new(state, Arc::new(move || ControlConfig::load(config_file.as_deref())));
+    #[cfg(unix)]
+    reload_on_sighup(handle.clone());
+    sweep_workers(handle.clone());
+    poll_worker_loads(handle.clone());
+    reports::schedule_reports(handle.clone());
+    audit::schedule_audits(handle.clone());
+
+    match &admin_addr {
+        Some(admin_addr) => info!("Starting control node on {}, admin routes on {}", addr, admin_addr),
+        None => info!("Starting control node on {}", addr),
+    }
+    let served = serve_split(listener, admin_listener, data_router(handle.clone()), admin_router(handle), ctrl_c()).await;
+    hot_reads.save();
+    forwarder.flush().await;
+    served.map_err(|e| RaftMetricsError::Internal(format!("Control server failed: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::{Body, Bytes}, http::Request};
+    use crate::api::load::{poll_loads, WorkerLoad};
+    use tower::ServiceExt;
+
+    /// Serves `router` on an ephemeral local port and returns its base URL.
+    async fn spawn_worker(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn spawn_real_worker() -> String {
+        spawn_real_worker_with(Arc::new(MetricsRegistry::new().unwrap())).await
+    }
+
+    async fn spawn_real_worker_with(metrics: Arc<MetricsRegistry>) -> String {
+        let state = crate::api::worker::WorkerState::new(1, metrics, crate::config::RaftConfig::default()).unwrap();
+        spawn_worker(crate::api::worker::worker_router(state)).await
+    }
+
+    fn state_for(worker_urls: Vec<String>) -> ControlState {
+        let mut config = ControlConfig::from_lookup(|_| None);
+        config.worker_urls = worker_urls;
+        ControlState::new(config).unwrap()
+    }
+
+    #[cfg(feature = "otel")]
+    #[tokio::test]
+    async fn test_forwarded_write_continues_one_trace_down_to_its_apply() {
+        use opentelemetry::trace::{SpanId, SpanKind, TracerProvider as _};
+        use opentelemetry_sdk::{propagation::TraceContextPropagator, testing::trace::InMemorySpanExporterBuilder, trace::TracerProvider};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let exporter = InMemorySpanExporterBuilder::new().build();
+        let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        // The test runtime is single-threaded, so the worker's tasks see this too
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let worker = spawn_real_worker().await;
+        let response = control_router(state_for(vec![worker]))
+            .oneshot(
+                Request::post("/metrics?ack=quorum")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":1.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let child_of = |parent: SpanId, name: &str| {
+            spans.iter()
+                .find(|span| span.parent_span_id == parent && span.name == name)
+                .unwrap_or_else(|| panic!("no {} span under {:?}", name, parent))
+        };
+        let control = child_of(SpanId::INVALID, "POST /metrics");
+        assert_eq!(control.span_kind, SpanKind::Server);
+        child_of(control.span_context.span_id(), "partition.select");
+        let forward = child_of(control.span_context.span_id(), "forward");
+        assert_eq!(forward.span_kind, SpanKind::Client);
+        let worker = child_of(forward.span_context.span_id(), "POST /process");
+        assert_eq!(worker.span_kind, SpanKind::Server);
+        let propose = child_of(worker.span_context.span_id(), "raft.propose");
+        child_of(worker.span_context.span_id(), "raft.commit_wait");
+        child_of(propose.span_context.span_id(), "raft.apply");
+
+        assert_eq!(worker.span_context.trace_id(), control.span_context.trace_id());
+    }
+
+    #[tokio::test]
+    async fn test_handlers_use_matching_pool() {
+        let worker = spawn_real_worker().await;
+        let state = state_for(vec![worker]);
+        let app = control_router(state.clone());
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":1.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!((state.read_pool.sent(), state.write_pool.sent()), (0, 1));
+
+        for uri in ["/metrics/cpu", "/metrics/cpu/aggregate"] {
+            let response = app.clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!((state.read_pool.sent(), state.write_pool.sent()), (2, 1));
+
+        let response = app
+            .oneshot(Request::delete("/metrics/cpu").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!((state.read_pool.sent(), state.write_pool.sent()), (2, 2));
+    }
+
+    /// A worker that takes two seconds to answer any metric read.
+    async fn spawn_slow_worker() -> String {
+        let slow = || async {
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+            Json(serde_json::json!({ "name": "cpu", "value": 0.0, "timestamp": 0, "worker_id": 0 }))
+        };
+        let router = Router::new()
+            .route("/metrics/:name", get(slow))
+            .route("/metrics/:name/aggregate", get(slow))
+            .route("/process", post(slow));
+        spawn_worker(router).await
+    }
+
+    #[tokio::test]
+    async fn test_buckets_are_proxied_with_empty_buckets_intact() {
+        let metrics = Arc::new(MetricsRegistry::new().unwrap());
+        for (value, timestamp) in [(4.0, 0), (6.0, 10), (9.0, 130)] {
+            metrics.record_metric_with_timestamp("disk", value, timestamp).await.unwrap();
+        }
+        let app = control_router(state_for(vec![spawn_real_worker_with(metrics).await]));
+
+        let response = app
+            .oneshot(Request::get("/metrics/disk/buckets?start=0&end=130&fn=avg,count&fill=null").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await["buckets"],
+            serde_json::json!([
+                { "bucket_start": 0, "avg": 5.0, "count": 2.0 },
+                { "bucket_start": 60, "avg": null, "count": null },
+                { "bucket_start": 120, "avg": 9.0, "count": 1.0 },
+            ])
+        );
+    }
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// A worker whose registry is not fed by raft, so it only advances when
+    /// the test applies writes to it by hand.
+    async fn spawn_lagging_worker(metrics: Arc<MetricsRegistry>, version_wait: std::time::Duration) -> String {
+        let state = crate::api::worker::WorkerState {
+            storage: Arc::new(MemStorage::new()),
+            metrics,
+            worker_id: 2,
+            proposal_tx: tokio::sync::mpsc::channel(1).0,
+            raft_status: Arc::new(crate::raft::node::RaftStatus::default()),
+            ready: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            serving: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            registration: None,
+            admin_token: None,
+            default_deadline: std::time::Duration::from_secs(10),
+            version_wait,
+            max_future_skew: crate::api::worker::DEFAULT_MAX_FUTURE_SKEW,
+            slo: Arc::new(crate::api::slo::SloTracker::new(&crate::config::SloConfig::default())),
+            query_limits: crate::config::QueryLimits::default(),
+            preflight: Default::default(),
+            raft_health: Default::default(),
+            info: Arc::new(crate::api::info::NodeInfo::new("worker", 2, "memory", None)),
+            ttls: Default::default(),
+            read_only: Default::default(),
+            downsampler: Default::default(),
+            journal: Default::default(),
+            forwarder: Default::default(),
+            network: None,
+            #[cfg(feature = "chaos")]
+            chaos: Default::default(),
+        };
+        spawn_worker(crate::api::worker::worker_router(state)).await
+    }
+
+    #[tokio::test]
+    async fn test_min_version_never_serves_stale_value() {
+        let primary = control_router(state_for(vec![spawn_real_worker().await]));
+        let response = primary
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":2.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let token = json_body(response).await["version"].as_str().unwrap().to_string();
+        let uri = format!("/metrics/cpu?min_version={}", token);
+
+        // A replica that still holds the value from before the write
+        let stale = Arc::new(MetricsRegistry::new().unwrap());
+        stale.record_metric("cpu", 1.0).await.unwrap();
+
+        let lagging = control_router(state_for(vec![
+            spawn_lagging_worker(stale.clone(), std::time::Duration::from_millis(50)).await,
+        ]));
+        let response = lagging.oneshot(Request::get(uri.as_str()).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        let catching_up = control_router(state_for(vec![
+            spawn_lagging_worker(stale.clone(), std::time::Duration::from_secs(2)).await,
+        ]));
+        let required = crate::api::consistency::decode_version(&token).unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+            stale.record_metric("cpu", 2.0).await.unwrap();
+            stale.set_applied_index(required).await.unwrap();
+        });
+        let response = catching_up.oneshot(Request::get(uri.as_str()).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["value"], 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_commits_metrics_on_one_partition_together() {
+        let state = state_for(vec![spawn_real_worker().await, spawn_real_worker().await]);
+        let app = control_router(state.clone());
+        let names = || (0..).map(|i| format!("queue_{}", i));
+        let local: Vec<String> = names().filter(|name| state.router.route(name) == 0).take(2).collect();
+        let remote = names().find(|name| state.router.route(name) == 1).unwrap();
+        let transaction = |body: serde_json::Value| {
+            Request::post("/metrics/transaction")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone()
+            .oneshot(transaction(serde_json::json!([
+                { "metric_name": local[0], "value": 4.0 },
+                { "metric_name": local[1], "value": 12.0 },
+            ])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        for (name, value) in local.iter().zip([4.0, 12.0]) {
+            let response = app.clone()
+                .oneshot(Request::get(format!("/metrics/{}", name)).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(json_body(response).await["value"], value);
+        }
+
+        // Spanning partitions, or writing a metric twice, is refused whole
+        for refused in [
+            serde_json::json!([{ "metric_name": local[0], "value": 5.0 }, { "metric_name": remote, "value": 1.0 }]),
+            serde_json::json!([{ "metric_name": local[0], "value": 5.0 }, { "metric_name": local[0], "value": 6.0 }]),
+            serde_json::json!([]),
+        ] {
+            let response = app.clone().oneshot(transaction(refused)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        let response = app
+            .oneshot(Request::get(format!("/metrics/{}", local[0])).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(json_body(response).await["value"], 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_histograms_merge_across_workers_only_with_one_layout() {
+        let state = state_for(vec![spawn_real_worker().await, spawn_real_worker().await]);
+        let app = control_router(state.clone());
+        let names = || (0..).map(|i| format!("latency_{}", i));
+        let local = names().find(|name| state.router.route(name) == 0).unwrap();
+        let remote: Vec<String> = names().filter(|name| state.router.route(name) == 1).take(2).collect();
+        let send_json = |method: &str, uri: String, body: serde_json::Value| {
+            let request = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+            app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap())
+        };
+
+        for (name, bounds) in [(&local, [1.0, 2.0]), (&remote[0], [1.0, 2.0]), (&remote[1], [1.0, 5.0])] {
+            let response = send_json("PUT", format!("/metrics/{}/histogram", name), serde_json::json!({ "buckets": bounds })).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        }
+        for (name, value) in [(&local, 0.5), (&remote[0], 1.5), (&remote[0], 3.0), (&remote[1], 1.0)] {
+            let response = send_json("POST", "/metrics".to_string(), serde_json::json!({ "metric_name": name, "value": value })).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = send_json("POST", "/histograms/merge".to_string(), serde_json::json!({ "names": [local, remote[0]] })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let merged = json_body(response).await;
+        let cumulative: Vec<u64> = merged["buckets"].as_array().unwrap().iter().map(|bucket| bucket["cumulative"].as_u64().unwrap()).collect();
+        assert_eq!(cumulative, [1, 2, 3]);
+        assert_eq!((merged["count"].as_u64(), merged["sum"].as_f64()), (Some(3), Some(5.0)));
+
+        let response = send_json("POST", "/histograms/merge".to_string(), serde_json::json!({ "names": [local, remote[1]] })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = send_json("POST", "/histograms/merge".to_string(), serde_json::json!({ "names": [local, "absent"] })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // A layout change made through the control node is refused by the worker
+        let response = send_json("PUT", format!("/metrics/{}/histogram", local), serde_json::json!({ "buckets": [1.0, 5.0] })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = send_json("PUT", format!("/metrics/{}/histogram?reset=true", local), serde_json::json!({ "buckets": [1.0, 5.0] })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = app.clone().oneshot(Request::get(format!("/metrics/{}/histogram", local)).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(json_body(response).await["count"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_error_codes_survive_the_control_node() {
+        let post_json = |uri: &str, body: serde_json::Value| {
+            Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let code = |body: &serde_json::Value| (body["code"].as_str().map(String::from), body["retriable"].as_bool());
+
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+        for uri in ["/metrics/absent", "/metrics/absent/aggregate"] {
+            let response = app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+            assert_eq!(code(&json_body(response).await), (Some("METRIC_NOT_FOUND".into()), Some(false)), "{}", uri);
+        }
+        let duplicated = serde_json::json!([{ "metric_name": "a", "value": 1.0 }, { "metric_name": "a", "value": 2.0 }]);
+        let response = app.oneshot(post_json("/metrics/transaction", duplicated)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(code(&json_body(response).await), (Some("VALIDATION_FAILED".into()), Some(false)));
+
+        // A deposed leader's refusal reaches the client as itself
+        let deposed = spawn_worker(Router::new().route(
+            "/process",
+            post(|| async { RaftMetricsError::LeadershipLost { term: 4, leader_id: Some(2) }.into_response() }),
+        ))
+        .await;
+        let record = serde_json::json!({ "metric_name": "cpu", "value": 1.0 });
+        let response = control_router(state_for(vec![deposed])).oneshot(post_json("/metrics", record.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = json_body(response).await;
+        assert_eq!(code(&body), (Some("RAFT_NO_LEADER".into()), Some(true)));
+        assert_eq!((body["term"].as_u64(), body["leader_id"].as_u64()), (Some(4), Some(2)));
+
+        let unreachable = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let response = control_router(state_for(vec![unreachable])).oneshot(post_json("/metrics", record)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(code(&json_body(response).await), (Some("WORKER_UNAVAILABLE".into()), Some(true)));
+    }
+
+    #[tokio::test]
+    async fn test_worker_read_errors_keep_their_status() {
+        // Answers by metric name, with plain bodies so only the status says what went wrong
+        let answer = |name: &str| match name {
+            "missing" => (StatusCode::NOT_FOUND, "no such metric"),
+            "malformed" => (StatusCode::BAD_REQUEST, "bad query"),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "disk on fire"),
+        };
+        let worker = spawn_worker(
+            Router::new()
+                .route("/metrics/:name", get(move |Path(name): Path<String>| async move { answer(&name) }))
+                .route("/metrics/:name/aggregate", get(move |Path(name): Path<String>| async move { answer(&name) })),
+        )
+        .await;
+        let app = control_router(state_for(vec![worker]));
+
+        for (name, status, code) in [
+            ("missing", StatusCode::NOT_FOUND, "METRIC_NOT_FOUND"),
+            ("malformed", StatusCode::BAD_REQUEST, "VALIDATION_FAILED"),
+            ("broken", StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL"),
+        ] {
+            for uri in [format!("/metrics/{}", name), format!("/metrics/{}/aggregate", name)] {
+                let response = app.clone().oneshot(Request::get(&uri).body(Body::empty()).unwrap()).await.unwrap();
+                assert_eq!(response.status(), status, "{}", uri);
+                let body = json_body(response).await;
+                assert_eq!(body["code"], code, "{}", uri);
+                assert!(body["error"].as_str().unwrap().contains(answer(name).1), "{}", uri);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_from_a_worker_without_latest_is_served() {
+        let older = spawn_worker(Router::new().route("/metrics/:name/aggregate", get(|| async {
+            Json(serde_json::json!({
+                "name": "cpu", "worker_id": 1, "count": 2, "sum": 3.0, "average": 1.5,
+                "min": 1.0, "max": 2.0, "first_seen": 100, "last_seen": 200
+            }))
+        })))
+        .await;
+        let app = control_router(state_for(vec![older]));
+
+        let response = app.oneshot(Request::get("/metrics/cpu/aggregate").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!((body["count"].as_u64(), body["latest"].as_f64()), (Some(2), Some(0.0)));
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_stream_counts_accepted_and_rejected() {
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+
+        let mut body = String::new();
+        for i in 0..1000 {
+            if i % 10 == 0 {
+                body.push_str("{\"metric_name\": \"broken\"\n");
+            } else {
+                body.push_str(&format!("{{\"metric_name\":\"m{}\",\"value\":{}}}\n", i % 5, i));
+            }
+        }
+        // The final record has no trailing newline
+        body.push_str(r#"{"metric_name":"m1","value":1.0}"#);
+
+        // Split the body into uneven chunks so lines straddle chunk boundaries
+        let chunks: Vec<std::result::Result<Vec<u8>, std::io::Error>> =
+            body.into_bytes().chunks(777).map(|chunk| Ok(chunk.to_vec())).collect();
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics/stream")
+                    .header("content-type", "application/x-ndjson")
+                    .body(Body::from_stream(futures_util::stream::iter(chunks)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let summary: IngestSummary = serde_json::from_value(json_body(response).await).unwrap();
+        assert_eq!(summary, IngestSummary { accepted: 901, rejected: 100, errors: Vec::new(), rate_limited: 0 });
+
+        let response = app
+            .oneshot(Request::get("/metrics/m1/aggregate").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(json_body(response).await["count"], 201);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_quotas_are_enforced_and_reported() {
+        let quotas = crate::metrics::quotas::TenantQuotas::parse("acme=series:1,rate:3", ".").unwrap();
+        let worker = spawn_real_worker_with(Arc::new(MetricsRegistry::new().unwrap().with_quotas(quotas.clone()))).await;
+        let mut state = state_for(vec![worker.clone()]);
+        state.config = Arc::new(ControlConfig { quotas, ..(*state.config).clone() });
+        let app = control_router(state);
+        let record = |name: &str| {
+            Request::post("/metrics")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "metric_name": name, "value": 1.0 }).to_string()))
+                .unwrap()
+        };
+
+        assert_eq!(app.clone().oneshot(record("acme.a")).await.unwrap().status(), StatusCode::OK);
+        // The worker refuses a second series; the control node passes that on
+        let response = app.clone().oneshot(record("acme.b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json_body(response).await["code"], "QUOTA_EXCEEDED");
+        assert_eq!(app.clone().oneshot(record("acme.a")).await.unwrap().status(), StatusCode::OK);
+        // The fourth sample within a second is over the rate
+        let response = app.clone().oneshot(record("acme.a")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(json_body(response).await["retriable"], true);
+        assert_eq!(app.clone().oneshot(record("other")).await.unwrap().status(), StatusCode::OK);
+
+        let response = app.oneshot(Request::get("/usage").body(Body::empty()).unwrap()).await.unwrap();
+        let usage = json_body(response).await;
+        assert_eq!(usage[0]["tenant"], "acme");
+        assert_eq!(usage[0]["series"], 1);
+        assert_eq!(usage[0]["quota"]["max_series"], 1);
+        assert_eq!(usage[0]["workers"][&worker]["series"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_usage_leaves_out_tenants_the_key_cannot_read() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-acl-usage-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"acme.": {"dash": "read"}, "beta.secret.": {"ops": "read"}}"#).unwrap();
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "ACL_PATH" => Some(path.display().to_string()),
+            "TENANT_QUOTAS" => Some("acme=series:5;beta=series:5;gamma=series:5".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![spawn_real_worker().await];
+        let app = control_router(ControlState::new(config).unwrap());
+        let tenants = |key: Option<&str>| {
+            let request = Request::get("/usage");
+            let request = match key {
+                Some(key) => request.header("authorization", format!("Bearer {}", key)),
+                None => request,
+            };
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap());
+            async move {
+                let usage = json_body(response.await.unwrap()).await;
+                usage.as_array().unwrap().iter().map(|report| report["tenant"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+            }
+        };
+
+        // A tenant with any protected name under it needs a key for all of them
+        assert_eq!(tenants(None).await, ["gamma"]);
+        assert_eq!(tenants(Some("dash")).await, ["acme", "gamma"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_pushes_quotas_to_workers_with_the_admin_token() {
+        let mut worker = crate::api::worker::WorkerState::new(1, Arc::new(MetricsRegistry::new().unwrap()), crate::config::RaftConfig::default()).unwrap();
+        worker.admin_token = Some(Arc::from("secret"));
+        let worker = spawn_worker(crate::api::worker::worker_router(worker)).await;
+        let client = reqwest::Client::new();
+
+        // Quotas from anyone but the control node are refused
+        let quotas = crate::metrics::quotas::TenantQuotas::parse("acme=series:1", ".").unwrap();
+        let response = client.put(format!("{}/admin/quotas", worker)).json(&quotas).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let usage: serde_json::Value = client.get(format!("{}/usage", worker)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(usage, serde_json::json!([]));
+
+        let hosts = worker.clone();
+        let loader: ConfigLoader = Arc::new(move || {
+            Ok(ControlConfig::from_lookup(|key| match key {
+                "WORKER_HOSTS" => Some(hosts.clone()),
+                "TENANT_QUOTAS" => Some("acme=series:1".to_string()),
+                "ADMIN_TOKEN" => Some("secret".to_string()),
+                _ => None,
+            }))
+        });
+        let app = reloadable_control_router(ControlHandle::new(ControlState::new(loader().unwrap()).unwrap(), loader));
+        let reload = Request::post("/admin/reload").header("authorization", "Bearer secret").body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(reload).await.unwrap().status(), StatusCode::OK);
+        let usage: serde_json::Value = client.get(format!("{}/usage", worker)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(usage[0]["tenant"], "acme");
+        assert_eq!(usage[0]["quota"]["max_series"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_metric_acls_limit_keys_to_their_prefixes() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-acl-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"acme.": {"ingest": "write", "dash": "read"}}"#).unwrap();
+        let mut config = ControlConfig::from_lookup(|key| (key == "ACL_PATH").then(|| path.display().to_string()));
+        config.worker_urls = vec![spawn_real_worker().await];
+        let loaded = config.clone();
+        let handle = ControlHandle::new(ControlState::new(config).unwrap(), Arc::new(move || Ok(loaded.clone())));
+        let app = reloadable_control_router(handle.clone());
+        let send = |request: axum::http::request::Builder, key: Option<&str>, body: String| {
+            let request = match key {
+                Some(key) => request.header("authorization", format!("Bearer {}", key)),
+                None => request,
+            };
+            app.clone().oneshot(request.header("content-type", "application/json").body(Body::from(body)).unwrap())
+        };
+        let record = |name: &str| serde_json::json!({ "metric_name": name, "value": 1.0 }).to_string();
+
+        assert_eq!(send(Request::post("/metrics"), Some("ingest"), record("acme.cpu")).await.unwrap().status(), StatusCode::OK);
+        let response = send(Request::post("/metrics"), Some("stranger"), record("acme.cpu")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json_body(response).await["code"], "FORBIDDEN");
+        let response = send(Request::post("/metrics"), None, record("acme.cpu")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // Metrics under no prefix stay open
+        assert_eq!(send(Request::post("/metrics"), None, record("other")).await.unwrap().status(), StatusCode::OK);
+
+        // A read-only key reads but cannot write, by body or by path
+        assert_eq!(send(Request::get("/metrics/acme.cpu"), Some("dash"), String::new()).await.unwrap().status(), StatusCode::OK);
+        let multi_get = serde_json::json!({ "names": ["acme.cpu"] }).to_string();
+        assert_eq!(send(Request::post("/metrics/query"), Some("dash"), multi_get.clone()).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send(Request::post("/metrics/query"), None, multi_get).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let response = send(Request::post("/metrics"), Some("dash"), record("acme.cpu")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(send(Request::delete("/metrics/acme.cpu"), Some("dash"), String::new()).await.unwrap().status(), StatusCode::FORBIDDEN);
+        let transaction = serde_json::json!([{ "metric_name": "acme.cpu", "value": 2.0 }]).to_string();
+        assert_eq!(send(Request::post("/metrics/transaction"), Some("dash"), transaction).await.unwrap().status(), StatusCode::FORBIDDEN);
+        let stream = format!("{}\n{}\n", record("acme.cpu"), record("other"));
+        let response = send(Request::post("/metrics/stream"), Some("dash"), stream).await.unwrap();
+        let summary = json_body(response).await;
+        assert_eq!((summary["accepted"].as_u64(), summary["rejected"].as_u64()), (Some(1), Some(1)));
+
+        // Edits to the file apply on reload
+        std::fs::write(&path, r#"{"acme.": {"ingest": "write", "dash": "write"}}"#).unwrap();
+        handle.reload().unwrap();
+        assert_eq!(send(Request::post("/metrics"), Some("dash"), record("acme.cpu")).await.unwrap().status(), StatusCode::OK);
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(handle.reload().is_err());
+        assert_eq!(send(Request::post("/metrics"), Some("dash"), record("acme.cpu")).await.unwrap().status(), StatusCode::OK);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hooks_need_the_admin_token_and_read_access_to_what_they_watch() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-acl-hooks-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"acme.": {"dash": "read", "secret": "read"}, "ops.": {"dash": "read"}}"#).unwrap();
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "ACL_PATH" => Some(path.display().to_string()),
+            "ADMIN_TOKEN" => Some("secret".to_string()),
+            _ => None,
+        });
+        let metrics = Arc::new(MetricsRegistry::new().unwrap());
+        let mut worker = crate::api::worker::WorkerState::new(1, metrics.clone(), crate::config::RaftConfig::default()).unwrap();
+        worker.admin_token = Some(Arc::from("secret"));
+        let worker = spawn_worker(crate::api::worker::worker_router(worker)).await;
+        config.worker_urls = vec![worker.clone()];
+        let app = control_router(ControlState::new(config).unwrap());
+        let install = |id: &str, metric: &str, key: Option<&str>| {
+            let request = Request::put(format!("/hooks/{}", id)).header("content-type", "application/json");
+            let request = match key {
+                Some(key) => request.header("authorization", format!("Bearer {}", key)),
+                None => request,
+            };
+            let body = serde_json::json!({ "metric": metric, "threshold": 1.0, "direction": "rising", "url": "http://127.0.0.1:9/hook" });
+            let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap());
+            async move { response.await.unwrap().status() }
+        };
+
+        // Readers of a metric cannot point a worker at an address of their choosing
+        assert_eq!(install("exact", "acme.cpu", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(install("exact", "acme.cpu", Some("dash")).await, StatusCode::UNAUTHORIZED);
+        let direct = reqwest::Client::new()
+            .put(format!("{}/hooks/exact", worker))
+            .json(&serde_json::json!({ "metric": "acme.cpu", "threshold": 1.0, "direction": "rising", "url": "http://127.0.0.1:9/hook" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(direct.status(), reqwest::StatusCode::UNAUTHORIZED);
+        // The admin key still needs read access to what the hook sends, prefixes included
+        assert_eq!(install("exact", "ops.cpu", Some("secret")).await, StatusCode::FORBIDDEN);
+        assert_eq!(install("broad", "o*", Some("secret")).await, StatusCode::FORBIDDEN);
+        assert!(["exact", "broad"].iter().all(|id| metrics.get_hook(id).is_none()));
+
+        assert_eq!(install("exact", "acme.cpu", Some("secret")).await, StatusCode::NO_CONTENT);
+        assert_eq!(install("prefix", "acme*", Some("secret")).await, StatusCode::NO_CONTENT);
+        assert_eq!(install("open", "other", Some("secret")).await, StatusCode::NO_CONTENT);
+        assert!(metrics.get_hook("prefix").is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listings_leave_out_names_the_key_cannot_read() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-acl-listings-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"acme.": {"dash": "read"}}"#).unwrap();
+        let mut config = ControlConfig::from_lookup(|key| (key == "ACL_PATH").then(|| path.display().to_string()));
+        let now = Arc::new(std::sync::atomic::AtomicI64::new(1_000));
+        let clock = now.clone();
+        let metrics = MetricsRegistry::new().unwrap().with_clock(Arc::new(move || clock.load(std::sync::atomic::Ordering::SeqCst)));
+        for name in ["acme.cpu", "acme.mem", "other"] {
+            metrics.record_metric_with_timestamp(name, 1.0, 1_000).await.unwrap();
+        }
+        now.store(200_000, std::sync::atomic::Ordering::SeqCst);
+        config.worker_urls = vec![spawn_real_worker_with(Arc::new(metrics)).await];
+        let app = control_router(ControlState::new(config).unwrap());
+        let list = |uri: &str, key: Option<&str>| {
+            let request = Request::get(uri);
+            let request = match key {
+                Some(key) => request.header("authorization", format!("Bearer {}", key)),
+                None => request,
+            };
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap());
+            async move { json_body(response.await.unwrap()).await }
+        };
+        let names = |entries: &serde_json::Value| -> Vec<String> {
+            entries.as_array().unwrap().iter().map(|entry| entry["name"].as_str().unwrap().to_string()).collect()
+        };
+
+        let page = list("/aggregates", None).await;
+        assert_eq!(names(&page["aggregates"]), ["other"]);
+        assert_eq!(names(&list("/aggregates", Some("dash")).await["aggregates"]), ["acme.cpu", "acme.mem", "other"]);
+        // Pages fill past hidden names, which never become the cursor
+        let page = list("/aggregates?limit=1", None).await;
+        assert_eq!(names(&page["aggregates"]), ["other"]);
+        assert_eq!(page["next"], "other");
+        assert!(names(&list("/aggregates?limit=1&after=other", None).await["aggregates"]).is_empty());
+
+        assert_eq!(names(&list("/metrics/stale?idle_seconds=86400", None).await["metrics"]), ["other"]);
+        assert_eq!(names(&list("/debug/top", None).await["metrics"]), ["other"]);
+        let tree = list("/metrics/tree", None).await;
+        assert_eq!(names(&tree["root"]["children"]), ["other"]);
+        let tree = list("/metrics/tree", Some("dash")).await;
+        assert_eq!(names(&tree["root"]["children"]), ["acme", "other"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_matching_writes_are_forwarded_to_a_downstream_cluster() {
+        let downstream = spawn_worker(control_router(state_for(vec![spawn_real_worker().await]))).await;
+        let mut state = state_for(vec![spawn_real_worker().await]);
+        let rules = format!("match=acme.*,to=raftmetrics:{}", downstream);
+        let config = ControlConfig::from_lookup(|key| (key == forwarding::RULES_KEY).then(|| rules.clone()));
+        state.config = Arc::new(ControlConfig { worker_urls: state.config.worker_urls.clone(), ..config });
+        state.forwarder = Arc::new(Forwarder::new(state.config.forwarding.clone(), ForwardFrom::Control, "edge"));
+        let app = control_router(state.clone());
+        let record = |name: &str, hops: u32| {
+            Request::post("/metrics")
+                .header("content-type", "application/json")
+                .header(forwarding::HOPS_HEADER, hops)
+                .body(Body::from(serde_json::json!({ "metric_name": name, "value": 7.0 }).to_string()))
+                .unwrap()
+        };
+
+        assert_eq!(app.clone().oneshot(record("acme.cpu", 0)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(app.clone().oneshot(record("mem", 0)).await.unwrap().status(), StatusCode::OK);
+        // Taken here at the hop limit, then refused downstream past it
+        assert_eq!(app.clone().oneshot(record("acme.loop", 3)).await.unwrap().status(), StatusCode::OK);
+        let response = app.clone().oneshot(record("acme.loop", 4)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::LOOP_DETECTED);
+        assert_eq!(json_body(response).await["code"], "FORWARDING_LOOP");
+
+        state.forwarder.flush().await;
+        let client = crate::client::RaftMetricsClient::new(&downstream);
+        assert_eq!(client.get_metric("acme.cpu").await.unwrap().value, 7.0);
+        assert!(client.get_metric("mem").await.is_err());
+        assert!(client.get_metric("acme.loop").await.is_err());
+
+        let response = app.oneshot(Request::get("/forwarding/status").body(Body::empty()).unwrap()).await.unwrap();
+        let status = json_body(response).await;
+        assert_eq!(status["active"], true);
+        assert_eq!(status["destinations"][0]["forwarded"], 1);
+        assert_eq!(status["destinations"][0]["dropped"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_cluster_topology_assembles_worker_state() {
+        let mock = |raft: RaftDebugResponse| {
+            Router::new()
+                .route("/health", get(|| async { Json(serde_json::json!({ "status": "healthy" })) }))
+                .route("/debug/raft", get(move || async move { Json(raft) }))
+        };
+        let raft = |node_id: u64, term: u64| RaftDebugResponse {
+            node_id,
+            role: crate::raft::node::RaftRole::Leader,
+            leader_id: node_id,
+            term,
+            applied_index: 10 * node_id,
+            lag: Default::default(),
+        };
+        let first = spawn_worker(mock(raft(1, 2))).await;
+        let second = spawn_worker(mock(raft(2, 5))).await;
+        // A port nothing listens on
+        let gone = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let app = control_router(state_for(vec![first.clone(), second.clone(), gone.clone()]));
+        let response = app
+            .oneshot(Request::get("/cluster/topology").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let topology: ClusterTopology = serde_json::from_value(json_body(response).await).unwrap();
+
+        assert_eq!(topology.workers, vec![
+            WorkerTopology { partition: 0, url: first, status: "healthy".to_string(), raft: Some(raft(1, 2)) },
+            WorkerTopology { partition: 1, url: second, status: "healthy".to_string(), raft: Some(raft(2, 5)) },
+            WorkerTopology { partition: 2, url: gone, status: "unknown".to_string(), raft: None },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_slow_worker_bounded_by_request_budget() {
+        let app = control_router(state_for(vec![spawn_slow_worker().await]));
+
+        let started = std::time::Instant::now();
+        let response = app
+            .oneshot(
+                Request::get("/metrics/cpu")
+                    .header(TIMEOUT_HEADER, "50")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(elapsed < std::time::Duration::from_millis(500), "took {:?}", elapsed);
+        let body = json_body(response).await;
+        assert_eq!(body["stage"], "worker");
+        assert_eq!(body["budget_ms"], 50);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_ms_header_bounds_a_write() {
+        let app = control_router(state_for(vec![spawn_slow_worker().await]));
+
+        let started = std::time::Instant::now();
+        let response = app
+            .oneshot(
+                Request::post("/metrics?ack=quorum")
+                    .header(crate::api::deadline::DEADLINE_MS_HEADER, "50")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::json!({ "metric_name": "cpu", "value": 1.0 }).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(elapsed < std::time::Duration::from_millis(500), "took {:?}", elapsed);
+        let body = json_body(response).await;
+        assert_eq!((body["stage"].as_str(), body["budget_ms"].as_u64()), (Some("worker"), Some(50)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_deadline_fails_in_control_queue() {
+        let app = control_router(state_for(vec![spawn_slow_worker().await]));
+
+        let response = app
+            .oneshot(
+                Request::get("/metrics/cpu/aggregate")
+                    .header(crate::api::deadline::DEADLINE_HEADER, "1000")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(json_body(response).await["stage"], "control_queue");
+    }
+
+    #[tokio::test]
+    async fn test_remaining_budget_forwarded_to_worker() {
+        let router = Router::new().route(
+            "/metrics/:name",
+            get(|headers: axum::http::HeaderMap| async move {
+                let budget = headers.get(TIMEOUT_HEADER).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+                Json(serde_json::json!({ "name": budget, "value": 0.0, "timestamp": 0, "worker_id": 0 }))
+            }),
+        );
+        let app = control_router(state_for(vec![spawn_worker(router).await]));
+
+        let response = app
+            .oneshot(
+                Request::get("/metrics/cpu")
+                    .header(TIMEOUT_HEADER, "1000")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let forwarded: u64 = json_body(response).await["name"].as_str().unwrap().parse().unwrap();
+        assert!(forwarded > 0 && forwarded <= 995, "forwarded {}", forwarded);
+    }
+
+    #[tokio::test]
+    async fn test_batch_aggregate_spans_workers() {
+        let state = state_for(vec![spawn_real_worker().await, spawn_real_worker().await]);
+        let app = control_router(state.clone());
+        let names: Vec<String> = (0..6).map(|i| format!("panel_{}", i)).collect();
+        let partitions: std::collections::HashSet<_> = names.iter().map(|name| state.router.route(name)).collect();
+        assert_eq!(partitions.len(), 2, "test names should cover both workers");
+
+        for (i, name) in names.iter().enumerate() {
+            for value in [i as f64, i as f64 + 2.0] {
+                let body = serde_json::json!({ "metric_name": name, "value": value }).to_string();
+                let response = app.clone()
+                    .oneshot(
+                        Request::post("/metrics")
+                            .header("content-type", "application/json")
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+        }
+
+        let mut requested = names.clone();
+        requested.push("missing".to_string());
+        let response = app
+            .oneshot(
+                Request::post("/aggregate/batch")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::json!({ "names": requested }).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let aggregates = json_body(response).await;
+        assert_eq!(aggregates.as_object().unwrap().len(), 7);
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(aggregates[name]["count"], 2);
+            assert_eq!(aggregates[name]["average"], i as f64 + 1.0);
+        }
+        assert!(aggregates["missing"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_namespace_tree_merges_three_levels_across_workers() {
+        let mut worker_urls = Vec::new();
+        let partitions = [
+            [("svc.api.latency", 1.0), ("svc.db.read.latency", 3.0), ("other.cpu", 100.0)],
+            [("svc.api.errors", 2.0), ("svc.db.write.latency", 4.0), ("svc.web.latency", 5.0)],
+        ];
+        for samples in partitions {
+            let metrics = Arc::new(MetricsRegistry::new().unwrap());
+            for (name, value) in samples {
+                metrics.record_metric_with_timestamp(name, value, 100).await.unwrap();
+            }
+            let state = crate::api::worker::WorkerState::new(1, metrics, crate::config::RaftConfig::default()).unwrap();
+            worker_urls.push(spawn_worker(crate::api::worker::worker_router(state)).await);
+        }
+        let app = control_router(state_for(worker_urls));
+
+        let response = app
+            .oneshot(Request::get("/metrics/tree?prefix=svc.&depth=3").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let tree: NamespaceTree = serde_json::from_value(json_body(response).await).unwrap();
+        assert!(!tree.depth_truncated && !tree.nodes_truncated);
+
+        let root = tree.root.unwrap();
+        assert_eq!((root.path.as_str(), root.rollup.metrics, root.rollup.count), ("svc", 5, 5));
+        assert_eq!((root.rollup.sum, root.rollup.min, root.rollup.max), (15.0, 1.0, 5.0));
+        let children: Vec<(&str, u64, f64)> =
+            root.children.iter().map(|node| (node.path.as_str(), node.rollup.metrics, node.rollup.sum)).collect();
+        assert_eq!(children, [("svc.api", 2, 3.0), ("svc.db", 2, 7.0), ("svc.web", 1, 5.0)]);
+        let db = &root.children[1];
+        let levels: Vec<(&str, f64, &str, f64)> = db
+            .children
+            .iter()
+            .map(|node| (node.path.as_str(), node.rollup.max, node.children[0].path.as_str(), node.children[0].rollup.sum))
+            .collect();
+        assert_eq!(levels, [("svc.db.read", 3.0, "svc.db.read.latency", 3.0), ("svc.db.write", 4.0, "svc.db.write.latency", 4.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_multi_get_merges_partitions_and_isolates_failures() {
+        let first = spawn_real_worker().await;
+        let state = state_for(vec![first.clone(), spawn_real_worker().await]);
+        let app = control_router(state.clone());
+        let names: Vec<String> = (0..6).map(|i| format!("stat_{}", i)).collect();
+        let partitions: std::collections::HashSet<_> = names.iter().map(|name| state.router.route(name)).collect();
+        assert_eq!(partitions.len(), 2, "test names should cover both workers");
+
+        for (i, name) in names.iter().enumerate() {
+            let body = serde_json::json!({ "metric_name": name, "value": i as f64, "timestamp": 1000 + i }).to_string();
+            let response = app.clone()
+                .oneshot(
+                    Request::post("/metrics")
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let mut requested = names.clone();
+        requested.extend(["gone_a".to_string(), "gone_b".to_string()]);
+        let multi_get = |app: Router| {
+            let body = serde_json::json!({ "names": requested }).to_string();
+            async move {
+                let request = Request::post("/metrics/query")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                json_body(response).await
+            }
+        };
+
+        let result = multi_get(app).await;
+        assert_eq!(result["values"].as_object().unwrap().len(), 6);
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(result["values"][name]["value"], i as f64);
+            assert_eq!(result["values"][name]["timestamp"], 1000 + i);
+        }
+        assert_eq!(result["missing"], serde_json::json!(["gone_a", "gone_b"]));
+        assert!(result.get("errors").is_none());
+
+        // The same partitioning, with the second worker failing
+        let failing = spawn_worker(Router::new().route(
+            "/metrics/multi_get",
+            post(|| async { (StatusCode::INTERNAL_SERVER_ERROR, "disk on fire") }),
+        ))
+        .await;
+        let result = multi_get(control_router(state_for(vec![first, failing]))).await;
+        for name in names.iter().chain(["gone_a".to_string(), "gone_b".to_string()].iter()) {
+            let answered = result["values"].get(name).is_some()
+                || result["missing"].as_array().unwrap().iter().any(|missing| missing == name);
+            let errored = result["errors"].get(name).is_some();
+            assert_ne!(answered, errored, "{} should be answered or errored, not both", name);
+            assert_eq!(errored, state.router.route(name) == 1, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_listing_pages_across_workers() {
+        let now = Arc::new(std::sync::atomic::AtomicI64::new(1_000));
+        let mut urls = Vec::new();
+        for _ in 0..2 {
+            let clock = now.clone();
+            let metrics = MetricsRegistry::new().unwrap()
+                .with_clock(Arc::new(move || clock.load(std::sync::atomic::Ordering::SeqCst)));
+            let worker = crate::api::worker::WorkerState::new(1, Arc::new(metrics), crate::config::RaftConfig::default()).unwrap();
+            urls.push(spawn_worker(crate::api::worker::worker_router(worker)).await);
+        }
+        let state = state_for(urls);
+        let app = control_router(state.clone());
+
+        let names: Vec<String> = (0..7).map(|i| format!("old_{}", i)).collect();
+        for name in names.iter().map(String::as_str).chain(["fresh"]) {
+            if name == "fresh" {
+                now.store(200_000, std::sync::atomic::Ordering::SeqCst);
+            }
+            let body = serde_json::json!({ "metric_name": name, "value": 1.0 }).to_string();
+            let request = Request::post("/metrics").header("content-type", "application/json").body(Body::from(body)).unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+        }
+        let partitions: std::collections::HashSet<_> = names.iter().map(|name| state.router.route(name)).collect();
+        assert_eq!(partitions.len(), 2, "test names should cover both workers");
+
+        let mut listed = Vec::new();
+        let mut after = String::new();
+        loop {
+            let uri = format!("/metrics/stale?idle_seconds=86400&limit=3&after={}", after);
+            let response = app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let page = json_body(response).await;
+            let metrics = page["metrics"].as_array().unwrap();
+            assert!(metrics.len() <= 3);
+            listed.extend(metrics.iter().map(|metric| metric["name"].as_str().unwrap().to_string()));
+            match page["next"].as_str() {
+                Some(next) => after = next.to_string(),
+                None => break,
+            }
+        }
+        assert_eq!(listed, names);
+    }
+
+    #[tokio::test]
+    async fn test_top_metrics_merge_across_workers() {
+        let state = state_for(vec![spawn_real_worker().await, spawn_real_worker().await]);
+        let app = control_router(state.clone());
+
+        let names: Vec<String> = (0..6).map(|i| format!("hot_{}", i)).collect();
+        let partitions: std::collections::HashSet<_> = names.iter().map(|name| state.router.route(name)).collect();
+        assert_eq!(partitions.len(), 2, "test names should cover both workers");
+        // hot_0 is written once, hot_5 six times
+        for (i, name) in names.iter().enumerate() {
+            for _ in 0..=i {
+                let body = serde_json::json!({ "metric_name": name, "value": i as f64 }).to_string();
+                let request = Request::post("/metrics").header("content-type", "application/json").body(Body::from(body)).unwrap();
+                assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+            }
+        }
+
+        let response = app.clone().oneshot(Request::get("/debug/top?n=3").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let top = json_body(response).await;
+        let ranked: Vec<(&str, u64)> = top["metrics"].as_array().unwrap().iter()
+            .map(|metric| (metric["name"].as_str().unwrap(), metric["writes"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(ranked, vec![("hot_5", 6), ("hot_4", 5), ("hot_3", 4)]);
+
+        let response = app.oneshot(Request::get("/debug/top?n=2&by=value").body(Body::empty()).unwrap()).await.unwrap();
+        let top = json_body(response).await;
+        assert_eq!(top["by"], "value");
+        let ranked: Vec<f64> = top["metrics"].as_array().unwrap().iter().map(|metric| metric["value"].as_f64().unwrap()).collect();
+        assert_eq!(ranked, vec![5.0, 4.0]);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_listing_pages_across_workers() {
+        let state = state_for(vec![spawn_real_worker().await, spawn_real_worker().await]);
+        let app = control_router(state.clone());
+
+        let names: Vec<String> = (0..7).map(|i| format!("disk_{}", i)).collect();
+        for name in names.iter().map(String::as_str).chain(["net"]) {
+            let body = serde_json::json!({ "metric_name": name, "value": 1.0 }).to_string();
+            let request = Request::post("/metrics").header("content-type", "application/json").body(Body::from(body)).unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+        }
+        let partitions: std::collections::HashSet<_> = names.iter().map(|name| state.router.route(name)).collect();
+        assert_eq!(partitions.len(), 2, "test names should cover both workers");
+
+        let mut listed = Vec::new();
+        let mut after = String::new();
+        loop {
+            let uri = format!("/aggregates?prefix=disk_&limit=3&after={}", after);
+            let response = app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let page = json_body(response).await;
+            let aggregates = page["aggregates"].as_array().unwrap();
+            assert!(aggregates.len() <= 3);
+            for aggregate in aggregates {
+                assert_eq!(aggregate["count"], 1);
+                listed.push(aggregate["name"].as_str().unwrap().to_string());
+            }
+            match page["next"].as_str() {
+                Some(next) => after = next.to_string(),
+                None => break,
+            }
+        }
+        assert_eq!(listed, names);
+    }
+
+    #[tokio::test]
+    async fn test_series_read_by_exact_labels() {
+        let mut config = ControlConfig::from_lookup(|key| (key == "PARTITION_LABEL_KEYS").then(|| "region".to_string()));
+        config.worker_urls = vec![spawn_real_worker().await, spawn_real_worker().await];
+        let app = control_router(ControlState::new(config).unwrap());
+        let post_json = |uri: &str, body: serde_json::Value| {
+            Request::post(uri).header("content-type", "application/json").body(Body::from(body.to_string())).unwrap()
+        };
+        let records = [
+            (serde_json::json!({ "region": "eu" }), 1.0, 100),
+            (serde_json::json!({ "region": "eu" }), 3.0, 200),
+            (serde_json::json!({ "region": "us" }), 7.0, 150),
+            (serde_json::json!({ "region": "eu", "host": "a" }), 9.0, 300),
+        ];
+        for (labels, value, timestamp) in records {
+            let body = serde_json::json!({ "metric_name": "requests", "value": value, "timestamp": timestamp, "labels": labels });
+            let response = app.clone().oneshot(post_json("/metrics", body)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let get = |labels: serde_json::Value| {
+            app.clone().oneshot(post_json("/metrics/get", serde_json::json!({ "name": "requests", "labels": labels })))
+        };
+
+        let response = get(serde_json::json!({ "region": "eu" })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let eu: SeriesResponse = serde_json::from_value(json_body(response).await).unwrap();
+        assert_eq!((eu.value, eu.timestamp, eu.count, eu.sum, eu.max), (3.0, 200, 2, 4.0, 3.0));
+        let us: SeriesResponse = serde_json::from_value(json_body(get(serde_json::json!({ "region": "us" })).await.unwrap()).await).unwrap();
+        assert_eq!((us.value, us.count), (7.0, 1));
+        let host: SeriesResponse = serde_json::from_value(json_body(get(serde_json::json!({ "region": "eu", "host": "a" })).await.unwrap()).await).unwrap();
+        assert_eq!((host.value, host.count), (9.0, 1));
+
+        for labels in [serde_json::json!({ "region": "ap" }), serde_json::json!({ "host": "a" }), serde_json::json!({})] {
+            assert_eq!(get(labels).await.unwrap().status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_envelope_wraps_the_bare_body_with_meta() {
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+        for name in ["cpu", "mem"] {
+            let body = serde_json::json!({ "metric_name": name, "value": 1.0 }).to_string();
+            let request = Request::post("/metrics").header("content-type", "application/json").body(Body::from(body)).unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+        }
+        let get = |uri: &str| app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap());
+
+        let bare = json_body(get("/aggregates?limit=1").await.unwrap()).await;
+        assert_eq!(bare["next"], "cpu");
+        assert!(bare.get("meta").is_none());
+
+        // Taken off before the read is forwarded, so the worker answers bare
+        let response = get("/aggregates?limit=1&envelope=true").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let envelope: crate::api::envelope::Envelope = serde_json::from_value(json_body(response).await).unwrap();
+        assert_eq!(envelope.data, bare);
+        assert_eq!((envelope.meta.node_id, envelope.meta.next_cursor.as_deref()), (0, Some("cpu")));
+        assert!(envelope.meta.served_at > 0);
+
+        assert_eq!(json_body(get("/aggregates?limit=1&envelope=false").await.unwrap()).await, bare);
+        assert_eq!(get("/aggregates?envelope=yes").await.unwrap().status(), StatusCode::BAD_REQUEST);
+        // Errors stay bare
+        let error = json_body(get("/metrics/missing?envelope=true").await.unwrap()).await;
+        assert!(error.get("data").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_timestamp_carried_through_to_storage() {
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"backfill","value":7.5,"timestamp":1000}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(Request::get("/metrics/backfill/range?from=0&to=2000").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"{\"value\":7.5,\"timestamp\":1000}\n");
+    }
+
+    #[tokio::test]
+    async fn test_range_proxied_without_buffering() {
+        // Sends one page, then stalls; a buffering proxy would never answer
+        let stalling = || async {
+            let pages = stream::once(async { Ok::<_, std::io::Error>(Bytes::from("{\"value\":1.0,\"timestamp\":1}\n")) })
+                .chain(stream::once(async {
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                    Ok(Bytes::new())
+                }));
+            Body::from_stream(pages)
+        };
+        let worker = spawn_worker(Router::new().route("/metrics/:name/range", get(stalling))).await;
+        let app = control_router(state_for(vec![worker]));
+
+        let response = app
+            .oneshot(Request::get("/metrics/cpu/range?from=0&to=10").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut chunks = response.into_body().into_data_stream();
+        let first = tokio::time::timeout(std::time::Duration::from_secs(1), chunks.next())
+            .await
+            .expect("first page should arrive while the worker is still streaming")
+            .unwrap()
+            .unwrap();
+        assert_eq!(&first[..], b"{\"value\":1.0,\"timestamp\":1}\n");
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_a_new_default_deadline() {
+        let slow = spawn_slow_worker().await;
+        let budget = Arc::new(std::sync::Mutex::new("10000".to_string()));
+        let loader_budget = budget.clone();
+        let loader: ConfigLoader = Arc::new(move || {
+            let default_deadline = loader_budget.lock().unwrap().clone();
+            Ok(ControlConfig::from_lookup(|key| match key {
+                "WORKER_HOSTS" => Some(slow.clone()),
+                "DEFAULT_DEADLINE_MS" => Some(default_deadline.clone()),
+                _ => None,
+            }))
+        });
+        let handle = ControlHandle::new(ControlState::new(loader().unwrap()).unwrap(), loader);
+        let app = data_router(handle.clone());
+
+        *budget.lock().unwrap() = "50".to_string();
+        handle.reload().unwrap();
+        let response = app.oneshot(Request::get("/metrics/cpu").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(json_body(response).await["budget_ms"], 50);
+    }
+
+    #[tokio::test]
+    async fn test_reload_switches_workers_without_dropping_in_flight_requests() {
+        let slow = spawn_slow_worker().await;
+        let fresh = spawn_real_worker().await;
+        let hosts = Arc::new(std::sync::Mutex::new(slow));
+        let loader_hosts = hosts.clone();
+        let loader: ConfigLoader = Arc::new(move || {
+            let worker_hosts = loader_hosts.lock().unwrap().clone();
+            Ok(ControlConfig::from_lookup(|key| match key {
+                "WORKER_HOSTS" => Some(worker_hosts.clone()),
+                "READ_REQUEST_TIMEOUT_MS" => Some("5000".to_string()),
+                "ADMIN_TOKEN" => Some("secret".to_string()),
+                _ => None,
+            }))
+        });
+        let handle = ControlHandle::new(ControlState::new(loader().unwrap()).unwrap(), loader);
+        let app = reloadable_control_router(handle);
+        let admin = |request: axum::http::request::Builder| {
+            request.header("authorization", "Bearer secret").body(Body::empty()).unwrap()
+        };
+
+        let in_flight = tokio::spawn(app.clone().oneshot(Request::get("/metrics/cpu").body(Body::empty()).unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        *hosts.lock().unwrap() = format!("{},{}", fresh, fresh);
+        let response = app.clone().oneshot(admin(Request::post("/admin/reload"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["errors"].as_array().unwrap().len(), 1);
+
+        *hosts.lock().unwrap() = fresh.clone();
+        let response = app.clone().oneshot(admin(Request::post("/admin/reload"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let effective = json_body(response).await;
+        assert_eq!(effective["worker_urls"], serde_json::json!([fresh]));
+        assert_eq!(effective["admin_token"], "<redacted>");
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":42.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/cpu").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(json_body(response).await["value"], 42.0);
+
+        let response = in_flight.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["value"], 0.0);
+
+        let response = app.oneshot(admin(Request::get("/admin/config"))).await.unwrap();
+        assert_eq!(json_body(response).await["worker_urls"], serde_json::json!([fresh]));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_mode_refuses_writes_everywhere_and_survives_a_restart() {
+        let mut worker = crate::api::worker::WorkerState::new(1, Arc::new(MetricsRegistry::new().unwrap()), crate::config::RaftConfig::default()).unwrap();
+        worker.admin_token = Some(Arc::from("secret"));
+        let worker = spawn_worker(crate::api::worker::worker_router(worker)).await;
+        let path = std::env::temp_dir().join(format!("raftmetrics-read-only-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let start = || {
+            let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+            config.worker_urls = vec![worker.clone()];
+            let mut state = ControlState::new(config).unwrap();
+            state.read_only = Arc::new(ReadOnly::load(Some(path.clone())));
+            control_router(state)
+        };
+        let record = |value: f64| {
+            Request::post("/metrics")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "metric_name": "cpu", "value": value }).to_string()))
+                .unwrap()
+        };
+        let set = |enabled: bool| {
+            Request::post(format!("/admin/readonly?enabled={}", enabled))
+                .header("authorization", "Bearer secret")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let app = start();
+        assert_eq!(app.clone().oneshot(record(1.0)).await.unwrap().status(), StatusCode::OK);
+        let response = app.clone().oneshot(set(true)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(path.exists());
+
+        let response = app.clone().oneshot(record(2.0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json_body(response).await["code"], "READ_ONLY");
+        let response = app.clone().oneshot(Request::delete("/metrics/cpu").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let direct = reqwest::Client::new()
+            .post(format!("{}/process", worker))
+            .json(&serde_json::json!({ "metric_name": "cpu", "value": 3.0 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(direct.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        // Reads and introspection keep working and report the mode
+        let response = app.clone().oneshot(get("/metrics/cpu")).await.unwrap();
+        assert_eq!(json_body(response).await["value"], 1.0);
+        let response = app.clone().oneshot(get("/metrics/cpu/aggregate")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        for uri in ["/health", "/info", "/cluster/health"] {
+            assert_eq!(json_body(app.clone().oneshot(get(uri)).await.unwrap()).await["read_only"], true, "{}", uri);
+        }
+        let health: serde_json::Value = reqwest::get(format!("{}/health", worker)).await.unwrap().json().await.unwrap();
+        assert_eq!(health["read_only"], true);
+
+        // A restarted control node comes back read-only
+        drop(app);
+        let app = start();
+        assert_eq!(app.clone().oneshot(record(4.0)).await.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(app.clone().oneshot(set(false)).await.unwrap().status(), StatusCode::OK);
+        assert!(!path.exists());
+        assert_eq!(app.clone().oneshot(record(5.0)).await.unwrap().status(), StatusCode::OK);
+        let response = app.oneshot(get("/metrics/cpu")).await.unwrap();
+        assert_eq!(json_body(response).await["value"], 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_mode_reaches_workers_with_a_separate_admin_listener() {
+        let mut worker = crate::api::worker::WorkerState::new(1, Arc::new(MetricsRegistry::new().unwrap()), crate::config::RaftConfig::default()).unwrap();
+        worker.admin_token = Some(Arc::from("secret"));
+        let data = spawn_worker(crate::api::worker::data_router(worker.clone())).await;
+        let admin = spawn_worker(crate::api::worker::admin_router(worker)).await;
+        let client = reqwest::Client::new();
+        let process = || client.post(format!("{}/process", data)).json(&serde_json::json!({ "metric_name": "cpu", "value": 1.0 })).send();
+
+        // Only the control node, holding the token, switches the mode
+        let response = client.post(format!("{}/admin/readonly?enabled=true", data)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client.post(format!("{}/admin/readonly?enabled=true", admin)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+        config.worker_urls = vec![data.clone()];
+        let app = control_router(ControlState::new(config).unwrap());
+        let set = |enabled: bool| {
+            Request::post(format!("/admin/readonly?enabled={}", enabled))
+                .header("authorization", "Bearer secret")
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert_eq!(app.clone().oneshot(set(true)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(process().await.unwrap().status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        // Nor can anyone else switch writes back on during the freeze
+        let response = client.post(format!("{}/admin/readonly?enabled=false", data)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(process().await.unwrap().status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(app.oneshot(set(false)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(process().await.unwrap().status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_routing_preview_counts_moved_keys() {
+        let config = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some("w0:8081,w1:8081,w2:8081".to_string()),
+            "ADMIN_TOKEN" => Some("secret".to_string()),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+        let names: Vec<String> = (0..200).map(|i| format!("metric_{}", i)).collect();
+        let body = serde_json::json!({ "worker_count": 4, "names": names });
+
+        let response = app
+            .oneshot(
+                Request::post("/admin/routing_preview")
+                    .header("authorization", "Bearer secret")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let preview = json_body(response).await;
+        let moved = names.iter().filter(|name| partitioning::get_partition(name, 3) != partitioning::get_partition(name, 4)).count();
+        assert!(moved > 0);
+        assert_eq!(preview["moved"], moved);
+        assert_eq!(preview["total"], 200);
+        assert_eq!(preview["proposed_workers"], 4);
+        assert_eq!(preview["keys"][0]["proposed"], partitioning::get_partition("metric_0", 4));
+    }
+
+    #[tokio::test]
+    async fn test_registered_worker_receives_metrics_until_it_lapses() {
+        let configured = spawn_real_worker().await;
+        let joining = spawn_real_worker().await;
+        let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+        config.worker_urls = vec![configured.clone()];
+        let handle = ControlHandle::new(ControlState::new(config).unwrap(), Arc::new(|| unreachable!()));
+        let app = reloadable_control_router(handle.clone());
+        let post = |uri: &str, body: String| {
+            Request::post(uri)
+                .header("authorization", "Bearer secret")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let register = serde_json::json!({ "url": joining, "capacity": 4 }).to_string();
+        let response = app.clone().oneshot(post("/cluster/register", register)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let registered: RegisterResponse = serde_json::from_value(json_body(response).await).unwrap();
+        assert_eq!(registered.workers, [configured.clone(), joining.clone()]);
+        assert_eq!(registered.ttl_secs, 30);
+
+        let routed = handle.snapshot();
+        let name = (0..).map(|i| format!("joined_{}", i)).find(|name| routed.router.route(name) == 1).unwrap();
+        let record = serde_json::json!({ "metric_name": name, "value": 7.0 }).to_string();
+        let response = app.clone().oneshot(post("/metrics", record)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let stored = reqwest::get(format!("{}/metrics/{}", joining, name)).await.unwrap();
+        assert_eq!(stored.status(), reqwest::StatusCode::OK);
+
+        // Heartbeats stop; once the TTL passes the worker drops out of routing
+        assert!(handle.expire_workers(Instant::now()).unwrap().is_empty());
+        let lapsed = handle.expire_workers(Instant::now() + std::time::Duration::from_secs(31)).unwrap();
+        assert_eq!(lapsed, vec![joining.clone()]);
+        let routed = handle.snapshot();
+        assert_eq!(*routed.worker_urls, [configured]);
+        assert_eq!(routed.router.route(&name), 0);
+
+        let deregister = serde_json::json!({ "url": joining }).to_string();
+        let response = app.oneshot(post("/cluster/deregister", deregister)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_ack_levels_in_a_three_node_group() {
+        use crate::{api::worker::WorkerState, raft::{network::LocalNetwork, node::RaftRole}};
+
+        let network = LocalNetwork::new();
+        let workers: Vec<WorkerState> = (0..3)
+            .map(|id| {
+                let metrics = Arc::new(MetricsRegistry::new().unwrap());
+                WorkerState::in_group(id, metrics, crate::config::RaftConfig::default(), vec![1, 2, 3], vec![], network.clone()).unwrap()
+            })
+            .collect();
+        let mut leader = None;
+        for _ in 0..100 {
+            leader = workers.iter().position(|worker| worker.raft_status.role() == RaftRole::Leader);
+            if leader.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let leader = leader.expect("no leader elected");
+        let app = control_router(state_for(vec![spawn_worker(crate::api::worker::worker_router(workers[leader].clone())).await]));
+        let record = |ack: &str, value: f64| {
+            Request::post(format!("/metrics?ack={}", ack))
+                .header("content-type", "application/json")
+                .header(TIMEOUT_HEADER, "500")
+                .body(Body::from(serde_json::json!({ "metric_name": "billing", "value": value }).to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(record("none", 1.0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(json_body(response).await["ack"], "none");
+        let mut stored = None;
+        for _ in 0..50 {
+            stored = workers[leader].metrics.get_metric("billing").await.unwrap();
+            if stored.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(stored, Some(1.0));
+
+        // Local waits for the leader to apply, which needs a majority's commit
+        let response = app.clone().oneshot(record("local", 2.0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!((body["ack"].as_str(), body.get("degraded")), (Some("quorum"), None));
+        assert_eq!(workers[leader].metrics.get_metric("billing").await.unwrap(), Some(2.0));
+        let response = app.clone().oneshot(record("quorum", 3.0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["ack"], "quorum");
+        assert!(body["version"].is_string());
+
+        // With both followers gone a quorum write degrades instead of claiming a commit
+        for (id, _) in workers.iter().enumerate().filter(|(id, _)| *id != leader) {
+            network.disconnect(id as u64 + 1);
+        }
+        let response = app.clone().oneshot(record("quorum", 4.0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = json_body(response).await;
+        assert_eq!((body["ack"].as_str(), body["degraded"].as_bool()), (Some("none"), Some(true)));
+        assert!(body.get("version").is_none());
+        // and a local one cannot apply, so it runs out of time
+        let response = app.oneshot(record("local", 5.0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(workers[leader].metrics.get_metric("billing").await.unwrap(), Some(3.0));
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_writes_are_forwarded_inline_once_their_slots_are_taken() {
+        let mut state = state_for(vec![spawn_real_worker().await]);
+        state.unacked = Arc::new(tokio::sync::Semaphore::new(0));
+        let request = Request::post("/metrics?ack=none")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({ "metric_name": "debug.hits", "value": 1.0 }).to_string()))
+            .unwrap();
+
+        let response = control_router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!((body["ack"].as_str(), body.get("degraded")), (Some("quorum"), None));
+        let read = control_router(state).oneshot(Request::get("/metrics/debug.hits").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(json_body(read).await["value"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_promoted_standby_takes_over_a_dead_primary() {
+        use crate::{api::worker::WorkerState, raft::{network::LocalNetwork, node::RaftRole}};
+
+        // Three voters and a standby that follows the log as a learner
+        let network = LocalNetwork::new();
+        let mut workers: Vec<WorkerState> = (0..4)
+            .map(|id| {
+                let metrics = Arc::new(MetricsRegistry::new().unwrap());
+                WorkerState::in_group(id, metrics, crate::config::RaftConfig::default(), vec![1, 2, 3], vec![4], network.clone()).unwrap()
+            })
+            .collect();
+        workers[3].admin_token = Some(Arc::from("secret"));
+        let standby = &workers[3];
+        standby.serving.store(false, std::sync::atomic::Ordering::Release);
+        let mut urls = Vec::new();
+        for worker in &workers {
+            urls.push(spawn_worker(crate::api::worker::worker_router(worker.clone())).await);
+        }
+        let leader_among = |candidates: &[usize]| {
+            candidates.iter().copied().find(|&i| workers[i].raft_status.role() == RaftRole::Leader)
+        };
+        let mut primary = None;
+        for _ in 0..100 {
+            primary = leader_among(&[0, 1, 2]);
+            if primary.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let primary = primary.expect("no leader elected");
+        let config = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some(urls[primary].clone()),
+            "STANDBY_HOSTS" => Some(format!("0={}", urls[3])),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+        let client = reqwest::Client::new();
+
+        // Unpromoted, the standby answers health checks but not data routes
+        for path in ["/health", "/raft/status"] {
+            assert_eq!(client.get(format!("{}{}", urls[3], path)).send().await.unwrap().status(), reqwest::StatusCode::OK);
+        }
+        let refused = client.get(format!("{}/metrics/anything", urls[3])).send().await.unwrap();
+        assert_eq!(refused.status(), reqwest::StatusCode::MISDIRECTED_REQUEST);
+        assert_eq!(refused.json::<serde_json::Value>().await.unwrap()["code"], "NOT_SERVING");
+
+        for i in 0..20 {
+            let request = Request::post("/metrics?ack=quorum")
+                .header("content-type", "application/json")
+                .header(TIMEOUT_HEADER, "5000")
+                .body(Body::from(serde_json::json!({ "metric_name": format!("orders_{}", i), "value": i as f64 }).to_string()))
+                .unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+        }
+
+        // Kill the primary: cut off from its group and refusing requests
+        network.disconnect(primary as u64 + 1);
+        workers[primary].set_ready(false);
+        let fetch = |name: String| Request::get(format!("/metrics/{}", name)).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(fetch("orders_0".to_string())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Only an operator holding the admin token may promote
+        let response = client.post(format!("{}/admin/promote", urls[3])).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert!(!standby.is_serving());
+        let promote = || async {
+            let response = client.post(format!("{}/admin/promote", urls[3])).bearer_auth("secret").send().await.unwrap();
+            response.json::<serde_json::Value>().await.unwrap()
+        };
+        assert_eq!(promote().await["promoted"], true);
+        assert_eq!(promote().await["promoted"], false);
+        assert!(standby.is_serving());
+
+        // Every acknowledged write is served by the standby
+        for i in 0..20 {
+            let mut body = serde_json::Value::Null;
+            for _ in 0..100 {
+                let response = app.clone().oneshot(fetch(format!("orders_{}", i))).await.unwrap();
+                if response.status() == StatusCode::OK {
+                    body = json_body(response).await;
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            assert_eq!((body["value"].as_f64(), body["source"].as_str()), (Some(i as f64), Some("standby")), "orders_{}", i);
+        }
+
+        // With the primary unreachable, writes go to the standby too
+        let mut survivor = None;
+        for _ in 0..100 {
+            survivor = leader_among(&[0, 1, 2].into_iter().filter(|&i| i != primary).collect::<Vec<_>>());
+            if survivor.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        survivor.expect("no new leader elected");
+        let unreachable = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let config = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some(unreachable.clone()),
+            "STANDBY_HOSTS" => Some(format!("0={}", urls[3])),
+            _ => None,
+        });
+        let failed_over = control_router(ControlState::new(config).unwrap());
+        let request = Request::post("/metrics?ack=quorum")
+            .header("content-type", "application/json")
+            .header(TIMEOUT_HEADER, "5000")
+            .body(Body::from(serde_json::json!({ "metric_name": "orders_new", "value": 99.0 }).to_string()))
+            .unwrap();
+        assert_eq!(failed_over.oneshot(request).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(standby.metrics.get_metric("orders_new").await.unwrap(), Some(99.0));
+    }
+
+    /// Serves a webhook that keeps each body it is sent and answers `status`.
+    async fn spawn_report_sink(status: StatusCode) -> (String, Arc<std::sync::Mutex<Vec<serde_json::Value>>>) {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let url = spawn_worker(Router::new().route("/hook", post(move |Json(body): Json<serde_json::Value>| {
+            let sink = sink.clone();
+            async move {
+                sink.lock().unwrap().push(body);
+                status
+            }
+        })))
+        .await;
+        (format!("{}/hook", url), received)
+    }
+
+    fn report_state(worker: String, sink: &str, format: &str) -> ControlState {
+        let reports = serde_json::json!([{
+            "id": "daily",
+            "schedule": "0 9 * * *",
+            "patterns": ["svc"],
+            "functions": ["sum", "count"],
+            "url": sink,
+            "format": format,
+            "idle_after_secs": 0,
+        }]);
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "REPORTS" => Some(reports.to_string()),
+            "ADMIN_TOKEN" => Some("secret".into()),
+            _ => None,
+        });
+        config.worker_urls = vec![worker];
+        ControlState::new(config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_report_fires_on_its_schedule() {
+        let (sink, received) = spawn_report_sink(StatusCode::OK).await;
+        let state = report_state(spawn_real_worker().await, &sink, "json");
+        let app = control_router(state.clone());
+        let written = [("svc.api.latency", 2.0), ("svc.api.errors", 1.0), ("svc.db.latency", 7.0), ("svcs.queue", 1.0), ("batch.jobs", 1.0)];
+        for (name, value) in written {
+            let request = Request::post("/metrics?ack=quorum")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "metric_name": name, "value": value }).to_string()))
+                .unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+        }
+        // Idle counts whole seconds since the last write
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let at = |text: &str| chrono::DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&chrono::Utc);
+
+        assert!(reports::fire_due(&state, at("2026-10-15T08:00:00Z"), at("2026-10-15T08:59:59Z")).is_empty());
+        assert_eq!(reports::fire_due(&state, at("2026-10-15T08:59:45Z"), at("2026-10-15T09:00:00Z")), ["daily"]);
+        for _ in 0..100 {
+            if !received.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let body: reports::ReportBody = serde_json::from_value(received.lock().unwrap()[0].clone()).unwrap();
+        assert_eq!(body.generated_at, at("2026-10-15T09:00:00Z").timestamp());
+        let section = &body.sections[0];
+        assert_eq!(section.total.as_ref().unwrap().values, [10.0, 3.0]);
+        let top: Vec<(&str, f64)> = section.top.iter().map(|row| (row.path.as_str(), row.values[0])).collect();
+        assert_eq!(top, [("svc.db", 7.0), ("svc.api", 3.0)]);
+        let mut idle: Vec<&str> = section.anomalies.iter().map(|metric| metric.name.as_str()).collect();
+        idle.sort();
+        assert_eq!(idle, ["svc.api.errors", "svc.api.latency", "svc.db.latency"]);
+
+        let listed = json_body(app.oneshot(Request::get("/reports").body(Body::empty()).unwrap()).await.unwrap()).await;
+        assert_eq!(listed[0]["last_run"]["status"], "succeeded");
+        assert_eq!(listed[0]["last_run"]["trigger"], "schedule");
+        assert!(listed[0].get("url").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failing_report_webhook_is_retried_then_recorded() {
+        let (sink, received) = spawn_report_sink(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let app = control_router(report_state(spawn_real_worker().await, &sink, "markdown"));
+
+        let response = app.clone().oneshot(Request::post("/reports/daily/run").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(received.lock().unwrap().is_empty());
+        let run_report = |id: &str| Request::post(format!("/reports/{}/run", id)).header("authorization", "Bearer secret").body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(run_report("daily")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let run = json_body(response).await;
+        assert_eq!((run["status"].as_str(), run["attempts"].as_u64()), (Some("failed"), Some(3)));
+        assert_eq!(run["error"], "webhook answered 500 Internal Server Error");
+        assert_eq!(received.lock().unwrap().len(), 3);
+        assert!(received.lock().unwrap()[0]["text"].as_str().unwrap().contains("_no metrics_"));
+
+        let listed = json_body(app.clone().oneshot(Request::get("/reports").body(Body::empty()).unwrap()).await.unwrap()).await;
+        assert_eq!((listed[0]["running"].as_bool(), listed[0]["last_run"]["status"].as_str()), (Some(false), Some("failed")));
+        assert_eq!(app.oneshot(run_report("weekly")).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cluster_health_asks_workers_that_send_no_heartbeats() {
+        use crate::api::membership::Liveness;
+
+        let quiet = spawn_real_worker().await;
+        let mut config = ControlConfig::from_lookup(|_| None);
+        config.worker_urls = vec![quiet.clone(), "http://127.0.0.1:9".to_string()];
+        let app = control_router(ControlState::new(config).unwrap());
+
+        let response = app.oneshot(Request::get("/cluster/health").body(Body::empty()).unwrap()).await.unwrap();
+        let health: ClusterHealth = serde_json::from_value(json_body(response).await).unwrap();
+        let statuses: Vec<_> = health.workers.iter().map(|worker| (worker.url.as_str(), worker.status)).collect();
+        assert_eq!(statuses, [(quiet.as_str(), Liveness::Unknown), ("http://127.0.0.1:9", Liveness::Unknown)]);
+        assert_eq!(health.workers[0].raft, Some(RaftHealth::default()));
+        let unreachable = health.workers[1].raft.as_ref().unwrap();
+        assert_eq!(unreachable.status, HealthStatus::Unhealthy);
+        assert!(unreachable.reason.as_ref().is_some_and(|reason| reason.starts_with("health check failed")));
+    }
+
+    #[tokio::test]
+    async fn test_worker_that_stops_heartbeating_is_marked_down() {
+        use crate::api::membership::Liveness;
+
+        let steady = spawn_real_worker().await;
+        let silent = spawn_real_worker().await;
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "ADMIN_TOKEN" => Some("secret".to_string()),
+            "HEARTBEAT_TIMEOUT_SECS" => Some("10".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![steady.clone(), silent.clone()];
+        let handle = ControlHandle::new(ControlState::new(config).unwrap(), Arc::new(|| unreachable!()));
+        let app = reloadable_control_router(handle.clone());
+        let heartbeat = |url: &str| {
+            Request::post("/cluster/heartbeat")
+                .header("authorization", "Bearer secret")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "url": url, "load": { "metric_count": 3, "sample_rows": 9, "tombstones": 0 } }).to_string()))
+                .unwrap()
+        };
+
+        for url in [&steady, &silent] {
+            let response = app.clone().oneshot(heartbeat(url.as_str())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        }
+        let response = app.clone().oneshot(heartbeat("http://stranger:9000")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Only the steady worker keeps beating past the timeout
+        let later = Instant::now() + std::time::Duration::from_secs(8);
+        handle.heartbeat_worker(&HeartbeatRequest { url: steady.clone(), load: Default::default(), raft: None }, later).unwrap();
+        assert!(handle.mark_down_workers(Instant::now()).unwrap().is_empty());
+        let names: Vec<String> = (0..100).map(|i| format!("metric_{}", i)).collect();
+        let before: Vec<usize> = names.iter().map(|name| handle.snapshot().router.route(name)).collect();
+        let timed_out = Instant::now() + std::time::Duration::from_secs(11);
+        assert_eq!(handle.mark_down_workers(timed_out).unwrap(), vec![silent.clone()]);
+
+        // The silent worker keeps its slot, so the steady worker's metrics stay put
+        let routed = handle.snapshot();
+        assert_eq!(routed.config.down_workers, *std::slice::from_ref(&silent));
+        assert!(before.contains(&0) && before.contains(&1));
+        assert!(names.iter().all(|name| routed.router.route(name) == 0));
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":1.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(Request::get("/cluster/health").body(Body::empty()).unwrap()).await.unwrap();
+        let health: ClusterHealth = serde_json::from_value(json_body(response).await).unwrap();
+        assert_eq!(health.heartbeat_timeout_secs, 10);
+        let statuses: Vec<_> = health.workers.iter().map(|worker| (worker.url.as_str(), worker.status, worker.routed)).collect();
+        assert_eq!(statuses, [(steady.as_str(), Liveness::Up, true), (silent.as_str(), Liveness::Down, false)]);
+        assert_eq!(health.workers[1].load.as_ref().map(|load| load.metric_count), Some(3));
+
+        // A heartbeat brings it back
+        let response = app.oneshot(heartbeat(silent.as_str())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(handle.snapshot().config.down_workers.is_empty());
+        assert_eq!(*handle.snapshot().worker_urls, [steady, silent]);
+        // and every metric is back where it was before it went down
+        let after: Vec<usize> = names.iter().map(|name| handle.snapshot().router.route(name)).collect();
+        assert_eq!(after, before);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_copies_marked_writes_without_affecting_clients() {
+        use crate::api::shadow::SHADOW_HEADER;
+
+        let primary = spawn_real_worker().await;
+        let marks = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = marks.clone();
+        let failing_shadow = Router::new()
+            .route("/process", post(move |headers: axum::http::HeaderMap| {
+                let recorder = recorder.clone();
+                async move {
+                    recorder.lock().unwrap().push(headers.get(SHADOW_HEADER).map(|v| v.to_str().unwrap().to_string()));
+                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }))
+            .route("/metrics/:name", get(|| async {
+                Json(serde_json::json!({ "name": "cpu", "value": -1.0, "timestamp": 0, "worker_id": 0 }))
+            }));
+        let shadow = spawn_worker(failing_shadow).await;
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "SHADOW_URL" => Some(shadow.clone()),
+            "SHADOW_PERCENT" => Some("25".to_string()),
+            "SHADOW_COMPARE_READS" => Some("true".to_string()),
+            "SHADOW_TIMEOUT_MS" => Some("5000".to_string()),
+            "ADMIN_TOKEN" => Some("secret".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![primary];
+        let app = control_router(ControlState::new(config).unwrap());
+
+        // Shadow copies take two seconds to fail; the client never waits on them
+        let started = Instant::now();
+        for i in 0..20 {
+            let response = app.clone()
+                .oneshot(
+                    Request::post("/metrics")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{"metric_name":"cpu","value":{}}}"#, i)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(2), "{:?}", started.elapsed());
+        let response = app.clone().oneshot(Request::get("/metrics/cpu").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(json_body(response).await["value"], 19.0);
+
+        let stats = || async {
+            let request = Request::get("/admin/shadow/stats").header("authorization", "Bearer secret");
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+            serde_json::from_value::<ShadowStats>(json_body(response).await).unwrap()
+        };
+        let mut settled = stats().await;
+        for _ in 0..50 {
+            if settled.failed == 5 && settled.comparisons == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            settled = stats().await;
+        }
+        assert_eq!((settled.writes_seen, settled.duplicated, settled.dropped), (20, 5, 0));
+        assert_eq!((settled.succeeded, settled.failed), (0, 5));
+        assert_eq!(settled.statuses, [("500".to_string(), 5)].into_iter().collect());
+        assert!(settled.mean_latency_ms >= 2000.0);
+        assert_eq!((settled.comparisons, settled.mismatches), (1, 1));
+        assert_eq!(*marks.lock().unwrap(), vec![Some("1".to_string()); 5]);
+    }
+
+    #[tokio::test]
+    async fn test_sample_rate_requires_admin_token() {
+        let mut worker = crate::api::worker::WorkerState::new(1, Arc::new(MetricsRegistry::new().unwrap()), crate::config::RaftConfig::default()).unwrap();
+        worker.admin_token = Some(Arc::from("secret"));
+        let worker = spawn_worker(crate::api::worker::worker_router(worker)).await;
+        let body = r#"{"sample_rate":0.0}"#;
+        let response = reqwest::Client::new()
+            .put(format!("{}/metrics/cpu/sample_rate", worker))
+            .header("content-type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+        config.worker_urls = vec![worker];
+        let app = control_router(ControlState::new(config).unwrap());
+
+        for (auth, status) in [(None, StatusCode::UNAUTHORIZED), (Some("Bearer secret"), StatusCode::NO_CONTENT)] {
+            let mut request = Request::put("/metrics/cpu/sample_rate").header("content-type", "application/json");
+            if let Some(auth) = auth {
+                request = request.header("authorization", auth);
+            }
+            let response = app.clone().oneshot(request.body(Body::from(body)).unwrap()).await.unwrap();
+            assert_eq!(response.status(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_reset_requires_token_and_clears_workers() {
+        let mut worker = crate::api::worker::WorkerState::new(1, Arc::new(MetricsRegistry::new().unwrap()), crate::config::RaftConfig::default()).unwrap();
+        worker.admin_token = Some(Arc::from("secret"));
+        let worker = spawn_worker(crate::api::worker::worker_router(worker)).await;
+        // Nor does the worker take a reset that skips the control node
+        let response = reqwest::Client::new().post(format!("{}/admin/reset", worker)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+        config.worker_urls = vec![worker];
+        let app = control_router(ControlState::new(config).unwrap());
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":1.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for auth in [None, Some("Bearer wrong")] {
+            let mut request = Request::post("/admin/reset");
+            if let Some(auth) = auth {
+                request = request.header("authorization", auth);
+            }
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/admin/reset")
+                    .header("authorization", "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for uri in ["/metrics/cpu", "/metrics/cpu/aggregate"] {
+            let response = app.clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert!(!response.status().is_success(), "{} still served data", uri);
+        }
+        let delta_uri = format!("/metrics/cpu/delta?from=0&to={}", chrono::Utc::now().timestamp() + 60);
+        let response = app
+            .oneshot(Request::get(delta_uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_capture_records_only_sampled_matching_ingest() {
+        let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+        config.worker_urls = vec![spawn_real_worker().await];
+        let app = control_router(ControlState::new(config).unwrap());
+        let admin = |request: axum::http::request::Builder, body: &str| {
+            request
+                .header("authorization", "Bearer secret")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let ingest = |name: &str| {
+            let body = format!(r#"{{"metric_name":"{}","value":1.0}}"#, name);
+            Request::post("/metrics")
+                .header("content-type", "application/json")
+                .header("content-length", body.len())
+                .header("x-api-key", "client-key")
+                .header("x-forwarded-for", "10.0.0.7")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let start = r#"{"metric":"cpu_*","sample_rate":0.5,"max_entries":10}"#;
+        let response = app.clone().oneshot(admin(Request::post("/admin/capture/start"), start)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for name in ["cpu_user", "mem_used", "cpu_system", "cpu_user", "disk_io", "cpu_idle"] {
+            let response = app.clone().oneshot(ingest(name)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app.clone().oneshot(admin(Request::get("/admin/capture"), "")).await.unwrap();
+        let capture = json_body(response).await;
+        assert_eq!(capture["active"], true);
+        assert_eq!(capture["matched"], 4);
+        let entries = capture["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        for entry in entries {
+            assert!(entry["metric_name"].as_str().unwrap().starts_with("cpu_"));
+            assert_eq!(entry["headers"]["x-api-key"], "<redacted>");
+            assert_eq!(entry["source"], "10.0.0.7");
+            assert_eq!(entry["partition"], 0);
+            assert!(entry["body"].as_str().unwrap().contains("\"value\":1.0"));
+        }
+
+        let response = app.clone().oneshot(admin(Request::post("/admin/capture/stop"), "")).await.unwrap();
+        assert_eq!(json_body(response).await["active"], false);
+        app.clone().oneshot(ingest("cpu_user")).await.unwrap();
+        app.clone().oneshot(ingest("cpu_user")).await.unwrap();
+        let response = app.oneshot(admin(Request::get("/admin/capture"), "")).await.unwrap();
+        assert_eq!(json_body(response).await["entries"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_admin_disabled_without_token() {
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+        let response = app
+            .oneshot(
+                Request::post("/admin/reset")
+                    .header("authorization", "Bearer anything")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_metric_etag_answers_not_modified_until_the_value_changes() {
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+        let record = |value: f64| {
+            Request::post("/metrics")
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"metric_name":"gauge","value":{}}}"#, value)))
+                .unwrap()
+        };
+        let fetch = |etag: Option<&str>| {
+            let mut request = Request::get("/metrics/gauge");
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        assert_eq!(app.clone().oneshot(record(1.0)).await.unwrap().status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(fetch(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/"));
+
+        let response = app.clone().oneshot(fetch(Some(&etag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag.as_str());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+
+        assert_eq!(app.clone().oneshot(record(2.0)).await.unwrap().status(), StatusCode::OK);
+        let response = app.clone().oneshot(fetch(Some(&etag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[ETAG], etag.as_str());
+        assert_eq!(json_body(response).await["value"], 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_envelope_etag_only_validates_the_wrapped_body() {
+        let app = control_router(state_for(vec![spawn_real_worker().await]));
+        let record = Request::post("/metrics")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"metric_name":"gauge","value":1.0}"#))
+            .unwrap();
+        assert_eq!(app.clone().oneshot(record).await.unwrap().status(), StatusCode::OK);
+        let fetch = |uri: &str, etag: &str| {
+            Request::get(uri).header(IF_NONE_MATCH, etag).body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(Request::get("/metrics/gauge").body(Body::empty()).unwrap()).await.unwrap();
+        let bare = response.headers()[ETAG].to_str().unwrap().to_string();
+        let response = app.clone().oneshot(fetch("/metrics/gauge?envelope=true", &bare)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let wrapped = response.headers()[ETAG].to_str().unwrap().to_string();
+        assert_ne!(wrapped, bare);
+        assert_eq!(json_body(response).await["data"]["value"], 1.0);
+
+        let response = app.clone().oneshot(fetch("/metrics/gauge?envelope=true", &wrapped)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], wrapped.as_str());
+        let response = app.clone().oneshot(fetch("/metrics/gauge", &wrapped)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], bare.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_reads_through_control_match_direct_worker_reads() {
+        let worker = spawn_real_worker().await;
+        let app = control_router(state_for(vec![worker.clone()]));
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":1.5,"timestamp":100}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for path in ["/metrics/cpu", "/metrics/cpu/aggregate"] {
+            let mut proxied = json_body(app.clone().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap()).await;
+            if path == "/metrics/cpu" {
+                assert_eq!(proxied.as_object_mut().unwrap().remove("source"), Some("primary".into()));
+            }
+            let direct: serde_json::Value = reqwest::get(format!("{}{}", worker, path)).await.unwrap().json().await.unwrap();
+            assert_eq!(proxied, direct, "{}", path);
+            assert_eq!(proxied["worker_id"], 1);
+        }
+
+        let metric: MetricValueResponse = serde_json::from_value(serde_json::json!({
+            "name": "cpu", "value": 1.5, "timestamp": 100, "worker_id": 1,
+        }))
+        .unwrap();
+        assert_eq!(metric.timestamp, 100);
+        let drifted = serde_json::json!({ "name": "cpu", "value": 1.5, "timestamp": 100, "worker_id": 1, "host": "w1" });
+        assert!(serde_json::from_value::<MetricValueResponse>(drifted).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_cache_serves_repeat_reads_until_a_write() {
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "READ_CACHE_SIZE" => Some("16".to_string()),
+            "READ_CACHE_TTL_MS" => Some("60000".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![spawn_real_worker().await];
+        let state = ControlState::new(config).unwrap();
+        let app = control_router(state.clone());
+        let record = |value: f64| {
+            Request::post("/metrics")
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"metric_name":"cached","value":{}}}"#, value)))
+                .unwrap()
+        };
+        let fetch = || Request::get("/metrics/cached").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(record(1.0)).await.unwrap().status(), StatusCode::OK);
+
+        let hits = CONTROL_CACHE_HITS_TOTAL.get();
+        assert_eq!(json_body(app.clone().oneshot(fetch()).await.unwrap()).await["value"], 1.0);
+        assert_eq!(json_body(app.clone().oneshot(fetch()).await.unwrap()).await["value"], 1.0);
+        assert_eq!(state.read_pool.sent(), 1);
+        assert!(CONTROL_CACHE_HITS_TOTAL.get() > hits);
+
+        // A write through the control node drops the cached value
+        assert_eq!(app.clone().oneshot(record(2.0)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(json_body(app.clone().oneshot(fetch()).await.unwrap()).await["value"], 2.0);
+        assert_eq!(state.read_pool.sent(), 2);
+    }
+
+    /// A worker answering every `GET /metrics/:name` after `delay`,
+    /// counting the reads.
+    async fn spawn_counting_worker(delay: std::time::Duration) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        let reads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = reads.clone();
+        let worker = spawn_worker(Router::new().route("/metrics/:name", get(move |Path(name): Path<String>| {
+            counted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(delay).await;
+                Json(serde_json::json!({ "name": name, "value": 4.0, "timestamp": 1_000, "worker_id": 0 }))
+            }
+        })))
+        .await;
+        (worker, reads)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_send_one_read() {
+        let (worker, reads) = spawn_counting_worker(std::time::Duration::from_millis(100)).await;
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "READ_CACHE_SIZE" => Some("16".to_string()),
+            "READ_CACHE_TTL_MS" => Some("60000".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![worker];
+        let app = control_router(ControlState::new(config).unwrap());
+
+        let fetches = (0..8).map(|_| app.clone().oneshot(Request::get("/metrics/hot").body(Body::empty()).unwrap()));
+        for response in futures_util::future::join_all(fetches).await {
+            assert_eq!(json_body(response.unwrap()).await["value"], 4.0);
+        }
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_priming_fills_the_cache_before_ready() {
+        let (worker, reads) = spawn_counting_worker(std::time::Duration::from_millis(50)).await;
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "READ_CACHE_SIZE" => Some("16".to_string()),
+            "READ_CACHE_TTL_MS" => Some("60000".to_string()),
+            "CACHE_PRIME_METRICS" => Some("cpu, mem,disk".to_string()),
+            "CACHE_PRIME_TOP" => Some("1".to_string()),
+            "CACHE_PRIME_CONCURRENCY" => Some("2".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![worker];
+        let state = ControlState::new(config).unwrap();
+        for name in ["cpu", "net", "net", "swap"] {
+            state.hot_reads.record(name);
+        }
+        let app = control_router(state.clone());
+
+        prime_on_startup(state.clone());
+        let response = app.clone().oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json_body(response).await["ready"], false);
+        while !state.ready.load(Ordering::Acquire) {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        for name in ["cpu", "mem", "disk", "net"] {
+            assert!(state.read_cache.get(name, Instant::now()).is_some(), "{} was not primed", name);
+        }
+        assert!(state.read_cache.get("swap", Instant::now()).is_none());
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 4);
+        let response = app.oneshot(Request::get("/metrics/mem").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(json_body(response).await["source"], "primary");
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 4);
+
+        // A worker slower than the timeout does not hold readiness back
+        let (slow, _) = spawn_counting_worker(std::time::Duration::from_secs(10)).await;
+        let mut config = ControlConfig::from_lookup(|key| match key {
+            "READ_CACHE_SIZE" => Some("16".to_string()),
+            "CACHE_PRIME_METRICS" => Some("cpu".to_string()),
+            "CACHE_PRIME_TIMEOUT_MS" => Some("50".to_string()),
+            _ => None,
+        });
+        config.worker_urls = vec![slow];
+        let state = ControlState::new(config).unwrap();
+        prime_on_startup(state.clone());
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(state.ready.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn test_quorum_read_repairs_the_stale_replica() {
+        let (ahead, behind) = (Arc::new(MetricsRegistry::new().unwrap()), Arc::new(MetricsRegistry::new().unwrap()));
+        ahead.record_metric_with_timestamp("cpu", 7.0, 2_000).await.unwrap();
+        behind.record_metric_with_timestamp("cpu", 3.0, 1_000).await.unwrap();
+        let replica = spawn_real_worker_with(behind.clone()).await;
+        let primary = spawn_real_worker_with(ahead).await;
+        let config = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some(primary.clone()),
+            "REPLICA_HOSTS" => Some(format!("0={}", replica)),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+        let repairs = || routing::CONTROL_READ_REPAIRS_TOTAL.with_label_values(&["repaired"]).get();
+        let before = repairs();
+
+        let response = app
+            .oneshot(Request::get("/metrics/cpu?consistency=quorum").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!((body["value"].as_f64(), body["source"].as_str()), (Some(7.0), Some("primary")));
+
+        // The replica is brought up to the newest sample in the background
+        for _ in 0..100 {
+            if behind.get_latest("cpu").await.unwrap().map(|latest| latest.value) == Some(7.0) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(behind.get_latest("cpu").await.unwrap(), Some(crate::metrics::MetricPoint { value: 7.0, timestamp: 2_000 }));
+        assert!(repairs() > before);
+    }
+
+    #[tokio::test]
+    async fn test_audit_pinpoints_a_replica_that_diverged() {
+        use crate::api::audit::{AuditMismatch, AuditRead};
+        let (primary, replica) = (Arc::new(MetricsRegistry::new().unwrap()), Arc::new(MetricsRegistry::new().unwrap()));
+        for metrics in [&primary, &replica] {
+            for (name, value) in [("cpu", 1.0), ("mem", 2.0), ("disk", 3.0)] {
+                metrics.record_metric_with_timestamp(name, value, 1_000).await.unwrap();
+            }
+        }
+        // Written to the replica alone, as if it had missed replication
+        replica.record_metric_with_timestamp("mem", 9.0, 2_000).await.unwrap();
+        let (primary_url, replica_url) = (spawn_real_worker_with(primary).await, spawn_real_worker_with(replica).await);
+        let config = ControlConfig::from_lookup(|key| match key {
+            "ADMIN_TOKEN" => Some("secret".to_string()),
+            "WORKER_HOSTS" => Some(primary_url.clone()),
+            "REPLICA_HOSTS" => Some(format!("0={}", replica_url)),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+        let admin = |request: axum::http::request::Builder| request.header("authorization", "Bearer secret").body(Body::empty()).unwrap();
+        let mismatches = || routing::AUDIT_MISMATCHES_TOTAL.with_label_values(&["replica"]).get();
+        let before = mismatches();
+
+        let response = app.clone().oneshot(admin(Request::post("/admin/audit/run?sample=10"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let report: AuditReport = serde_json::from_value(json_body(response).await).unwrap();
+        assert_eq!((report.candidates, report.audited, report.failed_reads), (3, 3, 0));
+        let read = |layer: &str, url: &str, value, timestamp| AuditRead {
+            layer: layer.to_string(),
+            url: Some(url.to_string()),
+            value: Some(value),
+            timestamp: Some(timestamp),
+        };
+        assert_eq!(report.mismatches, [AuditMismatch {
+            metric: "mem".to_string(),
+            expected: read("primary", &primary_url, 2.0, 1_000),
+            disagreeing: vec![read("replica", &replica_url, 9.0, 2_000)],
+        }]);
+        assert!(mismatches() > before);
+
+        let response = app.clone().oneshot(admin(Request::get("/admin/audit/reports"))).await.unwrap();
+        assert_eq!(json_body(response).await[0]["id"], report.id);
+        // Runs are spaced by AUDIT_MIN_GAP_SECS
+        let response = app.oneshot(admin(Request::post("/admin/audit/run"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_reads_fall_back_to_replicas_then_the_stale_cache() {
+        let unreachable = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let fallbacks = |source: &str, outcome: &str| {
+            routing::CONTROL_READ_FALLBACKS_TOTAL.with_label_values(&[source, outcome]).get()
+        };
+        let fetch = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        // The primary is down and the replica holds a copy
+        let copy = Arc::new(MetricsRegistry::new().unwrap());
+        copy.record_metric("cpu", 3.0).await.unwrap();
+        let replica = spawn_lagging_worker(copy, std::time::Duration::from_millis(50)).await;
+        let config = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some(unreachable.clone()),
+            "REPLICA_HOSTS" => Some(format!("0={}", replica)),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+        let served = fallbacks("replica", "served");
+        let response = app.clone().oneshot(fetch("/metrics/cpu")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!((body["source"].as_str(), body["value"].as_f64()), (Some("replica"), Some(3.0)));
+        assert_eq!(fallbacks("replica", "served"), served + 1);
+
+        // Every worker is down, but the control node read the value earlier
+        let up = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let switch = up.clone();
+        let primary = spawn_worker(Router::new().route("/metrics/:name", get(move || {
+            let up = switch.load(std::sync::atomic::Ordering::SeqCst);
+            async move {
+                match up {
+                    true => Json(serde_json::json!({ "name": "mem", "value": 7.0, "timestamp": 1_700, "worker_id": 0 })).into_response(),
+                    false => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                }
+            }
+        })))
+        .await;
+        let config = ControlConfig::from_lookup(|key| match key {
+            "READ_CACHE_SIZE" => Some("16".to_string()),
+            "READ_CACHE_TTL_MS" => Some("1".to_string()),
+            "WORKER_HOSTS" => Some(primary.clone()),
+            "REPLICA_HOSTS" => Some(format!("0={}", unreachable)),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+        assert_eq!(json_body(app.clone().oneshot(fetch("/metrics/mem")).await.unwrap()).await["source"], "primary");
+        up.store(false, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let response = app.clone().oneshot(fetch("/metrics/mem?allow_stale=false")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let served = fallbacks("stale-cache", "served");
+        let response = app.clone().oneshot(fetch("/metrics/mem")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!((body["source"].as_str(), body["timestamp"].as_i64()), (Some("stale-cache"), Some(1_700)));
+        assert_eq!(fallbacks("stale-cache", "served"), served + 1);
+        let response = app.oneshot(fetch("/metrics/never-read")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    /// Value of `series` in the control node's scrape, 0 when absent.
+    async fn scraped(app: &Router, series: &str) -> f64 {
+        let response = app.clone()
+            .oneshot(Request::get("/prometheus").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok())
+            .unwrap_or(0.0)
+    }
+
+    #[tokio::test]
+    async fn test_routing_metrics_follow_routed_requests() {
+        let worker = spawn_real_worker().await;
+        let app = control_router(state_for(vec![worker.clone()]));
+        let recorded = r#"raftmetrics_control_requests_total{endpoint="/metrics",status="2xx"}"#;
+        let read = r#"raftmetrics_control_requests_total{endpoint="/metrics/:name",status="2xx"}"#;
+        let aggregated = r#"raftmetrics_control_requests_total{endpoint="/metrics/:name/aggregate",status="2xx"}"#;
+        let assigned = r#"raftmetrics_control_partition_assignments_total{partition="0"}"#;
+        let applied = r#"raftmetrics_storage_operations_total{operation="record",result="success"}"#;
+        let before = [
+            scraped(&app, recorded).await,
+            scraped(&app, read).await,
+            scraped(&app, aggregated).await,
+            scraped(&app, assigned).await,
+            scraped(&app, applied).await,
+        ];
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":1.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        for path in ["/metrics/cpu", "/metrics/cpu/aggregate"] {
+            let response = app.clone().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        assert!(scraped(&app, recorded).await >= before[0] + 1.0);
+        assert!(scraped(&app, read).await >= before[1] + 1.0);
+        assert!(scraped(&app, aggregated).await >= before[2] + 1.0);
+        assert!(scraped(&app, assigned).await >= before[3] + 3.0);
+        assert!(scraped(&app, applied).await >= before[4] + 1.0);
+        // Only this test forwards to this worker
+        let forwards = format!(r#"raftmetrics_control_forward_duration_seconds_count{{worker="{}"}}"#, worker);
+        assert_eq!(scraped(&app, &forwards).await, 3.0);
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn test_reads_survive_a_worker_injecting_errors() {
+        let fetch = || Request::get("/metrics/cpu").body(Body::empty()).unwrap();
+        let metrics = Arc::new(MetricsRegistry::new().unwrap());
+        metrics.record_metric("cpu", 3.0).await.unwrap();
+        let state = crate::api::worker::WorkerState::new(0, metrics, crate::config::RaftConfig::default()).unwrap();
+        state.chaos.set_enabled(true);
+        let primary = spawn_worker(crate::api::worker::worker_router(state)).await;
+        let copy = Arc::new(MetricsRegistry::new().unwrap());
+        copy.record_metric("cpu", 3.0).await.unwrap();
+        let replica = spawn_lagging_worker(copy, std::time::Duration::from_millis(50)).await;
+        let config = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some(primary.clone()),
+            "REPLICA_HOSTS" => Some(format!("0={}", replica)),
+            _ => None,
+        });
+        let app = control_router(ControlState::new(config).unwrap());
+
+        let client = reqwest::Client::new();
+        let response = client.post(format!("{}/chaos/errors", primary))
+            .json(&serde_json::json!({ "status": 503, "percent": 20 }))
+            .send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let mut from_replica = 0;
+        for _ in 0..50 {
+            let response = app.clone().oneshot(fetch()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = json_body(response).await;
+            assert_eq!(body["value"], 3.0);
+            from_replica += usize::from(body["source"] == "replica");
+        }
+        // Every fifth read failed on the primary and was served by the replica
+        assert_eq!(from_replica, 10);
+
+        let response = client.post(format!("{}/chaos/clear", primary)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(json_body(app.oneshot(fetch()).await.unwrap()).await["source"], "primary");
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn test_raft_group_commits_through_dropped_messages() {
+        use crate::{api::worker::WorkerState, raft::{network::LocalNetwork, node::RaftRole}};
+
+        let network = LocalNetwork::new();
+        let workers: Vec<WorkerState> = (0..3)
+            .map(|id| {
+                let metrics = Arc::new(MetricsRegistry::new().unwrap());
+                WorkerState::in_group(id, metrics, crate::config::RaftConfig::default(), vec![1, 2, 3], vec![], network.clone()).unwrap()
+            })
+            .collect();
+        let client = reqwest::Client::new();
+        let mut worker_urls = Vec::new();
+        for worker in &workers {
+            worker.chaos.set_enabled(true);
+            let url = spawn_worker(crate::api::worker::worker_router(worker.clone())).await;
+            let response = client.post(format!("{}/chaos/drop-raft", url))
+                .json(&serde_json::json!({ "percent": 10 }))
+                .send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+            worker_urls.push(url);
+        }
+        let mut leader = None;
+        for _ in 0..100 {
+            leader = workers.iter().position(|worker| worker.raft_status.role() == RaftRole::Leader);
+            if leader.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let leader = leader.expect("no leader elected");
+        let app = control_router(state_for(vec![worker_urls[leader].clone()]));
+
+        for value in 1..=20 {
+            let request = Request::post("/metrics?ack=quorum")
+                .header("content-type", "application/json")
+                .header(TIMEOUT_HEADER, "5000")
+                .body(Body::from(serde_json::json!({ "metric_name": "billing", "value": value as f64 }).to_string()))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(json_body(response).await["ack"], "quorum");
+        }
+        // Followers catch up on whatever the dropped appends carried
+        for worker in &workers {
+            let mut stored = None;
+            for _ in 0..100 {
+                stored = worker.metrics.get_metric("billing").await.unwrap();
+                if stored == Some(20.0) {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            assert_eq!(stored, Some(20.0));
+        }
+        assert!(workers.iter().map(|worker| worker.chaos.raft_dropped()).sum::<u64>() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_least_loaded_worker_follows_load_reports() {
+        let loaded = |depth: u64| {
+            let load = WorkerLoad { metric_count: 10, records_total: 100, proposal_queue_depth: depth, cpu_hint: Some(0.5) };
+            Router::new().route("/load", get(move || async move { Json(load) }))
+        };
+        let busy = spawn_worker(loaded(40)).await;
+        let idle = spawn_worker(loaded(2)).await;
+        let real = spawn_real_worker().await;
+        let state = state_for(vec![busy.clone(), idle.clone(), real.clone(), "http://127.0.0.1:1".to_string()]);
+        assert_eq!(state.least_loaded_worker(), Some(busy.as_str()));
+
+        poll_loads(&state).await;
+        assert_eq!(state.loads.get(&idle).unwrap().proposal_queue_depth, 2);
+        assert!(state.loads.get("http://127.0.0.1:1").is_none());
+        let reported = state.loads.get(&real).unwrap();
+        assert_eq!((reported.metric_count, reported.records_total, reported.proposal_queue_depth), (0, 0, 0));
+
+        // Pinned so the host's own load average cannot decide
+        state.loads.record(&real, WorkerLoad { proposal_queue_depth: 3, cpu_hint: None, ..reported });
+        assert_eq!(state.least_loaded_worker(), Some(idle.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_are_missing_from_the_data_router() {
+        let mut config = ControlConfig::from_lookup(|key| (key == "ADMIN_TOKEN").then(|| "secret".to_string()));
+        config.worker_urls = vec![spawn_real_worker().await];
+        let loaded = config.clone();
+        let handle = ControlHandle::new(ControlState::new(config).unwrap(), Arc::new(move || Ok(loaded.clone())));
+        let (data, admin) = (data_router(handle.clone()), admin_router(handle));
+
+        for path in ["/admin/config", "/admin/preflight", "/prometheus"] {
+            let request = || Request::get(path).header("authorization", "Bearer secret").body(Body::empty()).unwrap();
+            let response = data.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+            let response = admin.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        }
+        let heartbeat = Request::post("/cluster/heartbeat")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"url": "http://worker:1", "load": {"metric_count": 0, "sample_rows": 0, "tombstones": 0}}"#))
+            .unwrap();
+        assert_eq!(data.clone().oneshot(heartbeat).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        let response = data.oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = admin.oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::{Result, RaftMetricsError, error::DeadlineStage};
+
+/// Absolute deadline in milliseconds since the Unix epoch.
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+/// Relative budget in milliseconds, measured from when the request arrives.
+pub const TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+/// Same as [`TIMEOUT_HEADER`], under the name some clients send.
+pub const DEADLINE_MS_HEADER: &str = "x-deadline-ms";
+
+/// The time budget of one request, fixed when it arrives.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    started: Instant,
+    expires: Instant,
+}
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Self {
+        let started = Instant::now();
+        Self { started, expires: started + budget }
+    }
+
+    /// Reads the budget from [`DEADLINE_HEADER`], [`TIMEOUT_HEADER`] or
+    /// [`DEADLINE_MS_HEADER`], taking the tightest when several are present.
+    /// Missing or malformed headers fall back to `default`.
+    pub fn from_headers(headers: &HeaderMap, default: Duration) -> Self {
+        let header_ms = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        };
+
+        let from_deadline = header_ms(DEADLINE_HEADER).map(|at_ms| {
+            let now_ms = chrono::Utc::now().timestamp_millis();
+            Duration::from_millis(at_ms.saturating_sub(now_ms).max(0) as u64)
+        });
+        let from_timeout = [TIMEOUT_HEADER, DEADLINE_MS_HEADER]
+            .into_iter()
+            .filter_map(header_ms)
+            .map(|ms| Duration::from_millis(ms.max(0) as u64));
+
+        let budget = from_deadline.into_iter().chain(from_timeout).min().unwrap_or(default);
+        Self::after(budget)
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn expires(&self) -> Instant {
+        self.expires
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.expires - self.started
+    }
+
+    pub fn exceeded(&self, stage: DeadlineStage) -> RaftMetricsError {
+        RaftMetricsError::DeadlineExceeded {
+            stage,
+            elapsed_ms: self.elapsed().as_millis() as u64,
+            budget_ms: self.budget().as_millis() as u64,
+        }
+    }
+
+    /// Runs `work`, abandoning it once the budget is spent.
+    pub async fn run<T>(&self, stage: DeadlineStage, work: impl Future<Output = Result<T>>) -> Result<T> {
+        if self.remaining().is_zero() {
+            return Err(self.exceeded(stage));
+        }
+        tokio::time::timeout_at(self.expires, work)
+            .await
+            .unwrap_or_else(|_| Err(self.exceeded(stage)))
+    }
+}
+
+/// Attaches a [`Deadline`] to every request, using the given default budget
+/// when the client sent no deadline header.
+pub async fn attach_deadline(State(default): State<Duration>, mut request: Request, next: Next) -> Response {
+    let deadline = Deadline::from_headers(request.headers(), default);
+    request.extensions_mut().insert(deadline);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_tighter_header_wins() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMEOUT_HEADER, HeaderValue::from_static("50"));
+        let far = chrono::Utc::now().timestamp_millis() + 60_000;
+        headers.insert(DEADLINE_HEADER, HeaderValue::from_str(&far.to_string()).unwrap());
+
+        let deadline = Deadline::from_headers(&headers, Duration::from_secs(10));
+        assert_eq!(deadline.budget(), Duration::from_millis(50));
+
+        headers.insert(DEADLINE_MS_HEADER, HeaderValue::from_static("20"));
+        let deadline = Deadline::from_headers(&headers, Duration::from_secs(10));
+        assert_eq!(deadline.budget(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_past_deadline_has_no_budget() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DEADLINE_HEADER, HeaderValue::from_static("1000"));
+        let deadline = Deadline::from_headers(&headers, Duration::from_secs(10));
+        assert!(deadline.remaining().is_zero());
+
+        headers.insert(DEADLINE_HEADER, HeaderValue::from_static("soon"));
+        let deadline = Deadline::from_headers(&headers, Duration::from_secs(10));
+        assert_eq!(deadline.budget(), Duration::from_secs(10));
+    }
+}
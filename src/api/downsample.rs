@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::metrics::{Admission, Labels, MetricOperation, SampleSummary, DOWNSAMPLED_TOTAL};
+
+/// How often the worker proposes summaries whose interval has ended, so a
+/// summary lands at most this long after its interval.
+pub const DOWNSAMPLE_FLUSH_TICK: Duration = Duration::from_millis(100);
+
+/// How a worker thins a metric's samples before proposing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownsampleMode {
+    /// Proposes the first sample of every `n`.
+    KeepEveryNth(u64),
+    /// Proposes a sample only once this long has passed since the last
+    /// one proposed.
+    MinInterval(Duration),
+    /// Folds the samples arriving within this long into one
+    /// [`MetricOperation::RecordSummary`], so the aggregate's count, sum,
+    /// min and max stay exact.
+    AggregateInterval(Duration),
+}
+
+impl DownsampleMode {
+    /// Parses `keep_every_nth:N`, `min_interval_ms:N` or
+    /// `aggregate_interval_ms:N`, with `N` positive.
+    fn parse(mode: &str) -> Option<Self> {
+        let (kind, arg) = mode.split_once(':')?;
+        let arg: u64 = arg.trim().parse().ok().filter(|&arg| arg > 0)?;
+        match kind.trim() {
+            "keep_every_nth" => Some(Self::KeepEveryNth(arg)),
+            "min_interval_ms" => Some(Self::MinInterval(Duration::from_millis(arg))),
+            "aggregate_interval_ms" => Some(Self::AggregateInterval(Duration::from_millis(arg))),
+            _ => None,
+        }
+    }
+
+    /// Label for the mode in [`DOWNSAMPLED_TOTAL`].
+    fn label(&self) -> &'static str {
+        match self {
+            Self::KeepEveryNth(_) => "keep_every_nth",
+            Self::MinInterval(_) => "min_interval_ms",
+            Self::AggregateInterval(_) => "aggregate_interval_ms",
+        }
+    }
+}
+
+/// A name, or a name prefix when it ends in `*`, and its mode.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: String,
+    prefix: bool,
+    mode: DownsampleMode,
+}
+
+impl Rule {
+    fn matches(&self, name: &str) -> bool {
+        if self.prefix { name.starts_with(&self.pattern) } else { name == self.pattern }
+    }
+}
+
+/// Where one series stands under its rule.
+#[derive(Debug)]
+enum Series {
+    /// Samples seen since the last one proposed.
+    Counted(u64),
+    /// When the last sample was proposed.
+    Kept(Instant),
+    /// Samples folded together until `until`, and the latest timestamp
+    /// among them.
+    Buffered { until: Instant, timestamp: i64, summary: SampleSummary },
+}
+
+/// Per-metric downsample rules from `DOWNSAMPLE_RULES`, applied on the
+/// worker before a record is proposed, so the raft log and the store only
+/// see the reduced stream. Each labeled series is thinned on its own.
+/// Transactions are never thinned. State is held by the worker that took
+/// the write and starts over when it restarts; summaries not yet proposed
+/// are lost if it stops without a clean shutdown.
+#[derive(Debug, Default)]
+pub struct Downsampler {
+    rules: Vec<Rule>,
+    series: Mutex<HashMap<(String, Labels), Series>>,
+}
+
+impl Downsampler {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `DOWNSAMPLE_RULES`, a comma-separated list of `pattern=mode`
+    /// pairs where a pattern ending in `*` matches every name starting
+    /// with the rest. A metric takes the first rule that matches it.
+    /// Malformed entries are skipped with a warning.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut rules = Vec::new();
+        for entry in lookup("DOWNSAMPLE_RULES").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(pattern, mode)| Some((pattern.trim(), DownsampleMode::parse(mode)?)));
+            match parsed {
+                Some((pattern, mode)) if !pattern.is_empty() && pattern != "*" => {
+                    let (pattern, prefix) = match pattern.strip_suffix('*') {
+                        Some(prefix) => (prefix, true),
+                        None => (pattern, false),
+                    };
+                    rules.push(Rule { pattern: pattern.to_string(), prefix, mode });
+                }
+                _ => warn!("Ignoring DOWNSAMPLE_RULES entry '{}'", entry),
+            }
+        }
+        Self { rules, series: Mutex::default() }
+    }
+
+    pub fn mode(&self, name: &str) -> Option<DownsampleMode> {
+        self.rules.iter().find(|rule| rule.matches(name)).map(|rule| rule.mode)
+    }
+
+    /// Whether any rule folds samples into summaries that need flushing.
+    pub fn buffers(&self) -> bool {
+        self.rules.iter().any(|rule| matches!(rule.mode, DownsampleMode::AggregateInterval(_)))
+    }
+
+    /// `operation` when it should be proposed now, or `None` when its
+    /// metric's rule drops it or folds it into a summary. Only records are
+    /// ever held back.
+    pub fn admit(&self, operation: MetricOperation, now: Instant) -> Option<MetricOperation> {
+        let MetricOperation::Record { name, value, timestamp, labels, .. } = &operation else {
+            return Some(operation);
+        };
+        let Some(mode) = self.mode(name) else {
+            return Some(operation);
+        };
+
+        let key = (name.clone(), labels.clone());
+        let mut series = self.series.lock().unwrap();
+        let kept = match (mode, series.get_mut(&key)) {
+            (DownsampleMode::KeepEveryNth(n), Some(Series::Counted(seen))) => {
+                *seen = (*seen + 1) % n;
+                *seen == 0
+            }
+            (DownsampleMode::MinInterval(interval), Some(Series::Kept(at))) => {
+                let due = now.duration_since(*at) >= interval;
+                if due {
+                    *at = now;
+                }
+                due
+            }
+            (DownsampleMode::AggregateInterval(_), Some(Series::Buffered { timestamp: latest, summary, .. })) => {
+                summary.add(*value, *timestamp >= *latest);
+                *latest = (*latest).max(*timestamp);
+                false
+            }
+            (DownsampleMode::KeepEveryNth(_), _) => {
+                series.insert(key, Series::Counted(0));
+                true
+            }
+            (DownsampleMode::MinInterval(_), _) => {
+                series.insert(key, Series::Kept(now));
+                true
+            }
+            (DownsampleMode::AggregateInterval(interval), _) => {
+                let summary = SampleSummary::new(*value);
+                series.insert(key, Series::Buffered { until: now + interval, timestamp: *timestamp, summary });
+                false
+            }
+        };
+        drop(series);
+
+        if !kept {
+            DOWNSAMPLED_TOTAL.with_label_values(&[mode.label()]).inc();
+        }
+        kept.then_some(operation)
+    }
+
+    /// Takes the summaries whose interval has ended by `now`, ready to
+    /// propose.
+    pub fn due(&self, now: Instant) -> Vec<MetricOperation> {
+        self.take(|until| until <= now)
+    }
+
+    /// Takes every summary, ended or not, as on shutdown.
+    pub fn drain(&self) -> Vec<MetricOperation> {
+        self.take(|_| true)
+    }
+
+    fn take(&self, ended: impl Fn(Instant) -> bool) -> Vec<MetricOperation> {
+        let mut series = self.series.lock().unwrap();
+        let keys: Vec<(String, Labels)> = series
+            .iter()
+            .filter(|(_, state)| matches!(state, Series::Buffered { until, .. } if ended(*until)))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| match series.remove(&key) {
+                Some(Series::Buffered { timestamp, summary, .. }) => {
+                    let (name, labels) = key;
+                    Some(MetricOperation::RecordSummary { name, timestamp, labels, summary, admission: Admission::default() })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, value: f64, timestamp: i64) -> MetricOperation {
+        MetricOperation::record(name, value, timestamp, Labels::new())
+    }
+
+    #[test]
+    fn test_rules_from_env() {
+        let rules = Downsampler::from_lookup(|_| {
+            Some("cpu=keep_every_nth:10, sensor.*=min_interval_ms:1000,sensor.x=keep_every_nth:2,lat=aggregate_interval_ms:500,bad=keep_every_nth:0,noeq,*=keep_every_nth:3,odd=sometimes:1".to_string())
+        });
+        assert_eq!(rules.mode("cpu"), Some(DownsampleMode::KeepEveryNth(10)));
+        assert_eq!(rules.mode("sensor.x"), Some(DownsampleMode::MinInterval(Duration::from_millis(1000))));
+        assert_eq!(rules.mode("lat"), Some(DownsampleMode::AggregateInterval(Duration::from_millis(500))));
+        assert_eq!(rules.mode("cpu2"), None);
+        assert_eq!(rules.mode("bad"), None);
+        assert_eq!(rules.mode("odd"), None);
+        assert!(rules.buffers());
+        assert!(!Downsampler::default().buffers());
+    }
+
+    #[test]
+    fn test_every_nth_and_min_interval_reduce_the_rate() {
+        let rules = Downsampler::from_lookup(|_| Some("cpu=keep_every_nth:10,temp=min_interval_ms:1000".to_string()));
+        let start = Instant::now();
+
+        // 1000 samples over ten seconds, one every 10ms
+        let mut kept = HashMap::<&str, usize>::new();
+        for i in 0..1000u64 {
+            let now = start + Duration::from_millis(i * 10);
+            for name in ["cpu", "temp", "mem"] {
+                if rules.admit(record(name, i as f64, i as i64), now).is_some() {
+                    *kept.entry(name).or_default() += 1;
+                }
+            }
+        }
+        assert_eq!(kept["cpu"], 100);
+        assert_eq!(kept["temp"], 10);
+        assert_eq!(kept["mem"], 1000);
+
+        let labeled = MetricOperation::record("cpu", 1.0, 0, Labels::from([("host".into(), "a".into())]));
+        assert!(rules.admit(labeled, start).is_some(), "each series is thinned on its own");
+        assert!(rules.admit(MetricOperation::Delete { name: "cpu".into(), deleted_at: 0 }, start).is_some());
+    }
+
+    #[test]
+    fn test_aggregate_interval_folds_samples_into_one_summary() {
+        let rules = Downsampler::from_lookup(|_| Some("lat*=aggregate_interval_ms:1000".to_string()));
+        let start = Instant::now();
+        for (value, timestamp) in [(3.0, 101), (9.0, 100), (6.0, 101), (2.0, 102)] {
+            assert_eq!(rules.admit(record("latency", value, timestamp), start), None);
+        }
+        assert_eq!(rules.due(start + Duration::from_millis(999)), vec![]);
+
+        let due = rules.due(start + Duration::from_millis(1000));
+        let [MetricOperation::RecordSummary { name, timestamp, summary, .. }] = due.as_slice() else {
+            panic!("expected one summary, got {:?}", due);
+        };
+        assert_eq!((name.as_str(), *timestamp), ("latency", 102));
+        assert_eq!((summary.last, summary.count, summary.sum, summary.min, summary.max), (2.0, 4, 20.0, 2.0, 9.0));
+        assert!((summary.m2 - 30.0).abs() < 1e-9);
+        assert_eq!(rules.drain(), vec![]);
+    }
+}
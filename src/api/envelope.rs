@@ -0,0 +1,166 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH}, uri::PathAndQuery, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{RaftMetricsError, Result};
+
+/// Added inside an ETag's quotes to tell the wrapped representation from
+/// the bare one.
+const ENVELOPE_TAG_SUFFIX: &str = "-envelope";
+
+/// A response body wrapped for `?envelope=true`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// The body as it is served bare.
+    pub data: serde_json::Value,
+    pub meta: EnvelopeMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeMeta {
+    /// The node that answered, as at `/info`.
+    pub node_id: usize,
+    /// Unix seconds.
+    pub served_at: i64,
+    /// The page's `next`, for paginated listings that have another page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Wraps successful JSON responses in an [`Envelope`] when the request has
+/// `?envelope=true`; without it, or with `false`, responses stay bare.
+/// Errors, `304`s and other content types are never wrapped. The parameter
+/// is taken off the request before it is routed, so handlers and the
+/// workers they forward to never see it. A wrapped response's ETag, and
+/// that of a `304` to a wrapped request, carries [`ENVELOPE_TAG_SUFFIX`],
+/// and only suffixed tags in `If-None-Match` reach the handler, so a tag
+/// for one representation never validates the other. Installed with
+/// `layer` on every router, with the node's id as state.
+pub async fn envelope_responses(State(node_id): State<usize>, mut request: Request, next: Next) -> Response {
+    let enveloped = match take_envelope(request.uri()) {
+        Ok(Some((enveloped, uri))) => {
+            *request.uri_mut() = uri;
+            enveloped
+        }
+        Ok(None) => false,
+        Err(e) => return e.into_response(),
+    };
+    if enveloped {
+        unwrap_if_none_match(request.headers_mut());
+    }
+    let mut response = next.run(request).await;
+    if enveloped && response.status() == StatusCode::NOT_MODIFIED {
+        suffix_etag(response.headers_mut());
+    }
+    if !enveloped || !response.status().is_success() || !is_json(response.headers()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let data = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => serde_json::from_slice::<serde_json::Value>(&body)
+            .map_err(|e| RaftMetricsError::Internal(format!("Failed to parse response: {}", e))),
+        Err(e) => Err(RaftMetricsError::Internal(format!("Failed to read response: {}", e))),
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => return e.into_response(),
+    };
+    let next_cursor = data.get("next").and_then(serde_json::Value::as_str).map(str::to_string);
+    let envelope = Envelope {
+        data,
+        meta: EnvelopeMeta { node_id, served_at: chrono::Utc::now().timestamp(), next_cursor },
+    };
+    let body = match serde_json::to_vec(&envelope) {
+        Ok(body) => body,
+        Err(e) => return RaftMetricsError::Internal(format!("Failed to encode response: {}", e)).into_response(),
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    suffix_etag(&mut parts.headers);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Marks the response's ETag, if any, as the wrapped representation's.
+fn suffix_etag(headers: &mut HeaderMap) {
+    let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok()) else {
+        return;
+    };
+    let suffixed = match etag.strip_suffix('"') {
+        Some(opaque) => format!("{}{}\"", opaque, ENVELOPE_TAG_SUFFIX),
+        None => format!("{}{}", etag, ENVELOPE_TAG_SUFFIX),
+    };
+    match HeaderValue::from_str(&suffixed) {
+        Ok(suffixed) => headers.insert(ETAG, suffixed),
+        Err(_) => headers.remove(ETAG),
+    };
+}
+
+/// Keeps only the `If-None-Match` tags given out for wrapped responses,
+/// with the suffix taken off so the handler compares them as its own.
+fn unwrap_if_none_match(headers: &mut HeaderMap) {
+    let tags: Vec<String> = headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter_map(|tag| match tag {
+            "*" => Some(tag.to_string()),
+            tag => tag
+                .strip_suffix(&format!("{}\"", ENVELOPE_TAG_SUFFIX))
+                .map(|opaque| format!("{}\"", opaque)),
+        })
+        .collect();
+    headers.remove(IF_NONE_MATCH);
+    if tags.is_empty() {
+        return;
+    }
+    if let Ok(tags) = HeaderValue::from_str(&tags.join(", ")) {
+        headers.insert(IF_NONE_MATCH, tags);
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+/// Whether `uri` asks for an envelope, with the URI to route once
+/// `envelope` is taken out of its query; `None` when it has no `envelope`.
+fn take_envelope(uri: &Uri) -> Result<Option<(bool, Uri)>> {
+    let Some(query) = uri.query() else {
+        return Ok(None);
+    };
+    let mut enveloped = None;
+    let mut rest = Vec::new();
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("envelope", value)) => {
+                enveloped = Some(value.parse().map_err(|_| RaftMetricsError::InvalidRequest(format!(
+                    "envelope must be true or false, not '{}'", value
+                )))?);
+            }
+            _ => rest.push(pair),
+        }
+    }
+    let Some(enveloped) = enveloped else {
+        return Ok(None);
+    };
+
+    let path_and_query = match rest.is_empty() {
+        true => uri.path().to_string(),
+        false => format!("{}?{}", uri.path(), rest.join("&")),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query)
+        .map_err(|e| RaftMetricsError::InvalidRequest(format!("invalid request URI: {}", e)))?);
+    let uri = Uri::from_parts(parts).map_err(|e| RaftMetricsError::InvalidRequest(format!("invalid request URI: {}", e)))?;
+    Ok(Some((enveloped, uri)))
+}
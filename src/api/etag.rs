@@ -0,0 +1,43 @@
+use axum::http::{header::IF_NONE_MATCH, HeaderMap};
+
+use crate::metrics::MetricPoint;
+
+/// Weak ETag for a metric's latest sample. It changes whenever the value or
+/// the timestamp of the latest sample does.
+pub fn metric_etag(latest: &MetricPoint) -> String {
+    format!("W/\"{:x}-{:x}\"", latest.value.to_bits(), latest.timestamp)
+}
+
+/// Whether `If-None-Match` names `etag`, compared weakly as RFC 9110 asks
+/// for conditional GETs.
+pub fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_if_none_match_compares_weakly() {
+        let etag = metric_etag(&MetricPoint { value: 1.5, timestamp: 1000 });
+        let mut headers = HeaderMap::new();
+        assert!(!not_modified(&headers, &etag));
+
+        let strong = etag.trim_start_matches("W/").to_string();
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_str(&format!("\"other\", {}", strong)).unwrap());
+        assert!(not_modified(&headers, &etag));
+
+        let changed = metric_etag(&MetricPoint { value: 1.5, timestamp: 1001 });
+        assert!(!not_modified(&headers, &changed));
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(not_modified(&headers, &changed));
+    }
+}
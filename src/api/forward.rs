@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use prometheus::IntGauge;
+
+use crate::api::resolve::{HostLookup, PeerResolver, SystemLookup};
+use crate::config::HttpPoolConfig;
+use crate::metrics::FORWARD_IN_FLIGHT;
+use crate::{RaftMetricsError, Result};
+
+/// An HTTP client with its own connection pool, used for one class of
+/// control→worker traffic so slow writes cannot starve reads. Worker host
+/// names are resolved through a [`PeerResolver`], looked up again after a
+/// connection to them fails.
+#[derive(Debug)]
+pub struct ForwardPool {
+    name: &'static str,
+    client: reqwest::Client,
+    resolver: PeerResolver,
+    request_timeout: Duration,
+    sent: AtomicU64,
+}
+
+impl ForwardPool {
+    pub fn new(name: &'static str, config: &HttpPoolConfig) -> Result<Self> {
+        Self::with_lookup(name, config, Arc::new(SystemLookup))
+    }
+
+    /// Like [`Self::new`], resolving host names through `lookup`.
+    pub fn with_lookup(name: &'static str, config: &HttpPoolConfig, lookup: Arc<dyn HostLookup>) -> Result<Self> {
+        let resolver = PeerResolver::new(lookup);
+        let client = config
+            .client_builder()
+            .dns_resolver(Arc::new(resolver.clone()))
+            .build()
+            .map_err(|e| RaftMetricsError::Internal(format!("Failed to build {} HTTP client: {}", name, e)))?;
+        Ok(Self { name, client, resolver, request_timeout: config.request_timeout, sent: AtomicU64::new(0) })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Timeout applied to calls that carry no tighter deadline.
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// Number of requests sent through this pool.
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// Sends `request`. A failure to connect has the worker's host name
+    /// looked up again on the next connection.
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        let (client, request) = request.build_split();
+        let request = request?;
+        let host = request.url().host_str().map(str::to_string);
+        let response = {
+            let _in_flight = InFlight::enter(FORWARD_IN_FLIGHT.with_label_values(&[self.name]));
+            client.execute(request).await
+        };
+        if let (Err(e), Some(host)) = (&response, host) {
+            if e.is_connect() {
+                self.resolver.invalidate(&host);
+            }
+        }
+        response
+    }
+}
+
+/// One request counted in a pool's in-flight gauge until dropped, so a
+/// caller that stops waiting on the request still releases it.
+struct InFlight(IntGauge);
+
+impl InFlight {
+    fn enter(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_abandoned_requests_leave_the_in_flight_gauge() {
+        // Accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        let _silent = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let pool = ForwardPool::new("abandoned", &HttpPoolConfig::read_defaults()).unwrap();
+        let in_flight = FORWARD_IN_FLIGHT.with_label_values(&["abandoned"]);
+        let send = pool.send(pool.client().get(&url));
+        assert!(tokio::time::timeout(Duration::from_millis(200), send).await.is_err());
+        assert_eq!(in_flight.get(), 0);
+    }
+}
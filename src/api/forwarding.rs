@@ -0,0 +1,887 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{
+    api::{push::label_segment, types::MetricRequest},
+    client::RaftMetricsClient,
+    config::units,
+    error::ErrorCode,
+    metrics::{
+        Labels, MetricOperation, SampleSummary, FORWARDING_BUFFERED, FORWARDING_DESTINATION_UP, FORWARDING_DROPPED_TOTAL,
+        FORWARDING_RETRIES_TOTAL, FORWARDING_SAMPLES_TOTAL,
+    },
+    RaftMetricsError,
+};
+
+/// Header counting the times a write has been forwarded between clusters.
+/// A write without it has not been forwarded.
+pub const HOPS_HEADER: &str = "x-raftmetrics-hops";
+/// `;`-separated rules, e.g.
+/// `match=acme.*|cpu,to=raftmetrics:http://central:8080,every=10s`.
+pub const RULES_KEY: &str = "FORWARD_RULES";
+/// Which node forwards: `control` or `worker`.
+pub const FROM_KEY: &str = "FORWARD_FROM";
+pub const DEFAULT_MAX_HOPS: u32 = 3;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_BUFFER_SAMPLES: usize = 10_000;
+const DEFAULT_BATCH_SAMPLES: usize = 500;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Wait before a failed batch is first sent again, doubled for each retry
+/// after that.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+/// Job forwarded gauges are grouped under on a Pushgateway.
+const PUSHGATEWAY_JOB: &str = "raftmetrics_forward";
+
+/// The node whose successful writes are forwarded. The other node type
+/// forwards nothing, so each sample leaves the cluster once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardFrom {
+    #[default]
+    Control,
+    Worker,
+}
+
+/// Where a rule's samples go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// Another cluster's control node, written to through
+    /// `POST /metrics/stream`.
+    RaftMetrics(String),
+    /// A Prometheus Pushgateway, given each series' latest value as a gauge.
+    Pushgateway(String),
+}
+
+impl Destination {
+    /// Parses `raftmetrics:URL` or `pushgateway:URL`.
+    fn parse(spec: &str) -> Result<Self, String> {
+        let Some((kind, url)) = spec.split_once(':') else {
+            return Err(format!("destination '{}' is not kind:url", spec));
+        };
+        let url = url.trim().trim_end_matches('/').to_string();
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("destination url '{}' must be http(s)", url));
+        }
+        match kind.trim() {
+            "raftmetrics" => Ok(Self::RaftMetrics(url)),
+            "pushgateway" => Ok(Self::Pushgateway(url)),
+            other => Err(format!("destination kind '{}' must be raftmetrics or pushgateway", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RaftMetrics(url) => write!(f, "raftmetrics:{}", url),
+            Self::Pushgateway(url) => write!(f, "pushgateway:{}", url),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardMode {
+    /// Every sample, as written.
+    #[default]
+    Raw,
+    /// Per series and interval, `<name>_count`, `_sum`, `_min` and `_max`
+    /// of the samples written in it.
+    Aggregate,
+}
+
+/// Metrics to forward, where to and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardRule {
+    /// Names, or name prefixes ending in `*`.
+    pub metrics: Vec<String>,
+    pub destination: Destination,
+    /// How often buffered samples are sent.
+    pub interval: Duration,
+    pub mode: ForwardMode,
+}
+
+impl ForwardRule {
+    /// Parses `match=PATTERNS,to=DESTINATION[,every=DURATION][,send=MODE]`,
+    /// with `PATTERNS` separated by `|` and `MODE` `raw` or `aggregate`.
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut metrics = Vec::new();
+        let mut destination = None;
+        let mut interval = DEFAULT_INTERVAL;
+        let mut mode = ForwardMode::Raw;
+        for field in spec.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+            let Some((key, value)) = field.split_once('=') else {
+                return Err(format!("expected key=value; got '{}'", field));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "match" => metrics = value.split('|').map(str::trim).filter(|pattern| !pattern.is_empty()).map(str::to_string).collect(),
+                "to" => destination = Some(Destination::parse(value)?),
+                "every" => {
+                    interval = units::parse_duration(value, Duration::from_secs(1))?;
+                    if interval.is_zero() {
+                        return Err("every must be longer than zero".to_string());
+                    }
+                }
+                "send" => {
+                    mode = match value {
+                        "raw" => ForwardMode::Raw,
+                        "aggregate" => ForwardMode::Aggregate,
+                        other => return Err(format!("send '{}' must be raw or aggregate", other)),
+                    }
+                }
+                other => return Err(format!("unknown field '{}'; expected match, to, every or send", other)),
+            }
+        }
+        if metrics.is_empty() || metrics.iter().any(|pattern| pattern == "*") {
+            return Err("match must list metric names or non-empty prefixes".to_string());
+        }
+        let destination = destination.ok_or_else(|| "to is required".to_string())?;
+        Ok(Self { metrics, destination, interval, mode })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.metrics.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => pattern == name,
+        })
+    }
+}
+
+/// Forwarding of selected metrics to downstream clusters or Pushgateways.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardingConfig {
+    pub from: ForwardFrom,
+    pub rules: Vec<ForwardRule>,
+    /// Samples each rule holds while its destination is slow or down;
+    /// samples past this are dropped.
+    pub buffer_samples: usize,
+    /// Most samples sent in one request.
+    pub batch_samples: usize,
+    /// Times a failed batch is sent again before it is dropped.
+    pub max_retries: u32,
+    /// Most times a write may have been forwarded for this node to take it.
+    pub max_hops: u32,
+    /// Longest one request to a destination may take.
+    pub timeout: Duration,
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            from: ForwardFrom::default(),
+            rules: Vec::new(),
+            buffer_samples: DEFAULT_BUFFER_SAMPLES,
+            batch_samples: DEFAULT_BATCH_SAMPLES,
+            max_retries: DEFAULT_MAX_RETRIES,
+            max_hops: DEFAULT_MAX_HOPS,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl ForwardingConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(&|key: &str| std::env::var(key).ok())
+    }
+
+    /// Reads [`RULES_KEY`], [`FROM_KEY`], `FORWARD_BUFFER_SAMPLES`,
+    /// `FORWARD_BATCH_SAMPLES`, `FORWARD_MAX_RETRIES`, `FORWARD_MAX_HOPS`
+    /// and `FORWARD_TIMEOUT_MS`. Malformed rules are ignored with a warning
+    /// here; [`forwarding_errors`] refuses them at startup.
+    pub fn from_lookup(lookup: &impl Fn(&str) -> Option<String>) -> Self {
+        let number = |key: &str| lookup(key).and_then(|value| value.trim().parse::<u64>().ok());
+        let rules = parse_rules(&lookup(RULES_KEY).unwrap_or_default()).unwrap_or_else(|errors| {
+            warn!("Ignoring {}: {}", RULES_KEY, errors.join("; "));
+            Vec::new()
+        });
+        let defaults = Self::default();
+        Self {
+            from: parse_from(lookup).unwrap_or_default(),
+            rules,
+            buffer_samples: number("FORWARD_BUFFER_SAMPLES").filter(|&n| n > 0).map_or(defaults.buffer_samples, |n| n as usize),
+            batch_samples: number("FORWARD_BATCH_SAMPLES").filter(|&n| n > 0).map_or(defaults.batch_samples, |n| n as usize),
+            max_retries: number("FORWARD_MAX_RETRIES").map_or(defaults.max_retries, |n| n.min(16) as u32),
+            max_hops: number("FORWARD_MAX_HOPS").map_or(defaults.max_hops, |n| n.min(u32::MAX as u64) as u32),
+            timeout: units::duration(lookup, "FORWARD_TIMEOUT_MS").filter(|timeout| !timeout.is_zero()).unwrap_or(defaults.timeout),
+        }
+    }
+}
+
+fn parse_rules(spec: &str) -> Result<Vec<ForwardRule>, Vec<String>> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for (number, rule) in spec.split(';').map(str::trim).filter(|rule| !rule.is_empty()).enumerate() {
+        match ForwardRule::parse(rule) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(format!("{} rule {}: {}", RULES_KEY, number + 1, e)),
+        }
+    }
+    if errors.is_empty() { Ok(rules) } else { Err(errors) }
+}
+
+fn parse_from(lookup: &impl Fn(&str) -> Option<String>) -> Result<ForwardFrom, String> {
+    match lookup(FROM_KEY).as_deref().map(str::trim) {
+        None | Some("") | Some("control") => Ok(ForwardFrom::Control),
+        Some("worker") => Ok(ForwardFrom::Worker),
+        Some(other) => Err(format!("{} '{}' must be control or worker", FROM_KEY, other)),
+    }
+}
+
+/// Everything wrong with [`RULES_KEY`] and [`FROM_KEY`], one line per
+/// problem.
+pub fn forwarding_errors(lookup: &impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut errors = parse_rules(&lookup(RULES_KEY).unwrap_or_default()).err().unwrap_or_default();
+    errors.extend(parse_from(lookup).err());
+    errors
+}
+
+/// Times the write carrying `headers` has been forwarded.
+pub fn hops(headers: &HeaderMap) -> u32 {
+    headers.get(HOPS_HEADER).and_then(|hops| hops.to_str().ok()).and_then(|hops| hops.trim().parse().ok()).unwrap_or(0)
+}
+
+/// `request` marked as carrying a write forwarded `hops` times.
+pub fn with_hops(request: reqwest::RequestBuilder, hops: u32) -> reqwest::RequestBuilder {
+    match hops {
+        0 => request,
+        hops => request.header(HOPS_HEADER, hops),
+    }
+}
+
+/// Refuses requests forwarded more than `max_hops` times with
+/// `508 Loop Detected`, so clusters forwarding to each other cannot pass
+/// a write around for ever.
+pub async fn refuse_loops(State(max_hops): State<u32>, request: Request, next: Next) -> Response {
+    let hops = hops(request.headers());
+    if hops > max_hops {
+        return RaftMetricsError::ForwardingLoop(format!(
+            "write has been forwarded {} times, more than FORWARD_MAX_HOPS ({})", hops, max_hops
+        ))
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// Served at `GET /forwarding/status`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForwardingStatus {
+    /// Whether this node forwards; only the node type in `FORWARD_FROM` does.
+    pub active: bool,
+    pub from: ForwardFrom,
+    pub max_hops: u32,
+    pub destinations: Vec<DestinationStatus>,
+}
+
+/// One rule's destination and how forwarding to it is going.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DestinationStatus {
+    pub destination: String,
+    pub metrics: Vec<String>,
+    pub mode: ForwardMode,
+    pub interval_ms: u64,
+    pub buffered: usize,
+    pub capacity: usize,
+    /// False once a batch has been dropped, until one gets through again.
+    pub healthy: bool,
+    pub forwarded: u64,
+    pub dropped: u64,
+    pub retries: u64,
+    /// Batches dropped in a row.
+    pub failures: u32,
+    /// Unix time the destination last took a batch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// A sample waiting to be forwarded, with the hops it arrived with.
+#[derive(Debug, Clone)]
+struct Pending {
+    name: String,
+    value: f64,
+    timestamp: i64,
+    labels: Labels,
+    hops: u32,
+}
+
+#[derive(Debug, Default)]
+struct Outcomes {
+    forwarded: u64,
+    dropped: u64,
+    retries: u64,
+    failures: u32,
+    last_success: Option<i64>,
+    last_error: Option<String>,
+}
+
+/// A rule with its buffer and how sending to its destination has gone.
+#[derive(Debug)]
+struct Pipeline {
+    rule: ForwardRule,
+    /// The destination as labeled in Prometheus.
+    label: String,
+    buffer: Mutex<VecDeque<Pending>>,
+    outcomes: Mutex<Outcomes>,
+}
+
+impl Pipeline {
+    fn new(rule: ForwardRule) -> Self {
+        let label = rule.destination.to_string();
+        FORWARDING_DESTINATION_UP.with_label_values(&[&label]).set(1);
+        Self { rule, label, buffer: Mutex::default(), outcomes: Mutex::default() }
+    }
+
+    /// Takes up to `limit` buffered samples from the front, stopping at the
+    /// first that arrived with other hops than the first did, so a sample
+    /// near the hop limit never takes fresher ones down with it.
+    fn take(&self, limit: usize) -> Vec<Pending> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let hops = buffer.front().map(|pending| pending.hops);
+        let count = buffer.iter().take(limit).take_while(|pending| Some(pending.hops) == hops).count();
+        let taken = buffer.drain(..count).collect();
+        FORWARDING_BUFFERED.with_label_values(&[&self.label]).set(buffer.len() as i64);
+        taken
+    }
+
+    fn take_all(&self) -> Vec<Pending> {
+        let taken = std::mem::take(&mut *self.buffer.lock().unwrap());
+        FORWARDING_BUFFERED.with_label_values(&[&self.label]).set(0);
+        taken.into()
+    }
+
+    fn drop_samples(&self, samples: u64, reason: &str) {
+        self.outcomes.lock().unwrap().dropped += samples;
+        FORWARDING_DROPPED_TOTAL.with_label_values(&[&self.label, reason]).inc_by(samples);
+    }
+}
+
+/// Forwards the samples this node writes that match a `FORWARD_RULES` rule.
+/// Writes only queue samples in each rule's bounded buffer; background
+/// tasks send them every rule's interval, retrying a failed batch with
+/// backoff and then dropping it, so a slow or failing destination never
+/// holds up ingestion. Buffered samples are lost if the node stops without
+/// a clean shutdown.
+#[derive(Debug)]
+pub struct Forwarder {
+    config: ForwardingConfig,
+    /// Names this node on a Pushgateway.
+    instance: String,
+    http: reqwest::Client,
+    pipelines: Vec<Pipeline>,
+}
+
+impl Default for Forwarder {
+    fn default() -> Self {
+        Self::new(ForwardingConfig::default(), ForwardFrom::default(), "")
+    }
+}
+
+impl Forwarder {
+    /// Forwards by `config` if `node` is the node type it forwards from.
+    pub fn new(config: ForwardingConfig, node: ForwardFrom, instance: &str) -> Self {
+        let http = reqwest::Client::builder().timeout(config.timeout).build().unwrap_or_default();
+        let pipelines = match config.from == node {
+            true => config.rules.iter().cloned().map(Pipeline::new).collect(),
+            false => Vec::new(),
+        };
+        Self { instance: instance.to_string(), http, pipelines, config }
+    }
+
+    pub fn config(&self) -> &ForwardingConfig {
+        &self.config
+    }
+
+    /// Whether this node forwards anything.
+    pub fn is_active(&self) -> bool {
+        !self.pipelines.is_empty()
+    }
+
+    /// Whether a sample of `name` would be forwarded.
+    pub fn wants(&self, name: &str) -> bool {
+        self.pipelines.iter().any(|pipeline| pipeline.rule.matches(name))
+    }
+
+    /// Queues a written sample for each rule matching `name`, dropping it
+    /// for a rule whose buffer is full.
+    pub fn offer(&self, name: &str, value: f64, timestamp: i64, labels: &Labels, hops: u32) {
+        for pipeline in self.pipelines.iter().filter(|pipeline| pipeline.rule.matches(name)) {
+            let mut buffer = pipeline.buffer.lock().unwrap();
+            if buffer.len() >= self.config.buffer_samples {
+                drop(buffer);
+                pipeline.drop_samples(1, "buffer_full");
+                continue;
+            }
+            buffer.push_back(Pending { name: name.to_string(), value, timestamp, labels: labels.clone(), hops });
+            FORWARDING_BUFFERED.with_label_values(&[&pipeline.label]).set(buffer.len() as i64);
+        }
+    }
+
+    /// Queues a request the control node wrote. A sample of a state metric
+    /// sent by state name has no value here and is not forwarded.
+    pub fn offer_request(&self, request: &MetricRequest, hops: u32) {
+        if request.value.is_nan() {
+            return;
+        }
+        let timestamp = request.timestamp.unwrap_or_else(|| chrono::Utc::now().timestamp());
+        self.offer(&request.metric_name, request.value, timestamp, &request.labels, hops);
+    }
+
+    /// Queues the records a worker wrote through `operation`.
+    pub fn offer_operation(&self, operation: &MetricOperation, hops: u32) {
+        match operation {
+            MetricOperation::Record { name, value, timestamp, labels, .. } => self.offer(name, *value, *timestamp, labels, hops),
+            MetricOperation::Transaction(operations) => {
+                for operation in operations {
+                    self.offer_operation(operation, hops);
+                }
+            }
+            MetricOperation::Keyed { operation, .. } => self.offer_operation(operation, hops),
+            _ => {}
+        }
+    }
+
+    /// Sends each rule's buffer on its interval, on background tasks.
+    pub fn spawn(self: &Arc<Self>) {
+        for index in 0..self.pipelines.len() {
+            let rule = &self.pipelines[index].rule;
+            info!("Forwarding {} to {} every {:?}", rule.metrics.join("|"), rule.destination, rule.interval);
+            let forwarder = self.clone();
+            tokio::spawn(async move {
+                let pipeline = &forwarder.pipelines[index];
+                let mut ticks = tokio::time::interval(pipeline.rule.interval);
+                ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                ticks.tick().await;
+                loop {
+                    ticks.tick().await;
+                    forwarder.flush_pipeline(pipeline).await;
+                }
+            });
+        }
+    }
+
+    /// Sends every rule's buffer now, as on shutdown.
+    pub async fn flush(&self) {
+        for pipeline in &self.pipelines {
+            self.flush_pipeline(pipeline).await;
+        }
+    }
+
+    /// Sends what `pipeline` has buffered, `FORWARD_BATCH_SAMPLES` to a
+    /// request and one hop count to a batch; an `aggregate` rule first folds
+    /// it into summaries. Stops at the first batch dropped, leaving the rest
+    /// of a raw buffer for the next interval.
+    async fn flush_pipeline(&self, pipeline: &Pipeline) {
+        match pipeline.rule.mode {
+            ForwardMode::Raw => loop {
+                let batch = pipeline.take(self.config.batch_samples);
+                if batch.is_empty() || !self.deliver(pipeline, batch).await {
+                    break;
+                }
+            },
+            ForwardMode::Aggregate => {
+                let mut summaries = summarize(pipeline.take_all());
+                summaries.sort_by_key(|summary| summary.hops);
+                let mut batches = summaries
+                    .chunk_by(|a, b| a.hops == b.hops)
+                    .flat_map(|run| run.chunks(self.config.batch_samples));
+                for batch in batches.by_ref() {
+                    if !self.deliver(pipeline, batch.to_vec()).await {
+                        break;
+                    }
+                }
+                let unsent = batches.map(|batch| batch.len() as u64).sum::<u64>();
+                if unsent > 0 {
+                    pipeline.drop_samples(unsent, "send_failed");
+                }
+            }
+        }
+    }
+
+    /// Sends `batch`, retrying with backoff while it fails in a way that may
+    /// pass. Returns whether the destination took it.
+    async fn deliver(&self, pipeline: &Pipeline, batch: Vec<Pending>) -> bool {
+        let hops = batch.iter().map(|pending| pending.hops).max().unwrap_or(0) + 1;
+        let mut retries = 0;
+        loop {
+            match self.send(&pipeline.rule.destination, &batch, hops).await {
+                Ok(rejected) => {
+                    let forwarded = batch.len() as u64 - rejected;
+                    FORWARDING_SAMPLES_TOTAL.with_label_values(&[&pipeline.label]).inc_by(forwarded);
+                    FORWARDING_DESTINATION_UP.with_label_values(&[&pipeline.label]).set(1);
+                    let mut outcomes = pipeline.outcomes.lock().unwrap();
+                    outcomes.forwarded += forwarded;
+                    outcomes.failures = 0;
+                    outcomes.last_success = Some(chrono::Utc::now().timestamp());
+                    drop(outcomes);
+                    if rejected > 0 {
+                        pipeline.drop_samples(rejected, "rejected");
+                    }
+                    return true;
+                }
+                Err((message, retriable)) if retriable && retries < self.config.max_retries => {
+                    retries += 1;
+                    pipeline.outcomes.lock().unwrap().retries += 1;
+                    FORWARDING_RETRIES_TOTAL.with_label_values(&[&pipeline.label]).inc();
+                    let pause = RETRY_BACKOFF * (1 << (retries - 1));
+                    warn!("Forwarding to {} failed, retrying in {:?}: {}", pipeline.label, pause, message);
+                    tokio::time::sleep(pause).await;
+                }
+                Err((message, _)) => {
+                    warn!("Dropping {} samples for {}: {}", batch.len(), pipeline.label, message);
+                    FORWARDING_DESTINATION_UP.with_label_values(&[&pipeline.label]).set(0);
+                    let mut outcomes = pipeline.outcomes.lock().unwrap();
+                    outcomes.failures += 1;
+                    outcomes.last_error = Some(message);
+                    drop(outcomes);
+                    pipeline.drop_samples(batch.len() as u64, "send_failed");
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Sends one batch, returning how many samples the destination refused.
+    /// A failure comes with whether sending it again may succeed.
+    async fn send(&self, destination: &Destination, batch: &[Pending], hops: u32) -> Result<u64, (String, bool)> {
+        match destination {
+            Destination::RaftMetrics(url) => {
+                let records: Vec<MetricRequest> = batch
+                    .iter()
+                    .map(|pending| MetricRequest {
+                        metric_name: pending.name.clone(),
+                        value: pending.value,
+                        state: None,
+                        timestamp: Some(pending.timestamp),
+                        labels: pending.labels.clone(),
+                    })
+                    .collect();
+                let client = RaftMetricsClient::with_http(url, self.http.clone());
+                match client.record_stream(&records, hops).await {
+                    Ok(summary) => Ok(summary.rejected),
+                    Err(e) => {
+                        let code = e.code();
+                        let retriable = code != ErrorCode::ForwardingLoop
+                            && (matches!(e, RaftMetricsError::Request(_)) || code.retriable() || code.status().is_server_error());
+                        Err((e.to_string(), retriable))
+                    }
+                }
+            }
+            Destination::Pushgateway(url) => {
+                let url = format!("{}/metrics/{}/{}", url, label_segment("job", PUSHGATEWAY_JOB), label_segment("instance", &self.instance));
+                let request = self.http
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(gauges(batch));
+                let response = with_hops(request, hops)
+                    .send()
+                    .await
+                    .map_err(|e| (format!("pushgateway unreachable: {}", e.without_url()), true))?;
+                let status = response.status();
+                match status.is_success() {
+                    true => Ok(0),
+                    false => Err((format!("pushgateway answered {}", status), status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS)),
+                }
+            }
+        }
+    }
+
+    pub fn status(&self) -> ForwardingStatus {
+        let destinations = self
+            .pipelines
+            .iter()
+            .map(|pipeline| {
+                let outcomes = pipeline.outcomes.lock().unwrap();
+                DestinationStatus {
+                    destination: pipeline.label.clone(),
+                    metrics: pipeline.rule.metrics.clone(),
+                    mode: pipeline.rule.mode,
+                    interval_ms: pipeline.rule.interval.as_millis() as u64,
+                    buffered: pipeline.buffer.lock().unwrap().len(),
+                    capacity: self.config.buffer_samples,
+                    healthy: outcomes.failures == 0,
+                    forwarded: outcomes.forwarded,
+                    dropped: outcomes.dropped,
+                    retries: outcomes.retries,
+                    failures: outcomes.failures,
+                    last_success: outcomes.last_success,
+                    last_error: outcomes.last_error.clone(),
+                }
+            })
+            .collect();
+        ForwardingStatus {
+            active: self.is_active(),
+            from: self.config.from,
+            max_hops: self.config.max_hops,
+            destinations,
+        }
+    }
+}
+
+/// Folds `samples` into `<name>_count`, `_sum`, `_min` and `_max` per
+/// series, stamped with the series' latest timestamp.
+fn summarize(samples: Vec<Pending>) -> Vec<Pending> {
+    let mut series = BTreeMap::<(String, Labels), (SampleSummary, i64, u32)>::new();
+    for sample in samples {
+        match series.get_mut(&(sample.name.clone(), sample.labels.clone())) {
+            Some((summary, timestamp, hops)) => {
+                summary.add(sample.value, sample.timestamp >= *timestamp);
+                *timestamp = (*timestamp).max(sample.timestamp);
+                *hops = (*hops).max(sample.hops);
+            }
+            None => {
+                series.insert((sample.name, sample.labels), (SampleSummary::new(sample.value), sample.timestamp, sample.hops));
+            }
+        }
+    }
+    series
+        .into_iter()
+        .flat_map(|((name, labels), (summary, timestamp, hops))| {
+            [("count", summary.count as f64), ("sum", summary.sum), ("min", summary.min), ("max", summary.max)]
+                .map(|(statistic, value)| Pending { name: format!("{}_{}", name, statistic), value, timestamp, labels: labels.clone(), hops })
+        })
+        .collect()
+}
+
+/// `batch` in the Prometheus text format, one gauge per series at its
+/// latest value; the Pushgateway takes no timestamps.
+fn gauges(batch: &[Pending]) -> String {
+    let mut latest = BTreeMap::<(String, String), (i64, f64)>::new();
+    for pending in batch {
+        let labels = pending
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", prometheus_name(key), value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+            .collect::<Vec<_>>()
+            .join(",");
+        let entry = latest.entry((prometheus_name(&pending.name), labels)).or_insert((pending.timestamp, pending.value));
+        if pending.timestamp >= entry.0 {
+            *entry = (pending.timestamp, pending.value);
+        }
+    }
+
+    let mut body = String::new();
+    let mut typed: Option<String> = None;
+    for ((name, labels), (_, value)) in latest {
+        if typed.as_ref() != Some(&name) {
+            body.push_str(&format!("# TYPE {} gauge\n", name));
+            typed = Some(name.clone());
+        }
+        match labels.is_empty() {
+            true => body.push_str(&format!("{} {}\n", name, value)),
+            false => body.push_str(&format!("{}{{{}}} {}\n", name, labels, value)),
+        }
+    }
+    body
+}
+
+/// `name` with every character Prometheus does not allow in metric and
+/// label names replaced by `_`.
+fn prometheus_name(name: &str) -> String {
+    let mut sanitized: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if !sanitized.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::IngestSummary;
+    use axum::{body::Bytes, http::{StatusCode, Uri}, routing::post, Json, Router};
+    use tower::ServiceExt;
+
+    /// A request the stub downstream received.
+    #[derive(Debug, Clone)]
+    struct Received {
+        path: String,
+        hops: u32,
+        body: String,
+    }
+
+    /// A downstream answering `/metrics/stream` and Pushgateway pushes with
+    /// `answers` in turn, `200` taking the samples, then always taking them.
+    async fn stub_downstream(answers: Vec<StatusCode>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let answers = Arc::new(Mutex::new(VecDeque::from(answers)));
+        let handler = {
+            let received = received.clone();
+            move |uri: Uri, headers: HeaderMap, body: Bytes| {
+                let received = received.clone();
+                let answers = answers.clone();
+                async move {
+                    let body = String::from_utf8(body.to_vec()).unwrap();
+                    let records = body.lines().count() as u64;
+                    received.lock().unwrap().push(Received { path: uri.path().to_string(), hops: hops(&headers), body });
+                    match answers.lock().unwrap().pop_front() {
+                        Some(status) if status != StatusCode::OK => (status, format!("stub answered {}", status.as_u16())).into_response(),
+                        _ => Json(IngestSummary { accepted: records, ..IngestSummary::default() }).into_response(),
+                    }
+                }
+            }
+        };
+        let router = Router::new()
+            .route("/metrics/stream", post(handler.clone()))
+            .route("/metrics/*grouping", post(handler));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (format!("http://{}", addr), received)
+    }
+
+    fn forwarder(rules: &str, extra: &[(&str, &str)]) -> Forwarder {
+        let lookup = |key: &str| match key {
+            RULES_KEY => Some(rules.to_string()),
+            _ => extra.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string()),
+        };
+        Forwarder::new(ForwardingConfig::from_lookup(&lookup), ForwardFrom::Control, "edge-1")
+    }
+
+    fn names(received: &Received) -> Vec<String> {
+        received.body.lines().map(|line| serde_json::from_str::<MetricRequest>(line).unwrap().metric_name).collect()
+    }
+
+    #[test]
+    fn test_rules_parse_and_match() {
+        let rules = parse_rules("match=acme.*|cpu,to=raftmetrics:http://central:8080/,every=30s; match=lat,to=pushgateway:https://pg:9091,send=aggregate").unwrap();
+        assert_eq!(rules[0].destination, Destination::RaftMetrics("http://central:8080".to_string()));
+        assert_eq!(rules[0].interval, Duration::from_secs(30));
+        assert_eq!((rules[1].interval, rules[1].mode), (DEFAULT_INTERVAL, ForwardMode::Aggregate));
+        assert!(rules[0].matches("acme.cpu") && rules[0].matches("cpu"));
+        assert!(!rules[0].matches("cpu2") && !rules[0].matches("beta.cpu"));
+
+        for bad in ["match=cpu", "to=raftmetrics:http://x", "match=*,to=raftmetrics:http://x", "match=cpu,to=kafka:http://x", "match=cpu,to=raftmetrics:ftp://x", "match=cpu,to=raftmetrics:http://x,every=0s", "match=cpu,to=raftmetrics:http://x,send=some"] {
+            assert!(parse_rules(bad).is_err(), "{}", bad);
+        }
+        let lookup = |key: &str| match key {
+            RULES_KEY => Some("match=cpu;match=mem,to=raftmetrics:http://x".to_string()),
+            FROM_KEY => Some("everywhere".to_string()),
+            _ => None,
+        };
+        assert_eq!(forwarding_errors(&lookup).len(), 2);
+        assert_eq!(ForwardingConfig::from_lookup(&lookup), ForwardingConfig::default());
+    }
+
+    #[tokio::test]
+    async fn test_matching_samples_are_batched_downstream() {
+        let (url, received) = stub_downstream(Vec::new()).await;
+        let forwarder = forwarder(&format!("match=acme.*|cpu,to=raftmetrics:{}", url), &[("FORWARD_BATCH_SAMPLES", "2")]);
+        let labels = Labels::new();
+        forwarder.offer("acme.a", 1.0, 100, &labels, 0);
+        forwarder.offer("acme.b", 2.0, 100, &labels, 0);
+        forwarder.offer("mem", 3.0, 100, &labels, 0);
+        forwarder.offer("cpu", 4.0, 100, &labels, 0);
+        // Arrived already forwarded once
+        forwarder.offer("acme.c", 5.0, 100, &labels, 1);
+        assert_eq!(forwarder.status().destinations[0].buffered, 4);
+
+        forwarder.flush().await;
+        let received = received.lock().unwrap().clone();
+        // Samples that arrived with other hops go in a batch of their own
+        assert_eq!(received.len(), 3);
+        assert_eq!((names(&received[0]), received[0].hops), (vec!["acme.a".to_string(), "acme.b".to_string()], 1));
+        assert_eq!((names(&received[1]), received[1].hops), (vec!["cpu".to_string()], 1));
+        assert_eq!((names(&received[2]), received[2].hops), (vec!["acme.c".to_string()], 2));
+        let record: MetricRequest = serde_json::from_str(&received[1].body).unwrap();
+        assert_eq!((record.value, record.timestamp), (4.0, Some(100)));
+
+        let status = &forwarder.status().destinations[0];
+        assert_eq!((status.forwarded, status.dropped, status.buffered, status.healthy), (4, 0, 0, true));
+        assert!(status.last_success.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_failed_batches_are_retried_then_dropped() {
+        let unavailable = StatusCode::SERVICE_UNAVAILABLE;
+        let answers = vec![unavailable, unavailable, StatusCode::OK, unavailable, unavailable, unavailable, StatusCode::BAD_REQUEST];
+        let (url, received) = stub_downstream(answers).await;
+        let forwarder = forwarder(&format!("match=cpu,to=raftmetrics:{}", url), &[("FORWARD_MAX_RETRIES", "2"), ("FORWARD_BUFFER_SAMPLES", "2")]);
+        let labels = Labels::new();
+
+        // Two failures, then taken on the second retry
+        forwarder.offer("cpu", 1.0, 100, &labels, 0);
+        forwarder.flush().await;
+        let status = forwarder.status().destinations[0].clone();
+        assert_eq!((status.forwarded, status.retries, status.healthy), (1, 2, true));
+
+        // Still failing after both retries: dropped
+        forwarder.offer("cpu", 2.0, 101, &labels, 0);
+        forwarder.flush().await;
+        let status = forwarder.status().destinations[0].clone();
+        assert_eq!((status.forwarded, status.dropped, status.retries, status.failures, status.healthy), (1, 1, 4, 1, false));
+        assert!(status.last_error.as_deref().is_some_and(|error| error.contains("503")), "{:?}", status.last_error);
+
+        // A refusal is not worth retrying, and a full buffer drops at once
+        for value in [3.0, 4.0, 5.0] {
+            forwarder.offer("cpu", value, 102, &labels, 0);
+        }
+        forwarder.flush().await;
+        let status = forwarder.status().destinations[0].clone();
+        assert_eq!((status.dropped, status.retries, status.failures), (4, 4, 2));
+        assert_eq!(received.lock().unwrap().len(), 7);
+
+        forwarder.offer("cpu", 6.0, 103, &labels, 0);
+        forwarder.flush().await;
+        assert!(forwarder.status().destinations[0].healthy);
+    }
+
+    #[tokio::test]
+    async fn test_aggregates_are_pushed_to_a_pushgateway() {
+        let (url, received) = stub_downstream(Vec::new()).await;
+        let forwarder = forwarder(&format!("match=lat.*,to=pushgateway:{},send=aggregate", url), &[]);
+        let eu = Labels::from([("region".to_string(), "eu".to_string())]);
+        forwarder.offer("lat.api", 3.0, 100, &eu, 0);
+        forwarder.offer("lat.api", 9.0, 101, &eu, 0);
+        forwarder.offer("lat.api", 1.0, 100, &Labels::new(), 0);
+        forwarder.flush().await;
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].path, "/metrics/job/raftmetrics_forward/instance/edge-1");
+        assert_eq!(received[0].hops, 1);
+        let body = &received[0].body;
+        for line in ["# TYPE lat_api_count gauge", "lat_api_count 1", "lat_api_count{region=\"eu\"} 2", "lat_api_sum{region=\"eu\"} 12", "lat_api_max{region=\"eu\"} 9", "lat_api_min 1"] {
+            assert!(body.lines().any(|sent| sent == line), "missing '{}' in\n{}", line, body);
+        }
+        assert_eq!(forwarder.status().destinations[0].forwarded, 8);
+        assert_eq!(prometheus_name("9.lat-ms"), "_9_lat_ms");
+    }
+
+    #[tokio::test]
+    async fn test_writes_forwarded_too_often_are_refused() {
+        let app = Router::new()
+            .route("/metrics", post(|| async { StatusCode::OK }))
+            .route_layer(axum::middleware::from_fn_with_state(2u32, refuse_loops));
+        let send = |hops: Option<&str>| {
+            let mut request = axum::http::Request::post("/metrics");
+            if let Some(hops) = hops {
+                request = request.header(HOPS_HEADER, hops);
+            }
+            app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+        };
+        assert_eq!(send(None).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send(Some("2")).await.unwrap().status(), StatusCode::OK);
+        let refused = send(Some("3")).await.unwrap();
+        assert_eq!(refused.status(), StatusCode::LOOP_DETECTED);
+        let body = axum::body::to_bytes(refused.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["code"], "FORWARDING_LOOP");
+
+        // Only the node type in FORWARD_FROM forwards
+        let rules = [(RULES_KEY, "match=cpu,to=raftmetrics:http://central:8080")];
+        let lookup = |key: &str| rules.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string());
+        let on_worker = Forwarder::new(ForwardingConfig::from_lookup(&lookup), ForwardFrom::Worker, "worker-0");
+        assert!(!on_worker.wants("cpu") && !on_worker.is_active());
+    }
+}
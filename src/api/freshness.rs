@@ -0,0 +1,81 @@
+use axum::http::header::{CACHE_CONTROL, EXPIRES};
+use axum::http::HeaderName;
+use chrono::DateTime;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::config::units;
+
+/// How long a metric's latest value stays fresh after its sample, from
+/// `METRIC_TTL_SECS` and `METRIC_TTLS`. Only response headers use it;
+/// nothing sweeps values past their TTL.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricTtls {
+    /// TTL in seconds for metrics without their own; `None` sends no
+    /// freshness headers.
+    pub default: Option<i64>,
+    /// TTLs by metric name; 0 turns the headers off for that metric.
+    pub ttls: HashMap<String, i64>,
+}
+
+impl MetricTtls {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `METRIC_TTL_SECS` and `METRIC_TTLS` (comma-separated
+    /// `name=secs` pairs). Malformed entries are skipped with a warning.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut ttls = HashMap::new();
+        for entry in lookup("METRIC_TTLS").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            match entry.split_once('=').and_then(|(name, secs)| Some((name.trim(), secs.trim().parse::<i64>().ok()?))) {
+                Some((name, secs)) if !name.is_empty() && secs >= 0 => {
+                    ttls.insert(name.to_string(), secs);
+                }
+                _ => warn!("Ignoring METRIC_TTLS entry '{}'", entry),
+            }
+        }
+        Self {
+            default: units::duration(&lookup, "METRIC_TTL_SECS").map(|ttl| ttl.as_secs() as i64).filter(|&secs| secs > 0),
+            ttls,
+        }
+    }
+
+    /// Unix seconds at which a sample of `name` taken at `timestamp` goes
+    /// stale, or `None` when the metric has no TTL.
+    pub fn expires_at(&self, name: &str, timestamp: i64) -> Option<i64> {
+        let ttl = self.ttls.get(name).copied().or(self.default).filter(|&secs| secs > 0)?;
+        Some(timestamp.saturating_add(ttl))
+    }
+}
+
+/// `Cache-Control` and `Expires` for a value going stale at `expires_at`,
+/// as seen at `now`; a value already past it gets `max-age=0`.
+pub fn freshness_headers(expires_at: i64, now: i64) -> [(HeaderName, String); 2] {
+    let expires = DateTime::from_timestamp(expires_at, 0)
+        .map_or_else(|| "0".to_string(), |time| time.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    [(CACHE_CONTROL, format!("max-age={}", (expires_at - now).max(0))), (EXPIRES, expires)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_metric_ttls_override_the_default() {
+        let ttls = MetricTtls::from_lookup(|key| match key {
+            "METRIC_TTL_SECS" => Some("60".to_string()),
+            "METRIC_TTLS" => Some("slow=600,raw=0,bad".to_string()),
+            _ => None,
+        });
+        assert_eq!(ttls.expires_at("cpu", 1_000), Some(1_060));
+        assert_eq!(ttls.expires_at("slow", 1_000), Some(1_600));
+        assert_eq!(ttls.expires_at("raw", 1_000), None);
+        assert_eq!(MetricTtls::default().expires_at("cpu", 1_000), None);
+
+        let [cache_control, expires] = freshness_headers(1_700_000_060, 1_700_000_000);
+        assert_eq!(cache_control.1, "max-age=60");
+        assert_eq!(expires.1, "Tue, 14 Nov 2023 22:14:20 GMT");
+        assert_eq!(freshness_headers(1_000, 2_000)[0].1, "max-age=0");
+    }
+}
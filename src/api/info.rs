@@ -0,0 +1,138 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::info;
+
+use crate::{config::ControlConfig, metrics::storage::StorageBackendKind};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short hash of the commit built, or "unknown" outside a git checkout.
+pub const GIT_COMMIT: &str = env!("RAFTMETRICS_GIT_COMMIT");
+/// Unix seconds the binary was built at, or "unknown".
+const BUILD_TIME: &str = env!("RAFTMETRICS_BUILD_TIME");
+
+/// Storage backend reported by nodes that keep no metrics themselves.
+pub const NO_STORAGE: &str = "none";
+
+lazy_static! {
+    static ref STARTED_AT: DateTime<Utc> = Utc::now();
+}
+
+/// Records now as the process start time unless it already was.
+pub fn mark_started() {
+    lazy_static::initialize(&STARTED_AT);
+}
+
+/// This node's id from `NODE_ID`, or `WORKER_ID`, defaulting to 1.
+pub fn node_id_from_env() -> usize {
+    std::env::var("NODE_ID")
+        .or_else(|_| std::env::var("WORKER_ID"))
+        .ok()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Cargo features this binary was built with.
+pub fn enabled_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if cfg!(feature = "duckdb-storage") {
+        features.push("duckdb-storage".to_string());
+    }
+    if cfg!(feature = "sqlite-storage") {
+        features.push("sqlite-storage".to_string());
+    }
+    features
+}
+
+/// What is running on a node, served at `/info` and logged at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub version: String,
+    pub git_commit: String,
+    /// RFC 3339, or "unknown".
+    pub build_time: String,
+    pub features: Vec<String>,
+    pub storage_backend: String,
+    pub node_id: usize,
+    pub node_type: String,
+    /// Workers the control node partitions metrics over; absent on workers,
+    /// which only hold their own partition.
+    pub partitions: Option<usize>,
+    /// RFC 3339.
+    pub started_at: String,
+    /// Whether the node refuses writes; see [`super::read_only::ReadOnly`].
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl NodeInfo {
+    pub fn new(node_type: &str, node_id: usize, storage_backend: &str, partitions: Option<usize>) -> Self {
+        let build_time = BUILD_TIME
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .map_or_else(|| "unknown".to_string(), |time| time.to_rfc3339_opts(SecondsFormat::Secs, true));
+        Self {
+            version: VERSION.to_string(),
+            git_commit: GIT_COMMIT.to_string(),
+            build_time,
+            features: enabled_features(),
+            storage_backend: storage_backend.to_string(),
+            node_id,
+            node_type: node_type.to_string(),
+            partitions,
+            started_at: STARTED_AT.to_rfc3339_opts(SecondsFormat::Secs, true),
+            read_only: false,
+        }
+    }
+
+    /// What a node of `node_type` would report as configured by the
+    /// environment, for `--check`.
+    pub fn from_env(node_type: &str) -> Self {
+        let (storage_backend, partitions) = match node_type {
+            "control" => {
+                let config_file = std::env::var("CONFIG_FILE").ok();
+                let config = ControlConfig::load(config_file.as_deref().map(Path::new)).ok();
+                (NO_STORAGE, config.map(|config| config.worker_urls.len()))
+            }
+            node_type => {
+                let backend = StorageBackendKind::from_env().map_or("unknown", StorageBackendKind::name);
+                (backend, (node_type == "standalone").then_some(1))
+            }
+        };
+        Self::new(node_type, node_id_from_env(), storage_backend, partitions)
+    }
+
+    /// Logs this as one structured event.
+    pub fn log(&self) {
+        info!(
+            version = %self.version,
+            git_commit = %self.git_commit,
+            build_time = %self.build_time,
+            features = %self.features.join(","),
+            storage_backend = %self.storage_backend,
+            node_id = self.node_id,
+            node_type = %self.node_type,
+            partitions = ?self.partitions,
+            started_at = %self.started_at,
+            "Node starting"
+        );
+    }
+
+    /// Human-readable lines for `--check`.
+    pub fn render(&self) -> String {
+        let partitions = self.partitions.map_or_else(|| "-".to_string(), |count| count.to_string());
+        format!(
+            "{} node {}: version {} ({}, built {}), features [{}], storage {}, partitions {}\n",
+            self.node_type,
+            self.node_id,
+            self.version,
+            self.git_commit,
+            self.build_time,
+            self.features.join(", "),
+            self.storage_backend,
+            partitions,
+        )
+    }
+}
@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc, Mutex,
+};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::{metrics::MetricOperation, RaftMetricsError, Result};
+
+/// Lines written after which the journal is rewritten with only the
+/// entries still pending.
+const COMPACT_AFTER_LINES: u64 = 4096;
+
+/// One line of the journal: an operation about to be proposed, or the
+/// mark that an earlier one was applied or failed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Entry { id: u64, operation: MetricOperation },
+    Done { done: u64 },
+}
+
+/// Work for the writer thread, answered once it is on disk.
+enum Request {
+    Append { operation: MetricOperation, appended: oneshot::Sender<std::io::Result<u64>> },
+    Complete { id: u64, completed: oneshot::Sender<()> },
+}
+
+#[derive(Debug)]
+struct Open {
+    path: PathBuf,
+    file: File,
+    next_id: u64,
+    pending: BTreeMap<u64, MetricOperation>,
+    lines: u64,
+}
+
+impl Open {
+    fn write(&mut self, line: &Line) -> std::io::Result<()> {
+        let mut bytes = serde_json::to_vec(line)?;
+        bytes.push(b'\n');
+        self.file.write_all(&bytes)?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Serves requests until the journal is dropped. Each pass takes every
+    /// request waiting, so appends made together share one flush to disk.
+    fn run(mut self, requests: mpsc::Receiver<Request>, fsync: bool, pending: Arc<AtomicUsize>) {
+        while let Ok(first) = requests.recv() {
+            let mut appended = Vec::new();
+            let mut completed = Vec::new();
+            for request in std::iter::once(first).chain(requests.try_iter()) {
+                match request {
+                    Request::Append { operation, appended: respond_to } => {
+                        let id = self.next_id;
+                        let written = self.write(&Line::Entry { id, operation: operation.clone() });
+                        if written.is_ok() {
+                            self.next_id += 1;
+                            self.pending.insert(id, operation);
+                        }
+                        appended.push((written.map(|()| id), respond_to));
+                    }
+                    Request::Complete { id, completed: respond_to } => {
+                        self.complete(id);
+                        completed.push(respond_to);
+                    }
+                }
+            }
+            let synced = match fsync && !appended.is_empty() {
+                true => self.file.sync_data(),
+                false => Ok(()),
+            };
+            if synced.is_err() {
+                // Their callers are told they failed, so none will complete them
+                for (written, _) in &appended {
+                    if let Ok(id) = written {
+                        self.pending.remove(id);
+                    }
+                }
+            }
+            pending.store(self.pending.len(), Ordering::Release);
+            for (written, respond_to) in appended {
+                let outcome = match (&synced, written) {
+                    (Err(e), Ok(_)) => Err(std::io::Error::new(e.kind(), e.to_string())),
+                    (_, written) => written,
+                };
+                let _ = respond_to.send(outcome);
+            }
+            for respond_to in completed {
+                let _ = respond_to.send(());
+            }
+        }
+    }
+
+    /// Marks entry `id` done, truncating the journal once nothing is
+    /// pending. A failure is logged; the entry is then replayed after a
+    /// restart.
+    fn complete(&mut self, id: u64) {
+        if self.pending.remove(&id).is_none() {
+            return;
+        }
+        let written = match self.pending.is_empty() || self.lines >= COMPACT_AFTER_LINES {
+            true => self.compact(),
+            false => self.write(&Line::Done { done: id }),
+        };
+        if let Err(e) = written {
+            warn!("Failed to mark journal entry {} done in {}: {}", id, self.path.display(), e);
+        }
+    }
+
+    /// Replaces the file with one holding only the pending entries.
+    fn compact(&mut self) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            self.file.set_len(0)?;
+            self.lines = 0;
+            return Ok(());
+        }
+        let temp = self.path.with_extension("compacting");
+        let mut contents = Vec::new();
+        for (&id, operation) in &self.pending {
+            serde_json::to_writer(&mut contents, &Line::Entry { id, operation: operation.clone() })?;
+            contents.push(b'\n');
+        }
+        let mut file = File::create(&temp)?;
+        file.write_all(&contents)?;
+        file.sync_data()?;
+        std::fs::rename(&temp, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.lines = self.pending.len() as u64;
+        Ok(())
+    }
+}
+
+/// Operations a worker accepted but raft has not applied yet, kept in an
+/// append-only file so they can be proposed again after a crash. Each is
+/// written before it is proposed and marked done once applied or refused.
+/// Replay is at least once: an operation applied just before a crash,
+/// whose mark did not reach the file, is proposed again, and is only
+/// skipped because the worker journals it as a
+/// [`MetricOperation::Keyed`]. Without `JOURNAL_PATH` nothing is journaled.
+/// The file is only touched by a writer thread of its own, so callers never
+/// block on disk while holding a lock.
+#[derive(Debug, Default)]
+pub struct Journal {
+    writer: Option<mpsc::Sender<Request>>,
+    /// Entries pending as of the writer's last pass.
+    pending: Arc<AtomicUsize>,
+    /// Entries left pending by the last run, until they are replayed.
+    recovered: Mutex<Vec<(u64, MetricOperation)>>,
+}
+
+impl Journal {
+    /// Reads `JOURNAL_PATH` and `JOURNAL_FSYNC` (default `true`).
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("JOURNAL_PATH").ok().filter(|path| !path.is_empty()) {
+            Some(path) => Self::open(Path::new(&path), std::env::var("JOURNAL_FSYNC").map_or(true, |v| v != "false")),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Opens the journal at `path`, keeping the entries a previous run left
+    /// pending for [`Self::take_recovered`]. A line cut short by a crash is
+    /// skipped.
+    pub fn open(path: &Path, fsync: bool) -> Result<Self> {
+        let failed = |e: std::io::Error| RaftMetricsError::Internal(format!("Failed to open journal {}: {}", path.display(), e));
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(failed(e)),
+        };
+        let mut pending = BTreeMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(Line::Entry { id, operation }) => {
+                    pending.insert(id, operation);
+                }
+                Ok(Line::Done { done }) => {
+                    pending.remove(&done);
+                }
+                Err(e) => warn!("Skipping unreadable journal line in {}: {}", path.display(), e),
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(failed)?;
+        let next_id = pending.keys().next_back().map_or(1, |id| id + 1);
+        let recovered = pending.iter().map(|(&id, operation)| (id, operation.clone())).collect::<Vec<_>>();
+        if !recovered.is_empty() {
+            info!("Journal {} holds {} operations to replay", path.display(), recovered.len());
+        }
+        let mut open = Open { path: path.to_path_buf(), file, next_id, pending, lines: 0 };
+        open.compact().map_err(failed)?;
+
+        let pending = Arc::new(AtomicUsize::new(open.pending.len()));
+        let (writer, requests) = mpsc::channel();
+        let published = pending.clone();
+        std::thread::Builder::new()
+            .name("journal-writer".to_string())
+            .spawn(move || open.run(requests, fsync, published))
+            .map_err(failed)?;
+        Ok(Self { writer: Some(writer), pending, recovered: Mutex::new(recovered) })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Operations pending in the journal.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Takes the entries the last run left pending, oldest first, to be
+    /// proposed again. They stay in the journal until [`Self::complete`].
+    pub fn take_recovered(&self) -> Vec<(u64, MetricOperation)> {
+        std::mem::take(&mut *self.recovered.lock().unwrap())
+    }
+
+    fn send(&self, request: Request) -> Result<()> {
+        let Some(writer) = &self.writer else {
+            return Ok(());
+        };
+        writer.send(request)
+            .map_err(|_| RaftMetricsError::Internal("Journal writer stopped".to_string()))
+    }
+
+    /// Writes `operation` to the journal, returning its id once it is on
+    /// disk, or `None` when journaling is off.
+    pub async fn append(&self, operation: &MetricOperation) -> Result<Option<u64>> {
+        if !self.enabled() {
+            return Ok(None);
+        }
+        let (appended, id) = oneshot::channel();
+        self.send(Request::Append { operation: operation.clone(), appended })?;
+        let id = id.await.map_err(|_| RaftMetricsError::Internal("Journal writer stopped".to_string()))?
+            .map_err(|e| RaftMetricsError::Internal(format!("Failed to journal operation: {}", e)))?;
+        Ok(Some(id))
+    }
+
+    /// Marks entry `id` done, truncating the journal once nothing is
+    /// pending. A failure is logged; the entry is then replayed after a
+    /// restart.
+    pub async fn complete(&self, id: u64) {
+        if !self.enabled() {
+            return;
+        }
+        let (completed, done) = oneshot::channel();
+        match self.send(Request::Complete { id, completed }) {
+            Ok(()) => {
+                let _ = done.await;
+            }
+            Err(e) => warn!("Failed to mark journal entry {} done: {}", id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Labels;
+
+    fn record(name: &str, value: f64) -> MetricOperation {
+        MetricOperation::record(name, value, 100, Labels::new())
+    }
+
+    #[tokio::test]
+    async fn test_only_unfinished_entries_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-journal-unit-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let journal = Journal::open(&path, false).unwrap();
+        let mut ids = Vec::new();
+        for name in ["cpu", "mem", "disk"] {
+            ids.push(journal.append(&record(name, 1.0)).await.unwrap().unwrap());
+        }
+        journal.complete(ids[1]).await;
+        drop(journal);
+        // A crash mid-write leaves a partial line
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"id\":9,\"oper").unwrap();
+
+        let journal = Journal::open(&path, false).unwrap();
+        let recovered = journal.take_recovered();
+        assert_eq!(recovered, vec![(ids[0], record("cpu", 1.0)), (ids[2], record("disk", 1.0))]);
+        assert!(journal.append(&record("net", 1.0)).await.unwrap().unwrap() > ids[2]);
+        for (id, _) in recovered {
+            journal.complete(id).await;
+        }
+        assert_eq!(journal.pending(), 1);
+
+        assert!(!Journal::default().enabled());
+        assert_eq!(Journal::default().append(&record("cpu", 1.0)).await.unwrap(), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_appends_made_together_are_all_written() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-journal-group-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let journal = Journal::open(&path, true).unwrap();
+        let operations: Vec<MetricOperation> = (0..50).map(|i| record(&format!("m{}", i), i as f64)).collect();
+        let ids = futures_util::future::try_join_all(operations.iter().map(|operation| journal.append(operation))).await.unwrap();
+        let mut unique: Vec<u64> = ids.into_iter().flatten().collect();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!((unique.len(), journal.pending()), (50, 50));
+        drop(journal);
+
+        let recovered = Journal::open(&path, true).unwrap().take_recovered();
+        assert_eq!(recovered.into_iter().map(|(_, operation)| operation).collect::<Vec<_>>(), operations);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::debug;
+
+use crate::{api::control::{ControlHandle, ControlState}, partitioning::LeastLoadedRouter};
+
+/// Body of a worker's `GET /load`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerLoad {
+    /// Metrics whose aggregate the worker holds in memory.
+    pub metric_count: u64,
+    /// Samples applied since the worker started.
+    pub records_total: u64,
+    /// Proposals waiting for the raft loop to pick them up.
+    pub proposal_queue_depth: u64,
+    /// One-minute load average per CPU, where the platform reports one.
+    pub cpu_hint: Option<f64>,
+}
+
+impl WorkerLoad {
+    /// Lower is less loaded. Queued proposals count most, as each is a
+    /// write the worker has not started on; the CPU hint breaks ties.
+    pub fn score(&self) -> f64 {
+        self.proposal_queue_depth as f64 + self.cpu_hint.unwrap_or(0.0)
+    }
+}
+
+/// One-minute load average from `/proc/loadavg` divided by the CPUs
+/// available, or `None` off Linux.
+pub fn cpu_hint() -> Option<f64> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+    let one_minute: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    Some(one_minute / cpus as f64)
+}
+
+/// The latest load each worker reported, by URL.
+#[derive(Debug, Default)]
+pub struct WorkerLoads {
+    loads: Mutex<HashMap<String, WorkerLoad>>,
+}
+
+impl WorkerLoads {
+    pub fn record(&self, url: &str, load: WorkerLoad) {
+        self.loads.lock().unwrap().insert(url.to_string(), load);
+    }
+
+    /// Drops the report of a worker that stopped answering.
+    pub fn forget(&self, url: &str) {
+        self.loads.lock().unwrap().remove(url);
+    }
+
+    pub fn get(&self, url: &str) -> Option<WorkerLoad> {
+        self.loads.lock().unwrap().get(url).cloned()
+    }
+
+    /// A router over `worker_urls`, by index, preferring the least loaded.
+    pub fn router(&self, worker_urls: &[String]) -> LeastLoadedRouter {
+        let loads = self.loads.lock().unwrap();
+        LeastLoadedRouter::new(worker_urls.iter().map(|url| loads.get(url).map(WorkerLoad::score)).collect())
+    }
+}
+
+/// Fetches every worker's `GET /load` into `state.loads`. A worker that
+/// does not answer has its last report dropped.
+pub async fn poll_loads(state: &ControlState) {
+    let client = state.read_pool.client();
+    for url in state.worker_urls.iter() {
+        let request = client.get(format!("{}/load", url)).timeout(state.read_pool.request_timeout());
+        let load = match request.send().await.and_then(|response| response.error_for_status()) {
+            Ok(response) => response.json::<WorkerLoad>().await,
+            Err(e) => Err(e),
+        };
+        match load {
+            Ok(load) => state.loads.record(url, load),
+            Err(e) => {
+                debug!("No load report from {}: {}", url, e);
+                state.loads.forget(url);
+            }
+        }
+    }
+}
+
+/// Polls worker loads every `LOAD_POLL_INTERVAL_SECS`, unless it is zero.
+pub fn poll_worker_loads(handle: ControlHandle) {
+    tokio::spawn(async move {
+        loop {
+            let interval = handle.snapshot().config.load_poll_interval;
+            if interval.is_zero() {
+                return;
+            }
+            tokio::time::sleep(interval).await;
+            poll_loads(&handle.snapshot()).await;
+        }
+    });
+}
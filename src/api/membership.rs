@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::{config::ControlConfig, metrics::StorageStats, raft::node::RaftHealth, RaftMetricsError, Result};
+
+/// Largest capacity a worker may announce; each unit places
+/// [`crate::partitioning::VNODES_PER_WEIGHT`] virtual nodes on the ring.
+pub const MAX_WORKER_CAPACITY: u32 = 100;
+
+/// Body of `POST /cluster/register`, sent by a worker on startup and again
+/// as its heartbeat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    /// Base URL the control node should forward to.
+    pub url: String,
+    /// Relative share of metrics the worker should own.
+    #[serde(default = "default_capacity")]
+    pub capacity: u32,
+}
+
+fn default_capacity() -> u32 {
+    1
+}
+
+/// Body of `POST /cluster/deregister`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeregisterRequest {
+    pub url: String,
+}
+
+/// Body of `POST /cluster/heartbeat`, sent periodically by each worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatRequest {
+    /// The worker's base URL, which identifies it.
+    pub url: String,
+    /// What the worker currently stores.
+    #[serde(default)]
+    pub load: StorageStats,
+    /// How the worker's raft loop is keeping up.
+    #[serde(default)]
+    pub raft: Option<RaftHealth>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    /// Has never sent a heartbeat, so is routed on trust.
+    Unknown,
+    Up,
+    /// Missed heartbeats for `HEARTBEAT_TIMEOUT_SECS`; not routed.
+    Down,
+}
+
+/// One worker in `GET /cluster/health`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerHealth {
+    pub url: String,
+    pub status: Liveness,
+    /// Seconds since the last heartbeat, if there was one.
+    pub last_seen_secs: Option<f64>,
+    pub load: Option<StorageStats>,
+    /// Raft health from the last heartbeat, if it reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raft: Option<RaftHealth>,
+    pub routed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterHealth {
+    pub workers: Vec<WorkerHealth>,
+    pub heartbeat_timeout_secs: u64,
+    /// Whether the cluster refuses writes for maintenance.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisterResponse {
+    /// Workers the control node now routes to.
+    pub workers: Vec<String>,
+    /// A registration lapses unless renewed within this many seconds.
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Registration {
+    url: String,
+    capacity: u32,
+    last_heartbeat: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct Heartbeat {
+    last_seen: Instant,
+    load: StorageStats,
+    raft: Option<RaftHealth>,
+    down: bool,
+}
+
+/// Workers known to the control node: the configured `WORKER_HOSTS` plus
+/// those that registered themselves and have not lapsed, less those that
+/// stopped sending heartbeats.
+#[derive(Debug, Clone)]
+pub struct Membership {
+    base: ControlConfig,
+    registered: Vec<Registration>,
+    heartbeats: BTreeMap<String, Heartbeat>,
+}
+
+impl Membership {
+    pub fn new(base: ControlConfig) -> Self {
+        Self { base, registered: Vec::new(), heartbeats: BTreeMap::new() }
+    }
+
+    /// Swaps in a reloaded config, keeping the registrations. A worker that
+    /// is now configured statically stops counting as registered.
+    pub fn set_base(&mut self, base: ControlConfig) {
+        self.base = base;
+        let base = &self.base;
+        self.registered.retain(|registration| !base.worker_urls.contains(&registration.url));
+        self.forget_departed();
+    }
+
+    /// Adds `request`'s worker, or renews it. Returns whether routing changes.
+    pub fn register(&mut self, request: &RegisterRequest, now: Instant) -> Result<bool> {
+        if reqwest::Url::parse(&request.url).map_or(true, |url| url.host_str().is_none()) {
+            return Err(RaftMetricsError::InvalidRequest(format!("'{}' is not a valid worker URL", request.url)));
+        }
+        if !(1..=MAX_WORKER_CAPACITY).contains(&request.capacity) {
+            return Err(RaftMetricsError::InvalidRequest(format!(
+                "capacity must be between 1 and {}",
+                MAX_WORKER_CAPACITY
+            )));
+        }
+        if self.base.worker_urls.contains(&request.url) {
+            return Ok(false);
+        }
+
+        match self.registered.iter_mut().find(|registration| registration.url == request.url) {
+            Some(registration) => {
+                registration.last_heartbeat = now;
+                let changed = registration.capacity != request.capacity;
+                registration.capacity = request.capacity;
+                Ok(changed)
+            }
+            None => {
+                self.registered.push(Registration {
+                    url: request.url.clone(),
+                    capacity: request.capacity,
+                    last_heartbeat: now,
+                });
+                Ok(true)
+            }
+        }
+    }
+
+    /// Removes a registered worker. Configured workers cannot be removed.
+    pub fn deregister(&mut self, url: &str) -> bool {
+        let before = self.registered.len();
+        self.registered.retain(|registration| registration.url != url);
+        self.forget_departed();
+        self.registered.len() != before
+    }
+
+    /// Drops registrations not renewed within `ttl` of `now`, returning
+    /// their URLs.
+    pub fn expire(&mut self, now: Instant, ttl: Duration) -> Vec<String> {
+        let (lapsed, live) = std::mem::take(&mut self.registered)
+            .into_iter()
+            .partition(|registration| now.saturating_duration_since(registration.last_heartbeat) > ttl);
+        self.registered = live;
+        self.forget_departed();
+        lapsed.into_iter().map(|registration: Registration| registration.url).collect()
+    }
+
+    /// Records a heartbeat from a configured or registered worker. Returns
+    /// whether routing changes, which it does when the worker was down.
+    pub fn heartbeat(&mut self, request: &HeartbeatRequest, now: Instant) -> Result<bool> {
+        if !self.is_member(&request.url) {
+            return Err(RaftMetricsError::NoData(format!("{} is not a known worker", request.url)));
+        }
+        let previous = self.heartbeats.insert(
+            request.url.clone(),
+            Heartbeat { last_seen: now, load: request.load.clone(), raft: request.raft.clone(), down: false },
+        );
+        Ok(previous.is_some_and(|heartbeat| heartbeat.down))
+    }
+
+    /// Marks workers whose last heartbeat is older than `timeout` at `now`
+    /// as down, returning the URLs of those newly down.
+    pub fn mark_down(&mut self, now: Instant, timeout: Duration) -> Vec<String> {
+        let mut newly_down = Vec::new();
+        for (url, heartbeat) in &mut self.heartbeats {
+            if !heartbeat.down && now.saturating_duration_since(heartbeat.last_seen) > timeout {
+                heartbeat.down = true;
+                newly_down.push(url.clone());
+            }
+        }
+        newly_down
+    }
+
+    /// Every known worker with its liveness as of `now`, configured workers
+    /// first.
+    pub fn health(&self, now: Instant) -> Vec<WorkerHealth> {
+        let routed = self.routed();
+        self.members()
+            .map(|url| {
+                let heartbeat = self.heartbeats.get(url);
+                WorkerHealth {
+                    url: url.to_string(),
+                    status: match heartbeat {
+                        None => Liveness::Unknown,
+                        Some(heartbeat) if heartbeat.down => Liveness::Down,
+                        Some(_) => Liveness::Up,
+                    },
+                    last_seen_secs: heartbeat.map(|heartbeat| now.saturating_duration_since(heartbeat.last_seen).as_secs_f64()),
+                    load: heartbeat.map(|heartbeat| heartbeat.load.clone()),
+                    raft: heartbeat.and_then(|heartbeat| heartbeat.raft.clone()),
+                    routed: routed.iter().any(|routed| routed == url),
+                }
+            })
+            .collect()
+    }
+
+    /// The config to route by, hashing the same way as the configured one.
+    /// Registered workers follow the configured ones, so configured workers
+    /// keep their partitions. On the weighted ring registrations carry their
+    /// capacity and down workers leave it; under jump hashing every worker
+    /// gets an equal share and down workers keep their slot in
+    /// `down_workers`. Either way no other worker's metrics move when a
+    /// worker goes down. Down workers are still routed when every worker is
+    /// down.
+    pub fn effective(&self) -> ControlConfig {
+        let mut config = self.base.clone();
+        let is_down = |url: &str| self.heartbeats.get(url).is_some_and(|heartbeat| heartbeat.down);
+        let all_down = self.members().all(is_down);
+        config.worker_urls.extend(self.registered.iter().map(|registration| registration.url.clone()));
+        let Some(mut weights) = config.worker_weights.take() else {
+            if !all_down {
+                config.down_workers = config.worker_urls.iter().filter(|url| is_down(url)).cloned().collect();
+            }
+            return config;
+        };
+        weights.extend(self.registered.iter().map(|registration| registration.capacity));
+        if !all_down {
+            let (urls, kept): (Vec<String>, Vec<u32>) = config
+                .worker_urls
+                .into_iter()
+                .zip(weights)
+                .filter(|(url, _)| !is_down(url.as_str()))
+                .unzip();
+            config.worker_urls = urls;
+            weights = kept;
+        }
+        config.worker_weights = Some(weights);
+        config
+    }
+
+    /// Workers that [`Membership::effective`] sends metrics to.
+    pub fn routed(&self) -> Vec<String> {
+        let config = self.effective();
+        config.worker_urls.into_iter().filter(|url| !config.down_workers.contains(url)).collect()
+    }
+
+    fn members(&self) -> impl Iterator<Item = &str> {
+        self.base
+            .worker_urls
+            .iter()
+            .chain(self.registered.iter().map(|registration| &registration.url))
+            .map(String::as_str)
+    }
+
+    fn is_member(&self, url: &str) -> bool {
+        self.members().any(|member| member == url)
+    }
+
+    /// Drops heartbeats of workers that are no longer members.
+    fn forget_departed(&mut self) {
+        let members: Vec<String> = self.members().map(str::to_string).collect();
+        self.heartbeats.retain(|url, _| members.contains(url));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(url: &str, capacity: u32) -> RegisterRequest {
+        RegisterRequest { url: url.to_string(), capacity }
+    }
+
+    #[test]
+    fn test_registrations_extend_config_and_lapse() {
+        let mut base = ControlConfig::from_lookup(|_| None);
+        base.worker_urls = vec!["http://static:8081".to_string()];
+        let mut membership = Membership::new(base.clone());
+        let start = Instant::now();
+        let ttl = Duration::from_secs(30);
+
+        assert!(membership.register(&request("not a url", 1), start).is_err());
+        assert!(membership.register(&request("http://new:8082", 0), start).is_err());
+        assert!(!membership.register(&request("http://static:8081", 1), start).unwrap());
+        assert_eq!(membership.effective(), base);
+
+        assert!(membership.register(&request("http://new:8082", 3), start).unwrap());
+        assert!(membership.register(&request("http://other:8083", 1), start).unwrap());
+        let effective = membership.effective();
+        assert_eq!(effective.worker_urls, ["http://static:8081", "http://new:8082", "http://other:8083"]);
+        // Jump hashing stays jump hashing; only the weighted ring uses capacity
+        assert_eq!(effective.worker_weights, None);
+        assert!(effective.validate().is_ok());
+        let mut weighted = membership.clone();
+        weighted.set_base(ControlConfig { worker_weights: Some(vec![2]), ..base.clone() });
+        assert_eq!(weighted.effective().worker_weights, Some(vec![2, 3, 1]));
+
+        // A heartbeat renews without changing routing
+        let later = start + Duration::from_secs(20);
+        assert!(!membership.register(&request("http://new:8082", 3), later).unwrap());
+        assert_eq!(membership.expire(start + Duration::from_secs(40), ttl), ["http://other:8083"]);
+        assert!(membership.deregister("http://new:8082"));
+        assert!(!membership.deregister("http://static:8081"));
+        assert_eq!(membership.effective(), base);
+    }
+
+    #[test]
+    fn test_workers_missing_heartbeats_leave_routing() {
+        let base = ControlConfig::from_lookup(|key| match key {
+            "WORKER_HOSTS" => Some("http://a:8081,http://b:8082".to_string()),
+            "REPLICA_HOSTS" => Some("1=http://b-copy:9082".to_string()),
+            _ => None,
+        });
+        let mut membership = Membership::new(base.clone());
+        let start = Instant::now();
+        let timeout = Duration::from_secs(15);
+        let beat = |url: &str| HeartbeatRequest { url: url.to_string(), load: StorageStats::default(), raft: None };
+
+        assert!(membership.heartbeat(&beat("http://stranger:9000"), start).is_err());
+        assert!(!membership.heartbeat(&beat("http://a:8081"), start).unwrap());
+        assert!(!membership.heartbeat(&beat("http://b:8082"), start).unwrap());
+
+        // b keeps beating, a goes quiet
+        let later = start + Duration::from_secs(10);
+        assert!(!membership.heartbeat(&beat("http://b:8082"), later).unwrap());
+        assert!(membership.mark_down(later, timeout).is_empty());
+        let timed_out = start + Duration::from_secs(16);
+        assert_eq!(membership.mark_down(timed_out, timeout), ["http://a:8081"]);
+        let effective = membership.effective();
+        assert_eq!(effective.worker_urls, ["http://a:8081", "http://b:8082"]);
+        assert_eq!((effective.worker_weights, effective.down_workers), (None, vec!["http://a:8081".to_string()]));
+        assert_eq!(membership.routed(), ["http://b:8082"]);
+        let mut weighted = membership.clone();
+        weighted.set_base(ControlConfig { worker_weights: Some(vec![1, 1]), ..base.clone() });
+        assert_eq!(weighted.effective().worker_urls, ["http://b:8082"]);
+        assert_eq!(weighted.effective().worker_weights, Some(vec![1]));
+        // b's replica follows b to the partition a left
+        assert_eq!(weighted.effective().replicas_of(&weighted.effective().worker_urls[0]), ["http://b-copy:9082"]);
+        let health = membership.health(timed_out);
+        assert_eq!((health[0].status, health[0].routed), (Liveness::Down, false));
+        assert_eq!((health[1].status, health[1].routed), (Liveness::Up, true));
+
+        // Once every worker is down, all stay routed rather than none
+        assert_eq!(membership.mark_down(start + Duration::from_secs(26), timeout), ["http://b:8082"]);
+        assert_eq!(membership.effective(), base);
+
+        assert!(membership.heartbeat(&beat("http://a:8081"), start + Duration::from_secs(30)).unwrap());
+        assert_eq!(membership.routed(), ["http://a:8081"]);
+    }
+}
@@ -0,0 +1,112 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Instrument;
+
+use crate::{
+    RaftMetricsError,
+    api::slo::SloTracker,
+    metrics::{routing::CONTROL_REQUESTS_TOTAL, status_class, ACTIVE_CONNECTIONS, REQUEST_COUNTER, REQUEST_DURATION, REQUEST_TOTAL},
+    telemetry,
+};
+
+/// Records request count, duration and status class per matched route, and
+/// feeds the duration to the node's SLO tracker.
+///
+/// Installed with `route_layer` so the `MatchedPath` is available and
+/// label cardinality stays bounded by the route table.
+pub async fn track_requests(State(slo): State<Arc<SloTracker>>, request: Request, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().clone();
+
+    REQUEST_COUNTER.inc();
+    ACTIVE_CONNECTIONS.inc();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    ACTIVE_CONNECTIONS.dec();
+    let elapsed = start.elapsed();
+    REQUEST_DURATION
+        .with_label_values(&[&endpoint])
+        .observe(elapsed.as_secs_f64());
+    slo.observe(method.as_str(), &endpoint, elapsed);
+    REQUEST_TOTAL
+        .with_label_values(&[&endpoint, &status_class(response.status().as_u16())])
+        .inc();
+
+    response
+}
+
+/// Runs each request in a server span named after its matched route,
+/// continuing the caller's trace when it sent a `traceparent`. Installed
+/// with `route_layer` next to [`track_requests`].
+pub async fn trace_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().clone();
+    let span = tracing::info_span!(
+        "http.request",
+        otel.kind = "server",
+        otel.name = %format!("{} {}", method, route),
+        http.method = %method,
+        http.route = %route,
+        http.status_code = tracing::field::Empty,
+    );
+    telemetry::set_parent(&span, request.headers());
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
+
+/// Counts requests the control node routes to a worker by matched route and
+/// status class. Installed only on the routed metric endpoints.
+pub async fn count_routed(request: Request, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    CONTROL_REQUESTS_TOTAL
+        .with_label_values(&[&endpoint, &status_class(response.status().as_u16())])
+        .inc();
+    response
+}
+
+/// Admits only requests bearing `Authorization: Bearer <token>`. With no
+/// token configured every request is refused.
+pub async fn require_admin(State(token): State<Option<Arc<str>>>, request: Request, next: Next) -> Response {
+    let Some(token) = token else {
+        return RaftMetricsError::Unauthorized("admin endpoints are disabled".to_string()).into_response();
+    };
+
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => next.run(request).await,
+        _ => RaftMetricsError::Unauthorized("invalid admin token".to_string()).into_response(),
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
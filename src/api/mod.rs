@@ -1,2 +1,36 @@
+pub mod acl;
+pub mod audit;
+pub mod capture;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod consistency;
 pub mod control;
+pub mod deadline;
+pub mod downsample;
+pub mod envelope;
+pub mod etag;
+pub mod forward;
+pub mod forwarding;
+pub mod freshness;
+pub mod info;
+pub mod journal;
+pub mod load;
+pub mod membership;
+pub mod middleware;
+pub mod msgpack;
+pub mod preflight;
+pub mod push;
+pub mod rate_limit;
+pub mod read_cache;
+pub mod read_only;
+pub mod reports;
+pub mod resolve;
+pub mod routing_preview;
+pub mod scrape;
+pub mod serve;
+pub mod shadow;
+pub mod slo;
+pub mod standalone;
+pub mod types;
+pub mod units;
 pub mod worker;
@@ -0,0 +1,110 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header::{ACCEPT, CONTENT_TYPE}, request::Parts, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::convert::Infallible;
+
+use crate::RaftMetricsError;
+
+/// Media type of MessagePack bodies, for agents that find JSON too bulky.
+pub const MSGPACK: &str = "application/msgpack";
+/// Spellings of [`MSGPACK`] that clients send.
+const MSGPACK_MEDIA_TYPES: [&str; 3] = [MSGPACK, "application/x-msgpack", "application/vnd.msgpack"];
+
+fn is_msgpack(media: &str) -> bool {
+    let media = media.split(';').next().unwrap_or("").trim();
+    MSGPACK_MEDIA_TYPES.iter().any(|msgpack| media.eq_ignore_ascii_case(msgpack))
+}
+
+/// A request body read as MessagePack when its `Content-Type` says so, and
+/// as JSON otherwise.
+pub struct JsonOrMsgpack<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for JsonOrMsgpack<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let msgpack = request.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).is_some_and(is_msgpack);
+        if !msgpack {
+            return Json::from_request(request, state).await.map(|Json(value)| Self(value)).map_err(IntoResponse::into_response);
+        }
+        let bytes = Bytes::from_request(request, state).await.map_err(IntoResponse::into_response)?;
+        rmp_serde::from_slice(&bytes)
+            .map(Self)
+            .map_err(|e| RaftMetricsError::InvalidRequest(format!("Invalid MessagePack body: {}", e)).into_response())
+    }
+}
+
+/// The body format a client accepts: MessagePack when its `Accept` header
+/// lists it, JSON otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Msgpack,
+}
+
+impl Format {
+    pub fn accepted(headers: &HeaderMap) -> Self {
+        let msgpack = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(is_msgpack);
+        if msgpack { Format::Msgpack } else { Format::Json }
+    }
+
+    /// `value` as a response body in this format.
+    pub fn respond<T: Serialize>(self, value: T) -> Encoded<T> {
+        Encoded(self, value)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::accepted(&parts.headers))
+    }
+}
+
+/// A response body encoded in the [`Format`] the client accepts.
+pub struct Encoded<T>(pub Format, pub T);
+
+impl<T: Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        match self.0 {
+            Format::Json => Json(self.1).into_response(),
+            // Named fields, so clients decode maps as they would the JSON
+            Format::Msgpack => match rmp_serde::to_vec_named(&self.1) {
+                Ok(body) => ([(CONTENT_TYPE, MSGPACK)], body).into_response(),
+                Err(e) => RaftMetricsError::Internal(format!("Failed to encode MessagePack: {}", e)).into_response(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_msgpack_is_accepted_in_any_spelling() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Format::accepted(&headers), Format::Json);
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json;q=0.5, Application/X-MsgPack"));
+        assert_eq!(Format::accepted(&headers), Format::Msgpack);
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        assert_eq!(Format::accepted(&headers), Format::Json);
+        assert!(is_msgpack("application/msgpack; charset=binary"));
+    }
+}
@@ -0,0 +1,327 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info, warn};
+
+use crate::{
+    Result,
+    config::{ControlConfig, PreflightConfig},
+    metrics::{register_collectors, storage::StorageBackendKind},
+};
+
+/// How long the storage check's trivial query may take.
+const STORAGE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of one check. A node refuses to start when any check fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreflightCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl PreflightCheck {
+    fn new(name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { name: name.into(), status, detail: detail.into() }
+    }
+}
+
+/// Checks run before a node starts serving, served at `GET /admin/preflight`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreflightReport {
+    /// The worst status among `checks`.
+    pub status: CheckStatus,
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl Default for PreflightReport {
+    fn default() -> Self {
+        Self { status: CheckStatus::Pass, checks: Vec::new() }
+    }
+}
+
+impl PreflightReport {
+    pub fn push(&mut self, check: PreflightCheck) {
+        self.status = self.status.max(check.status);
+        self.checks.push(check);
+    }
+
+    pub fn passed(&self) -> bool {
+        self.status != CheckStatus::Fail
+    }
+
+    /// One line per failed check, for [`crate::RaftMetricsError::InvalidConfig`].
+    pub fn failures(&self) -> Vec<String> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+            .map(|check| format!("{}: {}", check.name, check.detail))
+            .collect()
+    }
+
+    /// The report as printed by `--check`.
+    pub fn render(&self) -> String {
+        let mut out = format!("preflight: {:?}\n", self.status).to_uppercase();
+        for check in &self.checks {
+            let status = format!("{:?}", check.status).to_lowercase();
+            let _ = writeln!(out, "  [{:<4}] {}: {}", status, check.name, check.detail);
+        }
+        out
+    }
+
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                CheckStatus::Pass => info!("Preflight {}: {}", check.name, check.detail),
+                CheckStatus::Warn => warn!("Preflight {}: {}", check.name, check.detail),
+                CheckStatus::Fail => error!("Preflight {}: {}", check.name, check.detail),
+            }
+        }
+    }
+}
+
+/// Checks that the directory holding `db_path` accepts new files.
+pub fn check_data_dir(db_path: Option<&Path>) -> PreflightCheck {
+    let Some(db_path) = db_path else {
+        return PreflightCheck::new("data_dir", CheckStatus::Warn, "DB_PATH unset, metrics are kept in memory only");
+    };
+    let dir = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let probe = dir.join(format!(".preflight-{}", uuid::Uuid::new_v4()));
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            PreflightCheck::new("data_dir", CheckStatus::Pass, format!("{} is writable", dir.display()))
+        }
+        Err(e) => PreflightCheck::new("data_dir", CheckStatus::Fail, format!("{} is not writable: {}", dir.display(), e)),
+    }
+}
+
+/// Opens the store read-only and reads from it, then closes it again. A
+/// store not created yet or behind the latest schema is left for startup
+/// to create or migrate, so the check never writes to it.
+pub async fn check_storage(kind: Result<StorageBackendKind>, db_path: Option<&str>) -> PreflightCheck {
+    let result = async {
+        let kind = kind?;
+        let backend = match db_path.filter(|_| kind != StorageBackendKind::Memory) {
+            None => kind.open(None)?,
+            Some(path) if !Path::new(path).exists() => {
+                return Ok((CheckStatus::Pass, format!("no {} store at {} yet, starting creates one", kind.name(), path)));
+            }
+            Some(path) => {
+                let (version, latest) = kind.stored_schema(path)?;
+                if version > latest {
+                    return Err(crate::RaftMetricsError::Migration(format!(
+                        "{} is at schema version {}, newer than the latest supported version {}",
+                        path, version, latest
+                    )));
+                }
+                if version < latest {
+                    return Ok((
+                        CheckStatus::Warn,
+                        format!("{} is at schema version {} of {}, starting migrates it", path, version, latest),
+                    ));
+                }
+                kind.open_read_only(path)?
+            }
+        };
+        tokio::time::timeout(STORAGE_CHECK_TIMEOUT, backend.stats())
+            .await
+            .map_err(|_| crate::RaftMetricsError::Internal("store did not answer in time".to_string()))??;
+        Ok::<_, crate::RaftMetricsError>((CheckStatus::Pass, format!("{} store opened and answered a read", backend.name())))
+    };
+    match result.await {
+        Ok((status, detail)) => PreflightCheck::new("storage", status, detail),
+        Err(e) => PreflightCheck::new("storage", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// Checks that `url` names a host and port, and with `PREFLIGHT_CONNECT`
+/// that something accepts a TCP connection there. An unreachable peer only
+/// warns, since peers often start after this node.
+pub async fn check_address(kind: &str, url: &str, config: &PreflightConfig) -> PreflightCheck {
+    let name = format!("{} {}", kind, url);
+    let parsed = reqwest::Url::parse(url).ok().and_then(|url| {
+        let host = url.host_str()?.to_string();
+        Some(format!("{}:{}", host, url.port_or_known_default()?))
+    });
+    let Some(addr) = parsed else {
+        return PreflightCheck::new(name, CheckStatus::Fail, "not a valid http(s) URL with a host");
+    };
+    if !config.connect {
+        return PreflightCheck::new(name, CheckStatus::Pass, format!("{} parsed, not contacted", addr));
+    }
+    match tokio::time::timeout(config.connect_timeout, TcpStream::connect(&addr)).await {
+        Ok(Ok(_)) => PreflightCheck::new(name, CheckStatus::Pass, format!("{} accepted a connection", addr)),
+        Ok(Err(e)) => PreflightCheck::new(name, CheckStatus::Warn, format!("{} refused a connection: {}", addr, e)),
+        Err(_) => PreflightCheck::new(
+            name,
+            CheckStatus::Warn,
+            format!("{} did not answer within {:?}", addr, config.connect_timeout),
+        ),
+    }
+}
+
+/// Binds `addr`, handing back the listener so startup can serve on it.
+pub async fn check_listen(addr: &str) -> (PreflightCheck, Option<TcpListener>) {
+    bind_checked("listen", addr).await
+}
+
+/// Binds the admin listener at `addr` when an admin port is configured,
+/// recording the outcome in `report`.
+pub async fn check_admin_listen(report: &mut PreflightReport, addr: Option<&str>) -> Option<TcpListener> {
+    let (check, listener) = bind_checked("admin_listen", addr?).await;
+    report.push(check);
+    listener
+}
+
+async fn bind_checked(name: &str, addr: &str) -> (PreflightCheck, Option<TcpListener>) {
+    match TcpListener::bind(addr).await {
+        Ok(listener) => (PreflightCheck::new(name, CheckStatus::Pass, format!("bound {}", addr)), Some(listener)),
+        Err(e) => (PreflightCheck::new(name, CheckStatus::Fail, format!("cannot bind {}: {}", addr, e)), None),
+    }
+}
+
+pub fn check_collectors() -> PreflightCheck {
+    match register_collectors() {
+        Ok(()) => PreflightCheck::new("collectors", CheckStatus::Pass, "prometheus collectors registered"),
+        Err(e) => PreflightCheck::new("collectors", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// Runs a worker's checks, returning the listener for `listen` when it bound.
+pub async fn worker_preflight(listen: &str, db_path: Option<&str>) -> (PreflightReport, Option<TcpListener>) {
+    let mut report = PreflightReport::default();
+    report.push(check_data_dir(db_path.map(Path::new)));
+    report.push(check_storage(StorageBackendKind::from_env(), db_path).await);
+    report.push(check_collectors());
+    let (check, listener) = check_listen(listen).await;
+    report.push(check);
+    (report, listener)
+}
+
+/// Runs the control node's checks against `config`, returning the listener
+/// for `listen` when it bound.
+pub async fn control_preflight(
+    config: &ControlConfig,
+    listen: &str,
+    preflight: &PreflightConfig,
+) -> (PreflightReport, Option<TcpListener>) {
+    let mut report = PreflightReport::default();
+    report.push(match config.validate() {
+        Ok(()) => PreflightCheck::new("config", CheckStatus::Pass, "configuration is valid"),
+        Err(errors) => PreflightCheck::new("config", CheckStatus::Fail, errors.join("; ")),
+    });
+    for url in &config.worker_urls {
+        report.push(check_address("worker", url, preflight).await);
+    }
+    report.push(check_storage(Ok(StorageBackendKind::default()), None).await);
+    report.push(check_collectors());
+    let (check, listener) = check_listen(listen).await;
+    report.push(check);
+    (report, listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwritable_data_dir_fails() {
+        let file = std::env::temp_dir().join(format!("preflight-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, b"").unwrap();
+        // A regular file cannot hold the database, whoever runs the test
+        let check = check_data_dir(Some(&file.join("metrics.db")));
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(check.status, CheckStatus::Fail, "{}", check.detail);
+
+        let writable = std::env::temp_dir().join("metrics.db");
+        assert_eq!(check_data_dir(Some(&writable)).status, CheckStatus::Pass);
+        assert_eq!(check_data_dir(None).status, CheckStatus::Warn);
+    }
+
+    #[tokio::test]
+    async fn test_worker_preflight_reports_unwritable_dir() {
+        let file = std::env::temp_dir().join(format!("preflight-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, b"").unwrap();
+        let db_path = file.join("metrics.db");
+        let (report, listener) = worker_preflight("127.0.0.1:0", db_path.to_str()).await;
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(!report.passed());
+        assert!(listener.is_some());
+        let failed: Vec<&str> = report.checks.iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+            .map(|check| check.name.as_str())
+            .collect();
+        // The store is not created by the check, so only the directory fails
+        assert_eq!(failed, ["data_dir"]);
+        assert!(report.render().starts_with("PREFLIGHT: FAIL\n"));
+    }
+
+    #[cfg(feature = "sqlite-storage")]
+    #[tokio::test]
+    async fn test_storage_check_never_writes_the_store() {
+        let path = std::env::temp_dir().join(format!("preflight-{}.db", uuid::Uuid::new_v4()));
+        let db_path = path.to_str().unwrap();
+        let check = || check_storage(Ok(StorageBackendKind::Sqlite), Some(db_path));
+
+        assert_eq!(check().await.status, CheckStatus::Pass);
+        assert!(!path.exists());
+
+        // A store behind the latest schema is reported rather than migrated
+        rusqlite::Connection::open(&path).unwrap().execute_batch("CREATE TABLE legacy (x INTEGER)").unwrap();
+        assert_eq!(check().await.status, CheckStatus::Warn);
+        assert_eq!(StorageBackendKind::Sqlite.stored_schema(db_path).unwrap().0, 0);
+        std::fs::remove_file(&path).unwrap();
+
+        drop(StorageBackendKind::Sqlite.open(Some(db_path)).unwrap());
+        let before = std::fs::read(&path).unwrap();
+        let current = check().await;
+        assert_eq!(current.status, CheckStatus::Pass, "{}", current.detail);
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert!(!Path::new(&format!("{}-shm", db_path)).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bad_peer_address_fails_and_unreachable_warns() {
+        let connect = PreflightConfig { connect: true, connect_timeout: Duration::from_millis(500) };
+        assert_eq!(check_address("worker", "not a url", &connect).await.status, CheckStatus::Fail);
+        assert_eq!(check_address("worker", "unix:/tmp/worker.sock", &connect).await.status, CheckStatus::Fail);
+
+        // A port nothing listens on once its listener is dropped
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let unreachable = format!("http://{}", closed);
+        assert_eq!(check_address("worker", &unreachable, &connect).await.status, CheckStatus::Warn);
+        let skipped = PreflightConfig { connect: false, ..connect };
+        assert_eq!(check_address("worker", &unreachable, &skipped).await.status, CheckStatus::Pass);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = format!("http://{}", listener.local_addr().unwrap());
+        assert_eq!(check_address("worker", &reachable, &connect).await.status, CheckStatus::Pass);
+
+        let mut config = ControlConfig::from_lookup(|_| None);
+        config.worker_urls = vec![reachable, "http//missing-colon".to_string()];
+        let (report, _) = control_preflight(&config, "127.0.0.1:0", &connect).await;
+        assert_eq!(report.status, CheckStatus::Fail);
+        assert_eq!(report.failures().len(), 2, "{:?}", report.failures());
+    }
+
+    #[tokio::test]
+    async fn test_taken_port_fails() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (check, listener) = check_listen(&taken.local_addr().unwrap().to_string()).await;
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(listener.is_none());
+    }
+}
@@ -0,0 +1,246 @@
+use prometheus::{Encoder, TextEncoder};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{config::units, metrics::{PUSHGATEWAY_FAILURES_TOTAL, REGISTRY}};
+
+const DEFAULT_JOB: &str = "raftmetrics";
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// Longest a single push may take, however long the interval.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where and how often a node pushes [`REGISTRY`] to a Prometheus
+/// Pushgateway, for deployments Prometheus cannot scrape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushConfig {
+    /// Base URL of the Pushgateway, e.g. `http://pushgateway:9091`.
+    pub url: String,
+    pub job: String,
+    pub instance: String,
+    pub interval: Duration,
+    /// Longest wait between attempts while pushes keep failing.
+    pub max_backoff: Duration,
+}
+
+impl PushConfig {
+    /// Reads `PUSHGATEWAY_URL`, `PUSHGATEWAY_JOB`, `PUSHGATEWAY_INSTANCE`,
+    /// `PUSHGATEWAY_INTERVAL_SECS` and `PUSHGATEWAY_MAX_BACKOFF_SECS`.
+    /// `None` unless a URL is set. The instance defaults to `instance`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>, instance: &str) -> Option<Self> {
+        let url = lookup("PUSHGATEWAY_URL").map(|url| url.trim().trim_end_matches('/').to_string()).filter(|url| !url.is_empty())?;
+        let label = |key: &str, default: &str| lookup(key).filter(|value| !value.trim().is_empty()).unwrap_or_else(|| default.to_string());
+        let interval = units::duration(&lookup, "PUSHGATEWAY_INTERVAL_SECS").filter(|interval| !interval.is_zero()).unwrap_or(DEFAULT_INTERVAL);
+        Some(Self {
+            url,
+            job: label("PUSHGATEWAY_JOB", DEFAULT_JOB),
+            instance: label("PUSHGATEWAY_INSTANCE", instance),
+            interval,
+            max_backoff: units::duration(&lookup, "PUSHGATEWAY_MAX_BACKOFF_SECS").unwrap_or(DEFAULT_MAX_BACKOFF).max(interval),
+        })
+    }
+
+    pub fn from_env(instance: &str) -> Option<Self> {
+        Self::from_lookup(|key| std::env::var(key).ok(), instance)
+    }
+
+    /// The grouping key URL pushes replace, e.g.
+    /// `http://pushgateway:9091/metrics/job/raftmetrics/instance/worker-1`.
+    pub fn push_url(&self) -> String {
+        format!("{}/metrics/{}/{}", self.url, label_segment("job", &self.job), label_segment("instance", &self.instance))
+    }
+
+    /// How long to wait after `failures` pushes in a row have failed:
+    /// the interval, doubled per failure up to `max_backoff`.
+    pub fn pause_after(&self, failures: u32) -> Duration {
+        self.interval.checked_mul(1 << failures.min(16)).map_or(self.max_backoff, |pause| pause.min(self.max_backoff))
+    }
+}
+
+/// A grouping label as a path segment. Values the Pushgateway cannot take
+/// in a path, those empty or containing `/`, are sent base64-encoded.
+pub(crate) fn label_segment(name: &str, value: &str) -> String {
+    match value.is_empty() || value.contains('/') {
+        true => format!("{}@base64/{}", name, base64_url(value.as_bytes())),
+        false => format!("{}/{}", name, value),
+    }
+}
+
+/// URL-safe base64 with padding, as the Pushgateway decodes it.
+fn base64_url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    if bytes.is_empty() {
+        return "=".to_string();
+    }
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// Replaces the grouping key's metrics with everything in [`REGISTRY`],
+/// in one request.
+async fn push(client: &reqwest::Client, config: &PushConfig) -> Result<(), String> {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&REGISTRY.gather(), &mut body).map_err(|e| format!("failed to encode metrics: {}", e))?;
+    let response = client
+        .put(config.push_url())
+        .timeout(config.interval.min(PUSH_TIMEOUT))
+        .header(reqwest::header::CONTENT_TYPE, encoder.format_type())
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("pushgateway unreachable: {}", e.without_url()))?;
+    match response.status().is_success() {
+        true => Ok(()),
+        false => Err(format!("pushgateway answered {}", response.status())),
+    }
+}
+
+/// Pushes every `interval`, backing off while pushes fail. Each failure is
+/// counted in `raftmetrics_pushgateway_failures_total`.
+pub async fn run_pusher(config: PushConfig) {
+    let client = reqwest::Client::new();
+    let mut failures = 0;
+    loop {
+        tokio::time::sleep(config.pause_after(failures)).await;
+        match push(&client, &config).await {
+            Ok(()) => failures = 0,
+            Err(e) => {
+                failures += 1;
+                PUSHGATEWAY_FAILURES_TOTAL.inc();
+                warn!("Push to {} failed ({} in a row), retrying in {:?}: {}", config.url, failures, config.pause_after(failures), e);
+            }
+        }
+    }
+}
+
+/// Starts pushing when `PUSHGATEWAY_URL` is set.
+pub fn spawn_pusher(instance: &str) {
+    if let Some(config) = PushConfig::from_env(instance) {
+        info!("Pushing metrics to {} every {:?}", config.push_url(), config.interval);
+        tokio::spawn(run_pusher(config));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, extract::State, http::{HeaderMap, StatusCode, Uri}, routing::put, Router};
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio::sync::mpsc;
+
+    struct Push {
+        at: Instant,
+        path: String,
+        content_type: String,
+        body: String,
+    }
+
+    /// A Pushgateway answering every push with `status`, reporting each on
+    /// the returned channel.
+    async fn mock_pushgateway(status: StatusCode) -> (String, mpsc::UnboundedReceiver<Push>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let router = Router::new()
+            .route(
+                "/metrics/*grouping",
+                put(|State((tx, status)): State<(Arc<mpsc::UnboundedSender<Push>>, StatusCode)>, uri: Uri, headers: HeaderMap, body: Bytes| async move {
+                    let _ = tx.send(Push {
+                        at: Instant::now(),
+                        path: uri.path().to_string(),
+                        content_type: headers[axum::http::header::CONTENT_TYPE].to_str().unwrap().to_string(),
+                        body: String::from_utf8(body.to_vec()).unwrap(),
+                    });
+                    status
+                }),
+            )
+            .with_state((Arc::new(tx), status));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (format!("http://{}", addr), rx)
+    }
+
+    fn config(url: String, interval: Duration) -> PushConfig {
+        let lookup = |key: &str| match key {
+            "PUSHGATEWAY_URL" => Some(format!("{}/", url)),
+            "PUSHGATEWAY_INTERVAL_SECS" => Some(format!("{}ms", interval.as_millis())),
+            "PUSHGATEWAY_MAX_BACKOFF_SECS" => Some(format!("{}ms", interval.as_millis() * 4)),
+            _ => None,
+        };
+        PushConfig::from_lookup(lookup, "worker-1").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_registry_is_pushed_on_the_configured_interval() {
+        crate::metrics::register_collectors().unwrap();
+        crate::metrics::EVICTIONS_TOTAL.inc();
+        let (url, mut pushes) = mock_pushgateway(StatusCode::OK).await;
+        let interval = Duration::from_millis(100);
+        let started = Instant::now();
+        let pusher = tokio::spawn(run_pusher(config(url, interval)));
+
+        let mut last = started;
+        for _ in 0..3 {
+            let push = tokio::time::timeout(Duration::from_secs(5), pushes.recv()).await.unwrap().unwrap();
+            assert!(push.at - last >= interval, "pushed {:?} after the previous one", push.at - last);
+            assert_eq!(push.path, "/metrics/job/raftmetrics/instance/worker-1");
+            assert!(push.content_type.starts_with("text/plain; version=0.0.4"), "{}", push.content_type);
+            assert!(push.body.contains("# TYPE raftmetrics_evictions_total counter"), "{}", push.body);
+            last = push.at;
+        }
+        pusher.abort();
+    }
+
+    #[tokio::test]
+    async fn test_failed_pushes_are_counted_and_backed_off() {
+        crate::metrics::register_collectors().unwrap();
+        let (url, mut pushes) = mock_pushgateway(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let interval = Duration::from_millis(50);
+        let before = PUSHGATEWAY_FAILURES_TOTAL.get();
+        let pusher = tokio::spawn(run_pusher(config(url, interval)));
+
+        let mut at = Vec::new();
+        for _ in 0..4 {
+            at.push(tokio::time::timeout(Duration::from_secs(5), pushes.recv()).await.unwrap().unwrap().at);
+        }
+        pusher.abort();
+        assert!(at[1] - at[0] >= interval * 2, "{:?}", at[1] - at[0]);
+        assert!(at[2] - at[1] >= interval * 4, "{:?}", at[2] - at[1]);
+        // Capped at PUSHGATEWAY_MAX_BACKOFF_SECS
+        assert!(at[3] - at[2] < interval * 8, "{:?}", at[3] - at[2]);
+        assert!(PUSHGATEWAY_FAILURES_TOTAL.get() - before >= 3);
+    }
+
+    #[test]
+    fn test_push_config_defaults_and_grouping_key() {
+        assert_eq!(PushConfig::from_lookup(|_| None, "worker-1"), None);
+        let config = PushConfig::from_lookup(
+            |key| match key {
+                "PUSHGATEWAY_URL" => Some("http://pushgateway:9091".to_string()),
+                "PUSHGATEWAY_INSTANCE" => Some("eu/worker-1".to_string()),
+                _ => None,
+            },
+            "worker-1",
+        )
+        .unwrap();
+        assert_eq!(config.job, "raftmetrics");
+        assert_eq!(config.interval, DEFAULT_INTERVAL);
+        assert_eq!(config.max_backoff, DEFAULT_MAX_BACKOFF);
+        assert_eq!(config.push_url(), "http://pushgateway:9091/metrics/job/raftmetrics/instance@base64/ZXUvd29ya2VyLTE=");
+        assert_eq!(base64_url(b""), "=");
+        assert_eq!(base64_url(b"a/b?"), "YS9iPw==");
+        assert_eq!(config.pause_after(0), DEFAULT_INTERVAL);
+        assert_eq!(config.pause_after(3), DEFAULT_INTERVAL * 8);
+        assert_eq!(config.pause_after(100), DEFAULT_MAX_BACKOFF);
+    }
+}
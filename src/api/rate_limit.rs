@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Samples a tenant's bucket holds when full, in seconds of its rate.
+const BURST_SECS: f64 = 1.0;
+
+/// Per-tenant token buckets the control node admits writes through, with
+/// the samples each tenant sent over the last full second.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    /// Start of the second `admitted` counts samples in.
+    second: Instant,
+    admitted: u64,
+    /// Samples admitted in the second before `second`.
+    last_second: u64,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Self {
+        Self { tokens: f64::INFINITY, refilled: now, second: now, admitted: 0, last_second: 0 }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.second);
+        if elapsed >= Duration::from_secs(1) {
+            self.last_second = if elapsed < Duration::from_secs(2) { self.admitted } else { 0 };
+            self.admitted = 0;
+            self.second = now;
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Whether `tenant` may send `samples` more at `now` under `rate`
+    /// samples a second; `None` admits everything but still counts it. A
+    /// write larger than the bucket is admitted once the bucket is full,
+    /// and leaves it owing the difference.
+    pub fn admit(&self, tenant: &str, rate: Option<f64>, samples: u64, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(tenant.to_string()).or_insert_with(|| Bucket::new(now));
+        bucket.roll(now);
+        if let Some(rate) = rate {
+            let capacity = rate * BURST_SECS;
+            let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+            bucket.refilled = now;
+            if bucket.tokens < (samples as f64).min(capacity) {
+                return false;
+            }
+            bucket.tokens -= samples as f64;
+        }
+        bucket.admitted += samples;
+        true
+    }
+
+    /// Samples admitted for `tenant` over the last full second before
+    /// `now`, if it has sent any since the limiter started.
+    pub fn rate(&self, tenant: &str, now: Instant) -> Option<f64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_mut(tenant)?;
+        bucket.roll(now);
+        Some(bucket.last_second as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_refill_at_the_tenant_rate() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        assert!(limiter.admit("acme", Some(10.0), 10, start));
+        assert!(!limiter.admit("acme", Some(10.0), 1, start));
+        // Other tenants have buckets of their own
+        assert!(limiter.admit("beta", Some(10.0), 10, start));
+        assert!(limiter.admit("gamma", None, 1_000, start));
+
+        let later = start + Duration::from_millis(200);
+        assert!(limiter.admit("acme", Some(10.0), 2, later));
+        assert!(!limiter.admit("acme", Some(10.0), 1, later));
+
+        assert_eq!(limiter.rate("acme", start + Duration::from_millis(1_500)), Some(12.0));
+        assert_eq!(limiter.rate("acme", start + Duration::from_secs(5)), Some(0.0));
+        assert_eq!(limiter.rate("delta", start), None);
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::OwnedMutexGuard;
+use tracing::warn;
+
+use crate::{api::types::MetricValueResponse, config::ReadCacheConfig};
+
+/// A worker's answer to a plain `GET /metrics/:name`.
+#[derive(Debug, Clone)]
+pub struct CachedRead {
+    pub metric: MetricValueResponse,
+    pub etag: Option<String>,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    read: T,
+    expires: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+struct Entries<T> {
+    clock: u64,
+    cached: HashMap<String, Entry<T>>,
+    /// Reads in flight by name, with the ticket of the latest. A write to
+    /// the name drops its ticket so an answer read before the write landed
+    /// is not cached after it.
+    pending: HashMap<String, u64>,
+}
+
+impl<T> Default for Entries<T> {
+    fn default() -> Self {
+        Self { clock: 0, cached: HashMap::new(), pending: HashMap::new() }
+    }
+}
+
+/// Latest values recently read through the control node, served for a
+/// short TTL and evicted least-recently-used beyond the configured size.
+/// Writes and deletes routed through the control node invalidate their
+/// metric. Other reads, such as namespace trees, are cached by a key of
+/// their own and only expire.
+#[derive(Debug)]
+pub struct ReadCache<T = CachedRead> {
+    config: ReadCacheConfig,
+    entries: Mutex<Entries<T>>,
+    /// A lock per name being read from its worker after a miss.
+    flights: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+/// The right to read a name from its worker after a miss, held until the
+/// answer is cached; see [`ReadCache::join_flight`].
+pub struct Flight<'a, T> {
+    cache: &'a ReadCache<T>,
+    name: String,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl<T> Drop for Flight<'_, T> {
+    fn drop(&mut self) {
+        let mut flights = self.cache.flights.lock().unwrap();
+        // Held by the map and this guard alone, so nobody is waiting
+        if flights.get(&self.name).is_some_and(|lock| Arc::strong_count(lock) == 2) {
+            flights.remove(&self.name);
+        }
+    }
+}
+
+impl<T: Clone> ReadCache<T> {
+    pub fn new(config: ReadCacheConfig) -> Self {
+        Self { config, entries: Mutex::new(Entries::default()), flights: Mutex::default() }
+    }
+
+    pub fn config(&self) -> &ReadCacheConfig {
+        &self.config
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.capacity > 0
+    }
+
+    pub fn get(&self, name: &str, now: Instant) -> Option<T> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.cached.get_mut(name).filter(|entry| entry.expires > now)?;
+        entry.last_used = clock;
+        Some(entry.read.clone())
+    }
+
+    /// The cached read of `name` however old it is. Expired entries stay
+    /// until they are evicted or invalidated so a read can fall back to
+    /// them when no worker answers.
+    pub fn get_stale(&self, name: &str) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        entries.cached.get(name).map(|entry| entry.read.clone())
+    }
+
+    /// Starts a read of `name` from its worker, returning the ticket to
+    /// cache its answer with.
+    pub fn begin(&self, name: &str) -> u64 {
+        let mut entries = self.entries.lock().unwrap();
+        // Reads that failed never finished; forget them rather than grow
+        if entries.pending.len() >= self.config.capacity {
+            entries.pending.clear();
+        }
+        entries.clock += 1;
+        let ticket = entries.clock;
+        entries.pending.insert(name.to_string(), ticket);
+        ticket
+    }
+
+    /// Caches `read` unless `name` was written since [`Self::begin`] gave
+    /// out `ticket`.
+    pub fn insert(&self, name: &str, ticket: u64, read: T, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.pending.get(name) != Some(&ticket) {
+            return;
+        }
+        entries.pending.remove(name);
+        if !entries.cached.contains_key(name) && entries.cached.len() >= self.config.capacity {
+            let victim = entries.cached.iter().min_by_key(|(_, entry)| entry.last_used).map(|(name, _)| name.clone());
+            if let Some(victim) = victim {
+                entries.cached.remove(&victim);
+            }
+        }
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.cached.insert(name.to_string(), Entry { read, expires: now + self.config.ttl, last_used });
+    }
+
+    /// Waits until no other read of `name` is in flight and takes its turn,
+    /// so concurrent misses of a name send one read to its worker. Check
+    /// the cache again once this returns, as the read waited on may have
+    /// filled it.
+    pub async fn join_flight(&self, name: &str) -> Flight<'_, T> {
+        let lock = self.flights.lock().unwrap().entry(name.to_string()).or_default().clone();
+        let guard = lock.lock_owned().await;
+        Flight { cache: self, name: name.to_string(), _guard: guard }
+    }
+
+    pub fn invalidate(&self, name: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.cached.remove(name);
+        entries.pending.remove(name);
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.cached.clear();
+        entries.pending.clear();
+    }
+}
+
+/// Most metrics [`HotReads`] counts; beyond it the least read half is
+/// forgotten.
+const MAX_HOT_READS: usize = 10_000;
+
+/// Where the control node keeps its read counts unless `HOT_READS_FILE`
+/// says otherwise.
+pub const DEFAULT_HOT_READS_FILE: &str = "raftmetrics.hot_reads";
+
+/// How many times each metric's latest value was read through the control
+/// node, so a restarted node can prime its cache with the most read. The
+/// counts are saved to a file of `name count` lines and loaded back on
+/// startup.
+#[derive(Debug, Default)]
+pub struct HotReads {
+    path: Option<PathBuf>,
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+impl HotReads {
+    pub fn from_env(default: Option<PathBuf>) -> Self {
+        Self::load(std::env::var("HOT_READS_FILE").ok().map(PathBuf::from).or(default))
+    }
+
+    /// Counts saved at `path`, if it holds any. Lines that do not parse are
+    /// skipped.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut counts = HashMap::new();
+        if let Some(contents) = path.as_ref().and_then(|path| std::fs::read_to_string(path).ok()) {
+            for line in contents.lines() {
+                if let Some((name, count)) = line.rsplit_once(' ').and_then(|(name, count)| Some((name, count.parse().ok()?))) {
+                    counts.insert(name.to_string(), count);
+                }
+            }
+        }
+        Self { path, counts: Mutex::new(counts) }
+    }
+
+    pub fn record(&self, name: &str) {
+        let mut counts = self.counts.lock().unwrap();
+        if !counts.contains_key(name) && counts.len() >= MAX_HOT_READS {
+            let mut kept: Vec<(String, u64)> = counts.drain().collect();
+            kept.sort_unstable_by_key(|(_, count)| std::cmp::Reverse(*count));
+            kept.truncate(MAX_HOT_READS / 2);
+            counts.extend(kept);
+        }
+        *counts.entry(name.to_string()).or_default() += 1;
+    }
+
+    /// The `n` most read metrics, most read first.
+    pub fn hottest(&self, n: usize) -> Vec<String> {
+        let counts = self.counts.lock().unwrap();
+        let mut ranked: Vec<(&String, u64)> = counts.iter().map(|(name, count)| (name, *count)).collect();
+        ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked.into_iter().take(n).map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Writes the counts to the file, if there is one. Failures are logged;
+    /// the counts stay in memory.
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let contents: String = self.counts.lock().unwrap().iter().map(|(name, count)| format!("{} {}\n", name, count)).collect();
+        if let Err(e) = std::fs::write(path, contents) {
+            warn!("Failed to save read counts to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn read(value: f64) -> CachedRead {
+        CachedRead {
+            metric: MetricValueResponse {
+                name: "cpu".to_string(),
+                value,
+                timestamp: 0,
+                worker_id: 0,
+                unit: None,
+                version: None,
+                ack: None,
+                source: None,
+                expires_at: None,
+                state: None,
+            },
+            etag: None,
+        }
+    }
+
+    #[test]
+    fn test_entries_expire_evict_and_skip_reads_raced_by_writes() {
+        let cache = ReadCache::new(ReadCacheConfig { capacity: 2, ttl: Duration::from_secs(1) });
+        let now = Instant::now();
+        for name in ["a", "b"] {
+            let ticket = cache.begin(name);
+            cache.insert(name, ticket, read(1.0), now);
+        }
+        assert!(cache.get("a", now).is_some());
+        let ticket = cache.begin("c");
+        cache.insert("c", ticket, read(1.0), now);
+        // "b" was used least recently
+        assert!(cache.get("b", now).is_none());
+        assert!(cache.get("a", now + Duration::from_secs(1)).is_none());
+        assert!(cache.get_stale("a").is_some());
+
+        let ticket = cache.begin("a");
+        cache.invalidate("a");
+        cache.insert("a", ticket, read(2.0), now);
+        assert!(cache.get("a", now).is_none());
+    }
+
+    #[test]
+    fn test_hot_reads_rank_and_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-hot-reads-{}", std::process::id()));
+        let hot = HotReads::load(Some(path.clone()));
+        for name in ["cpu", "mem", "cpu", "disk io", "cpu", "mem"] {
+            hot.record(name);
+        }
+        assert_eq!(hot.hottest(2), vec!["cpu", "mem"]);
+        hot.save();
+
+        let restarted = HotReads::load(Some(path.clone()));
+        assert_eq!(restarted.hottest(10), vec!["cpu", "mem", "disk io"]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
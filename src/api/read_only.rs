@@ -0,0 +1,84 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::{RaftMetricsError, Result};
+
+/// Routes taking a `POST` body that only read, and so stay open while a
+/// node is read-only. Every other route not answering `GET` is a write.
+pub const READ_POSTS: &[&str] = &["/metrics/query", "/metrics/multi_get", "/metrics/get", "/aggregate/batch", "/histograms/merge", "/query"];
+
+/// Whether a node refuses writes, for maintenance windows. Kept in a file
+/// when it has one, so a restart does not quietly start taking writes.
+#[derive(Debug, Default)]
+pub struct ReadOnly {
+    enabled: AtomicBool,
+    /// Exists exactly while the node is read-only.
+    path: Option<PathBuf>,
+}
+
+impl ReadOnly {
+    /// Read-only if the file at `path` exists.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let enabled = path.as_ref().is_some_and(|path| path.exists());
+        if enabled {
+            warn!("Starting read-only: {} exists", path.as_ref().unwrap().display());
+        }
+        Self { enabled: AtomicBool::new(enabled), path }
+    }
+
+    /// Loads from `READ_ONLY_FILE`, or `default` when unset.
+    pub fn from_env(default: Option<PathBuf>) -> Self {
+        Self::load(std::env::var("READ_ONLY_FILE").ok().map(PathBuf::from).or(default))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Turns read-only mode on or off, persisting the change first so a
+    /// failure leaves the mode as it was.
+    pub fn set(&self, enabled: bool) -> Result<()> {
+        if let Some(path) = &self.path {
+            let persisted = match enabled {
+                true => std::fs::write(path, b""),
+                false => std::fs::remove_file(path).or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                }),
+            };
+            persisted.map_err(|e| {
+                RaftMetricsError::Internal(format!("Failed to persist read-only mode at {}: {}", path.display(), e))
+            })?;
+        }
+        if self.enabled.swap(enabled, Ordering::AcqRel) != enabled {
+            warn!("Read-only mode {}", if enabled { "enabled" } else { "disabled" });
+        }
+        Ok(())
+    }
+}
+
+/// Query of `POST /admin/readonly`.
+#[derive(Debug, Deserialize)]
+pub struct ReadOnlyQuery {
+    pub enabled: bool,
+}
+
+/// Refuses writes with [`RaftMetricsError::ReadOnly`] while the node is
+/// read-only. Installed with `route_layer` so the `MatchedPath` is known.
+pub async fn refuse_writes(State(read_only): State<Arc<ReadOnly>>, request: Request, next: Next) -> Response {
+    let read = matches!(*request.method(), Method::GET | Method::HEAD)
+        || request.extensions().get::<MatchedPath>().is_some_and(|path| READ_POSTS.contains(&path.as_str()));
+    if !read && read_only.enabled() {
+        return RaftMetricsError::ReadOnly("the cluster is read-only for maintenance".to_string()).into_response();
+    }
+    next.run(request).await
+}
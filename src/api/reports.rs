@@ -0,0 +1,673 @@
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        control::{fetch_metric_tree, fetch_stale_page, ControlHandle, ControlState},
+        deadline::Deadline,
+        types::{StaleQuery, TreeQuery, MAX_STALE_PAGE},
+    },
+    metrics::{namespace::{NamespaceNode, NamespaceTree, TreeShape}, AggregateFn, StaleMetric},
+    RaftMetricsError, Result,
+};
+
+/// How often the scheduler looks for reports that are due.
+const REPORT_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// Budget for the reads behind one section of a report.
+const REPORT_QUERY_BUDGET: Duration = Duration::from_secs(30);
+const REPORT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Wait before the first retry of a delivery, doubled for each further one.
+const REPORT_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+/// Most retries a report may ask for; the last waits about 100 seconds.
+pub const MAX_REPORT_RETRIES: u32 = 10;
+
+/// A five-field cron expression (`minute hour day-of-month month
+/// day-of-week`), evaluated in UTC. Each field takes `*`, a number, a range
+/// `a-b`, a step `*/n`, `a/n` or `a-b/n`, or a comma-separated list of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// Sunday is 0.
+    weekdays: u64,
+    /// As in cron, a day matching either day field fires when both are
+    /// restricted; a `*` field leaves the other to decide.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// The values a cron field allows, as bits.
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&step| step > 0)?),
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (from.parse().ok()?, to.parse().ok()?),
+            // `a/n` runs from `a` to the end of the field
+            None if step > 1 => (range.parse().ok()?, max),
+            None => {
+                let at = range.parse().ok()?;
+                (at, at)
+            }
+        };
+        if from < min || to > max || from > to {
+            return None;
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Some(bits)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let invalid = || RaftMetricsError::InvalidRequest(format!("invalid cron expression '{}'", expression));
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let &[minute, hour, day, month, weekday] = fields.as_slice() else { return Err(invalid()) };
+        let weekdays = parse_field(weekday, 0, 7).ok_or_else(invalid)?;
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).ok_or_else(invalid)?,
+            hours: parse_field(hour, 0, 23).ok_or_else(invalid)?,
+            days: parse_field(day, 1, 31).ok_or_else(invalid)?,
+            months: parse_field(month, 1, 12).ok_or_else(invalid)?,
+            // 7 is Sunday too
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    fn fires_on(&self, at: DateTime<Utc>) -> bool {
+        let day = self.days & 1 << at.day() != 0;
+        let weekday = self.weekdays & 1 << at.weekday().num_days_from_sunday() != 0;
+        let either = !self.any_day && !self.any_weekday;
+        self.months & 1 << at.month() != 0 && if either { day || weekday } else { day && weekday }
+    }
+
+    /// The first minute after `after` the schedule fires at, looking up to
+    /// four years ahead; `None` for a schedule that never fires then, like
+    /// `0 0 30 2 *`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut at = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let limit = after + ChronoDuration::days(4 * 366);
+        while at <= limit {
+            if !self.fires_on(at) {
+                at = Utc.from_utc_datetime(&at.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?);
+            } else if self.hours & 1 << at.hour() == 0 {
+                at = at.with_minute(0)? + ChronoDuration::hours(1);
+            } else if self.minutes & 1 << at.minute() == 0 {
+                at += ChronoDuration::minutes(1);
+            } else {
+                return Some(at);
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// The sections as JSON.
+    #[default]
+    Json,
+    /// A markdown table per section, sent as `{"text": ...}` as chat
+    /// webhooks expect.
+    Markdown,
+}
+
+fn default_functions() -> Vec<AggregateFn> {
+    vec![AggregateFn::Count, AggregateFn::Sum, AggregateFn::Avg]
+}
+
+fn default_top() -> usize {
+    10
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_idle_after_secs() -> u64 {
+    86_400
+}
+
+/// A summary of aggregates POSTed to a webhook on a schedule; see
+/// `REPORTS`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportConfig {
+    pub id: String,
+    /// Cron expression, in UTC; see [`CronSchedule`].
+    pub schedule: String,
+    /// Namespace prefixes summarised, as for `GET /metrics/tree`; `""`
+    /// covers every metric.
+    pub patterns: Vec<String>,
+    /// Aggregates shown for each namespace; the first ranks them.
+    #[serde(default = "default_functions")]
+    pub functions: Vec<AggregateFn>,
+    /// Namespaces one level below each pattern listed, highest first.
+    #[serde(default = "default_top")]
+    pub top: usize,
+    pub url: String,
+    #[serde(default)]
+    pub format: ReportFormat,
+    /// Further attempts after a failed delivery.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Metrics without a sample for this long are listed as anomalies.
+    #[serde(default = "default_idle_after_secs")]
+    pub idle_after_secs: u64,
+}
+
+impl ReportConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Err(e) = CronSchedule::parse(&self.schedule) {
+            errors.push(format!("report '{}': {}", self.id, e));
+        }
+        if self.patterns.is_empty() {
+            errors.push(format!("report '{}' must list at least one pattern", self.id));
+        }
+        if self.functions.is_empty() {
+            errors.push(format!("report '{}' must list at least one function", self.id));
+        }
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            errors.push(format!("report '{}' url must be http(s)", self.id));
+        }
+        if self.retries > MAX_REPORT_RETRIES {
+            errors.push(format!("report '{}' retries must be at most {}", self.id, MAX_REPORT_RETRIES));
+        }
+        errors
+    }
+}
+
+/// One namespace's aggregates, in the order of the report's functions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportRow {
+    pub path: String,
+    pub metrics: u64,
+    pub values: Vec<f64>,
+}
+
+impl ReportRow {
+    fn of(node: &NamespaceNode, functions: &[AggregateFn]) -> Self {
+        Self {
+            path: node.path.clone(),
+            metrics: node.rollup.metrics,
+            values: functions.iter().map(|&function| node.rollup.value(function)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSection {
+    pub pattern: String,
+    /// Every metric under the pattern; absent when there are none.
+    pub total: Option<ReportRow>,
+    /// Namespaces one level down, ranked by the first function.
+    pub top: Vec<ReportRow>,
+    /// Metrics under the pattern idle for the report's `idle_after_secs`,
+    /// longest idle first.
+    #[serde(default)]
+    pub anomalies: Vec<StaleMetric>,
+}
+
+impl ReportSection {
+    /// The section for `pattern` from its namespace tree and its idle
+    /// metrics, each cut to the report's `top`.
+    pub fn of(report: &ReportConfig, pattern: &str, tree: NamespaceTree, mut idle: Vec<StaleMetric>) -> Self {
+        let mut top: Vec<ReportRow> = tree.root.iter()
+            .flat_map(|root| &root.children)
+            .map(|node| ReportRow::of(node, &report.functions))
+            .collect();
+        let rank = |row: &ReportRow| row.values.first().copied().unwrap_or_default();
+        top.sort_by(|a, b| rank(b).total_cmp(&rank(a)).then_with(|| a.path.cmp(&b.path)));
+        top.truncate(report.top);
+        idle.sort_by(|a, b| a.last_seen.cmp(&b.last_seen).then_with(|| a.name.cmp(&b.name)));
+        idle.truncate(report.top);
+        Self {
+            pattern: pattern.to_string(),
+            total: tree.root.as_ref().map(|root| ReportRow::of(root, &report.functions)),
+            top,
+            anomalies: idle,
+        }
+    }
+}
+
+/// Body POSTed for [`ReportFormat::Json`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportBody {
+    pub report: String,
+    /// Unix seconds the report is for.
+    pub generated_at: i64,
+    pub functions: Vec<AggregateFn>,
+    pub sections: Vec<ReportSection>,
+}
+
+fn number(value: f64) -> String {
+    let text = format!("{:.3}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn function_name(function: AggregateFn) -> &'static str {
+    match function {
+        AggregateFn::Avg => "avg",
+        AggregateFn::Sum => "sum",
+        AggregateFn::Min => "min",
+        AggregateFn::Max => "max",
+        AggregateFn::Count => "count",
+    }
+}
+
+/// The report as markdown: a table per pattern, its total first, then its
+/// idle metrics.
+pub fn render_markdown(report: &ReportConfig, sections: &[ReportSection], at: DateTime<Utc>) -> String {
+    let mut text = format!("**Report `{}`** ({})\n", report.id, at.format("%Y-%m-%d %H:%M UTC"));
+    for section in sections {
+        let _ = write!(text, "\n`{}`\n", if section.pattern.is_empty() { "*" } else { &section.pattern });
+        let Some(total) = &section.total else {
+            text.push_str("_no metrics_\n");
+            continue;
+        };
+        let functions: Vec<&str> = report.functions.iter().map(|&function| function_name(function)).collect();
+        let _ = writeln!(text, "| namespace | metrics | {} |", functions.join(" | "));
+        let _ = writeln!(text, "|---|---:|{}", "---:|".repeat(functions.len()));
+        for row in std::iter::once(total).chain(&section.top) {
+            let values: Vec<String> = row.values.iter().map(|&value| number(value)).collect();
+            let _ = writeln!(text, "| {} | {} | {} |", row.path, row.metrics, values.join(" | "));
+        }
+        if !section.anomalies.is_empty() {
+            text.push_str("\nIdle:\n");
+        }
+        for metric in &section.anomalies {
+            let since = Utc.timestamp_opt(metric.last_seen, 0).single().map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string());
+            let _ = writeln!(text, "- `{}` since {}", metric.name, since.unwrap_or_else(|| metric.last_seen.to_string()));
+        }
+    }
+    text
+}
+
+fn render(report: &ReportConfig, sections: Vec<ReportSection>, at: DateTime<Utc>) -> serde_json::Value {
+    match report.format {
+        ReportFormat::Markdown => serde_json::json!({ "text": render_markdown(report, &sections, at) }),
+        ReportFormat::Json => serde_json::to_value(ReportBody {
+            report: report.id.clone(),
+            generated_at: at.timestamp(),
+            functions: report.functions.clone(),
+            sections,
+        })
+        .unwrap_or_default(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    Schedule,
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportRun {
+    pub trigger: RunTrigger,
+    /// Unix seconds.
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub status: RunStatus,
+    /// Deliveries attempted; 0 when the report could not be built.
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A report as listed by `GET /reports`. The webhook URL is left out, as
+/// chat webhooks embed their credentials in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSummary {
+    pub id: String,
+    pub schedule: String,
+    pub patterns: Vec<String>,
+    pub format: ReportFormat,
+    pub running: bool,
+    /// Unix seconds of the next scheduled run.
+    pub next_run: Option<i64>,
+    pub last_run: Option<ReportRun>,
+}
+
+#[derive(Debug, Default)]
+struct RunState {
+    running: bool,
+    last_run: Option<ReportRun>,
+}
+
+/// Run state of the configured reports. Definitions are read from the
+/// current config on every check, so a reload reschedules them.
+#[derive(Debug)]
+pub struct Reports {
+    runs: Mutex<HashMap<String, RunState>>,
+    client: reqwest::Client,
+}
+
+impl Default for Reports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reports {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REPORT_REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self { runs: Mutex::default(), client }
+    }
+
+    /// Marks `id` running unless it already is. The claim clears it again
+    /// when dropped, so a run that panics or is cancelled never blocks the
+    /// next one.
+    fn claim<'a>(&'a self, id: &'a str) -> Option<Claim<'a>> {
+        let mut runs = self.runs.lock().unwrap();
+        let run = runs.entry(id.to_string()).or_default();
+        if std::mem::replace(&mut run.running, true) {
+            return None;
+        }
+        Some(Claim { reports: self, id })
+    }
+
+    fn finish(&self, id: &str, last_run: ReportRun) {
+        let mut runs = self.runs.lock().unwrap();
+        *runs.entry(id.to_string()).or_default() = RunState { running: false, last_run: Some(last_run) };
+    }
+
+    pub fn summaries(&self, reports: &[ReportConfig], now: DateTime<Utc>) -> Vec<ReportSummary> {
+        let runs = self.runs.lock().unwrap();
+        reports
+            .iter()
+            .map(|report| {
+                let run = runs.get(&report.id);
+                ReportSummary {
+                    id: report.id.clone(),
+                    schedule: report.schedule.clone(),
+                    patterns: report.patterns.clone(),
+                    format: report.format,
+                    running: run.is_some_and(|run| run.running),
+                    next_run: CronSchedule::parse(&report.schedule).ok()
+                        .and_then(|schedule| schedule.next_after(now))
+                        .map(|at| at.timestamp()),
+                    last_run: run.and_then(|run| run.last_run.clone()),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A report's claim on running; see [`Reports::claim`].
+struct Claim<'a> {
+    reports: &'a Reports,
+    id: &'a str,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        if let Some(run) = self.reports.runs.lock().unwrap_or_else(|e| e.into_inner()).get_mut(self.id) {
+            run.running = false;
+        }
+    }
+}
+
+async fn gather(state: &ControlState, report: &ReportConfig) -> Result<Vec<ReportSection>> {
+    let mut sections = Vec::with_capacity(report.patterns.len());
+    for pattern in &report.patterns {
+        let query = TreeQuery { prefix: pattern.clone(), separator: None, depth: Some(1), max_nodes: None };
+        let shape = query.shape()?;
+        let tree = fetch_metric_tree(state, Deadline::after(REPORT_QUERY_BUDGET), query).await?;
+        let idle = idle_under(state, report, &shape).await?;
+        sections.push(ReportSection::of(report, pattern, tree, idle));
+    }
+    Ok(sections)
+}
+
+/// Metrics under `shape` idle for the report's `idle_after_secs`, paging
+/// the cluster's idle listing, which runs in name order, from just before
+/// the prefix until past it.
+async fn idle_under(state: &ControlState, report: &ReportConfig, shape: &TreeShape) -> Result<Vec<StaleMetric>> {
+    let deadline = Deadline::after(REPORT_QUERY_BUDGET);
+    let prefix = shape.prefix.as_str();
+    let mut after = prefix.char_indices().last().map(|(i, _)| prefix[..i].to_string()).filter(|after| !after.is_empty());
+    let mut idle = Vec::new();
+    loop {
+        let query = StaleQuery { idle_seconds: report.idle_after_secs, after, limit: Some(MAX_STALE_PAGE) };
+        let page = fetch_stale_page(state, deadline, query).await?;
+        let past = page.metrics.last().is_some_and(|metric| metric.name.as_str() > prefix && !metric.name.starts_with(prefix));
+        idle.extend(page.metrics.into_iter().filter(|metric| shape.contains(&metric.name)));
+        match page.next {
+            Some(next) if !past => after = Some(next),
+            _ => return Ok(idle),
+        }
+    }
+}
+
+/// POSTs `body`, retrying with backoff. Returns the attempts made and the
+/// last error if none succeeded.
+async fn deliver(client: &reqwest::Client, report: &ReportConfig, body: &serde_json::Value) -> (u32, Option<String>) {
+    let mut error = String::new();
+    for attempt in 1..=report.retries + 1 {
+        if attempt > 1 {
+            tokio::time::sleep(REPORT_RETRY_BACKOFF * 2u32.pow(attempt - 2)).await;
+        }
+        match client.post(&report.url).json(body).send().await {
+            Ok(response) if response.status().is_success() => return (attempt, None),
+            Ok(response) => error = format!("webhook answered {}", response.status()),
+            // The URL is left out of errors, which `GET /reports` serves
+            Err(e) => error = format!("webhook unreachable: {}", e.without_url()),
+        }
+        warn!("Report '{}' delivery failed (attempt {}): {}", report.id, attempt, error);
+    }
+    (report.retries + 1, Some(error))
+}
+
+/// Builds `report` as of `at` and delivers it, unless a run of it is
+/// already going. The outcome is kept for `GET /reports`.
+pub async fn run_report(state: &ControlState, report: &ReportConfig, trigger: RunTrigger, at: DateTime<Utc>) -> Result<ReportRun> {
+    let Some(_claim) = state.reports.claim(&report.id) else {
+        return Err(RaftMetricsError::Conflict(format!("report '{}' is already running", report.id)));
+    };
+    let started_at = Utc::now().timestamp();
+    let (attempts, error) = match gather(state, report).await {
+        Ok(sections) => deliver(&state.reports.client, report, &render(report, sections, at)).await,
+        Err(e) => (0, Some(e.to_string())),
+    };
+    let run = ReportRun {
+        trigger,
+        started_at,
+        finished_at: Utc::now().timestamp(),
+        status: if error.is_none() { RunStatus::Succeeded } else { RunStatus::Failed },
+        attempts,
+        error,
+    };
+    match &run.error {
+        None => info!("Report '{}' delivered", report.id),
+        Some(e) => warn!("Report '{}' failed: {}", report.id, e),
+    }
+    state.reports.finish(&report.id, run.clone());
+    Ok(run)
+}
+
+/// Starts each report whose schedule fires in `(since, until]`, once
+/// however many times it fired there. Returns the ids started.
+pub fn fire_due(state: &ControlState, since: DateTime<Utc>, until: DateTime<Utc>) -> Vec<String> {
+    let mut started = Vec::new();
+    for report in &state.config.reports {
+        let Ok(schedule) = CronSchedule::parse(&report.schedule) else { continue };
+        let Some(at) = schedule.next_after(since).filter(|at| *at <= until) else { continue };
+        started.push(report.id.clone());
+        let (state, report) = (state.clone(), report.clone());
+        tokio::spawn(async move {
+            if let Err(e) = run_report(&state, &report, RunTrigger::Schedule, at).await {
+                warn!("Skipping scheduled run: {}", e);
+            }
+        });
+    }
+    started
+}
+
+/// Checks for due reports every [`REPORT_CHECK_INTERVAL`].
+pub fn schedule_reports(handle: ControlHandle) {
+    tokio::spawn(async move {
+        let mut since = Utc::now();
+        loop {
+            tokio::time::sleep(REPORT_CHECK_INTERVAL).await;
+            let now = Utc::now();
+            fire_due(&handle.snapshot(), since, now);
+            since = now;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{namespace::TreeShape, MetricAggregate, Summation};
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    fn report(format: ReportFormat) -> ReportConfig {
+        ReportConfig {
+            id: "daily".to_string(),
+            schedule: "0 9 * * 1-5".to_string(),
+            patterns: vec!["svc".to_string(), "batch".to_string()],
+            functions: vec![AggregateFn::Sum, AggregateFn::Avg],
+            top: 2,
+            url: "http://example".to_string(),
+            format,
+            retries: 2,
+            idle_after_secs: 86_400,
+        }
+    }
+
+    #[test]
+    fn test_cron_schedule_finds_the_next_run() {
+        let weekdays = CronSchedule::parse("0 9 * * 1-5").unwrap();
+        // Friday 2026-10-16 after nine: next is Monday
+        assert_eq!(weekdays.next_after(at("2026-10-16T09:00:00Z")), Some(at("2026-10-19T09:00:00Z")));
+        assert_eq!(weekdays.next_after(at("2026-10-16T08:59:30Z")), Some(at("2026-10-16T09:00:00Z")));
+
+        let quarter_hours = CronSchedule::parse("*/15 * * * *").unwrap();
+        assert_eq!(quarter_hours.next_after(at("2026-10-15T23:50:00Z")), Some(at("2026-10-16T00:00:00Z")));
+        // Either day field matches once both are restricted
+        let either = CronSchedule::parse("30 6 1 * 0").unwrap();
+        assert_eq!(either.next_after(at("2026-10-15T00:00:00Z")), Some(at("2026-10-18T06:30:00Z")));
+        assert_eq!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(at("2026-10-15T00:00:00Z")), None);
+
+        for bad in ["0 9 * *", "60 * * * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"] {
+            assert!(CronSchedule::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_markdown_report_snapshot() {
+        let aggregate = |values: &[f64]| {
+            let mut aggregate = MetricAggregate { min: values[0], max: values[0], ..Default::default() };
+            for &value in values {
+                aggregate.add(value, Summation::Naive);
+            }
+            aggregate
+        };
+        let (a, b, c) = (aggregate(&[1.0, 2.0]), aggregate(&[10.0]), aggregate(&[0.5]));
+        let report = report(ReportFormat::Markdown);
+        let shape = |prefix: &str| TreeShape { prefix: prefix.to_string(), separator: ".".to_string(), depth: 1, max_nodes: 100 };
+        let metrics = [("svc.api.latency", &a), ("svc.db.latency", &b), ("svc.cache.hits", &c)];
+        let idle = |name: &str, last_seen: i64| StaleMetric { name: name.to_string(), first_seen: 0, last_seen };
+        let sections = vec![
+            ReportSection::of(&report, "svc", NamespaceTree::build(&shape("svc"), metrics), vec![
+                idle("svc.cache.hits", at("2026-10-14T08:00:00Z").timestamp()),
+                idle("svc.api.latency", at("2026-10-13T22:30:00Z").timestamp()),
+                idle("svc.db.latency", at("2026-10-14T09:00:00Z").timestamp()),
+            ]),
+            ReportSection::of(&report, "batch", NamespaceTree::build(&shape("batch"), metrics), Vec::new()),
+        ];
+
+        assert_eq!(render_markdown(&report, &sections, at("2026-10-15T09:00:00Z")), "\
+**Report `daily`** (2026-10-15 09:00 UTC)
+
+`svc`
+| namespace | metrics | sum | avg |
+|---|---:|---:|---:|
+| svc | 3 | 13.5 | 3.375 |
+| svc.db | 1 | 10 | 10 |
+| svc.api | 1 | 3 | 1.5 |
+
+Idle:
+- `svc.api.latency` since 2026-10-13 22:30 UTC
+- `svc.cache.hits` since 2026-10-14 08:00 UTC
+
+`batch`
+_no metrics_
+");
+    }
+
+    #[test]
+    fn test_overlapping_runs_are_refused() {
+        let reports = Reports::new();
+        let daily = reports.claim("daily").unwrap();
+        assert!(reports.claim("daily").is_none());
+        let weekly = reports.claim("weekly");
+        assert!(weekly.is_some());
+        reports.finish("daily", ReportRun {
+            trigger: RunTrigger::Manual,
+            started_at: 1,
+            finished_at: 2,
+            status: RunStatus::Succeeded,
+            attempts: 1,
+            error: None,
+        });
+        drop(daily);
+        let _daily = reports.claim("daily").unwrap();
+
+        let summaries = reports.summaries(&[report(ReportFormat::Json)], at("2026-10-15T10:00:00Z"));
+        assert!(summaries[0].running);
+        assert_eq!(summaries[0].next_run, Some(at("2026-10-16T09:00:00Z").timestamp()));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_run_releases_the_report() {
+        let reports = Reports::new();
+        let run = async {
+            let _claim = reports.claim("daily").unwrap();
+            std::future::pending::<()>().await;
+        };
+        // The client went away mid-run
+        assert!(tokio::time::timeout(Duration::from_millis(10), run).await.is_err());
+        assert!(reports.claim("daily").is_some());
+    }
+
+    #[test]
+    fn test_validate_caps_retries() {
+        let report = report(ReportFormat::Json);
+        assert!(report.validate().is_empty());
+        let greedy = ReportConfig { retries: MAX_REPORT_RETRIES + 1, ..report };
+        assert_eq!(greedy.validate(), ["report 'daily' retries must be at most 10"]);
+    }
+}
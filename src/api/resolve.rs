@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::metrics::PEER_ADDRESS_CHANGES_TOTAL;
+
+/// Lookups tried before falling back to a host's last good addresses.
+const LOOKUP_ATTEMPTS: u32 = 3;
+/// Wait before the second lookup, doubled before each one after.
+const LOOKUP_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Turns a host name into addresses.
+#[async_trait]
+pub trait HostLookup: Send + Sync {
+    async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>>;
+}
+
+/// The system resolver.
+pub struct SystemLookup;
+
+#[async_trait]
+impl HostLookup for SystemLookup {
+    async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        Ok(tokio::net::lookup_host((host, 0)).await?.map(|addr| addr.ip()).collect())
+    }
+}
+
+struct Resolved {
+    addrs: Vec<IpAddr>,
+    /// Set once a connection to the host failed, so the next one looks it
+    /// up again.
+    stale: bool,
+}
+
+/// Resolves worker host names for a client, keeping each host's addresses
+/// until a connection to it fails. Worker pods get new addresses when they
+/// restart, so a failed host is looked up again, with retries, on the next
+/// connection. While lookups fail or come back empty the last good
+/// addresses are kept and looked up again on every connection after.
+/// Addresses given as IPs never reach the resolver.
+#[derive(Clone)]
+pub struct PeerResolver {
+    lookup: Arc<dyn HostLookup>,
+    resolved: Arc<Mutex<HashMap<String, Resolved>>>,
+}
+
+impl std::fmt::Debug for PeerResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PeerResolver").finish_non_exhaustive()
+    }
+}
+
+impl PeerResolver {
+    pub fn new(lookup: Arc<dyn HostLookup>) -> Self {
+        Self { lookup, resolved: Arc::default() }
+    }
+
+    /// Has the next connection to `host` look it up again.
+    pub fn invalidate(&self, host: &str) {
+        if let Some(resolved) = self.resolved.lock().unwrap().get_mut(host) {
+            resolved.stale = true;
+        }
+    }
+
+    /// `host`'s addresses, looked up unless a fresh answer is cached.
+    pub async fn resolve_host(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        if let Some(resolved) = self.resolved.lock().unwrap().get(host).filter(|resolved| !resolved.stale) {
+            return Ok(resolved.addrs.clone());
+        }
+
+        let mut error = io::Error::new(io::ErrorKind::NotFound, format!("no addresses for {}", host));
+        for attempt in 0..LOOKUP_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(LOOKUP_BACKOFF * (1 << (attempt - 1))).await;
+            }
+            match self.lookup.lookup(host).await {
+                Ok(mut addrs) if !addrs.is_empty() => {
+                    addrs.sort();
+                    addrs.dedup();
+                    self.remember(host, &addrs);
+                    return Ok(addrs);
+                }
+                Ok(_) => error = io::Error::new(io::ErrorKind::NotFound, format!("no addresses for {}", host)),
+                Err(e) => error = e,
+            }
+        }
+
+        match self.resolved.lock().unwrap().get(host) {
+            Some(resolved) => {
+                warn!("Could not resolve {} ({}), keeping {:?}", host, error, resolved.addrs);
+                Ok(resolved.addrs.clone())
+            }
+            None => Err(error),
+        }
+    }
+
+    fn remember(&self, host: &str, addrs: &[IpAddr]) {
+        let mut resolved = self.resolved.lock().unwrap();
+        let previous = resolved.insert(host.to_string(), Resolved { addrs: addrs.to_vec(), stale: false });
+        if let Some(previous) = previous.filter(|previous| previous.addrs != addrs) {
+            info!("{} moved from {:?} to {:?}", host, previous.addrs, addrs);
+            PEER_ADDRESS_CHANGES_TOTAL.with_label_values(&[host]).inc();
+        }
+    }
+}
+
+impl Resolve for PeerResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.resolve_host(name.as_str()).await?;
+            // The connector fills in the URL's port
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::forward::ForwardPool, config::HttpPoolConfig};
+    use axum::{routing::get, Router};
+
+    /// Answers from a table the test rewrites as it goes.
+    #[derive(Default)]
+    struct FakeLookup {
+        answers: Mutex<HashMap<String, Vec<IpAddr>>>,
+        lookups: std::sync::atomic::AtomicUsize,
+    }
+
+    impl FakeLookup {
+        fn answer(&self, host: &str, addrs: &[&str]) {
+            let addrs = addrs.iter().map(|addr| addr.parse().unwrap()).collect();
+            self.answers.lock().unwrap().insert(host.to_string(), addrs);
+        }
+    }
+
+    #[async_trait]
+    impl HostLookup for FakeLookup {
+        async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+            self.lookups.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(self.answers.lock().unwrap().get(host).cloned().unwrap_or_default())
+        }
+    }
+
+    /// Serves `GET /whoami` with `name` on `ip:port`.
+    async fn serve(ip: &str, port: u16, name: &'static str) -> tokio::task::JoinHandle<()> {
+        let listener = tokio::net::TcpListener::bind((ip, port)).await.unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/whoami", get(move || async move { name }))).await.unwrap();
+        })
+    }
+
+    async fn whoami(pool: &ForwardPool, url: &str) -> Option<String> {
+        pool.send(pool.client().get(url)).await.ok()?.text().await.ok()
+    }
+
+    #[tokio::test]
+    async fn test_requests_follow_a_worker_to_its_new_address() {
+        crate::metrics::register_collectors().unwrap();
+        let lookup = Arc::new(FakeLookup::default());
+        lookup.answer("worker-1.test", &["127.0.0.1"]);
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let old = serve("127.0.0.1", port, "old").await;
+        let _new = serve("127.0.0.2", port, "new").await;
+
+        // No idle connections, so every request connects and resolves
+        let mut config = HttpPoolConfig::read_defaults();
+        config.pool_max_idle_per_host = 0;
+        let pool = ForwardPool::with_lookup("read", &config, lookup.clone()).unwrap();
+        let url = format!("http://worker-1.test:{}/whoami", port);
+        assert_eq!(whoami(&pool, &url).await.as_deref(), Some("old"));
+        assert_eq!(whoami(&pool, &url).await.as_deref(), Some("old"));
+        assert_eq!(lookup.lookups.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // The worker restarts elsewhere, and DNS answers nothing for a while
+        let changes = PEER_ADDRESS_CHANGES_TOTAL.with_label_values(&["worker-1.test"]).get();
+        old.abort();
+        let _ = old.await;
+        lookup.answer("worker-1.test", &[]);
+        assert_eq!(whoami(&pool, &url).await, None);
+        assert_eq!(whoami(&pool, &url).await, None);
+
+        lookup.answer("worker-1.test", &["127.0.0.2"]);
+        assert_eq!(whoami(&pool, &url).await.as_deref(), Some("new"));
+        assert_eq!(whoami(&pool, &url).await.as_deref(), Some("new"));
+        assert_eq!(PEER_ADDRESS_CHANGES_TOTAL.with_label_values(&["worker-1.test"]).get(), changes + 1);
+    }
+
+    #[tokio::test]
+    async fn test_last_good_addresses_outlive_empty_answers() {
+        let lookup = Arc::new(FakeLookup::default());
+        let resolver = PeerResolver::new(lookup.clone());
+        assert_eq!(resolver.resolve_host("worker-2.test").await.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        lookup.answer("worker-2.test", &["10.0.0.7", "10.0.0.5", "10.0.0.7"]);
+        let good: Vec<IpAddr> = vec!["10.0.0.5".parse().unwrap(), "10.0.0.7".parse().unwrap()];
+        assert_eq!(resolver.resolve_host("worker-2.test").await.unwrap(), good);
+
+        lookup.answer("worker-2.test", &[]);
+        resolver.invalidate("worker-2.test");
+        let before = lookup.lookups.load(std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(resolver.resolve_host("worker-2.test").await.unwrap(), good);
+        assert_eq!(lookup.lookups.load(std::sync::atomic::Ordering::Relaxed) - before, LOOKUP_ATTEMPTS as usize);
+        // Still stale, so the next connection tries DNS again
+        assert_eq!(resolver.resolve_host("worker-2.test").await.unwrap(), good);
+        assert_eq!(lookup.lookups.load(std::sync::atomic::Ordering::Relaxed) - before, 2 * LOOKUP_ATTEMPTS as usize);
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{normalize_worker_url, ControlConfig},
+    partitioning::Router,
+    RaftMetricsError,
+    Result,
+};
+
+/// Most metric names a preview covers; known names beyond it are left out.
+pub const MAX_PREVIEW_NAMES: usize = 100_000;
+
+/// Body of `POST /admin/routing_preview`: the proposed workers, as a list
+/// of URLs or a count, and the names to route.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RoutingPreviewRequest {
+    #[serde(default)]
+    pub worker_urls: Option<Vec<String>>,
+    /// Keeps the current workers up to this many, adding placeholders.
+    #[serde(default)]
+    pub worker_count: Option<usize>,
+    /// Weights for the proposed workers under weighted routing; workers
+    /// keep their current weight, and new ones get 1, when unset.
+    #[serde(default)]
+    pub worker_weights: Option<Vec<u32>>,
+    /// Names to route; every metric the workers hold when unset.
+    #[serde(default)]
+    pub names: Option<Vec<String>>,
+}
+
+impl RoutingPreviewRequest {
+    /// `current` with the workers replaced by the proposed ones.
+    pub fn proposed_config(&self, current: &ControlConfig) -> Result<ControlConfig> {
+        let worker_urls: Vec<String> = match (&self.worker_urls, self.worker_count) {
+            (Some(urls), None) => urls.iter().map(|url| normalize_worker_url(url)).collect(),
+            (None, Some(count)) => (0..count)
+                .map(|i| current.worker_urls.get(i).cloned().unwrap_or_else(|| format!("http://proposed-worker-{}", i)))
+                .collect(),
+            _ => {
+                return Err(RaftMetricsError::InvalidRequest(
+                    "give exactly one of worker_urls or worker_count".to_string(),
+                ))
+            }
+        };
+        let worker_weights = current.worker_weights.as_ref().map(|weights| match &self.worker_weights {
+            Some(proposed) => proposed.clone(),
+            None => worker_urls
+                .iter()
+                .map(|url| current.worker_urls.iter().position(|current| current == url).map_or(1, |i| weights[i]))
+                .collect(),
+        });
+        if worker_weights.as_ref().is_some_and(|weights| weights.len() != worker_urls.len()) {
+            return Err(RaftMetricsError::InvalidRequest(
+                "worker_weights must give one weight per proposed worker".to_string(),
+            ));
+        }
+
+        let proposed = ControlConfig { worker_urls, worker_weights, ..current.clone() };
+        proposed.validate().map_err(RaftMetricsError::InvalidConfig)?;
+        Ok(proposed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyRoute {
+    pub name: String,
+    pub current: usize,
+    pub proposed: usize,
+}
+
+/// How routing `names` changes between two routers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingPreview {
+    pub current_workers: usize,
+    pub proposed_workers: usize,
+    pub keys: Vec<KeyRoute>,
+    pub total: usize,
+    /// Keys whose partition changes.
+    pub moved: usize,
+    /// Whether known names past [`MAX_PREVIEW_NAMES`] were left out.
+    pub truncated: bool,
+}
+
+impl RoutingPreview {
+    pub fn compare(names: Vec<String>, current: &dyn Router, proposed: &dyn Router, truncated: bool) -> Self {
+        let keys: Vec<KeyRoute> = names
+            .into_iter()
+            .map(|name| KeyRoute { current: current.route(&name), proposed: proposed.route(&name), name })
+            .collect();
+        Self {
+            current_workers: current.worker_count(),
+            proposed_workers: proposed.worker_count(),
+            total: keys.len(),
+            moved: keys.iter().filter(|key| key.current != key.proposed).count(),
+            keys,
+            truncated,
+        }
+    }
+}
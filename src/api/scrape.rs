@@ -0,0 +1,94 @@
+use axum::{
+    http::{header::{ACCEPT, CONTENT_TYPE}, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use prometheus::{proto::MetricFamily, Encoder, ProtobufEncoder, TextEncoder};
+
+use crate::{metrics::REGISTRY, RaftMetricsError};
+
+/// Media types that select the protobuf exposition format.
+const PROTOBUF_MEDIA_TYPES: [&str; 2] = ["application/vnd.google.protobuf", "application/x-protobuf"];
+
+fn wants_protobuf(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            let media = media.split(';').next().unwrap_or("").trim();
+            PROTOBUF_MEDIA_TYPES.iter().any(|protobuf| media.eq_ignore_ascii_case(protobuf))
+        })
+}
+
+/// Serves [`REGISTRY`] for Prometheus: delimited protobuf when the client
+/// accepts it, the text format otherwise.
+pub async fn prometheus_scrape(headers: HeaderMap) -> Response {
+    encode_families(&headers, &REGISTRY.gather())
+}
+
+/// Serves `families` in the format `headers` ask for; see
+/// [`prometheus_scrape`].
+pub fn encode_families(headers: &HeaderMap, families: &[MetricFamily]) -> Response {
+    let mut body = Vec::new();
+    let encoded = if wants_protobuf(headers) {
+        let encoder = ProtobufEncoder::new();
+        encoder.encode(families, &mut body).map(|_| encoder.format_type().to_string())
+    } else {
+        let encoder = TextEncoder::new();
+        encoder.encode(families, &mut body).map(|_| encoder.format_type().to_string())
+    };
+    match encoded {
+        Ok(content_type) => ([(CONTENT_TYPE, content_type)], body).into_response(),
+        Err(e) => RaftMetricsError::Internal(format!("Failed to encode metrics: {}", e)).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use protobuf::Message;
+    use tower::ServiceExt;
+
+    async fn scrape(accept: Option<&str>) -> (String, Vec<u8>) {
+        crate::metrics::register_collectors().unwrap();
+        crate::metrics::EVICTIONS_TOTAL.inc();
+        let mut request = Request::get("/prometheus");
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        let app = Router::new().route("/prometheus", get(prometheus_scrape));
+        let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let content_type = response.headers()[CONTENT_TYPE].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (content_type, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_protobuf_scrape_decodes_to_metric_families() {
+        let (content_type, body) = scrape(Some("application/vnd.google.protobuf;q=0.9, text/plain;q=0.5")).await;
+        assert!(content_type.starts_with("application/vnd.google.protobuf"), "{}", content_type);
+
+        let mut input = protobuf::CodedInputStream::from_bytes(&body);
+        let mut families = Vec::new();
+        while !input.eof().unwrap() {
+            let family: prometheus::proto::MetricFamily = input.read_message().unwrap();
+            families.push(family);
+        }
+        let evictions = families
+            .iter()
+            .find(|family| family.get_name() == "raftmetrics_evictions_total")
+            .expect("evictions counter missing");
+        assert_eq!(evictions.get_field_type(), prometheus::proto::MetricType::COUNTER);
+        assert!(evictions.get_metric()[0].get_counter().get_value() >= 1.0);
+        assert!(families.iter().all(|family| family.is_initialized()));
+    }
+
+    #[tokio::test]
+    async fn test_text_scrape_is_the_default() {
+        let (content_type, body) = scrape(None).await;
+        assert!(content_type.starts_with("text/plain"), "{}", content_type);
+        assert!(String::from_utf8(body).unwrap().contains("# TYPE raftmetrics_evictions_total counter"));
+    }
+}
@@ -0,0 +1,101 @@
+use axum::Router;
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::info;
+
+/// Where to bind the admin listener when an `ADMIN_PORT` is configured.
+pub fn admin_listen_addr(port: Option<u16>) -> Option<String> {
+    port.map(|port| format!("0.0.0.0:{}", port))
+}
+
+/// Serves `data` on `listener` and `admin` on `admin_listener`, or both on
+/// `listener` when there is no admin listener. Both servers stop once
+/// `shutdown` completes or either of them fails, finishing the requests
+/// they are already serving.
+pub async fn serve_split(
+    listener: TcpListener,
+    admin_listener: Option<TcpListener>,
+    data: Router,
+    admin: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    let (stop, stopped) = watch::channel(false);
+    let stop = std::sync::Arc::new(stop);
+    tokio::spawn({
+        let stop = stop.clone();
+        async move {
+            shutdown.await;
+            info!("Shutting down listeners");
+            let _ = stop.send(true);
+        }
+    });
+    let until_stopped = |mut stopped: watch::Receiver<bool>| async move {
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    };
+    let serve = |listener: TcpListener, router: Router, stopped: watch::Receiver<bool>| {
+        let stop = stop.clone();
+        async move {
+            let served = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(until_stopped(stopped))
+                .await;
+            // One server failing takes the other down with it
+            let _ = stop.send(true);
+            served
+        }
+    };
+
+    match admin_listener {
+        None => serve(listener, data.merge(admin), stopped).await,
+        Some(admin_listener) => {
+            let (data, admin) = tokio::join!(
+                serve(listener, data, stopped.clone()),
+                serve(admin_listener, admin, stopped),
+            );
+            data.and(admin)
+        }
+    }
+}
+
+/// Completes on ctrl-c, for [`serve_split`].
+pub async fn ctrl_c() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+
+    #[tokio::test]
+    async fn test_split_listeners_serve_their_own_routes_and_stop_together() {
+        let data = Router::new().route("/metrics", get(|| async { "data" }));
+        let admin = Router::new().route("/admin/config", get(|| async { "admin" }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let admin_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (data_url, admin_url) = (
+            format!("http://{}", listener.local_addr().unwrap()),
+            format!("http://{}", admin_listener.local_addr().unwrap()),
+        );
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_split(listener, Some(admin_listener), data, admin, async {
+            let _ = stopped.await;
+        }));
+
+        let client = reqwest::Client::new();
+        let status = |url: String| {
+            let client = client.clone();
+            async move { client.get(url).send().await.unwrap().status().as_u16() }
+        };
+        assert_eq!(status(format!("{}/metrics", data_url)).await, 200);
+        assert_eq!(status(format!("{}/admin/config", data_url)).await, 404);
+        assert_eq!(status(format!("{}/admin/config", admin_url)).await, 200);
+        assert_eq!(status(format!("{}/metrics", admin_url)).await, 404);
+
+        // Idle keep-alive connections would hold up a graceful shutdown
+        drop(client);
+        stop.send(()).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    }
+}
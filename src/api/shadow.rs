@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::{api::types::MetricValueResponse, config::ShadowConfig, metrics::sampling::keeps_nth, RaftMetricsError, Result};
+
+/// Header marking a request as a shadow copy, so the shadow worker can keep
+/// it out of its alerts. Its value is always `1`.
+pub const SHADOW_HEADER: &str = "x-shadow-traffic";
+
+/// Served at `GET /admin/shadow/stats`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShadowStats {
+    pub enabled: bool,
+    pub percent: f64,
+    /// Successful primary writes on partitions that have a shadow.
+    pub writes_seen: u64,
+    /// Writes copied to a shadow.
+    pub duplicated: u64,
+    /// Copies dropped because `SHADOW_MAX_IN_FLIGHT` were already in flight.
+    pub dropped: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Shadow answers by status code, with `error` for transport failures.
+    pub statuses: BTreeMap<String, u64>,
+    pub mean_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// Reads repeated against a shadow with `SHADOW_COMPARE_READS`.
+    pub comparisons: u64,
+    /// Comparisons where the shadow returned a different value.
+    pub mismatches: u64,
+    /// Comparisons the shadow could not answer.
+    pub comparison_failures: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    stats: ShadowStats,
+    latency_total_ms: f64,
+}
+
+/// Duplicates a share of successful writes to shadow workers after the
+/// primary write has answered. Copies run on spawned tasks, at most
+/// `SHADOW_MAX_IN_FLIGHT` at once, and their outcome is only counted: it
+/// never reaches the client.
+pub struct ShadowTraffic {
+    config: ShadowConfig,
+    client: reqwest::Client,
+    permits: Arc<Semaphore>,
+    counters: Mutex<Counters>,
+}
+
+impl ShadowTraffic {
+    pub fn new(config: ShadowConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| RaftMetricsError::Internal(format!("Failed to build shadow client: {}", e)))?;
+        Ok(Self {
+            permits: Arc::new(Semaphore::new(config.max_in_flight)),
+            client,
+            counters: Mutex::default(),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &ShadowConfig {
+        &self.config
+    }
+
+    /// Sends the request built by `build` to `partition`'s shadow, if it has
+    /// one and this write falls within `SHADOW_PERCENT`. `build` gets the
+    /// shadow's base URL.
+    pub fn mirror<F>(self: &Arc<Self>, partition: usize, build: F)
+    where
+        F: FnOnce(&reqwest::Client, &str) -> reqwest::RequestBuilder + Send + 'static,
+    {
+        let Some(target) = self.config.target(partition) else { return };
+        if !self.sampled() {
+            return;
+        }
+        let Some(permit) = self.permit() else { return };
+        self.counters.lock().unwrap().stats.duplicated += 1;
+        let request = build(&self.client, target).header(SHADOW_HEADER, "1");
+
+        let shadow = self.clone();
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let result = request.send().await;
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            drop(permit);
+
+            let mut counters = shadow.counters.lock().unwrap();
+            let status = match &result {
+                Ok(response) => response.status().as_u16().to_string(),
+                Err(e) => {
+                    debug!("Shadow write failed: {}", e);
+                    "error".to_string()
+                }
+            };
+            if result.is_ok_and(|response| response.status().is_success()) {
+                counters.stats.succeeded += 1;
+            } else {
+                counters.stats.failed += 1;
+            }
+            *counters.stats.statuses.entry(status).or_default() += 1;
+            counters.latency_total_ms += latency_ms;
+            counters.stats.max_latency_ms = counters.stats.max_latency_ms.max(latency_ms);
+        });
+    }
+
+    /// With `SHADOW_COMPARE_READS`, reads `name` from `partition`'s shadow
+    /// and counts whether it matches the primary's `value`.
+    pub fn compare_read(self: &Arc<Self>, partition: usize, name: &str, value: f64) {
+        if !self.config.compare_reads {
+            return;
+        }
+        let Some(target) = self.config.target(partition) else { return };
+        let Some(permit) = self.permit() else { return };
+        let request = self.client.get(format!("{}/metrics/{}", target, name)).header(SHADOW_HEADER, "1");
+
+        let shadow = self.clone();
+        tokio::spawn(async move {
+            let shadowed = match request.send().await {
+                Ok(response) if response.status().is_success() => response.json::<MetricValueResponse>().await.ok(),
+                _ => None,
+            };
+            drop(permit);
+
+            let mut counters = shadow.counters.lock().unwrap();
+            match shadowed {
+                Some(shadowed) => {
+                    counters.stats.comparisons += 1;
+                    if shadowed.value != value {
+                        counters.stats.mismatches += 1;
+                    }
+                }
+                None => counters.stats.comparison_failures += 1,
+            }
+        });
+    }
+
+    pub fn stats(&self) -> ShadowStats {
+        let counters = self.counters.lock().unwrap();
+        let answered = counters.stats.succeeded + counters.stats.failed;
+        ShadowStats {
+            enabled: self.config.is_enabled(),
+            percent: self.config.percent,
+            mean_latency_ms: if answered == 0 { 0.0 } else { counters.latency_total_ms / answered as f64 },
+            ..counters.stats.clone()
+        }
+    }
+
+    /// Keeps `SHADOW_PERCENT` of writes, spread evenly.
+    fn sampled(&self) -> bool {
+        let mut counters = self.counters.lock().unwrap();
+        counters.stats.writes_seen += 1;
+        keeps_nth(counters.stats.writes_seen, self.config.percent / 100.0)
+    }
+
+    fn permit(&self) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        let permit = self.permits.clone().try_acquire_owned().ok();
+        if permit.is_none() {
+            self.counters.lock().unwrap().stats.dropped += 1;
+        }
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_shadows_and_even_sampling() {
+        let config = ShadowConfig::from_lookup(|key| match key {
+            "SHADOW_URL" => Some("http://shadow:9000/".to_string()),
+            "SHADOW_URLS" => Some("1=http://rebuilt:9001, bad".to_string()),
+            "SHADOW_PERCENT" => Some("40".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.target(0), Some("http://shadow:9000"));
+        assert_eq!(config.target(1), Some("http://rebuilt:9001"));
+
+        let shadow = ShadowTraffic::new(config).unwrap();
+        let kept: Vec<bool> = (0..10).map(|_| shadow.sampled()).collect();
+        assert_eq!(kept.iter().filter(|&&kept| kept).count(), 4);
+        assert!(kept.windows(2).all(|pair| !(pair[0] && pair[1])), "{:?}", kept);
+        assert_eq!(shadow.stats().writes_seen, 10);
+    }
+}
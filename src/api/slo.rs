@@ -0,0 +1,278 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{SloConfig, SloDefinition};
+
+/// Slots in each objective's rolling window. Memory per objective is fixed
+/// no matter how many requests arrive.
+pub const WINDOW_SLOTS: usize = 60;
+
+/// Upper bounds, in milliseconds, of the latency buckets percentiles are
+/// estimated from.
+const LATENCY_BOUNDS_MS: [f64; 13] = [1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0];
+
+/// Requests observed during one slot of the window.
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    /// Which slot-width interval since the tracker started this slot holds.
+    interval: u64,
+    total: u64,
+    within: u64,
+    /// Counts per [`LATENCY_BOUNDS_MS`] bucket, plus one for slower requests.
+    latency: [u64; LATENCY_BOUNDS_MS.len() + 1],
+}
+
+#[derive(Debug)]
+struct Ring {
+    definition: SloDefinition,
+    slot_width: Duration,
+    slots: [Slot; WINDOW_SLOTS],
+}
+
+impl Ring {
+    fn slot_at(&mut self, interval: u64) -> &mut Slot {
+        let slot = &mut self.slots[(interval % WINDOW_SLOTS as u64) as usize];
+        if slot.interval != interval {
+            *slot = Slot { interval, ..Slot::default() };
+        }
+        slot
+    }
+
+    /// Slots of the `WINDOW_SLOTS` intervals ending with `current`.
+    fn window(&self, current: u64) -> impl Iterator<Item = &Slot> {
+        self.slots
+            .iter()
+            .filter(move |slot| slot.total > 0 && slot.interval <= current && slot.interval + WINDOW_SLOTS as u64 > current)
+    }
+}
+
+/// Current standing of one objective.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SloStatus {
+    pub name: String,
+    pub endpoint: String,
+    pub percentile: f64,
+    pub threshold_ms: u64,
+    pub window_secs: u64,
+    /// Requests in the current window.
+    pub requests: u64,
+    /// Latency at `percentile` over the window, as the upper bound of the
+    /// bucket it falls in; `None` with no requests or beyond the largest bucket.
+    pub current_ms: Option<f64>,
+    /// Percentage of requests in the window that finished within the threshold.
+    pub compliance: f64,
+    /// Rate the error budget is being spent at; 1.0 spends exactly the
+    /// budget over the window.
+    pub burn_rate: f64,
+}
+
+/// Rolling per-endpoint latency tracking for the configured objectives.
+#[derive(Debug)]
+pub struct SloTracker {
+    origin: Instant,
+    degraded_burn_rate: f64,
+    rings: Vec<Mutex<Ring>>,
+}
+
+impl SloTracker {
+    pub fn new(config: &SloConfig) -> Self {
+        Self::starting_at(config, Instant::now())
+    }
+
+    fn starting_at(config: &SloConfig, origin: Instant) -> Self {
+        let rings = config
+            .slos
+            .iter()
+            .map(|definition| {
+                Mutex::new(Ring {
+                    definition: definition.clone(),
+                    slot_width: (definition.window / WINDOW_SLOTS as u32).max(Duration::from_millis(1)),
+                    slots: [Slot::default(); WINDOW_SLOTS],
+                })
+            })
+            .collect();
+        Self { origin, degraded_burn_rate: config.degraded_burn_rate, rings }
+    }
+
+    /// Feeds one request to every objective whose endpoint matches.
+    pub fn observe(&self, method: &str, route: &str, elapsed: Duration) {
+        self.observe_at(method, route, elapsed, Instant::now());
+    }
+
+    fn observe_at(&self, method: &str, route: &str, elapsed: Duration, now: Instant) {
+        for ring in &self.rings {
+            let mut ring = ring.lock().unwrap();
+            if !endpoint_matches(&ring.definition.endpoint, method, route) {
+                continue;
+            }
+            let within = elapsed <= ring.definition.threshold;
+            let bucket = LATENCY_BOUNDS_MS
+                .iter()
+                .position(|&bound| elapsed.as_secs_f64() * 1000.0 <= bound)
+                .unwrap_or(LATENCY_BOUNDS_MS.len());
+
+            let interval = self.interval(&ring, now);
+            let slot = ring.slot_at(interval);
+            slot.total += 1;
+            slot.within += within as u64;
+            slot.latency[bucket] += 1;
+        }
+    }
+
+    pub fn status(&self) -> Vec<SloStatus> {
+        self.status_at(Instant::now())
+    }
+
+    fn status_at(&self, now: Instant) -> Vec<SloStatus> {
+        self.rings
+            .iter()
+            .map(|ring| {
+                let ring = ring.lock().unwrap();
+                let current = self.interval(&ring, now);
+
+                let mut total = 0;
+                let mut within = 0;
+                let mut latency = [0u64; LATENCY_BOUNDS_MS.len() + 1];
+                for slot in ring.window(current) {
+                    total += slot.total;
+                    within += slot.within;
+                    for (sum, count) in latency.iter_mut().zip(slot.latency) {
+                        *sum += count;
+                    }
+                }
+
+                let definition = &ring.definition;
+                let compliance = if total == 0 { 100.0 } else { within as f64 * 100.0 / total as f64 };
+                SloStatus {
+                    name: definition.name.clone(),
+                    endpoint: definition.endpoint.clone(),
+                    percentile: definition.percentile,
+                    threshold_ms: definition.threshold.as_millis() as u64,
+                    window_secs: definition.window.as_secs(),
+                    requests: total,
+                    current_ms: percentile_bound(&latency, total, definition.percentile),
+                    compliance,
+                    burn_rate: (100.0 - compliance) / (100.0 - definition.percentile),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether any objective is burning its error budget faster than the
+    /// configured degraded rate.
+    pub fn degraded(&self) -> bool {
+        self.status().iter().any(|slo| slo.burn_rate > self.degraded_burn_rate)
+    }
+
+    fn interval(&self, ring: &Ring, now: Instant) -> u64 {
+        (now.saturating_duration_since(self.origin).as_nanos() / ring.slot_width.as_nanos()) as u64
+    }
+}
+
+/// Matches `pattern` (`[METHOD ]path[*]`) against a request's method and
+/// matched route.
+fn endpoint_matches(pattern: &str, method: &str, route: &str) -> bool {
+    let path = match pattern.split_once(' ') {
+        Some((pattern_method, path)) => {
+            if !pattern_method.eq_ignore_ascii_case(method) {
+                return false;
+            }
+            path
+        }
+        None => pattern,
+    };
+    match path.strip_suffix('*') {
+        Some(prefix) => route.starts_with(prefix),
+        None => route == path,
+    }
+}
+
+fn percentile_bound(latency: &[u64], total: u64, percentile: f64) -> Option<f64> {
+    if total == 0 {
+        return None;
+    }
+    let rank = ((percentile / 100.0) * total as f64).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (bucket, count) in latency.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return LATENCY_BOUNDS_MS.get(bucket).copied();
+        }
+    }
+    None
+}
+
+/// `GET /slo`: the standing of every configured objective.
+pub async fn slo_status(State(slo): State<Arc<SloTracker>>) -> Json<Vec<SloStatus>> {
+    Json(slo.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(origin: Instant) -> SloTracker {
+        let config = SloConfig {
+            slos: vec![SloDefinition::parse("reads=GET /metrics/*,p90,100ms,60s").unwrap()],
+            degraded_burn_rate: 2.0,
+        };
+        SloTracker::starting_at(&config, origin)
+    }
+
+    #[test]
+    fn test_compliance_over_rolling_window() {
+        let origin = Instant::now();
+        let slo = tracker(origin);
+        let at = |secs: f64| origin + Duration::from_secs_f64(secs);
+
+        // 8 fast and 2 slow reads in the first second; writes do not count
+        for _ in 0..8 {
+            slo.observe_at("GET", "/metrics/:name", Duration::from_millis(20), at(0.5));
+        }
+        for _ in 0..2 {
+            slo.observe_at("GET", "/metrics/:name/aggregate", Duration::from_millis(400), at(0.5));
+        }
+        slo.observe_at("POST", "/metrics", Duration::from_secs(3), at(0.5));
+
+        let status = &slo.status_at(at(1.0))[0];
+        assert_eq!(status.requests, 10);
+        assert_eq!(status.compliance, 80.0);
+        assert_eq!(status.current_ms, Some(500.0));
+        assert!((status.burn_rate - 2.0).abs() < 1e-9);
+
+        // Fast reads later in the window improve compliance
+        for _ in 0..10 {
+            slo.observe_at("GET", "/metrics/:name", Duration::from_millis(5), at(30.0));
+        }
+        let status = &slo.status_at(at(59.9))[0];
+        assert_eq!(status.requests, 20);
+        assert_eq!(status.compliance, 90.0);
+        assert_eq!(status.current_ms, Some(25.0));
+
+        // At 60s the first second's slot leaves the window
+        let status = &slo.status_at(at(60.0))[0];
+        assert_eq!(status.requests, 10);
+        assert_eq!(status.compliance, 100.0);
+        assert_eq!(status.burn_rate, 0.0);
+
+        // And once everything has aged out the window is empty
+        let status = &slo.status_at(at(95.0))[0];
+        assert_eq!((status.requests, status.compliance, status.current_ms), (0, 100.0, None));
+    }
+
+    #[test]
+    fn test_slot_reused_after_wrapping() {
+        let origin = Instant::now();
+        let slo = tracker(origin);
+        slo.observe_at("GET", "/metrics/:name", Duration::from_millis(400), origin);
+        // Same ring position one full window later
+        let later = origin + Duration::from_secs(60);
+        slo.observe_at("GET", "/metrics/:name", Duration::from_millis(5), later);
+
+        let status = &slo.status_at(later)[0];
+        assert_eq!(status.requests, 1);
+        assert_eq!(status.compliance, 100.0);
+    }
+}
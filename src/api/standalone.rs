@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::{
+    Result,
+    RaftMetricsError,
+    config::{ControlConfig, RaftConfig},
+    metrics::MetricsRegistry,
+    api::{
+        control::{control_router, ControlState},
+        info::{node_id_from_env, NodeInfo},
+        preflight::{worker_preflight, PreflightReport},
+        slo::SloTracker,
+        worker::{open_metrics_from_env, worker_router, WorkerState},
+    },
+};
+
+/// Starts an in-process worker over `metrics` on a loopback port and returns
+/// a control state that routes every metric to it. Both sides share the same
+/// registry, so the control node's own view matches what the worker stores.
+pub async fn standalone_state(
+    metrics: Arc<MetricsRegistry>,
+    mut config: ControlConfig,
+    raft_config: RaftConfig,
+) -> Result<ControlState> {
+    let mut worker_state = WorkerState::new(1, metrics.clone(), raft_config)?;
+    worker_state.default_deadline = config.deadline.default_budget;
+    worker_state.slo = Arc::new(SloTracker::new(&config.slo));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to bind local worker: {}", e)))?;
+    let worker_addr = listener.local_addr()
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to read local worker address: {}", e)))?;
+    info!("Starting in-process worker on {}", worker_addr);
+
+    tokio::spawn(async move {
+        axum::serve(listener, worker_router(worker_state)).await.unwrap();
+    });
+
+    config.worker_urls = vec![format!("http://{}", worker_addr)];
+    let mut state = ControlState::new(config)?;
+    state.metrics = metrics;
+    Ok(state)
+}
+
+/// Runs the standalone node's storage and listener checks, for `--check`.
+pub async fn preflight_standalone_node() -> PreflightReport {
+    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    let db_path = std::env::var("DB_PATH").ok();
+    let (report, _) = worker_preflight(&format!("0.0.0.0:{}", port), db_path.as_deref()).await;
+    report
+}
+
+pub async fn start_standalone_node() -> Result<()> {
+    let metrics = open_metrics_from_env()?;
+    let info = NodeInfo::new("standalone", node_id_from_env(), metrics.backend_name(), Some(1));
+    info.log();
+    metrics.recover().await?;
+    let mut state = standalone_state(metrics, ControlConfig::from_env(), RaftConfig::from_env()).await?;
+    state.info = Arc::new(info);
+
+    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    let addr = format!("0.0.0.0:{}", port);
+    info!("Starting standalone node on {}", addr);
+
+    let listener = TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, control_router(state)).await.unwrap();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Request, StatusCode}};
+    use tower::ServiceExt;
+    use crate::api::types::MetricValueResponse;
+
+    #[tokio::test]
+    async fn test_record_and_read_back_through_control_api() {
+        let metrics = Arc::new(MetricsRegistry::new().unwrap());
+        let state = standalone_state(
+            metrics.clone(),
+            ControlConfig::from_lookup(|_| None),
+            RaftConfig::default(),
+        )
+        .await
+        .unwrap();
+        let app = control_router(state);
+
+        let response = app.clone()
+            .oneshot(
+                Request::post("/metrics")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"cpu","value":42.5}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(Request::get("/metrics/cpu").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let metric: MetricValueResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(metric.value, 42.5);
+
+        // The worker wrote into the shared registry
+        assert_eq!(metrics.get_metric("cpu").await.unwrap(), Some(42.5));
+    }
+}
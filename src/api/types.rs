@@ -0,0 +1,712 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    Result,
+    RaftMetricsError,
+    api::consistency::AckLevel,
+    error::ErrorCode,
+    metrics::{
+        namespace::{self, TreeShape},
+        AggregateFn,
+        Labels,
+        MetricAggregate,
+        MetricPoint,
+        QuarantinedSample,
+        StaleMetric,
+        TopBy,
+        TopMetric,
+    },
+    raft::node::{RaftLag, RaftRole},
+};
+
+fn no_value() -> f64 {
+    f64::NAN
+}
+
+fn is_nan(v: &f64) -> bool {
+    v.is_nan()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricRequest {
+    pub metric_name: String,
+    /// Required unless `state` is given.
+    #[serde(default = "no_value", skip_serializing_if = "is_nan")]
+    pub value: f64,
+    /// For a state metric, the state by name in place of its index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// Sample time in Unix seconds; defaults to when the worker receives it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+}
+
+/// Latest value of a metric, or the sample a worker just recorded. The
+/// control node decodes a worker's reply into this same type, and unknown
+/// fields are refused so the two cannot drift apart unnoticed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricValueResponse {
+    pub name: String,
+    pub value: f64,
+    /// Unix seconds of the sample.
+    pub timestamp: i64,
+    /// Worker that owns the metric.
+    pub worker_id: usize,
+    /// Unit `value` was converted to on request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Version token for the write, to pass back as `?min_version=`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Acknowledgment level a write reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack: Option<AckLevel>,
+    /// Where the control node got a read from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ReadSource>,
+    /// Unix seconds at which the value goes stale, for metrics with a TTL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// State the metric is in now, for a state metric.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+/// Which copy of a metric answered a read through the control node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadSource {
+    /// The worker owning the metric's partition.
+    Primary,
+    /// A replica configured for the partition, after the primary failed.
+    Replica,
+    /// A promoted standby for the partition, after its replicas failed.
+    Standby,
+    /// The control node's cached value, kept past its TTL, after every
+    /// worker failed.
+    StaleCache,
+}
+
+impl ReadSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadSource::Primary => "primary",
+            ReadSource::Replica => "replica",
+            ReadSource::Standby => "standby",
+            ReadSource::StaleCache => "stale-cache",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricAggregateResponse {
+    pub name: String,
+    pub worker_id: usize,
+    pub count: u64,
+    pub sum: f64,
+    pub average: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Value of the sample with the greatest timestamp, as `GET
+    /// /metrics/:name` reads it; 0 from workers that predate it.
+    #[serde(default)]
+    pub latest: f64,
+    /// Unix seconds of the first and latest write.
+    pub first_seen: i64,
+    pub last_seen: i64,
+    /// Unit the values were converted to on request; `count` never is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+impl MetricAggregateResponse {
+    pub(crate) fn new(
+        name: String,
+        worker_id: usize,
+        aggregate: &MetricAggregate,
+        latest: f64,
+        round: impl Fn(f64) -> Result<f64>,
+    ) -> Result<Self> {
+        Ok(Self {
+            name,
+            worker_id,
+            count: aggregate.count,
+            sum: round(aggregate.total())?,
+            average: round(aggregate.average)?,
+            min: round(aggregate.min)?,
+            max: round(aggregate.max)?,
+            latest: round(latest)?,
+            first_seen: aggregate.first_seen,
+            last_seen: aggregate.last_seen,
+            unit: None,
+        })
+    }
+}
+
+/// Aggregates by metric name; names without data map to `null`.
+pub type BatchAggregateResponse = HashMap<String, Option<MetricAggregateResponse>>;
+
+/// Outcome of a write through the control node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WriteResponse {
+    pub success: bool,
+    pub message: String,
+    /// Opaque token for read-your-writes; pass it back as `?min_version=`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Acknowledgment level the write reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack: Option<AckLevel>,
+    /// Set when the write reached a lower level than `?ack=` asked for.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+}
+
+/// Outcome of a bulk ingest.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestSummary {
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Why each rejected record of a `POST /process/batch` was rejected.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<IngestError>,
+    /// Records the control node refused for their tenant's rate quota,
+    /// also counted in `rejected`.
+    #[serde(default)]
+    pub rate_limited: u64,
+}
+
+/// A record of a batch that was not applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IngestError {
+    /// Position of the record in the request.
+    pub index: usize,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+/// Most names one `POST /aggregate/batch` may ask for.
+pub const MAX_BATCH_AGGREGATE_NAMES: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchAggregateRequest {
+    pub names: Vec<String>,
+}
+
+/// Most names one `POST /metrics/multi_get` may ask for.
+pub const MAX_MULTI_GET_NAMES: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultiGetRequest {
+    pub names: Vec<String>,
+}
+
+/// Latest values by metric name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MultiGetResponse {
+    pub values: HashMap<String, MetricPoint>,
+    /// Names without a live value.
+    pub missing: Vec<String>,
+    /// Names whose worker could not be read, with the reason. Only the
+    /// control node fills this in.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+/// Body of `POST /metrics/get`: one series, named by its metric and its
+/// full label set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesRequest {
+    pub name: String,
+    #[serde(default)]
+    pub labels: Labels,
+}
+
+/// The latest value of one exact series, with statistics over its stored
+/// samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeriesResponse {
+    pub name: String,
+    pub labels: Labels,
+    pub value: f64,
+    /// Unix seconds of the latest sample.
+    pub timestamp: i64,
+    pub worker_id: usize,
+    pub count: u64,
+    pub sum: f64,
+    pub average: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Default and largest page of `GET /metrics/stale`.
+pub const DEFAULT_STALE_PAGE: usize = 100;
+pub const MAX_STALE_PAGE: usize = 1000;
+
+/// Default and largest `n` of `GET /debug/top`.
+pub const DEFAULT_TOP: usize = 20;
+pub const MAX_TOP: usize = 1000;
+
+/// Default and largest page of `GET /aggregates`.
+pub const DEFAULT_AGGREGATES_PAGE: usize = 100;
+pub const MAX_AGGREGATES_PAGE: usize = 1000;
+
+/// Query of `GET /aggregates`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AggregatesQuery {
+    /// Only metrics whose names start with this are listed.
+    #[serde(default)]
+    pub prefix: String,
+    /// Cursor from the previous page's `next`.
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl AggregatesQuery {
+    pub fn limit(&self) -> Result<usize> {
+        match self.limit.unwrap_or(DEFAULT_AGGREGATES_PAGE) {
+            0 => Err(RaftMetricsError::InvalidRequest("limit must be positive".to_string())),
+            limit => Ok(limit.min(MAX_AGGREGATES_PAGE)),
+        }
+    }
+}
+
+/// One page of aggregates, in name order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AggregatesPage {
+    pub aggregates: Vec<MetricAggregateResponse>,
+    /// Pass as `?after=` for the next page; absent on the last one.
+    pub next: Option<String>,
+}
+
+/// Query of `POST /metrics/:name/reset`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResetQuery {
+    /// Keeps the samples from before the reset in history.
+    #[serde(default)]
+    pub keep_history: bool,
+}
+
+/// Query of `PUT /metrics/:name/histogram`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HistogramQuery {
+    /// Replaces a different bucket layout, starting the counts over.
+    #[serde(default)]
+    pub reset: bool,
+}
+
+/// Query of `POST /admin/recompute_aggregates`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecomputeQuery {
+    /// Only metrics whose names start with this are rebuilt.
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/// Query of `GET /debug/aggregates`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MismatchQuery {
+    /// How far apart the cached and stored sums may be.
+    #[serde(default)]
+    pub tolerance: f64,
+}
+
+/// Query of `GET /debug/top`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TopQuery {
+    #[serde(default)]
+    pub n: Option<usize>,
+    #[serde(default)]
+    pub by: TopBy,
+}
+
+impl TopQuery {
+    pub fn n(&self) -> Result<usize> {
+        match self.n.unwrap_or(DEFAULT_TOP) {
+            0 => Err(RaftMetricsError::InvalidRequest("n must be positive".to_string())),
+            n => Ok(n.min(MAX_TOP)),
+        }
+    }
+}
+
+/// The metrics ranking highest, highest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopMetricsResponse {
+    pub by: TopBy,
+    pub metrics: Vec<TopMetric>,
+}
+
+/// Query of `GET /metrics/stale`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StaleQuery {
+    /// Metrics idle for at least this long are listed.
+    #[serde(default = "default_idle_seconds")]
+    pub idle_seconds: u64,
+    /// Cursor from the previous page's `next`.
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+fn default_idle_seconds() -> u64 {
+    86_400
+}
+
+impl StaleQuery {
+    pub fn limit(&self) -> Result<usize> {
+        match self.limit.unwrap_or(DEFAULT_STALE_PAGE) {
+            0 => Err(RaftMetricsError::InvalidRequest("limit must be positive".to_string())),
+            limit => Ok(limit.min(MAX_STALE_PAGE)),
+        }
+    }
+}
+
+/// Query of `GET /metrics/tree`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeQuery {
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub separator: Option<String>,
+    #[serde(default)]
+    pub depth: Option<usize>,
+    #[serde(default)]
+    pub max_nodes: Option<usize>,
+}
+
+impl TreeQuery {
+    /// The tree asked for, with `depth` and `max_nodes` capped.
+    pub fn shape(&self) -> Result<TreeShape> {
+        let separator = self.separator.clone().unwrap_or_else(|| namespace::DEFAULT_TREE_SEPARATOR.to_string());
+        if separator.is_empty() {
+            return Err(RaftMetricsError::InvalidRequest("separator must not be empty".to_string()));
+        }
+        let depth = match self.depth.unwrap_or(namespace::DEFAULT_TREE_DEPTH) {
+            0 => return Err(RaftMetricsError::InvalidRequest("depth must be positive".to_string())),
+            depth => depth.min(namespace::MAX_TREE_DEPTH),
+        };
+        let max_nodes = match self.max_nodes.unwrap_or(namespace::DEFAULT_TREE_MAX_NODES) {
+            0 => return Err(RaftMetricsError::InvalidRequest("max_nodes must be positive".to_string())),
+            max_nodes => max_nodes.min(namespace::MAX_TREE_NODES),
+        };
+        Ok(TreeShape { prefix: self.prefix.clone(), separator, depth, max_nodes })
+    }
+}
+
+/// One page of idle metrics, in name order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StaleMetricsPage {
+    pub metrics: Vec<StaleMetric>,
+    /// Pass as `?after=` for the next page; absent on the last one.
+    pub next: Option<String>,
+}
+
+/// This worker's view of its raft group, served at `/raft/status` and
+/// `/debug/raft`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaftDebugResponse {
+    pub node_id: u64,
+    pub role: RaftRole,
+    /// Current leader's raft id, or 0 when none is known.
+    pub leader_id: u64,
+    pub term: u64,
+    pub applied_index: u64,
+    #[serde(flatten)]
+    pub lag: RaftLag,
+}
+
+/// A worker's `/health`, served with `503` when `status` is `unhealthy`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerHealthResponse {
+    /// `healthy`, `degraded` or `unhealthy`.
+    pub status: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Missing from workers that predate raft health.
+    #[serde(default)]
+    pub raft: RaftLag,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeltaQuery {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// How `GET /metrics/:name/compare` tests the current value against the
+/// threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Gt,
+    Lt,
+    Eq,
+}
+
+impl CompareOp {
+    /// Whether `current` breaches `threshold`.
+    pub fn breached(self, current: f64, threshold: f64) -> bool {
+        match self {
+            CompareOp::Gt => current > threshold,
+            CompareOp::Lt => current < threshold,
+            CompareOp::Eq => current == threshold,
+        }
+    }
+}
+
+/// A statistic of a metric's earlier samples to compare its current value
+/// against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Baseline {
+    Avg,
+    /// The nearest-rank 95th percentile.
+    P95,
+}
+
+impl Baseline {
+    /// The statistic over `values`, which must not be empty.
+    pub fn of(self, values: &mut [f64]) -> f64 {
+        match self {
+            Baseline::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Baseline::P95 => {
+                values.sort_by(f64::total_cmp);
+                let rank = (0.95 * values.len() as f64).ceil().max(1.0) as usize;
+                values[rank - 1]
+            }
+        }
+    }
+}
+
+/// Query of `GET /metrics/:name/compare`: a constant `value` or a
+/// `baseline` to compare against, but not both.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareQuery {
+    pub op: CompareOp,
+    #[serde(default)]
+    pub value: Option<f64>,
+    #[serde(default)]
+    pub baseline: Option<Baseline>,
+    /// How many samples before the current one the baseline covers.
+    #[serde(default)]
+    pub samples: Option<usize>,
+}
+
+/// Whether a metric's current value breaches a threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompareResponse {
+    pub name: String,
+    pub op: CompareOp,
+    pub current: f64,
+    pub threshold: f64,
+    /// The statistic `threshold` was worked out as, when not a constant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<Baseline>,
+    pub breached: bool,
+}
+
+/// Query of `GET /metrics/:name/history`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Leaves out samples before this Unix time.
+    #[serde(default)]
+    pub since: Option<i64>,
+}
+
+/// Most recent samples of a metric, newest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricHistoryResponse {
+    pub name: String,
+    pub samples: Vec<MetricPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RangeParams {
+    pub from: i64,
+    pub to: i64,
+    /// Downsample to one point per `step` seconds.
+    pub step: Option<i64>,
+    /// How each step's samples are combined; `avg` when unset.
+    #[serde(rename = "fn")]
+    pub function: Option<String>,
+    /// Stop after this many points.
+    pub max_points: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupByParams {
+    pub label: String,
+    #[serde(rename = "fn")]
+    pub function: Option<String>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    /// Lower cap on the groups returned than [`crate::api::worker::MAX_GROUPS`].
+    pub max_groups: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupByResponse {
+    pub name: String,
+    pub label: String,
+    #[serde(rename = "fn")]
+    pub function: AggregateFn,
+    /// Aggregate per label value; samples without the label are under `"<none>"`.
+    pub groups: BTreeMap<String, f64>,
+    pub truncated: bool,
+}
+
+/// What a bucketed read answers for buckets without samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BucketFill {
+    /// Leave them out.
+    #[default]
+    None,
+    /// Every function as 0.
+    Zero,
+    /// Every function as `null`.
+    Null,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BucketsParams {
+    pub start: i64,
+    pub end: i64,
+    /// Bucket width in seconds; see [`crate::api::worker::bucket_step`].
+    pub step: Option<i64>,
+    /// Comma-separated functions per bucket; all of them when unset.
+    #[serde(rename = "fn")]
+    pub functions: Option<String>,
+    #[serde(default)]
+    pub fill: BucketFill,
+}
+
+/// One bucket of a bucketed read: its start and each requested function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub bucket_start: i64,
+    #[serde(flatten)]
+    pub values: BTreeMap<AggregateFn, Option<f64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BucketsResponse {
+    pub name: String,
+    pub start: i64,
+    pub end: i64,
+    /// The bucket width used, which may be wider than the one asked for.
+    pub step: i64,
+    #[serde(rename = "fn")]
+    pub functions: Vec<AggregateFn>,
+    pub fill: BucketFill,
+    pub buckets: Vec<Bucket>,
+}
+
+/// Optional `?precision=N` rounding of values in read responses.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PrecisionQuery {
+    pub precision: Option<u32>,
+}
+
+/// Most decimal places `?precision=` accepts; f64 carries about 15.
+pub const MAX_PRECISION: u32 = 15;
+
+impl PrecisionQuery {
+    /// Returns a rounding function for the requested number of decimal
+    /// places, or the identity when none was requested. The function fails
+    /// for a value too large to scale to that many places.
+    pub fn rounder(&self) -> Result<impl Fn(f64) -> Result<f64>> {
+        let digits = self.precision.unwrap_or(0);
+        let scale = match self.precision {
+            Some(digits) if digits > MAX_PRECISION => {
+                return Err(RaftMetricsError::InvalidRequest(format!(
+                    "precision must be at most {}", MAX_PRECISION
+                )));
+            }
+            Some(digits) => Some(10f64.powi(digits as i32)),
+            None => None,
+        };
+        Ok(move |value: f64| match scale {
+            Some(scale) => {
+                let scaled = value * scale;
+                if value.is_finite() && !scaled.is_finite() {
+                    return Err(RaftMetricsError::InvalidRequest(format!(
+                        "{} cannot be rounded to {} decimal places; ask for fewer",
+                        value, digits
+                    )));
+                }
+                Ok(scaled.round() / scale)
+            }
+            None => Ok(value),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricDeltaResponse {
+    pub name: String,
+    pub delta: f64,
+    pub from: MetricPoint,
+    pub to: MetricPoint,
+    /// Unit the values were converted to on request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuarantineResponse {
+    pub name: String,
+    pub samples: Vec<QuarantinedSample>,
+}
+
+/// Body of `PUT /metrics/:name/sample_rate`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SampleRateRequest {
+    /// Fraction of samples to keep, between 0 and 1.
+    pub sample_rate: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    pub sql: String,
+}
+
+/// One worker as seen from the control node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerTopology {
+    pub partition: usize,
+    pub url: String,
+    /// The status the worker's `/health` reports, or `unknown` when it
+    /// does not answer.
+    pub status: String,
+    /// The worker's raft state, when it answered `/debug/raft`.
+    pub raft: Option<RaftDebugResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterTopology {
+    pub workers: Vec<WorkerTopology>,
+}
+
+/// Outcome of priming the read cache.
+#[derive(Debug, Default, Serialize)]
+pub struct PrimeSummary {
+    /// Metrics read into the cache.
+    pub primed: usize,
+    /// Metrics their worker does not hold.
+    pub missing: usize,
+    /// Metrics whose worker could not be read.
+    pub failed: Vec<String>,
+}
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{RaftMetricsError, Result};
+
+/// What a unit measures. Units convert only within their dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Bytes,
+    Time,
+    Ratio,
+}
+
+impl Dimension {
+    fn name(self) -> &'static str {
+        match self {
+            Dimension::Bytes => "bytes",
+            Dimension::Time => "time",
+            Dimension::Ratio => "ratio",
+        }
+    }
+}
+
+/// A unit a read can be converted to, with its size in the base unit of
+/// its dimension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub name: &'static str,
+    dimension: Dimension,
+    scale: f64,
+}
+
+const UNITS: [Unit; 16] = [
+    Unit { name: "bytes", dimension: Dimension::Bytes, scale: 1.0 },
+    Unit { name: "B", dimension: Dimension::Bytes, scale: 1.0 },
+    Unit { name: "KB", dimension: Dimension::Bytes, scale: 1e3 },
+    Unit { name: "MB", dimension: Dimension::Bytes, scale: 1e6 },
+    Unit { name: "GB", dimension: Dimension::Bytes, scale: 1e9 },
+    Unit { name: "KiB", dimension: Dimension::Bytes, scale: 1024.0 },
+    Unit { name: "MiB", dimension: Dimension::Bytes, scale: 1024.0 * 1024.0 },
+    Unit { name: "GiB", dimension: Dimension::Bytes, scale: 1024.0 * 1024.0 * 1024.0 },
+    Unit { name: "h", dimension: Dimension::Time, scale: 3600.0 },
+    Unit { name: "min", dimension: Dimension::Time, scale: 60.0 },
+    Unit { name: "s", dimension: Dimension::Time, scale: 1.0 },
+    Unit { name: "ms", dimension: Dimension::Time, scale: 1e-3 },
+    Unit { name: "us", dimension: Dimension::Time, scale: 1e-6 },
+    Unit { name: "ns", dimension: Dimension::Time, scale: 1e-9 },
+    Unit { name: "ratio", dimension: Dimension::Ratio, scale: 1.0 },
+    Unit { name: "percent", dimension: Dimension::Ratio, scale: 0.01 },
+];
+
+/// Metric name suffixes that say which unit the metric is recorded in,
+/// following the Prometheus naming convention.
+const SUFFIXES: [(&str, &str); 11] = [
+    ("_bytes", "bytes"),
+    ("_seconds", "s"),
+    ("_milliseconds", "ms"),
+    ("_ms", "ms"),
+    ("_microseconds", "us"),
+    ("_us", "us"),
+    ("_nanoseconds", "ns"),
+    ("_ns", "ns"),
+    ("_ratio", "ratio"),
+    ("_percent", "percent"),
+    ("_pct", "percent"),
+];
+
+/// Response header naming the unit an NDJSON read was converted to.
+pub const UNIT_HEADER: &str = "x-metric-unit";
+
+impl Unit {
+    pub fn parse(name: &str) -> Result<Self> {
+        UNITS
+            .into_iter()
+            .find(|unit| unit.name == name)
+            .ok_or_else(|| RaftMetricsError::InvalidRequest(format!(
+                "unknown unit '{}'; expected one of {}",
+                name,
+                UNITS.map(|unit| unit.name).join(", ")
+            )))
+    }
+
+    /// Unit named by the suffix of `metric`, e.g. bytes for `disk_used_bytes`.
+    pub fn of_metric(metric: &str) -> Option<Self> {
+        SUFFIXES
+            .into_iter()
+            .find(|(suffix, _)| metric.ends_with(suffix))
+            .and_then(|(_, unit)| Self::parse(unit).ok())
+    }
+
+    /// Factor turning values in this unit into values in `to`, refusing
+    /// units of another dimension.
+    pub fn factor(self, to: Unit) -> Result<f64> {
+        if self.dimension != to.dimension {
+            return Err(RaftMetricsError::InvalidRequest(format!(
+                "cannot convert {} ({}) to {} ({}): they measure different things",
+                self.name, self.dimension.name(), to.name, to.dimension.name()
+            )));
+        }
+        Ok(self.scale / to.scale)
+    }
+}
+
+/// Optional `?as_unit=` conversion of a metric read. The recorded unit
+/// comes from `?from_unit=`, or else from the metric name's suffix.
+/// `?unit=` is the older spelling of `as_unit`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UnitQuery {
+    #[serde(alias = "as_unit")]
+    pub unit: Option<String>,
+    pub from_unit: Option<String>,
+}
+
+impl UnitQuery {
+    /// Returns the factor converting values of `metric` and the unit it
+    /// converts to, or `None` when no conversion was asked for.
+    pub fn conversion(&self, metric: &str) -> Result<Option<(f64, Unit)>> {
+        let Some(target) = self.unit.as_deref() else {
+            return Ok(None);
+        };
+        let to = Unit::parse(target)?;
+        let from = match self.from_unit.as_deref() {
+            Some(from) => Unit::parse(from)?,
+            None => Unit::of_metric(metric).ok_or_else(|| RaftMetricsError::InvalidRequest(format!(
+                "unit of '{}' is unknown; pass ?from_unit=", metric
+            )))?,
+        };
+        Ok(Some((from.factor(to)?, to)))
+    }
+
+    /// Scales values of `metric` into the requested unit, with the unit's
+    /// name for the response. Every conversion is a pure scale, so sums,
+    /// averages, extremes and spreads all convert by the same factor;
+    /// counts are not values and must not be passed through it.
+    pub fn converter(&self, metric: &str) -> Result<(impl Fn(f64) -> f64 + Clone, Option<String>)> {
+        let conversion = self.conversion(metric)?;
+        let factor = conversion.map_or(1.0, |(factor, _)| factor);
+        Ok((move |value: f64| value * factor, conversion.map(|(_, unit)| unit.name.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversion_within_a_dimension_only() {
+        let bytes = Unit::parse("bytes").unwrap();
+        assert_eq!(bytes.factor(Unit::parse("MiB").unwrap()).unwrap() * 3.0 * 1024.0 * 1024.0, 3.0);
+        assert_eq!(Unit::parse("s").unwrap().factor(Unit::parse("ms").unwrap()).unwrap() * 1.5, 1500.0);
+        assert!(Unit::parse("s").unwrap().factor(bytes).is_err());
+        assert!(Unit::parse("furlongs").is_err());
+
+        assert_eq!(Unit::of_metric("disk_used_bytes"), Some(bytes));
+        assert_eq!(Unit::of_metric("request_ms").map(|unit| unit.name), Some("ms"));
+        assert_eq!(Unit::of_metric("cpu"), None);
+    }
+
+    fn convert(value: f64, from: &str, to: &str) -> Result<f64> {
+        Ok(value * Unit::parse(from)?.factor(Unit::parse(to)?)?)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() <= expected.abs() * 1e-12, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_every_dimension_converts_across_prefixes() {
+        assert_close(convert(2_500_000.0, "ns", "ms").unwrap(), 2.5);
+        assert_close(convert(90.0, "min", "h").unwrap(), 1.5);
+        assert_close(convert(1.5, "h", "us").unwrap(), 5.4e9);
+        assert_close(convert(3.0 * 1024.0 * 1024.0 * 1024.0, "B", "GiB").unwrap(), 3.0);
+        assert_close(convert(2.0, "GB", "KB").unwrap(), 2e6);
+        assert_close(convert(1.0, "MiB", "MB").unwrap(), 1.048576);
+        assert_close(convert(0.25, "ratio", "percent").unwrap(), 25.0);
+        assert_close(convert(12.5, "percent", "ratio").unwrap(), 0.125);
+
+        assert_eq!(Unit::of_metric("gc_pause_ns").map(|unit| unit.name), Some("ns"));
+        assert_eq!(Unit::of_metric("cache_hit_ratio").map(|unit| unit.name), Some("ratio"));
+        assert_eq!(Unit::of_metric("cpu_percent").map(|unit| unit.name), Some("percent"));
+    }
+
+    #[test]
+    fn test_incompatible_dimensions_are_refused_with_both_named() {
+        for (from, to) in [("s", "bytes"), ("GiB", "ms"), ("ratio", "s"), ("percent", "KiB")] {
+            let error = convert(1.0, from, to).unwrap_err();
+            assert_eq!(error.code(), crate::error::ErrorCode::ValidationFailed, "{}", error);
+            assert!(error.to_string().contains("measure different things"), "{}", error);
+        }
+        let error = convert(1.0, "ms", "GiB").unwrap_err().to_string();
+        assert!(error.contains("ms (time)") && error.contains("GiB (bytes)"), "{}", error);
+    }
+
+    #[test]
+    fn test_as_unit_is_read_like_unit() {
+        let query: UnitQuery = serde_json::from_value(serde_json::json!({ "as_unit": "ms" })).unwrap();
+        let (convert, unit) = query.converter("latency_seconds").unwrap();
+        assert_eq!((convert(0.25), unit.as_deref()), (250.0, Some("ms")));
+
+        let (convert, unit) = UnitQuery::default().converter("latency_seconds").unwrap();
+        assert_eq!((convert(0.25), unit), (0.25, None));
+    }
+}
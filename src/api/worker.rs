@@ -1,21 +1,130 @@
 use axum::{
-    extract::{State, Path},
-    response::IntoResponse,
-    routing::{get, post},
+    body::{Body, Bytes},
+    extract::{State, Path, Query, Request},
+    Extension,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    http::{header::{CONTENT_TYPE, ETAG}, HeaderMap, HeaderName, StatusCode},
+    routing::{get, post, put},
     Json, Router,
 };
-use serde::{Deserialize, Serialize};
+use futures_util::{stream, StreamExt};
+use prost::Message as _;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
-use tracing::info;
-use tokio::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn, Instrument};
 use std::env;
 use chrono;
+use tokio::sync::{mpsc, oneshot};
 
+#[cfg(feature = "chaos")]
+use crate::api::chaos::{chaos_routes, inject_faults, ChaosState};
 use crate::{
     Result,
     RaftMetricsError,
-    metrics::MetricsRegistry,
-    raft::storage::MemStorage,
+    metrics::{
+        compaction::CompactionPolicy,
+        dedup::DedupPolicy,
+        histograms::{self, HistogramDefinition, HistogramMetric, HistogramReport},
+        hooks::{HookConfig, HookHosts},
+        namespace::NamespaceTree,
+        outliers::OutlierPolicy,
+        query::QueryResult,
+        quotas::{TenantQuotas, UsageReport},
+        sampling::SampleRates,
+        states::{StateDefinition, StateMetric, StateReport},
+        storage::StorageBackendKind,
+        transform::{IngestTransform, IngestTransforms},
+        AggregateFn,
+        AggregateMismatch,
+        AggregateWrite,
+        GroupByQuery,
+        MetricOperation,
+        MetricPoint,
+        MetricsRegistry,
+        RangeQuery,
+        RecomputeSummary,
+        StorageStats,
+        Summation,
+        DEFAULT_IDEMPOTENCY_TTL_SECS,
+        DEFAULT_TOMBSTONE_GRACE_SECS,
+        JOURNAL_REPLAYED_TOTAL,
+        check_transaction,
+        run_aggregate_flush,
+        run_maintenance,
+    },
+    raft::{network::LocalNetwork, node::{start_networked_raft_node, start_raft_node, HealthStatus, Proposal, RaftHealth, RaftLag, RaftStatus}, storage::MemStorage},
+    config::{units, DeadlineConfig, MemoryBudget, QueryLimits, RaftConfig, RaftGroupConfig, RaftHealthConfig, RegistrationConfig, SloConfig},
+    error::DeadlineStage,
+    telemetry,
+    api::{
+        consistency::{encode_version, AckLevel, AckQuery, ConsistencyQuery, DEFAULT_VERSION_WAIT},
+        deadline::{attach_deadline, Deadline},
+        downsample::{Downsampler, DOWNSAMPLE_FLUSH_TICK},
+        envelope::envelope_responses,
+        etag::{metric_etag, not_modified},
+        forwarding::{self, refuse_loops, ForwardFrom, Forwarder, ForwardingConfig, ForwardingStatus},
+        freshness::{freshness_headers, MetricTtls},
+        info::NodeInfo,
+        journal::Journal,
+        load::{cpu_hint, WorkerLoad},
+        membership::{HeartbeatRequest, RegisterRequest, RegisterResponse},
+        middleware::{require_admin, trace_requests, track_requests},
+        msgpack::{Encoded, Format, JsonOrMsgpack},
+        preflight::{check_admin_listen, worker_preflight, PreflightReport},
+        push::spawn_pusher,
+        read_only::{refuse_writes, ReadOnly, ReadOnlyQuery},
+        scrape::encode_families,
+        serve::{admin_listen_addr, ctrl_c, serve_split},
+        slo::{slo_status, SloTracker},
+        types::{
+            AggregatesPage,
+            AggregatesQuery,
+            BatchAggregateRequest,
+            BatchAggregateResponse,
+            Bucket,
+            BucketFill,
+            BucketsParams,
+            BucketsResponse,
+            CompareQuery,
+            CompareResponse,
+            DeltaQuery,
+            GroupByParams,
+            GroupByResponse,
+            HistogramQuery,
+            HistoryQuery,
+            IngestError,
+            IngestSummary,
+            MetricAggregateResponse,
+            MetricDeltaResponse,
+            MetricHistoryResponse,
+            MetricRequest,
+            MetricValueResponse,
+            MismatchQuery,
+            MultiGetRequest,
+            MultiGetResponse,
+            PrecisionQuery,
+            QuarantineResponse,
+            QueryRequest,
+            RaftDebugResponse,
+            RangeParams,
+            RecomputeQuery,
+            ResetQuery,
+            SampleRateRequest,
+            SeriesRequest,
+            SeriesResponse,
+            StaleMetricsPage,
+            StaleQuery,
+            TopMetricsResponse,
+            TopQuery,
+            TreeQuery,
+            WorkerHealthResponse,
+            MAX_BATCH_AGGREGATE_NAMES,
+            MAX_MULTI_GET_NAMES,
+        },
+        units::{UnitQuery, UNIT_HEADER},
+    },
 };
 
 #[derive(Clone)]
@@ -23,114 +132,3044 @@ pub struct WorkerState {
     pub storage: Arc<MemStorage>,
     pub metrics: Arc<MetricsRegistry>,
     pub worker_id: usize,
+    pub proposal_tx: mpsc::Sender<Proposal>,
+    pub raft_status: Arc<RaftStatus>,
+    /// Cleared while the worker is rebuilding state; data routes answer 503
+    /// until it is set.
+    pub ready: Arc<AtomicBool>,
+    /// Cleared on a standby, which applies its raft group's log but answers
+    /// data routes with 421 until `POST /admin/promote` sets it.
+    pub serving: Arc<AtomicBool>,
+    /// Control node to register with once serving, if any.
+    pub registration: Option<RegistrationConfig>,
+    /// `ADMIN_TOKEN`, which the control node sends on the cluster-wide
+    /// routes it calls here. Without one they refuse every request.
+    pub admin_token: Option<Arc<str>>,
+    /// Budget for requests that arrive without a deadline header.
+    pub default_deadline: std::time::Duration,
+    /// Longest a read waits for this worker to reach its `min_version`.
+    pub version_wait: std::time::Duration,
+    /// Furthest past this worker's clock a write's own timestamp may be.
+    pub max_future_skew: std::time::Duration,
+    pub slo: Arc<SloTracker>,
+    pub query_limits: QueryLimits,
+    /// Result of the checks run at startup.
+    pub preflight: Arc<PreflightReport>,
+    /// Apply lag and tick gap past which `/health` stops reporting healthy.
+    pub raft_health: RaftHealthConfig,
+    /// Served at `/info`.
+    pub info: Arc<NodeInfo>,
+    /// Freshness of latest values, sent as caching headers.
+    pub ttls: Arc<MetricTtls>,
+    /// Set while writes are refused for maintenance; see
+    /// [`ReadOnly`].
+    pub read_only: Arc<ReadOnly>,
+    /// Thins high-frequency metrics before their records are proposed.
+    pub downsampler: Arc<Downsampler>,
+    /// Keeps proposals until they apply, so a restart proposes them again.
+    pub journal: Arc<Journal>,
+    /// Sends matching writes downstream when `FORWARD_FROM` is `worker`.
+    pub forwarder: Arc<Forwarder>,
+    /// Carries raft messages for a group whose members run apart; remote
+    /// members' messages arrive through `/raft/message`.
+    pub network: Option<LocalNetwork>,
+    /// Faults injected for resilience testing.
+    #[cfg(feature = "chaos")]
+    pub chaos: Arc<ChaosState>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MetricRequest {
-    pub metric_name: String,
-    pub value: f64,
+impl WorkerState {
+    /// Starts the worker's raft group over `metrics` and returns a state whose
+    /// writes go through it.
+    pub fn new(worker_id: usize, metrics: Arc<MetricsRegistry>, raft_config: RaftConfig) -> Result<Self> {
+        let raft_id = worker_id as u64 + 1;
+        let raft = start_raft_node(raft_id, vec![raft_id], metrics.clone(), raft_config)?;
+        Ok(Self::over_raft(worker_id, metrics, raft))
+    }
+
+    /// Like [`WorkerState::new`], as one member of the raft group of `peers`
+    /// and `learners` running in this process and talking over `network`.
+    pub fn in_group(
+        worker_id: usize,
+        metrics: Arc<MetricsRegistry>,
+        raft_config: RaftConfig,
+        peers: Vec<u64>,
+        learners: Vec<u64>,
+        network: LocalNetwork,
+    ) -> Result<Self> {
+        let raft_id = worker_id as u64 + 1;
+        let raft = start_networked_raft_node(raft_id, peers, learners, metrics.clone(), raft_config, network.clone())?;
+        let mut state = Self::over_raft(worker_id, metrics, raft);
+        #[cfg(feature = "chaos")]
+        network.inject_chaos(raft_id, state.chaos.clone());
+        state.network = Some(network);
+        Ok(state)
+    }
+
+    /// Like [`WorkerState::new`], as member `group.id` of a raft group whose
+    /// other members run as their own processes. Messages to them carry
+    /// `admin_token`, which they must share.
+    pub fn joined(
+        worker_id: usize,
+        metrics: Arc<MetricsRegistry>,
+        raft_config: RaftConfig,
+        group: &RaftGroupConfig,
+        admin_token: Option<Arc<str>>,
+    ) -> Result<Self> {
+        let network = LocalNetwork::with_remotes(group.remotes(), admin_token.clone());
+        let peers = group.peers.keys().copied().collect();
+        let learners = group.learners.keys().copied().collect();
+        let raft = start_networked_raft_node(group.id, peers, learners, metrics.clone(), raft_config, network.clone())?;
+        let mut state = Self::over_raft(worker_id, metrics, raft);
+        #[cfg(feature = "chaos")]
+        network.inject_chaos(group.id, state.chaos.clone());
+        state.network = Some(network);
+        state.admin_token = admin_token;
+        Ok(state)
+    }
+
+    fn over_raft(
+        worker_id: usize,
+        metrics: Arc<MetricsRegistry>,
+        (proposal_tx, raft_status): (mpsc::Sender<Proposal>, Arc<RaftStatus>),
+    ) -> Self {
+        Self {
+            storage: Arc::new(MemStorage::new()),
+            worker_id,
+            proposal_tx,
+            raft_status,
+            ready: Arc::new(AtomicBool::new(true)),
+            serving: Arc::new(AtomicBool::new(true)),
+            registration: None,
+            admin_token: None,
+            default_deadline: DeadlineConfig::default().default_budget,
+            version_wait: DEFAULT_VERSION_WAIT,
+            max_future_skew: DEFAULT_MAX_FUTURE_SKEW,
+            slo: Arc::new(SloTracker::new(&SloConfig::default())),
+            query_limits: QueryLimits::default(),
+            preflight: Arc::new(PreflightReport::default()),
+            raft_health: RaftHealthConfig::default(),
+            info: Arc::new(NodeInfo::new("worker", worker_id, metrics.backend_name(), None)),
+            ttls: Arc::new(MetricTtls::default()),
+            read_only: Arc::new(ReadOnly::default()),
+            downsampler: Arc::new(Downsampler::default()),
+            journal: Arc::new(Journal::default()),
+            forwarder: Arc::new(Forwarder::default()),
+            network: None,
+            #[cfg(feature = "chaos")]
+            chaos: Arc::new(ChaosState::default()),
+            metrics,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Release);
+    }
+
+    pub fn is_serving(&self) -> bool {
+        self.serving.load(Ordering::Acquire)
+    }
+
+    /// Starts serving data routes, registering with the control node when
+    /// configured to. Returns whether this call did it; promoting a worker
+    /// that already serves changes nothing.
+    pub fn promote(&self) -> bool {
+        if self.serving.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.join_control();
+        true
+    }
+
+    /// Registers with the control node and starts heartbeats, when
+    /// `CONTROL_URL` and `ADVERTISE_URL` are set.
+    fn join_control(&self) {
+        if let Some(registration) = &self.registration {
+            info!("Registering as {} with {}", registration.advertise_url, registration.control_url);
+            send_heartbeats(registration.clone(), self.clone());
+            announce_to_control(registration.clone());
+        }
+    }
+
+    pub fn raft_lag(&self) -> RaftLag {
+        self.raft_status.lag(&self.raft_health)
+    }
+
+    /// Proposes `operation` and waits until it has been committed and applied.
+    pub async fn propose(&self, operation: MetricOperation) -> Result<()> {
+        let (proposal, applied) = Proposal::new(operation);
+        let applied = self.send_journaled(proposal, applied).await?;
+        applied.instrument(commit_wait_span()).await
+            .map_err(|_| RaftMetricsError::Internal("Raft node dropped the proposal".to_string()))?
+    }
+
+    /// Proposes `operation` and waits until it reaches `ack` or `deadline`
+    /// runs out. Returns the level reached. An entry applies only once a
+    /// majority has committed it, so a write that applied here reports
+    /// `quorum` whichever level was asked for. A `quorum` write still
+    /// uncommitted near the deadline reports `none` when it is in this
+    /// node's log, and a `local` one fails with the deadline.
+    pub async fn propose_acked(&self, operation: MetricOperation, ack: AckLevel, deadline: &Deadline) -> Result<AckLevel> {
+        let (proposal, applied, mut appended) = Proposal::tracked(operation);
+        let mut applied = self.send_journaled(proposal, applied).await?.instrument(commit_wait_span());
+        let committed = |outcome: std::result::Result<Result<()>, tokio::sync::oneshot::error::RecvError>| -> Result<AckLevel> {
+            outcome
+                .map_err(|_| RaftMetricsError::Internal("Raft node dropped the proposal".to_string()))?
+                .map(|()| AckLevel::Quorum)
+        };
+
+        match ack {
+            AckLevel::None => Ok(AckLevel::None),
+            AckLevel::Local => deadline.run(DeadlineStage::Worker, async { committed(applied.await) }).await,
+            AckLevel::Quorum => {
+                // Stop short of the deadline so the caller still hears what was reached
+                let within = deadline.remaining().saturating_sub(ACK_REPLY_MARGIN);
+                match tokio::time::timeout(within, &mut applied).await {
+                    Ok(outcome) => committed(outcome),
+                    Err(_) if appended.try_recv().is_ok() => {
+                        warn!("Worker {} write missed its quorum commit within {}ms", self.worker_id, within.as_millis());
+                        Ok(AckLevel::None)
+                    }
+                    Err(_) => Err(deadline.exceeded(DeadlineStage::Worker)),
+                }
+            }
+        }
+    }
+
+    /// Proposes every operation before waiting on any, so raft packs them
+    /// into as few log entries as its batching limits allow.
+    pub async fn propose_all(&self, operations: Vec<MetricOperation>) -> Result<Vec<Result<()>>> {
+        let mut pending = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let (proposal, applied) = Proposal::new(operation);
+            pending.push(self.send_journaled(proposal, applied).await?);
+        }
+
+        let mut results = Vec::with_capacity(pending.len());
+        let span = commit_wait_span();
+        for applied in pending {
+            results.push(applied.instrument(span.clone()).await.unwrap_or_else(|_| {
+                Err(RaftMetricsError::Internal("Raft node dropped the proposal".to_string()))
+            }));
+        }
+        Ok(results)
+    }
+
+    /// Admits `proposal` through [`MetricsRegistry::admit`] and checks it
+    /// against the tenant quotas, journals it when a journal is configured,
+    /// then sends it to raft. The returned receiver gives its outcome, as
+    /// `applied` would, once the journal entry has been marked done; an
+    /// operation whose samples were all sampled out succeeds at once, and
+    /// one over a quota fails at once. A journaled operation is keyed, so
+    /// replaying it after a restart cannot apply it twice.
+    async fn send_journaled(
+        &self,
+        mut proposal: Proposal,
+        applied: oneshot::Receiver<Result<()>>,
+    ) -> Result<oneshot::Receiver<Result<()>>> {
+        let operation = std::mem::replace(&mut proposal.operation, MetricOperation::Clear);
+        let Some(operation) = self.metrics.admit(operation) else {
+            let _ = proposal.respond_to.send(Ok(()));
+            return Ok(applied);
+        };
+        if let Err(e) = self.metrics.check_quota(&operation).await {
+            let _ = proposal.respond_to.send(Err(e));
+            return Ok(applied);
+        }
+        proposal.operation = operation;
+        let span = tracing::info_span!(
+            "raft.propose",
+            metric.name = proposal.operation.name().map(telemetry::metric_attr),
+            operation = proposal.operation.kind(),
+            worker.id = self.worker_id,
+        );
+        // The entry's apply is traced under this proposal
+        proposal.span = span.clone();
+        if self.journal.enabled() {
+            let operation = std::mem::replace(&mut proposal.operation, MetricOperation::Clear);
+            proposal.operation = MetricOperation::Keyed {
+                key: uuid::Uuid::new_v4().to_string(),
+                proposed_at: chrono::Utc::now().timestamp(),
+                operation: Box::new(operation),
+            };
+        }
+        let id = self.journal.append(&proposal.operation).instrument(span.clone()).await?;
+        if self.enqueue(proposal).instrument(span).await.is_err() {
+            if let Some(id) = id {
+                self.journal.complete(id).await;
+            }
+            return Err(RaftMetricsError::Internal("Raft node is not running".to_string()));
+        }
+        Ok(match id {
+            Some(id) => self.complete_on_apply(id, applied),
+            None => applied,
+        })
+    }
+
+    /// Sends `proposal` to the raft loop, waiting while the channel is full,
+    /// and publishes how many proposals are left waiting.
+    async fn enqueue(&self, proposal: Proposal) -> std::result::Result<(), mpsc::error::SendError<Proposal>> {
+        let sent = self.proposal_tx.send(proposal).await;
+        self.raft_status.publish_queue_depth(self.proposal_tx.max_capacity() - self.proposal_tx.capacity());
+        sent
+    }
+
+    /// Marks journal entry `id` done once `applied` has an outcome, which
+    /// is passed on through the returned receiver.
+    fn complete_on_apply(&self, id: u64, applied: oneshot::Receiver<Result<()>>) -> oneshot::Receiver<Result<()>> {
+        let (respond_to, outcome) = oneshot::channel();
+        let journal = self.journal.clone();
+        tokio::spawn(async move {
+            let result = applied.await;
+            journal.complete(id).await;
+            if let Ok(result) = result {
+                let _ = respond_to.send(result);
+            }
+        });
+        outcome
+    }
+
+    /// Proposes again the operations the journal held when the worker
+    /// started, waiting until each has applied or failed. Returns how many
+    /// applied.
+    pub async fn replay_journal(&self) -> usize {
+        let recovered = self.journal.take_recovered();
+        if recovered.is_empty() {
+            return 0;
+        }
+        info!("Worker {} replaying {} journaled operations", self.worker_id, recovered.len());
+        let mut pending = Vec::with_capacity(recovered.len());
+        for (id, operation) in recovered {
+            let (proposal, applied) = Proposal::new(operation);
+            if self.enqueue(proposal).await.is_err() {
+                warn!("Worker {} stopped replaying its journal: raft node is not running", self.worker_id);
+                break;
+            }
+            pending.push((id, self.complete_on_apply(id, applied)));
+        }
+
+        let mut replayed = 0;
+        for (id, applied) in pending {
+            match applied.await {
+                Ok(Ok(())) => replayed += 1,
+                Ok(Err(e)) => warn!("Worker {} could not replay journal entry {}: {}", self.worker_id, id, e),
+                Err(_) => warn!("Worker {} could not replay journal entry {}: raft node dropped it", self.worker_id, id),
+            }
+        }
+        JOURNAL_REPLAYED_TOTAL.inc_by(replayed as u64);
+        replayed
+    }
+
+    /// Proposes summaries taken from the downsampler. A summary that fails
+    /// is logged and lost, as its samples were already acknowledged.
+    pub async fn propose_summaries(&self, summaries: Vec<MetricOperation>) {
+        if summaries.is_empty() {
+            return;
+        }
+        let names: Vec<String> = summaries.iter().filter_map(MetricOperation::name).map(str::to_string).collect();
+        match self.propose_all(summaries).await {
+            Ok(results) => {
+                for (name, result) in names.iter().zip(results) {
+                    if let Err(e) = result {
+                        warn!("Worker {} failed to record the downsampled samples of '{}': {}", self.worker_id, name, e);
+                    }
+                }
+            }
+            Err(e) => warn!("Worker {} failed to propose {} downsampled summaries: {}", self.worker_id, names.len(), e),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WorkerMetricResponse {
-    pub name: String,
-    pub value: f64,
-    pub timestamp: i64,
+/// Proposes the downsampler's summaries as their intervals end.
+async fn run_downsample_flush(state: WorkerState) {
+    let mut tick = tokio::time::interval(DOWNSAMPLE_FLUSH_TICK);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tick.tick().await;
+        state.propose_summaries(state.downsampler.due(tokio::time::Instant::now())).await;
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MetricAggregateResponse {
-    pub name: String,
-    pub count: u64,
-    pub sum: f64,
-    pub average: f64,
-    pub min: f64,
-    pub max: f64,
+/// Time kept back from a write's deadline to answer with the
+/// acknowledgment level it reached.
+const ACK_REPLY_MARGIN: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Furthest ahead of the worker's clock a write's timestamp may be without
+/// `MAX_FUTURE_SKEW_SECS`.
+pub const DEFAULT_MAX_FUTURE_SKEW: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Span around waiting for proposals to commit and apply.
+fn commit_wait_span() -> tracing::Span {
+    tracing::info_span!("raft.commit_wait")
 }
 
+/// Samples a `GET /metrics/:name/compare` baseline is worked out from
+/// without a `samples`.
+pub const DEFAULT_COMPARE_SAMPLES: usize = 100;
+
+/// Samples returned by `GET /metrics/:name/history` without a `limit`.
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Samples read from the store per chunk of a streamed range response.
+pub const RANGE_PAGE_SIZE: usize = 1000;
+
+/// Most groups a group-by returns; the rest are dropped and the response
+/// marked truncated.
+pub const MAX_GROUPS: usize = 1000;
+
+/// Most buckets a bucketed read may span, empty ones included.
+pub const MAX_BUCKETS: u64 = 11_000;
+/// Bucket width when `step` is unset.
+pub const DEFAULT_BUCKET_STEP: i64 = 60;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// The bucket width used for a requested `step`: the smallest width at
+/// least as wide that divides a day evenly, or a whole number of days, so
+/// buckets line up with minutes, hours and days however the step is asked.
+pub fn bucket_step(step: i64) -> Result<i64> {
+    if step <= 0 {
+        return Err(RaftMetricsError::InvalidRequest("'step' must be positive".to_string()));
+    }
+    match step < SECONDS_PER_DAY {
+        true => Ok((step..SECONDS_PER_DAY).find(|width| SECONDS_PER_DAY % width == 0).unwrap_or(SECONDS_PER_DAY)),
+        false => step
+            .checked_add(SECONDS_PER_DAY - 1)
+            .map(|step| step / SECONDS_PER_DAY * SECONDS_PER_DAY)
+            .ok_or_else(|| RaftMetricsError::InvalidRequest("'step' is too large".to_string())),
+    }
+}
+
+/// Parses `?fn=avg,max,count` into functions in response order, defaulting
+/// to all of them.
+fn bucket_functions(functions: Option<&str>) -> Result<Vec<AggregateFn>> {
+    let functions: BTreeSet<AggregateFn> = match functions.filter(|functions| !functions.trim().is_empty()) {
+        Some(functions) => functions.split(',').map(|function| AggregateFn::from_name(function.trim())).collect::<Result<_>>()?,
+        None => [AggregateFn::Avg, AggregateFn::Sum, AggregateFn::Min, AggregateFn::Max, AggregateFn::Count].into(),
+    };
+    Ok(functions.into_iter().collect())
+}
+
+/// Serves `state` on one listener, data and admin routes together.
 pub fn worker_router(state: WorkerState) -> Router {
-    Router::new()
-        .route("/health", get(health_check))
+    data_router(state.clone()).merge(admin_router(state))
+}
+
+/// Ingest and reads, with health, readiness, load and info. `/admin/reset`,
+/// `/admin/quotas` and `/admin/readonly` stay here because the control
+/// node calls them on every worker's data URL; `/admin/readonly` is outside
+/// the write refusal so a read-only worker can be switched back.
+pub fn data_router(state: WorkerState) -> Router {
+    let default_budget = state.default_deadline;
+    let node_id = state.info.node_id;
+    let data_routes = Router::new()
         .route("/process", post(process_metric))
-        .route("/metrics/:name", get(get_metric))
+        .route("/process/batch", post(process_batch))
+        .route("/process/transaction", post(process_transaction))
+        .route("/metrics/:name", get(get_metric).delete(delete_metric))
+        .route("/metrics/multi_get", post(get_metrics))
+        .route("/metrics/get", post(get_series))
+        .route("/metrics/stale", get(get_stale_metrics))
+        .route("/metrics/tree", get(get_metric_tree))
         .route("/metrics/:name/aggregate", get(get_metric_aggregate))
+        .route("/aggregate/batch", post(get_aggregate_batch))
+        .route("/aggregates", get(get_aggregates))
+        .route("/metrics/:name/delta", get(get_metric_delta))
+        .route("/metrics/:name/compare", get(compare_metric))
+        .route("/metrics/:name/history", get(get_metric_history))
+        .route("/metrics/:name/range", get(get_metric_range))
+        .route("/metrics/:name/groupby", get(get_metric_groupby))
+        .route("/metrics/:name/buckets", get(get_metric_buckets))
+        .route("/metrics/:name/sample_rate", put(put_sample_rate).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .route("/metrics/:name/transform", put(put_transform))
+        .route("/metrics/:name/states", get(get_states).put(put_states))
+        .route("/metrics/:name/histogram", get(get_histogram).put(put_histogram))
+        .route("/metrics/:name/quarantine", get(get_quarantine))
+        .route("/metrics/:name/quarantine/apply", post(apply_quarantine))
+        .route("/metrics/:name/reset", post(reset_metric))
+        .route("/query", post(run_query))
+        .route("/usage", get(get_usage))
+        .route("/admin/reset", post(reset).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .route("/admin/quotas", put(put_quotas).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .route("/hooks/:id", put(put_hook).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .route_layer(middleware::from_fn_with_state(state.forwarder.config().max_hops, refuse_loops))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_ready))
+        .route_layer(middleware::from_fn_with_state(state.read_only.clone(), refuse_writes));
+    #[cfg(feature = "chaos")]
+    let data_routes = if state.chaos.enabled() {
+        data_routes.route_layer(middleware::from_fn_with_state(state.chaos.clone(), inject_faults))
+    } else {
+        data_routes
+    };
+
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/ready", get(readiness))
+        .route("/load", get(get_load))
+        .merge(Router::new().route("/slo", get(slo_status)).with_state(state.slo.clone()))
+        .route("/info", get(get_info))
+        .route("/forwarding/status", get(forwarding_status))
+        .route("/admin/readonly", post(set_read_only).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .merge(data_routes)
+        .route_layer(middleware::from_fn_with_state(state.slo.clone(), track_requests))
+        .route_layer(middleware::from_fn(trace_requests))
+        .layer(middleware::from_fn_with_state(default_budget, attach_deadline))
+        .layer(middleware::from_fn_with_state(node_id, envelope_responses))
         .with_state(state)
 }
 
-async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "message": "Worker node is operational"
+/// Debug, raft, maintenance, chaos and Prometheus routes, on `ADMIN_PORT`
+/// when one is set.
+pub fn admin_router(state: WorkerState) -> Router {
+    let default_budget = state.default_deadline;
+    let node_id = state.info.node_id;
+    let ready_routes = Router::new()
+        .route("/debug/stats", get(storage_stats))
+        .route("/debug/aggregates", get(aggregate_mismatches))
+        .route("/debug/top", get(top_metrics))
+        .route(
+            "/admin/recompute_aggregates",
+            post(recompute_aggregates).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)),
+        )
+        .route("/admin/preflight", get(preflight_report))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_ready));
+    #[cfg(feature = "chaos")]
+    let ready_routes = if state.chaos.enabled() {
+        ready_routes.merge(chaos_routes(state.chaos.clone()))
+    } else {
+        ready_routes
+    };
+
+    Router::new()
+        .route("/prometheus", get(scrape))
+        .route("/raft/status", get(raft_debug))
+        .route("/debug/raft", get(raft_debug))
+        .route("/admin/promote", post(promote).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .route("/raft/message", post(receive_raft_message).route_layer(middleware::from_fn_with_state(state.admin_token.clone(), require_admin)))
+        .merge(ready_routes)
+        .route_layer(middleware::from_fn_with_state(state.slo.clone(), track_requests))
+        .route_layer(middleware::from_fn(trace_requests))
+        .layer(middleware::from_fn_with_state(default_budget, attach_deadline))
+        .layer(middleware::from_fn_with_state(node_id, envelope_responses))
+        .with_state(state)
+}
+
+/// Rejects requests until startup recovery has finished, and on a standby
+/// until it is promoted.
+async fn require_ready(State(state): State<WorkerState>, request: Request, next: Next) -> Response {
+    if !state.is_ready() {
+        return RaftMetricsError::Unavailable("worker is recovering state".to_string()).into_response();
+    }
+    if !state.is_serving() {
+        return RaftMetricsError::NotServing(format!("worker {} is a standby", state.worker_id)).into_response();
+    }
+    next.run(request).await
+}
+
+/// How busy this worker is, for the control node's least-loaded routing.
+async fn get_load(State(state): State<WorkerState>) -> Json<WorkerLoad> {
+    let proposals = &state.proposal_tx;
+    Json(WorkerLoad {
+        metric_count: state.metrics.resident_count() as u64,
+        records_total: state.metrics.records_total(),
+        proposal_queue_depth: (proposals.max_capacity() - proposals.capacity()) as u64,
+        cpu_hint: cpu_hint(),
+    })
+}
+
+/// Turns a standby into a serving worker. Safe to repeat: `promoted` says
+/// whether this call did it. A standby that refused a snapshot without
+/// data is missing writes, so it is not promoted.
+async fn promote(State(state): State<WorkerState>) -> Result<Json<serde_json::Value>> {
+    if let Some(index) = state.raft_status.refused_snapshot().filter(|_| !state.is_serving()) {
+        return Err(RaftMetricsError::Conflict(format!(
+            "worker {} refused a snapshot at index {} without data and is missing writes up to it; seed it from a copy of a current store first",
+            state.worker_id, index
+        )));
+    }
+    let promoted = state.promote();
+    if promoted {
+        warn!("Worker {} promoted from standby", state.worker_id);
+    }
+    Ok(Json(serde_json::json!({ "serving": true, "promoted": promoted })))
+}
+
+/// Takes a raft message from another member of this worker's group.
+async fn receive_raft_message(State(state): State<WorkerState>, body: Bytes) -> Result<StatusCode> {
+    let Some(network) = &state.network else {
+        return Err(RaftMetricsError::NotFound);
+    };
+    let message = raft::eraftpb::Message::decode(body).map_err(|e| RaftMetricsError::InvalidRequest(format!("Invalid raft message: {}", e)))?;
+    network.deliver(message);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Turns read-only mode on or off, for the control node to pass its own
+/// switch on.
+async fn set_read_only(State(state): State<WorkerState>, Query(query): Query<ReadOnlyQuery>) -> Result<Json<serde_json::Value>> {
+    state.read_only.set(query.enabled)?;
+    Ok(Json(serde_json::json!({ "read_only": query.enabled })))
+}
+
+async fn get_info(State(state): State<WorkerState>) -> Json<NodeInfo> {
+    Json(NodeInfo { read_only: state.read_only.enabled(), ..state.info.as_ref().clone() })
+}
+
+async fn forwarding_status(State(state): State<WorkerState>) -> Json<ForwardingStatus> {
+    Json(state.forwarder.status())
+}
+
+/// Reports the worker's health, answering 503 while its raft loop is stuck.
+async fn health_check(State(state): State<WorkerState>) -> (StatusCode, Json<WorkerHealthResponse>) {
+    let raft = state.raft_lag();
+    let (code, status, message) = match &raft.health {
+        RaftHealth { status: HealthStatus::Unhealthy, reason } => (StatusCode::SERVICE_UNAVAILABLE, "unhealthy", reason.clone()),
+        RaftHealth { status: HealthStatus::Degraded, reason } => (StatusCode::OK, "degraded", reason.clone()),
+        _ if state.slo.degraded() => (StatusCode::OK, "degraded", Some("Worker node is burning an SLO error budget".to_string())),
+        _ => (StatusCode::OK, "healthy", Some("Worker node is operational".to_string())),
+    };
+    (code, Json(WorkerHealthResponse {
+        status: status.to_string(),
+        message,
+        raft,
+        read_only: state.read_only.enabled(),
     }))
 }
 
+async fn readiness(State(state): State<WorkerState>) -> impl IntoResponse {
+    if state.is_ready() {
+        (StatusCode::OK, Json(serde_json::json!({ "status": "ready" })))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "status": "recovering" })))
+    }
+}
+
+/// Records one metric, answering `202 Accepted` when it reached a lower
+/// acknowledgment level than `?ack=` asked for.
 async fn process_metric(
     State(state): State<WorkerState>,
-    Json(request): Json<MetricRequest>,
-) -> Result<Json<WorkerMetricResponse>> {
+    Extension(deadline): Extension<Deadline>,
+    Query(ack): Query<AckQuery>,
+    headers: HeaderMap,
+    format: Format,
+    JsonOrMsgpack(request): JsonOrMsgpack<MetricRequest>,
+) -> Result<(StatusCode, Encoded<MetricValueResponse>)> {
     info!(
         "Worker {} processing metric: {} = {}",
         state.worker_id, request.metric_name, request.value
     );
     
-    state.metrics.record_metric(&request.metric_name, request.value).await?;
+    let timestamp = sample_timestamp(request.timestamp, chrono::Utc::now().timestamp(), state.max_future_skew)?;
+    let value = request_value(&state.metrics, &request)?;
+    let operation = MetricOperation::record(request.metric_name.clone(), value, timestamp, request.labels);
+    let reached = match state.downsampler.admit(operation, tokio::time::Instant::now()) {
+        Some(operation) => {
+            let forwarded = state.forwarder.wants(&request.metric_name).then(|| operation.clone());
+            let reached = state.propose_acked(operation, ack.ack, &deadline).await?;
+            if let Some(operation) = forwarded {
+                state.forwarder.offer_operation(&operation, forwarding::hops(&headers));
+            }
+            reached
+        }
+        // Dropped or held for a summary by a downsample rule
+        None => AckLevel::None,
+    };
     
-    Ok(Json(WorkerMetricResponse {
+    let status = if reached < ack.ack { StatusCode::ACCEPTED } else { StatusCode::OK };
+    Ok((status, format.respond(MetricValueResponse {
         name: request.metric_name,
-        value: request.value,
-        timestamp: chrono::Utc::now().timestamp(),
-    }))
+        value,
+        timestamp,
+        worker_id: state.worker_id,
+        unit: None,
+        version: (reached == AckLevel::Quorum).then(|| encode_version(state.metrics.applied_index())),
+        ack: Some(reached),
+        source: None,
+        expires_at: None,
+        state: request.state,
+    })))
+}
+
+async fn process_batch(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    headers: HeaderMap,
+    Json(requests): Json<Vec<MetricRequest>>,
+) -> Result<Json<IngestSummary>> {
+    info!("Worker {} processing batch of {} metrics", state.worker_id, requests.len());
+
+    let now = chrono::Utc::now().timestamp();
+    let count = requests.len() as u64;
+    // Records a downsample rule holds back count as accepted
+    let arrived = tokio::time::Instant::now();
+    let mut errors = Vec::new();
+    let (indices, operations): (Vec<usize>, Vec<MetricOperation>) = requests
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, request)| match record_operation(&state, request, now) {
+            Ok(operation) => Some((index, state.downsampler.admit(operation, arrived)?)),
+            Err(e) => {
+                errors.push(IngestError { index, code: e.code(), message: e.to_string() });
+                None
+            }
+        })
+        .unzip();
+    let forwarded = state.forwarder.is_active().then(|| operations.clone());
+    let results = deadline.run(DeadlineStage::Worker, state.propose_all(operations)).await?;
+    for (operation, result) in forwarded.iter().flatten().zip(&results) {
+        if result.is_ok() {
+            state.forwarder.offer_operation(operation, forwarding::hops(&headers));
+        }
+    }
+
+    errors.extend(indices.into_iter().zip(results).filter_map(|(index, result)| {
+        let e = result.err()?;
+        Some(IngestError { index, code: e.code(), message: e.to_string() })
+    }));
+    errors.sort_by_key(|error| error.index);
+    let rejected = errors.len() as u64;
+    Ok(Json(IngestSummary { accepted: count - rejected, rejected, errors, rate_limited: 0 }))
+}
+
+/// Records every metric in the request or none of them, as one raft
+/// operation. The control node has checked that they all belong here.
+async fn process_transaction(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    headers: HeaderMap,
+    Json(requests): Json<Vec<MetricRequest>>,
+) -> Result<Json<IngestSummary>> {
+    info!("Worker {} processing transaction of {} metrics", state.worker_id, requests.len());
+
+    let now = chrono::Utc::now().timestamp();
+    let operations = requests
+        .into_iter()
+        .map(|request| record_operation(&state, request, now))
+        .collect::<Result<Vec<_>>>()?;
+    check_transaction(&operations)?;
+    let accepted = operations.len() as u64;
+    let transaction = MetricOperation::Transaction(operations);
+    let forwarded = state.forwarder.is_active().then(|| transaction.clone());
+    deadline.run(DeadlineStage::Worker, state.propose(transaction)).await?;
+    if let Some(transaction) = forwarded {
+        state.forwarder.offer_operation(&transaction, forwarding::hops(&headers));
+    }
+
+    Ok(Json(IngestSummary { accepted, rejected: 0, errors: Vec::new(), rate_limited: 0 }))
+}
+
+/// The write a request asks for, stamped `now` when it carries no timestamp.
+fn record_operation(state: &WorkerState, request: MetricRequest, now: i64) -> Result<MetricOperation> {
+    let timestamp = sample_timestamp(request.timestamp, now, state.max_future_skew)?;
+    let value = request_value(&state.metrics, &request)?;
+    Ok(MetricOperation::record(request.metric_name, value, timestamp, request.labels))
 }
 
+/// A write's timestamp, `now` when it carries none. A timestamp more than
+/// `max_skew` past `now` is refused, as it would stay the latest value
+/// until real time caught up with it.
+fn sample_timestamp(requested: Option<i64>, now: i64, max_skew: std::time::Duration) -> Result<i64> {
+    let Some(timestamp) = requested else {
+        return Ok(now);
+    };
+    let limit = now.saturating_add(i64::try_from(max_skew.as_secs()).unwrap_or(i64::MAX));
+    if timestamp > limit {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "timestamp {} is more than {}s ahead of this worker's clock",
+            timestamp,
+            max_skew.as_secs()
+        )));
+    }
+    Ok(timestamp)
+}
+
+/// The value a request records: its `value`, or the index of the state
+/// it names for a state metric.
+fn request_value(metrics: &MetricsRegistry, request: &MetricRequest) -> Result<f64> {
+    let invalid = |message: String| Err(RaftMetricsError::InvalidRequest(message));
+    match (&request.state, request.value.is_nan()) {
+        (None, false) => Ok(request.value),
+        (None, true) => invalid(format!("a value is required for '{}'", request.metric_name)),
+        (Some(_), false) => invalid("pass either a value or a state, not both".to_string()),
+        (Some(name), true) => match metrics.state_metric(&request.metric_name) {
+            Some(metric) => Ok(metric.lookup(name)? as f64),
+            None => invalid(format!("'{}' is not a state metric", request.metric_name)),
+        },
+    }
+}
+
+/// Latest value of a metric, tagged with a weak ETag of its latest sample.
+/// A request whose `If-None-Match` still matches gets `304 Not Modified`.
+/// With `?as_unit=` the value is converted before it is rounded.
 async fn get_metric(
     State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
     Path(name): Path<String>,
-) -> Result<Json<WorkerMetricResponse>> {
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Query(units): Query<UnitQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
     info!("Worker {} retrieving metric: {}", state.worker_id, name);
+    let round = precision.rounder()?;
+    let (convert, unit) = units.converter(&name)?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
     
-    let value = state.metrics.get_metric(&name).await?
+    let latest = deadline.run(DeadlineStage::Worker, state.metrics.get_latest(&name)).await?
         .ok_or(RaftMetricsError::NotFound)?;
+    let etag = metric_etag(&latest);
+    let expires_at = state.ttls.expires_at(&name, latest.timestamp);
+    let freshness = expires_at.map(|expires_at| freshness_headers(expires_at, chrono::Utc::now().timestamp()));
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, freshness, [(ETAG, etag)]).into_response());
+    }
     
-    Ok(Json(WorkerMetricResponse {
+    Ok((freshness, [(ETAG, etag)], Json(MetricValueResponse {
         name: name.clone(),
-        value,
-        timestamp: chrono::Utc::now().timestamp(),
-    }))
+        value: round(convert(latest.value))?,
+        timestamp: latest.timestamp,
+        worker_id: state.worker_id,
+        unit,
+        version: None,
+        ack: None,
+        source: None,
+        expires_at,
+        state: state.metrics.current_state(&name),
+    })).into_response())
 }
 
 async fn get_metric_aggregate(
     State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
     Path(name): Path<String>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Query(units): Query<UnitQuery>,
 ) -> Result<Json<MetricAggregateResponse>> {
     info!("Worker {} calculating aggregate for metric: {}", state.worker_id, name);
+    let round = precision.rounder()?;
+    let (convert, unit) = units.converter(&name)?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
     
-    let aggregate = state.metrics.get_metric_aggregate(&name).await?
-        .ok_or(RaftMetricsError::NotFound)?;
+    let (aggregate, latest) = deadline.run(DeadlineStage::Worker, async {
+        Ok((state.metrics.get_metric_aggregate(&name).await?, state.metrics.get_metric(&name).await?))
+    }).await?;
+    let (Some(aggregate), Some(latest)) = (aggregate, latest) else {
+        return Err(RaftMetricsError::NotFound);
+    };
     
-    Ok(Json(MetricAggregateResponse {
-        name: name.clone(),
-        count: aggregate.count,
-        sum: aggregate.sum,
-        average: aggregate.average,
-        min: aggregate.min,
-        max: aggregate.max,
+    let mut response = MetricAggregateResponse::new(name, state.worker_id, &aggregate, latest, |value| round(convert(value)))?;
+    response.unit = unit;
+    Ok(Json(response))
+}
+
+/// Aggregates for each of `names`, in one round trip.
+async fn get_aggregate_batch(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Json(request): Json<BatchAggregateRequest>,
+) -> Result<Json<BatchAggregateResponse>> {
+    info!("Worker {} calculating aggregates for {} metrics", state.worker_id, request.names.len());
+    if request.names.len() > MAX_BATCH_AGGREGATE_NAMES {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "at most {} names per batch", MAX_BATCH_AGGREGATE_NAMES
+        )));
+    }
+    let round = precision.rounder()?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    let aggregates = deadline.run(DeadlineStage::Worker, async {
+        let mut aggregates = BatchAggregateResponse::with_capacity(request.names.len());
+        for name in request.names {
+            let aggregate = state.metrics.get_metric_aggregate(&name).await?;
+            let latest = state.metrics.get_metric(&name).await?;
+            let response = aggregate
+                .zip(latest)
+                .map(|(aggregate, latest)| MetricAggregateResponse::new(name.clone(), state.worker_id, &aggregate, latest, &round))
+                .transpose()?;
+            aggregates.insert(name, response);
+        }
+        Ok(aggregates)
+    }).await?;
+    Ok(Json(aggregates))
+}
+
+async fn get_metrics(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Json(request): Json<MultiGetRequest>,
+) -> Result<Json<MultiGetResponse>> {
+    info!("Worker {} retrieving {} metrics", state.worker_id, request.names.len());
+    if request.names.len() > MAX_MULTI_GET_NAMES {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "at most {} names per request", MAX_MULTI_GET_NAMES
+        )));
+    }
+    let round = precision.rounder()?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    let mut values = deadline.run(DeadlineStage::Worker, state.metrics.get_metrics(&request.names)).await?;
+    for point in values.values_mut() {
+        point.value = round(point.value)?;
+    }
+    let mut missing: Vec<String> = request.names.into_iter().filter(|name| !values.contains_key(name)).collect();
+    missing.sort();
+    missing.dedup();
+    Ok(Json(MultiGetResponse { values, missing, errors: BTreeMap::new() }))
+}
+
+/// The series of a metric with exactly the labels asked for; `404` when
+/// none has that label set, even if others of the name exist.
+async fn get_series(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Json(request): Json<SeriesRequest>,
+) -> Result<Json<SeriesResponse>> {
+    info!("Worker {} retrieving series of {} with {} labels", state.worker_id, request.name, request.labels.len());
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    let series = deadline.run(DeadlineStage::Worker, state.metrics.get_series(&request.name, &request.labels)).await?
+        .ok_or(RaftMetricsError::NotFound)?;
+    Ok(Json(SeriesResponse {
+        name: request.name,
+        labels: request.labels,
+        value: series.latest.value,
+        timestamp: series.latest.timestamp,
+        worker_id: state.worker_id,
+        count: series.count,
+        sum: series.sum,
+        average: series.sum / series.count as f64,
+        min: series.min,
+        max: series.max,
     }))
 }
 
-pub async fn start_worker_node(worker_id: usize) {
-    let storage = Arc::new(MemStorage::new());
-    let metrics = Arc::new(MetricsRegistry::new());
+async fn get_stale_metrics(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<StaleQuery>,
+) -> Result<Json<StaleMetricsPage>> {
+    info!("Worker {} listing metrics idle for {}s", state.worker_id, query.idle_seconds);
+    let limit = query.limit()?;
+    let after = query.after.as_deref().unwrap_or("");
+
+    let metrics = deadline.run(DeadlineStage::Worker, state.metrics.stale_metrics(query.idle_seconds, after, limit)).await?;
+    let next = (metrics.len() == limit).then(|| metrics[limit - 1].name.clone());
+    Ok(Json(StaleMetricsPage { metrics, next }))
+}
 
-    let state = WorkerState {
-        storage: storage.clone(),
-        metrics: metrics.clone(),
-        worker_id,
+/// Every aggregate this worker holds, a page at a time.
+async fn get_aggregates(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<AggregatesQuery>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+) -> Result<Json<AggregatesPage>> {
+    info!("Worker {} listing aggregates under '{}'", state.worker_id, query.prefix);
+    let limit = query.limit()?;
+    let round = precision.rounder()?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+    let after = query.after.as_deref().unwrap_or("");
+
+    let page = deadline.run(DeadlineStage::Worker, async {
+        let aggregates = state.metrics.aggregates_under(&query.prefix, after, limit).await?;
+        let names: Vec<String> = aggregates.iter().map(|(name, _)| name.clone()).collect();
+        let latest = state.metrics.get_metrics(&names).await?;
+        let next = (aggregates.len() == limit).then(|| names[limit - 1].clone());
+        let aggregates = aggregates
+            .into_iter()
+            .filter_map(|(name, aggregate)| {
+                let latest = latest.get(&name)?.value;
+                Some(MetricAggregateResponse::new(name, state.worker_id, &aggregate, latest, &round))
+            })
+            .collect::<Result<_>>()?;
+        Ok(AggregatesPage { aggregates, next })
+    }).await?;
+    Ok(Json(page))
+}
+
+/// Aggregates of the metrics under a prefix rolled up by namespace.
+async fn get_metric_tree(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Query(query): Query<TreeQuery>,
+) -> Result<Json<NamespaceTree>> {
+    info!("Worker {} rolling up metrics under '{}'", state.worker_id, query.prefix);
+    let shape = query.shape()?;
+
+    let tree = deadline.run(DeadlineStage::Worker, state.metrics.namespace_tree(&shape)).await?;
+    Ok(Json(tree))
+}
+
+async fn get_metric_delta(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<DeltaQuery>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Query(units): Query<UnitQuery>,
+) -> Result<Json<MetricDeltaResponse>> {
+    info!("Worker {} calculating delta for metric: {} ({}..{})", state.worker_id, name, query.from, query.to);
+    let round = precision.rounder()?;
+    let (convert, unit) = units.converter(&name)?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    if query.from > query.to {
+        return Err(RaftMetricsError::InvalidRequest("'from' must not be after 'to'".to_string()));
+    }
+
+    let bounds = state.metrics.get_range_bounds(&name, query.from, query.to);
+    let (mut earliest, mut latest) = deadline.run(DeadlineStage::Worker, bounds).await?
+        .ok_or_else(|| RaftMetricsError::NoData(format!(
+            "metric '{}' has no samples between {} and {}", name, query.from, query.to
+        )))?;
+
+    let delta = round(convert(latest.value - earliest.value))?;
+    earliest.value = round(convert(earliest.value))?;
+    latest.value = round(convert(latest.value))?;
+    Ok(Json(MetricDeltaResponse {
+        name,
+        delta,
+        from: earliest,
+        to: latest,
+        unit,
+    }))
+}
+
+/// Whether `name`'s latest value breaches `?op=` against a constant
+/// `?value=`, or against a `?baseline=` statistic of the `?samples=`
+/// samples before it.
+async fn compare_metric(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<CompareQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+) -> Result<Json<CompareResponse>> {
+    info!("Worker {} comparing metric: {}", state.worker_id, name);
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    let current = deadline.run(DeadlineStage::Worker, state.metrics.get_metric(&name)).await?
+        .ok_or(RaftMetricsError::NotFound)?;
+    let threshold = match (query.value, query.baseline) {
+        (Some(value), None) => value,
+        (None, Some(baseline)) => {
+            let max = state.query_limits.max_history_samples;
+            let samples = match query.samples.unwrap_or(DEFAULT_COMPARE_SAMPLES.min(max)) {
+                0 => return Err(RaftMetricsError::InvalidRequest("samples must be positive".to_string())),
+                samples if samples > max => {
+                    return Err(RaftMetricsError::InvalidRequest(format!("samples must be at most {}", max)));
+                }
+                samples => samples,
+            };
+            // The newest sample is the current value, so it stays out of its own baseline
+            let recent = deadline.run(DeadlineStage::Worker, state.metrics.get_recent(&name, samples + 1, None)).await?
+                .ok_or(RaftMetricsError::NotFound)?;
+            let mut earlier: Vec<f64> = recent.iter().skip(1).map(|sample| sample.value).collect();
+            if earlier.is_empty() {
+                return Err(RaftMetricsError::NoData(format!("metric '{}' has no samples before its current one", name)));
+            }
+            baseline.of(&mut earlier)
+        }
+        _ => return Err(RaftMetricsError::InvalidRequest("pass either a value or a baseline".to_string())),
+    };
+
+    Ok(Json(CompareResponse {
+        name,
+        op: query.op,
+        current,
+        threshold,
+        baseline: query.baseline,
+        breached: query.op.breached(current, threshold),
+    }))
+}
+
+/// The latest `?limit=` samples of a metric, newest first, optionally only
+/// those at or after `?since=`. A known metric with none answers an empty
+/// list; an unknown one is `404`.
+async fn get_metric_history(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+) -> Result<Json<MetricHistoryResponse>> {
+    info!("Worker {} reading history of metric: {}", state.worker_id, name);
+    let round = precision.rounder()?;
+    let max = state.query_limits.max_history_samples;
+    let limit = match query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT.min(max)) {
+        0 => return Err(RaftMetricsError::InvalidRequest("limit must be positive".to_string())),
+        limit if limit > max => {
+            return Err(RaftMetricsError::InvalidRequest(format!("limit must be at most {}", max)));
+        }
+        limit => limit,
+    };
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    let samples = deadline.run(DeadlineStage::Worker, state.metrics.get_recent(&name, limit, query.since)).await?
+        .ok_or(RaftMetricsError::NotFound)?;
+    let samples = samples
+        .into_iter()
+        .map(|sample| Ok(MetricPoint { value: round(sample.value)?, ..sample }))
+        .collect::<Result<_>>()?;
+    Ok(Json(MetricHistoryResponse { name, samples }))
+}
+
+/// Streams `name`'s samples in `[from, to]` as NDJSON [`MetricPoint`]
+/// lines, reading the store a page at a time as the client consumes the
+/// body. A client that disconnects drops the stream, so no further pages
+/// are read. The first page is read up front so errors still get a status.
+async fn get_metric_range(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(params): Query<RangeParams>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+    Query(units): Query<UnitQuery>,
+) -> Result<Response> {
+    info!("Worker {} streaming range for metric: {} ({}..{})", state.worker_id, name, params.from, params.to);
+    let precision = precision.rounder()?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    if params.from > params.to {
+        return Err(RaftMetricsError::InvalidRequest("'from' must not be after 'to'".to_string()));
+    }
+    if params.step.is_some_and(|step| step <= 0) {
+        return Err(RaftMetricsError::InvalidRequest("'step' must be positive".to_string()));
+    }
+    let function = params.function.as_deref().map_or(Ok(AggregateFn::default()), AggregateFn::from_name)?;
+    let range = RangeQuery { from: params.from, to: params.to, step: params.step, function };
+    // Counts of samples keep their meaning in any unit
+    let (convert, unit) = match function {
+        AggregateFn::Count => (None, None),
+        _ => {
+            let (convert, unit) = units.converter(&name)?;
+            (Some(convert), unit)
+        }
+    };
+    let round = Arc::new(move |value: f64| precision(convert.as_ref().map_or(value, |convert| convert(value))));
+    let remaining = params.max_points.unwrap_or(usize::MAX);
+
+    let encode = move |points: Vec<MetricPoint>, round: &dyn Fn(f64) -> Result<f64>| {
+        let mut chunk = Vec::new();
+        for point in points {
+            let point = MetricPoint { value: round(point.value)?, ..point };
+            serde_json::to_writer(&mut chunk, &point).expect("points always serialize");
+            chunk.push(b'\n');
+        }
+        Ok::<_, RaftMetricsError>(Bytes::from(chunk))
+    };
+
+    let (points, next) = if remaining == 0 {
+        (Vec::new(), None)
+    } else {
+        let first = state.metrics.get_range_page(&name, range, None, RANGE_PAGE_SIZE.min(remaining));
+        deadline.run(DeadlineStage::Worker, first).await?
+    };
+    let remaining = remaining - points.len();
+    let first = encode(points, &*round)?;
+
+    let metrics = state.metrics.clone();
+    let rest = stream::unfold((next, remaining), move |(cursor, remaining)| {
+        let metrics = metrics.clone();
+        let name = name.clone();
+        let round = round.clone();
+        async move {
+            let cursor = cursor.filter(|_| remaining > 0)?;
+            let page = metrics.get_range_page(&name, range, Some(cursor), RANGE_PAGE_SIZE.min(remaining)).await;
+            Some(match page {
+                Ok((points, next)) => {
+                    let remaining = remaining - points.len();
+                    (encode(points, &*round), (next, remaining))
+                }
+                Err(e) => (Err(e), (None, 0)),
+            })
+        }
+    });
+
+    let body = Body::from_stream(stream::once(async move { Ok(first) }).chain(rest));
+    let unit = unit.map(|unit| [(HeaderName::from_static(UNIT_HEADER), unit)]);
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], unit, body).into_response())
+}
+
+/// Statistics of `name`'s samples in `[start, end]` per `step`-second
+/// bucket, from one grouped storage read. Buckets are aligned to the epoch,
+/// so the first and last may cover time outside the window.
+async fn get_metric_buckets(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(params): Query<BucketsParams>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+) -> Result<Json<BucketsResponse>> {
+    info!("Worker {} bucketing metric {}", state.worker_id, name);
+    let round = precision.rounder()?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    if params.start > params.end {
+        return Err(RaftMetricsError::InvalidRequest("'start' must not be after 'end'".to_string()));
+    }
+    let step = bucket_step(params.step.unwrap_or(DEFAULT_BUCKET_STEP))?;
+    let functions = bucket_functions(params.functions.as_deref())?;
+    let first = params.start.div_euclid(step) * step;
+    let last = params.end.div_euclid(step) * step;
+    let spanned = last.abs_diff(first) / step as u64 + 1;
+    if spanned > MAX_BUCKETS {
+        return Err(RaftMetricsError::InvalidRequest(format!(
+            "[{}, {}] spans {} buckets of {}s, more than the {} allowed; ask for a wider step",
+            params.start, params.end, spanned, step, MAX_BUCKETS
+        )));
+    }
+
+    let stats = deadline.run(DeadlineStage::Worker, state.metrics.buckets(&name, params.start, params.end, step)).await?;
+    let bucket = |bucket_start: i64, value: &dyn Fn(AggregateFn) -> Result<Option<f64>>| -> Result<Bucket> {
+        Ok(Bucket {
+            bucket_start,
+            values: functions.iter().map(|&function| Ok((function, value(function)?))).collect::<Result<_>>()?,
+        })
+    };
+    let buckets = match params.fill {
+        BucketFill::None => stats.iter().map(|stats| bucket(stats.start, &|function| round(stats.value(function)).map(Some))).collect::<Result<_>>(),
+        fill => {
+            let mut stats = stats.iter().peekable();
+            (0..spanned as i64)
+                .map(|i| first + i * step)
+                .map(|bucket_start| match stats.next_if(|stats| stats.start == bucket_start) {
+                    Some(stats) => bucket(bucket_start, &|function| round(stats.value(function)).map(Some)),
+                    None if fill == BucketFill::Zero => bucket(bucket_start, &|_| Ok(Some(0.0))),
+                    None => bucket(bucket_start, &|_| Ok(None)),
+                })
+                .collect::<Result<_>>()
+        }
+    }?;
+    Ok(Json(BucketsResponse { name, start: params.start, end: params.end, step, functions, fill: params.fill, buckets }))
+}
+
+/// Aggregates `name`'s samples in `[start, end]` per value of `label`.
+async fn get_metric_groupby(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(params): Query<GroupByParams>,
+    Query(precision): Query<PrecisionQuery>,
+    Query(consistency): Query<ConsistencyQuery>,
+) -> Result<Json<GroupByResponse>> {
+    info!("Worker {} grouping metric {} by label {}", state.worker_id, name, params.label);
+    let round = precision.rounder()?;
+    consistency.wait(&state.metrics, state.version_wait.min(deadline.remaining())).await?;
+
+    if params.label.is_empty() {
+        return Err(RaftMetricsError::InvalidRequest("'label' must not be empty".to_string()));
+    }
+    let function = params.function.as_deref().map_or(Ok(AggregateFn::default()), AggregateFn::from_name)?;
+    let query = GroupByQuery {
+        label: params.label.clone(),
+        function,
+        from: params.start.unwrap_or(i64::MIN),
+        to: params.end.unwrap_or(i64::MAX),
+        max_groups: params.max_groups.map_or(MAX_GROUPS, |max| max.min(MAX_GROUPS)),
+    };
+    if query.from > query.to {
+        return Err(RaftMetricsError::InvalidRequest("'start' must not be after 'end'".to_string()));
+    }
+
+    let (groups, truncated) = deadline.run(DeadlineStage::Worker, state.metrics.group_by(&name, query)).await?;
+    Ok(Json(GroupByResponse {
+        name,
+        label: params.label,
+        function,
+        groups: groups.into_iter().map(|(group, value)| Ok((group, round(value)?))).collect::<Result<_>>()?,
+        truncated,
+    }))
+}
+
+async fn delete_metric(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    info!("Worker {} deleting metric: {}", state.worker_id, name);
+
+    deadline.run(DeadlineStage::Worker, state.propose(MetricOperation::Delete {
+        name,
+        deleted_at: chrono::Utc::now().timestamp(),
+    })).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_quarantine(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<Json<QuarantineResponse>> {
+    info!("Worker {} listing quarantined samples of {}", state.worker_id, name);
+
+    let samples = deadline.run(DeadlineStage::Worker, state.metrics.quarantined(&name)).await?;
+    Ok(Json(QuarantineResponse { name, samples }))
+}
+
+/// Applies a metric's quarantined samples through raft, so every replica
+/// folds in the same ones.
+async fn apply_quarantine(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    info!("Worker {} applying quarantined samples of {}", state.worker_id, name);
+
+    deadline.run(DeadlineStage::Worker, state.propose(MetricOperation::ApplyQuarantine { name })).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Zeroes a counter through raft, so every replica restarts its aggregate
+/// at the same point.
+async fn reset_metric(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<ResetQuery>,
+) -> Result<StatusCode> {
+    info!("Worker {} resetting metric: {}", state.worker_id, name);
+    if deadline.run(DeadlineStage::Worker, state.metrics.get_latest(&name)).await?.is_none() {
+        return Err(RaftMetricsError::NotFound);
+    }
+
+    // Without history the delete goes in the same entry, so a failure
+    // cannot leave the metric deleted but not zeroed
+    let reset_at = chrono::Utc::now().timestamp();
+    let reset = MetricOperation::Reset { name: name.clone(), reset_at, keep_history: true };
+    let operation = match query.keep_history {
+        true => reset,
+        false => MetricOperation::Transaction(vec![MetricOperation::Delete { name, deleted_at: reset_at.saturating_sub(1) }, reset]),
     };
+    deadline.run(DeadlineStage::Worker, state.propose(operation)).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets the transform `name`'s later samples are recorded through, via
+/// raft, so every replica scales the same samples alike.
+async fn put_transform(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Json(request): Json<IngestTransform>,
+) -> Result<StatusCode> {
+    info!("Worker {} recording '{}' as value * {} + {}", state.worker_id, name, request.scale, request.offset);
+    let transform = IngestTransform::new(request.scale, request.offset)?;
+
+    deadline.run(DeadlineStage::Worker, state.propose(MetricOperation::SetTransform {
+        name,
+        scale: transform.scale,
+        offset: transform.offset,
+    })).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Makes `name` a state metric through raft, so every replica tracks the
+/// same states from the same samples.
+async fn put_states(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Json(request): Json<StateDefinition>,
+) -> Result<StatusCode> {
+    info!("Worker {} tracking '{}' as one of {:?}", state.worker_id, name, request.states);
+    StateMetric::new(request.states.clone(), request.stale_after_secs)?;
+
+    deadline.run(DeadlineStage::Worker, state.propose(MetricOperation::DefineStates {
+        name,
+        states: request.states,
+        stale_after_secs: request.stale_after_secs,
+    })).await?;
 
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Time `name` has spent in each of its states, and where it is now.
+async fn get_states(State(state): State<WorkerState>, Path(name): Path<String>) -> Result<Json<StateReport>> {
+    state.metrics.state_report(&name).map(Json).ok_or(RaftMetricsError::NotFound)
+}
+
+/// Counts `name`'s samples into buckets through raft, so every replica
+/// counts the same samples alike. A layout different from the current one
+/// is refused with 409 before anything is proposed, unless `?reset=true`.
+async fn put_histogram(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Path(name): Path<String>,
+    Query(query): Query<HistogramQuery>,
+    Json(request): Json<HistogramDefinition>,
+) -> Result<StatusCode> {
+    let bounds = request.buckets.unwrap_or_else(histograms::default_bounds);
+    info!("Worker {} counting '{}' into buckets up to {:?}", state.worker_id, name, bounds);
+    let defined = HistogramMetric::new(bounds.clone())?;
+    if let Some(existing) = state.metrics.histogram(&name) {
+        existing.redefined(defined, query.reset)?;
+    }
+
+    deadline.run(DeadlineStage::Worker, state.propose(MetricOperation::DefineHistogram {
+        name,
+        bounds,
+        reset: query.reset,
+    })).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `name`'s bucket counts, with the count and sum of its samples.
+async fn get_histogram(State(state): State<WorkerState>, Path(name): Path<String>) -> Result<Json<HistogramReport>> {
+    state.metrics.histogram_report(&name).map(Json).ok_or(RaftMetricsError::NotFound)
+}
+
+/// This node's collectors for Prometheus, followed by a histogram family
+/// for each histogram metric.
+async fn scrape(State(state): State<WorkerState>, headers: HeaderMap) -> Response {
+    let mut families = crate::metrics::REGISTRY.gather();
+    families.extend(state.metrics.histogram_families());
+    encode_families(&headers, &families)
+}
+
+/// Wipes this worker's metrics through raft.
+async fn reset(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+) -> Result<StatusCode> {
+    info!("Worker {} resetting all metrics", state.worker_id);
+
+    deadline.run(DeadlineStage::Worker, state.propose(MetricOperation::Clear)).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Each tenant's series and storage on this worker, against its quota.
+async fn get_usage(State(state): State<WorkerState>) -> Json<Vec<UsageReport>> {
+    Json(state.metrics.usage_reports())
+}
+
+/// Replaces the tenant quotas, as pushed by the control node on reload.
+async fn put_quotas(State(state): State<WorkerState>, Json(quotas): Json<TenantQuotas>) -> StatusCode {
+    info!("Worker {} now enforcing quotas for {} tenants", state.worker_id, quotas.tenants.len());
+    state.metrics.set_quotas(quotas);
+    StatusCode::NO_CONTENT
+}
+
+/// Rebuilds the aggregates on this worker from its stored samples.
+async fn recompute_aggregates(
+    State(state): State<WorkerState>,
+    Query(query): Query<RecomputeQuery>,
+) -> Result<Json<RecomputeSummary>> {
+    info!("Worker {} recomputing aggregates under '{}'", state.worker_id, query.prefix);
+    Ok(Json(state.metrics.recompute_all_aggregates(&query.prefix).await?))
+}
+
+async fn put_hook(
+    State(state): State<WorkerState>,
+    Path(id): Path<String>,
+    Json(hook): Json<HookConfig>,
+) -> Result<StatusCode> {
+    info!("Worker {} installing hook '{}' on {}", state.worker_id, id, hook.metric);
+
+    state.metrics.put_hook(&id, hook).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn put_sample_rate(
+    State(state): State<WorkerState>,
+    Path(name): Path<String>,
+    Json(request): Json<SampleRateRequest>,
+) -> Result<StatusCode> {
+    info!("Worker {} sampling '{}' at {}", state.worker_id, name, request.sample_rate);
+    state.metrics.set_sample_rate(&name, request.sample_rate)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Runs a read-only `SELECT` against this worker's store, within its
+/// configured row and time limits.
+async fn run_query(
+    State(state): State<WorkerState>,
+    Extension(deadline): Extension<Deadline>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryResult>> {
+    info!("Worker {} running query: {}", state.worker_id, request.sql);
+
+    let limit = Deadline::after(state.query_limits.timeout.min(deadline.remaining()));
+    let result = limit
+        .run(DeadlineStage::Worker, state.metrics.query(&request.sql, state.query_limits.max_rows, limit.expires().into_std()))
+        .await?;
+    Ok(Json(result))
+}
+
+async fn raft_debug(State(state): State<WorkerState>) -> Json<RaftDebugResponse> {
+    let status = &state.raft_status;
+    Json(RaftDebugResponse {
+        node_id: state.worker_id as u64 + 1,
+        role: status.role(),
+        leader_id: status.leader_id(),
+        term: status.term(),
+        applied_index: status.applied_index(),
+        lag: state.raft_lag(),
+    })
+}
+
+async fn preflight_report(State(state): State<WorkerState>) -> Json<PreflightReport> {
+    Json(state.preflight.as_ref().clone())
+}
+
+async fn storage_stats(
+    State(state): State<WorkerState>,
+) -> Result<Json<StorageStats>> {
+    Ok(Json(state.metrics.storage_stats().await?))
+}
+
+/// Metrics whose cached and stored aggregates disagree.
+async fn aggregate_mismatches(
+    State(state): State<WorkerState>,
+    Query(query): Query<MismatchQuery>,
+) -> Result<Json<Vec<AggregateMismatch>>> {
+    Ok(Json(state.metrics.aggregate_mismatches(query.tolerance).await?))
+}
+
+/// This worker's hottest metrics, by writes since it started or by
+/// latest value.
+async fn top_metrics(
+    State(state): State<WorkerState>,
+    Query(query): Query<TopQuery>,
+) -> Result<Json<TopMetricsResponse>> {
+    let metrics = state.metrics.top_metrics(query.n()?, query.by).await?;
+    Ok(Json(TopMetricsResponse { by: query.by, metrics }))
+}
+
+/// `metrics` applying writes under the settings in the environment, as a
+/// worker does; `replay` uses it too so it applies entries alike.
+pub fn configure_from_env(metrics: MetricsRegistry) -> MetricsRegistry {
+    metrics
+        .with_summation(Summation::from_env())
+        .with_memory_budget(MemoryBudget::from_env())
+        .with_sample_rates(SampleRates::from_env())
+        .with_hook_hosts(HookHosts::from_env())
+        .with_transforms(IngestTransforms::from_env())
+        .with_dedup(DedupPolicy::from_env())
+        .with_outliers(OutlierPolicy::from_env())
+        .with_aggregate_write(AggregateWrite::from_env())
+        .with_max_distinct_metrics(env::var("MAX_DISTINCT_METRICS").ok().and_then(|v| v.parse().ok()).filter(|&max| max > 0))
+        .with_quotas(TenantQuotas::from_lookup(&|key: &str| env::var(key).ok()))
+        .with_idempotency_ttl(
+            units::duration(&|key: &str| env::var(key).ok(), "IDEMPOTENCY_TTL_SECS")
+                .map_or(DEFAULT_IDEMPOTENCY_TTL_SECS, |ttl| ttl.as_secs() as i64),
+        )
+}
+
+/// Opens the `STORAGE_BACKEND` store at `DB_PATH`, or an in-memory one when
+/// unset, and starts its tombstone reaper.
+pub(crate) fn open_metrics_from_env() -> Result<Arc<MetricsRegistry>> {
+    let db_path = env::var("DB_PATH").ok();
+    let kind = StorageBackendKind::from_env()?;
+    if let Some(db_path) = &db_path {
+        info!("Opening {:?} metrics store at {}", kind, db_path);
+    }
+    let metrics = Arc::new(configure_from_env(MetricsRegistry::with_backend(kind.open(db_path.as_deref())?)?));
+    if let AggregateWrite::Async { interval } = metrics.aggregate_write() {
+        info!("Flushing aggregates to the store every {:?}", interval);
+        tokio::spawn(run_aggregate_flush(metrics.clone(), interval));
+    }
+
+    let grace_secs = units::duration(&|key: &str| env::var(key).ok(), "TOMBSTONE_GRACE_SECS")
+        .map_or(DEFAULT_TOMBSTONE_GRACE_SECS, |grace| grace.as_secs() as i64);
+    tokio::spawn(run_maintenance(
+        metrics.clone(),
+        grace_secs,
+        CompactionPolicy::from_env(),
+        std::time::Duration::from_secs(60),
+    ));
+
+    Ok(metrics)
+}
+
+/// How long a worker waits before retrying a failed registration.
+const REGISTRATION_RETRY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Registers with the control node and keeps renewing the registration at
+/// a third of the TTL it answers with, so one lost heartbeat never lapses it.
+fn announce_to_control(config: RegistrationConfig) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let request = RegisterRequest { url: config.advertise_url.clone(), capacity: config.capacity };
+        loop {
+            let mut call = client
+                .post(format!("{}/cluster/register", config.control_url))
+                .timeout(REGISTRATION_RETRY)
+                .json(&request);
+            if let Some(token) = &config.admin_token {
+                call = call.bearer_auth(token);
+            }
+            let pause = match call.send().await {
+                Ok(response) if response.status().is_success() => match response.json::<RegisterResponse>().await {
+                    Ok(registered) => std::time::Duration::from_secs(registered.ttl_secs.max(1)) / 3,
+                    Err(e) => {
+                        warn!("Unexpected registration response from {}: {}", config.control_url, e);
+                        REGISTRATION_RETRY
+                    }
+                },
+                Ok(response) => {
+                    warn!("Control node {} refused registration: {}", config.control_url, response.status());
+                    REGISTRATION_RETRY
+                }
+                Err(e) => {
+                    warn!("Cannot reach control node {}: {}", config.control_url, e);
+                    REGISTRATION_RETRY
+                }
+            };
+            tokio::time::sleep(pause).await;
+        }
+    });
+}
+
+/// Sends `POST /cluster/heartbeat` with what this worker stores every
+/// `HEARTBEAT_INTERVAL_SECS`, so the control node notices if it goes quiet.
+fn send_heartbeats(config: RegistrationConfig, state: WorkerState) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut ticks = tokio::time::interval(config.heartbeat_interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let load = state.metrics.storage_stats().await.unwrap_or_else(|e| {
+                warn!("Failed to read storage stats for heartbeat: {}", e);
+                StorageStats::default()
+            });
+            let mut call = client
+                .post(format!("{}/cluster/heartbeat", config.control_url))
+                .timeout(config.heartbeat_interval)
+                .json(&HeartbeatRequest {
+                    url: config.advertise_url.clone(),
+                    load,
+                    raft: Some(state.raft_lag().health),
+                });
+            if let Some(token) = &config.admin_token {
+                call = call.bearer_auth(token);
+            }
+            match call.send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => warn!("Control node {} refused heartbeat: {}", config.control_url, response.status()),
+                Err(e) => warn!("Cannot reach control node {}: {}", config.control_url, e),
+            }
+        }
+    });
+}
+
+fn worker_listen_addr() -> String {
     let port = env::var("PORT").unwrap_or_else(|_| "8081".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    info!("Starting worker node {} on {}", worker_id, addr);
+    format!("0.0.0.0:{}", port)
+}
+
+/// Where admin routes are served apart from the data API, from `ADMIN_PORT`.
+fn worker_admin_addr() -> Option<String> {
+    admin_listen_addr(env::var("ADMIN_PORT").ok().and_then(|port| port.trim().parse().ok()))
+}
+
+/// Runs the worker's startup checks without starting it, for `--check`.
+pub async fn preflight_worker_node() -> PreflightReport {
+    let db_path = env::var("DB_PATH").ok();
+    let (mut report, _) = worker_preflight(&worker_listen_addr(), db_path.as_deref()).await;
+    check_admin_listen(&mut report, worker_admin_addr().as_deref()).await;
+    report
+}
+
+pub async fn start_worker_node(worker_id: usize) -> Result<()> {
+    let addr = worker_listen_addr();
+    let db_path = env::var("DB_PATH").ok();
+    let (mut report, listener) = worker_preflight(&addr, db_path.as_deref()).await;
+    let admin_addr = worker_admin_addr();
+    let admin_listener = check_admin_listen(&mut report, admin_addr.as_deref()).await;
+    report.log();
+    let listener = match listener {
+        Some(listener) if report.passed() => listener,
+        _ => return Err(RaftMetricsError::InvalidConfig(report.failures())),
+    };
+
+    let metrics = open_metrics_from_env()?;
+
+    let admin_token: Option<Arc<str>> = env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty()).map(Arc::from);
+    let mut state = match RaftGroupConfig::from_env(worker_id)? {
+        Some(group) => {
+            info!("Worker node {} joins raft group {:?} as raft id {}", worker_id, group.peers, group.id);
+            WorkerState::joined(worker_id, metrics.clone(), RaftConfig::from_env(), &group, admin_token.clone())?
+        }
+        None => WorkerState::new(worker_id, metrics.clone(), RaftConfig::from_env())?,
+    };
+    state.default_deadline = DeadlineConfig::from_env().default_budget;
+    state.slo = Arc::new(SloTracker::new(&SloConfig::from_env()));
+    state.query_limits = QueryLimits::from_env();
+    state.preflight = Arc::new(report);
+    state.raft_health = RaftHealthConfig::from_env();
+    state.ttls = Arc::new(MetricTtls::from_env());
+    state.read_only = Arc::new(ReadOnly::from_env(db_path.as_ref().map(|db_path| format!("{}.read_only", db_path).into())));
+    state.downsampler = Arc::new(Downsampler::from_env());
+    state.journal = Arc::new(Journal::from_env()?);
+    state.forwarder = Arc::new(Forwarder::new(ForwardingConfig::from_env(), ForwardFrom::Worker, &format!("worker-{}", worker_id)));
+    #[cfg(feature = "chaos")]
+    state.chaos.set_enabled(env::var("CHAOS_ENABLED").is_ok_and(|v| v == "true"));
+    state.info.log();
+    if let Some(wait) = units::duration(&|key: &str| env::var(key).ok(), "MIN_VERSION_WAIT_MS") {
+        state.version_wait = wait;
+    }
+    if let Some(skew) = units::duration(&|key: &str| env::var(key).ok(), "MAX_FUTURE_SKEW_SECS") {
+        state.max_future_skew = skew;
+    }
+    state.registration = RegistrationConfig::from_env();
+    state.admin_token = admin_token;
+    let standby = env::var("WORKER_STANDBY").is_ok_and(|v| v == "true");
+    state.serving.store(!standby, Ordering::Release);
+    state.set_ready(false);
+
+    match &admin_addr {
+        Some(admin_addr) => info!("Starting worker node {} on {}, admin routes on {}", worker_id, addr, admin_addr),
+        None => info!("Starting worker node {} on {}", worker_id, addr),
+    }
+    let server = tokio::spawn(serve_split(
+        listener,
+        admin_listener,
+        data_router(state.clone()),
+        admin_router(state.clone()),
+        ctrl_c(),
+    ));
+
+    metrics.recover().await?;
+    let replayed = state.replay_journal().await;
+    if replayed > 0 {
+        info!("Worker node {} replayed {} journaled operations", worker_id, replayed);
+    }
+    spawn_pusher(&format!("worker-{}", worker_id));
+    state.forwarder.spawn();
+    if state.downsampler.buffers() {
+        tokio::spawn(run_downsample_flush(state.clone()));
+    }
+    state.set_ready(true);
+    match standby {
+        true => info!("Worker node {} is ready as a standby; POST /admin/promote to serve", worker_id),
+        false => {
+            info!("Worker node {} is ready", worker_id);
+            state.join_control();
+        }
+    }
+
+    let served = server.await;
+    state.propose_summaries(state.downsampler.drain()).await;
+    state.forwarder.flush().await;
+    // Deferred aggregates would otherwise only be rebuilt by a recompute
+    let flushed = metrics.flush_aggregates().await?;
+    if flushed > 0 {
+        info!("Flushed {} aggregates on shutdown", flushed);
+    }
+    served
+        .map_err(|e| RaftMetricsError::Internal(format!("Worker server task failed: {}", e)))?
+        .map_err(|e| RaftMetricsError::Internal(format!("Worker server failed: {}", e)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+    use crate::api::{msgpack::MSGPACK, types::Baseline};
+    use crate::metrics::{Labels, StaleMetric, TopBy, REQUEST_TOTAL};
+    use crate::raft::node::RaftRole;
+
+    fn test_state() -> WorkerState {
+        WorkerState::new(1, Arc::new(MetricsRegistry::new().unwrap()), RaftConfig::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_are_missing_from_the_data_router() {
+        let state = test_state();
+        let (data, admin) = (data_router(state.clone()), admin_router(state));
+
+        for path in ["/prometheus", "/raft/status", "/debug/stats", "/admin/preflight"] {
+            let response = data.clone().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+            let response = admin.clone().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        }
+        let response = data.oneshot(Request::get("/metrics/absent").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = admin.oneshot(Request::get("/load").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_recompute_route_reports_what_changed() {
+        let mut state = test_state();
+        state.admin_token = Some(Arc::from("secret"));
+        state.metrics.record_metric_with_timestamp("cpu", 2.0, 100).await.unwrap();
+        state.metrics.record_metric_with_timestamp("mem", 3.0, 100).await.unwrap();
+        let router = worker_router(state);
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/admin/recompute_aggregates?prefix=cp").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router
+            .oneshot(
+                Request::post("/admin/recompute_aggregates?prefix=cp")
+                    .header("authorization", "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let summary: RecomputeSummary = serde_json::from_slice(&body).unwrap();
+        assert_eq!(summary, RecomputeSummary { recomputed: 1, changed: 0, largest: vec![] });
+    }
+
+    #[tokio::test]
+    async fn test_journaled_write_is_proposed_again_after_a_restart() {
+        let path = std::env::temp_dir().join(format!("raftmetrics-journal-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // The worker crashes after journaling the write, before proposing it
+        let journal = crate::api::journal::Journal::open(&path, true).unwrap();
+        let operation = MetricOperation::record("cpu", 7.5, 100, Labels::new());
+        journal.append(&operation).await.unwrap();
+        drop(journal);
+
+        let mut state = test_state();
+        state.journal = Arc::new(crate::api::journal::Journal::open(&path, true).unwrap());
+        assert_eq!(state.journal.pending(), 1);
+        assert_eq!(state.replay_journal().await, 1);
+        let latest = state.metrics.get_latest("cpu").await.unwrap().unwrap();
+        assert_eq!((latest.value, latest.timestamp), (7.5, 100));
+        assert_eq!(state.journal.pending(), 0);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        // Writes through the worker are journaled and cleared as they apply
+        let response = worker_router(state.clone())
+            .oneshot(
+                Request::post("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"mem","value":1.0}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.journal.pending(), 0);
+        assert_eq!(state.replay_journal().await, 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_top_ranks_metrics_by_writes_and_by_value() {
+        let state = test_state();
+        for (name, writes, value) in [("quiet", 1, 50.0), ("busy", 30, 1.0), ("warm", 5, 7.0), ("gone", 40, 9.0)] {
+            for i in 0..writes {
+                state.metrics.record_metric_with_timestamp(name, value, 100 + i).await.unwrap();
+            }
+        }
+        state.metrics.delete_metric("gone", 1_000).await.unwrap();
+        let app = worker_router(state);
+        let top = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<TopMetricsResponse>(&body).ok())
+            }
+        };
+
+        let (status, by_writes) = top("/debug/top").await;
+        assert_eq!(status, StatusCode::OK);
+        let by_writes = by_writes.unwrap();
+        assert_eq!(by_writes.by, TopBy::Writes);
+        let ranked: Vec<(&str, u64, Option<f64>)> =
+            by_writes.metrics.iter().map(|metric| (metric.name.as_str(), metric.writes, metric.value)).collect();
+        assert_eq!(ranked, vec![("busy", 30, Some(1.0)), ("warm", 5, Some(7.0)), ("quiet", 1, Some(50.0))]);
+
+        let by_value = top("/debug/top?n=2&by=value").await.1.unwrap();
+        let ranked: Vec<&str> = by_value.metrics.iter().map(|metric| metric.name.as_str()).collect();
+        assert_eq!(ranked, vec!["quiet", "warm"]);
+
+        assert_eq!(top("/debug/top?n=0").await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_aggregates_route_lists_every_metric_a_page_at_a_time() {
+        let state = test_state();
+        for (name, value) in [("cpu.a", 1.0), ("cpu.b", 2.0), ("cpu.c", 3.0), ("mem", 4.0)] {
+            state.metrics.record_metric_with_timestamp(name, value, 100).await.unwrap();
+            state.metrics.record_metric_with_timestamp(name, value * 2.0, 200).await.unwrap();
+        }
+        let app = worker_router(state);
+        let page = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<AggregatesPage>(&body).unwrap()
+            }
+        };
+
+        let all = page("/aggregates").await;
+        let listed: Vec<_> = all.aggregates.iter().map(|aggregate| (aggregate.name.as_str(), aggregate.count, aggregate.sum, aggregate.latest)).collect();
+        assert_eq!(listed, [("cpu.a", 2, 3.0, 2.0), ("cpu.b", 2, 6.0, 4.0), ("cpu.c", 2, 9.0, 6.0), ("mem", 2, 12.0, 8.0)]);
+        assert_eq!(all.next, None);
+
+        let first = page("/aggregates?prefix=cpu.&limit=2").await;
+        assert_eq!(first.aggregates.iter().map(|aggregate| aggregate.name.as_str()).collect::<Vec<_>>(), ["cpu.a", "cpu.b"]);
+        assert_eq!(first.next.as_deref(), Some("cpu.b"));
+        let second = page("/aggregates?prefix=cpu.&limit=2&after=cpu.b").await;
+        assert_eq!(second.aggregates.iter().map(|aggregate| aggregate.name.as_str()).collect::<Vec<_>>(), ["cpu.c"]);
+        assert_eq!(second.next, None);
+    }
+
+    #[tokio::test]
+    async fn test_request_counter_distinguishes_status_class() {
+        let state = test_state();
+        state.metrics.record_metric("present", 1.0).await.unwrap();
+        let app = worker_router(state);
+
+        let ok_before = REQUEST_TOTAL.with_label_values(&["/metrics/:name", "2xx"]).get();
+        let missing_before = REQUEST_TOTAL.with_label_values(&["/metrics/:name", "4xx"]).get();
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/absent").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .oneshot(Request::get("/metrics/present").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert!(REQUEST_TOTAL.with_label_values(&["/metrics/:name", "4xx"]).get() > missing_before);
+        assert!(REQUEST_TOTAL.with_label_values(&["/metrics/:name", "2xx"]).get() > ok_before);
+    }
+
+    #[tokio::test]
+    async fn test_info_reports_version_with_a_stable_schema() {
+        let app = worker_router(test_state());
+        let response = app
+            .oneshot(Request::get("/info").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["node_type"], "worker");
+        assert_eq!(info["node_id"], 1);
+        assert_eq!(info["storage_backend"], "memory");
+        assert!(info["partitions"].is_null());
+        let mut fields: Vec<&str> = info.as_object().unwrap().keys().map(String::as_str).collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            ["build_time", "features", "git_commit", "node_id", "node_type", "partitions", "read_only", "started_at", "storage_backend", "version"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_freshness_headers_count_down_the_ttl() {
+        let mut state = test_state();
+        state.ttls = Arc::new(MetricTtls { default: Some(60), ..Default::default() });
+        state.metrics.record_metric("fresh", 1.0).await.unwrap();
+        state.metrics.record_metric_with_timestamp("expired", 1.0, 1_000).await.unwrap();
+        let app = worker_router(state);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/fresh").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let max_age = response.headers()[axum::http::header::CACHE_CONTROL].to_str().unwrap().to_string();
+        assert!(["max-age=60", "max-age=59"].contains(&max_age.as_str()), "{}", max_age);
+        assert!(response.headers().contains_key(axum::http::header::EXPIRES));
+
+        // Past its TTL but still stored
+        let response = app
+            .oneshot(Request::get("/metrics/expired").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[axum::http::header::CACHE_CONTROL], "max-age=0");
+        assert_eq!(response.headers()[axum::http::header::EXPIRES], "Thu, 01 Jan 1970 00:17:40 GMT");
+    }
+
+    #[tokio::test]
+    async fn test_stale_listing_contains_only_idle_metric() {
+        let now = Arc::new(std::sync::atomic::AtomicI64::new(1_000));
+        let clock = now.clone();
+        let metrics = MetricsRegistry::new().unwrap().with_clock(Arc::new(move || clock.load(Ordering::SeqCst)));
+        let state = WorkerState::new(1, Arc::new(metrics), RaftConfig::default()).unwrap();
+        let app = worker_router(state.clone());
+
+        state.metrics.record_metric("idle", 1.0).await.unwrap();
+        now.store(1_000 + 86_400 + 60, Ordering::SeqCst);
+        state.metrics.record_metric("active", 1.0).await.unwrap();
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/stale?idle_seconds=86400").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page: StaleMetricsPage = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page.metrics, [StaleMetric { name: "idle".into(), first_seen: 1_000, last_seen: 1_000 }]);
+        assert_eq!(page.next, None);
+
+        let response = app
+            .oneshot(Request::get("/metrics/active/aggregate").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let aggregate: MetricAggregateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!((aggregate.first_seen, aggregate.last_seen), (87_460, 87_460));
+        assert_eq!(aggregate.latest, 1.0);
+    }
+
+    #[cfg(feature = "duckdb-storage")]
+    #[tokio::test]
+    async fn test_query_endpoint_runs_selects_and_rejects_writes() {
+        let backend = Box::new(crate::metrics::storage::DuckDbBackend::open_in_memory().unwrap());
+        let metrics = Arc::new(MetricsRegistry::with_backend(backend).unwrap());
+        let mut state = WorkerState::new(1, metrics, RaftConfig::default()).unwrap();
+        state.query_limits.max_rows = 2;
+        for (name, value, timestamp) in [("cpu", 1.0, 100), ("cpu", 3.0, 200), ("mem", 8.0, 100), ("disk", 2.0, 100)] {
+            state.metrics.record_metric_with_timestamp(name, value, timestamp).await.unwrap();
+        }
+        state.metrics.delete_metric("disk", 150).await.unwrap();
+        let app = worker_router(state.clone());
+        let query = |sql: &str| {
+            Request::post("/query")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "sql": sql }).to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone()
+            .oneshot(query("SELECT name, max(value) AS peak FROM metrics GROUP BY name ORDER BY name"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let result: QueryResult = serde_json::from_slice(&body).unwrap();
+        // Deleted samples are hidden
+        assert_eq!(result.columns, vec!["name", "peak"]);
+        assert_eq!(result.rows, vec![vec![serde_json::json!("cpu"), serde_json::json!(3.0)], vec![serde_json::json!("mem"), serde_json::json!(8.0)]]);
+        assert!(!result.truncated);
+
+        let response = app.clone().oneshot(query("SELECT value FROM metrics")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let result: QueryResult = serde_json::from_slice(&body).unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert!(result.truncated);
+
+        for sql in ["DELETE FROM metrics", "UPDATE metric_aggregates SET max = 0", "SELECT * FROM metric_tombstones"] {
+            let response = app.clone().oneshot(query(sql)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", sql);
+        }
+        assert_eq!(state.metrics.get_metric("cpu").await.unwrap(), Some(3.0));
+    }
+
+    #[cfg(feature = "duckdb-storage")]
+    #[tokio::test]
+    async fn test_query_is_interrupted_at_its_deadline() {
+        let backend = Box::new(crate::metrics::storage::DuckDbBackend::open_in_memory().unwrap());
+        let metrics = MetricsRegistry::with_backend(backend).unwrap();
+        metrics.record_metric_with_timestamp("cpu", 1.0, 100).await.unwrap();
+        let slow = "WITH RECURSIVE t(n) AS (SELECT 1 FROM metrics UNION ALL SELECT n + 1 FROM t WHERE n < 1000000000) \
+                    SELECT count(*) FROM t";
+
+        let started = std::time::Instant::now();
+        let result = metrics.query(slow, 10, started + std::time::Duration::from_millis(200)).await;
+        // Returns once interrupted rather than when the statement finishes
+        assert!(matches!(result, Err(RaftMetricsError::Internal(ref message)) if message.contains("interrupted")), "{:?}", result);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "{:?}", started.elapsed());
+
+        let result = metrics.query("SELECT count(*) AS n FROM metrics", 10, std::time::Instant::now() + std::time::Duration::from_secs(5)).await.unwrap();
+        assert_eq!(result.rows, vec![vec![serde_json::json!(1)]]);
+    }
+
+    #[tokio::test]
+    async fn test_groupby_endpoint_buckets_by_label() {
+        let state = test_state();
+        let samples: [(f64, &[(&str, &str)]); 8] = [
+            (10.0, &[("region", "eu")]),
+            (20.0, &[("region", "eu"), ("host", "a")]),
+            (30.0, &[("region", "us")]),
+            (5.0, &[("region", "ap")]),
+            (15.0, &[("region", "ap")]),
+            (25.0, &[("region", "ap")]),
+            (100.0, &[]),
+            (200.0, &[("host", "b")]),
+        ];
+        for (i, (value, labels)) in samples.iter().enumerate() {
+            let labels: Labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            state.metrics.record_metric_with_labels("latency", *value, 1_000 + i as i64, &labels).await.unwrap();
+        }
+        let app = worker_router(state);
+        let groupby = |query: &str| {
+            let request = Request::get(format!("/metrics/latency/groupby?{}", query)).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+            }
+        };
+
+        let (status, body) = groupby("label=region&fn=avg").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["groups"], serde_json::json!({ "<none>": 150.0, "ap": 15.0, "eu": 15.0, "us": 30.0 }));
+        assert_eq!(body["truncated"], false);
+
+        let (_, body) = groupby("label=region&fn=count&start=1003&end=1007").await;
+        assert_eq!(body["groups"], serde_json::json!({ "<none>": 2.0, "ap": 3.0 }));
+
+        let (_, body) = groupby("label=region&fn=max&max_groups=2").await;
+        assert_eq!(body["groups"], serde_json::json!({ "<none>": 200.0, "ap": 25.0 }));
+        assert_eq!(body["truncated"], true);
+
+        let (status, _) = groupby("label=region&fn=median").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_buckets_endpoint_aligns_fills_and_caps() {
+        let state = test_state();
+        for (value, timestamp) in [(1.0, 90), (10.0, 100), (20.0, 110), (5.0, 150), (7.0, 250)] {
+            state.metrics.record_metric_with_timestamp("load", value, timestamp).await.unwrap();
+        }
+        let app = worker_router(state);
+        let buckets = |query: &str| {
+            let request = Request::get(format!("/metrics/load/buckets?{}", query)).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+            }
+        };
+
+        // Aligned to the epoch, not to `start`; the sample at 90 is outside the window
+        let (status, body) = buckets("start=100&end=250&fn=avg,max,count").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["step"], 60);
+        assert_eq!(body["fn"], serde_json::json!(["avg", "max", "count"]));
+        assert_eq!(
+            body["buckets"],
+            serde_json::json!([
+                { "bucket_start": 60, "avg": 15.0, "max": 20.0, "count": 2.0 },
+                { "bucket_start": 120, "avg": 5.0, "max": 5.0, "count": 1.0 },
+                { "bucket_start": 240, "avg": 7.0, "max": 7.0, "count": 1.0 },
+            ])
+        );
+
+        let (_, body) = buckets("start=100&end=250&fn=sum&fill=zero").await;
+        let filled: Vec<_> = body["buckets"].as_array().unwrap().iter().map(|b| (b["bucket_start"].clone(), b["sum"].clone())).collect();
+        assert_eq!(filled, vec![(60.into(), 30.0.into()), (120.into(), 5.0.into()), (180.into(), 0.0.into()), (240.into(), 7.0.into())]);
+
+        let (_, body) = buckets("start=100&end=250&fn=min,max&fill=null").await;
+        assert_eq!(body["buckets"][2], serde_json::json!({ "bucket_start": 180, "min": null, "max": null }));
+
+        // Widened to the next width that divides a day
+        let (_, body) = buckets("start=0&end=100&step=7").await;
+        assert_eq!(body["step"], 8);
+        assert_eq!(body["buckets"][0]["bucket_start"], 88);
+        assert_eq!(body["buckets"][0]["count"], 1.0);
+        assert_eq!(bucket_step(45).unwrap(), 45);
+        assert_eq!(bucket_step(90_000).unwrap(), 2 * SECONDS_PER_DAY);
+
+        let cap = MAX_BUCKETS as i64 * 60;
+        let (status, _) = buckets(&format!("start=0&end={}&fill=zero", cap - 1)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = buckets(&format!("start=0&end={}", cap)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("wider step"), "{}", body);
+        for query in ["start=10&end=0", "start=0&end=10&step=0", "start=0&end=10&fn=median", "start=0&end=10&fill=linear"] {
+            assert_eq!(buckets(query).await.0, StatusCode::BAD_REQUEST, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delta_endpoint() {
+        let state = test_state();
+        for (value, timestamp) in [(10.0, 1_000), (14.0, 1_060), (25.0, 1_120)] {
+            state.metrics.record_metric_with_timestamp("requests", value, timestamp).await.unwrap();
+        }
+        let app = worker_router(state);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/requests/delta?from=1000&to=1100").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let delta: MetricDeltaResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(delta.delta, 4.0);
+        assert_eq!(delta.from.timestamp, 1_000);
+        assert_eq!(delta.to.timestamp, 1_060);
+
+        let response = app
+            .oneshot(Request::get("/metrics/requests/delta?from=2000&to=3000").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_history_endpoint() {
+        let mut state = test_state();
+        state.query_limits.max_history_samples = 10;
+        for i in 0..20 {
+            state.metrics.record_metric_with_timestamp("load", i as f64, 1_000 + i).await.unwrap();
+        }
+        let app = worker_router(state);
+        let get = |uri: &str| app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap());
+
+        let response = get("/metrics/load/history?limit=3&since=1010").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let history: MetricHistoryResponse = serde_json::from_slice(&body).unwrap();
+        let timestamps: Vec<i64> = history.samples.iter().map(|sample| sample.timestamp).collect();
+        assert_eq!(timestamps, vec![1_019, 1_018, 1_017]);
+
+        let response = get("/metrics/load/history?since=5000").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(serde_json::from_slice::<MetricHistoryResponse>(&body).unwrap().samples.is_empty());
+
+        assert_eq!(get("/metrics/load/history?limit=11").await.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get("/metrics/load/history?limit=0").await.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get("/metrics/absent/history").await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_metric_read_converts_units() {
+        let state = test_state();
+        state.metrics.record_metric_with_timestamp("disk_used_bytes", 3.0 * 1024.0 * 1024.0, 1_000).await.unwrap();
+        state.metrics.record_metric_with_timestamp("latency_seconds", 0.25, 1_000).await.unwrap();
+        let app = worker_router(state);
+        let get = |uri: &str| app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap());
+
+        let response = get("/metrics/disk_used_bytes?unit=MiB").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let metric: MetricValueResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!((metric.value, metric.unit.as_deref()), (3.0, Some("MiB")));
+
+        let response = get("/metrics/latency_seconds?unit=ms").await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<MetricValueResponse>(&body).unwrap().value, 250.0);
+
+        assert_eq!(get("/metrics/latency_seconds?unit=bytes").await.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get("/metrics/latency_seconds?unit=ms&from_unit=bytes").await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_range_and_delta_convert_with_as_unit() {
+        let state = test_state();
+        for (value, timestamp) in [(0.25, 1_000), (0.75, 1_001), (0.5, 1_002)] {
+            state.metrics.record_metric_with_timestamp("latency_seconds", value, timestamp).await.unwrap();
+        }
+        let app = worker_router(state);
+        let get = |uri: &str| app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap());
+
+        let response = get("/metrics/latency_seconds/aggregate?as_unit=ms").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let aggregate: MetricAggregateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(aggregate.count, 3);
+        assert_eq!((aggregate.sum, aggregate.average, aggregate.min, aggregate.max), (1500.0, 500.0, 250.0, 750.0));
+        assert_eq!((aggregate.latest, aggregate.unit.as_deref()), (500.0, Some("ms")));
+
+        let response = get("/metrics/latency_seconds/range?from=1000&to=1002&as_unit=ms").await.unwrap();
+        assert_eq!(response.headers()[UNIT_HEADER], "ms");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let values: Vec<f64> = body
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<MetricPoint>(line).unwrap().value)
+            .collect();
+        assert_eq!(values, vec![250.0, 750.0, 500.0]);
+
+        let response = get("/metrics/latency_seconds/range?from=1000&to=1002&step=10&fn=count&as_unit=ms").await.unwrap();
+        assert!(response.headers().get(UNIT_HEADER).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<MetricPoint>(&body).unwrap().value, 3.0);
+
+        let response = get("/metrics/latency_seconds/delta?from=1000&to=1002&as_unit=ms").await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let delta: MetricDeltaResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!((delta.delta, delta.from.value, delta.unit.as_deref()), (250.0, 250.0, Some("ms")));
+
+        for uri in [
+            "/metrics/latency_seconds/aggregate?as_unit=GiB",
+            "/metrics/latency_seconds/range?from=1000&to=1002&as_unit=percent",
+            "/metrics/latency_seconds/delta?from=1000&to=1002&as_unit=bytes",
+        ] {
+            assert_eq!(get(uri).await.unwrap().status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_range_streams_pages_as_client_reads() {
+        let state = test_state();
+        let samples = RANGE_PAGE_SIZE as i64 * 2 + 100;
+        for i in 0..samples {
+            state.metrics.record_metric_with_timestamp("load", i as f64, 1_000 + i).await.unwrap();
+        }
+        let metrics = state.metrics.clone();
+        let app = worker_router(state);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/load/range?from=0&to=100000").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut chunks = response.into_body().into_data_stream();
+        let first = chunks.next().await.unwrap().unwrap();
+        assert_eq!(first.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count(), RANGE_PAGE_SIZE);
+
+        // Later pages are read only once the client gets to them
+        metrics.record_metric_with_timestamp("load", -1.0, 50_000).await.unwrap();
+        let mut lines = RANGE_PAGE_SIZE;
+        let mut last = None;
+        while let Some(chunk) = chunks.next().await {
+            for line in chunk.unwrap().split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+                last = Some(serde_json::from_slice::<MetricPoint>(line).unwrap());
+                lines += 1;
+            }
+        }
+        assert_eq!(lines, samples as usize + 1);
+        assert_eq!(last, Some(MetricPoint { value: -1.0, timestamp: 50_000 }));
+
+        let read = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                body.split(|&b| b == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .map(|line| serde_json::from_slice::<MetricPoint>(line).unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(read("/metrics/load/range?from=0&to=100000&max_points=1500").await.len(), 1500);
+        let downsampled = read("/metrics/load/range?from=1000&to=1999&step=100").await;
+        assert_eq!(downsampled.len(), 10);
+        assert_eq!(downsampled[1], MetricPoint { value: 149.5, timestamp: 1_100 });
+
+        let response = app
+            .oneshot(Request::get("/metrics/load/range?from=0&to=10&step=0").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_reset_route_zeroes_a_counter() {
+        let state = test_state();
+        for (value, timestamp) in [(3.0, 1_000), (8.0, 1_060)] {
+            state.metrics.record_metric_with_timestamp("jobs_total", value, timestamp).await.unwrap();
+        }
+        let app = worker_router(state.clone());
+        let reset = |uri: &str| Request::post(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(reset("/metrics/jobs_total/reset?keep_history=true")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.metrics.get_metric("jobs_total").await.unwrap(), Some(0.0));
+        assert_eq!(state.metrics.get_metric_aggregate("jobs_total").await.unwrap().unwrap().count, 1);
+        let history = state.metrics.get_recent("jobs_total", 10, None).await.unwrap().unwrap();
+        assert_eq!(history.len(), 3);
+
+        let response = app.oneshot(reset("/metrics/absent/reset")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_transform_scales_later_samples() {
+        let state = test_state();
+        state.metrics.record_metric_with_timestamp("latency_ns", 4_000_000.0, 1_000).await.unwrap();
+        let app = worker_router(state.clone());
+        let put = |body: &'static str| {
+            Request::put("/metrics/latency_ns/transform").header("content-type", "application/json").body(Body::from(body)).unwrap()
+        };
+
+        let response = app.clone().oneshot(put(r#"{"scale":0.001}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.metrics.transform("latency_ns"), IngestTransform { scale: 0.001, offset: 0.0 });
+        let response = app.clone()
+            .oneshot(
+                Request::post("/process")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"metric_name":"latency_ns","value":2500000.0,"timestamp":1060}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.metrics.get_metric("latency_ns").await.unwrap(), Some(2500.0));
+        let aggregate = state.metrics.get_metric_aggregate("latency_ns").await.unwrap().unwrap();
+        // The sample recorded before the transform keeps its value
+        assert_eq!((aggregate.min, aggregate.max), (2500.0, 4_000_000.0));
+
+        let response = app.clone().oneshot(put(r#"{"scale":0}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = app.oneshot(put(r#"{"scale":1,"offset":0}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        state.metrics.record_metric_with_timestamp("latency_ns", 7.0, 1_120).await.unwrap();
+        assert_eq!(state.metrics.get_metric("latency_ns").await.unwrap(), Some(7.0));
+    }
+
+    #[tokio::test]
+    async fn test_state_metric_takes_state_names_and_reports_its_durations() {
+        let state = test_state();
+        let app = worker_router(state.clone());
+        let send = |request: Request<Body>| app.clone().oneshot(request);
+        let post = |body: &'static str| {
+            Request::post("/process").header("content-type", "application/json").body(Body::from(body)).unwrap()
+        };
+
+        let definition = r#"{"states":["down","up"],"stale_after_secs":600}"#;
+        let put = Request::put("/metrics/link/states").header("content-type", "application/json").body(Body::from(definition)).unwrap();
+        assert_eq!(send(put).await.unwrap().status(), StatusCode::NO_CONTENT);
+        let now = chrono::Utc::now().timestamp();
+        for body in [
+            format!(r#"{{"metric_name":"link","state":"up","timestamp":{}}}"#, now - 100),
+            format!(r#"{{"metric_name":"link","value":0,"timestamp":{}}}"#, now - 40),
+            format!(r#"{{"metric_name":"link","state":"up","timestamp":{}}}"#, now - 10),
+        ] {
+            let request = Request::post("/process").header("content-type", "application/json").body(Body::from(body)).unwrap();
+            assert_eq!(send(request).await.unwrap().status(), StatusCode::OK);
+        }
+        for body in [
+            r#"{"metric_name":"link","state":"sideways"}"#,
+            r#"{"metric_name":"link","value":1,"state":"up"}"#,
+            r#"{"metric_name":"link"}"#,
+            r#"{"metric_name":"cpu","state":"up"}"#,
+        ] {
+            assert_eq!(send(post(body)).await.unwrap().status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+
+        let response = send(Request::get("/metrics/link").body(Body::empty()).unwrap()).await.unwrap();
+        let metric: MetricValueResponse = serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!((metric.value, metric.state.as_deref()), (1.0, Some("up")));
+        let response = send(Request::get("/metrics/link/states").body(Body::empty()).unwrap()).await.unwrap();
+        let report: StateReport = serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!((report.durations["up"], report.durations["down"], report.transitions), (60, 30, 2));
+        assert_eq!(report.since, Some(now - 10));
+
+        let response = send(Request::get("/metrics/cpu/states").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_compare_checks_the_current_value_against_a_constant_or_baseline() {
+        let state = test_state();
+        for (i, value) in [10.0, 20.0, 30.0, 40.0, 100.0].iter().enumerate() {
+            state.metrics.record_metric_with_timestamp("errors", *value, 1_000 + i as i64).await.unwrap();
+        }
+        let app = worker_router(state);
+        let compare = |query: &str| {
+            let request = Request::get(format!("/metrics/errors/compare?{}", query)).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<CompareResponse>(&body).unwrap()
+            }
+        };
+
+        let result = compare("op=gt&value=50").await;
+        assert_eq!((result.current, result.threshold, result.breached), (100.0, 50.0, true));
+        assert!(!compare("op=gt&value=150").await.breached);
+        assert!(compare("op=eq&value=100").await.breached);
+
+        // The baseline covers the samples before the current one
+        let result = compare("op=gt&baseline=avg").await;
+        assert_eq!((result.threshold, result.breached, result.baseline), (25.0, true, Some(Baseline::Avg)));
+        assert!(!compare("op=lt&baseline=avg").await.breached);
+        let result = compare("op=gt&baseline=p95").await;
+        assert_eq!((result.threshold, result.breached), (40.0, true));
+        let result = compare("op=gt&baseline=p95&samples=2").await;
+        assert_eq!(result.threshold, 40.0);
+        assert!(!compare("op=lt&baseline=p95").await.breached);
+
+        for (uri, status) in [
+            ("/metrics/absent/compare?op=gt&value=1", StatusCode::NOT_FOUND),
+            ("/metrics/errors/compare?op=gt&value=1&baseline=avg", StatusCode::BAD_REQUEST),
+            ("/metrics/errors/compare?op=gt", StatusCode::BAD_REQUEST),
+            ("/metrics/errors/compare?op=gt&baseline=avg&samples=0", StatusCode::BAD_REQUEST),
+        ] {
+            let response = app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), status, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_histogram_counts_samples_and_refuses_layout_changes() {
+        let state = test_state();
+        let app = worker_router(state.clone());
+        let send = |request: Request<Body>| app.clone().oneshot(request);
+        let put = |uri: &'static str, body: &'static str| {
+            Request::put(uri).header("content-type", "application/json").body(Body::from(body)).unwrap()
+        };
+
+        let layout = r#"{"buckets":[0.125,0.5,1]}"#;
+        assert_eq!(send(put("/metrics/api.latency/histogram", layout)).await.unwrap().status(), StatusCode::NO_CONTENT);
+        for (i, value) in [0.125, 0.5, 0.75, 3.0].iter().enumerate() {
+            let body = serde_json::json!({ "metric_name": "api.latency", "value": value, "timestamp": 1_000 + i as i64 });
+            let request = Request::post("/process").header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+            assert_eq!(send(request).await.unwrap().status(), StatusCode::OK);
+        }
+
+        let response = send(Request::get("/metrics/api.latency/histogram").body(Body::empty()).unwrap()).await.unwrap();
+        let report: HistogramReport = serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let buckets: Vec<(Option<f64>, u64)> = report.histogram.buckets.iter().map(|bucket| (bucket.le, bucket.count)).collect();
+        // Samples exactly on a bound fall in its bucket
+        assert_eq!(buckets, [(Some(0.125), 1), (Some(0.5), 1), (Some(1.0), 1), (None, 1)]);
+        assert_eq!((report.histogram.count, report.histogram.sum), (4, 4.375));
+
+        let response = send(Request::get("/prometheus").body(Body::empty()).unwrap()).await.unwrap();
+        let text = String::from_utf8(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap();
+        assert!(text.contains("# TYPE api_latency histogram"), "{}", text);
+        for series in [
+            r#"api_latency_bucket{le="0.125"} 1"#,
+            r#"api_latency_bucket{le="0.5"} 2"#,
+            r#"api_latency_bucket{le="1"} 3"#,
+            r#"api_latency_bucket{le="+Inf"} 4"#,
+            "api_latency_sum 4.375",
+            "api_latency_count 4",
+        ] {
+            assert!(text.lines().any(|line| line == series), "missing {} in {}", series, text);
+        }
+
+        // The same layout again keeps the counts; a different one needs a reset
+        assert_eq!(send(put("/metrics/api.latency/histogram", layout)).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.metrics.histogram("api.latency").unwrap().count, 4);
+        let response = send(put("/metrics/api.latency/histogram", r#"{"buckets":[0.25,1]}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(state.metrics.histogram("api.latency").unwrap().bounds, [0.125, 0.5, 1.0]);
+        let response = send(put("/metrics/api.latency/histogram?reset=true", r#"{"buckets":[0.25,1]}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let histogram = state.metrics.histogram("api.latency").unwrap();
+        assert_eq!((histogram.bounds, histogram.count), (vec![0.25, 1.0], 0));
+
+        assert_eq!(send(put("/metrics/api.latency/histogram", r#"{"buckets":[1,0.5]}"#)).await.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(send(put("/metrics/default/histogram", "{}")).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.metrics.histogram("default").unwrap().bounds, histograms::default_bounds());
+        let response = send(Request::get("/metrics/cpu/histogram").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_standby_in_its_own_process_follows_the_primary() {
+        // Each worker reaches the other only through its HTTP routes
+        let listeners = [tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap(), tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap()];
+        let urls: Vec<String> = listeners.iter().map(|listener| format!("http://{}", listener.local_addr().unwrap())).collect();
+        let workers: Vec<WorkerState> = (0..2)
+            .map(|worker_id| {
+                let group = RaftGroupConfig::from_lookup(worker_id, |key| match key {
+                    "RAFT_PEERS" => Some(format!("1={}", urls[0])),
+                    "RAFT_LEARNERS" => Some(format!("2={}", urls[1])),
+                    _ => None,
+                });
+                let metrics = Arc::new(MetricsRegistry::new().unwrap());
+                WorkerState::joined(worker_id, metrics, RaftConfig::default(), &group.unwrap().unwrap(), Some(Arc::from("secret"))).unwrap()
+            })
+            .collect();
+        workers[1].serving.store(false, Ordering::Release);
+        for (listener, worker) in listeners.into_iter().zip(&workers) {
+            let router = worker_router(worker.clone());
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        }
+
+        let body = serde_json::json!({ "metric_name": "orders", "value": 7.0 });
+        let request = Request::post("/process?ack=quorum").header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+        assert_eq!(worker_router(workers[0].clone()).oneshot(request).await.unwrap().status(), StatusCode::OK);
+        let mut followed = None;
+        for _ in 0..100 {
+            followed = workers[1].metrics.get_metric("orders").await.unwrap();
+            if followed.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(followed, Some(7.0));
+        assert_eq!(workers[1].raft_status.role(), RaftRole::Follower);
+
+        // Only members holding the admin token can speak for the group
+        let forged = reqwest::Client::new().post(format!("{}/raft/message", urls[1])).body(Vec::new()).send().await.unwrap();
+        assert_eq!(forged.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_interval_downsampling_keeps_aggregates_exact() {
+        let plain = test_state();
+        let mut sampled = test_state();
+        sampled.downsampler = Arc::new(Downsampler::from_lookup(|_| Some("latency=aggregate_interval_ms:3600000".to_string())));
+        let values: Vec<f64> = (0..200).map(|i| ((i * 37) % 101) as f64 / 4.0).collect();
+        for state in [&plain, &sampled] {
+            let app = worker_router(state.clone());
+            for (i, value) in values.iter().enumerate() {
+                let body = serde_json::json!({ "metric_name": "latency", "value": value, "timestamp": 1_000 + i as i64 / 10 });
+                let request = Request::post("/process").header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+                assert!(app.clone().oneshot(request).await.unwrap().status().is_success());
+            }
+        }
+        assert!(sampled.metrics.get_metric_aggregate("latency").await.unwrap().is_none());
+        sampled.propose_summaries(sampled.downsampler.drain()).await;
+
+        let (expected, downsampled) = (
+            plain.metrics.get_metric_aggregate("latency").await.unwrap().unwrap(),
+            sampled.metrics.get_metric_aggregate("latency").await.unwrap().unwrap(),
+        );
+        assert_eq!(downsampled.count, 200);
+        assert_eq!(
+            (downsampled.count, downsampled.total(), downsampled.min, downsampled.max),
+            (expected.count, expected.total(), expected.min, expected.max)
+        );
+        assert!((downsampled.stddev() - expected.stddev()).abs() < 1e-9);
+        assert_eq!(sampled.metrics.get_latest("latency").await.unwrap(), plain.metrics.get_latest("latency").await.unwrap());
+        // One sample reached raft and the store instead of 200
+        assert_eq!((plain.metrics.records_total(), sampled.metrics.records_total()), (200, 1));
+    }
+
+    #[tokio::test]
+    async fn test_process_accepts_explicit_timestamp() {
+        let app = worker_router(test_state());
+        for body in [
+            r#"{"metric_name":"temp","value":20.0,"timestamp":2000}"#,
+            r#"{"metric_name":"temp","value":10.0,"timestamp":1000}"#,
+        ] {
+            let response = app.clone()
+                .oneshot(
+                    Request::post("/process")
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/temp").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let metric: MetricValueResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(metric.value, 20.0);
+
+        let response = app
+            .oneshot(Request::get("/metrics/temp/delta?from=0&to=3000").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let delta: MetricDeltaResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(delta.from.timestamp, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_timestamps_far_in_the_future_are_rejected() {
+        let app = worker_router(test_state());
+        let now = chrono::Utc::now().timestamp();
+        let post = |path: &str, body: String| {
+            Request::post(path).header("content-type", "application/json").body(Body::from(body)).unwrap()
+        };
+
+        let soon = format!(r#"{{"metric_name":"temp","value":1.0,"timestamp":{}}}"#, now + 60);
+        let response = app.clone().oneshot(post("/process", soon)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let far = format!(r#"{{"metric_name":"temp","value":2.0,"timestamp":{}}}"#, now + 86_400);
+        let response = app.clone().oneshot(post("/process", far.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.clone().oneshot(post("/process/batch", format!("[{}]", far))).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let summary: IngestSummary = serde_json::from_slice(&body).unwrap();
+        assert_eq!((summary.accepted, summary.rejected), (0, 1));
+
+        assert_eq!(sample_timestamp(None, now, DEFAULT_MAX_FUTURE_SKEW).unwrap(), now);
+        assert!(sample_timestamp(Some(i64::MAX), i64::MAX - 1, std::time::Duration::MAX).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_process_reads_msgpack_like_json() {
+        let state = test_state();
+        let app = worker_router(state.clone());
+        let request = |name: &str| MetricRequest {
+            metric_name: name.to_string(),
+            value: 42.5,
+            state: None,
+            timestamp: Some(1_000),
+            labels: Labels::from([("region".to_string(), "eu".to_string())]),
+        };
+        let post = |content_type: &str, body: Vec<u8>| {
+            app.clone().oneshot(
+                Request::post("/process")
+                    .header("content-type", content_type)
+                    .header("accept", content_type)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+
+        let response = post("application/json", serde_json::to_vec(&request("json_temp")).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let from_json: MetricValueResponse = serde_json::from_slice(&body).unwrap();
+
+        let response = post(MSGPACK, rmp_serde::to_vec_named(&request("msgpack_temp")).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], MSGPACK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let from_msgpack: MetricValueResponse = rmp_serde::from_slice(&body).unwrap();
+        // Each write is a later raft entry, so only the version differs
+        assert_eq!(
+            from_msgpack,
+            MetricValueResponse { name: "msgpack_temp".to_string(), version: from_msgpack.version.clone(), ..from_json }
+        );
+
+        let stored = |name: &'static str| {
+            let metrics = state.metrics.clone();
+            async move {
+                let samples = metrics.get_recent(name, 10, None).await.unwrap().unwrap();
+                let aggregate = metrics.get_metric_aggregate(name).await.unwrap().unwrap();
+                let by_region = GroupByQuery {
+                    label: "region".to_string(),
+                    function: AggregateFn::Sum,
+                    from: i64::MIN,
+                    to: i64::MAX,
+                    max_groups: MAX_GROUPS,
+                };
+                (samples, aggregate.count, aggregate.total(), metrics.group_by(name, by_region).await.unwrap())
+            }
+        };
+        assert_eq!(stored("json_temp").await, stored("msgpack_temp").await);
+
+        assert_eq!(post(MSGPACK, b"not msgpack".to_vec()).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_health_degraded_while_slo_burning() {
+        let mut state = test_state();
+        state.slo = Arc::new(SloTracker::new(&SloConfig {
+            slos: vec![crate::config::SloDefinition::parse("reads=GET /metrics/*,p99,0ms,60s").unwrap()],
+            ..SloConfig::default()
+        }));
+        let app = worker_router(state);
+
+        let response = app.clone()
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["status"], "healthy");
+
+        app.clone()
+            .oneshot(Request::get("/metrics/missing").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let response = app.clone()
+            .oneshot(Request::get("/slo").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let slos: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(slos[0]["name"], "reads");
+        assert_eq!(slos[0]["requests"], 1);
+        assert_eq!(slos[0]["compliance"], 0.0);
+
+        let response = app
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["status"], "degraded");
+    }
+
+    #[tokio::test]
+    async fn test_health_follows_a_stalled_apply() {
+        let mut state = test_state();
+        state.raft_health = RaftHealthConfig {
+            max_apply_lag_entries: 1000,
+            max_apply_lag: std::time::Duration::from_millis(100),
+            max_tick_gap: std::time::Duration::from_millis(400),
+        };
+        let app = worker_router(state.clone());
+        let health = |app: Router| async move {
+            let response = app.oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+        };
+        let wait_for = |app: Router, wanted: &'static str| async move {
+            for _ in 0..100 {
+                let (status, body) = health(app.clone()).await;
+                if body["status"] == wanted {
+                    return (status, body);
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            panic!("health never became {}", wanted);
+        };
+        wait_for(app.clone(), "healthy").await;
+
+        let stall = state.metrics.stall_apply().await;
+        let writer = state.clone();
+        let write = tokio::spawn(async move {
+            writer.propose(MetricOperation::record("cpu", 1.0, 1000, Labels::new())).await
+        });
+
+        let (status, body) = wait_for(app.clone(), "degraded").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["raft"]["apply_lag_entries"], 1);
+        let (status, body) = wait_for(app.clone(), "unhealthy").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body["message"].as_str().unwrap().contains("not ticked"));
+
+        drop(stall);
+        write.await.unwrap().unwrap();
+        let (status, body) = wait_for(app.clone(), "healthy").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["raft"]["apply_lag_entries"], 0);
+
+        let response = app.oneshot(Request::get("/raft/status").body(Body::empty()).unwrap()).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let raft: RaftDebugResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(raft.lag.commit_index, raft.applied_index);
+        assert_eq!(raft.lag.health.status, HealthStatus::Healthy);
+    }
+
+    #[tokio::test]
+    async fn test_debug_raft_reports_single_voter_as_leader() {
+        let app = worker_router(test_state());
+        let mut raft = None;
+        for _ in 0..50 {
+            let response = app.clone()
+                .oneshot(Request::get("/debug/raft").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let status: RaftDebugResponse = serde_json::from_slice(&body).unwrap();
+            if status.role == RaftRole::Leader {
+                raft = Some(status);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let raft = raft.expect("single voter never became leader");
+        assert_eq!((raft.node_id, raft.leader_id), (2, 2));
+        assert!(raft.term >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_queue_depth_follows_the_proposal_channel() {
+        // No raft loop takes from the channel, so proposals stay queued
+        let (proposal_tx, mut proposal_rx) = mpsc::channel(4);
+        let status = Arc::new(RaftStatus::new(41));
+        let state = WorkerState::over_raft(1, Arc::new(MetricsRegistry::new().unwrap()), (proposal_tx, status.clone()));
+        let proposal = |value| Proposal::new(MetricOperation::record("cpu", value, 100, Labels::new())).0;
+
+        for value in 0..4 {
+            state.enqueue(proposal(value as f64)).await.unwrap();
+            assert_eq!(status.proposal_queue_depth(), value + 1);
+        }
+        assert!(state.proposal_tx.try_send(proposal(4.0)).is_err());
+        assert_eq!(status.proposal_queue_high_water(), 4);
+        let gauge = |gauge: &prometheus::IntGaugeVec| gauge.with_label_values(&["41"]).get();
+        assert_eq!(gauge(&crate::metrics::PROPOSAL_QUEUE_HIGH_WATER), 4);
+
+        for _ in 0..3 {
+            proposal_rx.recv().await.unwrap();
+        }
+        state.enqueue(proposal(5.0)).await.unwrap();
+        assert_eq!(status.proposal_queue_depth(), 2);
+        assert_eq!(status.proposal_queue_high_water(), 4);
+        assert_eq!((gauge(&crate::metrics::PROPOSAL_QUEUE_DEPTH), gauge(&crate::metrics::PROPOSAL_QUEUE_HIGH_WATER)), (2, 4));
+    }
+
+    #[tokio::test]
+    async fn test_precision_rounds_read_responses() {
+        let state = test_state();
+        state.metrics.record_metric("ratio", 2.0 / 3.0).await.unwrap();
+        state.metrics.record_metric("huge", 1e300).await.unwrap();
+        let app = worker_router(state);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/ratio?precision=3").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let metric: MetricValueResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(metric.value, 0.667);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/ratio/aggregate?precision=2").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let aggregate: MetricAggregateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(aggregate.sum, 0.67);
+        assert_eq!(aggregate.average, 0.67);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/ratio?precision=40").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Scaling 1e300 to 15 places overflows rather than rounding to infinity
+        let response = app
+            .oneshot(Request::get("/metrics/huge?precision=15").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_carries_the_latest_value() {
+        let state = test_state();
+        for (value, timestamp) in [(5.0, 200), (9.0, 100)] {
+            state.metrics.record_metric_with_timestamp("temp", value, timestamp).await.unwrap();
+        }
+        let app = worker_router(state);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/temp/aggregate").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let aggregate: MetricAggregateResponse = serde_json::from_slice(&body).unwrap();
+        // The late sample is the largest but not the latest
+        assert_eq!((aggregate.latest, aggregate.max), (5.0, 9.0));
+
+        let request = Request::post("/aggregate/batch")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"names":["temp","absent"]}"#))
+            .unwrap();
+        let body = axum::body::to_bytes(app.oneshot(request).await.unwrap().into_body(), usize::MAX).await.unwrap();
+        let batch: BatchAggregateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(batch["temp"].as_ref().map(|aggregate| aggregate.latest), Some(5.0));
+        assert_eq!(batch["absent"], None);
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_until_recovered() {
+        let state = test_state();
+        state.set_ready(false);
+        let app = worker_router(state.clone());
+
+        let response = app.clone()
+            .oneshot(Request::get("/ready").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = app.clone()
+            .oneshot(Request::get("/metrics/cpu").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = app.clone()
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state.metrics.recover().await.unwrap();
+        state.set_ready(true);
+
+        let response = app
+            .oneshot(Request::get("/ready").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_standby_missing_a_snapshot_is_not_promoted() {
+        let mut state = test_state();
+        state.admin_token = Some(Arc::from("secret"));
+        state.serving.store(false, Ordering::Release);
+        state.raft_status.refused_snapshot.store(6, Ordering::Relaxed);
+        let app = worker_router(state.clone());
+        let promote = || Request::post("/admin/promote").header("authorization", "Bearer secret").body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(promote()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(!state.is_serving());
+
+        state.raft_status.refused_snapshot.store(0, Ordering::Relaxed);
+        let response = app.oneshot(promote()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.is_serving());
+    }
+
+    #[tokio::test]
+    async fn test_error_bodies_carry_stable_codes() {
+        let state = test_state();
+        let app = worker_router(state.clone());
+        let code_of = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (status, body["code"].as_str().unwrap().to_string(), body["retriable"].as_bool().unwrap())
+            }
+        };
+
+        let missing = code_of(Request::get("/metrics/absent").body(Body::empty()).unwrap()).await;
+        assert_eq!(missing, (StatusCode::NOT_FOUND, "METRIC_NOT_FOUND".to_string(), false));
+        let empty_transaction = Request::post("/process/transaction")
+            .header("content-type", "application/json")
+            .body(Body::from("[]"))
+            .unwrap();
+        let invalid = code_of(empty_transaction).await;
+        assert_eq!(invalid, (StatusCode::BAD_REQUEST, "VALIDATION_FAILED".to_string(), false));
 
-    let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, worker_router(state)).await.unwrap();
+        state.set_ready(false);
+        let recovering = code_of(Request::get("/metrics/absent").body(Body::empty()).unwrap()).await;
+        assert_eq!(recovering, (StatusCode::SERVICE_UNAVAILABLE, "WORKER_UNAVAILABLE".to_string(), true));
+    }
 }
@@ -0,0 +1,96 @@
+//! A typed client for a control node's HTTP API, used by tests and to
+//! forward metrics to another cluster.
+
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+use crate::{
+    api::{
+        forwarding::HOPS_HEADER,
+        types::{IngestSummary, MetricAggregateResponse, MetricRequest, MetricValueResponse, WriteResponse},
+    },
+    RaftMetricsError, Result,
+};
+
+/// A client for the control node's HTTP API.
+#[derive(Debug, Clone)]
+pub struct RaftMetricsClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl RaftMetricsClient {
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http(base_url, reqwest::Client::new())
+    }
+
+    /// Like [`Self::new`], sending through `http`, e.g. one with a timeout.
+    pub fn with_http(base_url: &str, http: reqwest::Client) -> Self {
+        Self { base_url: base_url.trim_end_matches('/').to_string(), http }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Records `value` for `name` now, through `POST /metrics`.
+    pub async fn record(&self, name: &str, value: f64) -> Result<WriteResponse> {
+        let request = MetricRequest { metric_name: name.to_string(), value, state: None, timestamp: None, labels: Default::default() };
+        self.send(self.http.post(format!("{}/metrics", self.base_url)).json(&request)).await
+    }
+
+    /// Records `requests` as NDJSON through `POST /metrics/stream`, marked
+    /// as having been forwarded `hops` times already when that is not zero.
+    pub async fn record_stream(&self, requests: &[MetricRequest], hops: u32) -> Result<IngestSummary> {
+        let mut body = String::new();
+        for request in requests {
+            let line = serde_json::to_string(request).map_err(|e| RaftMetricsError::Internal(format!("Failed to encode record: {}", e)))?;
+            body.push_str(&line);
+            body.push('\n');
+        }
+        let mut request = self.http
+            .post(format!("{}/metrics/stream", self.base_url))
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body);
+        if hops > 0 {
+            request = request.header(HOPS_HEADER, hops);
+        }
+        self.send(request).await
+    }
+
+    /// The latest value of `name`, through `GET /metrics/:name`.
+    pub async fn get_metric(&self, name: &str) -> Result<MetricValueResponse> {
+        self.send(self.http.get(format!("{}/metrics/{}", self.base_url, name))).await
+    }
+
+    /// The aggregate of `name`, through `GET /metrics/:name/aggregate`.
+    pub async fn aggregate(&self, name: &str) -> Result<MetricAggregateResponse> {
+        self.send(self.http.get(format!("{}/metrics/{}/aggregate", self.base_url, name))).await
+    }
+
+    /// Polls `/health` until it answers `200`, for at most `timeout`.
+    pub async fn wait_until_healthy(&self, timeout: Duration) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match self.http.get(format!("{}/health", self.base_url)).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                _ if tokio::time::Instant::now() >= deadline => {
+                    return Err(RaftMetricsError::Unavailable(format!("{} was not healthy within {:?}", self.base_url, timeout)));
+                }
+                _ => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+    }
+
+    /// Sends `request`, decoding a success as `T` and anything else as the
+    /// error the node answered with.
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let response = request.send().await.map_err(|e| RaftMetricsError::Request(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(RaftMetricsError::from_response(status.as_u16(), &body));
+        }
+        response.json().await.map_err(|e| RaftMetricsError::Request(format!("Failed to parse response: {}", e)))
+    }
+}
@@ -0,0 +1,1239 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+pub mod units;
+
+use crate::{api::{audit::DEFAULT_AUDIT_REPORTS_FILE, forwarding::{self, ForwardingConfig}, reports::ReportConfig}, metrics::{codec::RaftCodec, quotas::{self, TenantQuotas}, MAX_OPS_PER_ENTRY}, partitioning::{parse_weights, PartitionKey}, RaftMetricsError, Result};
+
+/// Connection pool and timeout settings for one forwarding HTTP client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpPoolConfig {
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout: Duration,
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    pub tcp_keepalive: Duration,
+}
+
+impl HttpPoolConfig {
+    /// Defaults for latency-sensitive single-metric reads.
+    pub fn read_defaults() -> Self {
+        Self {
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: Duration::from_secs(90),
+            connect_timeout: Duration::from_millis(500),
+            request_timeout: Duration::from_secs(2),
+            tcp_keepalive: Duration::from_secs(60),
+        }
+    }
+
+    /// Defaults for writes, which may carry larger bodies and wait on consensus.
+    pub fn write_defaults() -> Self {
+        Self {
+            pool_max_idle_per_host: 16,
+            pool_idle_timeout: Duration::from_secs(90),
+            connect_timeout: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(10),
+            tcp_keepalive: Duration::from_secs(60),
+        }
+    }
+
+    /// Overrides defaults from `<PREFIX>_POOL_MAX_IDLE_PER_HOST`,
+    /// `<PREFIX>_POOL_IDLE_TIMEOUT_MS`, `<PREFIX>_CONNECT_TIMEOUT_MS`,
+    /// `<PREFIX>_REQUEST_TIMEOUT_MS` and `<PREFIX>_TCP_KEEPALIVE_SECS`.
+    fn from_lookup(prefix: &str, defaults: Self, lookup: &impl Fn(&str) -> Option<String>) -> Self {
+        let key = |key: &str| format!("{}_{}", prefix, key);
+        let duration = |name: &str| units::duration(lookup, &key(name));
+
+        Self {
+            pool_max_idle_per_host: lookup(&key("POOL_MAX_IDLE_PER_HOST"))
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.pool_max_idle_per_host),
+            pool_idle_timeout: duration("POOL_IDLE_TIMEOUT_MS").unwrap_or(defaults.pool_idle_timeout),
+            connect_timeout: duration("CONNECT_TIMEOUT_MS").unwrap_or(defaults.connect_timeout),
+            request_timeout: duration("REQUEST_TIMEOUT_MS").unwrap_or(defaults.request_timeout),
+            tcp_keepalive: duration("TCP_KEEPALIVE_SECS").unwrap_or(defaults.tcp_keepalive),
+        }
+    }
+
+    pub fn build_client(&self) -> reqwest::Result<reqwest::Client> {
+        self.client_builder().build()
+    }
+
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        reqwest::Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(true)
+    }
+}
+
+/// Default cap on how long an ingest capture runs.
+pub const DEFAULT_CAPTURE_MAX_SECS: u64 = 600;
+
+/// Default time a self-registered worker stays routed without a heartbeat.
+pub const DEFAULT_REGISTRATION_TTL_SECS: u64 = 30;
+pub const DEFAULT_HEARTBEAT_TIMEOUT_SECS: u64 = 15;
+pub const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 5;
+/// Default time between polls of every worker's `GET /load`.
+pub const DEFAULT_LOAD_POLL_INTERVAL_SECS: u64 = 10;
+
+/// Control node configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlConfig {
+    pub worker_urls: Vec<String>,
+    /// Per-worker weights for the weighted ring (`ROUTING_STRATEGY=weighted`);
+    /// jump hashing is used when unset.
+    pub worker_weights: Option<Vec<u32>>,
+    /// Workers in `worker_urls` that missed their heartbeats under jump
+    /// hashing. They keep their slot, so no other worker's metrics move,
+    /// but their own metrics are routed to the others. Only ever set from
+    /// the control node's membership, never from the environment.
+    pub down_workers: Vec<String>,
+    /// Mixed into metric name hashes with a fixed hash function when set, so
+    /// partition assignments are reproducible across builds.
+    pub partition_seed: Option<u64>,
+    /// Label keys routed on alongside the metric name.
+    pub partition_key: PartitionKey,
+    pub read_pool: HttpPoolConfig,
+    pub write_pool: HttpPoolConfig,
+    pub deadline: DeadlineConfig,
+    /// Bearer token for `/admin` endpoints; they are disabled when unset.
+    pub admin_token: Option<String>,
+    /// Serves admin, membership and Prometheus routes on this port, apart
+    /// from the data API on `PORT`. Unset serves everything on `PORT`.
+    pub admin_port: Option<u16>,
+    pub slo: SloConfig,
+    /// Longest an ingest capture may run before it stops on its own.
+    pub capture_max_duration: Duration,
+    /// How long a worker registered via `POST /cluster/register` stays
+    /// routed without renewing its registration.
+    pub registration_ttl: Duration,
+    /// How long a worker that has sent `POST /cluster/heartbeat` may go
+    /// without another before it is marked down and leaves routing.
+    pub heartbeat_timeout: Duration,
+    /// How often worker load reports are polled; zero turns polling off.
+    pub load_poll_interval: Duration,
+    pub shadow: ShadowConfig,
+    pub read_cache: ReadCacheConfig,
+    pub cache_prime: CachePrimeConfig,
+    /// Consistency audits of routed reads against each copy's own state.
+    pub audit: AuditConfig,
+    /// Workers holding copies of a worker's metrics, by the URL of the
+    /// worker they copy, tried in order when it cannot serve a read. Keyed
+    /// by URL so they follow their worker when membership reorders the
+    /// partitions.
+    pub replica_urls: BTreeMap<String, Vec<String>>,
+    /// Standby workers for a worker, by its URL, tried after its replicas.
+    /// A standby only answers once promoted.
+    pub standby_urls: BTreeMap<String, Vec<String>>,
+    /// Summaries pushed to webhooks on a schedule, from the JSON array in
+    /// `REPORTS`.
+    pub reports: Vec<ReportConfig>,
+    /// Per-tenant limits from `TENANT_QUOTAS`. The rate is enforced here;
+    /// series and storage are pushed to workers on reload.
+    pub quotas: TenantQuotas,
+    /// Metrics forwarded downstream, from `FORWARD_RULES` and friends.
+    pub forwarding: ForwardingConfig,
+    /// JSON file of per-prefix API key grants; see [`crate::api::acl::Acl`].
+    /// Read again on every reload.
+    pub acl_path: Option<PathBuf>,
+}
+
+impl ControlConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Builds the config from an arbitrary key lookup so tests need not touch
+    /// the process environment.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let worker_urls = lookup("WORKER_HOSTS")
+            .unwrap_or_else(|| "http://localhost:8081".to_string())
+            .split(',')
+            .map(normalize_worker_url)
+            .collect::<Vec<_>>();
+        let replica_urls = partition_hosts("REPLICA_HOSTS", &worker_urls, &lookup);
+        let standby_urls = partition_hosts("STANDBY_HOSTS", &worker_urls, &lookup);
+        let reports = match lookup("REPORTS").filter(|v| !v.trim().is_empty()) {
+            Some(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Ignoring malformed REPORTS: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let worker_weights = (lookup("ROUTING_STRATEGY").as_deref() == Some("weighted"))
+            .then(|| parse_weights(&lookup("WORKER_WEIGHTS").unwrap_or_default(), worker_urls.len()));
+
+        Self {
+            worker_urls,
+            worker_weights,
+            down_workers: Vec::new(),
+            partition_seed: lookup("PARTITION_SEED").and_then(|v| v.trim().parse().ok()),
+            partition_key: PartitionKey::from_lookup(&lookup),
+            read_pool: HttpPoolConfig::from_lookup("READ", HttpPoolConfig::read_defaults(), &lookup),
+            write_pool: HttpPoolConfig::from_lookup("WRITE", HttpPoolConfig::write_defaults(), &lookup),
+            deadline: DeadlineConfig::from_lookup(&lookup),
+            admin_token: lookup("ADMIN_TOKEN").filter(|token| !token.is_empty()),
+            admin_port: lookup("ADMIN_PORT").and_then(|v| v.trim().parse().ok()),
+            slo: SloConfig::from_lookup(&lookup),
+            capture_max_duration: units::duration(&lookup, "CAPTURE_MAX_SECS").unwrap_or(Duration::from_secs(DEFAULT_CAPTURE_MAX_SECS)),
+            shadow: ShadowConfig::from_lookup(&lookup),
+            read_cache: ReadCacheConfig::from_lookup(&lookup),
+            cache_prime: CachePrimeConfig::from_lookup(&lookup),
+            audit: AuditConfig::from_lookup(&lookup),
+            replica_urls,
+            standby_urls,
+            reports,
+            quotas: TenantQuotas::from_lookup(&lookup),
+            forwarding: ForwardingConfig::from_lookup(&lookup),
+            acl_path: lookup("ACL_PATH").filter(|path| !path.trim().is_empty()).map(PathBuf::from),
+            registration_ttl: units::duration(&lookup, "REGISTRATION_TTL_SECS").unwrap_or(Duration::from_secs(DEFAULT_REGISTRATION_TTL_SECS)),
+            heartbeat_timeout: units::duration(&lookup, "HEARTBEAT_TIMEOUT_SECS").unwrap_or(Duration::from_secs(DEFAULT_HEARTBEAT_TIMEOUT_SECS)),
+            load_poll_interval: units::duration(&lookup, "LOAD_POLL_INTERVAL_SECS").unwrap_or(Duration::from_secs(DEFAULT_LOAD_POLL_INTERVAL_SECS)),
+        }
+    }
+
+    /// Reads the config from `config_file`, a file of `KEY=VALUE` lines
+    /// (blank lines and `#` comments ignored), falling back to the process
+    /// environment for keys the file does not set. Without a file this is
+    /// [`ControlConfig::from_env`].
+    pub fn load(config_file: Option<&Path>) -> Result<Self> {
+        let Some(path) = config_file else {
+            return Ok(Self::from_env());
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            RaftMetricsError::InvalidConfig(vec![format!("cannot read {}: {}", path.display(), e)])
+        })?;
+
+        let mut vars = std::collections::HashMap::new();
+        let mut errors = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    vars.insert(key.trim().to_string(), value.trim().to_string());
+                }
+                None => errors.push(format!("{}:{}: expected KEY=VALUE", path.display(), number + 1)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(RaftMetricsError::InvalidConfig(errors));
+        }
+
+        let lookup = |key: &str| vars.get(key).cloned().or_else(|| std::env::var(key).ok());
+        let mut errors = units::errors(&lookup);
+        errors.extend(quotas::quota_errors(&lookup));
+        errors.extend(forwarding::forwarding_errors(&lookup));
+        if !errors.is_empty() {
+            return Err(RaftMetricsError::InvalidConfig(errors));
+        }
+        Ok(Self::from_lookup(lookup))
+    }
+
+    /// Replicas of the worker at `worker_url`, in the order to try them.
+    pub fn replicas_of(&self, worker_url: &str) -> &[String] {
+        self.replica_urls.get(worker_url).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Standbys of the worker at `worker_url`, in the order to try them.
+    pub fn standbys_of(&self, worker_url: &str) -> &[String] {
+        self.standby_urls.get(worker_url).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Lists everything wrong with this config, so a reload can be refused
+    /// before any of it takes effect.
+    pub fn validate(&self) -> std::result::Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.worker_urls.is_empty() {
+            errors.push("WORKER_HOSTS must list at least one worker".to_string());
+        }
+        for (i, url) in self.worker_urls.iter().enumerate() {
+            if reqwest::Url::parse(url).map_or(true, |url| url.host_str().is_none()) {
+                errors.push(format!("WORKER_HOSTS entry '{}' is not a valid URL", url));
+            }
+            if self.worker_urls[..i].contains(url) {
+                errors.push(format!("WORKER_HOSTS lists '{}' more than once", url));
+            }
+        }
+        if self.worker_weights.as_ref().is_some_and(|weights| weights.iter().all(|&w| w == 0)) {
+            errors.push("WORKER_WEIGHTS must give at least one worker a non-zero weight".to_string());
+        }
+        for (prefix, pool) in [("READ", &self.read_pool), ("WRITE", &self.write_pool)] {
+            if pool.request_timeout.is_zero() {
+                errors.push(format!("{}_REQUEST_TIMEOUT_MS must be positive", prefix));
+            }
+            if pool.connect_timeout.is_zero() {
+                errors.push(format!("{}_CONNECT_TIMEOUT_MS must be positive", prefix));
+            }
+        }
+        if self.deadline.default_budget <= self.deadline.safety_margin {
+            errors.push("DEFAULT_DEADLINE_MS must exceed DEADLINE_SAFETY_MARGIN_MS".to_string());
+        }
+        if self.registration_ttl.is_zero() {
+            errors.push("REGISTRATION_TTL_SECS must be positive".to_string());
+        }
+        if self.heartbeat_timeout.is_zero() {
+            errors.push("HEARTBEAT_TIMEOUT_SECS must be positive".to_string());
+        }
+        for (key, hosts) in [("REPLICA_HOSTS", &self.replica_urls), ("STANDBY_HOSTS", &self.standby_urls)] {
+            for (worker_url, urls) in hosts {
+                for url in urls {
+                    if reqwest::Url::parse(url).map_or(true, |url| url.host_str().is_none()) {
+                        errors.push(format!("{} entry '{}' for {} is not a valid URL", key, url, worker_url));
+                    }
+                }
+            }
+        }
+        errors.extend(self.shadow.validate());
+        if self.read_cache.capacity > 0 && self.read_cache.ttl.is_zero() {
+            errors.push("READ_CACHE_TTL_MS must be positive when READ_CACHE_SIZE is set".to_string());
+        }
+        for (i, report) in self.reports.iter().enumerate() {
+            errors.extend(report.validate());
+            if self.reports[..i].iter().any(|other| other.id == report.id) {
+                errors.push(format!("REPORTS lists '{}' more than once", report.id));
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+/// Reads `key` as comma-separated `partition=url` entries, grouping the URLs
+/// in the order given under the URL of the `worker_urls` entry the
+/// partition counts to. Malformed entries and partitions past the last
+/// worker are skipped with a warning.
+fn partition_hosts(key: &str, worker_urls: &[String], lookup: &impl Fn(&str) -> Option<String>) -> BTreeMap<String, Vec<String>> {
+    let mut hosts = BTreeMap::<String, Vec<String>>::new();
+    for entry in lookup(key).unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+        let parsed = entry.split_once('=').and_then(|(partition, host)| Some((partition.trim().parse::<usize>().ok()?, host.trim())));
+        match parsed.and_then(|(partition, host)| Some((worker_urls.get(partition)?, host))) {
+            Some((worker_url, host)) => hosts.entry(worker_url.clone()).or_default().push(normalize_worker_url(host)),
+            None => warn!("Ignoring {} entry '{}'", key, entry),
+        }
+    }
+    hosts
+}
+
+/// A `WORKER_HOSTS` entry as a URL, assuming `http://` when no scheme is given.
+pub fn normalize_worker_url(host: &str) -> String {
+    if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("http://{}", host)
+    }
+}
+
+/// Roles a node can start as through `NODE_TYPE`.
+pub const NODE_TYPES: [&str; 3] = ["control", "worker", "standalone"];
+
+/// Checks the process environment a node reads before it starts; see
+/// [`validate_startup_lookup`].
+pub fn validate_startup_config() -> std::result::Result<(), Vec<String>> {
+    validate_startup_lookup(|key| std::env::var(key).ok())
+}
+
+/// Lists every problem with the settings read before a node starts:
+/// `NODE_TYPE`, `NODE_ID`/`WORKER_ID`, `PORT`, the trace sampling ratio,
+/// `TENANT_QUOTAS`, `FORWARD_RULES`, every duration and size, for workers
+/// the raft health limits and, for the control node, `WORKER_HOSTS`. Unset keys keep their defaults and are not errors.
+pub fn validate_startup_lookup(lookup: impl Fn(&str) -> Option<String>) -> std::result::Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+
+    let node_type = lookup("NODE_TYPE").unwrap_or_else(|| "control".to_string());
+    if !NODE_TYPES.contains(&node_type.as_str()) {
+        errors.push(format!("NODE_TYPE '{}' must be one of {}", node_type, NODE_TYPES.join(", ")));
+    }
+    for key in ["NODE_ID", "WORKER_ID"] {
+        if let Some(id) = lookup(key).filter(|id| id.parse::<usize>().is_err()) {
+            errors.push(format!("{} '{}' is not a non-negative integer", key, id));
+        }
+    }
+    if let Some(port) = lookup("PORT").filter(|port| port.parse::<u16>().map_or(true, |port| port == 0)) {
+        errors.push(format!("PORT '{}' is not a port between 1 and 65535", port));
+    }
+    if node_type == "control" && lookup("WORKER_HOSTS").is_some_and(|hosts| hosts.split(',').all(|host| host.trim().is_empty())) {
+        errors.push("WORKER_HOSTS must list at least one worker".to_string());
+    }
+    let ratio_key = crate::telemetry::SAMPLE_RATIO_KEY;
+    if let Some(ratio) = lookup(ratio_key).filter(|ratio| !ratio.parse::<f64>().is_ok_and(|ratio| (0.0..=1.0).contains(&ratio))) {
+        errors.push(format!("{} '{}' is not a ratio between 0 and 1", ratio_key, ratio));
+    }
+    let raft_health = RaftHealthConfig::from_lookup(&lookup);
+    if node_type != "control" && raft_health.max_apply_lag >= raft_health.max_tick_gap {
+        errors.push("RAFT_MAX_APPLY_LAG_MS must be below RAFT_MAX_TICK_GAP_MS".to_string());
+    }
+    errors.extend(units::errors(&lookup));
+    errors.extend(quotas::quota_errors(&lookup));
+    errors.extend(forwarding::forwarding_errors(&lookup));
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Request budget settings shared by control and worker nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadlineConfig {
+    /// Budget for requests that carry no deadline header.
+    pub default_budget: Duration,
+    /// Time held back from a forwarded call so the control node can still
+    /// answer before its own client gives up.
+    pub safety_margin: Duration,
+}
+
+impl Default for DeadlineConfig {
+    fn default() -> Self {
+        Self {
+            default_budget: Duration::from_secs(10),
+            safety_margin: Duration::from_millis(5),
+        }
+    }
+}
+
+impl DeadlineConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(&|key: &str| std::env::var(key).ok())
+    }
+
+    /// Reads `DEFAULT_DEADLINE_MS` and `DEADLINE_SAFETY_MARGIN_MS`.
+    pub fn from_lookup(lookup: &impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            default_budget: units::duration(lookup, "DEFAULT_DEADLINE_MS").unwrap_or(defaults.default_budget),
+            safety_margin: units::duration(lookup, "DEADLINE_SAFETY_MARGIN_MS").unwrap_or(defaults.safety_margin),
+        }
+    }
+}
+
+/// A latency objective: `percentile`% of requests to endpoints matching
+/// `endpoint` finish within `threshold`, measured over a rolling `window`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SloDefinition {
+    pub name: String,
+    /// A route such as `/metrics/:name`, optionally prefixed with a method
+    /// (`GET /metrics/:name`) and ending in `*` to match a prefix.
+    pub endpoint: String,
+    pub percentile: f64,
+    pub threshold: Duration,
+    pub window: Duration,
+}
+
+impl SloDefinition {
+    /// Parses `name=<endpoint>,p<percentile>,<threshold>,<window>`, e.g.
+    /// `reads=GET /metrics/*,p99,100ms,5m`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (name, rest) = spec.split_once('=')?;
+        let mut fields = rest.split(',').map(str::trim);
+        let endpoint = fields.next()?.to_string();
+        let percentile: f64 = fields.next()?.strip_prefix('p')?.parse().ok()?;
+        let threshold = parse_duration(fields.next()?)?;
+        let window = parse_duration(fields.next()?)?;
+        if fields.next().is_some() || endpoint.is_empty() || !(0.0..100.0).contains(&percentile) || window.is_zero() {
+            return None;
+        }
+        Some(Self { name: name.trim().to_string(), endpoint, percentile, threshold, window })
+    }
+}
+
+/// Parses a duration such as `250ms`, `30s`, `5m` or `1h`. Unlike a
+/// setting's value, an SLO field has no key to name its unit, so it must
+/// carry one.
+fn parse_duration(value: &str) -> Option<Duration> {
+    if !value.ends_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    units::parse_duration(value, Duration::ZERO).ok()
+}
+
+/// Latency objectives tracked by a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SloConfig {
+    pub slos: Vec<SloDefinition>,
+    /// Burn rate above which `/health` reports the node as degraded.
+    pub degraded_burn_rate: f64,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self { slos: Vec::new(), degraded_burn_rate: 2.0 }
+    }
+}
+
+impl SloConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(&|key: &str| std::env::var(key).ok())
+    }
+
+    /// Reads `;`-separated definitions from `SLOS` (see
+    /// [`SloDefinition::parse`]) and `SLO_DEGRADED_BURN_RATE`. Malformed
+    /// definitions are skipped with a warning.
+    pub fn from_lookup(lookup: &impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let slos = lookup("SLOS")
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .filter_map(|spec| {
+                let slo = SloDefinition::parse(spec);
+                if slo.is_none() {
+                    warn!("Ignoring malformed SLO definition '{}'", spec);
+                }
+                slo
+            })
+            .collect();
+
+        Self {
+            slos,
+            degraded_burn_rate: lookup("SLO_DEGRADED_BURN_RATE")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.degraded_burn_rate),
+        }
+    }
+}
+
+/// Bounds on the series a worker keeps in memory. DuckDB stays the source
+/// of truth; evicted series are read back from it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryBudget {
+    /// Most series resident in memory, or `None` for no limit.
+    pub max_entries: Option<usize>,
+    /// How many of the most-read series are never evicted.
+    pub hot_entries: usize,
+    /// Most recent samples kept in memory per series for range reads; 0
+    /// keeps none. Older samples are read from the store.
+    pub max_history: usize,
+    /// Most series loaded into memory when a worker starts, or `None` for
+    /// as many as `max_entries` allows.
+    pub warm_entries: Option<usize>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self { max_entries: None, hot_entries: 64, max_history: 1000, warm_entries: None }
+    }
+}
+
+impl MemoryBudget {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `MEMORY_MAX_ENTRIES`, `MEMORY_HOT_ENTRIES`,
+    /// `MAX_HISTORY_PER_METRIC` and `MEMORY_WARM_ENTRIES`. The hot set is
+    /// capped at half the budget so eviction always has room to work.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let get = |key: &str| lookup(key).and_then(|v| v.parse::<usize>().ok());
+
+        let max_entries = get("MEMORY_MAX_ENTRIES").filter(|&max| max > 0);
+        let hot_entries = get("MEMORY_HOT_ENTRIES").unwrap_or(defaults.hot_entries);
+        Self {
+            max_entries,
+            hot_entries: max_entries.map_or(hot_entries, |max| hot_entries.min(max / 2)),
+            max_history: get("MAX_HISTORY_PER_METRIC").unwrap_or(defaults.max_history),
+            warm_entries: get("MEMORY_WARM_ENTRIES").or(defaults.warm_entries),
+        }
+    }
+
+    /// Most series to load into memory at startup: `warm_entries`, within
+    /// `max_entries`, or `None` for all of them.
+    pub fn warm_limit(&self) -> Option<usize> {
+        match (self.warm_entries, self.max_entries) {
+            (Some(warm), Some(max)) => Some(warm.min(max)),
+            (warm, max) => warm.or(max),
+        }
+    }
+}
+
+/// Limits on ad-hoc SQL run through a worker's `POST /query` and on its
+/// `GET /metrics/:name/history` reads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryLimits {
+    /// Most rows returned; the rest are dropped and the result marked truncated.
+    pub max_rows: usize,
+    /// Longest a query may run, further capped by the request's deadline.
+    pub timeout: Duration,
+    /// Largest `limit` a history read may ask for.
+    pub max_history_samples: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self { max_rows: 10_000, timeout: Duration::from_secs(5), max_history_samples: 1000 }
+    }
+}
+
+impl QueryLimits {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `QUERY_MAX_ROWS`, `QUERY_TIMEOUT_MS` and `HISTORY_MAX_LIMIT`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let get = |key: &str| lookup(key).and_then(|v| v.parse::<u64>().ok()).filter(|&v| v > 0);
+        Self {
+            max_rows: get("QUERY_MAX_ROWS").map_or(defaults.max_rows, |rows| rows as usize),
+            timeout: units::duration(&lookup, "QUERY_TIMEOUT_MS")
+                .filter(|timeout| !timeout.is_zero())
+                .unwrap_or(defaults.timeout),
+            max_history_samples: get("HISTORY_MAX_LIMIT").map_or(defaults.max_history_samples, |limit| limit as usize),
+        }
+    }
+}
+
+/// The control node's cache of recent `GET /metrics/:name` answers; see
+/// [`crate::api::read_cache::ReadCache`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadCacheConfig {
+    /// Most metrics cached; 0 turns the cache off.
+    pub capacity: usize,
+    /// How long a cached answer is served.
+    pub ttl: Duration,
+}
+
+impl Default for ReadCacheConfig {
+    fn default() -> Self {
+        Self { capacity: 0, ttl: Duration::from_secs(1) }
+    }
+}
+
+impl ReadCacheConfig {
+    /// Reads `READ_CACHE_SIZE` and `READ_CACHE_TTL_MS`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            capacity: lookup("READ_CACHE_SIZE").and_then(|v| v.parse().ok()).unwrap_or(defaults.capacity),
+            ttl: units::duration(&lookup, "READ_CACHE_TTL_MS").unwrap_or(defaults.ttl),
+        }
+    }
+}
+
+/// Which reads the control node fetches into its read cache before it
+/// reports ready, and on `POST /admin/cache/prime`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachePrimeConfig {
+    /// Metrics always primed, ahead of the most read ones.
+    pub metrics: Vec<String>,
+    /// How many of the most read metrics are primed besides `metrics`.
+    pub top: usize,
+    /// Most reads sent to workers at once while priming.
+    pub concurrency: usize,
+    /// Longest startup waits for priming before reporting ready anyway.
+    pub timeout: Duration,
+}
+
+impl Default for CachePrimeConfig {
+    fn default() -> Self {
+        Self { metrics: Vec::new(), top: 100, concurrency: 8, timeout: Duration::from_secs(10) }
+    }
+}
+
+impl CachePrimeConfig {
+    /// Reads `CACHE_PRIME_METRICS` (comma-separated names),
+    /// `CACHE_PRIME_TOP`, `CACHE_PRIME_CONCURRENCY` and
+    /// `CACHE_PRIME_TIMEOUT_MS`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let metrics = lookup("CACHE_PRIME_METRICS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self {
+            metrics,
+            top: lookup("CACHE_PRIME_TOP").and_then(|v| v.trim().parse().ok()).unwrap_or(defaults.top),
+            concurrency: lookup("CACHE_PRIME_CONCURRENCY")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&concurrency| concurrency > 0)
+                .unwrap_or(defaults.concurrency),
+            timeout: units::duration(&lookup, "CACHE_PRIME_TIMEOUT_MS").unwrap_or(defaults.timeout),
+        }
+    }
+}
+
+/// How the control node audits routed reads against the workers holding
+/// each metric, on `POST /admin/audit/run` and on a schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditConfig {
+    /// How often an audit runs on its own; zero leaves audits to the
+    /// admin route.
+    pub interval: Duration,
+    /// Metrics sampled by a run that does not ask for a number.
+    pub sample: usize,
+    /// Most metrics audited per second, each read through the control
+    /// node and from every copy.
+    pub metrics_per_sec: u32,
+    /// Shortest time between the start of one run and the next.
+    pub min_gap: Duration,
+    /// Where reports are kept so they outlive a restart.
+    pub reports_file: PathBuf,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::ZERO,
+            sample: 100,
+            metrics_per_sec: 20,
+            min_gap: Duration::from_secs(60),
+            reports_file: PathBuf::from(DEFAULT_AUDIT_REPORTS_FILE),
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Reads `AUDIT_INTERVAL_SECS`, `AUDIT_SAMPLE`, `AUDIT_METRICS_PER_SEC`,
+    /// `AUDIT_MIN_GAP_SECS` and `AUDIT_REPORTS_FILE`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            interval: units::duration(&lookup, "AUDIT_INTERVAL_SECS").unwrap_or(defaults.interval),
+            sample: lookup("AUDIT_SAMPLE")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&sample| sample > 0)
+                .unwrap_or(defaults.sample),
+            metrics_per_sec: lookup("AUDIT_METRICS_PER_SEC")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&rate| rate > 0)
+                .unwrap_or(defaults.metrics_per_sec),
+            min_gap: units::duration(&lookup, "AUDIT_MIN_GAP_SECS").unwrap_or(defaults.min_gap),
+            reports_file: lookup("AUDIT_REPORTS_FILE")
+                .filter(|path| !path.is_empty())
+                .map_or(defaults.reports_file, PathBuf::from),
+        }
+    }
+}
+
+/// Copies of write traffic sent to a worker under test; see
+/// [`crate::api::shadow::ShadowTraffic`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowConfig {
+    /// Shadow for every partition without its own entry in `partition_urls`.
+    pub url: Option<String>,
+    /// Shadows for single partitions, by partition index.
+    pub partition_urls: BTreeMap<usize, String>,
+    /// Share of writes duplicated, from 0 to 100.
+    pub percent: f64,
+    /// Whether reads are repeated against the shadow to compare values.
+    pub compare_reads: bool,
+    /// Most shadow requests in flight; copies beyond it are dropped.
+    pub max_in_flight: usize,
+    /// Timeout for each shadow request.
+    pub timeout: Duration,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            partition_urls: BTreeMap::new(),
+            percent: 100.0,
+            compare_reads: false,
+            max_in_flight: 32,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ShadowConfig {
+    /// Reads `SHADOW_URL`, `SHADOW_URLS` (`partition=url,...`),
+    /// `SHADOW_PERCENT`, `SHADOW_COMPARE_READS`, `SHADOW_MAX_IN_FLIGHT` and
+    /// `SHADOW_TIMEOUT_MS`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let partition_urls = lookup("SHADOW_URLS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let (partition, url) = entry.split_once('=')?;
+                Some((partition.trim().parse().ok()?, url.trim().trim_end_matches('/').to_string()))
+            })
+            .collect();
+        Self {
+            url: lookup("SHADOW_URL").filter(|url| !url.is_empty()).map(|url| url.trim_end_matches('/').to_string()),
+            partition_urls,
+            percent: lookup("SHADOW_PERCENT").and_then(|v| v.parse().ok()).unwrap_or(defaults.percent),
+            compare_reads: lookup("SHADOW_COMPARE_READS").is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+            max_in_flight: lookup("SHADOW_MAX_IN_FLIGHT").and_then(|v| v.parse().ok()).unwrap_or(defaults.max_in_flight),
+            timeout: units::duration(&lookup, "SHADOW_TIMEOUT_MS").unwrap_or(defaults.timeout),
+        }
+    }
+
+    /// The shadow for `partition`, if any.
+    pub fn target(&self, partition: usize) -> Option<&str> {
+        self.partition_urls.get(&partition).or(self.url.as_ref()).map(String::as_str)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some() || !self.partition_urls.is_empty()
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for url in self.url.iter().chain(self.partition_urls.values()) {
+            if reqwest::Url::parse(url).map_or(true, |url| url.host_str().is_none()) {
+                errors.push(format!("shadow URL '{}' is not a valid URL", url));
+            }
+        }
+        if !(0.0..=100.0).contains(&self.percent) {
+            errors.push("SHADOW_PERCENT must be between 0 and 100".to_string());
+        }
+        if self.is_enabled() && self.max_in_flight == 0 {
+            errors.push("SHADOW_MAX_IN_FLIGHT must be positive".to_string());
+        }
+        if self.is_enabled() && self.timeout.is_zero() {
+            errors.push("SHADOW_TIMEOUT_MS must be positive".to_string());
+        }
+        errors
+    }
+}
+
+/// Where a worker announces itself, read by workers from `CONTROL_URL`,
+/// `ADVERTISE_URL`, `WORKER_CAPACITY`, `HEARTBEAT_INTERVAL_SECS` and
+/// `ADMIN_TOKEN`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistrationConfig {
+    pub control_url: String,
+    /// This worker's base URL as the control node should reach it.
+    pub advertise_url: String,
+    pub capacity: u32,
+    /// How often the worker sends `POST /cluster/heartbeat`.
+    pub heartbeat_interval: Duration,
+    pub admin_token: Option<String>,
+}
+
+impl RegistrationConfig {
+    pub fn from_env() -> Option<Self> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// `None` unless both `CONTROL_URL` and `ADVERTISE_URL` are set.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let url = |key| lookup(key).filter(|url: &String| !url.is_empty()).map(|url| url.trim_end_matches('/').to_string());
+        Some(Self {
+            control_url: url("CONTROL_URL")?,
+            advertise_url: url("ADVERTISE_URL")?,
+            capacity: lookup("WORKER_CAPACITY").and_then(|v| v.parse().ok()).unwrap_or(1),
+            heartbeat_interval: units::duration(&lookup, "HEARTBEAT_INTERVAL_SECS")
+                .filter(|interval| !interval.is_zero())
+                .unwrap_or(Duration::from_secs(DEFAULT_HEARTBEAT_INTERVAL_SECS)),
+            admin_token: lookup("ADMIN_TOKEN").filter(|token| !token.is_empty()),
+        })
+    }
+}
+
+/// Startup self-check settings shared by control and worker nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreflightConfig {
+    /// Whether address checks also open a TCP connection to each peer.
+    pub connect: bool,
+    /// How long such a connection may take before the peer is reported.
+    pub connect_timeout: Duration,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self { connect: false, connect_timeout: Duration::from_secs(1) }
+    }
+}
+
+impl PreflightConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `PREFLIGHT_CONNECT` (`1` or `true`) and
+    /// `PREFLIGHT_CONNECT_TIMEOUT_MS`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            connect: lookup("PREFLIGHT_CONNECT").is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+            connect_timeout: units::duration(&lookup, "PREFLIGHT_CONNECT_TIMEOUT_MS")
+                .filter(|timeout| !timeout.is_zero())
+                .unwrap_or(defaults.connect_timeout),
+        }
+    }
+}
+
+/// Upper bound on a single raft message; proposal batches must fit inside it.
+pub const RAFT_MAX_SIZE_PER_MSG: u64 = 1024 * 1024;
+
+/// Raft proposal batching settings for a worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaftConfig {
+    /// Most operations packed into one log entry.
+    pub max_batch_size: usize,
+    /// Most encoded bytes packed into one log entry.
+    pub max_batch_bytes: usize,
+    /// How long to wait for more proposals after the first one arrives.
+    pub batch_window: Duration,
+    /// Encoding for new log entries; entries in any codec are readable.
+    pub codec: RaftCodec,
+    /// Average time between raft ticks.
+    pub tick_interval: Duration,
+    /// Most each tick is moved either way at random, so nodes started
+    /// together drift out of lockstep. At most half of `tick_interval`.
+    pub tick_jitter: Duration,
+    /// Proposals that can wait for the raft loop before writers block.
+    pub proposal_channel_cap: usize,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 256,
+            max_batch_bytes: (RAFT_MAX_SIZE_PER_MSG / 2) as usize,
+            batch_window: Duration::from_millis(5),
+            codec: RaftCodec::default(),
+            tick_interval: Duration::from_millis(100),
+            tick_jitter: Duration::from_millis(10),
+            proposal_channel_cap: 100,
+        }
+    }
+}
+
+impl RaftConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `RAFT_MAX_BATCH_SIZE`, `RAFT_MAX_BATCH_BYTES`,
+    /// `RAFT_BATCH_WINDOW_MS`, `RAFT_CODEC` (`protobuf`, `bincode` or `json`), `RAFT_TICK_MS`, `RAFT_TICK_JITTER_MS` and `PROPOSAL_CHANNEL_CAP`. Batch size is capped at [`MAX_OPS_PER_ENTRY`]
+    /// and batch bytes below the raft message size limit.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let get = |key: &str| lookup(key).and_then(|v| v.parse::<u64>().ok());
+        let tick_interval =
+            units::duration(&lookup, "RAFT_TICK_MS").filter(|tick| !tick.is_zero()).unwrap_or(defaults.tick_interval);
+
+        Self {
+            max_batch_size: get("RAFT_MAX_BATCH_SIZE")
+                .map_or(defaults.max_batch_size, |v| (v.max(1) as usize).min(MAX_OPS_PER_ENTRY)),
+            max_batch_bytes: units::size(&lookup, "RAFT_MAX_BATCH_BYTES")
+                .map_or(defaults.max_batch_bytes, |v| v.min(RAFT_MAX_SIZE_PER_MSG / 2) as usize),
+            batch_window: units::duration(&lookup, "RAFT_BATCH_WINDOW_MS").unwrap_or(defaults.batch_window),
+            codec: lookup("RAFT_CODEC").map_or(defaults.codec, |name| {
+                RaftCodec::from_name(&name).unwrap_or_else(|| {
+                    warn!("Unknown RAFT_CODEC '{}', using {:?}", name, defaults.codec);
+                    defaults.codec
+                })
+            }),
+            tick_interval,
+            tick_jitter: units::duration(&lookup, "RAFT_TICK_JITTER_MS")
+                .unwrap_or(defaults.tick_jitter)
+                .min(tick_interval / 2),
+            proposal_channel_cap: get("PROPOSAL_CHANNEL_CAP").map_or(defaults.proposal_channel_cap, |v| v.max(1) as usize),
+        }
+    }
+
+    /// One tick's interval: `tick_interval` moved by up to `tick_jitter`,
+    /// earlier as `unit` nears 0 and later as it nears 1.
+    pub fn jittered_tick(&self, unit: f64) -> Duration {
+        let offset = self.tick_jitter.as_nanos() as f64 * (2.0 * unit.clamp(0.0, 1.0) - 1.0);
+        Duration::from_nanos((self.tick_interval.as_nanos() as f64 + offset).round() as u64)
+    }
+}
+
+/// A worker's raft group when its members run as separate processes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaftGroupConfig {
+    /// This worker's raft id.
+    pub id: u64,
+    /// Voting members by raft id, with the URL their admin routes answer on.
+    pub peers: BTreeMap<u64, String>,
+    /// Members that follow the log without voting, such as standbys.
+    pub learners: BTreeMap<u64, String>,
+}
+
+impl RaftGroupConfig {
+    pub fn from_env(worker_id: usize) -> Result<Option<Self>> {
+        Self::from_lookup(worker_id, |key| std::env::var(key).ok())
+    }
+
+    /// Reads `RAFT_PEERS` and `RAFT_LEARNERS` (`id=url,...`) and `RAFT_ID`,
+    /// which defaults to `worker_id + 1`. Without `RAFT_PEERS` the worker is
+    /// its group's only voter. This worker must be listed in one of them.
+    pub fn from_lookup(worker_id: usize, lookup: impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        if lookup("RAFT_PEERS").is_none() {
+            return Ok(None);
+        }
+        let members = |key: &str| -> Result<BTreeMap<u64, String>> {
+            let mut members = BTreeMap::new();
+            for entry in lookup(key).unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+                let parsed = entry.split_once('=').and_then(|(id, url)| Some((id.trim().parse::<u64>().ok().filter(|&id| id > 0)?, url.trim())));
+                let Some((id, url)) = parsed else {
+                    return Err(RaftMetricsError::InvalidConfig(vec![format!("{} entry '{}' must be id=url with an id above 0", key, entry)]));
+                };
+                members.insert(id, normalize_worker_url(url));
+            }
+            Ok(members)
+        };
+        let peers = members("RAFT_PEERS")?;
+        let learners = members("RAFT_LEARNERS")?;
+        let id = match lookup("RAFT_ID") {
+            Some(id) => id.trim().parse().map_err(|_| RaftMetricsError::InvalidConfig(vec![format!("RAFT_ID '{}' is not a raft id", id)]))?,
+            None => worker_id as u64 + 1,
+        };
+        if peers.is_empty() || !(peers.contains_key(&id) || learners.contains_key(&id)) {
+            return Err(RaftMetricsError::InvalidConfig(vec![format!("raft id {} is not among RAFT_PEERS or RAFT_LEARNERS", id)]));
+        }
+        Ok(Some(Self { id, peers, learners }))
+    }
+
+    /// The other members, by raft id, with their URLs.
+    pub fn remotes(&self) -> impl Iterator<Item = (u64, &str)> + '_ {
+        self.peers.iter().chain(&self.learners).filter(|(&id, _)| id != self.id).map(|(&id, url)| (id, url.as_str()))
+    }
+}
+
+/// Limits past which a worker's raft loop is reported as lagging in `/health`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaftHealthConfig {
+    /// Committed but unapplied entries tolerated before reporting degraded.
+    pub max_apply_lag_entries: u64,
+    /// How long applying may trail the commit index before reporting
+    /// degraded. Kept below `max_tick_gap`: a stalled apply also holds up
+    /// the loop's ticks, so a longer limit would report unhealthy first.
+    pub max_apply_lag: Duration,
+    /// How long the loop may go without ticking before reporting unhealthy.
+    pub max_tick_gap: Duration,
+}
+
+impl Default for RaftHealthConfig {
+    fn default() -> Self {
+        Self {
+            max_apply_lag_entries: 1000,
+            max_apply_lag: Duration::from_secs(2),
+            max_tick_gap: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RaftHealthConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `RAFT_MAX_APPLY_LAG_ENTRIES`, `RAFT_MAX_APPLY_LAG_MS` and
+    /// `RAFT_MAX_TICK_GAP_MS`.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_apply_lag_entries: lookup("RAFT_MAX_APPLY_LAG_ENTRIES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_apply_lag_entries),
+            max_apply_lag: units::duration(&lookup, "RAFT_MAX_APPLY_LAG_MS").unwrap_or(defaults.max_apply_lag),
+            max_tick_gap: units::duration(&lookup, "RAFT_MAX_TICK_GAP_MS").unwrap_or(defaults.max_tick_gap),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = ControlConfig::from_lookup(lookup(&[]));
+        assert_eq!(config.worker_urls, vec!["http://localhost:8081"]);
+        assert_eq!(config.worker_weights, None);
+        assert_eq!(config.read_pool, HttpPoolConfig::read_defaults());
+        assert_eq!(config.write_pool, HttpPoolConfig::write_defaults());
+        assert_eq!(config.deadline, DeadlineConfig::default());
+        assert_eq!(config.admin_token, None);
+        assert_eq!(config.slo, SloConfig::default());
+        assert_eq!(config.audit, AuditConfig::default());
+    }
+
+    #[test]
+    fn test_pools_configured_independently() {
+        let config = ControlConfig::from_lookup(lookup(&[
+            ("WORKER_HOSTS", "worker-1:8081,http://worker-2:8082"),
+            ("READ_POOL_MAX_IDLE_PER_HOST", "64"),
+            ("READ_REQUEST_TIMEOUT_MS", "250"),
+            ("WRITE_CONNECT_TIMEOUT_MS", "3000"),
+        ]));
+
+        assert_eq!(config.worker_urls, vec!["http://worker-1:8081", "http://worker-2:8082"]);
+        assert_eq!(config.read_pool.pool_max_idle_per_host, 64);
+        assert_eq!(config.read_pool.request_timeout, Duration::from_millis(250));
+        assert_eq!(config.read_pool.connect_timeout, HttpPoolConfig::read_defaults().connect_timeout);
+        assert_eq!(config.write_pool.connect_timeout, Duration::from_secs(3));
+        assert_eq!(config.write_pool.pool_max_idle_per_host, HttpPoolConfig::write_defaults().pool_max_idle_per_host);
+        assert!(config.read_pool.build_client().is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        assert_eq!(ControlConfig::from_lookup(lookup(&[])).validate(), Ok(()));
+
+        let config = ControlConfig::from_lookup(lookup(&[
+            ("WORKER_HOSTS", "worker-1:8081,worker-1:8081,:80"),
+            ("READ_REQUEST_TIMEOUT_MS", "0"),
+        ]));
+        assert_eq!(config.validate().unwrap_err(), vec![
+            "WORKER_HOSTS lists 'http://worker-1:8081' more than once".to_string(),
+            "WORKER_HOSTS entry 'http://:80' is not a valid URL".to_string(),
+            "READ_REQUEST_TIMEOUT_MS must be positive".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_startup_validation_collects_every_problem() {
+        assert_eq!(validate_startup_lookup(lookup(&[])), Ok(()));
+        assert_eq!(
+            validate_startup_lookup(lookup(&[("NODE_TYPE", "worker"), ("NODE_ID", "3"), ("PORT", "8081"), ("WORKER_HOSTS", "")])),
+            Ok(())
+        );
+
+        let errors = validate_startup_lookup(lookup(&[
+            ("NODE_TYPE", "primary"),
+            ("NODE_ID", "one"),
+            ("WORKER_ID", "-1"),
+            ("PORT", "70000"),
+        ]));
+        assert_eq!(errors.unwrap_err(), vec![
+            "NODE_TYPE 'primary' must be one of control, worker, standalone".to_string(),
+            "NODE_ID 'one' is not a non-negative integer".to_string(),
+            "WORKER_ID '-1' is not a non-negative integer".to_string(),
+            "PORT '70000' is not a port between 1 and 65535".to_string(),
+        ]);
+
+        let errors = validate_startup_lookup(lookup(&[("PORT", "0"), ("WORKER_HOSTS", " , "), ("OTEL_TRACES_SAMPLER_ARG", "1.5")]));
+        assert_eq!(errors.unwrap_err(), vec![
+            "PORT '0' is not a port between 1 and 65535".to_string(),
+            "WORKER_HOSTS must list at least one worker".to_string(),
+            "OTEL_TRACES_SAMPLER_ARG '1.5' is not a ratio between 0 and 1".to_string(),
+        ]);
+
+        let errors = validate_startup_lookup(lookup(&[("RAFT_TICK_MS", "100ms"), ("CAPTURE_MAX_SECS", "10 minutes")]));
+        assert_eq!(errors.unwrap_err(), vec![format!("CAPTURE_MAX_SECS: expected {}; got '10 minutes'", units::DURATION_FORMATS)]);
+
+        let stalls = [("RAFT_MAX_APPLY_LAG_MS", "10s"), ("RAFT_MAX_TICK_GAP_MS", "5s")];
+        assert_eq!(validate_startup_lookup(lookup(&stalls)), Ok(()));
+        let errors = validate_startup_lookup(lookup(&[("NODE_TYPE", "worker"), stalls[0], stalls[1]]));
+        assert_eq!(errors.unwrap_err(), vec!["RAFT_MAX_APPLY_LAG_MS must be below RAFT_MAX_TICK_GAP_MS".to_string()]);
+
+        let errors = validate_startup_lookup(lookup(&[("TENANT_QUOTAS", "acme=series:1;beta=rate:fast")]));
+        assert_eq!(errors.unwrap_err(), vec!["TENANT_QUOTAS for 'beta': rate 'fast' is not a positive number".to_string()]);
+    }
+
+    #[test]
+    fn test_durations_and_sizes_accept_units() {
+        let config = ControlConfig::from_lookup(lookup(&[
+            ("READ_REQUEST_TIMEOUT_MS", "2s"),
+            ("WRITE_TCP_KEEPALIVE_SECS", "2m"),
+            ("HEARTBEAT_TIMEOUT_SECS", "30"),
+            ("CAPTURE_MAX_SECS", "1h"),
+        ]));
+        assert_eq!(config.read_pool.request_timeout, Duration::from_secs(2));
+        assert_eq!(config.write_pool.tcp_keepalive, Duration::from_secs(120));
+        assert_eq!(config.heartbeat_timeout, Duration::from_secs(30));
+        assert_eq!(config.capture_max_duration, Duration::from_secs(3600));
+
+        let raft = RaftConfig::from_lookup(lookup(&[("RAFT_MAX_BATCH_BYTES", "64KiB"), ("RAFT_BATCH_WINDOW_MS", "1s")]));
+        assert_eq!(raft.max_batch_bytes, 64 * 1024);
+        assert_eq!(raft.batch_window, Duration::from_secs(1));
+
+        let path = std::env::temp_dir().join(format!("raftmetrics-units-{}.env", std::process::id()));
+        std::fs::write(&path, "WORKER_HOSTS=worker-1:8081\nREAD_CACHE_TTL_MS=-5\n").unwrap();
+        let loaded = ControlConfig::load(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(RaftMetricsError::InvalidConfig(errors)) if errors[0].starts_with("READ_CACHE_TTL_MS: expected")));
+    }
+
+    #[test]
+    fn test_slo_definitions_parsed() {
+        let config = SloConfig::from_lookup(&lookup(&[
+            ("SLOS", "reads=GET /metrics/*,p99,100ms,5m; bad=/x,99,1s,1m;writes=POST /metrics,p99.9,1s,1h"),
+            ("SLO_DEGRADED_BURN_RATE", "5"),
+        ]));
+        assert_eq!(config.slos, vec![
+            SloDefinition {
+                name: "reads".to_string(),
+                endpoint: "GET /metrics/*".to_string(),
+                percentile: 99.0,
+                threshold: Duration::from_millis(100),
+                window: Duration::from_secs(300),
+            },
+            SloDefinition {
+                name: "writes".to_string(),
+                endpoint: "POST /metrics".to_string(),
+                percentile: 99.9,
+                threshold: Duration::from_secs(1),
+                window: Duration::from_secs(3600),
+            },
+        ]);
+        assert_eq!(config.degraded_burn_rate, 5.0);
+    }
+
+    #[test]
+    fn test_memory_budget_caps_hot_set() {
+        assert_eq!(MemoryBudget::from_lookup(lookup(&[])), MemoryBudget::default());
+        let budget = MemoryBudget::from_lookup(lookup(&[
+            ("MEMORY_MAX_ENTRIES", "10"),
+            ("MEMORY_HOT_ENTRIES", "64"),
+            ("MAX_HISTORY_PER_METRIC", "0"),
+            ("MEMORY_WARM_ENTRIES", "50"),
+        ]));
+        assert_eq!(budget, MemoryBudget { max_entries: Some(10), hot_entries: 5, max_history: 0, warm_entries: Some(50) });
+        assert_eq!(budget.warm_limit(), Some(10));
+        assert_eq!(MemoryBudget::default().warm_limit(), None);
+    }
+
+    #[test]
+    fn test_raft_group_lists_the_other_members() {
+        assert_eq!(RaftGroupConfig::from_lookup(0, lookup(&[])).unwrap(), None);
+
+        let members = [("RAFT_PEERS", "1=worker-1:8081"), ("RAFT_LEARNERS", "2=http://standby-1:8081")];
+        let group = RaftGroupConfig::from_lookup(1, lookup(&members)).unwrap().unwrap();
+        assert_eq!(group.id, 2);
+        assert_eq!(group.remotes().collect::<Vec<_>>(), [(1, "http://worker-1:8081")]);
+
+        let stranger = RaftGroupConfig::from_lookup(0, lookup(&[members[0], members[1], ("RAFT_ID", "3")]));
+        assert!(matches!(stranger, Err(RaftMetricsError::InvalidConfig(errors)) if errors[0].contains("raft id 3")));
+        assert!(RaftGroupConfig::from_lookup(0, lookup(&[("RAFT_PEERS", "one=worker-1:8081")])).is_err());
+    }
+
+    #[test]
+    fn test_worker_urls_keep_their_scheme() {
+        assert_eq!(normalize_worker_url("worker-1:8081"), "http://worker-1:8081");
+        assert_eq!(normalize_worker_url("http://worker-1:8081"), "http://worker-1:8081");
+        assert_eq!(normalize_worker_url("https://worker-1:8443"), "https://worker-1:8443");
+    }
+
+    #[test]
+    fn test_raft_batch_bytes_capped_below_message_limit() {
+        let config = RaftConfig::from_lookup(lookup(&[
+            ("RAFT_MAX_BATCH_SIZE", "32"),
+            ("RAFT_MAX_BATCH_BYTES", "999999999"),
+            ("RAFT_BATCH_WINDOW_MS", "2"),
+            ("RAFT_CODEC", "bincode"),
+            ("PROPOSAL_CHANNEL_CAP", "0"),
+        ]));
+        assert_eq!(config.max_batch_size, 32);
+        assert!(config.max_batch_bytes as u64 <= RAFT_MAX_SIZE_PER_MSG);
+        assert_eq!(config.batch_window, Duration::from_millis(2));
+        assert_eq!(config.codec, RaftCodec::Bincode);
+        assert_eq!(config.proposal_channel_cap, 1);
+    }
+}
@@ -0,0 +1,220 @@
+//! Durations and sizes in config values: `500ms`, `30s`, `15m`, `7d`,
+//! `64KiB` or `2MB`, with units in any case. A bare number is read in the
+//! unit the key's name ends in (`_MS`, `_SECS` or `_BYTES`), as every value
+//! was before units were accepted, so existing settings keep their meaning.
+
+use std::time::Duration;
+use tracing::warn;
+
+pub const DURATION_FORMATS: &str = "a whole number with an optional unit of ms, s, m, h or d, e.g. 500ms or 15m";
+pub const SIZE_FORMATS: &str = "a whole number with an optional unit of B, KB, MB, GB, KiB, MiB or GiB, e.g. 64KiB or 2MB";
+
+/// Every key holding a duration or size, checked at startup by [`errors`].
+pub const UNIT_KEYS: &[&str] = &[
+    "READ_POOL_IDLE_TIMEOUT_MS",
+    "READ_CONNECT_TIMEOUT_MS",
+    "READ_REQUEST_TIMEOUT_MS",
+    "READ_TCP_KEEPALIVE_SECS",
+    "WRITE_POOL_IDLE_TIMEOUT_MS",
+    "WRITE_CONNECT_TIMEOUT_MS",
+    "WRITE_REQUEST_TIMEOUT_MS",
+    "WRITE_TCP_KEEPALIVE_SECS",
+    "CAPTURE_MAX_SECS",
+    "REGISTRATION_TTL_SECS",
+    "HEARTBEAT_TIMEOUT_SECS",
+    "HEARTBEAT_INTERVAL_SECS",
+    "LOAD_POLL_INTERVAL_SECS",
+    "DEFAULT_DEADLINE_MS",
+    "DEADLINE_SAFETY_MARGIN_MS",
+    "QUERY_TIMEOUT_MS",
+    "READ_CACHE_TTL_MS",
+    "CACHE_PRIME_TIMEOUT_MS",
+    "AUDIT_INTERVAL_SECS",
+    "AUDIT_MIN_GAP_SECS",
+    "SHADOW_TIMEOUT_MS",
+    "PREFLIGHT_CONNECT_TIMEOUT_MS",
+    "RAFT_MAX_BATCH_BYTES",
+    "RAFT_BATCH_WINDOW_MS",
+    "RAFT_TICK_MS",
+    "RAFT_TICK_JITTER_MS",
+    "RAFT_MAX_APPLY_LAG_MS",
+    "RAFT_MAX_TICK_GAP_MS",
+    "AGGREGATE_FLUSH_INTERVAL_SECS",
+    "TOMBSTONE_GRACE_SECS",
+    "IDEMPOTENCY_TTL_SECS",
+    "MIN_VERSION_WAIT_MS",
+    "MAX_FUTURE_SKEW_SECS",
+    "DEDUP_INTERVAL_SECS",
+    "COMPACTION_MIN_AGE_SECS",
+    "COMPACTION_INTERVAL_SECS",
+    "METRIC_TTL_SECS",
+    "PUSHGATEWAY_INTERVAL_SECS",
+    "PUSHGATEWAY_MAX_BACKOFF_SECS",
+    "FORWARD_TIMEOUT_MS",
+];
+
+/// Splits `value` into its number and lowercased unit.
+fn split(value: &str) -> Option<(u64, String)> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let number = value[..digits].parse().ok()?;
+    Some((number, value[digits..].trim().to_ascii_lowercase()))
+}
+
+/// Parses a duration, reading a bare number as that many `bare` units.
+pub fn parse_duration(value: &str, bare: Duration) -> Result<Duration, String> {
+    let invalid = || format!("expected {}; got '{}'", DURATION_FORMATS, value);
+    let (number, unit) = split(value).ok_or_else(invalid)?;
+    let unit = match unit.as_str() {
+        "" => bare,
+        "ms" => Duration::from_millis(1),
+        "s" => Duration::from_secs(1),
+        "m" => Duration::from_secs(60),
+        "h" => Duration::from_secs(60 * 60),
+        "d" => Duration::from_secs(24 * 60 * 60),
+        _ => return Err(invalid()),
+    };
+    u32::try_from(number).ok().and_then(|number| unit.checked_mul(number)).ok_or_else(invalid)
+}
+
+/// Parses a size in bytes, reading a bare number as that many `bare` bytes.
+pub fn parse_size(value: &str, bare: u64) -> Result<u64, String> {
+    let invalid = || format!("expected {}; got '{}'", SIZE_FORMATS, value);
+    let (number, unit) = split(value).ok_or_else(invalid)?;
+    let unit = match unit.as_str() {
+        "" => bare,
+        "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(unit).ok_or_else(invalid)
+}
+
+/// What a bare number in `key` counts, by the suffix of its name.
+enum Bare {
+    Duration(Duration),
+    Size(u64),
+}
+
+fn bare(key: &str) -> Option<Bare> {
+    if key.ends_with("_MS") {
+        Some(Bare::Duration(Duration::from_millis(1)))
+    } else if key.ends_with("_SECS") {
+        Some(Bare::Duration(Duration::from_secs(1)))
+    } else if key.ends_with("_BYTES") {
+        Some(Bare::Size(1))
+    } else {
+        None
+    }
+}
+
+/// The duration set for `key`, if any. An unparsable value is ignored with
+/// a warning here; [`errors`] refuses it at startup.
+pub fn duration(lookup: &impl Fn(&str) -> Option<String>, key: &str) -> Option<Duration> {
+    let Some(Bare::Duration(bare)) = bare(key) else {
+        panic!("{} does not name a duration", key);
+    };
+    let value = lookup(key)?;
+    parse_duration(&value, bare).map_err(|e| warn!("Ignoring {}: {}", key, e)).ok()
+}
+
+/// The size in bytes set for `key`, if any, as [`duration`] reads durations.
+pub fn size(lookup: &impl Fn(&str) -> Option<String>, key: &str) -> Option<u64> {
+    let Some(Bare::Size(bare)) = bare(key) else {
+        panic!("{} does not name a size", key);
+    };
+    let value = lookup(key)?;
+    parse_size(&value, bare).map_err(|e| warn!("Ignoring {}: {}", key, e)).ok()
+}
+
+/// One line per key in [`UNIT_KEYS`] whose value does not parse, naming the
+/// key and the formats it accepts.
+pub fn errors(lookup: &impl Fn(&str) -> Option<String>) -> Vec<String> {
+    UNIT_KEYS
+        .iter()
+        .filter_map(|&key| {
+            let value = lookup(key)?;
+            let parsed = match bare(key)? {
+                Bare::Duration(bare) => parse_duration(&value, bare).map(|_| ()),
+                Bare::Size(bare) => parse_size(&value, bare).map(|_| ()),
+            };
+            parsed.err().map(|e| format!("{}: {}", key, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_durations_in_every_unit() {
+        for (value, expected) in [
+            ("500ms", Duration::from_millis(500)),
+            ("30s", Duration::from_secs(30)),
+            ("15m", Duration::from_secs(15 * 60)),
+            ("2h", Duration::from_secs(2 * 60 * 60)),
+            ("7d", Duration::from_secs(7 * 24 * 60 * 60)),
+            (" 30 s ", Duration::from_secs(30)),
+            ("30S", Duration::from_secs(30)),
+            ("500Ms", Duration::from_millis(500)),
+            ("250", Duration::from_millis(250)),
+            ("0", Duration::ZERO),
+        ] {
+            assert_eq!(parse_duration(value, MS), Ok(expected), "{}", value);
+        }
+        assert_eq!(parse_duration("250", Duration::from_secs(1)), Ok(Duration::from_secs(250)));
+    }
+
+    #[test]
+    fn test_sizes_in_every_unit() {
+        for (value, expected) in [
+            ("512", 512),
+            ("512B", 512),
+            ("64KiB", 64 * 1024),
+            ("64kib", 64 * 1024),
+            ("2MB", 2_000_000),
+            ("2mb", 2_000_000),
+            ("3MiB", 3 << 20),
+            ("1GB", 1_000_000_000),
+            ("1GiB", 1 << 30),
+        ] {
+            assert_eq!(parse_size(value, 1), Ok(expected), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_negative_and_nonsense_values_are_refused() {
+        for value in ["-5", "-5s", "", "s", "5 fortnights", "1.5s", "5sec", "ms500", "99999999999d"] {
+            let error = parse_duration(value, MS).unwrap_err();
+            assert!(error.contains(DURATION_FORMATS), "{}: {}", value, error);
+        }
+        for value in ["-1", "2 MBs", "1.5MB", "KiB", "99999999999GiB"] {
+            let error = parse_size(value, 1).unwrap_err();
+            assert!(error.contains(SIZE_FORMATS), "{}: {}", value, error);
+        }
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let lookup = |key: &str| match key {
+            "READ_REQUEST_TIMEOUT_MS" => Some("2s".to_string()),
+            "QUERY_TIMEOUT_MS" => Some("soon".to_string()),
+            "RAFT_MAX_BATCH_BYTES" => Some("-1".to_string()),
+            _ => None,
+        };
+        let errors = errors(&lookup);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].starts_with("QUERY_TIMEOUT_MS: expected"), "{}", errors[0]);
+        assert!(errors[1].starts_with("RAFT_MAX_BATCH_BYTES: expected"), "{}", errors[1]);
+        assert_eq!(duration(&lookup, "READ_REQUEST_TIMEOUT_MS"), Some(Duration::from_secs(2)));
+        assert_eq!(duration(&lookup, "QUERY_TIMEOUT_MS"), None);
+    }
+}
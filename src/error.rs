@@ -3,12 +3,122 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// Where a request's time budget ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadlineStage {
+    /// Spent on the control node before the worker call could start.
+    ControlQueue,
+    /// Spent waiting on, or inside, the worker.
+    Worker,
+}
+
+impl std::fmt::Display for DeadlineStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeadlineStage::ControlQueue => write!(f, "control queue"),
+            DeadlineStage::Worker => write!(f, "worker"),
+        }
+    }
+}
+
+/// Stable, machine-readable kind of an API error, sent as `code` in every
+/// error body so clients need not match on messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    MetricNotFound,
+    ValidationFailed,
+    InvalidConfig,
+    Unauthorized,
+    /// A request with an API key the metric's ACL does not grant.
+    Forbidden,
+    Conflict,
+    VersionNotReached,
+    WorkerUnavailable,
+    /// The raft node lost leadership before the write committed.
+    RaftNoLeader,
+    /// A standby worker that has not been promoted.
+    NotServing,
+    /// A write refused while the cluster is read-only for maintenance.
+    ReadOnly,
+    /// A write that would take a tenant past its series or storage quota.
+    QuotaExceeded,
+    /// A write refused for now because its tenant is sending samples
+    /// faster than its rate quota.
+    RateLimited,
+    /// A forwarded write that has already passed through more clusters
+    /// than `FORWARD_MAX_HOPS` allows.
+    ForwardingLoop,
+    DeadlineExceeded,
+    StorageError,
+    RaftError,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::MetricNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::ValidationFailed | ErrorCode::InvalidConfig => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden | ErrorCode::QuotaExceeded => StatusCode::FORBIDDEN,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::VersionNotReached => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::WorkerUnavailable | ErrorCode::RaftNoLeader | ErrorCode::ReadOnly => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::NotServing => StatusCode::MISDIRECTED_REQUEST,
+            ErrorCode::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::ForwardingLoop => StatusCode::LOOP_DETECTED,
+            ErrorCode::StorageError | ErrorCode::RaftError | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again unchanged.
+    pub fn retriable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::VersionNotReached
+                | ErrorCode::WorkerUnavailable
+                | ErrorCode::RaftNoLeader
+                | ErrorCode::NotServing
+                | ErrorCode::DeadlineExceeded
+                | ErrorCode::RateLimited
+        )
+    }
+
+    /// The code an error body without one implies, for nodes that predate
+    /// codes.
+    pub fn for_status(status: u16) -> Self {
+        match status {
+            400 => ErrorCode::ValidationFailed,
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::QuotaExceeded,
+            404 => ErrorCode::MetricNotFound,
+            409 => ErrorCode::Conflict,
+            412 => ErrorCode::VersionNotReached,
+            421 => ErrorCode::NotServing,
+            429 => ErrorCode::RateLimited,
+            503 => ErrorCode::WorkerUnavailable,
+            504 => ErrorCode::DeadlineExceeded,
+            508 => ErrorCode::ForwardingLoop,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RaftMetricsError {
+    #[cfg(feature = "duckdb-storage")]
     #[error("Database error: {0}")]
     Database(#[from] duckdb::Error),
+
+    #[cfg(feature = "sqlite-storage")]
+    #[error("Database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
     
     #[error("Raft error: {0}")]
     Raft(#[from] raft::Error),
@@ -21,41 +131,243 @@ pub enum RaftMetricsError {
     
     #[error("Resource not found")]
     NotFound,
+
+    #[error("No data: {0}")]
+    NoData(String),
     
     #[error("Internal error: {0}")]
     Internal(String),
     
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
+
+    #[error("Schema migration error: {0}")]
+    Migration(String),
+
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    InvalidConfig(Vec<String>),
+
+    #[error("Version not reached: {0}")]
+    VersionNotReached(String),
+
+    /// The node lost leadership before the write reached its log, or a
+    /// newer leader overwrote it there; either way it will never apply,
+    /// so the caller can retry.
+    #[error("Leadership lost at term {term} before the write committed")]
+    LeadershipLost {
+        term: u64,
+        /// Raft id of the new leader, when known.
+        leader_id: Option<u64>,
+    },
+
+    #[error("Deadline exceeded in {stage} after {elapsed_ms}ms of a {budget_ms}ms budget")]
+    DeadlineExceeded {
+        stage: DeadlineStage,
+        elapsed_ms: u64,
+        budget_ms: u64,
+    },
+
+    /// A standby worker refusing data-plane requests until it is promoted.
+    #[error("Not serving: {0}")]
+    NotServing(String),
+
+    /// A write refused until read-only mode is turned off.
+    #[error("Read-only: {0}")]
+    ReadOnly(String),
+
+    /// A request refused by the metric's ACL for the key it presented.
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// A write refused for a tenant's series or storage quota.
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    /// A write refused for a tenant's rate quota; it may pass once the
+    /// tenant slows down.
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    /// A forwarded write refused for having taken too many hops.
+    #[error("Forwarding loop: {0}")]
+    ForwardingLoop(String),
+
+    /// An error another node answered with, passed on under its own code.
+    #[error("{message}")]
+    Upstream { code: ErrorCode, message: String },
+}
+
+impl RaftMetricsError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            #[cfg(feature = "duckdb-storage")]
+            RaftMetricsError::Database(_) => ErrorCode::StorageError,
+            #[cfg(feature = "sqlite-storage")]
+            RaftMetricsError::Sqlite(_) => ErrorCode::StorageError,
+            RaftMetricsError::Migration(_) => ErrorCode::StorageError,
+            RaftMetricsError::Raft(_) => ErrorCode::RaftError,
+            RaftMetricsError::Request(_) | RaftMetricsError::Protobuf(_) | RaftMetricsError::Internal(_) => {
+                ErrorCode::Internal
+            }
+            RaftMetricsError::NotFound | RaftMetricsError::NoData(_) => ErrorCode::MetricNotFound,
+            RaftMetricsError::InvalidRequest(_) => ErrorCode::ValidationFailed,
+            RaftMetricsError::InvalidConfig(_) => ErrorCode::InvalidConfig,
+            RaftMetricsError::Unavailable(_) => ErrorCode::WorkerUnavailable,
+            RaftMetricsError::Unauthorized(_) => ErrorCode::Unauthorized,
+            RaftMetricsError::Conflict(_) => ErrorCode::Conflict,
+            RaftMetricsError::VersionNotReached(_) => ErrorCode::VersionNotReached,
+            RaftMetricsError::LeadershipLost { .. } => ErrorCode::RaftNoLeader,
+            RaftMetricsError::NotServing(_) => ErrorCode::NotServing,
+            RaftMetricsError::ReadOnly(_) => ErrorCode::ReadOnly,
+            RaftMetricsError::Forbidden(_) => ErrorCode::Forbidden,
+            RaftMetricsError::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            RaftMetricsError::RateLimited(_) => ErrorCode::RateLimited,
+            RaftMetricsError::ForwardingLoop(_) => ErrorCode::ForwardingLoop,
+            RaftMetricsError::DeadlineExceeded { .. } => ErrorCode::DeadlineExceeded,
+            RaftMetricsError::Upstream { code, .. } => *code,
+        }
+    }
+
+    /// Rebuilds the error another node answered with from its status and
+    /// body, keeping the body's `code`. A body without one takes the code
+    /// its status implies. Errors whose bodies carry fields of their own,
+    /// such as the new leader or where a deadline ran out, come back as
+    /// themselves so those fields are passed on too.
+    pub fn from_response(status: u16, body: &str) -> Self {
+        let parsed = serde_json::from_str::<serde_json::Value>(body).ok();
+        let message = parsed.as_ref().and_then(|body| body["error"].as_str()).unwrap_or(body).to_string();
+        let code = parsed
+            .as_ref()
+            .and_then(|body| serde_json::from_value(body["code"].clone()).ok())
+            .unwrap_or_else(|| ErrorCode::for_status(status));
+        let field = |name: &str| parsed.as_ref().map(|body| body[name].clone()).unwrap_or_default();
+        match code {
+            ErrorCode::RaftNoLeader => {
+                if let (Some(term), Ok(leader_id)) = (field("term").as_u64(), serde_json::from_value(field("leader_id"))) {
+                    return RaftMetricsError::LeadershipLost { term, leader_id };
+                }
+            }
+            ErrorCode::DeadlineExceeded => {
+                if let (Ok(stage), Some(elapsed_ms), Some(budget_ms)) =
+                    (serde_json::from_value(field("stage")), field("elapsed_ms").as_u64(), field("budget_ms").as_u64())
+                {
+                    return RaftMetricsError::DeadlineExceeded { stage, elapsed_ms, budget_ms };
+                }
+            }
+            ErrorCode::InvalidConfig => {
+                if let Ok(errors) = serde_json::from_value(field("errors")) {
+                    return RaftMetricsError::InvalidConfig(errors);
+                }
+            }
+            _ => {}
+        }
+        RaftMetricsError::Upstream { code, message }
+    }
 }
 
 impl IntoResponse for RaftMetricsError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            RaftMetricsError::NotFound => (
-                StatusCode::NOT_FOUND,
-                self.to_string(),
-            ),
-            RaftMetricsError::InvalidRequest(_) => (
-                StatusCode::BAD_REQUEST,
-                self.to_string(),
-            ),
-            RaftMetricsError::Raft(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Raft error: {}", e),
+        let code = self.code();
+        let mut body = serde_json::json!({
+            "error": self.to_string(),
+            "code": code,
+            "retriable": code.retriable(),
+        });
+        match &self {
+            RaftMetricsError::DeadlineExceeded { stage, elapsed_ms, budget_ms } => {
+                body["stage"] = serde_json::json!(stage);
+                body["elapsed_ms"] = serde_json::json!(elapsed_ms);
+                body["budget_ms"] = serde_json::json!(budget_ms);
+            }
+            RaftMetricsError::LeadershipLost { term, leader_id } => {
+                body["term"] = serde_json::json!(term);
+                body["leader_id"] = serde_json::json!(leader_id);
+            }
+            RaftMetricsError::InvalidConfig(errors) => body["errors"] = serde_json::json!(errors),
+            _ => {}
+        }
+
+        (code.status(), Json(body)).into_response()
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RaftMetricsError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_of(error: RaftMetricsError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_every_error_body_carries_its_code() {
+        let cases = [
+            (RaftMetricsError::NotFound, StatusCode::NOT_FOUND, "METRIC_NOT_FOUND", false),
+            (RaftMetricsError::InvalidRequest("bad".into()), StatusCode::BAD_REQUEST, "VALIDATION_FAILED", false),
+            (RaftMetricsError::InvalidConfig(vec!["bad".into()]), StatusCode::BAD_REQUEST, "INVALID_CONFIG", false),
+            (RaftMetricsError::Unavailable("down".into()), StatusCode::SERVICE_UNAVAILABLE, "WORKER_UNAVAILABLE", true),
+            (
+                RaftMetricsError::LeadershipLost { term: 3, leader_id: Some(2) },
+                StatusCode::SERVICE_UNAVAILABLE,
+                "RAFT_NO_LEADER",
+                true,
             ),
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                self.to_string(),
+            (
+                RaftMetricsError::DeadlineExceeded { stage: DeadlineStage::Worker, elapsed_ms: 10, budget_ms: 10 },
+                StatusCode::GATEWAY_TIMEOUT,
+                "DEADLINE_EXCEEDED",
+                true,
             ),
-        };
+            (RaftMetricsError::NotServing("standby".into()), StatusCode::MISDIRECTED_REQUEST, "NOT_SERVING", true),
+            (RaftMetricsError::ReadOnly("maintenance".into()), StatusCode::SERVICE_UNAVAILABLE, "READ_ONLY", false),
+            (RaftMetricsError::Forbidden("acl".into()), StatusCode::FORBIDDEN, "FORBIDDEN", false),
+            (RaftMetricsError::QuotaExceeded("series".into()), StatusCode::FORBIDDEN, "QUOTA_EXCEEDED", false),
+            (RaftMetricsError::RateLimited("rate".into()), StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED", true),
+            (RaftMetricsError::ForwardingLoop("hops".into()), StatusCode::LOOP_DETECTED, "FORWARDING_LOOP", false),
+            (RaftMetricsError::Internal("oops".into()), StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL", false),
+        ];
+        for (error, status, code, retriable) in cases {
+            let (answered, body) = body_of(error).await;
+            assert_eq!(answered, status, "{}", code);
+            assert_eq!(body["code"], code);
+            assert_eq!(body["retriable"], retriable, "{}", code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upstream_error_keeps_the_code_it_was_sent() {
+        let (_, sent) = body_of(RaftMetricsError::LeadershipLost { term: 3, leader_id: None }).await;
+        let passed = RaftMetricsError::from_response(503, &sent.to_string());
+        assert_eq!(passed.code(), ErrorCode::RaftNoLeader);
+        assert_eq!(passed.to_string(), sent["error"]);
 
-        let body = Json(serde_json::json!({
-            "error": error_message
-        }));
+        let (_, sent) = body_of(RaftMetricsError::LeadershipLost { term: 4, leader_id: Some(2) }).await;
+        let passed = RaftMetricsError::from_response(503, &sent.to_string());
+        assert!(matches!(passed, RaftMetricsError::LeadershipLost { term: 4, leader_id: Some(2) }));
+        assert_eq!(body_of(passed).await.1, sent);
 
-        (status, body).into_response()
+        let deadline = RaftMetricsError::DeadlineExceeded { stage: DeadlineStage::Worker, elapsed_ms: 12, budget_ms: 10 };
+        let (_, sent) = body_of(deadline).await;
+        let passed = RaftMetricsError::from_response(504, &sent.to_string());
+        assert!(matches!(passed, RaftMetricsError::DeadlineExceeded { stage: DeadlineStage::Worker, elapsed_ms: 12, budget_ms: 10 }));
+        assert_eq!(body_of(passed).await.1, sent);
+
+        // Bodies from nodes that predate codes fall back to the status
+        assert_eq!(RaftMetricsError::from_response(404, "gone").code(), ErrorCode::MetricNotFound);
+        assert_eq!(RaftMetricsError::from_response(500, r#"{"error":"disk on fire"}"#).to_string(), "disk on fire");
     }
 }
-
-pub type Result<T> = std::result::Result<T, RaftMetricsError>;
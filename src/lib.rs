@@ -1,10 +1,16 @@
 pub mod api;
+pub mod client;
+pub mod config;
 pub mod raft;
 pub mod proto;
 pub mod error;
 pub mod metrics;
 pub mod partitioning;
 pub mod logging;
+pub mod replay;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testkit;
 
 pub use error::{Result, RaftMetricsError};
 
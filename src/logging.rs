@@ -34,6 +34,7 @@ pub fn get_metrics_logger() -> Logger {
 use tracing_subscriber::{
     fmt,
     EnvFilter,
+    Layer,
     layer::SubscriberExt,
     util::SubscriberInitExt,
 };
@@ -44,6 +45,9 @@ use tracing::Level;
 /// # Arguments
 /// * `node_id` - Unique identifier for the node (unused for now but kept for future use)
 /// * `node_type` - Type of node (control/worker)
+///
+/// With the `otel` feature, spans are also exported over OTLP when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set; see [`crate::telemetry`].
 pub fn init_logger(_node_id: u64, node_type: &str) {
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| {
@@ -60,9 +64,10 @@ pub fn init_logger(_node_id: u64, node_type: &str) {
         .with_ansi(true)
         .compact();
 
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(fmt_layer)
+    let subscriber = tracing_subscriber::registry().with(fmt_layer.with_filter(env_filter));
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(crate::telemetry::layer(&crate::telemetry::TelemetryConfig::from_env(node_type)));
+    subscriber
         .try_init()
         .expect("Failed to initialize logger");
 }
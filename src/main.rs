@@ -1,31 +1,81 @@
 use std::env;
-use tracing::info;
+use tracing::{error, info};
+use distributed_analytics_system::api::{info::{self, NodeInfo}, preflight::PreflightReport};
+
+/// Prints `report` for `--check` and exits, non-zero when a check failed.
+fn exit_with_report(report: PreflightReport) -> ! {
+    print!("{}", report.render());
+    std::process::exit(if report.passed() { 0 } else { 1 });
+}
 
 #[tokio::main]
 async fn main() {
+    info::mark_started();
+    // `replay <DB_PATH> ...` rebuilds a worker's state offline and exits
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "replay") {
+        std::process::exit(distributed_analytics_system::replay::main(&args[1..]).await);
+    }
+    if let Err(errors) = distributed_analytics_system::config::validate_startup_config() {
+        eprintln!("Invalid configuration:");
+        for error in errors {
+            eprintln!("  - {}", error);
+        }
+        std::process::exit(2);
+    }
+
     // Get node type from environment variable
     let node_type = env::var("NODE_TYPE").unwrap_or_else(|_| "control".to_string());
+    // `--check` runs the startup checks and exits without serving
+    let check_only = args.iter().any(|arg| arg == "--check");
     
-    // Try WORKER_ID first, then NODE_ID for backward compatibility
-    let worker_id: usize = env::var("NODE_ID")
-        .or_else(|_| env::var("WORKER_ID"))
-        .unwrap_or_else(|_| "1".to_string())
-        .parse()
-        .unwrap_or(1);
+    let worker_id = info::node_id_from_env();
 
     // Initialize logging
     distributed_analytics_system::logging::init_logger(worker_id as u64, &node_type);
 
     info!("Starting node with type: {}", node_type);
+    if check_only {
+        print!("{}", NodeInfo::from_env(&node_type).render());
+    }
     
     match node_type.as_str() {
         "worker" => {
+            if check_only {
+                exit_with_report(distributed_analytics_system::api::worker::preflight_worker_node().await);
+            }
             info!("Starting worker node {}", worker_id);
-            distributed_analytics_system::api::worker::start_worker_node(worker_id).await;
+            if let Err(e) = distributed_analytics_system::api::worker::start_worker_node(worker_id).await {
+                error!("Worker node {} failed to start: {}", worker_id, e);
+                std::process::exit(1);
+            }
+        }
+        "standalone" => {
+            if check_only {
+                exit_with_report(distributed_analytics_system::api::standalone::preflight_standalone_node().await);
+            }
+            info!("Starting standalone node");
+            if let Err(e) = distributed_analytics_system::api::standalone::start_standalone_node().await {
+                error!("Standalone node failed to start: {}", e);
+                std::process::exit(1);
+            }
         }
         _ => {
+            if check_only {
+                match distributed_analytics_system::api::control::preflight_control_node().await {
+                    Ok(report) => exit_with_report(report),
+                    Err(e) => {
+                        error!("Control node preflight failed: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
             info!("Starting control node");
-            distributed_analytics_system::api::control::start_control_node().await;
+            if let Err(e) = distributed_analytics_system::api::control::start_control_node().await {
+                error!("Control node failed to start: {}", e);
+                std::process::exit(1);
+            }
         }
     }
+    distributed_analytics_system::telemetry::shutdown();
 }
@@ -0,0 +1,793 @@
+use prost::Message;
+
+use crate::{proto::metrics as pb, RaftMetricsError, Result};
+use super::{dedup::DedupWindow, outliers::OutlierBounds, Admission, Labels, MetricOperation, SampleSummary};
+
+const JSON_TAG: u8 = 1;
+const BINCODE_TAG: u8 = 2;
+const PROTOBUF_TAG: u8 = 3;
+
+/// Encoding for the operation batches carried in raft log entries. Every
+/// entry starts with a byte naming its codec, so a log written under one
+/// codec stays readable after switching to another.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RaftCodec {
+    Json,
+    /// bincode 1.x's default layout: little-endian fixed-width integers,
+    /// `u64` lengths and `u32` variant indices. Labeled records use their
+    /// own variant so unlabeled ones keep the original layout, as do
+    /// records and summaries with an [`Admission`]: one carrying only a
+    /// weight has its own variant, and any other the flagged layout of
+    /// [`encode_admission`]. Keyed operations carry their proposal time in
+    /// a variant of their own; the earlier one without it still decodes.
+    Bincode,
+    /// An `OperationBatch` from `metrics.proto`.
+    #[default]
+    Protobuf,
+}
+
+impl RaftCodec {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(RaftCodec::Json),
+            "bincode" => Some(RaftCodec::Bincode),
+            "protobuf" => Some(RaftCodec::Protobuf),
+            _ => None,
+        }
+    }
+
+    /// Encodes a batch of operations as a raft log entry payload.
+    pub fn encode(self, operations: &[MetricOperation]) -> Result<Vec<u8>> {
+        match self {
+            RaftCodec::Json => {
+                let mut data = vec![JSON_TAG];
+                serde_json::to_writer(&mut data, operations)
+                    .map_err(|e| RaftMetricsError::Internal(format!("Failed to encode operations: {}", e)))?;
+                Ok(data)
+            }
+            RaftCodec::Bincode => {
+                let mut data = vec![BINCODE_TAG];
+                data.extend_from_slice(&(operations.len() as u64).to_le_bytes());
+                for operation in operations {
+                    encode_bincode(operation, &mut data);
+                }
+                Ok(data)
+            }
+            RaftCodec::Protobuf => {
+                let batch = pb::OperationBatch { operations: operations.iter().map(to_proto).collect() };
+                let mut data = Vec::with_capacity(1 + batch.encoded_len());
+                data.push(PROTOBUF_TAG);
+                batch.encode(&mut data)
+                    .map_err(|e| RaftMetricsError::Internal(format!("Failed to encode operations: {}", e)))?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Encoded size of a single operation within a batch.
+    pub fn encoded_len(self, operation: &MetricOperation) -> usize {
+        match self {
+            RaftCodec::Json => serde_json::to_vec(operation).map_or(0, |bytes| bytes.len() + 1),
+            RaftCodec::Bincode => {
+                let mut data = Vec::new();
+                encode_bincode(operation, &mut data);
+                data.len()
+            }
+            // The field key, the length prefix and the message itself
+            RaftCodec::Protobuf => {
+                let len = to_proto(operation).encoded_len();
+                1 + prost::encoding::encoded_len_varint(len as u64) + len
+            }
+        }
+    }
+}
+
+/// Decodes an entry payload with whichever codec its first byte names.
+/// Entries from before codecs were tagged are bare JSON arrays.
+pub fn decode(data: &[u8]) -> Result<Vec<MetricOperation>> {
+    let decoded = match data.first() {
+        Some(&JSON_TAG) => serde_json::from_slice(&data[1..]).map_err(|e| e.to_string()),
+        Some(&BINCODE_TAG) => decode_bincode(&data[1..]),
+        Some(&PROTOBUF_TAG) => decode_protobuf(&data[1..]),
+        Some(b'[') => serde_json::from_slice(data).map_err(|e| e.to_string()),
+        Some(tag) => Err(format!("unknown codec tag {}", tag)),
+        None => Err("empty entry".to_string()),
+    };
+    decoded.map_err(|e| RaftMetricsError::Internal(format!("Failed to decode operations: {}", e)))
+}
+
+fn to_proto(operation: &MetricOperation) -> pb::Operation {
+    let kind = match operation {
+        MetricOperation::Keyed { key, proposed_at, operation } => {
+            return pb::Operation { key: key.clone(), key_proposed_at: *proposed_at, ..to_proto(operation) }
+        }
+        MetricOperation::Record { name, value, timestamp, labels, admission } => pb::operation::Kind::Record(pb::RecordOperation {
+            name: name.clone(),
+            value: *value,
+            timestamp: *timestamp,
+            labels: labels.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            admission: admission_to_proto(admission),
+        }),
+        MetricOperation::Delete { name, deleted_at } => {
+            pb::operation::Kind::Delete(pb::DeleteOperation { name: name.clone(), deleted_at: *deleted_at })
+        }
+        MetricOperation::Clear => pb::operation::Kind::Clear(pb::ClearOperation {}),
+        MetricOperation::ApplyQuarantine { name } => {
+            pb::operation::Kind::ApplyQuarantine(pb::ApplyQuarantineOperation { name: name.clone() })
+        }
+        MetricOperation::Transaction(operations) => {
+            pb::operation::Kind::Transaction(pb::TransactionOperation { operations: operations.iter().map(to_proto).collect() })
+        }
+        MetricOperation::Reset { name, reset_at, keep_history } => pb::operation::Kind::Reset(pb::ResetOperation {
+            name: name.clone(),
+            reset_at: *reset_at,
+            keep_history: *keep_history,
+        }),
+        MetricOperation::SetTransform { name, scale, offset } => pb::operation::Kind::SetTransform(pb::SetTransformOperation {
+            name: name.clone(),
+            scale: *scale,
+            offset: *offset,
+        }),
+        MetricOperation::RecordSummary { name, timestamp, labels, summary, admission } => {
+            pb::operation::Kind::RecordSummary(pb::RecordSummaryOperation {
+                name: name.clone(),
+                timestamp: *timestamp,
+                labels: labels.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                last: summary.last,
+                count: summary.count,
+                sum: summary.sum,
+                min: summary.min,
+                max: summary.max,
+                m2: summary.m2,
+                admission: admission_to_proto(admission),
+            })
+        }
+        MetricOperation::DefineStates { name, states, stale_after_secs } => {
+            pb::operation::Kind::DefineStates(pb::DefineStatesOperation {
+                name: name.clone(),
+                states: states.clone(),
+                stale_after_secs: stale_after_secs.unwrap_or(0),
+            })
+        }
+        MetricOperation::DefineHistogram { name, bounds, reset } => {
+            pb::operation::Kind::DefineHistogram(pb::DefineHistogramOperation {
+                name: name.clone(),
+                bounds: bounds.clone(),
+                reset: *reset,
+            })
+        }
+    };
+    pb::Operation { kind: Some(kind), key: String::new(), key_proposed_at: 0 }
+}
+
+fn admission_to_proto(admission: &Admission) -> Option<pb::Admission> {
+    (!admission.is_default()).then(|| pb::Admission {
+        weight: admission.weight,
+        dedup: admission.dedup.map(|dedup| pb::DedupWindow { interval: dedup.interval, count_skipped: dedup.count_skipped }),
+        outliers: admission.outliers.map(|outliers| pb::OutlierBounds {
+            stddevs: outliers.stddevs.unwrap_or(0.0),
+            max_deviation: outliers.max_deviation.unwrap_or(0.0),
+            min_samples: outliers.min_samples,
+        }),
+    })
+}
+
+fn admission_from_proto(admission: Option<pb::Admission>) -> Admission {
+    admission.map_or_else(Admission::default, |admission| Admission {
+        weight: admission.weight,
+        dedup: admission.dedup.map(|dedup| DedupWindow { interval: dedup.interval, count_skipped: dedup.count_skipped }),
+        outliers: admission.outliers.map(|outliers| OutlierBounds {
+            stddevs: Some(outliers.stddevs).filter(|&bound| bound != 0.0),
+            max_deviation: Some(outliers.max_deviation).filter(|&bound| bound != 0.0),
+            min_samples: outliers.min_samples,
+        }),
+    })
+}
+
+fn decode_protobuf(data: &[u8]) -> std::result::Result<Vec<MetricOperation>, String> {
+    let batch = pb::OperationBatch::decode(data).map_err(|e| e.to_string())?;
+    batch.operations.into_iter().map(from_proto).collect()
+}
+
+fn from_proto(operation: pb::Operation) -> std::result::Result<MetricOperation, String> {
+    let (key, proposed_at) = (operation.key, operation.key_proposed_at);
+    let unkeyed = match operation.kind {
+        Some(pb::operation::Kind::Record(record)) => Ok(MetricOperation::Record {
+            name: record.name,
+            value: record.value,
+            timestamp: record.timestamp,
+            labels: record.labels.into_iter().collect::<Labels>(),
+            admission: admission_from_proto(record.admission),
+        }),
+        Some(pb::operation::Kind::Delete(delete)) => {
+            Ok(MetricOperation::Delete { name: delete.name, deleted_at: delete.deleted_at })
+        }
+        Some(pb::operation::Kind::Clear(_)) => Ok(MetricOperation::Clear),
+        Some(pb::operation::Kind::ApplyQuarantine(apply)) => Ok(MetricOperation::ApplyQuarantine { name: apply.name }),
+        Some(pb::operation::Kind::Transaction(transaction)) => Ok(MetricOperation::Transaction(
+            transaction.operations.into_iter().map(from_proto).collect::<std::result::Result<_, _>>()?,
+        )),
+        Some(pb::operation::Kind::Reset(reset)) => Ok(MetricOperation::Reset {
+            name: reset.name,
+            reset_at: reset.reset_at,
+            keep_history: reset.keep_history,
+        }),
+        Some(pb::operation::Kind::SetTransform(set)) => Ok(MetricOperation::SetTransform {
+            name: set.name,
+            scale: set.scale,
+            offset: set.offset,
+        }),
+        Some(pb::operation::Kind::RecordSummary(record)) => Ok(MetricOperation::RecordSummary {
+            name: record.name,
+            timestamp: record.timestamp,
+            labels: record.labels.into_iter().collect::<Labels>(),
+            summary: SampleSummary {
+                last: record.last,
+                count: record.count,
+                sum: record.sum,
+                min: record.min,
+                max: record.max,
+                m2: record.m2,
+            },
+            admission: admission_from_proto(record.admission),
+        }),
+        Some(pb::operation::Kind::DefineStates(define)) => Ok(MetricOperation::DefineStates {
+            name: define.name,
+            states: define.states,
+            stale_after_secs: Some(define.stale_after_secs).filter(|&secs| secs != 0),
+        }),
+        Some(pb::operation::Kind::DefineHistogram(define)) => Ok(MetricOperation::DefineHistogram {
+            name: define.name,
+            bounds: define.bounds,
+            reset: define.reset,
+        }),
+        None => Err("operation without a kind".to_string()),
+    }?;
+    Ok(match key.is_empty() {
+        true => unkeyed,
+        false => MetricOperation::Keyed { key, proposed_at, operation: Box::new(unkeyed) },
+    })
+}
+
+fn encode_bincode(operation: &MetricOperation, data: &mut Vec<u8>) {
+    let put_str = |data: &mut Vec<u8>, s: &str| {
+        data.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        data.extend_from_slice(s.as_bytes());
+    };
+    match operation {
+        MetricOperation::Record { name, value, timestamp, labels, admission } => {
+            let variant: u32 = match (labels.is_empty(), admission.is_default()) {
+                (true, true) => 0,
+                (false, true) => 3,
+                (_, false) if weight_only(admission) => 12,
+                (_, false) => 14,
+            };
+            data.extend_from_slice(&variant.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&value.to_le_bytes());
+            data.extend_from_slice(&timestamp.to_le_bytes());
+            if variant != 0 {
+                data.extend_from_slice(&(labels.len() as u64).to_le_bytes());
+                for (key, value) in labels {
+                    put_str(data, key);
+                    put_str(data, value);
+                }
+            }
+            match variant {
+                12 => encode_weight(admission, data),
+                14 => encode_admission(admission, data),
+                _ => {}
+            }
+        }
+        MetricOperation::Delete { name, deleted_at } => {
+            data.extend_from_slice(&1u32.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&deleted_at.to_le_bytes());
+        }
+        MetricOperation::Clear => data.extend_from_slice(&2u32.to_le_bytes()),
+        MetricOperation::ApplyQuarantine { name } => {
+            data.extend_from_slice(&4u32.to_le_bytes());
+            put_str(data, name);
+        }
+        MetricOperation::Transaction(operations) => {
+            data.extend_from_slice(&5u32.to_le_bytes());
+            data.extend_from_slice(&(operations.len() as u64).to_le_bytes());
+            for operation in operations {
+                encode_bincode(operation, data);
+            }
+        }
+        MetricOperation::Reset { name, reset_at, keep_history } => {
+            data.extend_from_slice(&6u32.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&reset_at.to_le_bytes());
+            data.push(u8::from(*keep_history));
+        }
+        MetricOperation::SetTransform { name, scale, offset } => {
+            data.extend_from_slice(&7u32.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&scale.to_le_bytes());
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        MetricOperation::RecordSummary { name, timestamp, labels, summary, admission } => {
+            let variant: u32 = match admission.is_default() {
+                true => 8,
+                false if weight_only(admission) => 13,
+                false => 15,
+            };
+            data.extend_from_slice(&variant.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&timestamp.to_le_bytes());
+            data.extend_from_slice(&(labels.len() as u64).to_le_bytes());
+            for (key, value) in labels {
+                put_str(data, key);
+                put_str(data, value);
+            }
+            data.extend_from_slice(&summary.last.to_le_bytes());
+            data.extend_from_slice(&summary.count.to_le_bytes());
+            for field in [summary.sum, summary.min, summary.max, summary.m2] {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+            match variant {
+                13 => encode_weight(admission, data),
+                15 => encode_admission(admission, data),
+                _ => {}
+            }
+        }
+        MetricOperation::DefineStates { name, states, stale_after_secs } => {
+            data.extend_from_slice(&9u32.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&(states.len() as u64).to_le_bytes());
+            for state in states {
+                put_str(data, state);
+            }
+            data.extend_from_slice(&stale_after_secs.unwrap_or(0).to_le_bytes());
+        }
+        MetricOperation::DefineHistogram { name, bounds, reset } => {
+            data.extend_from_slice(&10u32.to_le_bytes());
+            put_str(data, name);
+            data.extend_from_slice(&(bounds.len() as u64).to_le_bytes());
+            for bound in bounds {
+                data.extend_from_slice(&bound.to_le_bytes());
+            }
+            data.push(u8::from(*reset));
+        }
+        MetricOperation::Keyed { key, proposed_at, operation } => {
+            data.extend_from_slice(&16u32.to_le_bytes());
+            put_str(data, key);
+            data.extend_from_slice(&proposed_at.to_le_bytes());
+            encode_bincode(operation, data);
+        }
+    }
+}
+
+const ADMISSION_WEIGHT: u8 = 1;
+const ADMISSION_DEDUP: u8 = 2;
+const ADMISSION_OUTLIERS: u8 = 4;
+
+fn weight_only(admission: &Admission) -> bool {
+    *admission == Admission { weight: admission.weight, ..Admission::default() }
+}
+
+/// An admission's weight, or 0 for none.
+fn encode_weight(admission: &Admission, data: &mut Vec<u8>) {
+    data.extend_from_slice(&admission.weight.unwrap_or(0).to_le_bytes());
+}
+
+/// A byte flagging which of an admission's fields follow, then each of
+/// them in flag order.
+fn encode_admission(admission: &Admission, data: &mut Vec<u8>) {
+    let mut flags = 0;
+    if admission.weight.is_some() {
+        flags |= ADMISSION_WEIGHT;
+    }
+    if admission.dedup.is_some() {
+        flags |= ADMISSION_DEDUP;
+    }
+    if admission.outliers.is_some() {
+        flags |= ADMISSION_OUTLIERS;
+    }
+    data.push(flags);
+    if let Some(weight) = admission.weight {
+        data.extend_from_slice(&weight.to_le_bytes());
+    }
+    if let Some(dedup) = admission.dedup {
+        data.extend_from_slice(&dedup.interval.to_le_bytes());
+        data.push(u8::from(dedup.count_skipped));
+    }
+    // Unset bounds as 0, which no policy sets
+    if let Some(outliers) = admission.outliers {
+        data.extend_from_slice(&outliers.stddevs.unwrap_or(0.0).to_le_bytes());
+        data.extend_from_slice(&outliers.max_deviation.unwrap_or(0.0).to_le_bytes());
+        data.extend_from_slice(&outliers.min_samples.to_le_bytes());
+    }
+}
+
+/// Reads bincode fields off the front of an entry.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> std::result::Result<[u8; N], String> {
+        if self.0.len() < N {
+            return Err("truncated entry".to_string());
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> std::result::Result<u64, String> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn weight(&mut self) -> std::result::Result<Admission, String> {
+        Ok(Admission { weight: Some(self.u64()?).filter(|&weight| weight != 0), ..Admission::default() })
+    }
+
+    fn admission(&mut self) -> std::result::Result<Admission, String> {
+        let [flags] = self.take::<1>()?;
+        if flags & !(ADMISSION_WEIGHT | ADMISSION_DEDUP | ADMISSION_OUTLIERS) != 0 {
+            return Err(format!("unknown admission flags {:#x}", flags));
+        }
+        let weight = if flags & ADMISSION_WEIGHT != 0 { Some(self.u64()?) } else { None };
+        let dedup = if flags & ADMISSION_DEDUP != 0 {
+            Some(DedupWindow { interval: i64::from_le_bytes(self.take()?), count_skipped: self.bool()? })
+        } else {
+            None
+        };
+        let outliers = if flags & ADMISSION_OUTLIERS != 0 {
+            let mut bound = || self.take().map(f64::from_le_bytes).map(|bound| Some(bound).filter(|&bound| bound != 0.0));
+            Some(OutlierBounds { stddevs: bound()?, max_deviation: bound()?, min_samples: self.u64()? })
+        } else {
+            None
+        };
+        Ok(Admission { weight, dedup, outliers })
+    }
+
+    fn bool(&mut self) -> std::result::Result<bool, String> {
+        match self.take::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [byte] => Err(format!("invalid bool {}", byte)),
+        }
+    }
+
+    fn string(&mut self) -> std::result::Result<String, String> {
+        let len = self.u64()? as usize;
+        if self.0.len() < len {
+            return Err("truncated entry".to_string());
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(head.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+fn decode_bincode(data: &[u8]) -> std::result::Result<Vec<MetricOperation>, String> {
+    let mut reader = Reader(data);
+    let operations = read_bincode_operations(&mut reader)?;
+    if !reader.0.is_empty() {
+        return Err("trailing bytes after operations".to_string());
+    }
+    Ok(operations)
+}
+
+/// Reads a count followed by that many operations.
+fn read_bincode_operations(reader: &mut Reader) -> std::result::Result<Vec<MetricOperation>, String> {
+    let count = reader.u64()?;
+    let mut operations = Vec::new();
+    for _ in 0..count {
+        operations.push(read_bincode_operation(reader)?);
+    }
+    Ok(operations)
+}
+
+fn read_bincode_operation(reader: &mut Reader) -> std::result::Result<MetricOperation, String> {
+    Ok(match u32::from_le_bytes(reader.take()?) {
+        variant @ (0 | 3 | 12 | 14) => {
+            let name = reader.string()?;
+            let value = f64::from_le_bytes(reader.take()?);
+            let timestamp = i64::from_le_bytes(reader.take()?);
+            let mut labels = Labels::new();
+            if variant != 0 {
+                for _ in 0..reader.u64()? {
+                    labels.insert(reader.string()?, reader.string()?);
+                }
+            }
+            let admission = match variant {
+                12 => reader.weight()?,
+                14 => reader.admission()?,
+                _ => Admission::default(),
+            };
+            MetricOperation::Record { name, value, timestamp, labels, admission }
+        }
+        1 => MetricOperation::Delete {
+            name: reader.string()?,
+            deleted_at: i64::from_le_bytes(reader.take()?),
+        },
+        2 => MetricOperation::Clear,
+        4 => MetricOperation::ApplyQuarantine { name: reader.string()? },
+        5 => MetricOperation::Transaction(read_bincode_operations(reader)?),
+        6 => MetricOperation::Reset {
+            name: reader.string()?,
+            reset_at: i64::from_le_bytes(reader.take()?),
+            keep_history: reader.bool()?,
+        },
+        7 => MetricOperation::SetTransform {
+            name: reader.string()?,
+            scale: f64::from_le_bytes(reader.take()?),
+            offset: f64::from_le_bytes(reader.take()?),
+        },
+        variant @ (8 | 13 | 15) => {
+            let name = reader.string()?;
+            let timestamp = i64::from_le_bytes(reader.take()?);
+            let mut labels = Labels::new();
+            for _ in 0..reader.u64()? {
+                labels.insert(reader.string()?, reader.string()?);
+            }
+            let summary = SampleSummary {
+                last: f64::from_le_bytes(reader.take()?),
+                count: reader.u64()?,
+                sum: f64::from_le_bytes(reader.take()?),
+                min: f64::from_le_bytes(reader.take()?),
+                max: f64::from_le_bytes(reader.take()?),
+                m2: f64::from_le_bytes(reader.take()?),
+            };
+            let admission = match variant {
+                13 => reader.weight()?,
+                15 => reader.admission()?,
+                _ => Admission::default(),
+            };
+            MetricOperation::RecordSummary { name, timestamp, labels, summary, admission }
+        }
+        9 => {
+            let name = reader.string()?;
+            let states = (0..reader.u64()?).map(|_| reader.string()).collect::<std::result::Result<_, _>>()?;
+            let stale_after_secs = Some(i64::from_le_bytes(reader.take()?)).filter(|&secs| secs != 0);
+            MetricOperation::DefineStates { name, states, stale_after_secs }
+        }
+        10 => MetricOperation::DefineHistogram {
+            name: reader.string()?,
+            bounds: (0..reader.u64()?).map(|_| reader.take().map(f64::from_le_bytes)).collect::<std::result::Result<_, _>>()?,
+            reset: reader.bool()?,
+        },
+        // Keyed before proposal times were carried
+        11 => MetricOperation::Keyed {
+            key: reader.string()?,
+            proposed_at: 0,
+            operation: Box::new(read_bincode_operation(reader)?),
+        },
+        16 => MetricOperation::Keyed {
+            key: reader.string()?,
+            proposed_at: i64::from_le_bytes(reader.take()?),
+            operation: Box::new(read_bincode_operation(reader)?),
+        },
+        variant => return Err(format!("unknown operation variant {}", variant)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operations() -> Vec<MetricOperation> {
+        vec![
+            MetricOperation::record("cpu", 1.5, 100, Labels::new()),
+            MetricOperation::record("latency", 12.0, 100, Labels::from([("region".into(), "eu".into()), ("host".into(), "a".into())])),
+            MetricOperation::Record {
+                name: "requests".into(),
+                value: 1.0,
+                timestamp: 150,
+                labels: Labels::new(),
+                admission: Admission { weight: Some(3), ..Admission::default() },
+            },
+            MetricOperation::Delete { name: "mem".into(), deleted_at: 200 },
+            MetricOperation::Clear,
+            MetricOperation::ApplyQuarantine { name: "latency".into() },
+            MetricOperation::Transaction(vec![
+                MetricOperation::record("queue_depth", 3.0, 300, Labels::new()),
+                MetricOperation::record("queue_pushes", 7.0, 300, Labels::new()),
+            ]),
+            MetricOperation::Reset { name: "requests_total".into(), reset_at: 400, keep_history: true },
+            MetricOperation::SetTransform { name: "latency_ns".into(), scale: 0.001, offset: 0.0 },
+            MetricOperation::RecordSummary {
+                name: "requests".into(),
+                timestamp: 500,
+                labels: Labels::from([("region".into(), "eu".into())]),
+                summary: SampleSummary { last: 4.0, count: 3, sum: 9.0, min: 2.0, max: 4.0, m2: 2.0 },
+                admission: Admission { weight: Some(4), ..Admission::default() },
+            },
+            MetricOperation::DefineStates {
+                name: "breaker".into(),
+                states: vec!["closed".into(), "open".into(), "half_open".into()],
+                stale_after_secs: Some(300),
+            },
+            MetricOperation::DefineHistogram { name: "latency".into(), bounds: vec![0.25, 0.5, 2.5], reset: true },
+            MetricOperation::Keyed {
+                key: "7f3c2a1e-journal".into(),
+                proposed_at: 1_700_000_000,
+                operation: Box::new(MetricOperation::record("cpu", 2.5, 600, Labels::new())),
+            },
+        ]
+    }
+
+    const CODECS: [RaftCodec; 3] = [RaftCodec::Json, RaftCodec::Bincode, RaftCodec::Protobuf];
+
+    #[test]
+    fn test_every_codec_round_trips() {
+        for codec in CODECS {
+            let data = codec.encode(&operations()).unwrap();
+            assert_eq!(decode(&data).unwrap(), operations(), "{:?}", codec);
+            assert_eq!(decode(&codec.encode(&[]).unwrap()).unwrap(), [], "{:?}", codec);
+        }
+        let json = RaftCodec::Json.encode(&operations()).unwrap();
+        let bincode = RaftCodec::Bincode.encode(&operations()).unwrap();
+        let protobuf = RaftCodec::Protobuf.encode(&operations()).unwrap();
+        assert!(bincode.len() < json.len());
+        assert!(protobuf.len() < bincode.len());
+        assert_eq!(
+            operations().iter().map(|op| RaftCodec::Bincode.encoded_len(op)).sum::<usize>() + 9,
+            bincode.len()
+        );
+        assert_eq!(
+            operations().iter().map(|op| RaftCodec::Protobuf.encoded_len(op)).sum::<usize>() + 1,
+            protobuf.len()
+        );
+    }
+
+    /// Small deterministic generator, so failures reproduce.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.next() % 12;
+            (0..len).map(|_| ['a', 'Z', '_', '9', 'é', '\u{1F4C8}', '"', ' '][self.next() as usize % 8]).collect()
+        }
+
+        fn admission(&mut self) -> Admission {
+            Admission {
+                weight: self.next().is_multiple_of(3).then(|| self.next() % 8 + 1),
+                dedup: self.next().is_multiple_of(3).then(|| DedupWindow {
+                    interval: (self.next() % 600) as i64 + 1,
+                    count_skipped: self.next().is_multiple_of(2),
+                }),
+                outliers: self.next().is_multiple_of(3).then(|| OutlierBounds {
+                    stddevs: self.next().is_multiple_of(2).then(|| (self.next() % 16 + 1) as f64 / 4.0),
+                    max_deviation: self.next().is_multiple_of(2).then(|| (self.next() % 1000 + 1) as f64),
+                    min_samples: self.next() % 100,
+                }),
+            }
+        }
+
+        fn operation(&mut self) -> MetricOperation {
+            match self.next() % 11 {
+                0 => MetricOperation::Record {
+                    name: self.string(),
+                    // Quarters print exactly, so JSON round trips them too
+                    value: (self.next() as i32) as f64 / 4.0,
+                    timestamp: self.next() as i64,
+                    labels: (0..self.next() % 4).map(|_| (self.string(), self.string())).collect(),
+                    admission: self.admission(),
+                },
+                1 => MetricOperation::Delete { name: self.string(), deleted_at: self.next() as i64 },
+                2 => MetricOperation::ApplyQuarantine { name: self.string() },
+                3 => MetricOperation::Transaction((0..self.next() % 3).map(|_| self.operation()).collect()),
+                4 => MetricOperation::Reset {
+                    name: self.string(),
+                    reset_at: self.next() as i64,
+                    keep_history: self.next().is_multiple_of(2),
+                },
+                5 => MetricOperation::SetTransform {
+                    name: self.string(),
+                    scale: (self.next() as i32) as f64 / 4.0,
+                    offset: (self.next() as i32) as f64 / 4.0,
+                },
+                6 => MetricOperation::RecordSummary {
+                    name: self.string(),
+                    timestamp: self.next() as i64,
+                    labels: (0..self.next() % 4).map(|_| (self.string(), self.string())).collect(),
+                    summary: SampleSummary {
+                        last: (self.next() as i32) as f64 / 4.0,
+                        count: self.next(),
+                        sum: (self.next() as i32) as f64 / 4.0,
+                        min: (self.next() as i32) as f64 / 4.0,
+                        max: (self.next() as i32) as f64 / 4.0,
+                        m2: (self.next() as i32) as f64 / 4.0,
+                    },
+                    admission: self.admission(),
+                },
+                7 => MetricOperation::DefineStates {
+                    name: self.string(),
+                    states: (0..self.next() % 4).map(|_| self.string()).collect(),
+                    stale_after_secs: (self.next().is_multiple_of(2)).then(|| (self.next() % 3600) as i64 + 1),
+                },
+                8 => MetricOperation::DefineHistogram {
+                    name: self.string(),
+                    bounds: (0..self.next() % 4).map(|_| (self.next() as i32) as f64 / 4.0).collect(),
+                    reset: self.next().is_multiple_of(2),
+                },
+                // Keys cannot be nested, and an empty key is no key
+                9 => MetricOperation::Keyed {
+                    key: format!("k{}", self.string()),
+                    proposed_at: self.next() as i64,
+                    operation: Box::new(std::iter::repeat_with(|| self.operation())
+                        .find(|operation| !matches!(operation, MetricOperation::Keyed { .. }))
+                        .unwrap()),
+                },
+                _ => MetricOperation::Clear,
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_batches_round_trip_in_every_codec() {
+        let mut random = Xorshift(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..500 {
+            let batch: Vec<MetricOperation> = (0..random.next() % 20).map(|_| random.operation()).collect();
+            for codec in CODECS {
+                let data = codec.encode(&batch).unwrap();
+                assert_eq!(decode(&data).unwrap(), batch, "{:?}", codec);
+                if codec != RaftCodec::Json {
+                    let sizes: usize = batch.iter().map(|op| codec.encoded_len(op)).sum();
+                    assert!(sizes < data.len(), "{:?}", codec);
+                }
+            }
+        }
+    }
+
+    /// A full entry of records, as the apply path sees them. Timings are
+    /// in `benches/codec.rs`.
+    #[test]
+    fn test_binary_codecs_shrink_a_full_entry() {
+        let batch: Vec<MetricOperation> = (0..10_000)
+            .map(|i| {
+                let labels = Labels::from([("region".into(), "eu-west-1".into())]);
+                MetricOperation::record(format!("service_{}_request_latency_seconds", i % 50), i as f64 * 0.25, 1_700_000_000 + i as i64, labels)
+            })
+            .collect();
+
+        let sizes: Vec<usize> = CODECS.iter().map(|codec| codec.encode(&batch).unwrap().len()).collect();
+        let (json, bincode, protobuf) = (sizes[0], sizes[1], sizes[2]);
+        assert!(bincode < json, "bincode {} bytes vs json {}", bincode, json);
+        assert!(protobuf < bincode, "protobuf {} bytes vs bincode {}", protobuf, bincode);
+        for codec in CODECS {
+            assert_eq!(decode(&codec.encode(&batch).unwrap()).unwrap(), batch, "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn test_decode_follows_entry_prefix_not_configured_codec() {
+        // A node configured for a binary codec still reads JSON entries from before the switch
+        let tagged = RaftCodec::Json.encode(&operations()).unwrap();
+        let untagged = serde_json::to_vec(&operations()).unwrap();
+        assert_eq!(decode(&tagged).unwrap(), operations());
+        assert_eq!(decode(&untagged).unwrap(), operations());
+
+        for codec in [RaftCodec::Bincode, RaftCodec::Protobuf] {
+            let mut truncated = codec.encode(&operations()).unwrap();
+            truncated.truncate(truncated.len() - 3);
+            assert!(decode(&truncated).is_err(), "{:?}", codec);
+        }
+        assert!(decode(&[9, 0]).is_err());
+    }
+
+    #[test]
+    fn test_keyed_entries_from_before_proposal_times_still_decode() {
+        let mut bincode = vec![BINCODE_TAG];
+        bincode.extend_from_slice(&1u64.to_le_bytes());
+        bincode.extend_from_slice(&11u32.to_le_bytes());
+        bincode.extend_from_slice(&3u64.to_le_bytes());
+        bincode.extend_from_slice(b"k-1");
+        bincode.extend_from_slice(&2u32.to_le_bytes());
+        let json = br#"[{"Keyed":{"key":"k-1","operation":"Clear"}}]"#;
+        let expected = [MetricOperation::Keyed { key: "k-1".into(), proposed_at: 0, operation: Box::new(MetricOperation::Clear) }];
+        assert_eq!(decode(&bincode).unwrap(), expected);
+        assert_eq!(decode(json).unwrap(), expected);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use crate::config::units;
+
+/// Rows a compaction removes per transaction unless configured otherwise.
+pub const DEFAULT_COMPACTION_BATCH_ROWS: usize = 10_000;
+pub const DEFAULT_COMPACTION_INTERVAL_SECS: i64 = 60;
+
+/// How old samples are thinned, from `COMPACTION_MIN_AGE_SECS`,
+/// `COMPACTION_INTERVAL_SECS`, `COMPACTION_EXEMPT` and
+/// `COMPACTION_BATCH_ROWS`. Samples older than the age keep only the latest
+/// of each interval-aligned bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionPolicy {
+    /// Age in seconds past which samples are thinned; `None` turns
+    /// compaction off.
+    pub min_age: Option<i64>,
+    /// Bucket width in seconds.
+    pub interval: i64,
+    /// Metrics never thinned.
+    pub exempt: HashSet<String>,
+    /// Most rows removed in one transaction.
+    pub batch_rows: usize,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            min_age: None,
+            interval: DEFAULT_COMPACTION_INTERVAL_SECS,
+            exempt: HashSet::new(),
+            batch_rows: DEFAULT_COMPACTION_BATCH_ROWS,
+        }
+    }
+}
+
+impl CompactionPolicy {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads the policy; `COMPACTION_EXEMPT` is a comma-separated list of
+    /// metric names. Unparsable values keep their defaults.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let secs = |key: &str| units::duration(&lookup, key).map(|age| age.as_secs() as i64);
+        Self {
+            min_age: secs("COMPACTION_MIN_AGE_SECS").filter(|&secs| secs > 0),
+            interval: secs("COMPACTION_INTERVAL_SECS")
+                .filter(|&secs| secs > 0)
+                .unwrap_or(defaults.interval),
+            exempt: lookup("COMPACTION_EXEMPT")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            batch_rows: lookup("COMPACTION_BATCH_ROWS")
+                .and_then(|v| v.parse().ok())
+                .filter(|&rows| rows > 0)
+                .unwrap_or(defaults.batch_rows),
+        }
+    }
+
+    /// Start of the first bucket not yet old enough at `now`; samples
+    /// before it are thinned. Only whole buckets are thinned, so a pass
+    /// keeps the same sample in a bucket however often it runs.
+    pub fn cutoff(&self, now: i64) -> Option<i64> {
+        let oldest = now - self.min_age?;
+        Some(oldest - oldest.rem_euclid(self.interval))
+    }
+
+    pub fn exempts(&self, name: &str) -> bool {
+        self.exempt.contains(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cutoff_is_bucket_aligned() {
+        let policy = CompactionPolicy::from_lookup(|key| match key {
+            "COMPACTION_MIN_AGE_SECS" => Some("3600".to_string()),
+            "COMPACTION_INTERVAL_SECS" => Some("60".to_string()),
+            "COMPACTION_EXEMPT" => Some("slo, ".to_string()),
+            _ => None,
+        });
+        assert_eq!(policy.cutoff(10_000), Some(6_360));
+        assert_eq!(policy.cutoff(10_059), Some(6_420));
+        assert!(policy.exempts("slo"));
+        assert_eq!(policy.exempt.len(), 1);
+        assert_eq!(CompactionPolicy::default().cutoff(10_000), None);
+    }
+}
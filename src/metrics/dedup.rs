@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+use super::MetricPoint;
+use crate::config::units;
+
+/// When a sample repeating a metric's latest value is left out of the raw
+/// table, from `DEDUP_INTERVAL_SECS`, `DEDUP_INTERVALS` and
+/// `DEDUP_COUNT_SKIPPED`. The proposing node puts the metric's
+/// [`DedupWindow`] in the entry, so every replica skips the same rows
+/// whatever its own policy says.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DedupPolicy {
+    /// Interval for metrics without their own; `None` stores every sample.
+    pub interval: Option<i64>,
+    /// Intervals by metric name; 0 turns dedup off for that metric.
+    pub intervals: HashMap<String, i64>,
+    /// Whether skipped samples still count in the aggregate.
+    pub count_skipped: bool,
+}
+
+impl DedupPolicy {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `DEDUP_INTERVAL_SECS`, `DEDUP_INTERVALS` (comma-separated
+    /// `name=secs` pairs) and `DEDUP_COUNT_SKIPPED`. Malformed entries are
+    /// skipped with a warning.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut intervals = HashMap::new();
+        for entry in lookup("DEDUP_INTERVALS").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            match entry.split_once('=').and_then(|(name, secs)| Some((name.trim(), secs.trim().parse::<i64>().ok()?))) {
+                Some((name, secs)) if !name.is_empty() && secs >= 0 => {
+                    intervals.insert(name.to_string(), secs);
+                }
+                _ => warn!("Ignoring DEDUP_INTERVALS entry '{}'", entry),
+            }
+        }
+        Self {
+            interval: units::duration(&lookup, "DEDUP_INTERVAL_SECS").map(|interval| interval.as_secs() as i64).filter(|&secs| secs > 0),
+            intervals,
+            count_skipped: lookup("DEDUP_COUNT_SKIPPED").is_some_and(|v| v == "true"),
+        }
+    }
+
+    /// The window `name`'s samples are deduplicated in, if any.
+    pub fn window(&self, name: &str) -> Option<DedupWindow> {
+        let interval = self.intervals.get(name).copied().or(self.interval).filter(|&secs| secs > 0)?;
+        Some(DedupWindow { interval, count_skipped: self.count_skipped })
+    }
+
+    /// Whether `sample` of `name` repeats `stored` within the metric's
+    /// window; see [`DedupWindow::skips`].
+    pub fn skips(&self, name: &str, stored: Option<MetricPoint>, sample: MetricPoint) -> bool {
+        self.window(name).is_some_and(|window| window.skips(stored, sample))
+    }
+}
+
+/// How one metric's samples are deduplicated, as decided by the node that
+/// proposed them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DedupWindow {
+    /// Seconds a repeat of the latest stored value is skipped for.
+    pub interval: i64,
+    /// Whether skipped samples still count in the aggregate.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub count_skipped: bool,
+}
+
+impl DedupWindow {
+    /// Whether `sample` repeats `stored`, the latest stored sample, less
+    /// than `interval` after it. Samples are still stored at least once per
+    /// interval, so a range read sees the value held up to the next change.
+    pub fn skips(&self, stored: Option<MetricPoint>, sample: MetricPoint) -> bool {
+        stored.is_some_and(|stored| {
+            stored.value == sample.value
+                && sample.timestamp >= stored.timestamp
+                && sample.timestamp - stored.timestamp < self.interval
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeats_within_the_interval_are_skipped() {
+        let policy = DedupPolicy::from_lookup(|key| match key {
+            "DEDUP_INTERVAL_SECS" => Some("60".to_string()),
+            "DEDUP_INTERVALS" => Some("raw=0,slow=600,bad".to_string()),
+            _ => None,
+        });
+        let stored = Some(MetricPoint { value: 1.0, timestamp: 1_000 });
+        let at = |value: f64, timestamp: i64| MetricPoint { value, timestamp };
+
+        assert!(policy.skips("gauge", stored, at(1.0, 1_059)));
+        assert!(!policy.skips("gauge", stored, at(1.0, 1_060)));
+        assert!(!policy.skips("gauge", stored, at(2.0, 1_001)));
+        // A late arrival is always stored
+        assert!(!policy.skips("gauge", stored, at(1.0, 999)));
+        assert!(!policy.skips("gauge", None, at(1.0, 1_001)));
+
+        assert!(!policy.skips("raw", stored, at(1.0, 1_001)));
+        assert!(policy.skips("slow", stored, at(1.0, 1_500)));
+        assert!(!DedupPolicy::default().skips("gauge", stored, at(1.0, 1_001)));
+    }
+}
@@ -0,0 +1,275 @@
+use prometheus::proto::{Bucket, Histogram, Metric, MetricFamily, MetricType};
+use serde::{Deserialize, Serialize};
+
+use crate::{RaftMetricsError, Result};
+
+/// Most bucket boundaries a histogram metric may have.
+pub const MAX_BUCKETS: usize = 128;
+
+/// Bucket boundaries used when a definition names none: 16 buckets
+/// doubling from 1ms to about 33s, for latencies in seconds.
+pub fn default_bounds() -> Vec<f64> {
+    (0..16).map(|i| 0.001 * f64::powi(2.0, i)).collect()
+}
+
+/// A metric whose samples are counted into buckets, as set through
+/// `PUT /metrics/:name/histogram`. A sample falls in the first bucket
+/// whose upper bound it does not exceed, as Prometheus's `le` does, or in
+/// the overflow bucket past the last bound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramMetric {
+    /// Upper bounds of the buckets, ascending.
+    pub bounds: Vec<f64>,
+    /// Samples in each bucket, not cumulative, with the overflow bucket
+    /// last.
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl HistogramMetric {
+    /// Validates a layout: 1 to [`MAX_BUCKETS`] finite bounds in strictly
+    /// ascending order.
+    pub fn new(bounds: Vec<f64>) -> Result<Self> {
+        let invalid = |message: String| Err(RaftMetricsError::InvalidRequest(message));
+        if bounds.is_empty() || bounds.len() > MAX_BUCKETS {
+            return invalid(format!("a histogram needs 1 to {} bucket bounds", MAX_BUCKETS));
+        }
+        if bounds.iter().any(|bound| !bound.is_finite()) {
+            return invalid("bucket bounds must be finite; the overflow bucket is implied".to_string());
+        }
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return invalid("bucket bounds must be strictly ascending".to_string());
+        }
+        let counts = vec![0; bounds.len() + 1];
+        Ok(Self { bounds, counts, count: 0, sum: 0.0 })
+    }
+
+    /// The histogram after redefining it as `next`: unchanged for the same
+    /// layout, `next` with `reset`. Any other layout change is a
+    /// [`RaftMetricsError::Conflict`], as counts cannot move between
+    /// buckets.
+    pub fn redefined(&self, next: HistogramMetric, reset: bool) -> Result<HistogramMetric> {
+        match (reset, next.bounds == self.bounds) {
+            (true, _) => Ok(next),
+            (false, true) => Ok(self.clone()),
+            (false, false) => Err(RaftMetricsError::Conflict(
+                "the histogram has a different bucket layout; redefine it with reset=true to start its counts over".to_string(),
+            )),
+        }
+    }
+
+    /// Index of the bucket counting `value`.
+    pub fn bucket(&self, value: f64) -> usize {
+        self.bounds.partition_point(|&bound| bound < value)
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bucket(value);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Forgets every sample, keeping the layout.
+    pub fn reset(&mut self) {
+        self.counts = vec![0; self.bounds.len() + 1];
+        self.count = 0;
+        self.sum = 0.0;
+    }
+
+    pub fn report(&self, name: &str) -> HistogramReport {
+        let mut cumulative = 0;
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| {
+                cumulative += count;
+                HistogramBucket { le: self.bounds.get(i).copied(), count, cumulative }
+            })
+            .collect();
+        HistogramReport { name: name.to_string(), histogram: HistogramCounts { buckets, count: self.count, sum: self.sum } }
+    }
+
+    /// `name`'s histogram as a Prometheus family named `family`, with
+    /// cumulative `_bucket` series up to the last bound; the `+Inf` bucket
+    /// is `_count`.
+    pub fn family(&self, name: &str, family: &str) -> MetricFamily {
+        let mut cumulative = 0;
+        let buckets: Vec<Bucket> = self
+            .bounds
+            .iter()
+            .zip(&self.counts)
+            .map(|(&bound, &count)| {
+                cumulative += count;
+                let mut bucket = Bucket::default();
+                bucket.set_upper_bound(bound);
+                bucket.set_cumulative_count(cumulative);
+                bucket
+            })
+            .collect();
+        let mut histogram = Histogram::default();
+        histogram.set_sample_count(self.count);
+        histogram.set_sample_sum(self.sum);
+        histogram.set_bucket(buckets.into());
+        let mut metric = Metric::default();
+        metric.set_histogram(histogram);
+
+        let mut exposed = MetricFamily::default();
+        exposed.set_name(family.to_string());
+        exposed.set_help(format!("Samples of the histogram metric '{}'", name));
+        exposed.set_field_type(MetricType::HISTOGRAM);
+        exposed.set_metric(vec![metric].into());
+        exposed
+    }
+}
+
+/// `name` as a Prometheus metric name: characters other than letters,
+/// digits, `_` and `:` become `_`, and a leading digit gets a `_` before it.
+pub fn family_name(name: &str) -> String {
+    let mut family: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == ':' { c } else { '_' })
+        .collect();
+    if !family.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == ':') {
+        family.insert(0, '_');
+    }
+    family
+}
+
+/// Body of `PUT /metrics/:name/histogram`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistogramDefinition {
+    /// Upper bounds of the buckets; [`default_bounds`] when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<f64>>,
+}
+
+/// One bucket of a [`HistogramReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    /// Upper bound, inclusive; `None` for the overflow bucket.
+    pub le: Option<f64>,
+    /// Samples in this bucket alone.
+    pub count: u64,
+    /// Samples in this bucket and every one below it.
+    pub cumulative: u64,
+}
+
+/// A histogram's buckets, count and sum.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramCounts {
+    pub buckets: Vec<HistogramBucket>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl HistogramCounts {
+    /// Adds `other`'s samples, bucket by bucket. Histograms with different
+    /// layouts cannot be summed and are a [`RaftMetricsError::Conflict`].
+    pub fn merge(&mut self, other: &HistogramCounts) -> Result<()> {
+        let layout = |counts: &HistogramCounts| counts.buckets.iter().map(|bucket| bucket.le).collect::<Vec<_>>();
+        if layout(self) != layout(other) {
+            return Err(RaftMetricsError::Conflict("histograms with different bucket layouts cannot be merged".to_string()));
+        }
+        for (bucket, other) in self.buckets.iter_mut().zip(&other.buckets) {
+            bucket.count += other.count;
+            bucket.cumulative += other.cumulative;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        Ok(())
+    }
+}
+
+/// A histogram metric's buckets, served at `GET /metrics/:name/histogram`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramReport {
+    pub name: String,
+    #[serde(flatten)]
+    pub histogram: HistogramCounts,
+}
+
+/// Body of `POST /histograms/merge`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramMergeRequest {
+    pub names: Vec<String>,
+}
+
+/// Several histogram metrics summed into one, from `POST /histograms/merge`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergedHistogram {
+    pub names: Vec<String>,
+    #[serde(flatten)]
+    pub histogram: HistogramCounts,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_on_a_bound_fall_in_its_bucket() {
+        let mut histogram = HistogramMetric::new(vec![0.25, 0.5, 1.0]).unwrap();
+        assert_eq!(histogram.bucket(0.25), 0);
+        assert_eq!(histogram.bucket(0.250001), 1);
+        assert_eq!(histogram.bucket(1.0), 2);
+        assert_eq!(histogram.bucket(1.5), 3);
+        assert_eq!(histogram.bucket(-5.0), 0);
+
+        for value in [0.25, 0.5, 0.5, 2.0] {
+            histogram.observe(value);
+        }
+        let report = histogram.report("latency").histogram;
+        assert_eq!(report.buckets.iter().map(|b| (b.le, b.count, b.cumulative)).collect::<Vec<_>>(), [
+            (Some(0.25), 1, 1),
+            (Some(0.5), 2, 3),
+            (Some(1.0), 0, 3),
+            (None, 1, 4),
+        ]);
+        assert_eq!((report.count, report.sum), (4, 3.25));
+    }
+
+    #[test]
+    fn test_layouts_are_validated_and_changes_need_a_reset() {
+        assert!(HistogramMetric::new(vec![]).is_err());
+        assert!(HistogramMetric::new(vec![1.0, 1.0]).is_err());
+        assert!(HistogramMetric::new(vec![2.0, 1.0]).is_err());
+        assert!(HistogramMetric::new(vec![1.0, f64::INFINITY]).is_err());
+        assert_eq!(HistogramMetric::new(default_bounds()).unwrap().bounds.len(), 16);
+
+        let mut histogram = HistogramMetric::new(vec![1.0, 2.0]).unwrap();
+        histogram.observe(1.5);
+        let same = histogram.redefined(HistogramMetric::new(vec![1.0, 2.0]).unwrap(), false).unwrap();
+        assert_eq!(same.count, 1);
+        let changed = histogram.redefined(HistogramMetric::new(vec![1.0, 5.0]).unwrap(), false);
+        assert_eq!(changed.unwrap_err().code(), crate::error::ErrorCode::Conflict);
+        let replaced = histogram.redefined(HistogramMetric::new(vec![1.0, 5.0]).unwrap(), true).unwrap();
+        assert_eq!((replaced.bounds, replaced.count), (vec![1.0, 5.0], 0));
+    }
+
+    #[test]
+    fn test_merges_need_matching_layouts() {
+        let mut a = HistogramMetric::new(vec![1.0, 2.0]).unwrap();
+        let mut b = a.clone();
+        a.observe(0.5);
+        b.observe(1.5);
+        b.observe(3.0);
+        let mut merged = a.report("a").histogram;
+        merged.merge(&b.report("b").histogram).unwrap();
+        assert_eq!(merged.buckets.iter().map(|b| b.cumulative).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!((merged.count, merged.sum), (3, 5.0));
+
+        let other = HistogramMetric::new(vec![1.0, 3.0]).unwrap();
+        assert!(merged.merge(&other.report("c").histogram).is_err());
+        assert_eq!(merged.count, 3);
+    }
+
+    #[test]
+    fn test_family_names_are_valid_prometheus_names() {
+        assert_eq!(family_name("api.latency-ms"), "api_latency_ms");
+        assert_eq!(family_name("5xx"), "_5xx");
+        assert_eq!(family_name("job:latency"), "job:latency");
+    }
+}
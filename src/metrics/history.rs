@@ -0,0 +1,105 @@
+use std::collections::{HashMap, VecDeque};
+use super::MetricPoint;
+
+/// The most recent samples of one series, in write order.
+#[derive(Debug, Default)]
+struct Series {
+    samples: VecDeque<MetricPoint>,
+    /// Greatest timestamp of a stored sample not held here. Every sample
+    /// after it is held, so windows starting later need not touch the store.
+    covered_after: Option<i64>,
+}
+
+/// Up to `max` recent samples per series, kept alongside the store so reads
+/// over a recent window are answered from memory.
+#[derive(Debug, Default)]
+pub(crate) struct RecentHistory {
+    max: usize,
+    series: HashMap<String, Series>,
+}
+
+impl RecentHistory {
+    pub fn new(max: usize) -> Self {
+        Self { max, series: HashMap::new() }
+    }
+
+    /// Appends a stored sample, dropping the oldest once `name` holds `max`.
+    /// `previous` is the series' latest sample before this one: a series
+    /// first seen here may already have older samples in the store.
+    pub fn push(&mut self, name: &str, sample: MetricPoint, previous: Option<MetricPoint>) {
+        if self.max == 0 {
+            return;
+        }
+        let series = self.series.entry(name.to_string()).or_insert_with(|| Series {
+            samples: VecDeque::new(),
+            covered_after: previous.map(|latest| latest.timestamp),
+        });
+        series.samples.push_back(sample);
+        while series.samples.len() > self.max {
+            if let Some(dropped) = series.samples.pop_front() {
+                series.covered_after = Some(series.covered_after.map_or(dropped.timestamp, |after| after.max(dropped.timestamp)));
+            }
+        }
+    }
+
+    /// Earliest and latest held samples with `from <= timestamp <= to`, ties
+    /// going to the earlier and later write, or `None` when the window may
+    /// reach samples no longer held and must be read from the store.
+    pub fn range_bounds(&self, name: &str, from: i64, to: i64) -> Option<Option<(MetricPoint, MetricPoint)>> {
+        let series = self.series.get(name)?;
+        if series.covered_after.is_some_and(|after| from <= after) {
+            return None;
+        }
+
+        let mut bounds: Option<(MetricPoint, MetricPoint)> = None;
+        for sample in series.samples.iter().filter(|sample| (from..=to).contains(&sample.timestamp)) {
+            bounds = Some(match bounds {
+                None => (*sample, *sample),
+                Some((first, last)) => (
+                    if sample.timestamp < first.timestamp { *sample } else { first },
+                    if sample.timestamp >= last.timestamp { *sample } else { last },
+                ),
+            });
+        }
+        Some(bounds)
+    }
+
+    pub fn forget(&mut self, name: &str) {
+        self.series.remove(name);
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(value: f64, timestamp: i64) -> MetricPoint {
+        MetricPoint { value, timestamp }
+    }
+
+    #[test]
+    fn test_windows_past_the_dropped_samples_are_answered() {
+        let mut history = RecentHistory::new(3);
+        // A late arrival at 150 is dropped after the sample at 200
+        for (value, timestamp) in [(1.0, 100), (2.0, 200), (3.0, 150), (4.0, 300), (5.0, 400), (6.0, 400)] {
+            history.push("cpu", point(value, timestamp), None);
+        }
+        assert_eq!(history.range_bounds("cpu", 200, 500), None);
+        assert_eq!(history.range_bounds("cpu", 201, 500), Some(Some((point(4.0, 300), point(6.0, 400)))));
+        assert_eq!(history.range_bounds("cpu", 401, 500), Some(None));
+        assert_eq!(history.range_bounds("disk", 0, 500), None);
+    }
+
+    #[test]
+    fn test_series_with_stored_samples_covers_only_later_windows() {
+        let mut history = RecentHistory::new(10);
+        history.push("cpu", point(2.0, 200), Some(point(1.0, 100)));
+
+        assert_eq!(history.range_bounds("cpu", 100, 300), None);
+        assert_eq!(history.range_bounds("cpu", 101, 300), Some(Some((point(2.0, 200), point(2.0, 200)))));
+    }
+}
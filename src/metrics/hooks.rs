@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{debug, warn};
+
+use crate::{Result, RaftMetricsError};
+use super::HOOK_FAILURES_TOTAL;
+
+/// Most webhook deliveries in flight at once.
+pub const HOOK_MAX_CONCURRENCY: usize = 8;
+/// Events waiting for a delivery slot; further events are dropped.
+pub const HOOK_QUEUE_CAPACITY: usize = 1024;
+const HOOK_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossingDirection {
+    /// From below the threshold to at or above it.
+    Rising,
+    /// From at or above the threshold to below it.
+    Falling,
+    Both,
+}
+
+fn default_debounce_ms() -> u64 {
+    60_000
+}
+
+/// A webhook fired when a metric's value crosses `threshold` at ingest time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// Metric name, or a name prefix ending in `*`.
+    pub metric: String,
+    pub threshold: f64,
+    pub direction: CrossingDirection,
+    pub url: String,
+    /// Minimum time between two firings for the same metric.
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl HookConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.metric.is_empty() || self.metric == "*" {
+            return Err(RaftMetricsError::InvalidRequest("hook metric must name a metric or a non-empty prefix".to_string()));
+        }
+        if !self.threshold.is_finite() {
+            return Err(RaftMetricsError::InvalidRequest("hook threshold must be finite".to_string()));
+        }
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err(RaftMetricsError::InvalidRequest("hook url must be http(s)".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The prefix this hook matches, if it is a prefix hook.
+    pub fn prefix(&self) -> Option<&str> {
+        self.metric.strip_suffix('*')
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self.prefix() {
+            Some(prefix) => name.starts_with(prefix),
+            None => self.metric == name,
+        }
+    }
+
+    fn crossed(&self, old: f64, new: f64) -> bool {
+        let rising = old < self.threshold && new >= self.threshold;
+        let falling = old >= self.threshold && new < self.threshold;
+        match self.direction {
+            CrossingDirection::Rising => rising,
+            CrossingDirection::Falling => falling,
+            CrossingDirection::Both => rising || falling,
+        }
+    }
+}
+
+/// Body POSTed to a hook's URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookEvent {
+    pub hook_id: String,
+    pub metric_name: String,
+    pub old_value: f64,
+    pub new_value: f64,
+    pub timestamp: i64,
+}
+
+/// Hosts hook URLs may point at, from `HOOK_ALLOWED_HOSTS`. Without the
+/// variable any host is allowed.
+#[derive(Debug, Clone, Default)]
+pub struct HookHosts {
+    allowed: Option<Vec<String>>,
+}
+
+impl HookHosts {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `HOOK_ALLOWED_HOSTS`, a comma-separated list of `host` or
+    /// `host:port` entries. An empty value allows no host at all.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let allowed = lookup("HOOK_ALLOWED_HOSTS").map(|hosts| {
+            hosts.split(',').map(|host| host.trim().to_ascii_lowercase()).filter(|host| !host.is_empty()).collect()
+        });
+        Self { allowed }
+    }
+
+    /// Whether a hook may post to `url`. An entry without a port allows
+    /// its host on any port.
+    pub fn permits(&self, url: &str) -> bool {
+        let Some(allowed) = &self.allowed else { return true };
+        let Ok(url) = reqwest::Url::parse(url) else { return false };
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else { return false };
+        let with_port = url.port_or_known_default().map(|port| format!("{}:{}", host, port));
+        allowed.iter().any(|entry| *entry == host || Some(entry) == with_port.as_ref())
+    }
+}
+
+struct Delivery {
+    url: String,
+    event: HookEvent,
+}
+
+/// Configured hooks and their dispatcher. Evaluation is synchronous and
+/// cheap; delivery happens on a background task so the apply loop never
+/// waits on a webhook.
+#[derive(Default)]
+pub struct HookRegistry {
+    hooks: RwLock<HashMap<String, HookConfig>>,
+    last_fired: Mutex<HashMap<(String, String), Instant>>,
+    dispatcher: OnceLock<mpsc::Sender<Delivery>>,
+    allowed_hosts: RwLock<HookHosts>,
+}
+
+impl HookRegistry {
+    /// Limits where hooks may post. Stored hooks outside `hosts` stay
+    /// configured but no longer fire.
+    pub fn set_allowed_hosts(&self, hosts: HookHosts) {
+        for (id, hook) in self.hooks.read().unwrap().iter() {
+            if !hosts.permits(&hook.url) {
+                warn!("Hook '{}' posts to {}, outside HOOK_ALLOWED_HOSTS; it will not fire", id, hook.url);
+            }
+        }
+        *self.allowed_hosts.write().unwrap() = hosts;
+    }
+
+    /// Refuses a hook posting to a host outside `HOOK_ALLOWED_HOSTS`.
+    pub fn check_destination(&self, hook: &HookConfig) -> Result<()> {
+        if !self.allowed_hosts.read().unwrap().permits(&hook.url) {
+            return Err(RaftMetricsError::Forbidden(format!("hook url {} is outside HOOK_ALLOWED_HOSTS", hook.url)));
+        }
+        Ok(())
+    }
+
+    pub fn insert(&self, id: String, hook: HookConfig) {
+        self.last_fired.lock().unwrap().retain(|(hook_id, _), _| *hook_id != id);
+        self.hooks.write().unwrap().insert(id, hook);
+    }
+
+    pub fn get(&self, id: &str) -> Option<HookConfig> {
+        self.hooks.read().unwrap().get(id).cloned()
+    }
+
+    /// Checks a newly applied sample against every hook and queues a
+    /// delivery for each crossing outside its debounce window.
+    pub fn observe(&self, name: &str, old: Option<f64>, new: f64, timestamp: i64) {
+        let Some(old) = old else { return };
+        let hooks = self.hooks.read().unwrap();
+        if hooks.is_empty() {
+            return;
+        }
+
+        for (id, hook) in hooks.iter() {
+            if !hook.matches(name) || !hook.crossed(old, new) {
+                continue;
+            }
+            if !self.allowed_hosts.read().unwrap().permits(&hook.url) {
+                continue;
+            }
+            if !self.claim_slot(id, name, Duration::from_millis(hook.debounce_ms)) {
+                debug!("Hook '{}' debounced for '{}'", id, name);
+                continue;
+            }
+
+            let delivery = Delivery {
+                url: hook.url.clone(),
+                event: HookEvent {
+                    hook_id: id.clone(),
+                    metric_name: name.to_string(),
+                    old_value: old,
+                    new_value: new,
+                    timestamp,
+                },
+            };
+            if self.dispatcher().try_send(delivery).is_err() {
+                warn!("Hook queue full, dropping event for hook '{}'", id);
+                HOOK_FAILURES_TOTAL.with_label_values(&[id]).inc();
+            }
+        }
+    }
+
+    /// Records a firing unless one happened within `debounce`.
+    fn claim_slot(&self, id: &str, name: &str, debounce: Duration) -> bool {
+        let mut last_fired = self.last_fired.lock().unwrap();
+        let key = (id.to_string(), name.to_string());
+        let now = Instant::now();
+        match last_fired.get(&key) {
+            Some(at) if now.duration_since(*at) < debounce => false,
+            _ => {
+                last_fired.insert(key, now);
+                true
+            }
+        }
+    }
+
+    fn dispatcher(&self) -> &mpsc::Sender<Delivery> {
+        self.dispatcher.get_or_init(|| {
+            let (tx, rx) = mpsc::channel(HOOK_QUEUE_CAPACITY);
+            tokio::spawn(run_dispatcher(rx));
+            tx
+        })
+    }
+}
+
+async fn run_dispatcher(mut deliveries: mpsc::Receiver<Delivery>) {
+    let client = reqwest::Client::builder()
+        .timeout(HOOK_REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let slots = Arc::new(Semaphore::new(HOOK_MAX_CONCURRENCY));
+
+    while let Some(delivery) = deliveries.recv().await {
+        let Ok(permit) = slots.clone().acquire_owned().await else { break };
+        let client = client.clone();
+        tokio::spawn(async move {
+            deliver(&client, &delivery).await;
+            drop(permit);
+        });
+    }
+}
+
+/// POSTs the event, retrying once before counting it as failed.
+async fn deliver(client: &reqwest::Client, delivery: &Delivery) {
+    for attempt in 1..=2 {
+        match client.post(&delivery.url).json(&delivery.event).send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => warn!(
+                "Hook '{}' got {} from {} (attempt {})",
+                delivery.event.hook_id, response.status(), delivery.url, attempt
+            ),
+            Err(e) => warn!(
+                "Hook '{}' failed to reach {} (attempt {}): {}",
+                delivery.event.hook_id, delivery.url, attempt, e
+            ),
+        }
+    }
+    HOOK_FAILURES_TOTAL.with_label_values(&[&delivery.event.hook_id]).inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricsRegistry;
+    use axum::{http::StatusCode, routing::post, Json, Router};
+
+    /// Serves a webhook that records each event and answers with `status`.
+    async fn spawn_receiver(status: StatusCode) -> (String, Arc<Mutex<Vec<HookEvent>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let router = Router::new().route(
+            "/hook",
+            post(move |Json(event): Json<HookEvent>| {
+                let sink = sink.clone();
+                async move {
+                    sink.lock().unwrap().push(event);
+                    status
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (format!("http://{}/hook", addr), received)
+    }
+
+    async fn wait_for(received: &Mutex<Vec<HookEvent>>, count: usize) {
+        for _ in 0..100 {
+            if received.lock().unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crossing_fires_once_within_debounce() {
+        let (url, received) = spawn_receiver(StatusCode::OK).await;
+        let registry = MetricsRegistry::new().unwrap();
+        registry.put_hook("deploy-flip", HookConfig {
+            metric: "deploy_*".to_string(),
+            threshold: 1.0,
+            direction: CrossingDirection::Rising,
+            url,
+            debounce_ms: 10_000,
+        }).await.unwrap();
+
+        registry.record_metric_with_timestamp("deploy_api", 0.0, 100).await.unwrap();
+        for i in 0..10 {
+            let value = if i % 2 == 0 { 1.0 } else { 0.0 };
+            registry.record_metric_with_timestamp("deploy_api", value, 101 + i).await.unwrap();
+        }
+        registry.record_metric_with_timestamp("unrelated", 5.0, 100).await.unwrap();
+        registry.record_metric_with_timestamp("unrelated", 0.0, 101).await.unwrap();
+
+        wait_for(&received, 1).await;
+        // Give any extra deliveries a chance to show up
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0], HookEvent {
+            hook_id: "deploy-flip".to_string(),
+            metric_name: "deploy_api".to_string(),
+            old_value: 0.0,
+            new_value: 1.0,
+            timestamp: 101,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried_once_then_counted() {
+        let (url, received) = spawn_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let registry = MetricsRegistry::new().unwrap();
+        registry.put_hook("flaky", HookConfig {
+            metric: "queue_depth".to_string(),
+            threshold: 100.0,
+            direction: CrossingDirection::Both,
+            url,
+            debounce_ms: 0,
+        }).await.unwrap();
+        let failures_before = HOOK_FAILURES_TOTAL.with_label_values(&["flaky"]).get();
+
+        registry.record_metric_with_timestamp("queue_depth", 150.0, 100).await.unwrap();
+        registry.record_metric_with_timestamp("queue_depth", 50.0, 101).await.unwrap();
+
+        wait_for(&received, 2).await;
+        for _ in 0..100 {
+            if HOOK_FAILURES_TOTAL.with_label_values(&["flaky"]).get() > failures_before {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(received.lock().unwrap().len(), 2);
+        assert_eq!(HOOK_FAILURES_TOTAL.with_label_values(&["flaky"]).get(), failures_before + 1);
+    }
+
+    #[tokio::test]
+    async fn test_hooks_only_post_to_allowed_hosts() {
+        let hosts = HookHosts::from_lookup(|_| Some("hooks.internal, 127.0.0.1:9000,".to_string()));
+        assert!(hosts.permits("https://HOOKS.internal/alert"));
+        assert!(hosts.permits("http://hooks.internal:8080/alert"));
+        assert!(hosts.permits("http://127.0.0.1:9000/alert"));
+        assert!(!hosts.permits("http://127.0.0.1:9001/alert"));
+        assert!(!hosts.permits("http://169.254.169.254/latest/meta-data"));
+        assert!(HookHosts::default().permits("http://anywhere"));
+
+        let registry = MetricsRegistry::new().unwrap().with_hook_hosts(hosts);
+        let hook = HookConfig {
+            metric: "cpu".to_string(),
+            threshold: 1.0,
+            direction: CrossingDirection::Rising,
+            url: "http://169.254.169.254/latest/meta-data".to_string(),
+            debounce_ms: 0,
+        };
+        assert!(matches!(registry.put_hook("metadata", hook.clone()).await, Err(RaftMetricsError::Forbidden(_))));
+        assert!(registry.get_hook("metadata").is_none());
+        let allowed = HookConfig { url: "https://hooks.internal/alert".to_string(), ..hook };
+        registry.put_hook("alert", allowed).await.unwrap();
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_config() {
+        let hook = HookConfig {
+            metric: "cpu".to_string(),
+            threshold: 1.0,
+            direction: CrossingDirection::Rising,
+            url: "ftp://example".to_string(),
+            debounce_ms: 0,
+        };
+        assert!(hook.validate().is_err());
+        assert!(HookConfig { url: "http://example".to_string(), ..hook.clone() }.validate().is_ok());
+        assert!(HookConfig { metric: "*".to_string(), url: "http://example".to_string(), ..hook }.validate().is_err());
+    }
+}
@@ -0,0 +1,620 @@
+use duckdb::{params, Connection, OptionalExt, Statement, Transaction};
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::{metrics::{storage::canonical_labels, Labels}, Result, RaftMetricsError};
+
+/// A single versioned schema step. Steps are applied in ascending `version`
+/// order, each inside its own transaction together with the version bump.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> duckdb::Result<()>,
+}
+
+/// Ordered list of every migration this binary knows about.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create metrics and metric_aggregates tables",
+        up: v1_initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "index metrics by name and timestamp",
+        up: v2_metrics_name_timestamp_index,
+    },
+    Migration {
+        version: 3,
+        description: "create metric_tombstones table",
+        up: v3_metric_tombstones,
+    },
+    Migration {
+        version: 4,
+        description: "create raft_applied_index table",
+        up: v4_raft_applied_index,
+    },
+    Migration {
+        version: 5,
+        description: "add apply sequence to metrics",
+        up: v5_metrics_seq,
+    },
+    Migration {
+        version: 6,
+        description: "create metric_hooks table",
+        up: v6_metric_hooks,
+    },
+    Migration {
+        version: 7,
+        description: "create metric_labels table",
+        up: v7_metric_labels,
+    },
+    Migration {
+        version: 8,
+        description: "add first_seen and last_seen to metric_aggregates",
+        up: v8_aggregate_seen_times,
+    },
+    Migration {
+        version: 9,
+        description: "create raft_log and raft_hard_state tables",
+        up: v9_raft_log,
+    },
+    Migration {
+        version: 10,
+        description: "add m2 to metric_aggregates and create quarantined_samples table",
+        up: v10_outlier_screening,
+    },
+    Migration {
+        version: 11,
+        description: "create metric_transforms table",
+        up: v11_metric_transforms,
+    },
+    Migration {
+        version: 12,
+        description: "create metric_states table",
+        up: v12_metric_states,
+    },
+    Migration {
+        version: 13,
+        description: "create metric_histograms table",
+        up: v13_metric_histograms,
+    },
+    Migration {
+        version: 14,
+        description: "move sample labels into the series dictionary",
+        up: v14_series_dictionary,
+    },
+    Migration {
+        version: 15,
+        description: "create applied_keys table",
+        up: v15_applied_keys,
+    },
+    Migration {
+        version: 16,
+        description: "add weight to quarantined_samples",
+        up: v16_quarantine_weight,
+    },
+    Migration {
+        version: 17,
+        description: "add each sample's contribution to its aggregate to metrics",
+        up: v17_sample_contributions,
+    },
+    Migration {
+        version: 18,
+        description: "add applied_seq to raft_applied_index",
+        up: v18_applied_seq,
+    },
+];
+
+fn v1_initial_schema(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metrics (
+            name VARCHAR NOT NULL,
+            value DOUBLE NOT NULL,
+            timestamp BIGINT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS metric_aggregates (
+            name VARCHAR NOT NULL,
+            count UBIGINT NOT NULL,
+            sum DOUBLE NOT NULL,
+            average DOUBLE NOT NULL,
+            min DOUBLE NOT NULL,
+            max DOUBLE NOT NULL
+        );",
+    )
+}
+
+fn v2_metrics_name_timestamp_index(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch("CREATE INDEX IF NOT EXISTS idx_metrics_name_ts ON metrics (name, timestamp);")
+}
+
+fn v3_metric_tombstones(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metric_tombstones (
+            name VARCHAR NOT NULL,
+            deleted_at BIGINT NOT NULL
+        );",
+    )
+}
+
+fn v4_raft_applied_index(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch("CREATE TABLE IF NOT EXISTS raft_applied_index (applied_index UBIGINT NOT NULL);")
+}
+
+fn v5_metrics_seq(tx: &Transaction) -> duckdb::Result<()> {
+    // DuckDB cannot alter a table that has an index on it
+    tx.execute_batch(
+        "DROP INDEX IF EXISTS idx_metrics_name_ts;
+        ALTER TABLE metrics ADD COLUMN seq UBIGINT DEFAULT 0;
+        CREATE INDEX IF NOT EXISTS idx_metrics_name_ts ON metrics (name, timestamp);",
+    )
+}
+
+fn v6_metric_hooks(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metric_hooks (
+            id VARCHAR NOT NULL,
+            config VARCHAR NOT NULL
+        );",
+    )
+}
+
+/// One row per label of a sample, keyed by the sample's metric and seq.
+fn v7_metric_labels(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metric_labels (
+            name VARCHAR NOT NULL,
+            seq UBIGINT NOT NULL,
+            key VARCHAR NOT NULL,
+            value VARCHAR NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_metric_labels_name_key ON metric_labels (name, key);",
+    )
+}
+
+/// Existing metrics take their earliest and latest sample timestamps, the
+/// closest record of when they were written.
+fn v8_aggregate_seen_times(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE metric_aggregates ADD COLUMN first_seen BIGINT DEFAULT 0;
+        ALTER TABLE metric_aggregates ADD COLUMN last_seen BIGINT DEFAULT 0;
+        UPDATE metric_aggregates SET
+            first_seen = COALESCE((SELECT MIN(m.timestamp) FROM metrics m WHERE m.name = metric_aggregates.name), 0),
+            last_seen = COALESCE((SELECT MAX(m.timestamp) FROM metrics m WHERE m.name = metric_aggregates.name), 0);",
+    )
+}
+
+fn v9_raft_log(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS raft_log (
+            idx UBIGINT NOT NULL,
+            entry BLOB NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_raft_log_idx ON raft_log (idx);
+        CREATE TABLE IF NOT EXISTS raft_hard_state (state BLOB NOT NULL);",
+    )
+}
+
+/// Existing metrics take the spread of their stored samples, the closest
+/// record of what they were aggregated from.
+fn v10_outlier_screening(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE metric_aggregates ADD COLUMN m2 DOUBLE DEFAULT 0;
+        UPDATE metric_aggregates SET
+            m2 = COALESCE((SELECT VAR_POP(m.value) * COUNT(*) FROM metrics m WHERE m.name = metric_aggregates.name), 0);
+        CREATE TABLE IF NOT EXISTS quarantined_samples (
+            name VARCHAR NOT NULL,
+            value DOUBLE NOT NULL,
+            timestamp BIGINT NOT NULL,
+            seq UBIGINT NOT NULL,
+            labels VARCHAR NOT NULL,
+            reason VARCHAR NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_quarantined_samples_name ON quarantined_samples (name);",
+    )
+}
+
+fn v11_metric_transforms(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metric_transforms (
+            name VARCHAR NOT NULL,
+            scale DOUBLE NOT NULL,
+            value_offset DOUBLE NOT NULL
+        );",
+    )
+}
+
+fn v12_metric_states(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metric_states (
+            name VARCHAR NOT NULL,
+            config VARCHAR NOT NULL
+        );",
+    )
+}
+
+fn v13_metric_histograms(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS metric_histograms (
+            name VARCHAR NOT NULL,
+            config VARCHAR NOT NULL
+        );",
+    )
+}
+
+/// Each distinct label set of a metric becomes one `series` row, stored
+/// once in `series_labels` rather than once per sample, and samples point
+/// at it by `series_id`. A metric's unlabeled samples share a series with
+/// the empty label set.
+fn v14_series_dictionary(tx: &Transaction) -> duckdb::Result<()> {
+    // DuckDB cannot alter a table that has an index on it
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS series (
+            series_id UBIGINT NOT NULL,
+            name VARCHAR NOT NULL,
+            labels VARCHAR NOT NULL,
+            first_seen BIGINT NOT NULL,
+            last_seen BIGINT NOT NULL,
+            samples UBIGINT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_series_id ON series (series_id);
+        CREATE INDEX IF NOT EXISTS idx_series_name_labels ON series (name, labels);
+        CREATE TABLE IF NOT EXISTS series_labels (
+            series_id UBIGINT NOT NULL,
+            key VARCHAR NOT NULL,
+            value VARCHAR NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_series_labels_id_key ON series_labels (series_id, key);
+        CREATE TEMPORARY TABLE sample_series (name VARCHAR NOT NULL, seq UBIGINT NOT NULL, series_id UBIGINT NOT NULL);
+        DROP INDEX IF EXISTS idx_metrics_name_ts;
+        ALTER TABLE metrics ADD COLUMN series_id UBIGINT;",
+    )?;
+
+    {
+        let mut interner = SeriesInterner {
+            ids: HashMap::new(),
+            insert_series: tx.prepare("INSERT INTO series (series_id, name, labels, first_seen, last_seen, samples) VALUES (?, ?, ?, 0, 0, 0)")?,
+            insert_label: tx.prepare("INSERT INTO series_labels (series_id, key, value) VALUES (?, ?, ?)")?,
+            assign: tx.prepare("INSERT INTO sample_series (name, seq, series_id) VALUES (?, ?, ?)")?,
+        };
+        let mut stmt = tx.prepare("SELECT name, seq, key, value FROM metric_labels ORDER BY name, seq")?;
+        let mut rows = stmt.query([])?;
+        let mut sample: Option<(String, u64, Labels)> = None;
+        while let Some(row) = rows.next()? {
+            let (name, seq): (String, u64) = (row.get(0)?, row.get(1)?);
+            if sample.as_ref().is_some_and(|(n, s, _)| (n, *s) != (&name, seq)) {
+                interner.assign(sample.take().unwrap())?;
+            }
+            sample.get_or_insert_with(|| (name, seq, Labels::new())).2.insert(row.get(2)?, row.get(3)?);
+        }
+        if let Some(sample) = sample {
+            interner.assign(sample)?;
+        }
+    }
+
+    tx.execute_batch(
+        "UPDATE metrics SET series_id = s.series_id FROM sample_series s WHERE s.name = metrics.name AND s.seq = metrics.seq;
+        INSERT INTO series (series_id, name, labels, first_seen, last_seen, samples)
+            SELECT (SELECT COALESCE(MAX(series_id), 0) FROM series) + ROW_NUMBER() OVER (ORDER BY name), name, '{}', 0, 0, 0
+            FROM (SELECT DISTINCT name FROM metrics WHERE series_id IS NULL);
+        UPDATE metrics SET series_id = s.series_id FROM series s
+            WHERE metrics.series_id IS NULL AND s.name = metrics.name AND s.labels = '{}';
+        UPDATE series SET first_seen = c.first_seen, last_seen = c.last_seen, samples = c.samples
+            FROM (SELECT series_id, MIN(timestamp) AS first_seen, MAX(timestamp) AS last_seen, COUNT(*) AS samples
+                  FROM metrics GROUP BY series_id) c
+            WHERE c.series_id = series.series_id;
+        DROP TABLE sample_series;
+        DROP TABLE metric_labels;
+        CREATE INDEX IF NOT EXISTS idx_metrics_name_ts ON metrics (name, timestamp);",
+    )
+}
+
+/// Series created while migrating `metric_labels` rows, by metric name and
+/// canonical label set.
+struct SeriesInterner<'a> {
+    ids: HashMap<(String, String), u64>,
+    insert_series: Statement<'a>,
+    insert_label: Statement<'a>,
+    assign: Statement<'a>,
+}
+
+impl SeriesInterner<'_> {
+    /// Points the sample `seq` of `name` at the series of its labels.
+    fn assign(&mut self, (name, seq, labels): (String, u64, Labels)) -> duckdb::Result<()> {
+        let key = (name, canonical_labels(&labels));
+        let id = match self.ids.get(&key) {
+            Some(&id) => id,
+            None => {
+                let id = self.ids.len() as u64 + 1;
+                self.insert_series.execute(params![id, key.0, key.1])?;
+                for (label, value) in &labels {
+                    self.insert_label.execute(params![id, label, value])?;
+                }
+                self.ids.insert(key.clone(), id);
+                id
+            }
+        };
+        self.assign.execute(params![key.0, seq, id])?;
+        Ok(())
+    }
+}
+
+fn v15_applied_keys(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS applied_keys (
+            key VARCHAR NOT NULL,
+            expires_at BIGINT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_applied_keys_key ON applied_keys (key);",
+    )
+}
+
+fn v16_quarantine_weight(tx: &Transaction) -> duckdb::Result<()> {
+    // DuckDB cannot alter a table that has an index on it
+    tx.execute_batch(
+        "DROP INDEX IF EXISTS idx_quarantined_samples_name;
+        ALTER TABLE quarantined_samples ADD COLUMN weight UBIGINT DEFAULT 1;
+        CREATE INDEX IF NOT EXISTS idx_quarantined_samples_name ON quarantined_samples (name);",
+    )
+}
+
+fn v17_sample_contributions(tx: &Transaction) -> duckdb::Result<()> {
+    // Samples stored so far each counted once
+    tx.execute_batch(
+        "DROP INDEX IF EXISTS idx_metrics_name_ts;
+        ALTER TABLE metrics ADD COLUMN sample_count UBIGINT DEFAULT 1;
+        ALTER TABLE metrics ADD COLUMN sample_sum DOUBLE;
+        ALTER TABLE metrics ADD COLUMN sample_min DOUBLE;
+        ALTER TABLE metrics ADD COLUMN sample_max DOUBLE;
+        ALTER TABLE metrics ADD COLUMN sample_m2 DOUBLE DEFAULT 0;
+        UPDATE metrics SET sample_sum = value, sample_min = value, sample_max = value;
+        CREATE INDEX IF NOT EXISTS idx_metrics_name_ts ON metrics (name, timestamp);",
+    )
+}
+
+fn v18_applied_seq(tx: &Transaction) -> duckdb::Result<()> {
+    tx.execute_batch("ALTER TABLE raft_applied_index ADD COLUMN applied_seq UBIGINT;")
+}
+
+/// Highest schema version known to this binary.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Returns the schema version recorded in the database, or 0 for a fresh one.
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL, applied_at BIGINT NOT NULL);")?;
+    stored_version(conn)
+}
+
+/// Like [`current_version`], but without creating the version table, so it
+/// can read a database opened read-only.
+pub fn stored_version(conn: &Connection) -> Result<u32> {
+    let tracked: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM information_schema.tables WHERE table_name = 'schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if tracked == 0 {
+        return Ok(0);
+    }
+    let version: Option<u32> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))
+        .optional()?
+        .flatten();
+    Ok(version.unwrap_or(0))
+}
+
+/// Applies every pending migration and returns the resulting schema version.
+///
+/// Refuses to touch a database whose recorded version is newer than
+/// [`latest_version`], so an older binary cannot run against a newer schema.
+pub fn run_migrations(conn: &mut Connection) -> Result<u32> {
+    run_migrations_with(conn, MIGRATIONS)
+}
+
+pub(crate) fn run_migrations_with(conn: &mut Connection, migrations: &[Migration]) -> Result<u32> {
+    let known = migrations.last().map_or(0, |m| m.version);
+    let on_disk = current_version(conn)?;
+
+    if on_disk > known {
+        return Err(RaftMetricsError::Migration(format!(
+            "database schema version {} is newer than the latest supported version {}",
+            on_disk, known
+        )));
+    }
+
+    let mut version = on_disk;
+    for migration in migrations.iter().filter(|m| m.version > on_disk) {
+        info!("Applying schema migration v{}: {}", migration.version, migration.description);
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).map_err(|e| {
+            RaftMetricsError::Migration(format!(
+                "migration v{} ({}) failed: {}",
+                migration.version, migration.description, e
+            ))
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES (?, ?)",
+            duckdb::params![migration.version, chrono::Utc::now().timestamp()],
+        )?;
+        tx.commit()?;
+
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.duckdb", name, uuid::Uuid::new_v4()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn table_columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT column_name FROM information_schema.columns WHERE table_name = ? ORDER BY ordinal_position")
+            .unwrap();
+        let rows = stmt.query_map([table], |row| row.get(0)).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    /// Builds a database file as a v1 binary would have left it.
+    fn simulate_v1_database(path: &std::path::Path) {
+        let mut conn = Connection::open(path).unwrap();
+        run_migrations_with(&mut conn, &MIGRATIONS[..1]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO metrics VALUES ('cpu', 1.5, 100), ('cpu', 2.5, 200);
+             INSERT INTO metric_aggregates VALUES ('cpu', 2, 4.0, 2.0, 1.5, 2.5);",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert_eq!(table_columns(&conn, "metrics"), vec!["name", "value", "timestamp", "seq", "series_id"]);
+
+        // Re-running is a no-op
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        let applied: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_upgrade_from_v1_preserves_data() {
+        let path = temp_db_path("migrate-v1");
+        simulate_v1_database(&path);
+
+        let mut conn = Connection::open(&path).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+
+        let indexes: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM duckdb_indexes() WHERE index_name = 'idx_metrics_name_ts'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(indexes, 1);
+
+        let (count, sum): (i64, f64) = conn
+            .query_row("SELECT COUNT(*), SUM(value) FROM metrics WHERE name = 'cpu'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, sum), (2, 4.0));
+        let (counted, summed): (u64, f64) = conn
+            .query_row("SELECT SUM(sample_count)::UBIGINT, SUM(sample_sum) FROM metrics WHERE name = 'cpu'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((counted, summed), (2, 4.0));
+        let (agg_count, first_seen, last_seen): (u64, i64, i64) = conn
+            .query_row("SELECT count, first_seen, last_seen FROM metric_aggregates WHERE name = 'cpu'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((agg_count, first_seen, last_seen), (2, 100, 200));
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_labels_move_into_the_series_dictionary() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let before_series = MIGRATIONS.iter().position(|m| m.version == 14).unwrap();
+        run_migrations_with(&mut conn, &MIGRATIONS[..before_series]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO metrics (name, value, timestamp, seq) VALUES
+                ('cpu', 1.0, 100, 1), ('cpu', 2.0, 200, 2), ('cpu', 3.0, 300, 3), ('cpu', 4.0, 400, 4), ('mem', 5.0, 500, 5);
+             INSERT INTO metric_labels (name, seq, key, value) VALUES
+                ('cpu', 1, 'host', 'a'), ('cpu', 1, 'dc', 'east'),
+                ('cpu', 2, 'dc', 'east'), ('cpu', 2, 'host', 'a'),
+                ('cpu', 3, 'host', 'b');",
+        )
+        .unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let mut stmt = conn
+            .prepare("SELECT name, labels, first_seen, last_seen, samples FROM series ORDER BY name, labels")
+            .unwrap();
+        let series: Vec<(String, String, i64, i64, u64)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(series, vec![
+            ("cpu".to_string(), r#"{"dc":"east","host":"a"}"#.to_string(), 100, 200, 2),
+            ("cpu".to_string(), r#"{"host":"b"}"#.to_string(), 300, 300, 1),
+            ("cpu".to_string(), "{}".to_string(), 400, 400, 1),
+            ("mem".to_string(), "{}".to_string(), 500, 500, 1),
+        ]);
+
+        // Three label rows for the four cpu samples' five, and none unassigned
+        let (labels, unassigned): (i64, i64) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM series_labels), (SELECT COUNT(*) FROM metrics WHERE series_id IS NULL)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((labels, unassigned), (3, 0));
+        let hosts: Vec<(u64, String)> = conn
+            .prepare(
+                "SELECT m.seq, l.value FROM metrics m JOIN series_labels l ON l.series_id = m.series_id AND l.key = 'host' ORDER BY m.seq",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(hosts, vec![(1, "a".to_string()), (2, "a".to_string()), (3, "b".to_string())]);
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES (?, 0)",
+            [latest_version() + 1],
+        )
+        .unwrap();
+
+        match run_migrations(&mut conn) {
+            Err(RaftMetricsError::Migration(msg)) => assert!(msg.contains("newer")),
+            other => panic!("expected migration error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_failed_migration_is_not_half_applied() {
+        fn broken(tx: &Transaction) -> duckdb::Result<()> {
+            tx.execute_batch("ALTER TABLE metrics ADD COLUMN labels VARCHAR;")?;
+            tx.execute_batch("SELECT * FROM table_that_does_not_exist;")
+        }
+        let migrations = [
+            Migration { version: 1, description: "initial", up: v1_initial_schema },
+            Migration { version: 2, description: "broken", up: broken },
+        ];
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        let err = run_migrations_with(&mut conn, &migrations).unwrap_err();
+        assert!(err.to_string().contains("v2"));
+
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert_eq!(table_columns(&conn, "metrics"), vec!["name", "value", "timestamp"]);
+    }
+}
@@ -1,9 +1,49 @@
-use std::collections::HashMap;
-use std::sync::Arc;
-use prometheus::{Registry, Gauge, HistogramVec, HistogramOpts, IntCounter};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, OnceLock};
+use prometheus::{Registry, Gauge, Histogram, HistogramVec, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts};
 use lazy_static::lazy_static;
-use tokio::sync::RwLock as AsyncRwLock;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, RwLock as AsyncRwLock};
+use tracing::{debug, info, warn};
 use crate::Result;
+use crate::config::{units, MemoryBudget};
+
+pub mod codec;
+pub mod compaction;
+pub mod dedup;
+pub mod histograms;
+pub mod hooks;
+mod history;
+#[cfg(feature = "duckdb-storage")]
+pub mod migrations;
+pub mod namespace;
+pub mod outliers;
+pub mod query;
+pub mod quotas;
+mod residency;
+pub mod routing;
+pub mod sampling;
+pub mod states;
+pub mod storage;
+pub mod transform;
+
+use codec::RaftCodec;
+use compaction::CompactionPolicy;
+use dedup::{DedupPolicy, DedupWindow};
+use histograms::{HistogramMetric, HistogramReport};
+use history::RecentHistory;
+use hooks::{HookConfig, HookHosts, HookRegistry};
+use namespace::{NamespaceTree, TreeShape};
+use outliers::{OutlierBounds, OutlierPolicy};
+use quotas::{TenantQuotas, TenantUsage, UsageReport};
+use residency::Residency;
+use sampling::SampleRates;
+use states::{StateMetric, StateReport};
+#[cfg(feature = "duckdb-storage")]
+use storage::DuckDbBackend;
+use storage::{MemoryBackend, MetricStorageBackend, StagedWrite};
+use transform::{IngestTransform, IngestTransforms};
+pub use storage::{AggregateFn, BucketStats, GroupByQuery, RaftLogState, RangeCursor, RangeQuery, SeriesSummary, NO_LABEL_GROUP};
 
 #[derive(Debug, Clone, Default)]
 pub struct MetricAggregate {
@@ -12,6 +52,134 @@ pub struct MetricAggregate {
     pub average: f64,
     pub min: f64,
     pub max: f64,
+    /// Low-order bits lost from `sum` under [`Summation::Kahan`]. Kept in
+    /// memory only; the store holds the corrected [`Self::total`].
+    pub compensation: f64,
+    /// Sum of squared deviations from `average`, weighted like the count,
+    /// for [`Self::stddev`].
+    pub m2: f64,
+    /// Unix seconds, by the registry's clock, of the first and latest write.
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// How aggregate sums are accumulated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Summation {
+    /// Plain floating-point addition.
+    #[default]
+    Naive,
+    /// Compensated (Kahan-Babuska-Neumaier) summation: slower, but the
+    /// error no longer grows with the number of samples.
+    Kahan,
+}
+
+impl Summation {
+    /// Reads `AGGREGATION_SUMMATION` (`naive` or `kahan`).
+    pub fn from_env() -> Self {
+        match std::env::var("AGGREGATION_SUMMATION").as_deref() {
+            Ok("kahan") => Summation::Kahan,
+            _ => Summation::Naive,
+        }
+    }
+}
+
+/// Default time between flushes under [`AggregateWrite::Async`].
+pub const DEFAULT_AGGREGATE_FLUSH_INTERVAL_SECS: u64 = 5;
+
+/// When aggregate rows reach the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AggregateWrite {
+    /// With every sample, in the same store write.
+    #[default]
+    Sync,
+    /// Every `interval`, from the latest aggregate in memory, so a metric
+    /// written many times in between costs one upsert. A crash loses the
+    /// updates since the last flush; recomputing rebuilds them from the
+    /// samples.
+    Async { interval: std::time::Duration },
+}
+
+impl AggregateWrite {
+    /// Reads `AGGREGATE_WRITE` (`sync` or `async`) and, for `async`,
+    /// `AGGREGATE_FLUSH_INTERVAL_SECS`.
+    pub fn from_env() -> Self {
+        match std::env::var("AGGREGATE_WRITE").as_deref() {
+            Ok("async") => {
+                let interval = units::duration(&|key: &str| std::env::var(key).ok(), "AGGREGATE_FLUSH_INTERVAL_SECS")
+                    .filter(|interval| !interval.is_zero())
+                    .unwrap_or(std::time::Duration::from_secs(DEFAULT_AGGREGATE_FLUSH_INTERVAL_SECS));
+                AggregateWrite::Async { interval }
+            }
+            _ => AggregateWrite::Sync,
+        }
+    }
+}
+
+impl MetricAggregate {
+    /// Folds one sample into the aggregate.
+    pub fn add(&mut self, value: f64, summation: Summation) {
+        self.add_weighted(value, 1, summation);
+    }
+
+    /// Folds in a sample standing for `weight` identical samples, as a kept
+    /// sample of a sampled metric does.
+    pub fn add_weighted(&mut self, value: f64, weight: u64, summation: Summation) {
+        let previous_average = if self.count == 0 { value } else { self.average };
+        self.count += weight;
+        self.add_to_sum(value * weight as f64, summation);
+        self.average = self.total() / self.count as f64;
+        // Welford's update, so the deviation never cancels catastrophically
+        self.m2 += weight as f64 * (value - previous_average) * (value - self.average);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Folds in samples pre-aggregated on ingest, `weight` times over. Count,
+    /// sum, min and max come out as if each sample had been added, and the
+    /// deviations are merged with Chan's formula.
+    pub fn add_summary(&mut self, summary: &SampleSummary, weight: u64, summation: Summation) {
+        if summary.count == 0 {
+            return;
+        }
+        let (count, m2) = (summary.count * weight, summary.m2 * weight as f64);
+        let delta = summary.mean() - if self.count == 0 { summary.mean() } else { self.average };
+        let merged = self.count + count;
+        self.m2 += m2 + delta * delta * (self.count as f64 * count as f64 / merged as f64);
+        self.count = merged;
+        self.add_to_sum(summary.sum * weight as f64, summation);
+        self.average = self.total() / self.count as f64;
+        self.min = self.min.min(summary.min);
+        self.max = self.max.max(summary.max);
+    }
+
+    fn add_to_sum(&mut self, added: f64, summation: Summation) {
+        match summation {
+            Summation::Naive => self.sum += added,
+            Summation::Kahan => {
+                let sum = self.sum + added;
+                if self.sum.abs() >= added.abs() {
+                    self.compensation += (self.sum - sum) + added;
+                } else {
+                    self.compensation += (added - sum) + self.sum;
+                }
+                self.sum = sum;
+            }
+        }
+    }
+
+    /// Best estimate of the sum of all samples.
+    pub fn total(&self) -> f64 {
+        self.sum + self.compensation
+    }
+
+    /// Population standard deviation of the samples.
+    pub fn stddev(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            count => (self.m2.max(0.0) / count as f64).sqrt(),
+        }
+    }
 }
 
 lazy_static! {
@@ -25,66 +193,4047 @@ lazy_static! {
             HistogramOpts::new("request_duration_seconds", "Request duration in seconds"),
             &["endpoint"]
         ).unwrap();
+    pub static ref REQUEST_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_requests_total", "Requests handled, by endpoint and status class"),
+            &["endpoint", "status"]
+        ).unwrap();
+    pub static ref FORWARD_FAILURES_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_forward_failures_total", "Failed control to worker forwarding calls"),
+            &["worker"]
+        ).unwrap();
+    pub static ref RAFT_BATCH_SIZE: Histogram =
+        Histogram::with_opts(
+            HistogramOpts::new("raftmetrics_raft_batch_size", "Operations per proposed raft entry")
+                .buckets(vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0, 512.0])
+        ).unwrap();
+    pub static ref RAFT_PROPOSAL_DURATION: Histogram =
+        Histogram::with_opts(
+            HistogramOpts::new("raftmetrics_raft_proposal_duration_seconds", "Time taken to append a proposed entry to the local raft log")
+                .buckets(prometheus::exponential_buckets(0.00001, 4.0, 10).unwrap())
+        ).unwrap();
+    pub static ref RAFT_PROPOSALS_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_raft_proposals_total", "Entries proposed to raft, by outcome"),
+            &["outcome"]
+        ).unwrap();
+    pub static ref RAFT_FORCED_STEPDOWNS_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_forced_stepdowns_total", "Times a raft leader stepped down because it could not apply committed entries").unwrap();
+    pub static ref JOURNAL_REPLAYED_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_journal_replayed_total", "Journaled operations proposed again and applied after a restart").unwrap();
+    pub static ref KEYED_SKIPPED_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_keyed_skipped_total", "Keyed operations skipped as already applied").unwrap();
+    pub static ref FORWARD_IN_FLIGHT: IntGaugeVec =
+        IntGaugeVec::new(
+            Opts::new("raftmetrics_forward_in_flight", "Forwarded requests currently awaiting a worker, by pool"),
+            &["pool"]
+        ).unwrap();
+    pub static ref HOOK_FAILURES_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_hook_failures_total", "Ingest hook events dropped or not delivered after a retry"),
+            &["hook"]
+        ).unwrap();
+    pub static ref RESIDENT_SERIES: IntGauge =
+        IntGauge::new("raftmetrics_resident_series", "Series currently held in the in-memory maps").unwrap();
+    pub static ref EVICTIONS_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_evictions_total", "Series evicted from memory to stay within the memory budget").unwrap();
+    pub static ref FALLBACK_READS_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_fallback_reads_total", "Reads of non-resident series served from the store").unwrap();
+    pub static ref DEDUPLICATED_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_deduplicated_total", "Samples repeating the latest value that were not stored").unwrap();
+    pub static ref COMPACTED_SAMPLES_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_compacted_samples_total", "Old samples removed by compaction").unwrap();
+    pub static ref QUARANTINED_SAMPLES_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_quarantined_samples_total", "Samples held out of their metric as outliers").unwrap();
+    pub static ref SAMPLED_OUT_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_sampled_out_total", "Samples dropped by a metric's sample rate").unwrap();
+    pub static ref DOWNSAMPLED_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_downsampled_samples_total", "Samples held back from raft by a downsample rule, by mode"),
+            &["mode"]
+        ).unwrap();
+    pub static ref CARDINALITY_REJECTED_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_cardinality_rejected_total", "Writes refused for creating a metric beyond MAX_DISTINCT_METRICS").unwrap();
+    pub static ref QUOTA_REJECTED_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_quota_rejected_total", "Writes a worker refused for a tenant's quota, by tenant and quota (series or storage)"),
+            &["tenant", "quota"]
+        ).unwrap();
+    pub static ref PEER_ADDRESS_CHANGES_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_peer_address_changes_total", "Times a worker host name resolved to new addresses"),
+            &["host"]
+        ).unwrap();
+    pub static ref PUSHGATEWAY_FAILURES_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_pushgateway_failures_total", "Pushes to PUSHGATEWAY_URL that failed").unwrap();
+    pub static ref FORWARDING_SAMPLES_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_forwarding_samples_total", "Samples or summaries forwarded to a downstream by a FORWARD_RULES rule, by destination"),
+            &["destination"]
+        ).unwrap();
+    pub static ref FORWARDING_DROPPED_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_forwarding_dropped_total", "Samples not forwarded, by destination and reason (buffer_full, send_failed or rejected)"),
+            &["destination", "reason"]
+        ).unwrap();
+    pub static ref FORWARDING_RETRIES_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_forwarding_retries_total", "Forwarded batches sent again after a failure, by destination"),
+            &["destination"]
+        ).unwrap();
+    pub static ref FORWARDING_BUFFERED: IntGaugeVec =
+        IntGaugeVec::new(
+            Opts::new("raftmetrics_forwarding_buffered_samples", "Samples waiting to be forwarded, by destination"),
+            &["destination"]
+        ).unwrap();
+    pub static ref FORWARDING_DESTINATION_UP: IntGaugeVec =
+        IntGaugeVec::new(
+            Opts::new("raftmetrics_forwarding_destination_up", "1 while a forwarding destination took its last batch, 0 after one was dropped"),
+            &["destination"]
+        ).unwrap();
+    pub static ref STORAGE_OPERATIONS_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_storage_operations_total", "Committed operations applied to the store, by operation and result"),
+            &["operation", "result"]
+        ).unwrap();
+    pub static ref RAFT_COMMIT_INDEX: IntGauge =
+        IntGauge::new("raftmetrics_raft_commit_index", "Highest raft log index known to be committed").unwrap();
+    pub static ref RAFT_APPLIED_INDEX: IntGauge =
+        IntGauge::new("raftmetrics_raft_applied_index", "Highest raft log index applied to the store").unwrap();
+    pub static ref RAFT_APPLY_LAG_ENTRIES: IntGauge =
+        IntGauge::new("raftmetrics_raft_apply_lag_entries", "Committed raft entries not yet applied").unwrap();
+    pub static ref RAFT_LAST_TICK_TIMESTAMP: Gauge =
+        Gauge::new("raftmetrics_raft_last_tick_timestamp_seconds", "Unix time of the raft loop's last tick").unwrap();
+    pub static ref RAFT_LAST_APPLY_TIMESTAMP: Gauge =
+        Gauge::new("raftmetrics_raft_last_apply_timestamp_seconds", "Unix time of the last applied raft entry").unwrap();
+    pub static ref PROPOSAL_QUEUE_DEPTH: IntGaugeVec =
+        IntGaugeVec::new(
+            Opts::new("raftmetrics_proposal_queue_depth", "Proposals waiting in the channel to the raft loop, by worker"),
+            &["worker"]
+        ).unwrap();
+    pub static ref PROPOSAL_QUEUE_HIGH_WATER: IntGaugeVec =
+        IntGaugeVec::new(
+            Opts::new("raftmetrics_proposal_queue_high_water", "Most proposals seen waiting in the channel to the raft loop, by worker"),
+            &["worker"]
+        ).unwrap();
+}
+
+static COLLECTORS_REGISTERED: OnceLock<std::result::Result<(), String>> = OnceLock::new();
+
+/// Registers the process-wide collectors with [`REGISTRY`] exactly once.
+/// A conflict is reported on every call rather than aborting the process.
+pub fn register_collectors() -> Result<()> {
+    COLLECTORS_REGISTERED
+        .get_or_init(|| register_collectors_with(&REGISTRY).map_err(|e| e.to_string()))
+        .clone()
+        .map_err(|e| crate::RaftMetricsError::Internal(format!("Failed to register metrics collectors: {}", e)))
+}
+
+fn register_collectors_with(registry: &Registry) -> prometheus::Result<()> {
+    registry.register(Box::new(REQUEST_COUNTER.clone()))?;
+    registry.register(Box::new(ACTIVE_CONNECTIONS.clone()))?;
+    registry.register(Box::new(REQUEST_DURATION.clone()))?;
+    registry.register(Box::new(REQUEST_TOTAL.clone()))?;
+    registry.register(Box::new(FORWARD_FAILURES_TOTAL.clone()))?;
+    registry.register(Box::new(FORWARD_IN_FLIGHT.clone()))?;
+    registry.register(Box::new(RAFT_BATCH_SIZE.clone()))?;
+    registry.register(Box::new(RAFT_PROPOSAL_DURATION.clone()))?;
+    registry.register(Box::new(RAFT_PROPOSALS_TOTAL.clone()))?;
+    registry.register(Box::new(RAFT_FORCED_STEPDOWNS_TOTAL.clone()))?;
+    registry.register(Box::new(JOURNAL_REPLAYED_TOTAL.clone()))?;
+    registry.register(Box::new(KEYED_SKIPPED_TOTAL.clone()))?;
+    registry.register(Box::new(HOOK_FAILURES_TOTAL.clone()))?;
+    registry.register(Box::new(RESIDENT_SERIES.clone()))?;
+    registry.register(Box::new(EVICTIONS_TOTAL.clone()))?;
+    registry.register(Box::new(FALLBACK_READS_TOTAL.clone()))?;
+    registry.register(Box::new(SAMPLED_OUT_TOTAL.clone()))?;
+    registry.register(Box::new(DOWNSAMPLED_TOTAL.clone()))?;
+    registry.register(Box::new(CARDINALITY_REJECTED_TOTAL.clone()))?;
+    registry.register(Box::new(QUOTA_REJECTED_TOTAL.clone()))?;
+    registry.register(Box::new(DEDUPLICATED_TOTAL.clone()))?;
+    registry.register(Box::new(COMPACTED_SAMPLES_TOTAL.clone()))?;
+    registry.register(Box::new(QUARANTINED_SAMPLES_TOTAL.clone()))?;
+    registry.register(Box::new(PEER_ADDRESS_CHANGES_TOTAL.clone()))?;
+    registry.register(Box::new(PUSHGATEWAY_FAILURES_TOTAL.clone()))?;
+    registry.register(Box::new(FORWARDING_SAMPLES_TOTAL.clone()))?;
+    registry.register(Box::new(FORWARDING_DROPPED_TOTAL.clone()))?;
+    registry.register(Box::new(FORWARDING_RETRIES_TOTAL.clone()))?;
+    registry.register(Box::new(FORWARDING_BUFFERED.clone()))?;
+    registry.register(Box::new(FORWARDING_DESTINATION_UP.clone()))?;
+    registry.register(Box::new(STORAGE_OPERATIONS_TOTAL.clone()))?;
+    registry.register(Box::new(RAFT_COMMIT_INDEX.clone()))?;
+    registry.register(Box::new(RAFT_APPLIED_INDEX.clone()))?;
+    registry.register(Box::new(RAFT_APPLY_LAG_ENTRIES.clone()))?;
+    registry.register(Box::new(RAFT_LAST_TICK_TIMESTAMP.clone()))?;
+    registry.register(Box::new(RAFT_LAST_APPLY_TIMESTAMP.clone()))?;
+    registry.register(Box::new(PROPOSAL_QUEUE_DEPTH.clone()))?;
+    registry.register(Box::new(PROPOSAL_QUEUE_HIGH_WATER.clone()))?;
+    routing::register(registry)?;
+    Ok(())
+}
+
+/// Maps a status code to the class label used by [`REQUEST_TOTAL`], e.g. `2xx`.
+pub fn status_class(status: u16) -> String {
+    format!("{}xx", status / 100)
+}
+
+/// Key/value labels attached to a sample, e.g. `region=eu`.
+pub type Labels = BTreeMap<String, String>;
+
+/// Samples of one series folded together before they are proposed, and
+/// recorded as a single sample of the `last` value; see
+/// [`crate::api::downsample`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SampleSummary {
+    /// Value of the latest sample folded in.
+    pub last: f64,
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Sum of squared deviations from the mean, as in [`MetricAggregate`].
+    pub m2: f64,
+}
+
+impl SampleSummary {
+    pub fn new(value: f64) -> Self {
+        Self { last: value, count: 1, sum: value, min: value, max: value, m2: 0.0 }
+    }
+
+    /// Folds in another sample, which becomes `last` when it is `latest`.
+    pub fn add(&mut self, value: f64, latest: bool) {
+        let previous_mean = self.mean();
+        self.count += 1;
+        self.sum += value;
+        self.m2 += (value - previous_mean) * (value - self.mean());
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if latest {
+            self.last = value;
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    /// The summary counted `weight` times over, as a kept sample of a
+    /// sampled metric is.
+    pub fn weighted(self, weight: u64) -> Self {
+        Self { count: self.count * weight, sum: self.sum * weight as f64, m2: self.m2 * weight as f64, ..self }
+    }
+}
+
+/// What the node proposing a sample decided about it from its own config,
+/// through [`MetricsRegistry::admit`]. Carried in the log so every replica
+/// applies the sample alike, whatever its own config says.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Admission {
+    /// How many samples this one stands for under a sample rate; `None`
+    /// for a metric that is not sampled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u64>,
+    /// The window repeats of the latest value are skipped in; `None`
+    /// stores every sample.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup: Option<DedupWindow>,
+    /// The bounds past which the sample is quarantined as an outlier;
+    /// `None` keeps it unscreened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outliers: Option<OutlierBounds>,
+}
+
+impl Admission {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn weight(&self) -> u64 {
+        self.weight.unwrap_or(1)
+    }
+}
+
+/// A state-machine operation. Every replica applies the same sequence of
+/// operations through [`MetricsRegistry::apply_operation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MetricOperation {
+    Record {
+        name: String,
+        value: f64,
+        timestamp: i64,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+        #[serde(default, skip_serializing_if = "Admission::is_default")]
+        admission: Admission,
+    },
+    Delete { name: String, deleted_at: i64 },
+    /// Wipes every metric on the replica.
+    Clear,
+    /// Applies the metric's quarantined samples after all.
+    ApplyQuarantine { name: String },
+    /// Records, resets and deletes applied all-or-nothing; see
+    /// [`check_transaction`].
+    Transaction(Vec<MetricOperation>),
+    /// Zeroes a counter; see [`MetricsRegistry::stage_reset`]. Unless
+    /// `keep_history`, the metric is deleted first, in the same write.
+    Reset { name: String, reset_at: i64, keep_history: bool },
+    /// Records the metric's later samples through `scale` and `offset`;
+    /// see [`transform::IngestTransform`].
+    SetTransform { name: String, scale: f64, offset: f64 },
+    /// Records samples pre-aggregated on ingest as one sample at
+    /// `timestamp`, folding all of them into the aggregate.
+    RecordSummary {
+        name: String,
+        timestamp: i64,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+        summary: SampleSummary,
+        #[serde(default, skip_serializing_if = "Admission::is_default")]
+        admission: Admission,
+    },
+    /// Makes the metric a state metric whose samples index `states`; see
+    /// [`states::StateMetric`].
+    DefineStates {
+        name: String,
+        states: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stale_after_secs: Option<i64>,
+    },
+    /// Counts the metric's samples into buckets bounded by `bounds`; see
+    /// [`histograms::HistogramMetric`]. A different layout is refused
+    /// unless `reset`, which starts the counts over.
+    DefineHistogram {
+        name: String,
+        bounds: Vec<f64>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        reset: bool,
+    },
+    /// Applies `operation` at most once per `key`: a replica that applied
+    /// an operation with the same key proposed less than
+    /// `IDEMPOTENCY_TTL_SECS` before this one's `proposed_at`, in Unix
+    /// seconds, skips it. Expiry goes by proposal times alone, so every
+    /// replica skips the same operations. Keys cannot be nested.
+    Keyed {
+        key: String,
+        #[serde(default)]
+        proposed_at: i64,
+        operation: Box<MetricOperation>,
+    },
+}
+
+impl MetricOperation {
+    /// A sample of `name`, not yet admitted.
+    pub fn record(name: impl Into<String>, value: f64, timestamp: i64, labels: Labels) -> Self {
+        MetricOperation::Record { name: name.into(), value, timestamp, labels, admission: Admission::default() }
+    }
+
+    /// Label for the operation in [`STORAGE_OPERATIONS_TOTAL`].
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MetricOperation::Record { .. } => "record",
+            MetricOperation::Delete { .. } => "delete",
+            MetricOperation::Clear => "clear",
+            MetricOperation::ApplyQuarantine { .. } => "apply_quarantine",
+            MetricOperation::Transaction(_) => "transaction",
+            MetricOperation::Reset { .. } => "reset",
+            MetricOperation::SetTransform { .. } => "set_transform",
+            MetricOperation::RecordSummary { .. } => "record_summary",
+            MetricOperation::DefineStates { .. } => "define_states",
+            MetricOperation::DefineHistogram { .. } => "define_histogram",
+            MetricOperation::Keyed { operation, .. } => operation.kind(),
+        }
+    }
+
+    /// The metric this operation targets, or `None` for store-wide
+    /// operations and transactions.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            MetricOperation::Record { name, .. }
+            | MetricOperation::Delete { name, .. }
+            | MetricOperation::ApplyQuarantine { name }
+            | MetricOperation::Reset { name, .. }
+            | MetricOperation::SetTransform { name, .. }
+            | MetricOperation::RecordSummary { name, .. }
+            | MetricOperation::DefineStates { name, .. }
+            | MetricOperation::DefineHistogram { name, .. } => Some(name),
+            MetricOperation::Clear | MetricOperation::Transaction(_) => None,
+            MetricOperation::Keyed { operation, .. } => operation.name(),
+        }
+    }
+}
+
+/// Names `operation` records samples of into `stored`, and names it
+/// resets into `reset`.
+fn collect_written<'a>(operation: &'a MetricOperation, stored: &mut Vec<&'a str>, reset: &mut Vec<&'a str>) {
+    match operation {
+        MetricOperation::Record { name, .. } | MetricOperation::RecordSummary { name, .. } => stored.push(name),
+        MetricOperation::Reset { name, .. } => reset.push(name),
+        MetricOperation::Transaction(operations) => operations.iter().for_each(|operation| collect_written(operation, stored, reset)),
+        MetricOperation::Keyed { operation, .. } => collect_written(operation, stored, reset),
+        _ => {}
+    }
+}
+
+/// The key watermark a store with `keys` stood at: the newest key was
+/// remembered `ttl` before it expires.
+fn key_watermark(keys: &HashMap<String, i64>, ttl: i64) -> i64 {
+    keys.values().max().map_or(i64::MIN, |&expires_at| expires_at - ttl)
+}
+
+fn nested_key() -> crate::RaftMetricsError {
+    crate::RaftMetricsError::InvalidRequest("a keyed operation cannot hold another keyed operation".to_string())
+}
+
+/// Checks that `operations` can make up a [`MetricOperation::Transaction`]:
+/// one or more records, resets and deletes. Each metric is written at most
+/// once, and a metric deleted in the transaction can only be reset after.
+pub fn check_transaction(operations: &[MetricOperation]) -> Result<()> {
+    if operations.is_empty() {
+        return Err(crate::RaftMetricsError::InvalidRequest("a transaction needs at least one operation".to_string()));
+    }
+    let refuse = |reason: String| Err(crate::RaftMetricsError::InvalidRequest(reason));
+    let mut written = std::collections::HashSet::new();
+    let mut deleted = std::collections::HashSet::new();
+    for operation in operations {
+        match operation {
+            MetricOperation::Record { name, .. } | MetricOperation::Reset { name, .. } if !written.insert(name.as_str()) => {
+                return refuse(format!("a transaction writes '{}' more than once", name));
+            }
+            MetricOperation::Record { name, .. } if deleted.contains(name.as_str()) => {
+                return refuse(format!("a transaction records to '{}' after deleting it", name));
+            }
+            MetricOperation::Delete { name, .. } if written.contains(name.as_str()) || !deleted.insert(name.as_str()) => {
+                return refuse(format!("a transaction deletes '{}' after writing or deleting it", name));
+            }
+            MetricOperation::Record { .. } | MetricOperation::Reset { .. } | MetricOperation::Delete { .. } => {}
+            other => return refuse(format!("a transaction cannot hold a {} operation", other.kind())),
+        }
+    }
+    Ok(())
+}
+
+/// Whether applying a committed operation failed for a reason local to
+/// this replica, such as storage I/O, rather than being rejected. Other
+/// replicas would apply the operation, so it must be retried, not skipped.
+pub fn halts_apply(error: &crate::RaftMetricsError) -> bool {
+    error.code() == crate::error::ErrorCode::StorageError
+}
+
+/// Upper bound on operations in one raft entry, so an operation's position
+/// in the log packs into a single sequence number.
+pub const MAX_OPS_PER_ENTRY: usize = 1 << 16;
+
+/// Sequence number of the operation at `offset` within the entry at `index`.
+/// Sequences increase with commit order.
+pub fn operation_seq(index: u64, offset: usize) -> u64 {
+    (index << 16) | offset as u64
+}
+
+/// What recording a sample changes, worked out before anything is written
+/// so several samples can be persisted together.
+struct StagedSample<'a> {
+    write: StagedWrite<'a>,
+    sample: MetricPoint,
+    previous: Option<MetricPoint>,
+    /// The metric's latest sample and aggregate once the write lands;
+    /// unset for a quarantined sample.
+    visible: Option<(MetricPoint, MetricAggregate)>,
+    /// Whether the write creates the metric.
+    created: bool,
+}
+
+/// A delete worked out but not yet in the store; see
+/// [`MetricsRegistry::stage_delete`].
+struct StagedDelete<'a> {
+    name: &'a str,
+    deleted_at: i64,
+    /// Samples the metric counted, when it existed.
+    stored: Option<u64>,
+}
+
+/// Labels of the zero sample a reset records.
+static NO_LABELS: Labels = BTreeMap::new();
+
+/// A metric that has not been written to for a while, from
+/// [`MetricsRegistry::stale_metrics`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleMetric {
+    pub name: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// What [`MetricsRegistry::top_metrics`] ranks metrics by, highest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopBy {
+    /// Samples applied since the registry was opened.
+    #[default]
+    Writes,
+    /// Latest value.
+    Value,
+}
+
+impl TopBy {
+    /// Orders `a` before `b` when it ranks higher, ties broken by name so
+    /// every worker and the control node rank the same way.
+    pub fn rank(self, a: &TopMetric, b: &TopMetric) -> std::cmp::Ordering {
+        let by = match self {
+            TopBy::Writes => b.writes.cmp(&a.writes),
+            TopBy::Value => {
+                let value = |metric: &TopMetric| metric.value.unwrap_or(f64::NEG_INFINITY);
+                value(b).total_cmp(&value(a))
+            }
+        };
+        by.then_with(|| a.name.cmp(&b.name))
+    }
+}
+
+/// A metric ranked by [`MetricsRegistry::top_metrics`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopMetric {
+    pub name: String,
+    /// Samples applied since the registry was opened.
+    pub writes: u64,
+    /// Latest value, absent once the metric has none.
+    pub value: Option<f64>,
+}
+
+/// A metric whose aggregate in memory disagrees with the stored one, from
+/// [`MetricsRegistry::aggregate_mismatches`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateMismatch {
+    pub name: String,
+    pub cached_count: u64,
+    pub stored_count: u64,
+    pub cached_sum: f64,
+    pub stored_sum: f64,
+    pub cached_last_seen: i64,
+    pub stored_last_seen: i64,
+}
+
+/// How a rebuilt aggregate differs from the one it replaced, rebuilt minus
+/// previous.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateDelta {
+    pub name: String,
+    pub count: i64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl AggregateDelta {
+    /// `None` when the two agree up to floating-point rounding.
+    fn between(name: &str, previous: &MetricAggregate, rebuilt: &MetricAggregate) -> Option<Self> {
+        let differs = |a: f64, b: f64| (a - b).abs() > 1e-9 * a.abs().max(b.abs()).max(1.0);
+        let changed = previous.count != rebuilt.count
+            || differs(previous.total(), rebuilt.total())
+            || differs(previous.min, rebuilt.min)
+            || differs(previous.max, rebuilt.max)
+            || differs(previous.m2, rebuilt.m2);
+        changed.then(|| Self {
+            name: name.to_string(),
+            count: rebuilt.count as i64 - previous.count as i64,
+            sum: rebuilt.total() - previous.total(),
+            min: rebuilt.min - previous.min,
+            max: rebuilt.max - previous.max,
+        })
+    }
+}
+
+/// Outcome of [`MetricsRegistry::recompute_all_aggregates`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecomputeSummary {
+    /// Metrics whose aggregate was rebuilt.
+    pub recomputed: usize,
+    /// Those whose rebuilt aggregate differs from the one it replaced.
+    pub changed: usize,
+    /// The changed metrics that moved most, largest first.
+    pub largest: Vec<AggregateDelta>,
+}
+
+/// Whether `stored` reflects a later write than `cached`. A tie goes to
+/// the cached copy, which also carries the Kahan compensation.
+fn stored_is_newer(cached: &MetricAggregate, stored: &MetricAggregate) -> bool {
+    (stored.last_seen, stored.count) > (cached.last_seen, cached.count)
+}
+
+/// A sample held out of its metric by outlier screening; see
+/// [`outliers::OutlierPolicy`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedSample {
+    pub value: f64,
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+    /// Sequence the sample was committed at.
+    pub seq: u64,
+    pub reason: String,
+    /// Samples it stands for under the metric's sample rate.
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub weight: u64,
+}
+
+fn one() -> u64 {
+    1
+}
+
+fn is_one(weight: &u64) -> bool {
+    *weight == 1
+}
+
+/// Source of the current Unix time in seconds, replaceable in tests.
+pub type Clock = Arc<dyn Fn() -> i64 + Send + Sync>;
+
+/// A stored sample.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricPoint {
+    pub value: f64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StorageStats {
+    pub metric_count: u64,
+    pub sample_rows: u64,
+    pub tombstones: u64,
+    /// Distinct label sets stored, each metric's unlabeled samples counting
+    /// as one.
+    #[serde(default)]
+    pub series: u64,
+    /// Samples removed by the latest compaction pass.
+    #[serde(default)]
+    pub compacted_rows: u64,
+    /// Distinct metrics stored, counted against `max_distinct_metrics`.
+    #[serde(default)]
+    pub distinct_metrics: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_distinct_metrics: Option<u64>,
+}
+
+/// Outcome of [`MetricsRegistry::recover`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecoverySummary {
+    pub series: usize,
+    /// Series loaded into memory, picked by their latest write.
+    pub warmed: usize,
+    pub applied_index: u64,
+    pub elapsed: std::time::Duration,
 }
 
-#[derive(Debug, Clone)]
+/// Number of series loaded per query during recovery.
+const RECOVERY_BATCH_SIZE: usize = 10_000;
+
+/// Metrics rebuilt per batch by [`MetricsRegistry::recompute_all_aggregates`].
+pub const RECOMPUTE_BATCH_SIZE: usize = 1_000;
+
+/// Changed metrics listed in a [`RecomputeSummary`].
+const RECOMPUTE_LARGEST_DELTAS: usize = 10;
+
+/// Default time a tombstone is kept before its rows are purged.
+pub const DEFAULT_TOMBSTONE_GRACE_SECS: i64 = 24 * 60 * 60;
+
+/// How long an applied [`MetricOperation::Keyed`] key is remembered by
+/// default.
+pub const DEFAULT_IDEMPOTENCY_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Proposal time between two prunes of the expired applied keys.
+const KEY_PRUNE_INTERVAL_SECS: i64 = 60;
+
+#[derive(Clone)]
 pub struct MetricsRegistry {
-    metrics: Arc<AsyncRwLock<HashMap<String, f64>>>,
-    aggregates: Arc<AsyncRwLock<HashMap<String, MetricAggregate>>>,
+    /// Latest sample per metric: the one with the greatest timestamp, ties
+    /// going to the later write.
+    metrics: Arc<AsyncRwLock<HashMap<String, MetricPoint>>>,
+    /// Aggregate per resident metric. Only changed while `metrics` is
+    /// write-locked, and never locked across an await, so aggregate reads
+    /// copy an entry out without waiting for a write to reach the store.
+    aggregates: Arc<std::sync::RwLock<HashMap<String, MetricAggregate>>>,
+    tombstones: Arc<AsyncRwLock<HashMap<String, i64>>>,
+    sequences: Arc<AsyncRwLock<HashMap<String, u64>>>,
+    applied_index: Arc<watch::Sender<u64>>,
+    /// The store's applied sequence when it was opened: every operation at
+    /// or below it has its writes there already.
+    stored_seq: u64,
+    hooks: Arc<HookRegistry>,
+    summation: Summation,
+    budget: MemoryBudget,
+    residency: Arc<std::sync::Mutex<Residency>>,
+    history: Arc<std::sync::Mutex<RecentHistory>>,
+    dedup: Arc<DedupPolicy>,
+    outliers: Arc<OutlierPolicy>,
+    sample_rates: Arc<SampleRates>,
+    transforms: Arc<IngestTransforms>,
+    /// Transforms set through raft, overriding `transforms`.
+    replicated_transforms: Arc<std::sync::RwLock<HashMap<String, IngestTransform>>>,
+    /// Metrics whose samples name states, with their progress.
+    state_metrics: Arc<std::sync::RwLock<HashMap<String, StateMetric>>>,
+    /// Metrics whose samples are counted into buckets.
+    histograms: Arc<std::sync::RwLock<HashMap<String, HistogramMetric>>>,
+    /// Keys of the keyed operations applied, with when each expires, as
+    /// stored.
+    applied_keys: Arc<std::sync::Mutex<HashMap<String, i64>>>,
+    /// Latest `proposed_at` of the keyed operations applied, which moves
+    /// with the log alone, so keys expire at the same entry on every
+    /// replica.
+    key_watermark: Arc<std::sync::atomic::AtomicI64>,
+    idempotency_ttl: i64,
+    clock: Clock,
+    /// Samples removed by the latest compaction pass.
+    compacted_rows: Arc<std::sync::atomic::AtomicU64>,
+    /// Samples applied since the registry was opened.
+    records: Arc<std::sync::atomic::AtomicU64>,
+    /// `records` per metric, forgotten when the metric is deleted.
+    writes: Arc<std::sync::Mutex<HashMap<String, u64>>>,
+    aggregate_write: AggregateWrite,
+    /// Aggregates changed since the last flush under
+    /// [`AggregateWrite::Async`], kept even once evicted, as the store is
+    /// behind them.
+    unflushed: Arc<std::sync::Mutex<HashMap<String, MetricAggregate>>>,
+    /// Most metrics stored at once; writes creating more are refused.
+    max_distinct_metrics: Option<usize>,
+    /// Metrics stored, resident or not. Only changed while `metrics` is
+    /// write-locked.
+    distinct: Arc<std::sync::atomic::AtomicUsize>,
+    /// Per-tenant series and storage limits; see [`Self::set_quotas`].
+    quotas: Arc<std::sync::RwLock<TenantQuotas>>,
+    /// What each tenant stores, kept alongside `distinct`.
+    tenant_usage: Arc<std::sync::Mutex<HashMap<String, TenantUsage>>>,
+    backend: Arc<dyn MetricStorageBackend>,
+    /// Applies still to fail; see [`Self::fail_next_applies`].
+    #[cfg(test)]
+    failing_applies: Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl std::fmt::Debug for MetricsRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsRegistry").finish_non_exhaustive()
+    }
 }
 
 impl MetricsRegistry {
-    pub fn new() -> Self {
-        Self {
+    /// Creates a registry over a [`MemoryBackend`] with the default
+    /// retention, which needs no native libraries.
+    pub fn new() -> Result<Self> {
+        Self::with_backend(Box::new(MemoryBackend::new()))
+    }
+
+    /// Opens (or creates) the DuckDB file at `path` and migrates it to the latest schema.
+    #[cfg(feature = "duckdb-storage")]
+    pub fn open(path: &str) -> Result<Self> {
+        Self::with_backend(Box::new(DuckDbBackend::open(path)?))
+    }
+
+    /// Creates a registry over `backend`, loading its tombstones, hooks,
+    /// transforms, state metrics, histograms, applied keys and raft
+    /// progress.
+    pub fn with_backend(backend: Box<dyn MetricStorageBackend>) -> Result<Self> {
+        Self::with_backend_registering(backend, register_collectors)
+    }
+
+    fn with_backend_registering(
+        backend: Box<dyn MetricStorageBackend>,
+        register: impl FnOnce() -> Result<()>,
+    ) -> Result<Self> {
+        register()?;
+
+        let tombstones: HashMap<String, i64> = backend.tombstones()?.into_iter().collect();
+        let hooks = HookRegistry::default();
+        for (id, config) in backend.hooks()? {
+            match serde_json::from_str::<HookConfig>(&config) {
+                Ok(hook) => hooks.insert(id, hook),
+                Err(e) => warn!("Ignoring unreadable hook '{}': {}", id, e),
+            }
+        }
+        let replicated_transforms: HashMap<String, IngestTransform> = backend.transforms()?.into_iter().collect();
+        let mut state_metrics = HashMap::new();
+        for (name, config) in backend.state_metrics()? {
+            match serde_json::from_str::<StateMetric>(&config) {
+                Ok(metric) => {
+                    state_metrics.insert(name, metric);
+                }
+                Err(e) => warn!("Ignoring unreadable state metric '{}': {}", name, e),
+            }
+        }
+        let mut histograms = HashMap::new();
+        for (name, config) in backend.histograms()? {
+            match serde_json::from_str::<HistogramMetric>(&config) {
+                Ok(histogram) => {
+                    histograms.insert(name, histogram);
+                }
+                Err(e) => warn!("Ignoring unreadable histogram '{}': {}", name, e),
+            }
+        }
+        let applied_keys: HashMap<String, i64> = backend.applied_keys()?.into_iter().collect();
+        let applied_index = backend.applied_index()?;
+        let stored_seq = backend.applied_seq()?.unwrap_or(0);
+
+        Ok(Self {
             metrics: Arc::new(AsyncRwLock::new(HashMap::new())),
-            aggregates: Arc::new(AsyncRwLock::new(HashMap::new())),
+            aggregates: Arc::new(std::sync::RwLock::new(HashMap::new())),
+            tombstones: Arc::new(AsyncRwLock::new(tombstones)),
+            sequences: Arc::new(AsyncRwLock::new(HashMap::new())),
+            applied_index: Arc::new(watch::channel(applied_index.unwrap_or(0)).0),
+            stored_seq,
+            hooks: Arc::new(hooks),
+            summation: Summation::default(),
+            budget: MemoryBudget::default(),
+            residency: Arc::new(std::sync::Mutex::new(Residency::default())),
+            history: Arc::new(std::sync::Mutex::new(RecentHistory::new(MemoryBudget::default().max_history))),
+            dedup: Arc::new(DedupPolicy::default()),
+            outliers: Arc::new(OutlierPolicy::default()),
+            sample_rates: Arc::new(SampleRates::default()),
+            transforms: Arc::new(IngestTransforms::default()),
+            replicated_transforms: Arc::new(std::sync::RwLock::new(replicated_transforms)),
+            state_metrics: Arc::new(std::sync::RwLock::new(state_metrics)),
+            histograms: Arc::new(std::sync::RwLock::new(histograms)),
+            key_watermark: Arc::new(std::sync::atomic::AtomicI64::new(key_watermark(&applied_keys, DEFAULT_IDEMPOTENCY_TTL_SECS))),
+            applied_keys: Arc::new(std::sync::Mutex::new(applied_keys)),
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL_SECS,
+            clock: Arc::new(|| chrono::Utc::now().timestamp()),
+            compacted_rows: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            records: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            writes: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            aggregate_write: AggregateWrite::default(),
+            unflushed: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            max_distinct_metrics: None,
+            distinct: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            quotas: Arc::new(std::sync::RwLock::new(TenantQuotas::default())),
+            tenant_usage: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            backend: Arc::from(backend),
+            #[cfg(test)]
+            failing_applies: Arc::default(),
+        })
+    }
+
+    /// Selects how aggregate sums are accumulated from now on.
+    pub fn with_summation(mut self, summation: Summation) -> Self {
+        self.summation = summation;
+        self
+    }
+
+    /// Selects when aggregates are written to the store from now on.
+    pub fn with_aggregate_write(mut self, aggregate_write: AggregateWrite) -> Self {
+        self.aggregate_write = aggregate_write;
+        self
+    }
+
+    pub fn aggregate_write(&self) -> AggregateWrite {
+        self.aggregate_write
+    }
+
+    /// Refuses writes that would create more than `max` metrics from now
+    /// on; `None` allows any number.
+    pub fn with_max_distinct_metrics(mut self, max: Option<usize>) -> Self {
+        self.max_distinct_metrics = max;
+        self
+    }
+
+    /// Refuses writes taking a tenant past its series or storage quota from
+    /// now on.
+    pub fn with_quotas(self, quotas: TenantQuotas) -> Self {
+        *self.quotas.write().unwrap() = quotas;
+        self
+    }
+
+    /// Remembers the key of each keyed operation applied for `ttl` seconds
+    /// from now on.
+    pub fn with_idempotency_ttl(mut self, ttl: i64) -> Self {
+        self.idempotency_ttl = ttl;
+        let watermark = key_watermark(&self.applied_keys.lock().unwrap(), ttl);
+        self.key_watermark.store(watermark, std::sync::atomic::Ordering::Relaxed);
+        self
+    }
+
+    /// Leaves repeats of a metric's latest value out of the raw table as
+    /// `dedup` says, from now on.
+    pub fn with_dedup(mut self, dedup: DedupPolicy) -> Self {
+        self.dedup = Arc::new(dedup);
+        self
+    }
+
+    /// Quarantines samples `outliers` says are outliers, from now on.
+    pub fn with_outliers(mut self, outliers: OutlierPolicy) -> Self {
+        self.outliers = Arc::new(outliers);
+        self
+    }
+
+    /// Stamps `first_seen` and `last_seen` with `clock` instead of the
+    /// system time.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Starts sampling the metrics `rates` names.
+    pub fn with_sample_rates(mut self, rates: SampleRates) -> Self {
+        self.sample_rates = Arc::new(rates);
+        self
+    }
+
+    /// Only lets hooks post to `hosts`.
+    pub fn with_hook_hosts(self, hosts: HookHosts) -> Self {
+        self.hooks.set_allowed_hosts(hosts);
+        self
+    }
+
+    /// Records the metrics `transforms` names through their transform,
+    /// unless one has been set through raft.
+    pub fn with_transforms(mut self, transforms: IngestTransforms) -> Self {
+        self.transforms = Arc::new(transforms);
+        self
+    }
+
+    /// Name of the storage backend metrics are kept in.
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    pub fn sample_rate(&self, name: &str) -> Option<f64> {
+        self.sample_rates.get(name)
+    }
+
+    /// Keeps only `rate` of the samples later written to `name`.
+    pub fn set_sample_rate(&self, name: &str, rate: f64) -> Result<()> {
+        self.sample_rates.set(name, rate)?;
+        info!("Sample rate for '{}' set to {}", name, rate);
+        Ok(())
+    }
+
+    /// Decides on this node, before `operation` is proposed, what the
+    /// sample rates, dedup and outlier policies make of its samples: `None`
+    /// once every one is dropped, otherwise the operation with a weight on
+    /// each sampled one and the dedup window and outlier bounds of each
+    /// record. Replicas apply what the log
+    /// says, so config set on one node alone never makes the others
+    /// disagree.
+    pub fn admit(&self, operation: MetricOperation) -> Option<MetricOperation> {
+        match operation {
+            MetricOperation::Record { name, value, timestamp, labels, mut admission } => {
+                admission.weight = self.sampled_weight(&name, timestamp)?;
+                admission.dedup = self.dedup.window(&name);
+                admission.outliers = self.outliers.bounds(&name);
+                Some(MetricOperation::Record { name, value, timestamp, labels, admission })
+            }
+            MetricOperation::RecordSummary { name, timestamp, labels, summary, mut admission } => {
+                admission.weight = self.sampled_weight(&name, timestamp)?;
+                Some(MetricOperation::RecordSummary { name, timestamp, labels, summary, admission })
+            }
+            MetricOperation::Transaction(operations) => {
+                let admitted: Vec<MetricOperation> = operations.into_iter().filter_map(|operation| self.admit(operation)).collect();
+                (!admitted.is_empty()).then_some(MetricOperation::Transaction(admitted))
+            }
+            operation => Some(operation),
         }
     }
 
-    pub async fn record_metric(&self, name: &str, value: f64) -> Result<()> {
-        let mut metrics = self.metrics.write().await;
-        metrics.insert(name.to_string(), value);
-        
-        // Update aggregate
-        let mut aggregates = self.aggregates.write().await;
-        let aggregate = aggregates.entry(name.to_string()).or_insert_with(|| MetricAggregate {
-            count: 0,
-            sum: 0.0,
-            average: 0.0,
-            min: value,
-            max: value,
-        });
-        
-        aggregate.count += 1;
-        aggregate.sum += value;
-        aggregate.average = aggregate.sum / aggregate.count as f64;
-        aggregate.min = aggregate.min.min(value);
-        aggregate.max = aggregate.max.max(value);
-        
+    /// `None` when the sample rate of `name` drops its sample at
+    /// `timestamp`, otherwise the weight to record it with, if sampled.
+    fn sampled_weight(&self, name: &str, timestamp: i64) -> Option<Option<u64>> {
+        if self.sample_rates.get(name).is_none() {
+            return Some(None);
+        }
+        match self.sample_rates.weight(name, timestamp) {
+            Some(weight) => Some(Some(weight)),
+            None => {
+                SAMPLED_OUT_TOTAL.inc();
+                None
+            }
+        }
+    }
+
+    /// The transform `name`'s samples are recorded through.
+    pub fn transform(&self, name: &str) -> IngestTransform {
+        let replicated = self.replicated_transforms.read().unwrap().get(name).copied();
+        replicated.or_else(|| self.transforms.get(name)).unwrap_or_default()
+    }
+
+    /// Records `name`'s later samples through `scale` and `offset`, in
+    /// place of any `INGEST_TRANSFORMS` entry. Samples already recorded
+    /// keep their values.
+    async fn set_transform(&self, name: &str, scale: f64, offset: f64) -> Result<()> {
+        let transform = IngestTransform::new(scale, offset)?;
+        self.backend.put_transform(name, &transform).await?;
+        self.replicated_transforms.write().unwrap().insert(name.to_string(), transform);
+        info!("Samples of '{}' now recorded as value * {} + {}", name, scale, offset);
         Ok(())
     }
 
-    pub async fn get_metric(&self, name: &str) -> Result<Option<f64>> {
-        let metrics = self.metrics.read().await;
-        Ok(metrics.get(name).copied())
+    /// `name`'s definition and progress, if its samples name states.
+    pub fn state_metric(&self, name: &str) -> Option<StateMetric> {
+        self.state_metrics.read().unwrap().get(name).cloned()
     }
 
-    pub async fn get_metric_aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
-        let aggregates = self.aggregates.read().await;
-        Ok(aggregates.get(name).cloned())
+    /// `name`'s durations and transitions as of now, if it is a state
+    /// metric.
+    pub fn state_report(&self, name: &str) -> Option<StateReport> {
+        let now = (self.clock)();
+        self.state_metrics.read().unwrap().get(name).map(|metric| metric.report(name, now))
     }
 
-    pub async fn get_all_metrics(&self) -> Result<HashMap<String, f64>> {
-        Ok(self.metrics.read().await.clone())
+    /// The state `name` is in now, if it is a state metric with a sample.
+    pub fn current_state(&self, name: &str) -> Option<String> {
+        let now = (self.clock)();
+        self.state_metrics.read().unwrap().get(name)?.current(now).map(str::to_string)
     }
 
-    pub async fn get_all_aggregates(&self) -> Result<HashMap<String, MetricAggregate>> {
-        Ok(self.aggregates.read().await.clone())
+    /// Makes `name` a state metric. Redefining it with the same states
+    /// keeps what it has observed; other changes start it over. Samples
+    /// already recorded are not replayed.
+    async fn define_states(&self, name: &str, states: &[String], stale_after: Option<i64>) -> Result<()> {
+        let defined = StateMetric::new(states.to_vec(), stale_after)?;
+        let defined = match self.state_metric(name) {
+            Some(existing) => existing.redefined(defined),
+            None => defined,
+        };
+        self.put_state_metric(name, &defined).await?;
+        self.state_metrics.write().unwrap().insert(name.to_string(), defined);
+        info!("Samples of '{}' now name one of {} states", name, states.len());
+        Ok(())
     }
-}
 
-impl Default for MetricsRegistry {
-    fn default() -> Self {
-        Self::new()
+    async fn put_state_metric(&self, name: &str, metric: &StateMetric) -> Result<()> {
+        let config = serde_json::to_string(metric)
+            .map_err(|e| crate::RaftMetricsError::Internal(format!("Failed to encode state metric: {}", e)))?;
+        self.backend.put_state_metric(name, &config).await
+    }
+
+    /// The state a sample of `name` names, or `None` when `name` is not a
+    /// state metric. A value that names no state is refused.
+    fn state_index(&self, name: &str, value: f64) -> Result<Option<usize>> {
+        self.state_metrics.read().unwrap().get(name).map(|metric| metric.index(value)).transpose()
+    }
+
+    /// Moves `name`'s state to `state` at `timestamp`, persisting the
+    /// change before the sample itself is written. Observing is idempotent,
+    /// so an entry retried after the sample's write failed changes nothing
+    /// more.
+    async fn observe_state(&self, name: &str, state: usize, timestamp: i64) -> Result<()> {
+        let Some(mut metric) = self.state_metric(name) else {
+            return Ok(());
+        };
+        if !metric.observe(state, timestamp) {
+            return Ok(());
+        }
+        self.put_state_metric(name, &metric).await?;
+        self.state_metrics.write().unwrap().insert(name.to_string(), metric);
+        Ok(())
+    }
+
+    /// Forgets what `name`, or every state metric when `None`, has
+    /// observed, keeping the definitions.
+    async fn reset_states(&self, name: Option<&str>) -> Result<()> {
+        let reset: Vec<(String, StateMetric)> = self
+            .state_metrics
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(metric, state)| name.unwrap_or(metric) == metric.as_str() && state.tracker.current.is_some())
+            .map(|(metric, state)| {
+                let mut state = state.clone();
+                state.reset();
+                (metric.clone(), state)
+            })
+            .collect();
+        for (metric, state) in reset {
+            self.put_state_metric(&metric, &state).await?;
+            self.state_metrics.write().unwrap().insert(metric, state);
+        }
+        Ok(())
+    }
+
+    /// `name`'s layout and counts, if its samples are counted into buckets.
+    pub fn histogram(&self, name: &str) -> Option<HistogramMetric> {
+        self.histograms.read().unwrap().get(name).cloned()
+    }
+
+    pub fn histogram_report(&self, name: &str) -> Option<HistogramReport> {
+        self.histograms.read().unwrap().get(name).map(|histogram| histogram.report(name))
+    }
+
+    /// Every histogram metric as a Prometheus histogram family, in name
+    /// order. A name that comes out the same as an earlier one once made
+    /// a valid Prometheus name is left out.
+    pub fn histogram_families(&self) -> Vec<prometheus::proto::MetricFamily> {
+        let defined = self.histograms.read().unwrap();
+        let mut names: Vec<&String> = defined.keys().collect();
+        names.sort();
+        let mut exposed = std::collections::HashSet::new();
+        let mut families = Vec::with_capacity(names.len());
+        for name in names {
+            let family = histograms::family_name(name);
+            if !exposed.insert(family.clone()) {
+                debug!("Not exposing histogram '{}', which would repeat the family {}", name, family);
+                continue;
+            }
+            families.push(defined[name].family(name, &family));
+        }
+        families
+    }
+
+    /// Counts `name`'s later samples into buckets bounded by `bounds`.
+    /// Redefining it with the same layout changes nothing, and a different
+    /// one is refused unless `reset`, which starts the counts over.
+    /// Samples already recorded are not counted.
+    async fn define_histogram(&self, name: &str, bounds: &[f64], reset: bool) -> Result<()> {
+        let defined = HistogramMetric::new(bounds.to_vec())?;
+        let defined = match self.histogram(name) {
+            Some(existing) => existing.redefined(defined, reset)?,
+            None => defined,
+        };
+        self.put_histogram(name, &defined).await?;
+        self.histograms.write().unwrap().insert(name.to_string(), defined);
+        info!("Samples of '{}' now counted into {} buckets", name, bounds.len() + 1);
+        Ok(())
+    }
+
+    async fn put_histogram(&self, name: &str, histogram: &HistogramMetric) -> Result<()> {
+        let config = serde_json::to_string(histogram)
+            .map_err(|e| crate::RaftMetricsError::Internal(format!("Failed to encode histogram: {}", e)))?;
+        self.backend.put_histogram(name, &config).await
+    }
+
+    /// Counts a stored sample of `name` into its bucket, when `name` is a
+    /// histogram metric. Called once the sample is in the store, so an
+    /// entry retried after the sample's write failed counts it once.
+    async fn observe_histogram(&self, name: &str, value: f64) -> Result<()> {
+        let Some(mut histogram) = self.histogram(name) else {
+            return Ok(());
+        };
+        histogram.observe(value);
+        self.put_histogram(name, &histogram).await?;
+        self.histograms.write().unwrap().insert(name.to_string(), histogram);
+        Ok(())
+    }
+
+    /// Forgets the samples counted by `name`, or by every histogram when
+    /// `None`, keeping the layouts.
+    async fn reset_histograms(&self, name: Option<&str>) -> Result<()> {
+        let reset: Vec<(String, HistogramMetric)> = self
+            .histograms
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(metric, histogram)| name.unwrap_or(metric) == metric.as_str() && histogram.count > 0)
+            .map(|(metric, histogram)| {
+                let mut histogram = histogram.clone();
+                histogram.reset();
+                (metric.clone(), histogram)
+            })
+            .collect();
+        for (metric, histogram) in reset {
+            self.put_histogram(&metric, &histogram).await?;
+            self.histograms.write().unwrap().insert(metric, histogram);
+        }
+        Ok(())
+    }
+
+    /// Limits how many series, and how many recent samples of each, stay in
+    /// memory from now on.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.budget = budget;
+        self.history = Arc::new(std::sync::Mutex::new(RecentHistory::new(budget.max_history)));
+        self
+    }
+
+    /// Rebuilds the in-memory maps from the store: the aggregate row and
+    /// the latest live sample per metric, so the first reads after a
+    /// restart are served from memory. Series are loaded in pages of
+    /// [`RECOVERY_BATCH_SIZE`] names so a large table is never materialized
+    /// in one result, and only the [`MemoryBudget::warm_limit`] most
+    /// recently written are kept; the rest are read back on first use.
+    pub async fn recover(&self) -> Result<RecoverySummary> {
+        self.recover_with_batch_size(RECOVERY_BATCH_SIZE).await
+    }
+
+    pub(crate) async fn recover_with_batch_size(&self, batch_size: usize) -> Result<RecoverySummary> {
+        let started = std::time::Instant::now();
+        let mut sequences = self.sequences.write().await;
+        let mut metrics = self.metrics.write().await;
+        let limit = self.budget.warm_limit();
+        // The warmest series so far, with the coldest on top to be displaced
+        let mut warmest = std::collections::BinaryHeap::new();
+        let mut warm: HashMap<String, (MetricAggregate, Option<MetricPoint>)> = HashMap::new();
+        let mut usage: HashMap<String, TenantUsage> = HashMap::new();
+
+        let mut series = 0;
+        let mut after = String::new();
+        loop {
+            let page = self.backend.aggregates_page(&after, batch_size).await?;
+            let Some((last, _)) = page.last() else { break };
+            let last = last.clone();
+
+            series += page.len();
+            {
+                let quotas = self.quotas.read().unwrap();
+                for (name, aggregate) in &page {
+                    if let Some(tenant) = quotas.tenant_of(name) {
+                        let usage = usage.entry(tenant.to_string()).or_default();
+                        usage.series += 1;
+                        usage.storage_bytes += aggregate.count.saturating_mul(quotas::sample_bytes(name));
+                    }
+                }
+            }
+            for (name, aggregate) in page {
+                warmest.push(std::cmp::Reverse((aggregate.last_seen, name.clone())));
+                warm.insert(name, (aggregate, None));
+                if limit.is_some_and(|limit| warm.len() > limit) {
+                    if let Some(std::cmp::Reverse((_, coldest))) = warmest.pop() {
+                        warm.remove(&coldest);
+                    }
+                }
+            }
+            for (name, latest, seq) in self.backend.latest_between(&after, &last).await? {
+                if let Some((_, warm_latest)) = warm.get_mut(&name) {
+                    *warm_latest = Some(latest);
+                }
+                sequences.insert(name, seq);
+            }
+            after = last;
+        }
+        self.history.lock().unwrap().clear();
+        self.distinct.store(series, std::sync::atomic::Ordering::Relaxed);
+        *self.tenant_usage.lock().unwrap() = usage;
+
+        let warmed = warm.len();
+        {
+            let mut aggregates = self.aggregates.write().unwrap();
+            let mut residency = self.residency.lock().unwrap();
+            // Coldest first, so they are the first evicted
+            for std::cmp::Reverse((_, name)) in warmest.into_sorted_vec().into_iter().rev() {
+                let Some((aggregate, latest)) = warm.remove(&name) else { continue };
+                if let Some(latest) = latest {
+                    metrics.insert(name.clone(), latest);
+                }
+                residency.admit(&name);
+                aggregates.insert(name, aggregate);
+            }
+            drop(residency);
+            self.enforce_budget(&mut metrics, &mut aggregates);
+        }
+
+        let summary = RecoverySummary {
+            series,
+            warmed,
+            applied_index: self.applied_index(),
+            elapsed: started.elapsed(),
+        };
+        info!(
+            "Recovered {} series up to raft index {} in {:?}, {} warmed into memory",
+            summary.series, summary.applied_index, summary.elapsed, summary.warmed
+        );
+        Ok(summary)
+    }
+
+    /// Index of the last raft entry applied to this store.
+    pub fn applied_index(&self) -> u64 {
+        *self.applied_index.borrow()
+    }
+
+    /// Persists the index of the last applied raft entry so a restarted
+    /// node resumes its log from there. Each operation's writes store its
+    /// sequence with them, so an entry replayed because this did not happen
+    /// skips whatever the store already has.
+    pub async fn set_applied_index(&self, index: u64) -> Result<()> {
+        self.backend.set_applied_index(index).await?;
+        self.applied_index.send_replace(index);
+        Ok(())
+    }
+
+    /// The raft hard state and log entries from index `from` on, as the
+    /// node persisted them.
+    pub fn raft_log(&self, from: u64) -> Result<RaftLogState> {
+        self.backend.raft_log(from)
+    }
+
+    /// Persists encoded log entries by index, replacing any stored at or
+    /// after the first.
+    pub async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()> {
+        self.backend.append_raft_entries(entries).await
+    }
+
+    pub async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()> {
+        self.backend.set_raft_hard_state(hard_state).await
+    }
+
+    /// Drops persisted log entries before `index`.
+    pub async fn compact_raft_log(&self, index: u64) -> Result<()> {
+        self.backend.compact_raft_log(index).await
+    }
+
+    /// Resolves once the applied index reaches `index`.
+    pub async fn wait_for_applied(&self, index: u64) {
+        let mut applied = self.applied_index.subscribe();
+        let _ = applied.wait_for(|&applied| applied >= index).await;
+    }
+
+    /// Applies a replicated operation to local state.
+    pub async fn apply_operation(&self, operation: &MetricOperation) -> Result<()> {
+        self.check_quota(operation).await?;
+        let MetricOperation::Keyed { key, proposed_at, operation } = operation else {
+            return self.apply_unkeyed(operation).await;
+        };
+        if self.applied_key(key, *proposed_at) {
+            KEYED_SKIPPED_TOTAL.inc();
+            return Ok(());
+        }
+        self.apply_unkeyed(operation).await?;
+        self.remember_key(key, *proposed_at).await
+    }
+
+    async fn apply_unkeyed(&self, operation: &MetricOperation) -> Result<()> {
+        match operation {
+            MetricOperation::Record { name, value, timestamp, labels, admission } => {
+                self.record_admitted(name, *value, *timestamp, labels, admission).await
+            }
+            MetricOperation::Delete { name, deleted_at } => {
+                self.delete_metric(name, *deleted_at).await
+            }
+            MetricOperation::Clear => self.clear(&mut *self.sequences.write().await).await,
+            MetricOperation::ApplyQuarantine { name } => {
+                let _sequences = self.sequences.write().await;
+                self.apply_quarantined(name).await
+            }
+            MetricOperation::Transaction(operations) => {
+                let mut sequences = self.sequences.write().await;
+                let seq = self.local_seq(&sequences, operations.iter().filter_map(MetricOperation::name))?;
+                self.apply_transaction(&mut sequences, seq, operations).await
+            }
+            MetricOperation::Reset { name, .. } => {
+                let mut sequences = self.sequences.write().await;
+                let seq = self.local_seq(&sequences, [name.as_str()])?;
+                self.apply_transaction(&mut sequences, seq, std::slice::from_ref(operation)).await
+            }
+            MetricOperation::SetTransform { name, scale, offset } => {
+                let _sequences = self.sequences.write().await;
+                self.set_transform(name, *scale, *offset).await
+            }
+            MetricOperation::RecordSummary { name, timestamp, labels, summary, admission } => {
+                let mut sequences = self.sequences.write().await;
+                let seq = self.local_seq(&sequences, [name.as_str()])?;
+                self.record_summary(name, *timestamp, labels, summary, seq, admission).await?;
+                sequences.insert(name.to_string(), seq);
+                Ok(())
+            }
+            MetricOperation::DefineStates { name, states, stale_after_secs } => {
+                let _sequences = self.sequences.write().await;
+                self.define_states(name, states, *stale_after_secs).await
+            }
+            MetricOperation::DefineHistogram { name, bounds, reset } => {
+                let _sequences = self.sequences.write().await;
+                self.define_histogram(name, bounds, *reset).await
+            }
+            MetricOperation::Keyed { .. } => Err(nested_key()),
+        }
+    }
+
+    /// Sequence for a write to `names` applied outside the raft log, from
+    /// [`operation_seq`] like every other: the next offset after each
+    /// name's last write within the last entry applied, so it orders after
+    /// them and can never cover an operation of a later entry.
+    fn local_seq<'a>(&self, sequences: &HashMap<String, u64>, names: impl IntoIterator<Item = &'a str>) -> Result<u64> {
+        let floor = operation_seq(self.applied_index(), 0);
+        let seq = names
+            .into_iter()
+            .map(|name| sequences.get(name).map_or(floor, |&last| (last + 1).max(floor)))
+            .max()
+            .unwrap_or(floor);
+        // An offset that wrapped to zero would take the next entry's first sequence
+        if seq != floor && seq % MAX_OPS_PER_ENTRY as u64 == 0 {
+            return Err(crate::RaftMetricsError::Conflict(format!(
+                "{} writes were applied outside the raft log since entry {}; propose them instead",
+                MAX_OPS_PER_ENTRY,
+                (seq >> 16) - 1
+            )));
+        }
+        Ok(seq)
+    }
+
+    /// Whether an operation keyed `key` was applied and its key had not
+    /// expired by `proposed_at`.
+    fn applied_key(&self, key: &str, proposed_at: i64) -> bool {
+        let watermark = self.key_watermark.load(std::sync::atomic::Ordering::Relaxed);
+        self.applied_keys.lock().unwrap().get(key).is_some_and(|&expires_at| expires_at > proposed_at && expires_at > watermark)
+    }
+
+    /// Stores `key` as applied, for `idempotency_ttl` seconds from when it
+    /// was proposed, and moves the key watermark up to `proposed_at`. Each
+    /// time the watermark enters a new [`KEY_PRUNE_INTERVAL_SECS`], the
+    /// keys it has expired are pruned; they are skipped already, so a
+    /// prune that fails is only logged.
+    async fn remember_key(&self, key: &str, proposed_at: i64) -> Result<()> {
+        let expires_at = proposed_at + self.idempotency_ttl;
+        self.backend.put_applied_key(key, expires_at).await?;
+        self.applied_keys.lock().unwrap().insert(key.to_string(), expires_at);
+        let previous = self.key_watermark.fetch_max(proposed_at, std::sync::atomic::Ordering::Relaxed);
+        if proposed_at.div_euclid(KEY_PRUNE_INTERVAL_SECS) > previous.div_euclid(KEY_PRUNE_INTERVAL_SECS) {
+            if let Err(e) = self.prune_applied_keys().await {
+                warn!("Pruning applied keys failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets the applied keys expired by the key watermark, returning
+    /// how many.
+    async fn prune_applied_keys(&self) -> Result<usize> {
+        let watermark = self.key_watermark.load(std::sync::atomic::Ordering::Relaxed);
+        self.backend.prune_applied_keys(watermark).await?;
+        let mut keys = self.applied_keys.lock().unwrap();
+        let before = keys.len();
+        keys.retain(|_, &mut expires_at| expires_at > watermark);
+        Ok(before - keys.len())
+    }
+
+    /// Encodes a batch of operations as a raft log entry payload.
+    pub fn serialize_batch(operations: &[MetricOperation], codec: RaftCodec) -> Result<Vec<u8>> {
+        codec.encode(operations)
+    }
+
+    /// Decodes an entry payload written with any codec.
+    pub fn deserialize_batch(data: &[u8]) -> Result<Vec<MetricOperation>> {
+        codec::decode(data)
+    }
+
+    /// Encoded size of a single operation, used to keep batches under the
+    /// raft message size limit.
+    pub fn encoded_len(operation: &MetricOperation, codec: RaftCodec) -> usize {
+        codec.encoded_len(operation)
+    }
+
+    /// Applies every operation in the entry committed at `index`, in order,
+    /// returning one outcome per operation. An operation that is rejected
+    /// leaves the rest to apply, and is rejected alike on every replica, as
+    /// rejections depend only on replicated state. A failure that
+    /// [`halts_apply`] stops the entry with that error instead: skipping
+    /// the operation would leave this replica apart from the others, so the
+    /// entry must be applied again. Operations it already applied are then
+    /// skipped, as each metric's sequence has moved past them.
+    pub async fn apply_raft_batch(&self, index: u64, data: &[u8]) -> Result<Vec<Result<()>>> {
+        #[cfg(test)]
+        {
+            let failing = &self.failing_applies;
+            if failing.fetch_update(std::sync::atomic::Ordering::SeqCst, std::sync::atomic::Ordering::SeqCst, |left| left.checked_sub(1)).is_ok() {
+                return Err(crate::RaftMetricsError::Upstream {
+                    code: crate::error::ErrorCode::StorageError,
+                    message: "injected storage failure".to_string(),
+                });
+            }
+        }
+        let operations = Self::deserialize_batch(data)?;
+        let mut results = Vec::with_capacity(operations.len());
+        for (offset, operation) in operations.iter().enumerate() {
+            match self.apply_sequenced(operation_seq(index, offset), operation).await {
+                Err(e) if halts_apply(&e) => return Err(e),
+                applied => results.push(applied),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Applies an operation committed at sequence `seq`. Operations at or
+    /// below the last sequence applied to the same metric are skipped, so a
+    /// replayed or retried entry can never reorder a metric's writes, and so
+    /// are those at or below the store's applied sequence when it was
+    /// opened, whose writes it already holds. A keyed operation whose key
+    /// was already applied is skipped too, which covers an operation
+    /// proposed again under a later entry.
+    pub async fn apply_sequenced(&self, seq: u64, operation: &MetricOperation) -> Result<()> {
+        if seq <= self.stored_seq {
+            debug!("Skipping operation at seq {} already in the store up to seq {}", seq, self.stored_seq);
+            return Ok(());
+        }
+        let MetricOperation::Keyed { key, proposed_at, operation } = operation else {
+            return self.apply_sequenced_unkeyed(seq, operation).await;
+        };
+        if self.applied_key(key, *proposed_at) {
+            debug!("Skipping operation keyed '{}' at seq {}, already applied", key, seq);
+            KEYED_SKIPPED_TOTAL.inc();
+            return Ok(());
+        }
+        self.apply_sequenced_unkeyed(seq, operation).await?;
+        self.remember_key(key, *proposed_at).await
+    }
+
+    async fn apply_sequenced_unkeyed(&self, seq: u64, operation: &MetricOperation) -> Result<()> {
+        let mut sequences = self.sequences.write().await;
+        if let Some(name) = operation.name() {
+            if let Some(last) = sequences.get(name).copied() {
+                if seq <= last {
+                    debug!("Skipping operation on '{}' at seq {} already covered by seq {}", name, seq, last);
+                    return Ok(());
+                }
+            }
+        }
+
+        let applied = match operation {
+            MetricOperation::Record { name, value, timestamp, labels, admission } => {
+                let value = self.transform(name).apply(*value);
+                self.record_sample(name, value, *timestamp, labels, seq, true, None, admission).await
+            }
+            MetricOperation::RecordSummary { name, timestamp, labels, summary, admission } => {
+                self.record_summary(name, *timestamp, labels, summary, seq, admission).await
+            }
+            MetricOperation::Delete { name, deleted_at } => self.delete_metric(name, *deleted_at).await,
+            MetricOperation::Clear => self.clear(&mut sequences).await,
+            MetricOperation::ApplyQuarantine { name } => self.apply_quarantined(name).await,
+            MetricOperation::Transaction(operations) => self.apply_transaction(&mut sequences, seq, operations).await,
+            MetricOperation::Reset { .. } => self.apply_transaction(&mut sequences, seq, std::slice::from_ref(operation)).await,
+            MetricOperation::SetTransform { name, scale, offset } => self.set_transform(name, *scale, *offset).await,
+            MetricOperation::DefineStates { name, states, stale_after_secs } => {
+                self.define_states(name, states, *stale_after_secs).await
+            }
+            MetricOperation::DefineHistogram { name, bounds, reset } => self.define_histogram(name, bounds, *reset).await,
+            MetricOperation::Keyed { .. } => Err(nested_key()),
+        };
+        let result = if applied.is_ok() { "success" } else { "failure" };
+        STORAGE_OPERATIONS_TOTAL.with_label_values(&[operation.kind(), result]).inc();
+        applied?;
+
+        if let Some(name) = operation.name() {
+            sequences.insert(name.to_string(), seq);
+        }
+        Ok(())
+    }
+
+    /// Fails the next `count` raft entries applied with a storage error, as
+    /// a full disk would.
+    #[cfg(test)]
+    pub(crate) fn fail_next_applies(&self, count: u64) {
+        self.failing_applies.store(count, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Blocks applying any operation until the guard is dropped.
+    #[cfg(test)]
+    pub(crate) async fn stall_apply(&self) -> tokio::sync::OwnedRwLockWriteGuard<HashMap<String, u64>> {
+        self.sequences.clone().write_owned().await
+    }
+
+    pub async fn record_metric(&self, name: &str, value: f64) -> Result<()> {
+        self.record_metric_with_timestamp(name, value, chrono::Utc::now().timestamp()).await
+    }
+
+    /// Records a sample at an explicit timestamp, sequenced after every
+    /// write already applied to the metric.
+    pub async fn record_metric_with_timestamp(&self, name: &str, value: f64, timestamp: i64) -> Result<()> {
+        self.record_metric_with_labels(name, value, timestamp, &Labels::new()).await
+    }
+
+    pub async fn record_metric_with_labels(&self, name: &str, value: f64, timestamp: i64, labels: &Labels) -> Result<()> {
+        self.check_quota(&MetricOperation::record(name, value, timestamp, labels.clone())).await?;
+        self.record_admitted(name, value, timestamp, labels, &Admission::default()).await
+    }
+
+    /// Records a sample as its [`Admission`] says, sequenced after every
+    /// write already applied to the metric.
+    async fn record_admitted(&self, name: &str, value: f64, timestamp: i64, labels: &Labels, admission: &Admission) -> Result<()> {
+        let mut sequences = self.sequences.write().await;
+        let seq = self.local_seq(&sequences, [name])?;
+        let value = self.transform(name).apply(value);
+        self.record_sample(name, value, timestamp, labels, seq, true, None, admission).await?;
+        sequences.insert(name.to_string(), seq);
+        Ok(())
+    }
+
+    /// `name`'s quarantined samples, oldest write first.
+    pub async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>> {
+        self.backend.quarantined(name).await
+    }
+
+    /// Records `name`'s quarantined samples without screening them, at the
+    /// sequences they were first committed at, and empties its quarantine.
+    /// Callers hold the sequence lock, so nothing is quarantined meanwhile.
+    async fn apply_quarantined(&self, name: &str) -> Result<()> {
+        let samples = self.backend.quarantined(name).await?;
+        for sample in &samples {
+            let admission = Admission { weight: Some(sample.weight), ..Admission::default() };
+            self.record_sample(name, sample.value, sample.timestamp, &sample.labels, sample.seq, false, None, &admission).await?;
+        }
+        self.backend.drop_quarantined(name).await?;
+        if !samples.is_empty() {
+            info!("Applied {} quarantined samples of '{}'", samples.len(), name);
+        }
+        Ok(())
+    }
+
+    /// Applies a transaction's records at sequence `seq`, all or none.
+    /// Every record is staged against the current state, the lot persisted
+    /// in one backend write, and only then do they become visible. Records
+    /// already covered by a later write to their metric are skipped, as in
+    /// [`Self::apply_sequenced`].
+    async fn apply_transaction(&self, sequences: &mut HashMap<String, u64>, seq: u64, operations: &[MetricOperation]) -> Result<()> {
+        check_transaction(operations)?;
+        let mut metrics = self.metrics.write().await;
+
+        let mut applied = Vec::with_capacity(operations.len());
+        let mut deleted = Vec::new();
+        let mut staged = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let Some(name) = operation.name() else {
+                continue;
+            };
+            if let Some(last) = sequences.get(name).copied() {
+                if seq <= last {
+                    debug!("Skipping transaction write to '{}' at seq {} already covered by seq {}", name, seq, last);
+                    continue;
+                }
+            }
+            applied.push(name);
+            match operation {
+                MetricOperation::Delete { name, deleted_at } => deleted.push(self.stage_delete(name, *deleted_at).await?),
+                MetricOperation::Reset { name, reset_at, keep_history } => {
+                    if !keep_history {
+                        deleted.push(self.stage_delete(name, reset_at.saturating_sub(1)).await?);
+                    }
+                    let cleared = deleted.iter().any(|delete| delete.name == name);
+                    staged.push((name, self.stage_reset(&metrics, name, *reset_at, seq, cleared).await?, None));
+                }
+                MetricOperation::Record { name, value, timestamp, labels, admission } => {
+                    let sample = MetricPoint { value: self.transform(name).apply(*value), timestamp: *timestamp };
+                    let state = self.state_index(name, sample.value)?;
+                    if let Some(sample) = self.stage_sample(&metrics, name, sample, labels, seq, true, None, admission).await? {
+                        staged.push((name, sample, state));
+                    }
+                }
+                // Refused by the check above
+                _ => {}
+            }
+        }
+
+        // A metric deleted and then reset here goes on counting as one
+        let recreated = |name: &str| deleted.iter().any(|delete| delete.name == name && delete.stored.is_some());
+        let created: Vec<&str> =
+            staged.iter().filter(|(name, sample, _)| sample.created && !recreated(name)).map(|(name, _, _)| name.as_str()).collect();
+        self.check_cardinality(&created)?;
+        for delete in &deleted {
+            self.reset_states(Some(delete.name)).await?;
+            self.reset_histograms(Some(delete.name)).await?;
+        }
+        for (name, sample, state) in &staged {
+            if let (Some(state), false) = (state, matches!(sample.write, StagedWrite::Quarantine { .. })) {
+                self.observe_state(name, *state, sample.sample.timestamp).await?;
+            }
+        }
+        let writes: Vec<StagedWrite> = deleted
+            .iter()
+            .map(|delete| StagedWrite::Delete { name: delete.name, deleted_at: delete.deleted_at })
+            .chain(staged.iter().filter_map(|(_, sample, _)| self.persisted(&sample.write)))
+            .chain([StagedWrite::Applied { seq }])
+            .collect();
+        self.backend.write_batch(&writes).await?;
+        if !deleted.is_empty() {
+            let mut tombstones = self.tombstones.write().await;
+            for delete in &deleted {
+                self.forget_deleted(&mut metrics, &mut tombstones, delete);
+            }
+        }
+        for (name, sample, _) in &staged {
+            if let StagedWrite::Sample { sample: point, .. } = &sample.write {
+                self.observe_histogram(name, point.value).await?;
+            }
+        }
+        for (name, sample, _) in staged {
+            self.commit_sample(&mut metrics, name, sample);
+        }
+        for name in applied {
+            sequences.insert(name.to_string(), seq);
+        }
+        Ok(())
+    }
+
+    /// Stores samples pre-aggregated on ingest at sequence `seq`, through
+    /// the metric's transform. They are neither screened for outliers nor
+    /// deduplicated.
+    async fn record_summary(&self, name: &str, timestamp: i64, labels: &Labels, summary: &SampleSummary, seq: u64, admission: &Admission) -> Result<()> {
+        if self.state_metrics.read().unwrap().contains_key(name) {
+            return Err(crate::RaftMetricsError::InvalidRequest(format!("state metric '{}' takes samples, not summaries", name)));
+        }
+        if self.histograms.read().unwrap().contains_key(name) {
+            return Err(crate::RaftMetricsError::InvalidRequest(format!("histogram '{}' takes samples, not summaries", name)));
+        }
+        let summary = self.transform(name).apply_summary(summary);
+        self.record_sample(name, summary.last, timestamp, labels, seq, false, Some(&summary), admission).await
+    }
+
+    /// Stores a sample at sequence `seq`; see [`Self::stage_sample`].
+    #[allow(clippy::too_many_arguments)]
+    async fn record_sample(
+        &self,
+        name: &str,
+        value: f64,
+        timestamp: i64,
+        labels: &Labels,
+        seq: u64,
+        screen: bool,
+        summary: Option<&SampleSummary>,
+        admission: &Admission,
+    ) -> Result<()> {
+        let mut metrics = self.metrics.write().await;
+        let sample = MetricPoint { value, timestamp };
+        let state = self.state_index(name, value)?;
+        let Some(staged) = self.stage_sample(&metrics, name, sample, labels, seq, screen, summary, admission).await? else {
+            return Ok(());
+        };
+        if staged.created {
+            self.check_cardinality(&[name])?;
+        }
+        if let (Some(state), false) = (state, matches!(staged.write, StagedWrite::Quarantine { .. })) {
+            self.observe_state(name, state, timestamp).await?;
+        }
+
+        // Persist first so memory never runs ahead of the store, and with
+        // the sequence so a replay finds the write already there
+        if let Some(write) = self.persisted(&staged.write) {
+            self.backend.write_batch(&[write, StagedWrite::Applied { seq }]).await?;
+        }
+        if matches!(staged.write, StagedWrite::Sample { .. }) {
+            self.observe_histogram(name, value).await?;
+        }
+        self.commit_sample(&mut metrics, name, staged);
+        Ok(())
+    }
+
+    /// Refuses a write creating the metrics `created` when they would take
+    /// the store past `max_distinct_metrics`.
+    fn check_cardinality(&self, created: &[&str]) -> Result<()> {
+        let (Some(max), Some(first)) = (self.max_distinct_metrics, created.first()) else {
+            return Ok(());
+        };
+        if self.distinct.load(std::sync::atomic::Ordering::Relaxed) + created.len() <= max {
+            return Ok(());
+        }
+        CARDINALITY_REJECTED_TOTAL.inc();
+        Err(crate::RaftMetricsError::InvalidRequest(format!(
+            "cannot create metric '{}': the worker already stores the maximum of {} distinct metrics",
+            first, max
+        )))
+    }
+
+    /// Refuses `operation` on the node proposing it when it would take a
+    /// tenant past its series or storage quota here, counting a new series
+    /// for each metric it records that is not stored yet and a sample for
+    /// each record. Quotas are set per worker, so they are checked before
+    /// proposing rather than on apply, where replicas would disagree.
+    pub async fn check_quota(&self, operation: &MetricOperation) -> Result<()> {
+        if self.quotas.read().unwrap().tenants.is_empty() {
+            return Ok(());
+        }
+        let mut stored = Vec::new();
+        let mut reset = Vec::new();
+        collect_written(operation, &mut stored, &mut reset);
+        let metrics = self.metrics.read().await;
+        let mut created: Vec<&str> = stored.iter().chain(&reset).copied().filter(|name| !metrics.contains_key(*name)).collect();
+        created.sort_unstable();
+        created.dedup();
+        self.check_quotas(&created, &stored)
+    }
+
+    /// Refuses a write creating the metrics `created` and storing samples
+    /// of `stored` when it would take a tenant past its series or storage
+    /// quota on this worker. Existing series stay writable at the series
+    /// quota.
+    fn check_quotas(&self, created: &[&str], stored: &[&str]) -> Result<()> {
+        let quotas = self.quotas.read().unwrap();
+        if quotas.tenants.is_empty() {
+            return Ok(());
+        }
+        let mut charged = BTreeMap::<&str, TenantUsage>::new();
+        for name in created {
+            if let Some(tenant) = quotas.tenant_of(name) {
+                charged.entry(tenant).or_default().series += 1;
+            }
+        }
+        for name in stored {
+            if let Some(tenant) = quotas.tenant_of(name) {
+                charged.entry(tenant).or_default().storage_bytes += quotas::sample_bytes(name);
+            }
+        }
+
+        let usage = self.tenant_usage.lock().unwrap();
+        for (tenant, charge) in charged {
+            let Some(quota) = quotas.quota(tenant) else { continue };
+            let used = usage.get(tenant).copied().unwrap_or_default();
+            if let Some(max) = quota.max_series.filter(|&max| charge.series > 0 && used.series + charge.series > max) {
+                QUOTA_REJECTED_TOTAL.with_label_values(&[tenant, "series"]).inc();
+                return Err(crate::RaftMetricsError::QuotaExceeded(format!(
+                    "tenant '{}' already has its quota of {} series on this worker", tenant, max
+                )));
+            }
+            if let Some(max) = quota.max_storage_bytes.filter(|&max| used.storage_bytes + charge.storage_bytes > max) {
+                QUOTA_REJECTED_TOTAL.with_label_values(&[tenant, "storage"]).inc();
+                return Err(crate::RaftMetricsError::QuotaExceeded(format!(
+                    "tenant '{}' has used its storage quota of {} bytes on this worker", tenant, max
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a series `created` and a sample `stored` of `name` to its
+    /// tenant's usage.
+    fn charge_tenant(&self, name: &str, created: bool, stored: bool) {
+        let quotas = self.quotas.read().unwrap();
+        let Some(tenant) = quotas.tenant_of(name) else { return };
+        let mut usage = self.tenant_usage.lock().unwrap();
+        let usage = usage.entry(tenant.to_string()).or_default();
+        usage.series += created as u64;
+        if stored {
+            usage.storage_bytes += quotas::sample_bytes(name);
+        }
+    }
+
+    /// Takes a deleted metric that held `samples` samples off its tenant's
+    /// usage.
+    fn release_tenant(&self, name: &str, samples: u64) {
+        let quotas = self.quotas.read().unwrap();
+        let Some(tenant) = quotas.tenant_of(name) else { return };
+        if let Some(usage) = self.tenant_usage.lock().unwrap().get_mut(tenant) {
+            usage.series = usage.series.saturating_sub(1);
+            usage.storage_bytes = usage.storage_bytes.saturating_sub(samples.saturating_mul(quotas::sample_bytes(name)));
+        }
+    }
+
+    /// Replaces the quotas from now on, for a reload. The tenant separator
+    /// is kept, as usage is counted by it.
+    pub fn set_quotas(&self, mut quotas: TenantQuotas) {
+        let mut current = self.quotas.write().unwrap();
+        if quotas.separator != current.separator {
+            warn!("{} changes take effect after a restart", quotas::SEPARATOR_KEY);
+            quotas.separator = current.separator.clone();
+        }
+        *current = quotas;
+    }
+
+    pub fn quotas(&self) -> TenantQuotas {
+        self.quotas.read().unwrap().clone()
+    }
+
+    /// Each tenant with a quota or anything stored here, in name order.
+    pub fn usage_reports(&self) -> Vec<UsageReport> {
+        let quotas = self.quotas.read().unwrap();
+        let usage = self.tenant_usage.lock().unwrap();
+        let tenants: std::collections::BTreeSet<&String> = quotas.tenants.keys().chain(usage.keys()).collect();
+        tenants
+            .into_iter()
+            .map(|tenant| {
+                let used = usage.get(tenant).copied().unwrap_or_default();
+                UsageReport {
+                    tenant: tenant.clone(),
+                    quota: quotas.quota(tenant).copied().unwrap_or_default(),
+                    series: used.series,
+                    storage_bytes: used.storage_bytes,
+                    samples_per_sec: None,
+                    workers: BTreeMap::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// `write` as it goes to the store now: under [`AggregateWrite::Async`]
+    /// without its aggregate, which [`Self::flush_aggregates`] writes later.
+    fn persisted<'a>(&self, write: &StagedWrite<'a>) -> Option<StagedWrite<'a>> {
+        match (self.aggregate_write, write) {
+            (AggregateWrite::Sync, _) | (_, StagedWrite::Quarantine { .. } | StagedWrite::Delete { .. } | StagedWrite::Applied { .. }) => {
+                Some(write.clone())
+            }
+            (AggregateWrite::Async { .. }, StagedWrite::Sample { name, sample, labels, seq, summary, .. }) => {
+                Some(StagedWrite::Sample { name, sample: *sample, labels, seq: *seq, summary: *summary, aggregate: None })
+            }
+            (AggregateWrite::Async { .. }, StagedWrite::Aggregate { .. }) => None,
+        }
+    }
+
+    /// Works out what storing a sample at sequence `seq` changes, reading
+    /// the store but not writing to it. Samples at or before the metric's
+    /// tombstone are ignored so a replayed write cannot resurrect it. Every
+    /// sample is kept in history, but only one at or after the current
+    /// latest timestamp becomes the metric's latest value, so late arrivals
+    /// never overwrite newer data. The sample counts as many times in the
+    /// aggregate as `admission` weighs it, and a repeat within its dedup
+    /// window only moves the aggregate. With `screen` set, an
+    /// outlier is quarantined instead of stored. With a `summary`, the
+    /// sample stands for every sample folded into it.
+    #[allow(clippy::too_many_arguments)]
+    async fn stage_sample<'a>(
+        &self,
+        metrics: &HashMap<String, MetricPoint>,
+        name: &'a str,
+        sample: MetricPoint,
+        labels: &'a Labels,
+        seq: u64,
+        screen: bool,
+        summary: Option<&SampleSummary>,
+        admission: &Admission,
+    ) -> Result<Option<StagedSample<'a>>> {
+        let MetricPoint { value, timestamp } = sample;
+        let weight = admission.weight();
+
+        let deleted_at = self.tombstones.read().await.get(name).copied();
+        if let Some(deleted_at) = deleted_at {
+            if timestamp <= deleted_at {
+                debug!("Ignoring write to '{}' at {} older than its tombstone at {}", name, timestamp, deleted_at);
+                return Ok(None);
+            }
+        }
+
+        // An evicted series still has a previous value in the store
+        let previous = match metrics.get(name) {
+            Some(latest) => Some(*latest),
+            None => self.backend.latest(name, deleted_at.unwrap_or(i64::MIN)).await?,
+        };
+        // A deduplicated repeat is not stored, so the stored sample stays latest
+        let dedup = admission.dedup.filter(|_| summary.is_none());
+        let deduplicated = dedup.is_some_and(|dedup| dedup.skips(previous, sample));
+        let latest = match previous {
+            Some(latest) if deduplicated || timestamp < latest.timestamp => latest,
+            _ => sample,
+        };
+        let now = (self.clock)();
+        let resident = self.aggregates.read().unwrap().get(name).cloned();
+        let stored = match resident {
+            Some(aggregate) => Some(aggregate),
+            None => self.load_aggregate(name).await?,
+        };
+        let created = stored.is_none();
+        let mut aggregate = stored.unwrap_or(MetricAggregate {
+            count: 0,
+            sum: 0.0,
+            average: 0.0,
+            min: value,
+            max: value,
+            compensation: 0.0,
+            m2: 0.0,
+            first_seen: now,
+            last_seen: now,
+        });
+
+        if let Some(reason) = admission.outliers.filter(|_| screen).and_then(|bounds| bounds.check(&aggregate, value)) {
+            debug!("Quarantining sample of '{}' at {}: {}", name, timestamp, reason);
+            let quarantined = QuarantinedSample { value, timestamp, labels: labels.clone(), seq, reason, weight };
+            let write = StagedWrite::Quarantine { name, sample: quarantined };
+            return Ok(Some(StagedSample { write, sample, previous, visible: None, created: false }));
+        }
+
+        match summary {
+            Some(summary) => aggregate.add_summary(summary, weight, self.summation),
+            None if !deduplicated || dedup.is_some_and(|dedup| dedup.count_skipped) => aggregate.add_weighted(value, weight, self.summation),
+            None => {}
+        }
+        aggregate.last_seen = aggregate.last_seen.max(now);
+
+        let write = if deduplicated {
+            StagedWrite::Aggregate { name, aggregate: aggregate.clone() }
+        } else {
+            // Stored with the sample, so a recompute counts it as this did
+            let summary = summary.copied().unwrap_or_else(|| SampleSummary::new(value)).weighted(weight);
+            StagedWrite::Sample { name, sample, labels, seq, summary, aggregate: Some(aggregate.clone()) }
+        };
+        Ok(Some(StagedSample { write, sample, previous, visible: Some((latest, aggregate)), created }))
+    }
+
+    /// Makes a staged sample visible once its write is in the store.
+    fn commit_sample(&self, metrics: &mut HashMap<String, MetricPoint>, name: &str, staged: StagedSample<'_>) {
+        self.records.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        *self.writes.lock().unwrap().entry(name.to_string()).or_default() += 1;
+        let Some((latest, aggregate)) = staged.visible else {
+            QUARANTINED_SAMPLES_TOTAL.inc();
+            return;
+        };
+        if matches!(staged.write, StagedWrite::Aggregate { .. }) {
+            DEDUPLICATED_TOTAL.inc();
+        } else {
+            self.history.lock().unwrap().push(name, staged.sample, staged.previous);
+        }
+
+        metrics.insert(name.to_string(), latest);
+        if staged.created {
+            self.distinct.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+        self.charge_tenant(name, staged.created, matches!(staged.write, StagedWrite::Sample { .. }));
+        if let AggregateWrite::Async { .. } = self.aggregate_write {
+            self.unflushed.lock().unwrap().insert(name.to_string(), aggregate.clone());
+        }
+        {
+            let mut aggregates = self.aggregates.write().unwrap();
+            aggregates.insert(name.to_string(), aggregate);
+            self.residency.lock().unwrap().admit(name);
+            self.enforce_budget(metrics, &mut aggregates);
+        }
+        if latest == staged.sample {
+            self.hooks.observe(name, staged.previous.map(|latest| latest.value), staged.sample.value, staged.sample.timestamp);
+        }
+    }
+
+    /// Works out zeroing `name` at `reset_at`, at sequence `seq`: its latest
+    /// value becomes 0 and its aggregate starts over from that zero, while
+    /// earlier samples stay in history and range queries. `cleared` when the
+    /// same write deletes the metric first, so the zero starts it afresh.
+    async fn stage_reset<'a>(
+        &self,
+        metrics: &HashMap<String, MetricPoint>,
+        name: &'a str,
+        reset_at: i64,
+        seq: u64,
+        cleared: bool,
+    ) -> Result<StagedSample<'a>> {
+        let created = cleared || (!self.aggregates.read().unwrap().contains_key(name) && self.load_aggregate(name).await?.is_none());
+        let zero = MetricPoint { value: 0.0, timestamp: reset_at };
+        let now = (self.clock)();
+        let mut aggregate = MetricAggregate {
+            count: 0,
+            sum: 0.0,
+            average: 0.0,
+            min: 0.0,
+            max: 0.0,
+            compensation: 0.0,
+            m2: 0.0,
+            first_seen: now,
+            last_seen: now,
+        };
+        aggregate.add(0.0, self.summation);
+
+        let write =
+            StagedWrite::Sample { name, sample: zero, labels: &NO_LABELS, seq, summary: SampleSummary::new(0.0), aggregate: Some(aggregate.clone()) };
+        let previous = if cleared { None } else { metrics.get(name).copied() };
+        Ok(StagedSample { write, sample: zero, previous, visible: Some((zero, aggregate)), created })
+    }
+
+    pub async fn get_metric(&self, name: &str) -> Result<Option<f64>> {
+        Ok(self.get_latest(name).await?.map(|latest| latest.value))
+    }
+
+    /// Latest live sample of `name`, with its timestamp.
+    pub async fn get_latest(&self, name: &str) -> Result<Option<MetricPoint>> {
+        if let Some(latest) = self.metrics.read().await.get(name).copied() {
+            self.residency.lock().unwrap().touch(name);
+            return Ok(Some(latest));
+        }
+        Ok(self.load_resident(name).await?.0)
+    }
+
+    /// Up to `limit` metrics not written to in the last `idle_secs`, with
+    /// names after `after`, in name order.
+    pub async fn stale_metrics(&self, idle_secs: u64, after: &str, limit: usize) -> Result<Vec<StaleMetric>> {
+        let cutoff = (self.clock)().saturating_sub(idle_secs.min(i64::MAX as u64) as i64);
+        self.backend.stale_page(cutoff, after, limit).await
+    }
+
+    /// Latest live samples of `names`, leaving out those without one.
+    /// Resident series are read under one lock and the rest from the store
+    /// in one statement; those are not made resident again.
+    pub async fn get_metrics(&self, names: &[String]) -> Result<HashMap<String, MetricPoint>> {
+        let mut found = HashMap::with_capacity(names.len());
+        let mut absent = Vec::new();
+        {
+            let metrics = self.metrics.read().await;
+            for name in names {
+                match metrics.get(name) {
+                    Some(latest) => {
+                        found.insert(name.clone(), *latest);
+                    }
+                    None => absent.push(name.clone()),
+                }
+            }
+        }
+        {
+            let mut residency = self.residency.lock().unwrap();
+            for name in found.keys() {
+                residency.touch(name);
+            }
+        }
+
+        if !absent.is_empty() {
+            FALLBACK_READS_TOTAL.inc_by(absent.len() as u64);
+            found.extend(self.backend.latest_many(&absent).await?);
+        }
+        Ok(found)
+    }
+
+    /// Reads a non-resident series from the store and makes it resident
+    /// again. Runs under the `metrics` write lock so it cannot interleave
+    /// with [`Self::record_sample`] and resurrect an older value.
+    async fn load_resident(&self, name: &str) -> Result<(Option<MetricPoint>, Option<MetricAggregate>)> {
+        let mut metrics = self.metrics.write().await;
+        let resident = self.aggregates.read().unwrap().get(name).cloned();
+        if let (Some(latest), Some(aggregate)) = (metrics.get(name), resident) {
+            return Ok((Some(*latest), Some(aggregate)));
+        }
+
+        FALLBACK_READS_TOTAL.inc();
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        let value = self.backend.latest(name, deleted_at).await?;
+        let aggregate = self.load_aggregate(name).await?;
+
+        if let (Some(value), Some(aggregate)) = (value, &aggregate) {
+            metrics.insert(name.to_string(), value);
+            let mut aggregates = self.aggregates.write().unwrap();
+            aggregates.insert(name.to_string(), aggregate.clone());
+            {
+                let mut residency = self.residency.lock().unwrap();
+                residency.admit(name);
+                residency.touch(name);
+            }
+            self.enforce_budget(&mut metrics, &mut aggregates);
+        }
+        Ok((value, aggregate))
+    }
+
+    /// Evicts the least-recently-read series once the memory budget is
+    /// exceeded, down to 90% of it so eviction is not paid on every write.
+    /// Every resident value was committed to the store by
+    /// [`Self::record_sample`] before it entered the maps, so eviction
+    /// never loses a write.
+    fn enforce_budget(&self, metrics: &mut HashMap<String, MetricPoint>, aggregates: &mut HashMap<String, MetricAggregate>) {
+        if let Some(max) = self.budget.max_entries {
+            let resident = metrics.len().max(aggregates.len());
+            if resident > max {
+                let target = max - max / 10;
+                let victims = self.residency.lock().unwrap().victims(resident - target, self.budget.hot_entries);
+                let mut history = self.history.lock().unwrap();
+                for name in &victims {
+                    metrics.remove(name);
+                    aggregates.remove(name);
+                    history.forget(name);
+                }
+                EVICTIONS_TOTAL.inc_by(victims.len() as u64);
+            }
+        }
+        RESIDENT_SERIES.set(metrics.len().max(aggregates.len()) as i64);
+    }
+
+    /// Returns the earliest and latest samples with `from <= timestamp <= to`,
+    /// or `None` if the range holds no samples. A window covered by the
+    /// in-memory history is answered from it; longer ones go to the store.
+    pub async fn get_range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>> {
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        let from = from.max(deleted_at.saturating_add(1));
+
+        if let Some(bounds) = self.history.lock().unwrap().range_bounds(name, from, to) {
+            return Ok(bounds);
+        }
+        self.backend.range_bounds(name, from, to).await
+    }
+
+    /// Reads up to `limit` samples of `name` with `from <= timestamp <= to`
+    /// in timestamp order, starting after `after`. With a `step`, returns
+    /// one point per `step`-second bucket instead, valued at the bucket's
+    /// `range.function` and stamped with its start. Returns the cursor for
+    /// the next page, or `None` once the range is exhausted.
+    pub async fn get_range_page(
+        &self,
+        name: &str,
+        range: RangeQuery,
+        after: Option<RangeCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)> {
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        let range = RangeQuery { from: range.from.max(deleted_at.saturating_add(1)), ..range };
+        self.backend.range_page(name, range, after, limit).await
+    }
+
+    /// Up to `limit` of `name`'s most recent live samples, newest first,
+    /// leaving out those before `since`. `None` when the metric is unknown;
+    /// a known metric without samples in the window gives an empty list.
+    pub async fn get_recent(&self, name: &str, limit: usize, since: Option<i64>) -> Result<Option<Vec<MetricPoint>>> {
+        if self.get_metric_aggregate(name).await?.is_none() {
+            return Ok(None);
+        }
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        let from = since.unwrap_or(i64::MIN).max(deleted_at.saturating_add(1));
+        Ok(Some(self.backend.recent(name, from, limit).await?))
+    }
+
+    /// Statistics of `name`'s stored samples per `step`-second bucket; see
+    /// [`MetricStorageBackend::buckets`].
+    pub async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>> {
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        self.backend.buckets(name, from.max(deleted_at.saturating_add(1)), to, step).await
+    }
+
+    /// Aggregates `name`'s stored samples per value of a label; see
+    /// [`MetricStorageBackend::group_by`].
+    pub async fn group_by(&self, name: &str, query: GroupByQuery) -> Result<(Vec<(String, f64)>, bool)> {
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        let query = GroupByQuery { from: query.from.max(deleted_at.saturating_add(1)), ..query };
+        self.backend.group_by(name, &query).await
+    }
+
+    /// The live series of `name` carrying exactly `labels`, no more and no
+    /// fewer; see [`MetricStorageBackend::series`].
+    pub async fn get_series(&self, name: &str, labels: &Labels) -> Result<Option<SeriesSummary>> {
+        let deleted_at = self.tombstones.read().await.get(name).copied().unwrap_or(i64::MIN);
+        self.backend.series(name, labels, deleted_at).await
+    }
+
+    /// Deletes a metric by writing a tombstone. Raw rows are kept until the
+    /// tombstone's grace period expires; see [`Self::purge_tombstones`].
+    pub async fn delete_metric(&self, name: &str, deleted_at: i64) -> Result<()> {
+        let mut metrics = self.metrics.write().await;
+        let delete = self.stage_delete(name, deleted_at).await?;
+        self.backend.delete(name, delete.deleted_at).await?;
+        self.reset_states(Some(name)).await?;
+        self.reset_histograms(Some(name)).await?;
+        self.forget_deleted(&mut metrics, &mut *self.tombstones.write().await, &delete);
+        Ok(())
+    }
+
+    /// Works out deleting `name` as of `deleted_at`, never moving an
+    /// existing tombstone back.
+    async fn stage_delete<'a>(&self, name: &'a str, deleted_at: i64) -> Result<StagedDelete<'a>> {
+        let deleted_at = self.tombstones.read().await.get(name).map_or(deleted_at, |existing| (*existing).max(deleted_at));
+        let resident = self.aggregates.read().unwrap().get(name).map(|aggregate| aggregate.count);
+        let stored = match resident {
+            Some(count) => Some(count),
+            None => self.load_aggregate(name).await?.map(|aggregate| aggregate.count),
+        };
+        Ok(StagedDelete { name, deleted_at, stored })
+    }
+
+    /// Drops a deleted metric from memory once its delete is in the store.
+    fn forget_deleted(&self, metrics: &mut HashMap<String, MetricPoint>, tombstones: &mut HashMap<String, i64>, delete: &StagedDelete<'_>) {
+        let name = delete.name;
+        if let Some(samples) = delete.stored {
+            self.distinct.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+            self.release_tenant(name, samples);
+        }
+        metrics.remove(name);
+        self.aggregates.write().unwrap().remove(name);
+        self.unflushed.lock().unwrap().remove(name);
+        self.residency.lock().unwrap().forget(name);
+        self.history.lock().unwrap().forget(name);
+        self.writes.lock().unwrap().remove(name);
+        tombstones.insert(name.to_string(), delete.deleted_at);
+    }
+
+    /// Creates or replaces the ingest hook `id`.
+    pub async fn put_hook(&self, id: &str, hook: HookConfig) -> Result<()> {
+        hook.validate()?;
+        self.hooks.check_destination(&hook)?;
+        let config = serde_json::to_string(&hook)
+            .map_err(|e| crate::RaftMetricsError::Internal(format!("Failed to encode hook: {}", e)))?;
+        self.backend.put_hook(id, &config).await?;
+        self.hooks.insert(id.to_string(), hook);
+        Ok(())
+    }
+
+    pub fn get_hook(&self, id: &str) -> Option<HookConfig> {
+        self.hooks.get(id)
+    }
+
+    /// Removes every sample, aggregate and tombstone in one transaction and
+    /// empties the in-memory maps, forgetting each metric's last sequence
+    /// so writes after the clear start afresh. Schema and raft progress are
+    /// kept. Callers hold the sequence lock and pass its map.
+    async fn clear(&self, sequences: &mut HashMap<String, u64>) -> Result<()> {
+        let mut metrics = self.metrics.write().await;
+        let mut tombstones = self.tombstones.write().await;
+
+        self.backend.clear().await?;
+        self.reset_states(None).await?;
+        self.reset_histograms(None).await?;
+
+        metrics.clear();
+        sequences.clear();
+        self.aggregates.write().unwrap().clear();
+        self.unflushed.lock().unwrap().clear();
+        self.distinct.store(0, std::sync::atomic::Ordering::Relaxed);
+        self.tenant_usage.lock().unwrap().clear();
+        tombstones.clear();
+        self.residency.lock().unwrap().clear();
+        self.history.lock().unwrap().clear();
+        self.writes.lock().unwrap().clear();
+        RESIDENT_SERIES.set(0);
+        info!("Cleared all metrics");
+        Ok(())
+    }
+
+    /// Permanently removes rows covered by tombstones created at or before
+    /// `cutoff`, along with the tombstones themselves. Returns how many
+    /// tombstones were reclaimed.
+    pub async fn purge_tombstones(&self, cutoff: i64) -> Result<usize> {
+        let mut tombstones = self.tombstones.write().await;
+        let expired: Vec<(String, i64)> = tombstones
+            .iter()
+            .filter(|(_, deleted_at)| **deleted_at <= cutoff)
+            .map(|(name, deleted_at)| (name.clone(), *deleted_at))
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        self.backend.purge(&expired).await?;
+
+        for (name, _) in &expired {
+            tombstones.remove(name);
+        }
+        info!("Purged {} expired tombstones", expired.len());
+        Ok(expired.len())
+    }
+
+    /// Thins samples older than the policy's age in every metric it does
+    /// not exempt, a bounded transaction at a time. A pass interrupted
+    /// part way is finished by the next, which removes nothing already
+    /// thinned. Returns the samples removed.
+    pub async fn compact_samples(&self, policy: &CompactionPolicy, now: i64) -> Result<u64> {
+        let Some(before) = policy.cutoff(now) else {
+            return Ok(0);
+        };
+        let mut removed = 0;
+        let mut after = String::new();
+        loop {
+            let page = self.backend.aggregates_page(&after, RECOVERY_BATCH_SIZE).await?;
+            for (name, _) in &page {
+                if policy.exempts(name) {
+                    continue;
+                }
+                let mut thinned = 0;
+                loop {
+                    let batch = self.backend.compact(name, before, policy.interval, policy.batch_rows).await?;
+                    thinned += batch as u64;
+                    if batch < policy.batch_rows {
+                        break;
+                    }
+                }
+                if thinned > 0 {
+                    // The ring may still hold samples that were just removed
+                    self.history.lock().unwrap().forget(name);
+                    removed += thinned;
+                }
+            }
+            match page.last() {
+                Some((name, _)) if page.len() == RECOVERY_BATCH_SIZE => after = name.clone(),
+                _ => break,
+            }
+        }
+
+        COMPACTED_SAMPLES_TOTAL.inc_by(removed);
+        self.compacted_rows.store(removed, std::sync::atomic::Ordering::Relaxed);
+        if removed > 0 {
+            info!("Compaction removed {} samples older than {}", removed, before);
+        }
+        Ok(removed)
+    }
+
+    pub async fn storage_stats(&self) -> Result<StorageStats> {
+        Ok(StorageStats {
+            compacted_rows: self.compacted_rows.load(std::sync::atomic::Ordering::Relaxed),
+            distinct_metrics: self.distinct.load(std::sync::atomic::Ordering::Relaxed) as u64,
+            max_distinct_metrics: self.max_distinct_metrics.map(|max| max as u64),
+            ..self.backend.stats().await?
+        })
+    }
+
+    /// Samples applied since the registry was opened, quarantined ones
+    /// included.
+    pub fn records_total(&self) -> u64 {
+        self.records.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The `n` metrics ranking highest `by` their writes since the registry
+    /// was opened or their latest value.
+    pub async fn top_metrics(&self, n: usize, by: TopBy) -> Result<Vec<TopMetric>> {
+        let mut top: Vec<TopMetric> = match by {
+            TopBy::Writes => {
+                let writes = self.writes.lock().unwrap();
+                writes
+                    .iter()
+                    .map(|(name, writes)| TopMetric { name: name.clone(), writes: *writes, value: None })
+                    .collect()
+            }
+            TopBy::Value => {
+                let writes = self.writes.lock().unwrap().clone();
+                self.get_all_metrics().await?
+                    .into_iter()
+                    .map(|(name, value)| TopMetric { writes: writes.get(&name).copied().unwrap_or(0), name, value: Some(value) })
+                    .collect()
+            }
+        };
+        top.sort_by(|a, b| by.rank(a, b));
+        top.truncate(n);
+
+        if by == TopBy::Writes {
+            let names: Vec<String> = top.iter().map(|metric| metric.name.clone()).collect();
+            let latest = self.get_metrics(&names).await?;
+            for metric in &mut top {
+                metric.value = latest.get(&metric.name).map(|point| point.value);
+            }
+        }
+        Ok(top)
+    }
+
+    /// Metrics whose aggregate is held in memory.
+    pub fn resident_count(&self) -> usize {
+        self.aggregates.read().unwrap().len()
+    }
+
+    /// Runs an ad-hoc read-only `SELECT` over the stored metrics; see
+    /// [`query::validate`] for what is allowed.
+    pub async fn query(&self, sql: &str, max_rows: usize, deadline: std::time::Instant) -> Result<query::QueryResult> {
+        let sql = query::validate(sql)?;
+        self.backend.query(sql, max_rows, deadline).await
+    }
+
+    /// Served from memory when `name` is resident, without waiting for
+    /// writes in flight; otherwise read from the store.
+    pub async fn get_metric_aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
+        let resident = self.aggregates.read().unwrap().get(name).cloned();
+        if let Some(aggregate) = resident {
+            self.residency.lock().unwrap().touch(name);
+            return Ok(Some(aggregate));
+        }
+        Ok(self.load_resident(name).await?.1)
+    }
+
+    /// `name`'s aggregate as last written, which under
+    /// [`AggregateWrite::Async`] may not have reached the store yet.
+    async fn load_aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
+        let unflushed = self.unflushed.lock().unwrap().get(name).cloned();
+        match unflushed {
+            Some(aggregate) => Ok(Some(aggregate)),
+            None => self.backend.aggregate(name).await,
+        }
+    }
+
+    pub async fn get_all_metrics(&self) -> Result<HashMap<String, f64>> {
+        let mut all: HashMap<String, f64> = self.metrics.read().await
+            .iter()
+            .map(|(name, latest)| (name.clone(), latest.value))
+            .collect();
+
+        // Evicted series are only in the store
+        for (name, value) in self.backend.all_latest().await? {
+            all.entry(name).or_insert(value);
+        }
+        Ok(all)
+    }
+
+    /// Rolls up the aggregates of every metric under `shape`'s prefix,
+    /// reading only the stored names that start with it, along with any
+    /// not flushed yet.
+    pub async fn namespace_tree(&self, shape: &TreeShape) -> Result<NamespaceTree> {
+        let prefix = shape.name_prefix();
+        let mut under = self.stored_aggregates_under(prefix, "", usize::MAX).await?;
+        for (name, aggregate) in self.unflushed.lock().unwrap().iter() {
+            if name.starts_with(prefix) {
+                under.insert(name.clone(), aggregate.clone());
+            }
+        }
+        Ok(NamespaceTree::build(shape, under.iter().map(|(name, aggregate)| (name.as_str(), aggregate))))
+    }
+
+    /// Up to `limit` stored aggregates of metrics starting with `prefix`
+    /// and named after `after`, reading only the names around the prefix.
+    async fn stored_aggregates_under(&self, prefix: &str, after: &str, limit: usize) -> Result<BTreeMap<String, MetricAggregate>> {
+        // Names sorting right after this one start with the prefix
+        let mut start = prefix.to_string();
+        start.pop();
+        let mut after = start.max(after.to_string());
+        let mut under = BTreeMap::new();
+        'pages: loop {
+            let page = self.backend.aggregates_page(&after, RECOVERY_BATCH_SIZE).await?;
+            let full = page.len() == RECOVERY_BATCH_SIZE;
+            for (name, aggregate) in page {
+                let inside = name.starts_with(prefix);
+                if under.len() == limit || (!inside && name.as_str() > prefix) {
+                    break 'pages;
+                }
+                after = name.clone();
+                if inside {
+                    under.insert(name, aggregate);
+                }
+            }
+            if !full {
+                break;
+            }
+        }
+        Ok(under)
+    }
+
+    /// Merges the in-memory aggregates of the metrics `keep` accepts into
+    /// `all`. Where both hold a metric, whichever saw the later write wins,
+    /// so a stale copy on either side never hides a newer one. Aggregates
+    /// awaiting a flush count as held in memory.
+    fn merge_cached(&self, all: &mut BTreeMap<String, MetricAggregate>, keep: impl Fn(&str) -> bool) {
+        let mut merge = |name: &String, cached: &MetricAggregate| match all.get_mut(name) {
+            Some(stored) if stored_is_newer(cached, stored) => {}
+            Some(stored) => *stored = cached.clone(),
+            None => {
+                all.insert(name.clone(), cached.clone());
+            }
+        };
+        for (name, cached) in self.unflushed.lock().unwrap().iter().filter(|(name, _)| keep(name)) {
+            merge(name, cached);
+        }
+        for (name, cached) in self.aggregates.read().unwrap().iter().filter(|(name, _)| keep(name)) {
+            merge(name, cached);
+        }
+    }
+
+    /// Up to `limit` aggregates from the `offset`th metric on, in name
+    /// order, taking the newer of the copies in memory and in the store.
+    pub async fn get_all_aggregates(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        // The first `offset + limit` names of the union are among the first
+        // `offset + limit` of each side
+        let end = offset.saturating_add(limit);
+        let mut all: BTreeMap<_, _> = self.backend.aggregates_slice(0, end).await?.into_iter().collect();
+        self.merge_cached(&mut all, |_| true);
+        Ok(all.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// Up to `limit` aggregates of metrics starting with `prefix` and named
+    /// after `after`, in name order, taking the newer of the copies in
+    /// memory and in the store. The last name is the cursor for the next
+    /// page.
+    pub async fn aggregates_under(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        // As with offsets, the first `limit` names of the union are among
+        // the first `limit` of each side
+        let mut all = self.stored_aggregates_under(prefix, after, limit).await?;
+        self.merge_cached(&mut all, |name| name > after && name.starts_with(prefix));
+        Ok(all.into_iter().take(limit).collect())
+    }
+
+    /// Rebuilds the aggregate of every metric starting with `prefix` from
+    /// its live samples, as after samples arrive out of order, and
+    /// refreshes the resident copies. Metrics are rebuilt in batches of
+    /// [`RECOMPUTE_BATCH_SIZE`], each holding off writes only while it runs,
+    /// so a sample is either in the scan or applied after the batch is
+    /// written. `first_seen` and `last_seen` are kept.
+    pub async fn recompute_all_aggregates(&self, prefix: &str) -> Result<RecomputeSummary> {
+        let mut summary = RecomputeSummary::default();
+        let mut after = String::new();
+        loop {
+            let _sequences = self.sequences.write().await;
+            let mut rebuilt = self.backend.recomputed_aggregates(prefix, &after, RECOMPUTE_BATCH_SIZE).await?;
+            let Some((last, _)) = rebuilt.last() else { break };
+            after = last.clone();
+            let full = rebuilt.len() == RECOMPUTE_BATCH_SIZE;
+
+            for (name, aggregate) in &mut rebuilt {
+                let resident = self.aggregates.read().unwrap().get(name.as_str()).cloned();
+                let previous = match resident {
+                    Some(previous) => Some(previous),
+                    None => self.load_aggregate(name).await?,
+                };
+                match previous {
+                    Some(previous) => {
+                        (aggregate.first_seen, aggregate.last_seen) = (previous.first_seen, previous.last_seen);
+                        if let Some(delta) = AggregateDelta::between(name, &previous, aggregate) {
+                            summary.changed += 1;
+                            summary.largest.push(delta);
+                        }
+                    }
+                    None => summary.changed += 1,
+                }
+            }
+            let writes: Vec<_> = rebuilt
+                .iter()
+                .map(|(name, aggregate)| StagedWrite::Aggregate { name, aggregate: aggregate.clone() })
+                .collect();
+            self.backend.write_batch(&writes).await?;
+            {
+                let mut aggregates = self.aggregates.write().unwrap();
+                let mut unflushed = self.unflushed.lock().unwrap();
+                for (name, aggregate) in rebuilt.iter() {
+                    if let Some(resident) = aggregates.get_mut(name) {
+                        *resident = aggregate.clone();
+                    }
+                    unflushed.remove(name);
+                }
+            }
+            summary.recomputed += rebuilt.len();
+            // By how far the count moved, then the sum
+            summary.largest.sort_by(|a, b| {
+                b.count.unsigned_abs().cmp(&a.count.unsigned_abs()).then(b.sum.abs().total_cmp(&a.sum.abs()))
+            });
+            summary.largest.truncate(RECOMPUTE_LARGEST_DELTAS);
+            if !full {
+                break;
+            }
+        }
+        info!("Recomputed {} aggregates under '{}' from stored samples, {} changed", summary.recomputed, prefix, summary.changed);
+        Ok(summary)
+    }
+
+    /// Every metric whose aggregate in memory differs from the stored one:
+    /// a different count or `last_seen`, or a sum further than `tolerance`
+    /// apart. Metrics held on only one side, or awaiting a flush, are not
+    /// reported.
+    pub async fn aggregate_mismatches(&self, tolerance: f64) -> Result<Vec<AggregateMismatch>> {
+        let mut mismatches = Vec::new();
+        let mut after = String::new();
+        loop {
+            let page = self.backend.aggregates_page(&after, RECOVERY_BATCH_SIZE).await?;
+            let full = page.len() == RECOVERY_BATCH_SIZE;
+            let cached = self.aggregates.read().unwrap();
+            let unflushed = self.unflushed.lock().unwrap();
+            for (name, stored) in page {
+                if let Some(cached) = cached.get(&name).filter(|_| !unflushed.contains_key(&name)) {
+                    if cached.count != stored.count
+                        || cached.last_seen != stored.last_seen
+                        || (cached.total() - stored.total()).abs() > tolerance
+                    {
+                        mismatches.push(AggregateMismatch {
+                            name: name.clone(),
+                            cached_count: cached.count,
+                            stored_count: stored.count,
+                            cached_sum: cached.total(),
+                            stored_sum: stored.total(),
+                            cached_last_seen: cached.last_seen,
+                            stored_last_seen: stored.last_seen,
+                        });
+                    }
+                }
+                after = name;
+            }
+            if !full {
+                break;
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Writes every aggregate deferred under [`AggregateWrite::Async`] to
+    /// the store in one batch, returning how many. Writes are held off
+    /// while it runs, so nothing it writes is older than memory.
+    pub async fn flush_aggregates(&self) -> Result<usize> {
+        let _sequences = self.sequences.write().await;
+        let _metrics = self.metrics.write().await;
+        let unflushed: Vec<_> = self
+            .unflushed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, aggregate)| (name.clone(), aggregate.clone()))
+            .collect();
+        if unflushed.is_empty() {
+            return Ok(0);
+        }
+        let writes: Vec<_> = unflushed
+            .iter()
+            .map(|(name, aggregate)| StagedWrite::Aggregate { name, aggregate: aggregate.clone() })
+            .collect();
+        self.backend.write_batch(&writes).await?;
+        self.unflushed.lock().unwrap().clear();
+        debug!("Flushed {} aggregates", unflushed.len());
+        Ok(unflushed.len())
+    }
+}
+
+/// Flushes deferred aggregates every `interval`; see
+/// [`MetricsRegistry::flush_aggregates`].
+pub async fn run_aggregate_flush(registry: Arc<MetricsRegistry>, interval: std::time::Duration) {
+    let mut timer = tokio::time::interval(interval);
+    loop {
+        timer.tick().await;
+        if let Err(e) = registry.flush_aggregates().await {
+            warn!("Aggregate flush failed: {}", e);
+        }
+    }
+}
+
+/// Periodically purges tombstones older than `grace_secs` and compacts old
+/// samples under `compaction`.
+pub async fn run_maintenance(
+    registry: Arc<MetricsRegistry>,
+    grace_secs: i64,
+    compaction: CompactionPolicy,
+    interval: std::time::Duration,
+) {
+    let mut timer = tokio::time::interval(interval);
+    loop {
+        timer.tick().await;
+        let now = chrono::Utc::now().timestamp();
+        if let Err(e) = registry.purge_tombstones(now - grace_secs).await {
+            warn!("Tombstone reaper failed: {}", e);
+        }
+        if let Err(e) = registry.compact_samples(&compaction, now).await {
+            warn!("Compaction failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::StorageBackendKind;
+
+    /// One empty registry per compiled-in storage backend.
+    fn registries() -> Vec<MetricsRegistry> {
+        StorageBackendKind::ALL
+            .iter()
+            .map(|kind| MetricsRegistry::with_backend(kind.open(None).unwrap()).unwrap())
+            .collect()
+    }
+
+    /// Every compiled-in backend that keeps its store on disk.
+    fn persistent_kinds() -> impl Iterator<Item = StorageBackendKind> {
+        StorageBackendKind::ALL.iter().copied().filter(|&kind| kind != StorageBackendKind::Memory)
+    }
+
+    /// A fresh path for a `kind` store in the temp directory.
+    fn store_path(prefix: &str, kind: StorageBackendKind) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.{}", prefix, uuid::Uuid::new_v4(), kind.name()));
+        path.to_str().unwrap().to_string()
+    }
+
+    /// A registry over the `kind` store at `path`, as a restarted worker opens it.
+    fn reopen(kind: StorageBackendKind, path: &str) -> MetricsRegistry {
+        MetricsRegistry::with_backend(kind.open(Some(path)).unwrap()).unwrap()
+    }
+
+    /// Removes the store at `path` and any SQLite journal beside it.
+    fn remove_store(path: &str) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    /// A memory store whose sample writes wait until `release` is
+    /// notified, announcing each on `stalled`.
+    struct StallingBackend {
+        inner: MemoryBackend,
+        stalled: Arc<tokio::sync::Notify>,
+        release: Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl MetricStorageBackend for StallingBackend {
+        fn name(&self) -> &'static str { "stalling" }
+        fn applied_index(&self) -> Result<Option<u64>> { self.inner.applied_index() }
+        async fn set_applied_index(&self, index: u64) -> Result<()> { self.inner.set_applied_index(index).await }
+        fn applied_seq(&self) -> Result<Option<u64>> { self.inner.applied_seq() }
+        fn raft_log(&self, from: u64) -> Result<RaftLogState> { self.inner.raft_log(from) }
+        async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()> { self.inner.append_raft_entries(entries).await }
+        async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()> { self.inner.set_raft_hard_state(hard_state).await }
+        async fn compact_raft_log(&self, index: u64) -> Result<()> { self.inner.compact_raft_log(index).await }
+        fn tombstones(&self) -> Result<Vec<(String, i64)>> { self.inner.tombstones() }
+        fn hooks(&self) -> Result<Vec<(String, String)>> { self.inner.hooks() }
+        async fn put_hook(&self, id: &str, config: &str) -> Result<()> { self.inner.put_hook(id, config).await }
+        fn transforms(&self) -> Result<Vec<(String, IngestTransform)>> { self.inner.transforms() }
+        async fn put_transform(&self, name: &str, transform: &IngestTransform) -> Result<()> { self.inner.put_transform(name, transform).await }
+        fn state_metrics(&self) -> Result<Vec<(String, String)>> { self.inner.state_metrics() }
+        async fn put_state_metric(&self, name: &str, config: &str) -> Result<()> { self.inner.put_state_metric(name, config).await }
+        fn histograms(&self) -> Result<Vec<(String, String)>> { self.inner.histograms() }
+        async fn put_histogram(&self, name: &str, config: &str) -> Result<()> { self.inner.put_histogram(name, config).await }
+        fn applied_keys(&self) -> Result<Vec<(String, i64)>> { self.inner.applied_keys() }
+        async fn put_applied_key(&self, key: &str, expires_at: i64) -> Result<()> { self.inner.put_applied_key(key, expires_at).await }
+        async fn prune_applied_keys(&self, watermark: i64) -> Result<()> { self.inner.prune_applied_keys(watermark).await }
+        async fn insert_sample(&self, name: &str, sample: MetricPoint, labels: &Labels, seq: u64, aggregate: Option<&MetricAggregate>) -> Result<()> {
+            self.inner.insert_sample(name, sample, labels, seq, aggregate).await
+        }
+        async fn put_aggregate(&self, name: &str, aggregate: &MetricAggregate) -> Result<()> { self.inner.put_aggregate(name, aggregate).await }
+        async fn recomputed_aggregates(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> { self.inner.recomputed_aggregates(prefix, after, limit).await }
+        async fn write_batch(&self, writes: &[StagedWrite<'_>]) -> Result<()> {
+            if writes.iter().any(|write| matches!(write, StagedWrite::Sample { .. })) {
+                self.stalled.notify_one();
+                self.release.notified().await;
+            }
+            self.inner.write_batch(writes).await
+        }
+        async fn latest(&self, name: &str, deleted_at: i64) -> Result<Option<MetricPoint>> { self.inner.latest(name, deleted_at).await }
+        async fn latest_many(&self, names: &[String]) -> Result<Vec<(String, MetricPoint)>> { self.inner.latest_many(names).await }
+        async fn aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> { self.inner.aggregate(name).await }
+        async fn recent(&self, name: &str, from: i64, limit: usize) -> Result<Vec<MetricPoint>> { self.inner.recent(name, from, limit).await }
+        async fn range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>> { self.inner.range_bounds(name, from, to).await }
+        async fn range_page(&self, name: &str, range: RangeQuery, after: Option<RangeCursor>, limit: usize) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)> {
+            self.inner.range_page(name, range, after, limit).await
+        }
+        async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>> { self.inner.buckets(name, from, to, step).await }
+        async fn group_by(&self, name: &str, query: &GroupByQuery) -> Result<(Vec<(String, f64)>, bool)> { self.inner.group_by(name, query).await }
+        async fn series(&self, name: &str, labels: &Labels, deleted_at: i64) -> Result<Option<SeriesSummary>> { self.inner.series(name, labels, deleted_at).await }
+        async fn quarantine(&self, name: &str, sample: &QuarantinedSample) -> Result<()> { self.inner.quarantine(name, sample).await }
+        async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>> { self.inner.quarantined(name).await }
+        async fn drop_quarantined(&self, name: &str) -> Result<()> { self.inner.drop_quarantined(name).await }
+        async fn delete(&self, name: &str, deleted_at: i64) -> Result<()> { self.inner.delete(name, deleted_at).await }
+        async fn purge(&self, expired: &[(String, i64)]) -> Result<()> { self.inner.purge(expired).await }
+        async fn compact(&self, name: &str, before: i64, interval: i64, limit: usize) -> Result<usize> { self.inner.compact(name, before, interval, limit).await }
+        async fn clear(&self) -> Result<()> { self.inner.clear().await }
+        async fn stats(&self) -> Result<StorageStats> { self.inner.stats().await }
+        async fn aggregates_page(&self, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> { self.inner.aggregates_page(after, limit).await }
+        async fn stale_page(&self, cutoff: i64, after: &str, limit: usize) -> Result<Vec<StaleMetric>> { self.inner.stale_page(cutoff, after, limit).await }
+        async fn latest_between(&self, after: &str, last: &str) -> Result<Vec<(String, MetricPoint, u64)>> { self.inner.latest_between(after, last).await }
+        async fn all_latest(&self) -> Result<Vec<(String, f64)>> { self.inner.all_latest().await }
+        async fn aggregates_slice(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>> { self.inner.aggregates_slice(offset, limit).await }
+    }
+
+    /// A store counting the reads of single metrics' data that reach it.
+    struct CountingBackend {
+        inner: Box<dyn MetricStorageBackend>,
+        reads: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl CountingBackend {
+        fn read(&self) {
+            self.reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MetricStorageBackend for CountingBackend {
+        fn name(&self) -> &'static str { self.inner.name() }
+        fn applied_index(&self) -> Result<Option<u64>> { self.inner.applied_index() }
+        async fn set_applied_index(&self, index: u64) -> Result<()> { self.inner.set_applied_index(index).await }
+        fn applied_seq(&self) -> Result<Option<u64>> { self.inner.applied_seq() }
+        fn raft_log(&self, from: u64) -> Result<RaftLogState> { self.inner.raft_log(from) }
+        async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()> { self.inner.append_raft_entries(entries).await }
+        async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()> { self.inner.set_raft_hard_state(hard_state).await }
+        async fn compact_raft_log(&self, index: u64) -> Result<()> { self.inner.compact_raft_log(index).await }
+        fn tombstones(&self) -> Result<Vec<(String, i64)>> { self.inner.tombstones() }
+        fn hooks(&self) -> Result<Vec<(String, String)>> { self.inner.hooks() }
+        async fn put_hook(&self, id: &str, config: &str) -> Result<()> { self.inner.put_hook(id, config).await }
+        fn transforms(&self) -> Result<Vec<(String, IngestTransform)>> { self.inner.transforms() }
+        async fn put_transform(&self, name: &str, transform: &IngestTransform) -> Result<()> { self.inner.put_transform(name, transform).await }
+        fn state_metrics(&self) -> Result<Vec<(String, String)>> { self.inner.state_metrics() }
+        async fn put_state_metric(&self, name: &str, config: &str) -> Result<()> { self.inner.put_state_metric(name, config).await }
+        fn histograms(&self) -> Result<Vec<(String, String)>> { self.inner.histograms() }
+        async fn put_histogram(&self, name: &str, config: &str) -> Result<()> { self.inner.put_histogram(name, config).await }
+        fn applied_keys(&self) -> Result<Vec<(String, i64)>> { self.inner.applied_keys() }
+        async fn put_applied_key(&self, key: &str, expires_at: i64) -> Result<()> { self.inner.put_applied_key(key, expires_at).await }
+        async fn prune_applied_keys(&self, watermark: i64) -> Result<()> { self.inner.prune_applied_keys(watermark).await }
+        async fn insert_sample(&self, name: &str, sample: MetricPoint, labels: &Labels, seq: u64, aggregate: Option<&MetricAggregate>) -> Result<()> {
+            self.inner.insert_sample(name, sample, labels, seq, aggregate).await
+        }
+        async fn put_aggregate(&self, name: &str, aggregate: &MetricAggregate) -> Result<()> { self.inner.put_aggregate(name, aggregate).await }
+        async fn recomputed_aggregates(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> { self.inner.recomputed_aggregates(prefix, after, limit).await }
+        async fn write_batch(&self, writes: &[StagedWrite<'_>]) -> Result<()> { self.inner.write_batch(writes).await }
+        async fn latest(&self, name: &str, deleted_at: i64) -> Result<Option<MetricPoint>> {
+            self.read();
+            self.inner.latest(name, deleted_at).await
+        }
+        async fn latest_many(&self, names: &[String]) -> Result<Vec<(String, MetricPoint)>> {
+            self.read();
+            self.inner.latest_many(names).await
+        }
+        async fn aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
+            self.read();
+            self.inner.aggregate(name).await
+        }
+        async fn recent(&self, name: &str, from: i64, limit: usize) -> Result<Vec<MetricPoint>> {
+            self.read();
+            self.inner.recent(name, from, limit).await
+        }
+        async fn range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>> {
+            self.read();
+            self.inner.range_bounds(name, from, to).await
+        }
+        async fn range_page(&self, name: &str, range: RangeQuery, after: Option<RangeCursor>, limit: usize) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)> {
+            self.read();
+            self.inner.range_page(name, range, after, limit).await
+        }
+        async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>> {
+            self.read();
+            self.inner.buckets(name, from, to, step).await
+        }
+        async fn group_by(&self, name: &str, query: &GroupByQuery) -> Result<(Vec<(String, f64)>, bool)> {
+            self.read();
+            self.inner.group_by(name, query).await
+        }
+        async fn series(&self, name: &str, labels: &Labels, deleted_at: i64) -> Result<Option<SeriesSummary>> {
+            self.read();
+            self.inner.series(name, labels, deleted_at).await
+        }
+        async fn quarantine(&self, name: &str, sample: &QuarantinedSample) -> Result<()> { self.inner.quarantine(name, sample).await }
+        async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>> { self.inner.quarantined(name).await }
+        async fn drop_quarantined(&self, name: &str) -> Result<()> { self.inner.drop_quarantined(name).await }
+        async fn delete(&self, name: &str, deleted_at: i64) -> Result<()> { self.inner.delete(name, deleted_at).await }
+        async fn purge(&self, expired: &[(String, i64)]) -> Result<()> { self.inner.purge(expired).await }
+        async fn compact(&self, name: &str, before: i64, interval: i64, limit: usize) -> Result<usize> { self.inner.compact(name, before, interval, limit).await }
+        async fn clear(&self) -> Result<()> { self.inner.clear().await }
+        async fn stats(&self) -> Result<StorageStats> { self.inner.stats().await }
+        async fn aggregates_page(&self, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> { self.inner.aggregates_page(after, limit).await }
+        async fn stale_page(&self, cutoff: i64, after: &str, limit: usize) -> Result<Vec<StaleMetric>> { self.inner.stale_page(cutoff, after, limit).await }
+        async fn latest_between(&self, after: &str, last: &str) -> Result<Vec<(String, MetricPoint, u64)>> { self.inner.latest_between(after, last).await }
+        async fn all_latest(&self) -> Result<Vec<(String, f64)>> { self.inner.all_latest().await }
+        async fn aggregates_slice(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>> { self.inner.aggregates_slice(offset, limit).await }
+    }
+
+    #[test]
+    fn test_collector_conflict_fails_construction_instead_of_panicking() {
+        let conflicting = Registry::new();
+        conflicting
+            .register(Box::new(IntCounter::new("requests_total", "already taken").unwrap()))
+            .unwrap();
+
+        let backend = Box::new(MemoryBackend::new());
+        let result = MetricsRegistry::with_backend_registering(backend, || {
+            register_collectors_with(&conflicting)
+                .map_err(|e| crate::RaftMetricsError::Internal(e.to_string()))
+        });
+        assert!(result.is_err());
+        assert!(register_collectors().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_reads_proceed_while_a_write_is_stalled() {
+        let stalled = Arc::new(tokio::sync::Notify::new());
+        let release = Arc::new(tokio::sync::Notify::new());
+        let backend = StallingBackend { inner: MemoryBackend::new(), stalled: stalled.clone(), release: release.clone() };
+        let registry = MetricsRegistry::with_backend(Box::new(backend)).unwrap();
+
+        let first = tokio::spawn({
+            let registry = registry.clone();
+            async move { registry.record_metric_with_timestamp("cpu", 1.0, 100).await }
+        });
+        stalled.notified().await;
+        release.notify_one();
+        first.await.unwrap().unwrap();
+
+        let second = tokio::spawn({
+            let registry = registry.clone();
+            async move { registry.record_metric_with_timestamp("cpu", 3.0, 200).await }
+        });
+        stalled.notified().await;
+
+        // The second write is parked in the store, holding the write path
+        let aggregate = tokio::time::timeout(std::time::Duration::from_secs(1), registry.get_metric_aggregate("cpu"))
+            .await
+            .expect("aggregate read waited for the stalled write")
+            .unwrap()
+            .unwrap();
+        assert_eq!(aggregate.count, 1);
+        assert_eq!(aggregate.sum, 1.0);
+        assert!(!second.is_finished());
+
+        release.notify_one();
+        second.await.unwrap().unwrap();
+        assert_eq!(registry.get_metric_aggregate("cpu").await.unwrap().unwrap().count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_metric_is_not_resurrected_by_replay() {
+        for registry in registries() {
+            registry.record_metric_with_timestamp("disk", 1.0, 100).await.unwrap();
+            registry.record_metric_with_timestamp("disk", 2.0, 200).await.unwrap();
+
+            registry
+                .apply_operation(&MetricOperation::Delete { name: "disk".into(), deleted_at: 300 })
+                .await
+                .unwrap();
+            assert_eq!(registry.get_metric("disk").await.unwrap(), None);
+
+            // A buffered write from before the delete replays late
+            registry
+                .apply_operation(&MetricOperation::record("disk", 3.0, 250, Labels::new()))
+                .await
+                .unwrap();
+            assert_eq!(registry.get_metric("disk").await.unwrap(), None);
+            assert!(registry.get_metric_aggregate("disk").await.unwrap().is_none());
+
+            let stats = registry.storage_stats().await.unwrap();
+            assert_eq!(stats.tombstones, 1);
+            assert_eq!(stats.sample_rows, 2);
+
+            // Not yet past the grace period
+            assert_eq!(registry.purge_tombstones(299).await.unwrap(), 0);
+            assert_eq!(registry.purge_tombstones(300 + DEFAULT_TOMBSTONE_GRACE_SECS).await.unwrap(), 1);
+
+            let stats = registry.storage_stats().await.unwrap();
+            assert_eq!(stats.tombstones, 0);
+            assert_eq!(stats.sample_rows, 0);
+            assert_eq!(registry.get_metric("disk").await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_range_bounds_bracket_the_window() {
+        for registry in registries() {
+            for (value, timestamp) in [(5.0, 100), (8.0, 200), (20.0, 300), (50.0, 400)] {
+                registry.record_metric_with_timestamp("bytes_sent", value, timestamp).await.unwrap();
+            }
+
+            let (earliest, latest) = registry.get_range_bounds("bytes_sent", 150, 350).await.unwrap().unwrap();
+            assert_eq!(earliest, MetricPoint { value: 8.0, timestamp: 200 });
+            assert_eq!(latest, MetricPoint { value: 20.0, timestamp: 300 });
+            assert_eq!(latest.value - earliest.value, 12.0);
+
+            assert!(registry.get_range_bounds("bytes_sent", 500, 600).await.unwrap().is_none());
+            assert!(registry.get_range_bounds("unknown", 0, 1000).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_after_delete_starts_fresh() {
+        for registry in registries() {
+            registry.record_metric_with_timestamp("mem", 10.0, 100).await.unwrap();
+            registry.delete_metric("mem", 150).await.unwrap();
+            registry.record_metric_with_timestamp("mem", 4.0, 200).await.unwrap();
+
+            assert_eq!(registry.get_metric("mem").await.unwrap(), Some(4.0));
+            let aggregate = registry.get_metric_aggregate("mem").await.unwrap().unwrap();
+            assert_eq!(aggregate.count, 1);
+            assert_eq!(aggregate.max, 4.0);
+        }
+    }
+
+    /// Renders the full in-memory state in a stable order.
+    async fn snapshot(registry: &MetricsRegistry) -> String {
+        let metrics: std::collections::BTreeMap<_, _> = registry.metrics.read().await.clone().into_iter().collect();
+        let aggregates: std::collections::BTreeMap<_, _> = registry.aggregates.read().unwrap().clone().into_iter().collect();
+        format!("{:?}\n{:?}\n{}", metrics, aggregates, registry.applied_index())
+    }
+
+    #[tokio::test]
+    async fn test_recover_warms_the_most_recently_written_series() {
+        for kind in persistent_kinds() {
+            let path = store_path("warm", kind);
+
+            {
+                let clock = Arc::new(std::sync::atomic::AtomicI64::new(100));
+                let written = clock.clone();
+                let registry = reopen(kind, &path)
+                    .with_clock(Arc::new(move || written.load(std::sync::atomic::Ordering::SeqCst)));
+                // Written newest first by name, so name order is not warmth order
+                for i in (0..6).rev() {
+                    clock.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    registry.record_metric_with_timestamp(&format!("series_{}", i), i as f64, 1_000).await.unwrap();
+                    registry.record_metric_with_timestamp(&format!("series_{}", i), i as f64 * 2.0, 1_001).await.unwrap();
+                }
+            }
+
+            let reads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let backend = CountingBackend { inner: kind.open(Some(&path)).unwrap(), reads: reads.clone() };
+            let registry = MetricsRegistry::with_backend(Box::new(backend)).unwrap()
+                .with_memory_budget(MemoryBudget { warm_entries: Some(3), ..Default::default() });
+            let summary = registry.recover_with_batch_size(2).await.unwrap();
+            assert_eq!((summary.series, summary.warmed), (6, 3));
+            let read_count = || reads.load(std::sync::atomic::Ordering::SeqCst);
+            let resident = |registry: &MetricsRegistry| {
+                let mut names: Vec<String> = registry.aggregates.read().unwrap().keys().cloned().collect();
+                names.sort();
+                names
+            };
+            assert_eq!(resident(&registry), vec!["series_0", "series_1", "series_2"]);
+            assert_eq!(registry.metrics.read().await.len(), 3);
+
+            // Warmed series are read from memory, leaving the resident set as it was
+            for i in 0..3 {
+                let aggregate = registry.get_metric_aggregate(&format!("series_{}", i)).await.unwrap().unwrap();
+                assert_eq!((aggregate.count, aggregate.total()), (2, i as f64 * 3.0));
+                assert_eq!(registry.get_metric(&format!("series_{}", i)).await.unwrap(), Some(i as f64 * 2.0));
+            }
+            assert_eq!(resident(&registry).len(), 3);
+            assert_eq!(read_count(), 0, "{} store answered reads of warmed series", kind.name());
+
+            // A series left cold is read from the store and becomes resident
+            let aggregate = registry.get_metric_aggregate("series_5").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.total()), (2, 15.0));
+            assert_eq!(resident(&registry).len(), 4);
+            assert!(read_count() > 0);
+
+            drop(registry);
+            remove_store(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_restores_full_state() {
+        for kind in persistent_kinds() {
+            let path = store_path("recover", kind);
+
+            let before = {
+                let registry = reopen(kind, &path);
+                for i in 0..50 {
+                    let name = format!("series_{}", i % 7);
+                    registry.record_metric_with_timestamp(&name, i as f64 * 1.5, 1_000 + i).await.unwrap();
+                }
+                registry.delete_metric("series_3", 2_000).await.unwrap();
+                registry.record_metric_with_timestamp("series_4", -2.0, 3_000).await.unwrap();
+                registry.set_applied_index(42).await.unwrap();
+                snapshot(&registry).await
+            };
+
+            let registry = reopen(kind, &path);
+            assert!(registry.metrics.read().await.is_empty());
+
+            let summary = registry.recover_with_batch_size(4).await.unwrap();
+            assert_eq!(summary.series, 6);
+            assert_eq!(summary.applied_index, 42);
+            assert_eq!(snapshot(&registry).await, before);
+
+            drop(registry);
+            remove_store(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_all_aggregates_take_the_newer_copy_and_report_mismatches() {
+        for registry in registries() {
+            for name in ["cpu", "disk", "mem"] {
+                registry.record_metric_with_timestamp(name, 1.0, 100).await.unwrap();
+                registry.record_metric_with_timestamp(name, 3.0, 110).await.unwrap();
+            }
+            assert!(registry.aggregate_mismatches(1e-9).await.unwrap().is_empty());
+
+            {
+                let mut cached = registry.aggregates.write().unwrap();
+                // Memory lags the store for cpu and leads it for mem
+                let cpu = cached.get_mut("cpu").unwrap();
+                cpu.count = 1;
+                cpu.sum = 1.0;
+                cpu.last_seen -= 10;
+                let mem = cached.get_mut("mem").unwrap();
+                mem.count = 3;
+                mem.sum = 9.0;
+                mem.last_seen += 10;
+            }
+
+            let all = registry.get_all_aggregates(0, usize::MAX).await.unwrap();
+            let counts: Vec<_> = all.iter().map(|(name, aggregate)| (name.as_str(), aggregate.count)).collect();
+            assert_eq!(counts, vec![("cpu", 2), ("disk", 2), ("mem", 3)]);
+            let page = registry.get_all_aggregates(1, 1).await.unwrap();
+            assert_eq!(page.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["disk"]);
+
+            let mismatches = registry.aggregate_mismatches(1e-9).await.unwrap();
+            assert_eq!(mismatches.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["cpu", "mem"]);
+            assert_eq!((mismatches[0].cached_count, mismatches[0].stored_count), (1, 2));
+            assert_eq!((mismatches[1].cached_sum, mismatches[1].stored_sum), (9.0, 4.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recompute_rebuilds_corrupted_aggregates_from_samples() {
+        for registry in registries() {
+            for (value, timestamp) in [(4.0, 100), (1.0, 120), (7.0, 110)] {
+                registry.record_metric_with_timestamp("cpu", value, timestamp).await.unwrap();
+            }
+            let seen = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            let corrupted = MetricAggregate { count: 9, sum: 100.0, average: 11.0, min: -5.0, max: 50.0, m2: 0.0, ..seen.clone() };
+            registry.backend.put_aggregate("cpu", &corrupted).await.unwrap();
+            registry.aggregates.write().unwrap().insert("cpu".to_string(), corrupted);
+
+            registry.record_metric_with_timestamp("mem", 2.0, 100).await.unwrap();
+            let summary = registry.recompute_all_aggregates("").await.unwrap();
+            assert_eq!((summary.recomputed, summary.changed), (2, 1));
+            let delta = &summary.largest[0];
+            assert_eq!((delta.name.as_str(), delta.count, delta.sum), ("cpu", -6, -88.0));
+            assert_eq!((delta.min, delta.max), (6.0, -43.0));
+            // Scoped to a prefix, only matching metrics are rebuilt
+            assert_eq!(registry.recompute_all_aggregates("me").await.unwrap().recomputed, 1);
+
+            let samples = registry.get_recent("cpu", 100, None).await.unwrap().unwrap();
+            let values: Vec<f64> = samples.iter().map(|point| point.value).collect();
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!(aggregate.count, values.len() as u64);
+            assert_eq!(aggregate.sum, values.iter().sum::<f64>());
+            assert_eq!((aggregate.min, aggregate.max), (1.0, 7.0));
+            assert_eq!(aggregate.average, 4.0);
+            assert!((aggregate.stddev() - 6.0_f64.sqrt()).abs() < 1e-9);
+            assert_eq!((aggregate.first_seen, aggregate.last_seen), (seen.first_seen, seen.last_seen));
+            assert!(registry.aggregate_mismatches(1e-9).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recompute_keeps_samples_written_while_it_runs() {
+        for registry in registries() {
+            let names: Vec<String> = (0..RECOMPUTE_BATCH_SIZE * 2).map(|i| format!("m{:05}", i)).collect();
+            for name in &names {
+                registry.record_metric_with_timestamp(name, 1.0, 100).await.unwrap();
+            }
+            let writes = async {
+                for (i, name) in names.iter().enumerate().step_by(50) {
+                    registry.record_metric_with_timestamp(name, 2.0, 200 + i as i64).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            };
+            let (summary, ()) = tokio::join!(registry.recompute_all_aggregates(""), writes);
+            assert_eq!(summary.unwrap().recomputed, names.len());
+
+            for (i, name) in names.iter().enumerate() {
+                let aggregate = registry.get_metric_aggregate(name).await.unwrap().unwrap();
+                let expected = if i % 50 == 0 { (2, 3.0) } else { (1, 1.0) };
+                assert_eq!((aggregate.count, aggregate.sum), expected, "{}", name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_aggregate_writes_wait_for_a_flush() {
+        let deferred = AggregateWrite::Async { interval: std::time::Duration::from_secs(60) };
+        for registry in registries() {
+            let registry = registry.with_aggregate_write(deferred);
+            for i in 1..=200 {
+                registry.record_metric_with_timestamp("cpu", i as f64, i).await.unwrap();
+            }
+            registry.record_metric_with_timestamp("mem", 7.0, 100).await.unwrap();
+
+            // Samples are stored as they arrive; none of the 201 upserts are
+            assert_eq!(registry.backend.latest("cpu", i64::MIN).await.unwrap().unwrap().value, 200.0);
+            assert!(registry.backend.aggregate("cpu").await.unwrap().is_none());
+            let cached = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!((cached.count, cached.sum, cached.min, cached.max), (200, 20_100.0, 1.0, 200.0));
+            assert!(registry.aggregate_mismatches(0.0).await.unwrap().is_empty());
+
+            assert_eq!(registry.flush_aggregates().await.unwrap(), 2);
+            let stored = registry.backend.aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!(
+                (stored.count, stored.total(), stored.min, stored.max, stored.first_seen, stored.last_seen),
+                (cached.count, cached.total(), cached.min, cached.max, cached.first_seen, cached.last_seen)
+            );
+            assert_eq!(registry.backend.aggregate("mem").await.unwrap().unwrap().count, 1);
+            assert_eq!(registry.flush_aggregates().await.unwrap(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_aggregates_outlive_eviction_but_not_deletion() {
+        for registry in registries() {
+            let registry = registry
+                .with_memory_budget(MemoryBudget { max_entries: Some(2), hot_entries: 1, ..Default::default() })
+                .with_aggregate_write(AggregateWrite::Async { interval: std::time::Duration::from_secs(60) });
+            for round in 0..2 {
+                for i in 0..6 {
+                    registry.record_metric_with_timestamp(&format!("series_{}", i), i as f64, 100 + round).await.unwrap();
+                }
+            }
+            registry.delete_metric("series_5", 200).await.unwrap();
+
+            // Evicted before any flush, yet reads and the next write see both samples
+            assert_eq!(registry.get_metric_aggregate("series_0").await.unwrap().unwrap().count, 2);
+            registry.record_metric_with_timestamp("series_0", 0.0, 300).await.unwrap();
+            let all = registry.get_all_aggregates(0, usize::MAX).await.unwrap();
+            assert_eq!(all.len(), 5);
+            assert_eq!(all[0].1.count, 3);
+
+            registry.flush_aggregates().await.unwrap();
+            assert_eq!(registry.backend.aggregate("series_0").await.unwrap().unwrap().count, 3);
+            assert!(registry.backend.aggregate("series_5").await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_empties_every_read() {
+        for registry in registries() {
+            registry.record_metric_with_timestamp("cpu", 1.0, 100).await.unwrap();
+            registry.record_metric_with_timestamp("mem", 2.0, 100).await.unwrap();
+            registry.delete_metric("mem", 150).await.unwrap();
+
+            registry.apply_operation(&MetricOperation::Clear).await.unwrap();
+
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), None);
+            assert!(registry.get_metric_aggregate("cpu").await.unwrap().is_none());
+            assert!(registry.get_range_bounds("cpu", 0, 1_000).await.unwrap().is_none());
+            assert!(registry.get_all_metrics().await.unwrap().is_empty());
+            assert!(registry.get_all_aggregates(0, usize::MAX).await.unwrap().is_empty());
+            assert_eq!(registry.storage_stats().await.unwrap(), StorageStats::default());
+
+            // Writes work normally afterwards
+            registry.record_metric_with_timestamp("mem", 3.0, 120).await.unwrap();
+            assert_eq!(registry.get_metric("mem").await.unwrap(), Some(3.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_writes_are_sequenced_within_the_last_applied_entry() {
+        for registry in registries() {
+            registry.apply_raft_batch(3, &MetricsRegistry::serialize_batch(&[MetricOperation::record("mem", 1.0, 100, Labels::new())], RaftCodec::default()).unwrap())
+                .await
+                .unwrap();
+            registry.set_applied_index(3).await.unwrap();
+
+            registry.record_metric_with_timestamp("cpu", 1.0, 100).await.unwrap();
+            registry.record_metric_with_timestamp("mem", 2.0, 100).await.unwrap();
+            let seq = |name: &'static str| {
+                let registry = &registry;
+                async move { registry.sequences.read().await.get(name).copied() }
+            };
+            assert_eq!(seq("cpu").await, Some(operation_seq(3, 0)));
+            assert_eq!(seq("mem").await, Some(operation_seq(3, 1)));
+
+            // The next entry's writes still apply after them
+            registry.apply_sequenced(operation_seq(4, 0), &MetricOperation::record("cpu", 5.0, 100, Labels::new())).await.unwrap();
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(5.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_forgets_each_metrics_sequence() {
+        for registry in registries() {
+            registry.apply_sequenced(operation_seq(5, 0), &MetricOperation::record("cpu", 1.0, 100, Labels::new())).await.unwrap();
+            registry.apply_sequenced(operation_seq(6, 0), &MetricOperation::Clear).await.unwrap();
+
+            // A write sequenced below the metric's last one before the clear still applies
+            registry.apply_sequenced(operation_seq(2, 0), &MetricOperation::record("cpu", 2.0, 200, Labels::new())).await.unwrap();
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replayed_entry_is_applied_once() {
+        for registry in registries() {
+            let entry = MetricsRegistry::serialize_batch(&[
+                MetricOperation::record("cpu", 1.0, 100, Labels::new()),
+                MetricOperation::record("cpu", 3.0, 100, Labels::new()),
+            ], RaftCodec::Json)
+            .unwrap();
+
+            registry.apply_raft_batch(7, &entry).await.unwrap();
+            registry.apply_raft_batch(7, &entry).await.unwrap();
+
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!(aggregate.count, 2);
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(3.0));
+
+            // An older entry arriving late is ignored too
+            let stale = MetricsRegistry::serialize_batch(&[
+                MetricOperation::record("cpu", 9.0, 200, Labels::new()),
+            ], RaftCodec::Bincode)
+            .unwrap();
+            registry.apply_raft_batch(6, &stale).await.unwrap();
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(3.0));
+        }
+    }
+
+    fn queue_transaction() -> MetricOperation {
+        MetricOperation::Transaction(vec![
+            MetricOperation::record("queue_depth", 4.0, 100, Labels::new()),
+            MetricOperation::record("queue_pushes", 12.0, 100, Labels::from([("queue".into(), "jobs".into())])),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_transaction_applies_every_record() {
+        for registry in registries() {
+            let entry = MetricsRegistry::serialize_batch(&[queue_transaction()], RaftCodec::Protobuf).unwrap();
+            let results = registry.apply_raft_batch(3, &entry).await.unwrap();
+            assert!(results[0].is_ok());
+            assert_eq!(registry.get_metric("queue_depth").await.unwrap(), Some(4.0));
+            assert_eq!(registry.get_metric("queue_pushes").await.unwrap(), Some(12.0));
+
+            // Replayed, it is skipped like any other entry
+            registry.apply_raft_batch(3, &entry).await.unwrap();
+            assert_eq!(registry.get_metric_aggregate("queue_pushes").await.unwrap().unwrap().count, 1);
+
+            // A transaction that cannot apply in full applies nothing
+            let invalid = MetricOperation::Transaction(vec![
+                MetricOperation::record("queue_depth", 5.0, 200, Labels::new()),
+                MetricOperation::Delete { name: "queue_depth".into(), deleted_at: 200 },
+            ]);
+            assert!(registry.apply_operation(&invalid).await.is_err());
+            assert_eq!(registry.get_metric("queue_depth").await.unwrap(), Some(4.0));
+            assert_eq!(registry.get_metric("queue_pushes").await.unwrap(), Some(12.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_zeroes_a_counter_and_restarts_its_aggregate() {
+        for registry in registries() {
+            for keep_history in [true, false] {
+                let name = format!("requests_total_{}", keep_history);
+                for (value, timestamp) in [(10.0, 100), (25.0, 200)] {
+                    registry.record_metric_with_timestamp(&name, value, timestamp).await.unwrap();
+                }
+                let reset = MetricOperation::Reset { name: name.clone(), reset_at: 300, keep_history };
+                let entry = MetricsRegistry::serialize_batch(&[reset], RaftCodec::Protobuf).unwrap();
+                assert!(registry.apply_raft_batch(40, &entry).await.unwrap()[0].is_ok());
+                assert_eq!(registry.get_metric(&name).await.unwrap(), Some(0.0));
+                let aggregate = registry.get_metric_aggregate(&name).await.unwrap().unwrap();
+                assert_eq!((aggregate.count, aggregate.total(), aggregate.max), (1, 0.0, 0.0));
+
+                // Counting resumes from zero
+                registry.record_metric_with_timestamp(&name, 4.0, 400).await.unwrap();
+                let aggregate = registry.get_metric_aggregate(&name).await.unwrap().unwrap();
+                assert_eq!((aggregate.count, aggregate.total(), aggregate.max), (2, 4.0, 4.0));
+                let recent: Vec<f64> = registry.get_recent(&name, 10, None).await.unwrap().unwrap().iter().map(|p| p.value).collect();
+                let expected = if keep_history { vec![4.0, 0.0, 25.0, 10.0] } else { vec![4.0, 0.0] };
+                assert_eq!(recent, expected, "keep_history={}", keep_history);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_with_its_delete_applies_all_or_nothing() {
+        for registry in registries() {
+            let registry = registry.with_max_distinct_metrics(Some(1));
+            for (value, timestamp) in [(10.0, 100), (25.0, 200)] {
+                registry.record_metric_with_timestamp("requests_total", value, timestamp).await.unwrap();
+            }
+            let reset = |reset_at: i64| {
+                vec![
+                    MetricOperation::Delete { name: "requests_total".into(), deleted_at: reset_at - 1 },
+                    MetricOperation::Reset { name: "requests_total".into(), reset_at, keep_history: true },
+                ]
+            };
+
+            // A refused write in the same transaction keeps the delete out too
+            let mut refused = reset(300);
+            refused.push(MetricOperation::record("errors_total", 1.0, 300, Labels::new()));
+            assert!(registry.apply_operation(&MetricOperation::Transaction(refused)).await.is_err());
+            assert_eq!(registry.get_metric("requests_total").await.unwrap(), Some(25.0));
+            assert_eq!(registry.get_metric_aggregate("requests_total").await.unwrap().unwrap().count, 2);
+
+            // Deleted and zeroed at once, the metric still counts once
+            registry.apply_operation(&MetricOperation::Transaction(reset(300))).await.unwrap();
+            assert_eq!(registry.get_metric("requests_total").await.unwrap(), Some(0.0));
+            assert_eq!(registry.get_metric_aggregate("requests_total").await.unwrap().unwrap().count, 1);
+            let recent: Vec<f64> = registry.get_recent("requests_total", 10, None).await.unwrap().unwrap().iter().map(|p| p.value).collect();
+            assert_eq!(recent, [0.0]);
+            assert_eq!(registry.storage_stats().await.unwrap().distinct_metrics, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_state_durations_follow_sample_timestamps() {
+        let breaker = |value: f64, timestamp: i64| MetricOperation::record("breaker", value, timestamp, Labels::new());
+        let mut script = vec![MetricOperation::DefineStates {
+            name: "breaker".into(),
+            states: vec!["closed".into(), "open".into(), "half_open".into()],
+            stale_after_secs: Some(60),
+        }];
+        script.extend([
+            (0.0, 1_000),
+            (0.0, 1_030),
+            (1.0, 1_050),
+            (2.0, 1_090),
+            (0.0, 1_100),
+            // 200s without a sample count as unknown
+            (0.0, 1_300),
+            (1.0, 1_320),
+            // Older than the latest sample, so left out
+            (0.0, 1_310),
+            (1.0, 1_500),
+            (0.0, 1_520),
+        ].map(|(value, timestamp)| breaker(value, timestamp)));
+        script.push(MetricOperation::Transaction(vec![breaker(2.0, 1_540)]));
+
+        for registry in registries() {
+            let now = Arc::new(std::sync::atomic::AtomicI64::new(1_560));
+            let clock = now.clone();
+            let registry = registry.with_clock(Arc::new(move || clock.load(std::sync::atomic::Ordering::SeqCst)));
+            for (index, operation) in script.iter().enumerate() {
+                let entry = MetricsRegistry::serialize_batch(std::slice::from_ref(operation), RaftCodec::Bincode).unwrap();
+                // Replaying every entry changes nothing
+                for _ in 0..2 {
+                    assert!(registry.apply_raft_batch(index as u64 + 1, &entry).await.unwrap()[0].is_ok());
+                }
+            }
+            let invalid = MetricsRegistry::serialize_batch(&[breaker(3.0, 1_550)], RaftCodec::Bincode).unwrap();
+            assert!(registry.apply_raft_batch(100, &invalid).await.unwrap()[0].is_err());
+
+            let report = registry.state_report("breaker").unwrap();
+            let durations: Vec<(&str, i64)> = report.durations.iter().map(|(state, secs)| (state.as_str(), *secs)).collect();
+            assert_eq!(durations, [("closed", 90), ("half_open", 10), ("open", 60), ("unknown", 380)]);
+            assert_eq!(report.transitions, 6);
+            assert_eq!((report.current.as_deref(), report.since, report.in_state_secs), (Some("half_open"), Some(1_540), Some(20)));
+
+            // Past the window since the latest sample, the state is unknown
+            now.store(1_700, std::sync::atomic::Ordering::SeqCst);
+            assert_eq!(registry.current_state("breaker").as_deref(), Some("unknown"));
+            assert_eq!(registry.state_report("breaker").unwrap().in_state_secs, Some(160));
+
+            // Deleting the metric keeps its states but forgets its progress
+            registry.delete_metric("breaker", 2_000).await.unwrap();
+            let report = registry.state_report("breaker").unwrap();
+            assert_eq!((report.current, report.transitions, report.durations["closed"]), (None, 0, 0));
+            assert!(registry.record_metric_with_timestamp("breaker", 0.5, 2_100).await.is_err());
+        }
+    }
+
+    #[cfg(feature = "duckdb-storage")]
+    #[tokio::test]
+    async fn test_failed_transaction_write_rolls_back_every_record() {
+        let backend = DuckDbBackend::open_in_memory().unwrap();
+        // New labeled series can no longer be stored
+        backend.execute_batch("DROP TABLE series_labels").unwrap();
+        let registry = MetricsRegistry::with_backend(Box::new(backend)).unwrap();
+
+        // A storage failure halts the entry rather than rejecting the write
+        let entry = MetricsRegistry::serialize_batch(&[queue_transaction()], RaftCodec::Protobuf).unwrap();
+        let halted = registry.apply_raft_batch(3, &entry).await.unwrap_err();
+        assert!(halts_apply(&halted), "{}", halted);
+        for name in ["queue_depth", "queue_pushes"] {
+            assert_eq!(registry.get_metric(name).await.unwrap(), None, "{}", name);
+            assert!(registry.get_metric_aggregate(name).await.unwrap().is_none(), "{}", name);
+        }
+
+        // The unlabeled record alone still goes through
+        registry.record_metric_with_timestamp("queue_depth", 4.0, 100).await.unwrap();
+        assert_eq!(registry.get_metric_aggregate("queue_depth").await.unwrap().unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_latest_follows_timestamp_then_commit_order_after_reopen() {
+        for kind in persistent_kinds() {
+            let path = store_path("seq", kind);
+
+            {
+                let registry = reopen(kind, &path);
+                let record = |value: f64, timestamp: i64| MetricOperation::record("cpu", value, timestamp, Labels::new());
+                registry.apply_sequenced(operation_seq(1, 0), &record(1.0, 500)).await.unwrap();
+                registry.apply_sequenced(operation_seq(2, 0), &record(2.0, 500)).await.unwrap();
+                registry.apply_sequenced(operation_seq(3, 0), &record(3.0, 400)).await.unwrap();
+                assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+            }
+
+            let registry = reopen(kind, &path);
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+            registry.recover().await.unwrap();
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+
+            // Recovery resumes sequencing after the highest applied seq, not the
+            // seq of the latest sample
+            registry.apply_sequenced(operation_seq(3, 0), &MetricOperation::record("cpu", 9.0, 900, Labels::new()))
+                .await
+                .unwrap();
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+
+            drop(registry);
+            remove_store(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entry_replayed_before_its_applied_index_is_stored_applies_once() {
+        for kind in persistent_kinds() {
+            let path = store_path("replay", kind);
+            // The repeat only counts in the aggregate, so no stored sample has its seq
+            let dedup = DedupWindow { interval: 60, count_skipped: true };
+            let repeat = MetricOperation::Record {
+                name: "cpu".to_string(),
+                value: 2.0,
+                timestamp: 510,
+                labels: Labels::new(),
+                admission: Admission { dedup: Some(dedup), ..Admission::default() },
+            };
+            let entry = MetricsRegistry::serialize_batch(&[MetricOperation::record("cpu", 2.0, 500, Labels::new()), repeat], RaftCodec::Protobuf)
+                .unwrap();
+
+            {
+                // Crashes before the entry's applied index is stored
+                let registry = reopen(kind, &path);
+                registry.apply_raft_batch(1, &entry).await.unwrap();
+                assert_eq!(registry.applied_index(), 0);
+            }
+
+            let registry = reopen(kind, &path);
+            registry.recover().await.unwrap();
+            assert_eq!(registry.applied_index(), 0);
+            registry.apply_raft_batch(1, &entry).await.unwrap();
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.sum), (2, 4.0), "{}", kind.name());
+
+            drop(registry);
+            remove_store(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keyed_operations_skip_alike_whatever_each_replica_clock_reads() {
+        let keyed = |proposed_at: i64, value: f64| {
+            let record = MetricOperation::record("cpu", value, proposed_at, Labels::new());
+            let keyed = MetricOperation::Keyed { key: "journal-1".into(), proposed_at, operation: Box::new(record) };
+            MetricsRegistry::serialize_batch(&[keyed], RaftCodec::Protobuf).unwrap()
+        };
+        // Proposed again within the TTL, then once it has run out
+        let entries = [keyed(1_000, 1.0), keyed(1_050, 2.0), keyed(1_101, 3.0)];
+
+        for (clock, drift) in [(1_000, 0), (1_000, 500), (90_000, -80_000)] {
+            let now = Arc::new(std::sync::atomic::AtomicI64::new(clock));
+            let read = now.clone();
+            let registry = MetricsRegistry::new()
+                .unwrap()
+                .with_idempotency_ttl(100)
+                .with_clock(Arc::new(move || read.load(std::sync::atomic::Ordering::SeqCst)));
+            for (index, entry) in (1..).zip(&entries) {
+                assert!(registry.apply_raft_batch(index, entry).await.unwrap().iter().all(Result::is_ok));
+                now.fetch_add(drift, std::sync::atomic::Ordering::SeqCst);
+            }
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.total()), (2, 4.0), "clock {} drifting {}", clock, drift);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keyed_operation_replayed_after_reopen_applies_once() {
+        for kind in persistent_kinds() {
+            let path = store_path("keyed", kind);
+            let record = MetricOperation::record("cpu", 4.0, 100, Labels::new());
+            let proposed_at = chrono::Utc::now().timestamp();
+            let keyed = MetricOperation::Keyed { key: "journal-1".into(), proposed_at, operation: Box::new(record) };
+            let entry = MetricsRegistry::serialize_batch(&[keyed], RaftCodec::Protobuf).unwrap();
+
+            {
+                let registry = reopen(kind, &path);
+                assert!(registry.apply_raft_batch(1, &entry).await.unwrap().iter().all(Result::is_ok));
+            }
+
+            // The journal proposes it again after the restart, under a later entry
+            let registry = reopen(kind, &path);
+            registry.recover().await.unwrap();
+            assert!(registry.apply_raft_batch(7, &entry).await.unwrap().iter().all(Result::is_ok));
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.total()), (1, 4.0));
+
+            // Once an operation proposed a TTL later has applied, the key has
+            // expired and been pruned, and no longer holds writes back
+            let later = MetricOperation::Keyed {
+                key: "journal-2".into(),
+                proposed_at: proposed_at + DEFAULT_IDEMPOTENCY_TTL_SECS,
+                operation: Box::new(MetricOperation::record("mem", 1.0, 100, Labels::new())),
+            };
+            let later = MetricsRegistry::serialize_batch(&[later], RaftCodec::Protobuf).unwrap();
+            assert!(registry.apply_raft_batch(8, &later).await.unwrap().iter().all(Result::is_ok));
+            assert!(!registry.applied_keys.lock().unwrap().contains_key("journal-1"));
+            assert!(registry.apply_raft_batch(9, &entry).await.unwrap().iter().all(Result::is_ok));
+            assert_eq!(registry.get_metric_aggregate("cpu").await.unwrap().unwrap().count, 2);
+
+            drop(registry);
+            remove_store(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_fall_back_to_store_after_reopen() {
+        for kind in persistent_kinds() {
+            let path = store_path("registry", kind);
+
+            {
+                let registry = reopen(kind, &path);
+                registry.record_metric("cpu", 10.0).await.unwrap();
+                registry.record_metric("cpu", 30.0).await.unwrap();
+            }
+
+            let registry = reopen(kind, &path);
+            assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(30.0));
+
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!(aggregate.count, 2);
+            assert_eq!(aggregate.sum, 40.0);
+            assert_eq!(aggregate.min, 10.0);
+            assert_eq!(aggregate.max, 30.0);
+
+            // Aggregation continues from the persisted state
+            registry.record_metric("cpu", 5.0).await.unwrap();
+            let aggregate = registry.get_metric_aggregate("cpu").await.unwrap().unwrap();
+            assert_eq!(aggregate.count, 3);
+            assert_eq!(aggregate.min, 5.0);
+
+            drop(registry);
+            remove_store(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_series_beyond_memory_budget_read_back_from_store() {
+        for registry in registries() {
+            let registry = registry.with_memory_budget(MemoryBudget { max_entries: Some(4), hot_entries: 1, ..Default::default() });
+            registry.record_metric_with_timestamp("hot", 1.0, 100).await.unwrap();
+            for i in 0..20 {
+                let name = format!("series_{}", i);
+                registry.record_metric_with_timestamp(&name, i as f64, 100).await.unwrap();
+                registry.record_metric_with_timestamp(&name, i as f64 * 10.0, 200).await.unwrap();
+                registry.get_metric("hot").await.unwrap();
+            }
+
+            assert!(registry.metrics.read().await.len() <= 4);
+            assert!(registry.aggregates.read().unwrap().len() <= 4);
+            assert!(registry.metrics.read().await.contains_key("hot"));
+
+            for i in 0..20 {
+                let name = format!("series_{}", i);
+                assert_eq!(registry.get_metric(&name).await.unwrap(), Some(i as f64 * 10.0));
+                let aggregate = registry.get_metric_aggregate(&name).await.unwrap().unwrap();
+                assert_eq!((aggregate.count, aggregate.sum), (2, i as f64 * 11.0));
+            }
+            assert_eq!(registry.get_all_metrics().await.unwrap().len(), 21);
+
+            // Writing to an evicted series continues its persisted aggregate
+            registry.record_metric_with_timestamp("series_0", 7.0, 300).await.unwrap();
+            let aggregate = registry.get_metric_aggregate("series_0").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.sum, aggregate.max), (3, 7.0, 7.0));
+            assert!(registry.metrics.read().await.len() <= 4);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_metrics_beyond_the_cardinality_limit_are_refused() {
+        for registry in registries() {
+            let registry = registry.with_max_distinct_metrics(Some(3)).with_memory_budget(MemoryBudget {
+                max_entries: Some(2),
+                hot_entries: 0,
+                ..Default::default()
+            });
+            for name in ["a", "b", "c"] {
+                registry.record_metric_with_timestamp(name, 1.0, 100).await.unwrap();
+            }
+            let rejected = CARDINALITY_REJECTED_TOTAL.get();
+
+            let err = registry.record_metric_with_timestamp("d", 1.0, 100).await.unwrap_err();
+            assert!(matches!(err, crate::RaftMetricsError::InvalidRequest(_)), "{}", err);
+            assert!(CARDINALITY_REJECTED_TOTAL.get() > rejected);
+            assert_eq!(registry.get_metric("d").await.unwrap(), None);
+            // Existing metrics are still written to, evicted ones included
+            for name in ["a", "b", "c"] {
+                registry.record_metric_with_timestamp(name, 2.0, 200).await.unwrap();
+            }
+            assert_eq!(registry.storage_stats().await.unwrap().distinct_metrics, 3);
+
+            let transaction = MetricOperation::Transaction(vec![
+                MetricOperation::record("a", 3.0, 300, Labels::new()),
+                MetricOperation::record("e", 3.0, 300, Labels::new()),
+            ]);
+            assert!(registry.apply_operation(&transaction).await.is_err());
+            assert_eq!(registry.get_metric("a").await.unwrap(), Some(2.0));
+
+            // Deleting a metric frees its slot
+            registry.delete_metric("a", 1_000).await.unwrap();
+            registry.record_metric_with_timestamp("d", 1.0, 100).await.unwrap();
+            assert_eq!(registry.storage_stats().await.unwrap().distinct_metrics, 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tenants_are_held_to_their_series_and_storage_quotas() {
+        let quotas = quotas::TenantQuotas::parse("acme=series:2;beta=bytes:100", ".").unwrap();
+        for registry in registries() {
+            let registry = registry.with_quotas(quotas.clone());
+            for name in ["acme.a", "acme.b"] {
+                registry.record_metric_with_timestamp(name, 1.0, 100).await.unwrap();
+            }
+            let err = registry.record_metric_with_timestamp("acme.c", 1.0, 100).await.unwrap_err();
+            assert_eq!(err.code(), crate::error::ErrorCode::QuotaExceeded, "{}", err);
+            // Existing series stay writable; other tenants and untenanted
+            // names are unaffected
+            registry.record_metric_with_timestamp("acme.a", 2.0, 200).await.unwrap();
+            registry.record_metric_with_timestamp("other", 1.0, 100).await.unwrap();
+
+            // Each beta sample is reckoned at 38 bytes, so the third is refused
+            for timestamp in [100, 200] {
+                registry.record_metric_with_timestamp("beta.x", 1.0, timestamp).await.unwrap();
+            }
+            assert!(registry.record_metric_with_timestamp("beta.x", 1.0, 300).await.is_err());
+
+            let usage = registry.usage_reports();
+            let acme = usage.iter().find(|report| report.tenant == "acme").unwrap();
+            assert_eq!(acme.series, 2);
+            assert_eq!(acme.quota.max_series, Some(2));
+
+            // Deleting a series frees its slot and its storage
+            registry.delete_metric("acme.b", 1_000).await.unwrap();
+            registry.record_metric_with_timestamp("acme.c", 1.0, 100).await.unwrap();
+            registry.delete_metric("beta.x", 1_000).await.unwrap();
+            registry.record_metric_with_timestamp("beta.x", 1.0, 2_000).await.unwrap();
+
+            // The proposing node checked it, so a replica applies what the
+            // log holds whatever its own quotas say
+            registry.apply_sequenced(operation_seq(1, 0), &MetricOperation::record("acme.d", 1.0, 100, Labels::new())).await.unwrap();
+            assert_eq!(registry.get_metric("acme.d").await.unwrap(), Some(1.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compaction_thins_old_samples_once() {
+        let policy = CompactionPolicy::from_lookup(|key| match key {
+            "COMPACTION_MIN_AGE_SECS" => Some("1000".to_string()),
+            "COMPACTION_INTERVAL_SECS" => Some("60".to_string()),
+            "COMPACTION_EXEMPT" => Some("exempt".to_string()),
+            "COMPACTION_BATCH_ROWS" => Some("100".to_string()),
+            _ => None,
+        });
+        for registry in registries() {
+            for timestamp in 0..600 {
+                registry.record_metric_with_timestamp("dense", timestamp as f64, timestamp).await.unwrap();
+                registry.record_metric_with_timestamp("exempt", timestamp as f64, timestamp).await.unwrap();
+            }
+
+            // Samples before 300 keep the last of each minute
+            assert_eq!(registry.compact_samples(&policy, 1_300).await.unwrap(), 295);
+            let stats = registry.storage_stats().await.unwrap();
+            assert_eq!((stats.sample_rows, stats.compacted_rows), (305 + 600, 295));
+            let at = |timestamp: i64| MetricPoint { value: timestamp as f64, timestamp };
+            assert_eq!(registry.get_range_bounds("dense", 0, 119).await.unwrap(), Some((at(59), at(119))));
+            assert_eq!(registry.get_range_bounds("dense", 290, 310).await.unwrap(), Some((at(299), at(310))));
+            assert_eq!(registry.get_range_bounds("exempt", 0, 119).await.unwrap(), Some((at(0), at(119))));
+            assert_eq!(registry.get_metric("dense").await.unwrap(), Some(599.0));
+            assert_eq!(registry.get_metric_aggregate("dense").await.unwrap().unwrap().count, 600);
+
+            // Re-running finds nothing left to thin
+            assert_eq!(registry.compact_samples(&policy, 1_300).await.unwrap(), 0);
+            let stats = registry.storage_stats().await.unwrap();
+            assert_eq!((stats.sample_rows, stats.compacted_rows), (305 + 600, 0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dedup_stores_a_constant_series_once_per_interval() {
+        let policy = |count_skipped: bool| DedupPolicy { interval: Some(60), count_skipped, ..Default::default() };
+        for kind in StorageBackendKind::ALL {
+            let plain = MetricsRegistry::with_backend(kind.open(None).unwrap()).unwrap();
+            let counted = MetricsRegistry::with_backend(kind.open(None).unwrap()).unwrap().with_dedup(policy(true));
+            let uncounted = MetricsRegistry::with_backend(kind.open(None).unwrap()).unwrap().with_dedup(policy(false));
+            // A replica skips what the proposer's window says, whatever its own policy
+            let replica = MetricsRegistry::new().unwrap().with_dedup(policy(true));
+            for registry in [&plain, &counted, &uncounted] {
+                let samples = (0..300).map(|i| (5.0, 1_000 + i)).chain([(7.0, 1_300)]);
+                for (value, timestamp) in samples {
+                    let operation = registry.admit(MetricOperation::record("gauge", value, timestamp, Labels::new())).unwrap();
+                    registry.apply_operation(&operation).await.unwrap();
+                    if std::ptr::eq(registry, &uncounted) {
+                        replica.apply_operation(&operation).await.unwrap();
+                    }
+                }
+            }
+
+            assert_eq!(plain.storage_stats().await.unwrap().sample_rows, 301);
+            // One row per minute of the flat run, plus the change
+            assert_eq!(counted.storage_stats().await.unwrap().sample_rows, 6);
+            assert_eq!(uncounted.storage_stats().await.unwrap().sample_rows, 6);
+
+            assert_eq!(replica.storage_stats().await.unwrap().sample_rows, 6);
+            for (registry, expected) in [(&plain, (301, 1507.0)), (&counted, (301, 1507.0)), (&uncounted, (6, 32.0)), (&replica, (6, 32.0))] {
+                let aggregate = registry.get_metric_aggregate("gauge").await.unwrap().unwrap();
+                assert_eq!((aggregate.count, aggregate.sum), expected);
+            }
+
+            // The last stored sample of the flat run still bounds the step
+            let bounds = counted.get_range_bounds("gauge", 1_200, 1_300).await.unwrap().unwrap();
+            assert_eq!(bounds.0, MetricPoint { value: 5.0, timestamp: 1_240 });
+            assert_eq!(counted.get_latest("gauge").await.unwrap(), Some(MetricPoint { value: 7.0, timestamp: 1_300 }));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outliers_are_quarantined_until_applied() {
+        let policy = OutlierPolicy {
+            metrics: std::collections::HashSet::from(["latency".to_string()]),
+            stddevs: Some(3.0),
+            min_samples: 5,
+            ..Default::default()
+        };
+        for registry in registries() {
+            let registry = registry.with_outliers(policy.clone());
+            // A replica screens by the bounds in the entry, whatever its own policy
+            let replica = MetricsRegistry::new().unwrap();
+            let record = |value: f64, timestamp: i64| {
+                let operation = registry.admit(MetricOperation::record("latency", value, timestamp, Labels::new())).unwrap();
+                let (registry, replica) = (&registry, &replica);
+                async move {
+                    registry.apply_operation(&operation).await.unwrap();
+                    replica.apply_operation(&operation).await.unwrap();
+                }
+            };
+            // Too few samples to judge, so the early spike is kept
+            for (timestamp, value) in [10.0, 500.0, 11.0, 9.0, 10.0, 12.0, 8.0, 10.0].into_iter().enumerate() {
+                record(value, timestamp as i64).await;
+            }
+            let before = registry.get_metric_aggregate("latency").await.unwrap().unwrap();
+            assert_eq!((before.count, before.max), (8, 500.0));
+
+            record(1e6, 8).await;
+            record(11.0, 9).await;
+            assert_eq!(replica.quarantined("latency").await.unwrap().len(), 1);
+            let screened = registry.get_metric_aggregate("latency").await.unwrap().unwrap();
+            assert_eq!((screened.count, screened.max), (9, 500.0));
+            assert_eq!(registry.get_metric("latency").await.unwrap(), Some(11.0));
+            let quarantined = registry.quarantined("latency").await.unwrap();
+            assert_eq!(quarantined.iter().map(|sample| (sample.value, sample.timestamp)).collect::<Vec<_>>(), [(1e6, 8)]);
+            assert_eq!(quarantined[0].seq, operation_seq(0, 8));
+
+            registry.apply_operation(&MetricOperation::ApplyQuarantine { name: "latency".to_string() }).await.unwrap();
+            let applied = registry.get_metric_aggregate("latency").await.unwrap().unwrap();
+            assert_eq!((applied.count, applied.max), (10, 1e6));
+            assert!(registry.quarantined("latency").await.unwrap().is_empty());
+            // Late, so the newer sample stays latest
+            assert_eq!(registry.get_metric("latency").await.unwrap(), Some(11.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recent_samples_are_newest_first() {
+        for registry in registries() {
+            for (value, timestamp) in [(1.0, 100), (3.0, 300), (2.0, 200)] {
+                registry.record_metric_with_timestamp("cpu", value, timestamp).await.unwrap();
+            }
+            let point = |value: f64| MetricPoint { value, timestamp: value as i64 * 100 };
+
+            // Fewer samples than the limit
+            assert_eq!(registry.get_recent("cpu", 5, None).await.unwrap(), Some(vec![point(3.0), point(2.0), point(1.0)]));
+            // Exactly the limit
+            assert_eq!(registry.get_recent("cpu", 2, None).await.unwrap(), Some(vec![point(3.0), point(2.0)]));
+            assert_eq!(registry.get_recent("cpu", 5, Some(200)).await.unwrap(), Some(vec![point(3.0), point(2.0)]));
+
+            // Known but nothing in the window, and unknown
+            assert_eq!(registry.get_recent("cpu", 5, Some(301)).await.unwrap(), Some(Vec::new()));
+            assert_eq!(registry.get_recent("absent", 5, None).await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_is_capped_while_the_store_keeps_every_sample() {
+        for registry in registries() {
+            let registry = registry.with_memory_budget(MemoryBudget { max_history: 5, ..Default::default() });
+            for i in 1..=20 {
+                registry.record_metric_with_timestamp("cpu", i as f64, i * 100).await.unwrap();
+            }
+
+            {
+                // Only the five samples from 1600 on are held
+                let history = registry.history.lock().unwrap();
+                assert_eq!(history.range_bounds("cpu", 1500, 2000), None);
+                assert!(history.range_bounds("cpu", 1501, 2000).is_some());
+            }
+            assert_eq!(registry.storage_stats().await.unwrap().sample_rows, 20);
+
+            // Recent windows are held in memory, longer ones read the store
+            let point = |value: f64| MetricPoint { value, timestamp: value as i64 * 100 };
+            assert_eq!(registry.get_range_bounds("cpu", 1700, 1900).await.unwrap(), Some((point(17.0), point(19.0))));
+            assert_eq!(registry.get_range_bounds("cpu", 0, 5000).await.unwrap(), Some((point(1.0), point(20.0))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_metrics_reads_resident_and_evicted_series() {
+        for registry in registries() {
+            let registry = registry.with_memory_budget(MemoryBudget { max_entries: Some(2), hot_entries: 1, ..Default::default() });
+            for i in 0..6 {
+                registry.record_metric_with_timestamp(&format!("series_{}", i), i as f64, 100 + i).await.unwrap();
+            }
+            registry.delete_metric("series_5", 200).await.unwrap();
+
+            let names: Vec<String> = ["series_0", "series_4", "series_5", "absent", "series_0"]
+                .into_iter()
+                .map(String::from)
+                .collect();
+            let found = registry.get_metrics(&names).await.unwrap();
+            assert_eq!(found.len(), 2);
+            assert_eq!(found["series_0"], MetricPoint { value: 0.0, timestamp: 100 });
+            assert_eq!(found["series_4"], MetricPoint { value: 4.0, timestamp: 104 });
+            assert!(registry.metrics.read().await.len() <= 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sampled_metric_keeps_about_half_with_unbiased_totals() {
+        for registry in registries() {
+            registry.set_sample_rate("hot", 0.5).unwrap();
+            // A replica applies the weights in the log, whatever its own rates
+            let replica = MetricsRegistry::new().unwrap();
+            let dropped_before = SAMPLED_OUT_TOTAL.get();
+            for i in 0..4000 {
+                for name in ["hot", "cold"] {
+                    if let Some(operation) = registry.admit(MetricOperation::record(name, 2.0, i, Labels::new())) {
+                        registry.apply_operation(&operation).await.unwrap();
+                        replica.apply_operation(&operation).await.unwrap();
+                    }
+                }
+            }
+
+            // Every cold sample is stored, so the rest are hot
+            let stored = registry.storage_stats().await.unwrap().sample_rows - 4000;
+            assert!((1800..2200).contains(&stored), "stored {}", stored);
+            assert!(SAMPLED_OUT_TOTAL.get() - dropped_before >= 4000 - stored);
+
+            let hot = registry.get_metric_aggregate("hot").await.unwrap().unwrap();
+            assert_eq!(hot.count, stored * 2);
+            assert!((3600..4400).contains(&hot.count), "estimated {}", hot.count);
+            assert_eq!((hot.average, hot.total()), (2.0, hot.count as f64 * 2.0));
+            assert_eq!(registry.get_metric_aggregate("cold").await.unwrap().unwrap().count, 4000);
+            assert_eq!(replica.get_metric_aggregate("hot").await.unwrap().unwrap().count, hot.count);
+            assert_eq!(replica.storage_stats().await.unwrap().sample_rows - 4000, stored);
+
+            // Sampling is decided before proposing, not while applying
+            registry.record_metric_with_timestamp("hot", 2.0, 5000).await.unwrap();
+            assert_eq!(registry.get_metric_aggregate("hot").await.unwrap().unwrap().count, hot.count + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_metrics_follow_the_clock() {
+        for registry in registries() {
+            let now = Arc::new(std::sync::atomic::AtomicI64::new(1_000));
+            let clock = now.clone();
+            let registry = registry
+                .with_clock(Arc::new(move || clock.load(std::sync::atomic::Ordering::SeqCst)));
+
+            for name in ["idle_a", "idle_b", "idle_c"] {
+                registry.record_metric_with_timestamp(name, 1.0, 10).await.unwrap();
+            }
+            now.store(5_000, std::sync::atomic::Ordering::SeqCst);
+            registry.record_metric_with_timestamp("idle_a", 2.0, 20).await.unwrap();
+            now.store(100_000, std::sync::atomic::Ordering::SeqCst);
+            registry.record_metric_with_timestamp("busy", 1.0, 30).await.unwrap();
+
+            let idle_a = registry.get_metric_aggregate("idle_a").await.unwrap().unwrap();
+            assert_eq!((idle_a.first_seen, idle_a.last_seen), (1_000, 5_000));
+
+            // Idle for 96000s means last written before 4000
+            let page = registry.stale_metrics(96_000, "", 1).await.unwrap();
+            assert_eq!(page, [StaleMetric { name: "idle_b".into(), first_seen: 1_000, last_seen: 1_000 }]);
+            let page = registry.stale_metrics(96_000, "idle_b", 1).await.unwrap();
+            assert_eq!(page[0].name, "idle_c");
+            assert_eq!(registry.stale_metrics(96_000, "idle_c", 1).await.unwrap(), []);
+            assert_eq!(registry.stale_metrics(86_400, "", 10).await.unwrap().len(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_late_arrival_does_not_replace_newer_latest() {
+        for registry in registries() {
+            registry.record_metric_with_timestamp("temp", 20.0, 200).await.unwrap();
+            registry.record_metric_with_timestamp("temp", 10.0, 100).await.unwrap();
+
+            assert_eq!(registry.get_metric("temp").await.unwrap(), Some(20.0));
+            let (earliest, latest) = registry.get_range_bounds("temp", 0, 1_000).await.unwrap().unwrap();
+            assert_eq!(earliest, MetricPoint { value: 10.0, timestamp: 100 });
+            assert_eq!(latest, MetricPoint { value: 20.0, timestamp: 200 });
+            assert_eq!(registry.get_metric_aggregate("temp").await.unwrap().unwrap().count, 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backends_agree_on_timestamp_and_aggregate_edge_cases() {
+        let mut seen = Vec::new();
+        for registry in registries() {
+            // A tie on timestamp goes to the later write; an earlier one never replaces it
+            registry.record_metric_with_timestamp("edge", 1.0, 100).await.unwrap();
+            registry.record_metric_with_timestamp("edge", 2.0, 100).await.unwrap();
+            registry.record_metric_with_timestamp("edge", -5.0, 0).await.unwrap();
+            registry.record_metric_with_timestamp("edge", 0.0, 99).await.unwrap();
+            assert_eq!(registry.get_latest("edge").await.unwrap(), Some(MetricPoint { value: 2.0, timestamp: 100 }));
+
+            let aggregate = registry.get_metric_aggregate("edge").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.sum, aggregate.min, aggregate.max), (4, -2.0, -5.0, 2.0));
+            assert_eq!(aggregate.average, -0.5);
+            let bounds = registry.get_range_bounds("edge", 0, 100).await.unwrap().unwrap();
+            assert_eq!(bounds.0, MetricPoint { value: -5.0, timestamp: 0 });
+            assert_eq!(registry.get_range_bounds("edge", 101, 200).await.unwrap(), None);
+
+            // A delete at the latest timestamp hides it; a write after starts over
+            registry.delete_metric("edge", 100).await.unwrap();
+            assert_eq!(registry.get_latest("edge").await.unwrap(), None);
+            registry.record_metric_with_timestamp("edge", 3.0, 101).await.unwrap();
+            let aggregate = registry.get_metric_aggregate("edge").await.unwrap().unwrap();
+            assert_eq!((aggregate.count, aggregate.min, aggregate.max), (1, 3.0, 3.0));
+            seen.push((registry.get_latest("edge").await.unwrap(), registry.get_recent("edge", 10, None).await.unwrap()));
+        }
+        assert!(seen.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", seen);
+    }
+
+    #[test]
+    fn test_kahan_summation_beats_naive() {
+        let small = 1e-16;
+        let n = 1_000_000;
+        let truth = 1.0 + small * n as f64;
+
+        let mut naive = MetricAggregate::default();
+        let mut kahan = MetricAggregate::default();
+        for aggregate in [&mut naive, &mut kahan] {
+            aggregate.min = f64::INFINITY;
+            aggregate.max = f64::NEG_INFINITY;
+        }
+        naive.add(1.0, Summation::Naive);
+        kahan.add(1.0, Summation::Kahan);
+        for _ in 0..n {
+            naive.add(small, Summation::Naive);
+            kahan.add(small, Summation::Kahan);
+        }
+
+        let naive_error = (naive.total() - truth).abs();
+        let kahan_error = (kahan.total() - truth).abs();
+        assert!(kahan_error < naive_error, "kahan {} vs naive {}", kahan_error, naive_error);
+        assert!(kahan_error < 1e-15);
+        assert_eq!(kahan.count, naive.count);
     }
 }
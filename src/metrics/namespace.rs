@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{AggregateFn, MetricAggregate};
+
+pub const DEFAULT_TREE_SEPARATOR: &str = ".";
+pub const DEFAULT_TREE_DEPTH: usize = 1;
+pub const MAX_TREE_DEPTH: usize = 16;
+pub const DEFAULT_TREE_MAX_NODES: usize = 1000;
+pub const MAX_TREE_NODES: usize = 10_000;
+
+/// Which part of the namespace a tree covers and how far it unfolds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeShape {
+    /// Namespace rolled up; a trailing separator is optional, so `service`
+    /// and `service.` both cover `service.api.latency` but not `services`.
+    pub prefix: String,
+    pub separator: String,
+    /// Levels of nodes below the prefix.
+    pub depth: usize,
+    /// Most nodes below the prefix.
+    pub max_nodes: usize,
+}
+
+impl TreeShape {
+    fn namespace(&self) -> &str {
+        self.prefix.strip_suffix(self.separator.as_str()).unwrap_or(&self.prefix)
+    }
+
+    /// Whether `name` is the prefix's namespace or under it.
+    pub fn contains(&self, name: &str) -> bool {
+        self.segments(name).is_some()
+    }
+
+    /// The segments of `name` below the prefix, or `None` outside it.
+    fn segments<'a>(&self, name: &'a str) -> Option<Vec<&'a str>> {
+        let namespace = self.namespace();
+        let rest = match namespace {
+            "" => name,
+            namespace => match name.strip_prefix(namespace)? {
+                "" => return Some(Vec::new()),
+                rest => rest.strip_prefix(self.separator.as_str())?,
+            },
+        };
+        Some(rest.split(self.separator.as_str()).collect())
+    }
+
+    fn path(&self, segments: &[String]) -> String {
+        let namespace = self.namespace();
+        let mut parts: Vec<&str> = Vec::with_capacity(segments.len() + 1);
+        if !namespace.is_empty() {
+            parts.push(namespace);
+        }
+        parts.extend(segments.iter().map(String::as_str));
+        parts.join(&self.separator)
+    }
+
+    /// Names every tree of this shape may be built from start with this.
+    pub fn name_prefix(&self) -> &str {
+        self.namespace()
+    }
+
+    /// Identifies trees of this shape, e.g. in a cache.
+    pub fn key(&self) -> String {
+        format!("{}\u{0}{}\u{0}{}\u{0}{}", self.prefix, self.separator, self.depth, self.max_nodes)
+    }
+}
+
+/// Aggregates of one or more metrics rolled into one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rollup {
+    /// Metrics rolled up.
+    pub metrics: u64,
+    pub count: u64,
+    pub sum: f64,
+    pub average: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Rollup {
+    pub fn of(aggregate: &MetricAggregate) -> Self {
+        Self {
+            metrics: 1,
+            count: aggregate.count,
+            sum: aggregate.total(),
+            average: aggregate.average,
+            min: aggregate.min,
+            max: aggregate.max,
+        }
+    }
+
+    pub fn value(&self, function: AggregateFn) -> f64 {
+        match function {
+            AggregateFn::Avg => self.average,
+            AggregateFn::Sum => self.sum,
+            AggregateFn::Min => self.min,
+            AggregateFn::Max => self.max,
+            AggregateFn::Count => self.count as f64,
+        }
+    }
+
+    /// Folds `other` in. Metrics roll up on a worker and a node's rollups
+    /// from each worker merge on the control node alike.
+    pub fn merge(&mut self, other: &Rollup) {
+        self.metrics += other.metrics;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.average = if self.count == 0 { 0.0 } else { self.sum / self.count as f64 };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceNode {
+    /// Last segment of the path; the prefix itself for the root.
+    pub name: String,
+    pub path: String,
+    /// Every metric at or below the path.
+    #[serde(flatten)]
+    pub rollup: Rollup,
+    /// In name order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NamespaceNode>,
+}
+
+/// Rolled-up aggregates for each namespace below a prefix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceTree {
+    pub prefix: String,
+    pub separator: String,
+    pub depth: usize,
+    /// Absent when no metric is under the prefix.
+    pub root: Option<NamespaceNode>,
+    /// Whether metrics nest deeper than `depth`; their aggregates still
+    /// count in the deepest node shown.
+    pub depth_truncated: bool,
+    /// Whether nodes past `max_nodes` were left out; their aggregates
+    /// still count in the nodes above them.
+    pub nodes_truncated: bool,
+}
+
+/// Rollups by the segments of their path below the prefix. Segment lists
+/// sort parents first and then each subtree in turn, as a tree walk does.
+type Nodes = BTreeMap<Vec<String>, Rollup>;
+
+fn add(nodes: &mut Nodes, segments: Vec<String>, rollup: &Rollup) {
+    match nodes.get_mut(&segments) {
+        Some(node) => node.merge(rollup),
+        None => {
+            nodes.insert(segments, *rollup);
+        }
+    }
+}
+
+fn flatten(node: NamespaceNode, segments: Vec<String>, nodes: &mut Nodes) {
+    for child in node.children {
+        let mut child_segments = segments.clone();
+        child_segments.push(child.name.clone());
+        flatten(child, child_segments, nodes);
+    }
+    nodes.insert(segments, node.rollup);
+}
+
+impl NamespaceTree {
+    /// Rolls up the aggregates of the metrics under `shape`'s prefix.
+    pub fn build<'a>(shape: &TreeShape, aggregates: impl IntoIterator<Item = (&'a str, &'a MetricAggregate)>) -> Self {
+        let mut nodes = Nodes::new();
+        let mut depth_truncated = false;
+        for (name, aggregate) in aggregates {
+            let Some(segments) = shape.segments(name) else { continue };
+            depth_truncated |= segments.len() > shape.depth;
+            let rollup = Rollup::of(aggregate);
+            for level in 0..=segments.len().min(shape.depth) {
+                add(&mut nodes, segments[..level].iter().map(|s| s.to_string()).collect(), &rollup);
+            }
+        }
+        Self::assemble(shape, nodes, depth_truncated, false)
+    }
+
+    /// Merges trees of `shape` built by several workers. A worker that
+    /// left nodes out kept only those first in tree order, so the merge
+    /// stops where the earliest such worker did: every node it keeps holds
+    /// every worker's share.
+    pub fn merge(shape: &TreeShape, trees: impl IntoIterator<Item = NamespaceTree>) -> Self {
+        let mut nodes = Nodes::new();
+        let mut depth_truncated = false;
+        let mut nodes_truncated = false;
+        let mut last_complete: Option<Vec<String>> = None;
+        for tree in trees {
+            depth_truncated |= tree.depth_truncated;
+            let mut worker_nodes = Nodes::new();
+            if let Some(root) = tree.root {
+                flatten(root, Vec::new(), &mut worker_nodes);
+            }
+            if tree.nodes_truncated {
+                nodes_truncated = true;
+                if let Some((last, _)) = worker_nodes.last_key_value() {
+                    if last_complete.as_ref().is_none_or(|complete| complete > last) {
+                        last_complete = Some(last.clone());
+                    }
+                }
+            }
+            for (segments, rollup) in worker_nodes {
+                add(&mut nodes, segments, &rollup);
+            }
+        }
+        if let Some(last) = last_complete {
+            nodes.retain(|segments, _| *segments <= last);
+        }
+        Self::assemble(shape, nodes, depth_truncated, nodes_truncated)
+    }
+
+    /// Nests `nodes`, keeping the first `max_nodes` below the root in tree
+    /// order so every node kept has its parent.
+    fn assemble(shape: &TreeShape, nodes: Nodes, depth_truncated: bool, mut nodes_truncated: bool) -> Self {
+        let mut stack: Vec<NamespaceNode> = Vec::new();
+        let mut kept = 0;
+        for (segments, rollup) in nodes {
+            if !segments.is_empty() {
+                if kept == shape.max_nodes {
+                    nodes_truncated = true;
+                    break;
+                }
+                kept += 1;
+            }
+            while stack.len() > segments.len() {
+                let child = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(child);
+            }
+            let name = segments.last().map_or_else(|| shape.namespace().to_string(), String::clone);
+            stack.push(NamespaceNode { name, path: shape.path(&segments), rollup, children: Vec::new() });
+        }
+        while stack.len() > 1 {
+            let child = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(child);
+        }
+
+        Self {
+            prefix: shape.prefix.clone(),
+            separator: shape.separator.clone(),
+            depth: shape.depth,
+            root: stack.pop(),
+            depth_truncated,
+            nodes_truncated,
+        }
+    }
+
+    /// Leaves out the nodes whose paths fail `keep`, with everything below
+    /// them. Their metrics still count in the nodes above.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        fn prune(node: &mut NamespaceNode, keep: &dyn Fn(&str) -> bool) {
+            node.children.retain(|child| keep(&child.path));
+            for child in &mut node.children {
+                prune(child, keep);
+            }
+        }
+        if self.root.as_ref().is_some_and(|root| !keep(&root.path)) {
+            self.root = None;
+        }
+        if let Some(root) = &mut self.root {
+            prune(root, &keep);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Summation;
+
+    fn aggregate(values: &[f64]) -> MetricAggregate {
+        let mut aggregate = MetricAggregate { min: values[0], max: values[0], ..Default::default() };
+        for &value in values {
+            aggregate.add(value, Summation::Naive);
+        }
+        aggregate
+    }
+
+    #[test]
+    fn test_node_cap_keeps_parents_and_merges_only_complete_nodes() {
+        let shape = TreeShape { prefix: "svc.".to_string(), separator: ".".to_string(), depth: 2, max_nodes: 4 };
+        let (a, b, c) = (aggregate(&[1.0]), aggregate(&[2.0, 4.0]), aggregate(&[8.0]));
+        let first = NamespaceTree::build(&shape, [("svc.api.latency", &a), ("svc.db.latency", &b), ("svcs.x", &c)]);
+        let second = NamespaceTree::build(
+            &shape,
+            [("svc.api.errors", &c), ("svc.api.latency.p99", &b), ("svc.api.queue", &a), ("svc.db.x", &a)],
+        );
+
+        assert!(!first.nodes_truncated);
+        let root = first.root.as_ref().unwrap();
+        assert_eq!((root.path.as_str(), root.rollup.metrics, root.rollup.sum), ("svc", 2, 7.0));
+        // svc.api and its three children fill the cap
+        assert!(second.depth_truncated);
+        assert!(second.nodes_truncated);
+
+        let merged = NamespaceTree::merge(&shape, [first, second]);
+        let root = merged.root.unwrap();
+        assert_eq!((root.rollup.metrics, root.rollup.count, root.rollup.sum, root.rollup.max), (6, 8, 23.0, 8.0));
+        let paths: Vec<&str> = root.children[0].children.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, ["svc.api.errors", "svc.api.latency", "svc.api.queue"]);
+        assert_eq!(root.children[0].children[1].rollup.sum, 7.0);
+        // svc.db is missing the second worker's share, so it is cut
+        assert_eq!(root.children.len(), 1);
+        assert!(merged.nodes_truncated);
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::MetricAggregate;
+
+/// Samples a metric needs before it is screened unless configured otherwise.
+pub const DEFAULT_OUTLIER_MIN_SAMPLES: u64 = 30;
+
+/// Which samples are held out of their metric as outliers, from
+/// `OUTLIER_METRICS`, `OUTLIER_STDDEVS`, `OUTLIER_MAX_DEVIATION` and
+/// `OUTLIER_MIN_SAMPLES`. The proposing node puts the metric's
+/// [`OutlierBounds`] in the entry, and a sample is judged against the
+/// aggregate it would join, which every replica holds identically, so
+/// replicas quarantine the same samples whatever their own policy says.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlierPolicy {
+    /// Whether every metric is screened.
+    pub all: bool,
+    /// Metrics screened when `all` is unset.
+    pub metrics: HashSet<String>,
+    /// Standard deviations from the average past which a sample is an outlier.
+    pub stddevs: Option<f64>,
+    /// Distance from the average past which a sample is an outlier.
+    pub max_deviation: Option<f64>,
+    /// Samples aggregated before screening starts, since the deviation of
+    /// the first few says little.
+    pub min_samples: u64,
+}
+
+impl Default for OutlierPolicy {
+    fn default() -> Self {
+        Self {
+            all: false,
+            metrics: HashSet::new(),
+            stddevs: None,
+            max_deviation: None,
+            min_samples: DEFAULT_OUTLIER_MIN_SAMPLES,
+        }
+    }
+}
+
+impl OutlierPolicy {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads the policy; `OUTLIER_METRICS` is a comma-separated list of
+    /// metric names, or `*` for every metric. Unparsable or non-positive
+    /// bounds are ignored.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let listed: HashSet<String> = lookup("OUTLIER_METRICS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        let bound = |key: &str| lookup(key).and_then(|v| v.parse::<f64>().ok()).filter(|&bound| bound > 0.0);
+        Self {
+            all: listed.contains("*"),
+            metrics: listed.into_iter().filter(|name| name != "*").collect(),
+            stddevs: bound("OUTLIER_STDDEVS"),
+            max_deviation: bound("OUTLIER_MAX_DEVIATION"),
+            min_samples: lookup("OUTLIER_MIN_SAMPLES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.min_samples),
+        }
+    }
+
+    pub fn screens(&self, name: &str) -> bool {
+        (self.all || self.metrics.contains(name)) && (self.stddevs.is_some() || self.max_deviation.is_some())
+    }
+
+    /// The bounds `name`'s samples are screened against, if it is screened.
+    pub fn bounds(&self, name: &str) -> Option<OutlierBounds> {
+        self.screens(name).then_some(OutlierBounds {
+            stddevs: self.stddevs,
+            max_deviation: self.max_deviation,
+            min_samples: self.min_samples,
+        })
+    }
+
+    /// Why `value` is an outlier for `name` given its current `aggregate`,
+    /// or `None` when it may join it.
+    pub fn check(&self, name: &str, aggregate: &MetricAggregate, value: f64) -> Option<String> {
+        self.bounds(name)?.check(aggregate, value)
+    }
+}
+
+/// What one metric's samples are screened against, as decided by the node
+/// that proposed them; see [`OutlierPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OutlierBounds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stddevs: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_deviation: Option<f64>,
+    pub min_samples: u64,
+}
+
+impl OutlierBounds {
+    /// Why `value` is an outlier given the `aggregate` it would join, or
+    /// `None` when it may join it.
+    pub fn check(&self, aggregate: &MetricAggregate, value: f64) -> Option<String> {
+        if aggregate.count < self.min_samples.max(1) {
+            return None;
+        }
+        let deviation = (value - aggregate.average).abs();
+        if let Some(max) = self.max_deviation.filter(|&max| deviation > max) {
+            return Some(format!("{} is more than {} from the average {}", value, max, aggregate.average));
+        }
+        // Samples that never varied give no scale to measure against
+        let stddev = aggregate.stddev();
+        match self.stddevs {
+            Some(stddevs) if stddev > 0.0 && deviation > stddevs * stddev => Some(format!(
+                "{} is more than {} standard deviations ({}) from the average {}",
+                value, stddevs, stddev, aggregate.average
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Summation;
+
+    #[test]
+    fn test_screening_starts_after_the_minimum_sample_count() {
+        let policy = OutlierPolicy::from_lookup(|key| match key {
+            "OUTLIER_METRICS" => Some("latency, ".to_string()),
+            "OUTLIER_STDDEVS" => Some("4".to_string()),
+            "OUTLIER_MAX_DEVIATION" => Some("-1".to_string()),
+            "OUTLIER_MIN_SAMPLES" => Some("5".to_string()),
+            _ => None,
+        });
+        assert_eq!(policy.max_deviation, None);
+        let mut aggregate = MetricAggregate::default();
+        for value in [10.0, 12.0, 11.0, 9.0] {
+            aggregate.add(value, Summation::Naive);
+        }
+
+        assert_eq!(policy.check("latency", &aggregate, 1e7), None);
+        aggregate.add(10.0, Summation::Naive);
+        assert!(policy.check("latency", &aggregate, 1e7).is_some());
+        assert_eq!(policy.check("latency", &aggregate, 12.0), None);
+        assert_eq!(policy.check("cpu", &aggregate, 1e7), None);
+        assert!(!OutlierPolicy::default().screens("latency"));
+    }
+}
@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{RaftMetricsError, Result};
+
+/// Tables an ad-hoc query may read.
+pub const QUERYABLE_TABLES: [&str; 2] = ["metrics", "metric_aggregates"];
+
+/// Keywords that only appear in statements that write, reconfigure or reach
+/// outside the store.
+const FORBIDDEN_KEYWORDS: [&str; 22] = [
+    "insert", "update", "delete", "drop", "create", "alter", "truncate", "attach", "detach",
+    "copy", "export", "import", "install", "load", "pragma", "set", "reset", "call", "vacuum",
+    "checkpoint", "begin", "commit",
+];
+
+/// Keywords that end a FROM clause.
+const CLAUSE_KEYWORDS: [&str; 15] = [
+    "where", "group", "having", "order", "limit", "offset", "window", "qualify", "union",
+    "intersect", "except", "on", "using", "select", "returning",
+];
+
+/// Rows returned by `POST /query`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Set when rows past the limit were dropped.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Unquoted identifiers and keywords, lowercased.
+    Word(String),
+    /// Double-quoted identifiers, as written.
+    Quoted(String),
+    Literal,
+    Symbol(char),
+}
+
+fn rejected(reason: impl Into<String>) -> RaftMetricsError {
+    RaftMetricsError::InvalidRequest(format!("Query rejected: {}", reason.into()))
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(c) => previous = c,
+                        None => return Err(rejected("unterminated comment")),
+                    }
+                }
+            }
+            '\'' | '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        // A doubled quote is an escaped one
+                        Some(q) if q == c && chars.peek() == Some(&c) => {
+                            chars.next();
+                            text.push(q);
+                        }
+                        Some(q) if q == c => break,
+                        Some(other) => text.push(other),
+                        None => return Err(rejected("unterminated quote")),
+                    }
+                }
+                tokens.push(if c == '"' { Token::Quoted(text) } else { Token::Literal });
+            }
+            c if c.is_ascii_digit() => {
+                while chars.peek().is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.') {
+                    chars.next();
+                }
+                tokens.push(Token::Literal);
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = c.to_lowercase().to_string();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_' || **c == '$') {
+                    word.extend(c.to_lowercase());
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn name_of(token: &Token) -> Option<String> {
+    match token {
+        Token::Word(word) => Some(word.clone()),
+        Token::Quoted(name) => Some(name.to_lowercase()),
+        _ => None,
+    }
+}
+
+/// The index just past the parenthesized group at `at`, or `at` when
+/// there is none there.
+fn skip_group(tokens: &[Token], at: usize) -> usize {
+    if tokens.get(at) != Some(&Token::Symbol('(')) {
+        return at;
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(at) {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') if depth == 1 => return i + 1,
+            Token::Symbol(')') => depth -= 1,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// The CTEs a `WITH` list starting at `at` defines, each written
+/// `name [(columns)] AS [[NOT] MATERIALIZED] (query)`.
+fn cte_names(tokens: &[Token], mut at: usize) -> Vec<String> {
+    let word = |at: usize, word: &str| tokens.get(at) == Some(&Token::Word(word.into()));
+    if word(at, "recursive") {
+        at += 1;
+    }
+    let mut names = Vec::new();
+    while let Some(name) = tokens.get(at).and_then(name_of) {
+        at = skip_group(tokens, at + 1);
+        if !word(at, "as") {
+            break;
+        }
+        at += 1;
+        if word(at, "not") {
+            at += 1;
+        }
+        if word(at, "materialized") {
+            at += 1;
+        }
+        if tokens.get(at) != Some(&Token::Symbol('(')) {
+            break;
+        }
+        names.push(name);
+        at = skip_group(tokens, at);
+        if tokens.get(at) != Some(&Token::Symbol(',')) {
+            break;
+        }
+        at += 1;
+    }
+    names
+}
+
+/// Checks that `sql` is a single read-only `SELECT` over
+/// [`QUERYABLE_TABLES`] and returns it without its trailing semicolon.
+///
+/// Every table reference must name one of those tables, a CTE, or a
+/// subquery, which also keeps out table functions that read files.
+pub fn validate(sql: &str) -> Result<&str> {
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    let tokens = tokenize(sql)?;
+
+    match tokens.first() {
+        Some(Token::Word(word)) if word == "select" || word == "with" => {}
+        Some(_) => return Err(rejected("only SELECT statements are allowed")),
+        None => return Err(rejected("empty statement")),
+    }
+    if tokens.contains(&Token::Symbol(';')) {
+        return Err(rejected("only a single statement is allowed"));
+    }
+    for token in &tokens {
+        if let Token::Word(word) = token {
+            if FORBIDDEN_KEYWORDS.contains(&word.as_str()) {
+                return Err(rejected(format!("'{}' is not allowed", word.to_uppercase())));
+            }
+        }
+    }
+
+    let mut ctes = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token == &Token::Word("with".into()) {
+            ctes.extend(cte_names(&tokens, i + 1));
+        }
+    }
+
+    // One flag per nesting level: whether that level is inside a FROM clause
+    let mut in_from = vec![false];
+    let mut expect_table = false;
+    for (i, token) in tokens.iter().enumerate() {
+        if expect_table {
+            expect_table = false;
+            if token == &Token::Symbol('(') {
+                // Subquery; its own FROM is checked at the next level
+            } else {
+                let name = name_of(token).filter(|name| {
+                    QUERYABLE_TABLES.contains(&name.as_str()) || ctes.contains(name)
+                });
+                let qualified = matches!(tokens.get(i + 1), Some(Token::Symbol('.') | Token::Symbol('(')));
+                if name.is_none() || qualified {
+                    return Err(rejected(format!("queries may only read from {}", QUERYABLE_TABLES.join(", "))));
+                }
+            }
+        }
+
+        match token {
+            Token::Symbol('(') => in_from.push(false),
+            Token::Symbol(')') => {
+                in_from.pop();
+                if in_from.is_empty() {
+                    return Err(rejected("unbalanced parentheses"));
+                }
+            }
+            Token::Symbol(',') if *in_from.last().unwrap() => expect_table = true,
+            Token::Word(word) if word == "from" || word == "join" => {
+                *in_from.last_mut().unwrap() = true;
+                expect_table = true;
+            }
+            Token::Word(word) if CLAUSE_KEYWORDS.contains(&word.as_str()) => {
+                *in_from.last_mut().unwrap() = false;
+            }
+            _ => {}
+        }
+    }
+    if expect_table {
+        return Err(rejected("missing table after FROM"));
+    }
+    if in_from.len() != 1 {
+        return Err(rejected("unbalanced parentheses"));
+    }
+
+    Ok(sql)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_accepts_reads_of_metric_tables() {
+        for sql in [
+            "SELECT name, value FROM metrics WHERE name = 'cpu' ORDER BY timestamp DESC;",
+            "select m.name, a.max from metrics m join metric_aggregates a on m.name = a.name",
+            "WITH recent AS (SELECT * FROM metrics WHERE timestamp > 100) SELECT count(*) FROM recent",
+            "SELECT * FROM (SELECT name FROM metric_aggregates) t, metrics",
+            "SELECT 'DELETE FROM metrics' AS note FROM \"metrics\" -- drop everything",
+            "WITH a(n) AS NOT MATERIALIZED (SELECT name FROM metrics), b AS (SELECT * FROM a) SELECT * FROM b",
+            "SELECT * FROM (WITH inner_cte AS (SELECT 1 FROM metrics) SELECT * FROM inner_cte) t",
+        ] {
+            assert!(validate(sql).is_ok(), "{}", sql);
+        }
+        assert_eq!(validate("SELECT 1 FROM metrics; ").unwrap(), "SELECT 1 FROM metrics");
+    }
+
+    #[test]
+    fn test_validate_rejects_writes_and_other_sources() {
+        for sql in [
+            "DELETE FROM metrics",
+            "UPDATE metrics SET value = 0",
+            "SELECT 1 FROM metrics; DROP TABLE metrics",
+            "WITH gone AS (DELETE FROM metrics RETURNING *) SELECT * FROM gone",
+            "SELECT * FROM metric_tombstones",
+            "SELECT * FROM read_csv_auto('/etc/passwd')",
+            "SELECT * FROM metrics, '/etc/passwd'",
+            "SELECT * FROM main.metrics",
+            "SELECT (SELECT max(applied_index) FROM raft_applied_index) FROM metrics",
+            "SELECT * FROM metrics /* unterminated",
+            "SELECT * FROM raft_log WINDOW raft_log AS (ORDER BY 1)",
+            "SELECT sum(value) OVER w FROM metrics, raft_log WINDOW w AS (), raft_log AS ()",
+            "",
+        ] {
+            assert!(matches!(validate(sql), Err(RaftMetricsError::InvalidRequest(_))), "{}", sql);
+        }
+    }
+}
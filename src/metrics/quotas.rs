@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+use crate::config::units;
+
+/// `;`-separated `tenant=limit:value,...` entries, e.g.
+/// `acme=series:1000,rate:500,bytes:10MB;beta=series:10`.
+pub const QUOTAS_KEY: &str = "TENANT_QUOTAS";
+/// What ends the tenant part of a metric name.
+pub const SEPARATOR_KEY: &str = "TENANT_SEPARATOR";
+pub const DEFAULT_TENANT_SEPARATOR: &str = ".";
+/// Bytes a stored sample is reckoned to take besides its metric name: the
+/// value, timestamp, sequence and row overhead.
+pub const SAMPLE_ROW_BYTES: u64 = 32;
+
+/// Limits on one tenant; each is unlimited when unset. Series and storage
+/// are enforced by each worker on what it stores, the rate by the control
+/// node on what it forwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TenantQuota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_series: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_samples_per_sec: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_storage_bytes: Option<u64>,
+}
+
+impl TenantQuota {
+    /// Parses `series:N,rate:N,bytes:SIZE`, in any order and any subset.
+    fn parse(limits: &str) -> Result<Self, String> {
+        let mut quota = Self::default();
+        for limit in limits.split(',').map(str::trim).filter(|limit| !limit.is_empty()) {
+            let Some((key, value)) = limit.split_once(':') else {
+                return Err(format!("expected limit:value; got '{}'", limit));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "series" => {
+                    quota.max_series = Some(value.parse().map_err(|_| format!("series '{}' is not a whole number", value))?);
+                }
+                "rate" => {
+                    let rate = value.parse::<f64>().ok().filter(|rate| rate.is_finite() && *rate > 0.0);
+                    quota.max_samples_per_sec = Some(rate.ok_or_else(|| format!("rate '{}' is not a positive number", value))?);
+                }
+                "bytes" => quota.max_storage_bytes = Some(units::parse_size(value, 1)?),
+                other => return Err(format!("unknown limit '{}'; expected series, rate or bytes", other)),
+            }
+        }
+        Ok(quota)
+    }
+}
+
+/// Quotas by tenant. A metric belongs to the tenant its name starts with,
+/// up to the first separator: `acme.cpu` to `acme`. Names without one
+/// belong to no tenant and are never limited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TenantQuotas {
+    pub separator: String,
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantQuota>,
+}
+
+impl Default for TenantQuotas {
+    fn default() -> Self {
+        Self { separator: DEFAULT_TENANT_SEPARATOR.to_string(), tenants: BTreeMap::new() }
+    }
+}
+
+impl TenantQuotas {
+    pub fn parse(spec: &str, separator: &str) -> Result<Self, Vec<String>> {
+        let mut tenants = BTreeMap::new();
+        let mut errors = Vec::new();
+        for entry in spec.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let Some((tenant, limits)) = entry.split_once('=') else {
+                errors.push(format!("{} entry '{}' is not tenant=limits", QUOTAS_KEY, entry));
+                continue;
+            };
+            let tenant = tenant.trim();
+            if tenant.is_empty() || tenant.contains(separator) {
+                errors.push(format!("{} tenant '{}' must be non-empty and not contain '{}'", QUOTAS_KEY, tenant, separator));
+                continue;
+            }
+            match TenantQuota::parse(limits) {
+                Ok(quota) => {
+                    if tenants.insert(tenant.to_string(), quota).is_some() {
+                        errors.push(format!("{} lists '{}' more than once", QUOTAS_KEY, tenant));
+                    }
+                }
+                Err(e) => errors.push(format!("{} for '{}': {}", QUOTAS_KEY, tenant, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(Self { separator: separator.to_string(), tenants })
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The quotas set by [`QUOTAS_KEY`]. A malformed value is ignored with
+    /// a warning here; [`quota_errors`] refuses it at startup and reload.
+    pub fn from_lookup(lookup: &impl Fn(&str) -> Option<String>) -> Self {
+        let separator = separator(lookup);
+        match Self::parse(&lookup(QUOTAS_KEY).unwrap_or_default(), &separator) {
+            Ok(quotas) => quotas,
+            Err(errors) => {
+                warn!("Ignoring {}: {}", QUOTAS_KEY, errors.join("; "));
+                Self { separator, ..Self::default() }
+            }
+        }
+    }
+
+    pub fn tenant_of<'a>(&self, name: &'a str) -> Option<&'a str> {
+        name.split_once(self.separator.as_str()).map(|(tenant, _)| tenant).filter(|tenant| !tenant.is_empty())
+    }
+
+    pub fn quota(&self, tenant: &str) -> Option<&TenantQuota> {
+        self.tenants.get(tenant)
+    }
+}
+
+fn separator(lookup: &impl Fn(&str) -> Option<String>) -> String {
+    lookup(SEPARATOR_KEY).filter(|separator| !separator.is_empty()).unwrap_or_else(|| DEFAULT_TENANT_SEPARATOR.to_string())
+}
+
+/// Everything wrong with [`QUOTAS_KEY`], one line per problem.
+pub fn quota_errors(lookup: &impl Fn(&str) -> Option<String>) -> Vec<String> {
+    TenantQuotas::parse(&lookup(QUOTAS_KEY).unwrap_or_default(), &separator(lookup)).err().unwrap_or_default()
+}
+
+/// Bytes a sample of `name` is reckoned to take in the store.
+pub fn sample_bytes(name: &str) -> u64 {
+    name.len() as u64 + SAMPLE_ROW_BYTES
+}
+
+/// What a tenant stores on one worker. Storage is estimated from the
+/// samples written, at [`sample_bytes`] each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantUsage {
+    pub series: u64,
+    pub storage_bytes: u64,
+}
+
+/// A tenant's quota next to its consumption, as served at `GET /usage`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub tenant: String,
+    pub quota: TenantQuota,
+    /// Series stored; on the control node, summed over workers.
+    pub series: u64,
+    pub storage_bytes: u64,
+    /// Samples the control node forwarded over the last full second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples_per_sec: Option<f64>,
+    /// Consumption on each worker, against which series and storage
+    /// quotas are enforced; only on the control node.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub workers: BTreeMap<String, TenantUsage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotas_parse_and_name_tenants() {
+        let quotas = TenantQuotas::parse("acme=series:2,rate:50,bytes:1KiB; beta=series:10", ".").unwrap();
+        assert_eq!(quotas.quota("acme"), Some(&TenantQuota {
+            max_series: Some(2),
+            max_samples_per_sec: Some(50.0),
+            max_storage_bytes: Some(1024),
+        }));
+        assert_eq!(quotas.quota("beta").unwrap().max_samples_per_sec, None);
+        assert_eq!(quotas.tenant_of("acme.cpu"), Some("acme"));
+        assert_eq!(quotas.tenant_of("cpu"), None);
+        assert_eq!(quotas.tenant_of(".cpu"), None);
+
+        assert!(TenantQuotas::parse("acme=series:many", ".").is_err());
+        assert!(TenantQuotas::parse("acme=burst:5", ".").is_err());
+        assert!(TenantQuotas::parse("acme=rate:0", ".").is_err());
+        assert!(TenantQuotas::parse("a.b=series:1", ".").is_err());
+        assert_eq!(TenantQuotas::parse("acme=series:1;acme=series:2", ".").unwrap_err().len(), 1);
+    }
+}
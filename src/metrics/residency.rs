@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    /// Logical time of the last read; 0 if never read.
+    last_read: u64,
+    reads: u64,
+}
+
+/// Read recency and frequency of the series resident in memory, used to
+/// pick eviction victims.
+#[derive(Debug, Default)]
+pub(crate) struct Residency {
+    clock: u64,
+    entries: HashMap<String, Usage>,
+}
+
+impl Residency {
+    /// Tracks a series that has just become resident.
+    pub fn admit(&mut self, name: &str) {
+        if !self.entries.contains_key(name) {
+            self.entries.insert(name.to_string(), Usage::default());
+        }
+    }
+
+    /// Records a read of a resident series.
+    pub fn touch(&mut self, name: &str) {
+        self.clock += 1;
+        if let Some(usage) = self.entries.get_mut(name) {
+            usage.last_read = self.clock;
+            usage.reads += 1;
+        }
+    }
+
+    pub fn forget(&mut self, name: &str) {
+        self.entries.remove(name);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Picks up to `count` least-recently-read series to evict, sparing the
+    /// `hot` most-read ones.
+    pub fn victims(&mut self, count: usize, hot: usize) -> Vec<String> {
+        let mut candidates: Vec<(&String, Usage)> = self.entries.iter().map(|(name, usage)| (name, *usage)).collect();
+        if hot > 0 {
+            candidates.sort_unstable_by_key(|(_, usage)| std::cmp::Reverse(usage.reads));
+            candidates.drain(..hot.min(candidates.len()));
+        }
+        candidates.sort_unstable_by_key(|(_, usage)| usage.last_read);
+
+        let victims: Vec<String> = candidates.into_iter().take(count).map(|(name, _)| name.clone()).collect();
+        for name in &victims {
+            self.entries.remove(name);
+        }
+        victims
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_victims_spare_hot_and_recent() {
+        let mut residency = Residency::default();
+        for name in ["hot", "recent", "cold", "never"] {
+            residency.admit(name);
+        }
+        for _ in 0..10 {
+            residency.touch("hot");
+        }
+        residency.touch("cold");
+        residency.touch("recent");
+
+        assert_eq!(residency.victims(2, 1), vec!["never", "cold"]);
+        assert_eq!(residency.victims(5, 1), vec!["recent"]);
+    }
+}
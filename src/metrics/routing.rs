@@ -0,0 +1,79 @@
+//! Collectors for how the control node routes and forwards requests.
+
+use lazy_static::lazy_static;
+use prometheus::{HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry};
+
+lazy_static! {
+    pub static ref CONTROL_REQUESTS_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_control_requests_total", "Routed metric requests handled by the control node, by route and status class"),
+            &["endpoint", "status"]
+        ).unwrap();
+    pub static ref CONTROL_FORWARD_DURATION: HistogramVec =
+        HistogramVec::new(
+            HistogramOpts::new("raftmetrics_control_forward_duration_seconds", "Time from forwarding a request to a worker to its response"),
+            &["worker"]
+        ).unwrap();
+    pub static ref CONTROL_PARTITION_ASSIGNMENTS_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_control_partition_assignments_total", "Metric requests routed to each partition"),
+            &["partition"]
+        ).unwrap();
+    pub static ref CONTROL_CACHE_HITS_TOTAL: IntCounter =
+        IntCounter::new("raftmetrics_control_cache_hits_total", "Metric reads answered from the control node's read cache").unwrap();
+    pub static ref CONTROL_READ_FALLBACKS_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_control_read_fallbacks_total", "Fallbacks tried after a metric's owning worker failed a read, by fallback and outcome"),
+            &["source", "outcome"]
+        ).unwrap();
+    pub static ref CONTROL_READ_REPAIRS_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_read_repairs_total", "Stale copies a quorum read wrote the newest sample back to, by outcome"),
+            &["outcome"]
+        ).unwrap();
+    pub static ref RATE_LIMITED_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_rate_limited_total", "Samples the control node refused for a tenant's rate quota, by tenant"),
+            &["tenant"]
+        ).unwrap();
+    pub static ref ACL_DENIED_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_acl_denied_total", "Metric requests the control node refused under ACL_PATH, by access asked for"),
+            &["access"]
+        ).unwrap();
+    pub static ref AUDIT_MISMATCHES_TOTAL: IntCounterVec =
+        IntCounterVec::new(
+            Opts::new("raftmetrics_audit_mismatches_total", "Reads a consistency audit found disagreeing with the metric's primary, by the layer that disagreed"),
+            &["layer"]
+        ).unwrap();
+}
+
+pub(super) fn register(registry: &Registry) -> prometheus::Result<()> {
+    registry.register(Box::new(CONTROL_REQUESTS_TOTAL.clone()))?;
+    registry.register(Box::new(CONTROL_FORWARD_DURATION.clone()))?;
+    registry.register(Box::new(CONTROL_PARTITION_ASSIGNMENTS_TOTAL.clone()))?;
+    registry.register(Box::new(CONTROL_CACHE_HITS_TOTAL.clone()))?;
+    registry.register(Box::new(CONTROL_READ_FALLBACKS_TOTAL.clone()))?;
+    registry.register(Box::new(CONTROL_READ_REPAIRS_TOTAL.clone()))?;
+    registry.register(Box::new(RATE_LIMITED_TOTAL.clone()))?;
+    registry.register(Box::new(ACL_DENIED_TOTAL.clone()))?;
+    registry.register(Box::new(AUDIT_MISMATCHES_TOTAL.clone()))?;
+    Ok(())
+}
+
+/// Counts a read fallback to `source` (`replica` or `stale-cache`) ending
+/// in `outcome`: `served`, `failed`, `missing` when there was nothing to
+/// fall back to, or `disallowed` by the client.
+pub fn record_read_fallback(source: &str, outcome: &str) {
+    CONTROL_READ_FALLBACKS_TOTAL.with_label_values(&[source, outcome]).inc();
+}
+
+/// Counts a read repair ending in `outcome`: `repaired` or `failed`.
+pub fn record_read_repair(outcome: &str) {
+    CONTROL_READ_REPAIRS_TOTAL.with_label_values(&[outcome]).inc();
+}
+
+/// Counts a request routed to `partition`.
+pub fn record_assignment(partition: usize) {
+    CONTROL_PARTITION_ASSIGNMENTS_TOTAL.with_label_values(&[&partition.to_string()]).inc();
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tracing::warn;
+
+use crate::{partitioning::seeded_hash, RaftMetricsError, Result};
+
+/// Per-metric fractions of samples to keep, from `SAMPLE_RATES` and
+/// `PUT /metrics/:name/sample_rate`. Metrics without a rate keep every
+/// sample.
+#[derive(Debug, Default)]
+pub struct SampleRates {
+    rates: RwLock<HashMap<String, f64>>,
+}
+
+impl SampleRates {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `SAMPLE_RATES`, a comma-separated list of `name=rate` pairs.
+    /// Malformed entries are skipped with a warning.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let rates = Self::default();
+        for entry in lookup("SAMPLE_RATES").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(name, rate)| Some((name.trim(), rate.trim().parse().ok()?)));
+            match parsed.map(|(name, rate)| rates.set(name, rate)) {
+                Some(Ok(())) => {}
+                _ => warn!("Ignoring SAMPLE_RATES entry '{}'", entry),
+            }
+        }
+        rates
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.rates.read().unwrap().get(name).copied()
+    }
+
+    /// Sets the rate for `name`; `1.0` keeps every sample again.
+    pub fn set(&self, name: &str, rate: f64) -> Result<()> {
+        if name.is_empty() {
+            return Err(RaftMetricsError::InvalidRequest("metric name must not be empty".to_string()));
+        }
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(RaftMetricsError::InvalidRequest("sample_rate must be between 0 and 1".to_string()));
+        }
+        let mut rates = self.rates.write().unwrap();
+        if rate == 1.0 {
+            rates.remove(name);
+        } else {
+            rates.insert(name.to_string(), rate);
+        }
+        Ok(())
+    }
+
+    /// How many samples the one of `name` at `timestamp` stands for, or
+    /// `None` when it is dropped. A kept sample counts `1/rate` times on
+    /// average, so counts and sums stay unbiased. The draw is a fixed hash
+    /// of `name` and `timestamp`, so a retried write is kept or dropped
+    /// exactly as it was the first time, by any node on any build.
+    pub fn weight(&self, name: &str, timestamp: i64) -> Option<u64> {
+        let Some(rate) = self.get(name) else { return Some(1) };
+        let bits = mix(seeded_hash(timestamp as u64, name));
+        let keep_draw = (bits >> 32) as f64 / (1u64 << 32) as f64;
+        let round_draw = (bits & 0xffff_ffff) as f64 / (1u64 << 32) as f64;
+        if keep_draw >= rate {
+            return None;
+        }
+        // Round 1/rate up or down at random so the expected weight is exact
+        let weight = 1.0 / rate;
+        let whole = weight.floor();
+        Some(whole as u64 + u64::from(round_draw < weight - whole))
+    }
+}
+
+/// The splitmix64 finalizer, so both halves of a hash of nearby inputs
+/// are evenly spread.
+fn mix(mut bits: u64) -> u64 {
+    bits = (bits ^ (bits >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    bits = (bits ^ (bits >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    bits ^ (bits >> 31)
+}
+
+/// Whether the `n`-th of a stream of items, counting from 1, is kept when
+/// keeping `rate` of them spread evenly: it is when it pushes `n * rate`
+/// past a whole number.
+pub fn keeps_nth(n: u64, rate: f64) -> bool {
+    (n as f64 * rate).floor() > (n.saturating_sub(1) as f64 * rate).floor()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_from_env_and_validation() {
+        let rates = SampleRates::from_lookup(|_| Some("hot=0.25, warm = 0.5,bad=2,noeq,cold=1".to_string()));
+        assert_eq!(rates.get("hot"), Some(0.25));
+        assert_eq!(rates.get("warm"), Some(0.5));
+        assert_eq!(rates.get("bad"), None);
+        assert_eq!(rates.get("cold"), None);
+        assert!(rates.set("hot", -0.1).is_err());
+        assert_eq!(rates.weight("other", 7), Some(1));
+    }
+
+    #[test]
+    fn test_weights_are_deterministic_and_unbiased() {
+        let rates = SampleRates::default();
+        rates.set("cpu", 0.3).unwrap();
+        let weights: Vec<Option<u64>> = (0..20_000).map(|timestamp| rates.weight("cpu", timestamp)).collect();
+        assert_eq!(weights, (0..20_000).map(|timestamp| rates.weight("cpu", timestamp)).collect::<Vec<_>>());
+
+        let kept = weights.iter().flatten().count();
+        let total: u64 = weights.iter().flatten().sum();
+        assert!((5_400..6_600).contains(&kept), "kept {}", kept);
+        assert!((18_000..22_000).contains(&total), "estimated {}", total);
+        assert!(weights.iter().flatten().all(|&w| w == 3 || w == 4));
+    }
+
+    #[test]
+    fn test_keeps_nth_spreads_evenly() {
+        let kept = |rate| (1..=12).filter(|&n| keeps_nth(n, rate)).collect::<Vec<_>>();
+        assert_eq!(kept(0.25), [4, 8, 12]);
+        assert_eq!(kept(0.5), [2, 4, 6, 8, 10, 12]);
+        assert_eq!(kept(1.0), (1..=12).collect::<Vec<_>>());
+        assert!(kept(0.0).is_empty());
+    }
+}
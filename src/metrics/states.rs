@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{RaftMetricsError, Result};
+
+/// Most states a state metric may have.
+pub const MAX_STATES: usize = 64;
+/// Longest name a state may have.
+pub const MAX_STATE_NAME: usize = 64;
+/// Where time between samples further apart than the staleness window
+/// accrues.
+pub const UNKNOWN_STATE: &str = "unknown";
+
+/// Where a state metric stands, driven only by the timestamps of its
+/// samples so every replica arrives at the same figures.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateTracker {
+    /// Index of the state the latest sample named.
+    pub current: Option<usize>,
+    /// When the metric entered `current`.
+    pub since: i64,
+    /// Timestamp of the latest sample.
+    pub last: i64,
+    /// Seconds spent in each state, by index.
+    pub durations: Vec<i64>,
+    /// Seconds between samples further apart than the staleness window.
+    pub unknown: i64,
+    /// Changes from one state to another.
+    pub transitions: u64,
+}
+
+/// A metric whose samples name one of a fixed list of states, by index,
+/// as set through `PUT /metrics/:name/states`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateMetric {
+    pub states: Vec<String>,
+    /// Gap between samples, in seconds, beyond which the time between them
+    /// counts as [`UNKNOWN_STATE`] rather than the earlier state; `None`
+    /// never does.
+    pub stale_after: Option<i64>,
+    #[serde(default)]
+    pub tracker: StateTracker,
+}
+
+impl StateMetric {
+    /// Validates a definition: 1 to [`MAX_STATES`] distinct, non-empty
+    /// names other than [`UNKNOWN_STATE`], and a positive window.
+    pub fn new(states: Vec<String>, stale_after: Option<i64>) -> Result<Self> {
+        let invalid = |message: String| Err(RaftMetricsError::InvalidRequest(message));
+        if states.is_empty() || states.len() > MAX_STATES {
+            return invalid(format!("a state metric needs 1 to {} states", MAX_STATES));
+        }
+        for (i, state) in states.iter().enumerate() {
+            if state.is_empty() || state.len() > MAX_STATE_NAME {
+                return invalid(format!("state names must be 1 to {} bytes", MAX_STATE_NAME));
+            }
+            if state == UNKNOWN_STATE {
+                return invalid(format!("'{}' is reserved for stale gaps", UNKNOWN_STATE));
+            }
+            if states[..i].contains(state) {
+                return invalid(format!("state '{}' is listed more than once", state));
+            }
+        }
+        if stale_after.is_some_and(|secs| secs <= 0) {
+            return invalid("stale_after_secs must be positive".to_string());
+        }
+        let tracker = StateTracker { durations: vec![0; states.len()], ..StateTracker::default() };
+        Ok(Self { states, stale_after, tracker })
+    }
+
+    /// This definition with `self`'s progress carried over when the states
+    /// are unchanged, so only the window changes.
+    pub fn redefined(&self, mut next: StateMetric) -> StateMetric {
+        if next.states == self.states {
+            next.tracker = self.tracker.clone();
+        }
+        next
+    }
+
+    /// The state a sample value names: a whole number indexing `states`.
+    pub fn index(&self, value: f64) -> Result<usize> {
+        if value.fract() == 0.0 && value >= 0.0 && (value as usize) < self.states.len() {
+            return Ok(value as usize);
+        }
+        Err(RaftMetricsError::InvalidRequest(format!(
+            "{} is not a state; expected 0 to {}", value, self.states.len() - 1
+        )))
+    }
+
+    /// Index of the state called `state`.
+    pub fn lookup(&self, state: &str) -> Result<usize> {
+        self.states.iter().position(|known| known == state).ok_or_else(|| {
+            RaftMetricsError::InvalidRequest(format!("unknown state '{}'; expected one of {}", state, self.states.join(", ")))
+        })
+    }
+
+    /// Moves to `state` at `timestamp`, crediting the time since the last
+    /// sample to the state it named, or to [`UNKNOWN_STATE`] when the gap
+    /// is past the window. Samples older than the latest are left out, so
+    /// replaying one changes nothing. Returns whether anything changed.
+    pub fn observe(&mut self, state: usize, timestamp: i64) -> bool {
+        let tracker = &mut self.tracker;
+        let Some(current) = tracker.current else {
+            *tracker = StateTracker { current: Some(state), since: timestamp, last: timestamp, ..std::mem::take(tracker) };
+            return true;
+        };
+        if timestamp < tracker.last || (timestamp == tracker.last && state == current) {
+            return false;
+        }
+
+        let gap = timestamp - tracker.last;
+        let stale = self.stale_after.is_some_and(|window| gap > window);
+        if stale {
+            tracker.unknown += gap;
+        } else {
+            tracker.durations[current] += gap;
+        }
+        if state != current {
+            tracker.transitions += 1;
+        }
+        if stale || state != current {
+            tracker.since = timestamp;
+        }
+        tracker.current = Some(state);
+        tracker.last = timestamp;
+        true
+    }
+
+    /// Forgets everything observed, keeping the definition.
+    pub fn reset(&mut self) {
+        self.tracker = StateTracker { durations: vec![0; self.states.len()], ..StateTracker::default() };
+    }
+
+    /// The state at `now`: the latest sample's, or [`UNKNOWN_STATE`] once
+    /// the window has passed since it.
+    pub fn current(&self, now: i64) -> Option<&str> {
+        let current = self.tracker.current?;
+        if self.stale_after.is_some_and(|window| now - self.tracker.last > window) {
+            return Some(UNKNOWN_STATE);
+        }
+        Some(&self.states[current])
+    }
+
+    pub fn report(&self, name: &str, now: i64) -> StateReport {
+        let tracker = &self.tracker;
+        let mut durations: BTreeMap<String, i64> =
+            self.states.iter().cloned().zip(tracker.durations.iter().copied()).collect();
+        durations.insert(UNKNOWN_STATE.to_string(), tracker.unknown);
+        let current = self.current(now).map(str::to_string);
+        // Unknown since the latest sample once the window passes
+        let since = match current.as_deref() {
+            Some(UNKNOWN_STATE) => Some(tracker.last),
+            Some(_) => Some(tracker.since),
+            None => None,
+        };
+        StateReport {
+            name: name.to_string(),
+            states: self.states.clone(),
+            current,
+            since,
+            in_state_secs: since.map(|since| (now - since).max(0)),
+            last_sample: tracker.current.map(|_| tracker.last),
+            transitions: tracker.transitions,
+            durations,
+            stale_after_secs: self.stale_after,
+        }
+    }
+}
+
+/// Body of `PUT /metrics/:name/states`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateDefinition {
+    pub states: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_after_secs: Option<i64>,
+}
+
+/// A state metric's durations and transitions, served at
+/// `GET /metrics/:name/states`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateReport {
+    pub name: String,
+    pub states: Vec<String>,
+    /// State at the time of the read; absent before the first sample.
+    pub current: Option<String>,
+    /// When the metric entered `current`.
+    pub since: Option<i64>,
+    pub in_state_secs: Option<i64>,
+    pub last_sample: Option<i64>,
+    pub transitions: u64,
+    /// Seconds spent in each state between samples, including
+    /// [`UNKNOWN_STATE`].
+    pub durations: BTreeMap<String, i64>,
+    pub stale_after_secs: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definitions_are_validated() {
+        let states = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert!(StateMetric::new(states(&["closed", "open"]), Some(60)).is_ok());
+        assert!(StateMetric::new(states(&[]), None).is_err());
+        assert!(StateMetric::new(states(&["open", "open"]), None).is_err());
+        assert!(StateMetric::new(states(&["ok", "unknown"]), None).is_err());
+        assert!(StateMetric::new(states(&["ok"]), Some(0)).is_err());
+
+        let metric = StateMetric::new(states(&["closed", "open"]), None).unwrap();
+        assert_eq!(metric.index(1.0).unwrap(), 1);
+        assert!(metric.index(2.0).is_err());
+        assert!(metric.index(0.5).is_err());
+        assert_eq!(metric.lookup("open").unwrap(), 1);
+        assert!(metric.lookup("half-open").is_err());
+    }
+}
@@ -0,0 +1,925 @@
+use async_trait::async_trait;
+use duckdb::{params, Connection, OptionalExt, Transaction};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::{RaftMetricsError, Result};
+use crate::metrics::{migrations, query::QueryResult, transform::IngestTransform, Labels, MetricAggregate, MetricPoint, QuarantinedSample, SampleSummary, StaleMetric, StorageStats};
+use super::duckdb_raw::RawDatabase;
+use super::{canonical_labels, BucketStats, GroupByQuery, MetricStorageBackend, RaftLogState, RangeCursor, RangeQuery, SeriesSummary, StagedWrite, NO_LABEL_GROUP};
+
+/// Prepared statements kept per connection. Covers every fixed statement on
+/// the write and read paths plus one per aggregate function for ranges and
+/// group-bys.
+pub const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Reader connections kept open between reads that run off the write lock.
+const MAX_IDLE_READERS: usize = 4;
+
+/// Writes `aggregate` as `name`'s aggregate row, creating it if missing.
+fn upsert_aggregate(db: &Connection, name: &str, aggregate: &MetricAggregate) -> Result<()> {
+    let updated = db
+        .prepare_cached(
+            "UPDATE metric_aggregates SET count = ?, sum = ?, average = ?, min = ?, max = ?, first_seen = ?, last_seen = ?, m2 = ? \
+             WHERE name = ?",
+        )?
+        .execute(params![
+            aggregate.count, aggregate.total(), aggregate.average, aggregate.min, aggregate.max,
+            aggregate.first_seen, aggregate.last_seen, aggregate.m2, name,
+        ])?;
+    if updated == 0 {
+        db.prepare_cached(
+            "INSERT INTO metric_aggregates (name, count, sum, average, min, max, first_seen, last_seen, m2) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            name, aggregate.count, aggregate.total(), aggregate.average, aggregate.min, aggregate.max,
+            aggregate.first_seen, aggregate.last_seen, aggregate.m2,
+        ])?;
+    }
+    Ok(())
+}
+
+/// Series ids by metric name and canonical label set, so a sample of a
+/// series already seen is stored without looking its labels up.
+#[derive(Debug, Default)]
+struct SeriesCache {
+    ids: HashMap<String, HashMap<String, u64>>,
+    next_id: u64,
+    /// Series created by the open transaction, to forget if it rolls back.
+    created: Vec<(String, String)>,
+}
+
+impl SeriesCache {
+    fn load(db: &Connection) -> Result<Self> {
+        let last: u64 = db.query_row("SELECT COALESCE(MAX(series_id), 0) FROM series", [], |row| row.get(0))?;
+        Ok(Self { next_id: last + 1, ..Default::default() })
+    }
+
+    fn forget_created(&mut self) {
+        for (name, labels) in self.created.drain(..) {
+            if let Some(ids) = self.ids.get_mut(&name) {
+                ids.remove(&labels);
+            }
+        }
+    }
+}
+
+/// Id of `name`'s series with `labels`, created on its first sample, with
+/// a sample at `timestamp` counted against it. One dictionary lookup at
+/// most, on a cache miss.
+fn intern_series(db: &Connection, series: &mut SeriesCache, name: &str, labels: &Labels, timestamp: i64) -> Result<u64> {
+    let key = canonical_labels(labels);
+    let id = match series.ids.get(name).and_then(|ids| ids.get(&key)) {
+        Some(&id) => id,
+        None => {
+            let stored = db
+                .prepare_cached("SELECT series_id FROM series WHERE name = ? AND labels = ?")?
+                .query_row(params![name, key], |row| row.get(0))
+                .optional()?;
+            let id = match stored {
+                Some(id) => id,
+                None => {
+                    let id = series.next_id;
+                    series.next_id += 1;
+                    db.prepare_cached(
+                        "INSERT INTO series (series_id, name, labels, first_seen, last_seen, samples) VALUES (?, ?, ?, ?, ?, 0)",
+                    )?
+                    .execute(params![id, name, key, timestamp, timestamp])?;
+                    if !labels.is_empty() {
+                        let mut insert_label = db.prepare_cached("INSERT INTO series_labels (series_id, key, value) VALUES (?, ?, ?)")?;
+                        for (label, value) in labels {
+                            insert_label.execute(params![id, label, value])?;
+                        }
+                    }
+                    series.created.push((name.to_string(), key.clone()));
+                    id
+                }
+            };
+            series.ids.entry(name.to_string()).or_default().insert(key, id);
+            id
+        }
+    };
+    db.prepare_cached(
+        "UPDATE series SET first_seen = LEAST(first_seen, ?), last_seen = GREATEST(last_seen, ?), samples = samples + 1 \
+         WHERE series_id = ?",
+    )?
+    .execute(params![timestamp, timestamp, id])?;
+    Ok(id)
+}
+
+/// Drops `name`'s series left without samples and recounts the rest,
+/// after samples of it were deleted.
+fn prune_series(db: &Connection, series: &mut SeriesCache, name: &str) -> Result<()> {
+    db.prepare_cached(
+        "DELETE FROM series_labels WHERE series_id IN ( \
+            SELECT series_id FROM series WHERE name = ? AND series_id NOT IN (SELECT series_id FROM metrics WHERE name = ?))",
+    )?
+    .execute(params![name, name])?;
+    db.prepare_cached("DELETE FROM series WHERE name = ? AND series_id NOT IN (SELECT series_id FROM metrics WHERE name = ?)")?
+        .execute(params![name, name])?;
+    db.prepare_cached("UPDATE series SET samples = (SELECT COUNT(*) FROM metrics m WHERE m.series_id = series.series_id) WHERE name = ?")?
+        .execute([name])?;
+    series.ids.remove(name);
+    Ok(())
+}
+
+/// Appends a sample under its series, with what it adds to the aggregate,
+/// and replaces the metric's aggregate.
+#[allow(clippy::too_many_arguments)]
+fn insert_sample_rows(
+    db: &Connection,
+    series: &mut SeriesCache,
+    name: &str,
+    sample: MetricPoint,
+    labels: &Labels,
+    seq: u64,
+    summary: &SampleSummary,
+    aggregate: Option<&MetricAggregate>,
+) -> Result<()> {
+    let series_id = intern_series(db, series, name, labels, sample.timestamp)?;
+    db.prepare_cached(
+        "INSERT INTO metrics (name, value, timestamp, seq, series_id, sample_count, sample_sum, sample_min, sample_max, sample_m2) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )?
+    .execute(params![
+        name, sample.value, sample.timestamp, seq, series_id, summary.count, summary.sum, summary.min, summary.max, summary.m2
+    ])?;
+    match aggregate {
+        Some(aggregate) => upsert_aggregate(db, name, aggregate),
+        None => Ok(()),
+    }
+}
+
+/// Hides `name`'s samples up to `deleted_at` and drops its aggregate and
+/// quarantined samples.
+fn tombstone(db: &Connection, name: &str, deleted_at: i64) -> Result<()> {
+    db.execute("DELETE FROM metric_tombstones WHERE name = ?", [name])?;
+    db.execute("INSERT INTO metric_tombstones (name, deleted_at) VALUES (?, ?)", params![name, deleted_at])?;
+    db.execute("DELETE FROM metric_aggregates WHERE name = ?", [name])?;
+    db.execute("DELETE FROM quarantined_samples WHERE name = ?", [name])?;
+    Ok(())
+}
+
+/// Raises the stored applied sequence to `seq`, creating the row at
+/// applied index 0 if no entry has been applied yet.
+fn raise_applied_seq(db: &Connection, seq: u64) -> Result<()> {
+    let updated = db
+        .prepare_cached("UPDATE raft_applied_index SET applied_seq = greatest(coalesce(applied_seq, 0), ?)")?
+        .execute([seq])?;
+    if updated == 0 {
+        db.prepare_cached("INSERT INTO raft_applied_index (applied_index, applied_seq) VALUES (0, ?)")?.execute([seq])?;
+    }
+    Ok(())
+}
+
+fn insert_quarantined(db: &Connection, name: &str, sample: &QuarantinedSample) -> Result<()> {
+    let labels = serde_json::to_string(&sample.labels)
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to encode labels: {}", e)))?;
+    db.prepare_cached(
+        "INSERT INTO quarantined_samples (name, value, timestamp, seq, labels, reason, weight) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )?
+    .execute(params![name, sample.value, sample.timestamp, sample.seq, labels, sample.reason, sample.weight])?;
+    Ok(())
+}
+
+/// Stores metrics in a DuckDB database, migrated to the latest schema on open.
+/// Queries run on the caller's task, under a lock never held across an await.
+/// Hot statements are prepared once and reused from the connection's cache,
+/// which lives and dies with the connection behind the lock.
+pub struct DuckDbBackend {
+    db: Mutex<Connection>,
+    /// Only locked while holding `db`.
+    series: Mutex<SeriesCache>,
+    /// Idle connections for reads that do not take `db`; see
+    /// [`DuckDbBackend::reader`].
+    readers: Mutex<Vec<Connection>>,
+    /// Declared last so the database closes after the connection in `db`.
+    raw: RawDatabase,
+}
+
+impl DuckDbBackend {
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_database(RawDatabase::open(None, false)?)
+    }
+
+    /// Opens (or creates) the DuckDB file at `path`.
+    pub fn open(path: &str) -> Result<Self> {
+        Self::with_database(RawDatabase::open(Some(path), false)?)
+    }
+
+    /// Opens the DuckDB file at `path` without migrating or writing to it,
+    /// for reading a worker's store offline. Refused while a worker has
+    /// the file open, as it holds the file's lock.
+    pub fn open_read_only(path: &str) -> Result<Self> {
+        let raw = Self::open_raw_read_only(path)?;
+        let conn = raw.connect()?;
+        let version = migrations::stored_version(&conn)?;
+        if version != migrations::latest_version() {
+            return Err(RaftMetricsError::InvalidRequest(format!(
+                "{} is at schema version {} rather than {}; start a worker on it once to migrate it",
+                path, version, migrations::latest_version()
+            )));
+        }
+        let series = SeriesCache::load(&conn)?;
+        Ok(Self { db: Mutex::new(conn), series: Mutex::new(series), readers: Mutex::default(), raw })
+    }
+
+    /// Schema version of the DuckDB file at `path`, read without migrating
+    /// or writing to it.
+    pub fn stored_schema_version(path: &str) -> Result<u32> {
+        let raw = Self::open_raw_read_only(path)?;
+        let version = migrations::stored_version(&raw.connect()?)?;
+        Ok(version)
+    }
+
+    fn open_raw_read_only(path: &str) -> Result<RawDatabase> {
+        RawDatabase::open(Some(path), true).map_err(|e| match e.to_string().contains("lock") {
+            true => RaftMetricsError::Conflict(format!("{} is in use by a running worker; stop it or use a copy", path)),
+            false => e,
+        })
+    }
+
+    fn with_database(raw: RawDatabase) -> Result<Self> {
+        let mut conn = raw.connect()?;
+        let version = migrations::run_migrations(&mut conn)?;
+        info!("Metrics store ready at schema version {}", version);
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        let series = SeriesCache::load(&conn)?;
+        Ok(Self { db: Mutex::new(conn), series: Mutex::new(series), readers: Mutex::default(), raw })
+    }
+
+    /// An idle reader connection, or a new one to the same database when
+    /// every reader is busy.
+    fn reader(&self) -> Result<Connection> {
+        if let Some(conn) = self.readers.lock().unwrap().pop() {
+            return Ok(conn);
+        }
+        Ok(self.db.lock().unwrap().try_clone()?)
+    }
+
+    /// Keeps `conn` for the next read, up to [`MAX_IDLE_READERS`].
+    fn release_reader(&self, conn: Connection) {
+        let mut readers = self.readers.lock().unwrap();
+        if readers.len() < MAX_IDLE_READERS {
+            readers.push(conn);
+        }
+    }
+
+    /// Runs `write` in a transaction, forgetting the series it created if
+    /// the transaction does not commit.
+    fn write<T>(&self, write: impl FnOnce(&Transaction, &mut SeriesCache) -> Result<T>) -> Result<T> {
+        let mut db = self.db.lock().unwrap();
+        let mut series = self.series.lock().unwrap();
+        // Dropped without a commit on the first failure, which rolls it back
+        let tx = db.transaction()?;
+        let written = write(&tx, &mut series).and_then(|value| {
+            tx.commit()?;
+            Ok(value)
+        });
+        match written {
+            Ok(_) => series.created.clear(),
+            Err(_) => series.forget_created(),
+        }
+        written
+    }
+
+    /// Runs raw SQL against the store, for tests that need to break it.
+    #[cfg(test)]
+    pub(crate) fn execute_batch(&self, sql: &str) -> Result<()> {
+        Ok(self.db.lock().unwrap().execute_batch(sql)?)
+    }
+}
+
+fn aggregate_from_row(row: &duckdb::Row, first: usize) -> duckdb::Result<MetricAggregate> {
+    Ok(MetricAggregate {
+        count: row.get(first)?,
+        sum: row.get(first + 1)?,
+        average: row.get(first + 2)?,
+        min: row.get(first + 3)?,
+        max: row.get(first + 4)?,
+        first_seen: row.get(first + 5)?,
+        last_seen: row.get(first + 6)?,
+        m2: row.get(first + 7)?,
+        ..Default::default()
+    })
+}
+
+#[async_trait]
+impl MetricStorageBackend for DuckDbBackend {
+    fn name(&self) -> &'static str {
+        "duckdb"
+    }
+
+    fn applied_index(&self) -> Result<Option<u64>> {
+        let applied_index = self.db.lock().unwrap()
+            .query_row("SELECT MAX(applied_index) FROM raft_applied_index", [], |row| row.get(0))
+            .optional()?
+            .flatten();
+        Ok(applied_index)
+    }
+
+    async fn set_applied_index(&self, index: u64) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        let updated = tx.prepare_cached("UPDATE raft_applied_index SET applied_index = ?")?.execute([index])?;
+        if updated == 0 {
+            tx.prepare_cached("INSERT INTO raft_applied_index (applied_index) VALUES (?)")?.execute([index])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn applied_seq(&self) -> Result<Option<u64>> {
+        let applied_seq = self.db.lock().unwrap()
+            .query_row("SELECT MAX(applied_seq) FROM raft_applied_index", [], |row| row.get(0))
+            .optional()?
+            .flatten();
+        Ok(applied_seq)
+    }
+
+    fn raft_log(&self, from: u64) -> Result<RaftLogState> {
+        let db = self.db.lock().unwrap();
+        let hard_state = db
+            .query_row("SELECT state FROM raft_hard_state", [], |row| row.get(0))
+            .optional()?;
+        let mut stmt = db.prepare("SELECT idx, entry FROM raft_log WHERE idx >= ? ORDER BY idx")?;
+        let rows = stmt.query_map([from], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(RaftLogState { hard_state, entries: rows.collect::<duckdb::Result<_>>()? })
+    }
+
+    async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()> {
+        let Some((first, _)) = entries.first() else {
+            return Ok(());
+        };
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.prepare_cached("DELETE FROM raft_log WHERE idx >= ?")?.execute([first])?;
+        let mut insert = tx.prepare_cached("INSERT INTO raft_log (idx, entry) VALUES (?, ?)")?;
+        for (index, entry) in entries {
+            insert.execute(params![index, entry])?;
+        }
+        drop(insert);
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        let updated = tx.prepare_cached("UPDATE raft_hard_state SET state = ?")?.execute([hard_state])?;
+        if updated == 0 {
+            tx.prepare_cached("INSERT INTO raft_hard_state (state) VALUES (?)")?.execute([hard_state])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn compact_raft_log(&self, index: u64) -> Result<()> {
+        self.db.lock().unwrap().prepare_cached("DELETE FROM raft_log WHERE idx < ?")?.execute([index])?;
+        Ok(())
+    }
+
+    fn tombstones(&self) -> Result<Vec<(String, i64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, deleted_at FROM metric_tombstones")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    fn hooks(&self) -> Result<Vec<(String, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT id, config FROM metric_hooks")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn put_hook(&self, id: &str, config: &str) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM metric_hooks WHERE id = ?", [id])?;
+        tx.execute("INSERT INTO metric_hooks (id, config) VALUES (?, ?)", params![id, config])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn transforms(&self) -> Result<Vec<(String, IngestTransform)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, scale, value_offset FROM metric_transforms")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, IngestTransform { scale: row.get(1)?, offset: row.get(2)? })))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn put_transform(&self, name: &str, transform: &IngestTransform) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM metric_transforms WHERE name = ?", [name])?;
+        tx.execute(
+            "INSERT INTO metric_transforms (name, scale, value_offset) VALUES (?, ?, ?)",
+            params![name, transform.scale, transform.offset],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn state_metrics(&self) -> Result<Vec<(String, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, config FROM metric_states")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn put_state_metric(&self, name: &str, config: &str) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM metric_states WHERE name = ?", [name])?;
+        tx.execute("INSERT INTO metric_states (name, config) VALUES (?, ?)", params![name, config])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn histograms(&self) -> Result<Vec<(String, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, config FROM metric_histograms")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn put_histogram(&self, name: &str, config: &str) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM metric_histograms WHERE name = ?", [name])?;
+        tx.execute("INSERT INTO metric_histograms (name, config) VALUES (?, ?)", params![name, config])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn applied_keys(&self) -> Result<Vec<(String, i64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT key, expires_at FROM applied_keys")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn put_applied_key(&self, key: &str, expires_at: i64) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM applied_keys WHERE key = ?", [key])?;
+        tx.execute("INSERT INTO applied_keys (key, expires_at) VALUES (?, ?)", params![key, expires_at])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn prune_applied_keys(&self, watermark: i64) -> Result<()> {
+        self.db.lock().unwrap().execute("DELETE FROM applied_keys WHERE expires_at <= ?", [watermark])?;
+        Ok(())
+    }
+
+    async fn insert_sample(
+        &self,
+        name: &str,
+        sample: MetricPoint,
+        labels: &Labels,
+        seq: u64,
+        aggregate: Option<&MetricAggregate>,
+    ) -> Result<()> {
+        self.write(|tx, series| insert_sample_rows(tx, series, name, sample, labels, seq, &SampleSummary::new(sample.value), aggregate))
+    }
+
+    async fn put_aggregate(&self, name: &str, aggregate: &MetricAggregate) -> Result<()> {
+        upsert_aggregate(&self.db.lock().unwrap(), name, aggregate)
+    }
+
+    async fn recomputed_aggregates(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let db = self.db.lock().unwrap();
+        // Deviations merge as in MetricAggregate::add_summary: each sample's
+        // about its own mean plus its mean's about the metric's
+        let mut stmt = db.prepare(
+            "SELECT name, SUM(sample_count)::UBIGINT, SUM(sample_sum), SUM(sample_sum) / SUM(sample_count),
+                    MIN(sample_min), MAX(sample_max), MIN(timestamp), MAX(timestamp),
+                    SUM(sample_m2 + sample_count * POW(sample_sum / sample_count - mean, 2))
+             FROM (
+                SELECT m.name, m.timestamp, m.sample_count, m.sample_sum, m.sample_min, m.sample_max, m.sample_m2,
+                       SUM(m.sample_sum) OVER (PARTITION BY m.name) / SUM(m.sample_count) OVER (PARTITION BY m.name) AS mean
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE (t.deleted_at IS NULL OR m.timestamp > t.deleted_at) AND m.name > ? AND starts_with(m.name, ?)
+             )
+             GROUP BY name ORDER BY name LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![after, prefix, limit as i64], |row| Ok((row.get(0)?, aggregate_from_row(row, 1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn write_batch(&self, writes: &[StagedWrite<'_>]) -> Result<()> {
+        self.write(|tx, series| {
+            for write in writes {
+                match write {
+                    StagedWrite::Sample { name, sample, labels, seq, summary, aggregate } => {
+                        insert_sample_rows(tx, series, name, *sample, labels, *seq, summary, aggregate.as_ref())?;
+                    }
+                    StagedWrite::Aggregate { name, aggregate } => upsert_aggregate(tx, name, aggregate)?,
+                    StagedWrite::Quarantine { name, sample } => insert_quarantined(tx, name, sample)?,
+                    StagedWrite::Delete { name, deleted_at } => tombstone(tx, name, *deleted_at)?,
+                    StagedWrite::Applied { seq } => raise_applied_seq(tx, *seq)?,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn latest(&self, name: &str, deleted_at: i64) -> Result<Option<MetricPoint>> {
+        let latest = self.db.lock().unwrap()
+            .prepare_cached(
+                "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp > ? \
+                 ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT 1",
+            )?
+            .query_row(params![name, deleted_at], |row| {
+                Ok(MetricPoint { value: row.get(0)?, timestamp: row.get(1)? })
+            })
+            .optional()?;
+        Ok(latest)
+    }
+
+    async fn latest_many(&self, names: &[String]) -> Result<Vec<(String, MetricPoint)>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; names.len()].join(", ");
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(&format!(
+            "SELECT name, value, timestamp FROM (
+                SELECT m.name, m.value, m.timestamp,
+                       row_number() OVER (PARTITION BY m.name ORDER BY m.timestamp DESC, m.seq DESC, m.rowid DESC) AS rn
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE m.name IN ({})
+                  AND (t.deleted_at IS NULL OR m.timestamp > t.deleted_at)
+             ) WHERE rn = 1",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(duckdb::params_from_iter(names), |row| {
+            Ok((row.get(0)?, MetricPoint { value: row.get(1)?, timestamp: row.get(2)? }))
+        })?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
+        // Off the runtime, on a reader connection, so the read neither
+        // stalls other tasks nor queues behind writes for the lock
+        let conn = self.reader()?;
+        let name = name.to_string();
+        let task = tokio::task::spawn_blocking(move || {
+            let aggregate = conn
+                .prepare_cached(
+                    "SELECT count, sum, average, min, max, first_seen, last_seen, m2 FROM metric_aggregates WHERE name = ?",
+                )
+                .and_then(|mut stmt| stmt.query_row([name], |row| aggregate_from_row(row, 0)).optional());
+            (conn, aggregate)
+        });
+        let (conn, aggregate) = task.await.map_err(|e| RaftMetricsError::Internal(format!("Aggregate read task failed: {}", e)))?;
+        self.release_reader(conn);
+        Ok(aggregate?)
+    }
+
+    async fn recent(&self, name: &str, from: i64, limit: usize) -> Result<Vec<MetricPoint>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare_cached(
+            "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp >= ? \
+             ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![name, from, limit as i64], |row| {
+            Ok(MetricPoint { value: row.get(0)?, timestamp: row.get(1)? })
+        })?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>> {
+        let db = self.db.lock().unwrap();
+        let bound = |order: &str| {
+            db.prepare_cached(&format!(
+                "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp BETWEEN ? AND ? \
+                 ORDER BY timestamp {order}, rowid {order} LIMIT 1"
+            ))?
+            .query_row(params![name, from, to], |row| {
+                Ok(MetricPoint { value: row.get(0)?, timestamp: row.get(1)? })
+            })
+            .optional()
+        };
+
+        match (bound("ASC")?, bound("DESC")?) {
+            (Some(earliest), Some(latest)) => Ok(Some((earliest, latest))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn range_page(
+        &self,
+        name: &str,
+        range: RangeQuery,
+        after: Option<RangeCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)> {
+        let after = after.unwrap_or(RangeCursor { timestamp: i64::MIN, position: i64::MIN });
+
+        let db = self.db.lock().unwrap();
+        let rows: Vec<(MetricPoint, i64)> = match range.step {
+            Some(step) => {
+                let mut stmt = db.prepare_cached(&format!(
+                    "SELECT {}, ? + (timestamp - ?) // ? * ? AS bucket FROM metrics \
+                     WHERE name = ? AND timestamp BETWEEN ? AND ? \
+                     GROUP BY bucket HAVING bucket > ? ORDER BY bucket LIMIT ?",
+                    range.function.sql()
+                ))?;
+                let rows = stmt.query_map(
+                    params![range.from, range.from, step, step, name, range.from, range.to, after.timestamp, limit as i64],
+                    |row| Ok((MetricPoint { value: row.get(0)?, timestamp: row.get(1)? }, i64::MAX)),
+                )?;
+                rows.collect::<duckdb::Result<_>>()?
+            }
+            None => {
+                let mut stmt = db.prepare_cached(
+                    "SELECT value, timestamp, rowid FROM metrics \
+                     WHERE name = ? AND timestamp BETWEEN ? AND ? \
+                     AND (timestamp > ? OR (timestamp = ? AND rowid > ?)) \
+                     ORDER BY timestamp, rowid LIMIT ?",
+                )?;
+                let rows = stmt.query_map(
+                    params![name, range.from, range.to, after.timestamp, after.timestamp, after.position, limit as i64],
+                    |row| Ok((MetricPoint { value: row.get(0)?, timestamp: row.get(1)? }, row.get(2)?)),
+                )?;
+                rows.collect::<duckdb::Result<_>>()?
+            }
+        };
+
+        let next = match rows.last() {
+            Some(&(last, position)) if rows.len() == limit => Some(RangeCursor { timestamp: last.timestamp, position }),
+            _ => None,
+        };
+        Ok((rows.into_iter().map(|(point, _)| point).collect(), next))
+    }
+
+    async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>> {
+        let db = self.db.lock().unwrap();
+        // `%` keeps the dividend's sign, so the second one floors pre-epoch timestamps
+        let mut stmt = db.prepare_cached(
+            "SELECT timestamp - (timestamp % ? + ?) % ? AS bucket, count(value), sum(value), min(value), max(value) \
+             FROM metrics WHERE name = ? AND timestamp BETWEEN ? AND ? \
+             GROUP BY bucket ORDER BY bucket",
+        )?;
+        let rows = stmt.query_map(params![step, step, step, name, from, to], |row| {
+            Ok(BucketStats {
+                start: row.get(0)?,
+                count: row.get::<_, i64>(1)? as u64,
+                sum: row.get(2)?,
+                min: row.get(3)?,
+                max: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn group_by(&self, name: &str, query: &GroupByQuery) -> Result<(Vec<(String, f64)>, bool)> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare_cached(&format!(
+            "SELECT grp, {} FROM ( \
+                SELECT COALESCE(l.value, ?) AS grp, m.value AS value FROM metrics m \
+                LEFT JOIN series_labels l ON l.series_id = m.series_id AND l.key = ? \
+                WHERE m.name = ? AND m.timestamp BETWEEN ? AND ? \
+             ) GROUP BY grp ORDER BY grp LIMIT ?",
+            query.function.sql()
+        ))?;
+        let rows = stmt.query_map(
+            params![NO_LABEL_GROUP, query.label, name, query.from, query.to, query.max_groups as i64 + 1],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let mut groups: Vec<(String, f64)> = rows.collect::<duckdb::Result<_>>()?;
+        let truncated = groups.len() > query.max_groups;
+        groups.truncate(query.max_groups);
+        Ok((groups, truncated))
+    }
+
+    async fn series(&self, name: &str, labels: &Labels, deleted_at: i64) -> Result<Option<SeriesSummary>> {
+        let db = self.db.lock().unwrap();
+        // The window totals cover every row the filter keeps, not just the
+        // one left by the limit
+        let series = db.prepare_cached(
+            "SELECT value, timestamp, count(*) OVER (), sum(value) OVER (), min(value) OVER (), max(value) OVER () \
+             FROM metrics WHERE name = ? AND timestamp > ? \
+               AND series_id = (SELECT series_id FROM series WHERE name = ? AND labels = ?) \
+             ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT 1",
+        )?
+        .query_row(params![name, deleted_at, name, canonical_labels(labels)], |row| {
+            Ok(SeriesSummary {
+                latest: MetricPoint { value: row.get(0)?, timestamp: row.get(1)? },
+                count: row.get::<_, i64>(2)? as u64,
+                sum: row.get(3)?,
+                min: row.get(4)?,
+                max: row.get(5)?,
+            })
+        })
+        .optional()?;
+        Ok(series)
+    }
+
+    async fn quarantine(&self, name: &str, sample: &QuarantinedSample) -> Result<()> {
+        insert_quarantined(&self.db.lock().unwrap(), name, sample)
+    }
+
+    async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare_cached(
+            "SELECT value, timestamp, seq, labels, reason, weight FROM quarantined_samples WHERE name = ? ORDER BY seq, rowid",
+        )?;
+        let rows = stmt.query_map([name], |row| {
+            Ok((
+                row.get::<_, f64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, u64>(5)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (value, timestamp, seq, labels, reason, weight) = row?;
+            let labels = serde_json::from_str(&labels)
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to decode labels: {}", e)))?;
+            Ok(QuarantinedSample { value, timestamp, labels, seq, reason, weight })
+        })
+        .collect()
+    }
+
+    async fn drop_quarantined(&self, name: &str) -> Result<()> {
+        self.db.lock().unwrap().execute("DELETE FROM quarantined_samples WHERE name = ?", [name])?;
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str, deleted_at: i64) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tombstone(&tx, name, deleted_at)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn purge(&self, expired: &[(String, i64)]) -> Result<()> {
+        self.write(|tx, series| {
+            for (name, deleted_at) in expired {
+                tx.execute(
+                    "DELETE FROM metrics WHERE name = ? AND timestamp <= ?",
+                    params![name, deleted_at],
+                )?;
+                prune_series(tx, series, name)?;
+                tx.execute("DELETE FROM metric_tombstones WHERE name = ?", [name])?;
+            }
+            Ok(())
+        })
+    }
+
+    async fn compact(&self, name: &str, before: i64, interval: i64, limit: usize) -> Result<usize> {
+        self.write(|tx, series| {
+            let removed = tx
+                .prepare_cached(
+                    "DELETE FROM metrics WHERE rowid IN (
+                        SELECT rowid FROM (
+                            SELECT rowid, ROW_NUMBER() OVER (
+                                PARTITION BY FLOOR(timestamp::DOUBLE / ?) ORDER BY timestamp DESC, seq DESC, rowid DESC
+                            ) AS rank
+                            FROM metrics WHERE name = ? AND timestamp < ?
+                        ) WHERE rank > 1 ORDER BY rowid LIMIT ?
+                    )",
+                )?
+                .execute(params![interval, name, before, limit as i64])?;
+            if removed > 0 {
+                prune_series(tx, series, name)?;
+            }
+            Ok(removed)
+        })
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.write(|tx, series| {
+            tx.execute_batch(
+                "DELETE FROM metrics;
+                 DELETE FROM series;
+                 DELETE FROM series_labels;
+                 DELETE FROM metric_aggregates;
+                 DELETE FROM metric_tombstones;
+                 DELETE FROM quarantined_samples;",
+            )?;
+            series.ids.clear();
+            Ok(())
+        })
+    }
+
+    async fn stats(&self) -> Result<StorageStats> {
+        let (metric_count, sample_rows, tombstones, series) = self.db.lock().unwrap().query_row(
+            "SELECT (SELECT COUNT(*) FROM metric_aggregates),
+                    (SELECT COUNT(*) FROM metrics),
+                    (SELECT COUNT(*) FROM metric_tombstones),
+                    (SELECT COUNT(*) FROM series)",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)),
+        )?;
+        Ok(StorageStats {
+            metric_count: metric_count as u64,
+            sample_rows: sample_rows as u64,
+            tombstones: tombstones as u64,
+            series: series as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn aggregates_page(&self, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, count, sum, average, min, max, first_seen, last_seen, m2 FROM metric_aggregates
+             WHERE name > ? ORDER BY name LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![after, limit as i64], |row| Ok((row.get(0)?, aggregate_from_row(row, 1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn stale_page(&self, cutoff: i64, after: &str, limit: usize) -> Result<Vec<StaleMetric>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, first_seen, last_seen FROM metric_aggregates
+             WHERE last_seen < ? AND name > ? ORDER BY name LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![cutoff, after, limit as i64], |row| {
+            Ok(StaleMetric { name: row.get(0)?, first_seen: row.get(1)?, last_seen: row.get(2)? })
+        })?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn latest_between(&self, after: &str, last: &str) -> Result<Vec<(String, MetricPoint, u64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, value, timestamp, max_seq FROM (
+                SELECT m.name, m.value, m.timestamp,
+                       max(m.seq) OVER (PARTITION BY m.name) AS max_seq,
+                       row_number() OVER (PARTITION BY m.name ORDER BY m.timestamp DESC, m.seq DESC, m.rowid DESC) AS rn
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE m.name > ? AND m.name <= ?
+                  AND (t.deleted_at IS NULL OR m.timestamp > t.deleted_at)
+             ) WHERE rn = 1",
+        )?;
+        let rows = stmt.query_map(params![after, last], |row| {
+            Ok((
+                row.get(0)?,
+                MetricPoint { value: row.get(1)?, timestamp: row.get(2)? },
+                row.get(3)?,
+            ))
+        })?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn all_latest(&self) -> Result<Vec<(String, f64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, value FROM (
+                SELECT m.name, m.value,
+                       row_number() OVER (PARTITION BY m.name ORDER BY m.timestamp DESC, m.seq DESC, m.rowid DESC) AS rn
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE t.deleted_at IS NULL OR m.timestamp > t.deleted_at
+             ) WHERE rn = 1",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn aggregates_slice(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, count, sum, average, min, max, first_seen, last_seen, m2 FROM metric_aggregates
+             ORDER BY name LIMIT ? OFFSET ?",
+        )?;
+        // DuckDB refuses a LIMIT past 2^62
+        let limit = limit.min(1 << 62) as i64;
+        let rows = stmt.query_map(params![limit, offset as i64], |row| Ok((row.get(0)?, aggregate_from_row(row, 1)?)))?;
+        Ok(rows.collect::<duckdb::Result<_>>()?)
+    }
+
+    async fn query(&self, sql: &str, max_rows: usize, deadline: std::time::Instant) -> Result<QueryResult> {
+        // The CTEs shadow the tables, hiding deleted samples and internal columns
+        let sql = format!(
+            "WITH metrics AS (
+                SELECT m.name, m.value, m.timestamp FROM main.metrics m
+                LEFT JOIN main.metric_tombstones t ON t.name = m.name
+                WHERE t.deleted_at IS NULL OR m.timestamp > t.deleted_at
+             ), metric_aggregates AS (
+                SELECT name, count, sum, average, min, max, first_seen, last_seen FROM main.metric_aggregates
+             )
+             SELECT * FROM ({}) AS query LIMIT {}",
+            sql,
+            max_rows + 1
+        );
+
+        // On a connection of its own, so a slow query never holds up writes
+        self.raw.query(&sql, max_rows, deadline).await
+    }
+}
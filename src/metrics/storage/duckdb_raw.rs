@@ -0,0 +1,178 @@
+//! The parts of DuckDB's C API that the `duckdb` crate keeps to itself: the
+//! database handle, and connections that can be interrupted from another
+//! thread while a query runs on them.
+
+use duckdb::{ffi, Connection};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::metrics::query::QueryResult;
+use crate::{RaftMetricsError, Result};
+
+/// An open DuckDB database, closed when dropped. Connections made from it
+/// keep the database itself alive until they are dropped too.
+pub struct RawDatabase(ffi::duckdb_database);
+
+// DuckDB databases and connections are safe to use from any thread
+unsafe impl Send for RawDatabase {}
+unsafe impl Sync for RawDatabase {}
+
+impl RawDatabase {
+    /// Opens the file at `path`, or a database in memory.
+    pub fn open(path: Option<&str>, read_only: bool) -> Result<Self> {
+        let path = CString::new(path.unwrap_or(":memory:"))
+            .map_err(|_| RaftMetricsError::InvalidRequest("database path contains a NUL byte".to_string()))?;
+        unsafe {
+            let mut config: ffi::duckdb_config = ptr::null_mut();
+            if ffi::duckdb_create_config(&mut config) != ffi::duckdb_state_DuckDBSuccess {
+                return Err(RaftMetricsError::Internal("Failed to create a DuckDB config".to_string()));
+            }
+            if read_only {
+                ffi::duckdb_set_config(config, c"access_mode".as_ptr(), c"READ_ONLY".as_ptr());
+            }
+            let mut db: ffi::duckdb_database = ptr::null_mut();
+            let mut error: *mut c_char = ptr::null_mut();
+            let state = ffi::duckdb_open_ext(path.as_ptr(), &mut db, config, &mut error);
+            ffi::duckdb_destroy_config(&mut config);
+            if state != ffi::duckdb_state_DuckDBSuccess {
+                let message = match error.is_null() {
+                    true => "Failed to open the database".to_string(),
+                    false => CStr::from_ptr(error).to_string_lossy().into_owned(),
+                };
+                ffi::duckdb_free(error as *mut c_void);
+                return Err(RaftMetricsError::Internal(message));
+            }
+            Ok(Self(db))
+        }
+    }
+
+    /// A new `duckdb` connection, which must not outlive `self`.
+    pub fn connect(&self) -> Result<Connection> {
+        Ok(unsafe { Connection::open_from_raw(self.0) }?)
+    }
+
+    /// Runs `sql` on a connection of its own off the runtime, interrupting
+    /// it once `deadline` passes so a slow statement stops using the CPU.
+    pub async fn query(&self, sql: &str, max_rows: usize, deadline: Instant) -> Result<QueryResult> {
+        let sql = CString::new(sql).map_err(|_| RaftMetricsError::InvalidRequest("query contains a NUL byte".to_string()))?;
+        let connection = Arc::new(QueryConnection::connect(self)?);
+        let running = connection.clone();
+        let task = tokio::task::spawn_blocking(move || running.run(&sql, max_rows));
+        // Detached, so it still fires when the caller gives up on the query first
+        let watchdog = tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            connection.interrupt();
+        });
+
+        let result = task.await.map_err(|e| RaftMetricsError::Internal(format!("Query task failed: {}", e)))?;
+        watchdog.abort();
+        result.map_err(|e| match Instant::now() >= deadline {
+            true => RaftMetricsError::Internal("Query interrupted at its deadline".to_string()),
+            false => e,
+        })
+    }
+}
+
+impl Drop for RawDatabase {
+    fn drop(&mut self) {
+        unsafe { ffi::duckdb_close(&mut self.0) }
+    }
+}
+
+/// A connection that [`QueryConnection::interrupt`] may stop from another
+/// thread. Disconnected once the last reference is dropped, so an
+/// interrupt never reaches a closed connection.
+struct QueryConnection(ffi::duckdb_connection);
+
+unsafe impl Send for QueryConnection {}
+unsafe impl Sync for QueryConnection {}
+
+impl QueryConnection {
+    fn connect(db: &RawDatabase) -> Result<Self> {
+        let mut con: ffi::duckdb_connection = ptr::null_mut();
+        if unsafe { ffi::duckdb_connect(db.0, &mut con) } != ffi::duckdb_state_DuckDBSuccess {
+            return Err(RaftMetricsError::Internal("Failed to connect to the database".to_string()));
+        }
+        Ok(Self(con))
+    }
+
+    fn interrupt(&self) {
+        unsafe { ffi::duckdb_interrupt(self.0) }
+    }
+
+    /// Runs `sql` and reads its first `max_rows` rows.
+    fn run(&self, sql: &CStr, max_rows: usize) -> Result<QueryResult> {
+        let mut result: ffi::duckdb_result = unsafe { std::mem::zeroed() };
+        let state = unsafe { ffi::duckdb_query(self.0, sql.as_ptr(), &mut result) };
+        let read = match state == ffi::duckdb_state_DuckDBSuccess {
+            true => Ok(unsafe { read_result(&mut result, max_rows) }),
+            false => Err(RaftMetricsError::InvalidRequest(unsafe {
+                let error = ffi::duckdb_result_error(&mut result);
+                match error.is_null() {
+                    true => "Query failed".to_string(),
+                    false => CStr::from_ptr(error).to_string_lossy().into_owned(),
+                }
+            })),
+        };
+        unsafe { ffi::duckdb_destroy_result(&mut result) };
+        read
+    }
+}
+
+impl Drop for QueryConnection {
+    fn drop(&mut self) {
+        unsafe { ffi::duckdb_disconnect(&mut self.0) }
+    }
+}
+
+/// Reads a materialized result into JSON values: integers and floats as
+/// numbers, timestamps as microseconds, dates as days and the rest as text.
+unsafe fn read_result(result: &mut ffi::duckdb_result, max_rows: usize) -> QueryResult {
+    let column_count = ffi::duckdb_column_count(result);
+    let row_count = ffi::duckdb_row_count(result) as usize;
+    let columns = (0..column_count)
+        .map(|col| CStr::from_ptr(ffi::duckdb_column_name(result, col)).to_string_lossy().into_owned())
+        .collect();
+    let rows = (0..row_count.min(max_rows) as u64)
+        .map(|row| (0..column_count).map(|col| read_value(result, col, row)).collect())
+        .collect();
+    QueryResult { columns, rows, truncated: row_count > max_rows }
+}
+
+unsafe fn read_value(result: &mut ffi::duckdb_result, col: ffi::idx_t, row: ffi::idx_t) -> serde_json::Value {
+    use serde_json::Value as Json;
+    if ffi::duckdb_value_is_null(result, col, row) {
+        return Json::Null;
+    }
+    match ffi::duckdb_column_type(result, col) {
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BOOLEAN => Json::from(ffi::duckdb_value_boolean(result, col, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TINYINT
+        | ffi::DUCKDB_TYPE_DUCKDB_TYPE_SMALLINT
+        | ffi::DUCKDB_TYPE_DUCKDB_TYPE_INTEGER
+        | ffi::DUCKDB_TYPE_DUCKDB_TYPE_BIGINT
+        | ffi::DUCKDB_TYPE_DUCKDB_TYPE_UTINYINT
+        | ffi::DUCKDB_TYPE_DUCKDB_TYPE_USMALLINT
+        | ffi::DUCKDB_TYPE_DUCKDB_TYPE_UINTEGER => Json::from(ffi::duckdb_value_int64(result, col, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UBIGINT => Json::from(ffi::duckdb_value_uint64(result, col, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_FLOAT | ffi::DUCKDB_TYPE_DUCKDB_TYPE_DOUBLE => {
+            serde_json::Number::from_f64(ffi::duckdb_value_double(result, col, row)).map_or(Json::Null, Json::Number)
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP => Json::from(ffi::duckdb_value_timestamp(result, col, row).micros),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DATE => Json::from(ffi::duckdb_value_date(result, col, row).days),
+        // HUGEINTs that fit in an i64 as numbers, like the smaller integers
+        _ => {
+            let text = ffi::duckdb_value_varchar(result, col, row);
+            if text.is_null() {
+                return Json::Null;
+            }
+            let value = CStr::from_ptr(text).to_string_lossy().into_owned();
+            ffi::duckdb_free(text as *mut c_void);
+            match ffi::duckdb_column_type(result, col) {
+                ffi::DUCKDB_TYPE_DUCKDB_TYPE_HUGEINT => value.parse::<i64>().map_or(Json::from(value), Json::from),
+                _ => Json::from(value),
+            }
+        }
+    }
+}
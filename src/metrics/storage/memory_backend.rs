@@ -0,0 +1,612 @@
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::Bound;
+use std::sync::Mutex;
+
+use crate::Result;
+use crate::metrics::{transform::IngestTransform, Labels, MetricAggregate, MetricPoint, QuarantinedSample, SampleSummary, StaleMetric, StorageStats, Summation};
+use super::{AggregateFn, BucketStats, GroupByQuery, MetricStorageBackend, RaftLogState, RangeCursor, RangeQuery, SeriesSummary, StagedWrite, NO_LABEL_GROUP};
+
+/// Samples the memory backend keeps per metric unless configured otherwise.
+pub const DEFAULT_MEMORY_RETENTION: usize = 10_000;
+
+/// A stored sample. `position` orders samples like DuckDB's `rowid`, and
+/// `summary` is what it added to its aggregate.
+#[derive(Debug, Clone)]
+struct Sample {
+    point: MetricPoint,
+    seq: u64,
+    position: i64,
+    labels: Labels,
+    summary: SampleSummary,
+}
+
+#[derive(Debug, Default)]
+struct Store {
+    applied_index: Option<u64>,
+    applied_seq: Option<u64>,
+    raft_hard_state: Option<Vec<u8>>,
+    raft_log: BTreeMap<u64, Vec<u8>>,
+    samples: BTreeMap<String, VecDeque<Sample>>,
+    aggregates: BTreeMap<String, MetricAggregate>,
+    tombstones: BTreeMap<String, i64>,
+    hooks: BTreeMap<String, String>,
+    transforms: BTreeMap<String, IngestTransform>,
+    state_metrics: BTreeMap<String, String>,
+    histograms: BTreeMap<String, String>,
+    applied_keys: BTreeMap<String, i64>,
+    quarantined: BTreeMap<String, Vec<QuarantinedSample>>,
+    next_position: i64,
+}
+
+impl Store {
+    /// Samples of `name` written after its tombstone, if any.
+    fn live<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a Sample> + 'a {
+        let deleted_at = self.tombstones.get(name).copied().unwrap_or(i64::MIN);
+        self.samples.get(name).into_iter().flatten().filter(move |sample| sample.point.timestamp > deleted_at)
+    }
+
+    /// Hides `name`'s samples up to `deleted_at` and drops its aggregate
+    /// and quarantined samples.
+    fn tombstone(&mut self, name: &str, deleted_at: i64) {
+        self.tombstones.insert(name.to_string(), deleted_at);
+        self.aggregates.remove(name);
+        self.quarantined.remove(name);
+    }
+
+    /// Appends a sample, keeping at most `retention` of the metric's.
+    #[allow(clippy::too_many_arguments)]
+    fn insert(
+        &mut self,
+        name: &str,
+        sample: MetricPoint,
+        labels: &Labels,
+        seq: u64,
+        summary: SampleSummary,
+        aggregate: Option<&MetricAggregate>,
+        retention: usize,
+    ) {
+        let position = self.next_position;
+        self.next_position += 1;
+        let samples = self.samples.entry(name.to_string()).or_default();
+        samples.push_back(Sample { point: sample, seq, position, labels: labels.clone(), summary });
+        while samples.len() > retention {
+            samples.pop_front();
+        }
+        if let Some(aggregate) = aggregate {
+            self.aggregates.insert(name.to_string(), stored(aggregate));
+        }
+    }
+
+    fn in_range<'a>(&'a self, name: &str, from: i64, to: i64) -> impl Iterator<Item = &'a Sample> + 'a {
+        self.samples
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(move |sample| (from..=to).contains(&sample.point.timestamp))
+    }
+}
+
+/// Latest of `samples`: the greatest timestamp, ties going to the later
+/// sequence and then the later write.
+fn latest<'a>(samples: impl Iterator<Item = &'a Sample>) -> Option<&'a Sample> {
+    samples.max_by_key(|sample| (sample.point.timestamp, sample.seq, sample.position))
+}
+
+/// `aggregate` as DuckDB stores it: the corrected sum, no compensation.
+fn stored(aggregate: &MetricAggregate) -> MetricAggregate {
+    MetricAggregate { sum: aggregate.total(), compensation: 0.0, ..aggregate.clone() }
+}
+
+fn apply(function: AggregateFn, values: &[f64]) -> f64 {
+    match function {
+        AggregateFn::Avg => values.iter().sum::<f64>() / values.len() as f64,
+        AggregateFn::Sum => values.iter().sum(),
+        AggregateFn::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+        AggregateFn::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        AggregateFn::Count => values.len() as f64,
+    }
+}
+
+/// Keeps metrics in process memory, for builds without DuckDB and for
+/// embedding and tests. Each metric keeps only its most recent `retention`
+/// samples, so range and group-by reads cover that much history; latest
+/// values and aggregates are always complete. Nothing survives a restart
+/// and SQL queries are not supported.
+#[derive(Debug)]
+pub struct MemoryBackend {
+    store: Mutex<Store>,
+    retention: usize,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::with_retention(DEFAULT_MEMORY_RETENTION)
+    }
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps at most `retention` samples per metric, the oldest going first.
+    pub fn with_retention(retention: usize) -> Self {
+        Self { store: Mutex::new(Store::default()), retention: retention.max(1) }
+    }
+
+    /// Reads `MEMORY_RETENTION_SAMPLES`.
+    pub fn from_env() -> Self {
+        Self::with_retention(
+            std::env::var("MEMORY_RETENTION_SAMPLES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MEMORY_RETENTION),
+        )
+    }
+}
+
+#[async_trait]
+impl MetricStorageBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn applied_index(&self) -> Result<Option<u64>> {
+        Ok(self.store.lock().unwrap().applied_index)
+    }
+
+    async fn set_applied_index(&self, index: u64) -> Result<()> {
+        self.store.lock().unwrap().applied_index = Some(index);
+        Ok(())
+    }
+
+    fn applied_seq(&self) -> Result<Option<u64>> {
+        Ok(self.store.lock().unwrap().applied_seq)
+    }
+
+    fn raft_log(&self, from: u64) -> Result<RaftLogState> {
+        let store = self.store.lock().unwrap();
+        Ok(RaftLogState {
+            hard_state: store.raft_hard_state.clone(),
+            entries: store.raft_log.range(from..).map(|(index, entry)| (*index, entry.clone())).collect(),
+        })
+    }
+
+    async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        if let Some((first, _)) = entries.first() {
+            store.raft_log.split_off(first);
+        }
+        store.raft_log.extend(entries.iter().cloned());
+        Ok(())
+    }
+
+    async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()> {
+        self.store.lock().unwrap().raft_hard_state = Some(hard_state.to_vec());
+        Ok(())
+    }
+
+    async fn compact_raft_log(&self, index: u64) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        store.raft_log = store.raft_log.split_off(&index);
+        Ok(())
+    }
+
+    fn tombstones(&self) -> Result<Vec<(String, i64)>> {
+        Ok(self.store.lock().unwrap().tombstones.iter().map(|(name, at)| (name.clone(), *at)).collect())
+    }
+
+    fn hooks(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.store.lock().unwrap().hooks.iter().map(|(id, config)| (id.clone(), config.clone())).collect())
+    }
+
+    async fn put_hook(&self, id: &str, config: &str) -> Result<()> {
+        self.store.lock().unwrap().hooks.insert(id.to_string(), config.to_string());
+        Ok(())
+    }
+
+    fn transforms(&self) -> Result<Vec<(String, IngestTransform)>> {
+        Ok(self.store.lock().unwrap().transforms.iter().map(|(name, transform)| (name.clone(), *transform)).collect())
+    }
+
+    async fn put_transform(&self, name: &str, transform: &IngestTransform) -> Result<()> {
+        self.store.lock().unwrap().transforms.insert(name.to_string(), *transform);
+        Ok(())
+    }
+
+    fn state_metrics(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.store.lock().unwrap().state_metrics.iter().map(|(name, config)| (name.clone(), config.clone())).collect())
+    }
+
+    async fn put_state_metric(&self, name: &str, config: &str) -> Result<()> {
+        self.store.lock().unwrap().state_metrics.insert(name.to_string(), config.to_string());
+        Ok(())
+    }
+
+    fn histograms(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.store.lock().unwrap().histograms.iter().map(|(name, config)| (name.clone(), config.clone())).collect())
+    }
+
+    async fn put_histogram(&self, name: &str, config: &str) -> Result<()> {
+        self.store.lock().unwrap().histograms.insert(name.to_string(), config.to_string());
+        Ok(())
+    }
+
+    fn applied_keys(&self) -> Result<Vec<(String, i64)>> {
+        Ok(self.store.lock().unwrap().applied_keys.iter().map(|(key, &expires_at)| (key.clone(), expires_at)).collect())
+    }
+
+    async fn put_applied_key(&self, key: &str, expires_at: i64) -> Result<()> {
+        self.store.lock().unwrap().applied_keys.insert(key.to_string(), expires_at);
+        Ok(())
+    }
+
+    async fn prune_applied_keys(&self, watermark: i64) -> Result<()> {
+        self.store.lock().unwrap().applied_keys.retain(|_, &mut expires_at| expires_at > watermark);
+        Ok(())
+    }
+
+    async fn insert_sample(
+        &self,
+        name: &str,
+        sample: MetricPoint,
+        labels: &Labels,
+        seq: u64,
+        aggregate: Option<&MetricAggregate>,
+    ) -> Result<()> {
+        self.store.lock().unwrap().insert(name, sample, labels, seq, SampleSummary::new(sample.value), aggregate, self.retention);
+        Ok(())
+    }
+
+    async fn put_aggregate(&self, name: &str, aggregate: &MetricAggregate) -> Result<()> {
+        self.store.lock().unwrap().aggregates.insert(name.to_string(), stored(aggregate));
+        Ok(())
+    }
+
+    async fn recomputed_aggregates(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let store = self.store.lock().unwrap();
+        let mut rebuilt = Vec::new();
+        let names = store.samples.range::<str, _>((Bound::Excluded(after), Bound::Unbounded)).map(|(name, _)| name);
+        for name in names.filter(|name| name.starts_with(prefix)) {
+            if rebuilt.len() == limit {
+                break;
+            }
+            let mut live = store.live(name);
+            let Some(first) = live.next() else { continue };
+            let mut aggregate = MetricAggregate {
+                min: first.summary.min,
+                max: first.summary.max,
+                first_seen: first.point.timestamp,
+                last_seen: first.point.timestamp,
+                ..Default::default()
+            };
+            for sample in std::iter::once(first).chain(live) {
+                aggregate.add_summary(&sample.summary, 1, Summation::Naive);
+                aggregate.first_seen = aggregate.first_seen.min(sample.point.timestamp);
+                aggregate.last_seen = aggregate.last_seen.max(sample.point.timestamp);
+            }
+            rebuilt.push((name.clone(), aggregate));
+        }
+        Ok(rebuilt)
+    }
+
+    async fn write_batch(&self, writes: &[StagedWrite<'_>]) -> Result<()> {
+        // Nothing here can fail, so writing under one lock is all-or-nothing
+        let mut store = self.store.lock().unwrap();
+        for write in writes {
+            match write {
+                StagedWrite::Sample { name, sample, labels, seq, summary, aggregate } => {
+                    store.insert(name, *sample, labels, *seq, *summary, aggregate.as_ref(), self.retention);
+                }
+                StagedWrite::Aggregate { name, aggregate } => {
+                    store.aggregates.insert(name.to_string(), stored(aggregate));
+                }
+                StagedWrite::Quarantine { name, sample } => {
+                    store.quarantined.entry(name.to_string()).or_default().push(sample.clone());
+                }
+                StagedWrite::Delete { name, deleted_at } => store.tombstone(name, *deleted_at),
+                StagedWrite::Applied { seq } => store.applied_seq = store.applied_seq.max(Some(*seq)),
+            }
+        }
+        Ok(())
+    }
+
+    async fn latest(&self, name: &str, deleted_at: i64) -> Result<Option<MetricPoint>> {
+        let store = self.store.lock().unwrap();
+        let samples = store.samples.get(name).into_iter().flatten();
+        Ok(latest(samples.filter(|sample| sample.point.timestamp > deleted_at)).map(|sample| sample.point))
+    }
+
+    async fn latest_many(&self, names: &[String]) -> Result<Vec<(String, MetricPoint)>> {
+        let store = self.store.lock().unwrap();
+        Ok(names
+            .iter()
+            .filter_map(|name| latest(store.live(name)).map(|sample| (name.clone(), sample.point)))
+            .collect())
+    }
+
+    async fn aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
+        Ok(self.store.lock().unwrap().aggregates.get(name).cloned())
+    }
+
+    async fn recent(&self, name: &str, from: i64, limit: usize) -> Result<Vec<MetricPoint>> {
+        let store = self.store.lock().unwrap();
+        let mut samples: Vec<&Sample> = store.in_range(name, from, i64::MAX).collect();
+        samples.sort_unstable_by_key(|sample| std::cmp::Reverse((sample.point.timestamp, sample.seq, sample.position)));
+        Ok(samples.into_iter().take(limit).map(|sample| sample.point).collect())
+    }
+
+    async fn range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>> {
+        let store = self.store.lock().unwrap();
+        let key = |sample: &&Sample| (sample.point.timestamp, sample.position);
+        let earliest = store.in_range(name, from, to).min_by_key(key);
+        let latest = store.in_range(name, from, to).max_by_key(key);
+        Ok(earliest.zip(latest).map(|(earliest, latest)| (earliest.point, latest.point)))
+    }
+
+    async fn range_page(
+        &self,
+        name: &str,
+        range: RangeQuery,
+        after: Option<RangeCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)> {
+        let after = after.unwrap_or(RangeCursor { timestamp: i64::MIN, position: i64::MIN });
+        let store = self.store.lock().unwrap();
+
+        let rows: Vec<(MetricPoint, i64)> = match range.step {
+            Some(step) => {
+                let mut buckets: BTreeMap<i64, Vec<f64>> = BTreeMap::new();
+                for sample in store.in_range(name, range.from, range.to) {
+                    let bucket = range.from + (sample.point.timestamp - range.from).div_euclid(step) * step;
+                    buckets.entry(bucket).or_default().push(sample.point.value);
+                }
+                buckets
+                    .range(after.timestamp.saturating_add(1)..)
+                    .take(limit)
+                    .map(|(&bucket, values)| {
+                        (MetricPoint { value: apply(range.function, values), timestamp: bucket }, i64::MAX)
+                    })
+                    .collect()
+            }
+            None => {
+                let mut samples: Vec<&Sample> = store
+                    .in_range(name, range.from, range.to)
+                    .filter(|sample| (sample.point.timestamp, sample.position) > (after.timestamp, after.position))
+                    .collect();
+                samples.sort_by_key(|sample| (sample.point.timestamp, sample.position));
+                samples.into_iter().take(limit).map(|sample| (sample.point, sample.position)).collect()
+            }
+        };
+
+        let next = match rows.last() {
+            Some(&(last, position)) if rows.len() == limit => Some(RangeCursor { timestamp: last.timestamp, position }),
+            _ => None,
+        };
+        Ok((rows.into_iter().map(|(point, _)| point).collect(), next))
+    }
+
+    async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>> {
+        let store = self.store.lock().unwrap();
+        let mut buckets: BTreeMap<i64, BucketStats> = BTreeMap::new();
+        for sample in store.in_range(name, from, to) {
+            let value = sample.point.value;
+            let start = sample.point.timestamp.div_euclid(step) * step;
+            let bucket = buckets.entry(start).or_insert(BucketStats { start, count: 0, sum: 0.0, min: value, max: value });
+            bucket.count += 1;
+            bucket.sum += value;
+            bucket.min = bucket.min.min(value);
+            bucket.max = bucket.max.max(value);
+        }
+        Ok(buckets.into_values().collect())
+    }
+
+    async fn group_by(&self, name: &str, query: &GroupByQuery) -> Result<(Vec<(String, f64)>, bool)> {
+        let store = self.store.lock().unwrap();
+        let mut groups: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+        for sample in store.in_range(name, query.from, query.to) {
+            let group = sample.labels.get(&query.label).map_or(NO_LABEL_GROUP, String::as_str);
+            groups.entry(group).or_default().push(sample.point.value);
+        }
+        let truncated = groups.len() > query.max_groups;
+        let groups = groups
+            .into_iter()
+            .take(query.max_groups)
+            .map(|(group, values)| (group.to_string(), apply(query.function, &values)))
+            .collect();
+        Ok((groups, truncated))
+    }
+
+    async fn series(&self, name: &str, labels: &Labels, deleted_at: i64) -> Result<Option<SeriesSummary>> {
+        let store = self.store.lock().unwrap();
+        let samples: Vec<&Sample> = store.samples.get(name).into_iter().flatten()
+            .filter(|sample| sample.point.timestamp > deleted_at && sample.labels == *labels)
+            .collect();
+        let Some(latest) = latest(samples.iter().copied()) else {
+            return Ok(None);
+        };
+        let values: Vec<f64> = samples.iter().map(|sample| sample.point.value).collect();
+        Ok(Some(SeriesSummary {
+            latest: latest.point,
+            count: values.len() as u64,
+            sum: apply(AggregateFn::Sum, &values),
+            min: apply(AggregateFn::Min, &values),
+            max: apply(AggregateFn::Max, &values),
+        }))
+    }
+
+    async fn quarantine(&self, name: &str, sample: &QuarantinedSample) -> Result<()> {
+        self.store.lock().unwrap().quarantined.entry(name.to_string()).or_default().push(sample.clone());
+        Ok(())
+    }
+
+    async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>> {
+        let mut samples = self.store.lock().unwrap().quarantined.get(name).cloned().unwrap_or_default();
+        samples.sort_by_key(|sample| sample.seq);
+        Ok(samples)
+    }
+
+    async fn drop_quarantined(&self, name: &str) -> Result<()> {
+        self.store.lock().unwrap().quarantined.remove(name);
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str, deleted_at: i64) -> Result<()> {
+        self.store.lock().unwrap().tombstone(name, deleted_at);
+        Ok(())
+    }
+
+    async fn purge(&self, expired: &[(String, i64)]) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        for (name, deleted_at) in expired {
+            if let Some(samples) = store.samples.get_mut(name) {
+                samples.retain(|sample| sample.point.timestamp > *deleted_at);
+                if samples.is_empty() {
+                    store.samples.remove(name);
+                }
+            }
+            store.tombstones.remove(name);
+        }
+        Ok(())
+    }
+
+    async fn compact(&self, name: &str, before: i64, interval: i64, limit: usize) -> Result<usize> {
+        let mut store = self.store.lock().unwrap();
+        let Some(samples) = store.samples.get_mut(name) else {
+            return Ok(0);
+        };
+        let bucket = |sample: &Sample| sample.point.timestamp.div_euclid(interval);
+        let mut kept: HashMap<i64, (i64, u64, i64)> = HashMap::new();
+        for sample in samples.iter().filter(|sample| sample.point.timestamp < before) {
+            let key = (sample.point.timestamp, sample.seq, sample.position);
+            let latest = kept.entry(bucket(sample)).or_insert(key);
+            *latest = (*latest).max(key);
+        }
+        let mut removed = 0;
+        samples.retain(|sample| {
+            if removed == limit || sample.point.timestamp >= before
+                || kept[&bucket(sample)] == (sample.point.timestamp, sample.seq, sample.position)
+            {
+                return true;
+            }
+            removed += 1;
+            false
+        });
+        Ok(removed)
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        store.samples.clear();
+        store.aggregates.clear();
+        store.tombstones.clear();
+        store.quarantined.clear();
+        Ok(())
+    }
+
+    async fn stats(&self) -> Result<StorageStats> {
+        let store = self.store.lock().unwrap();
+        Ok(StorageStats {
+            metric_count: store.aggregates.len() as u64,
+            sample_rows: store.samples.values().map(|samples| samples.len() as u64).sum(),
+            tombstones: store.tombstones.len() as u64,
+            series: store
+                .samples
+                .iter()
+                .flat_map(|(name, samples)| samples.iter().map(move |sample| (name, &sample.labels)))
+                .collect::<HashSet<_>>()
+                .len() as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn aggregates_page(&self, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let store = self.store.lock().unwrap();
+        Ok(store
+            .aggregates
+            .iter()
+            .filter(|(name, _)| name.as_str() > after)
+            .take(limit)
+            .map(|(name, aggregate)| (name.clone(), aggregate.clone()))
+            .collect())
+    }
+
+    async fn stale_page(&self, cutoff: i64, after: &str, limit: usize) -> Result<Vec<StaleMetric>> {
+        let store = self.store.lock().unwrap();
+        Ok(store
+            .aggregates
+            .iter()
+            .filter(|(name, aggregate)| aggregate.last_seen < cutoff && name.as_str() > after)
+            .take(limit)
+            .map(|(name, aggregate)| StaleMetric {
+                name: name.clone(),
+                first_seen: aggregate.first_seen,
+                last_seen: aggregate.last_seen,
+            })
+            .collect())
+    }
+
+    async fn latest_between(&self, after: &str, last: &str) -> Result<Vec<(String, MetricPoint, u64)>> {
+        let store = self.store.lock().unwrap();
+        Ok(store
+            .samples
+            .keys()
+            .filter(|name| name.as_str() > after && name.as_str() <= last)
+            .filter_map(|name| {
+                let latest = latest(store.live(name))?;
+                let max_seq = store.live(name).map(|sample| sample.seq).max()?;
+                Some((name.clone(), latest.point, max_seq))
+            })
+            .collect())
+    }
+
+    async fn all_latest(&self) -> Result<Vec<(String, f64)>> {
+        let store = self.store.lock().unwrap();
+        Ok(store
+            .samples
+            .keys()
+            .filter_map(|name| latest(store.live(name)).map(|sample| (name.clone(), sample.point.value)))
+            .collect())
+    }
+
+    async fn aggregates_slice(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let store = self.store.lock().unwrap();
+        Ok(store
+            .aggregates
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(name, aggregate)| (name.clone(), aggregate.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_backend_keeps_only_recent_samples() {
+        let backend = MemoryBackend::with_retention(3);
+        let mut aggregate = MetricAggregate::default();
+        for seq in 1..=5u64 {
+            aggregate.add(seq as f64, crate::metrics::Summation::Naive);
+            let sample = MetricPoint { value: seq as f64, timestamp: seq as i64 * 10 };
+            backend.insert_sample("cpu", sample, &Labels::new(), seq, Some(&aggregate)).await.unwrap();
+        }
+
+        let range = RangeQuery { from: 0, to: 100, step: None, function: AggregateFn::Avg };
+        let (page, next) = backend.range_page("cpu", range, None, 10).await.unwrap();
+        assert_eq!(page.iter().map(|point| point.value).collect::<Vec<_>>(), vec![3.0, 4.0, 5.0]);
+        assert!(next.is_none());
+        // Aggregates still cover every sample
+        assert_eq!(backend.aggregate("cpu").await.unwrap().unwrap().count, 5);
+        assert_eq!(backend.stats().await.unwrap().sample_rows, 3);
+
+        backend.delete("cpu", 50).await.unwrap();
+        assert_eq!(backend.latest_many(&["cpu".to_string()]).await.unwrap(), vec![]);
+        assert_eq!(backend.latest("cpu", i64::MIN).await.unwrap().map(|point| point.value), Some(5.0));
+        assert!(backend.all_latest().await.unwrap().is_empty());
+    }
+}
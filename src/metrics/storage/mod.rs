@@ -0,0 +1,583 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{RaftMetricsError, Result};
+use super::{query::QueryResult, transform::IngestTransform, Labels, MetricAggregate, MetricPoint, QuarantinedSample, SampleSummary, StaleMetric, StorageStats};
+
+#[cfg(feature = "duckdb-storage")]
+mod duckdb_backend;
+#[cfg(feature = "duckdb-storage")]
+mod duckdb_raw;
+mod memory_backend;
+#[cfg(feature = "sqlite-storage")]
+mod sqlite_backend;
+
+#[cfg(feature = "duckdb-storage")]
+pub use duckdb_backend::DuckDbBackend;
+pub use memory_backend::{MemoryBackend, DEFAULT_MEMORY_RETENTION};
+#[cfg(feature = "sqlite-storage")]
+pub use sqlite_backend::SqliteBackend;
+
+/// How the samples in a bucket or group are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateFn {
+    #[default]
+    Avg,
+    Sum,
+    Min,
+    Max,
+    Count,
+}
+
+impl AggregateFn {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "avg" => Ok(AggregateFn::Avg),
+            "sum" => Ok(AggregateFn::Sum),
+            "min" => Ok(AggregateFn::Min),
+            "max" => Ok(AggregateFn::Max),
+            "count" => Ok(AggregateFn::Count),
+            other => Err(RaftMetricsError::InvalidRequest(format!(
+                "unknown aggregate function '{}' (expected avg, sum, min, max or count)", other
+            ))),
+        }
+    }
+
+    /// SQL expression applying the function to `value`, as a double.
+    pub fn sql(self) -> &'static str {
+        match self {
+            AggregateFn::Avg => "avg(value)",
+            AggregateFn::Sum => "sum(value)",
+            AggregateFn::Min => "min(value)",
+            AggregateFn::Max => "max(value)",
+            AggregateFn::Count => "CAST(count(value) AS DOUBLE)",
+        }
+    }
+}
+
+/// Samples to read for a range query; see [`MetricStorageBackend::range_page`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeQuery {
+    pub from: i64,
+    pub to: i64,
+    /// Bucket width in seconds for downsampling; raw samples when unset.
+    pub step: Option<i64>,
+    /// How samples in a bucket are combined when downsampling.
+    pub function: AggregateFn,
+}
+
+/// Statistics of the samples in one bucket of a bucketed read; see
+/// [`MetricStorageBackend::buckets`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketStats {
+    /// Bucket start, a multiple of the step.
+    pub start: i64,
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl BucketStats {
+    /// `function` of the bucket's samples.
+    pub fn value(&self, function: AggregateFn) -> f64 {
+        match function {
+            AggregateFn::Avg => self.sum / self.count as f64,
+            AggregateFn::Sum => self.sum,
+            AggregateFn::Min => self.min,
+            AggregateFn::Max => self.max,
+            AggregateFn::Count => self.count as f64,
+        }
+    }
+}
+
+/// Label value standing in for samples without the grouped label.
+pub const NO_LABEL_GROUP: &str = "<none>";
+
+/// A group-by read; see [`MetricStorageBackend::group_by`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupByQuery {
+    pub label: String,
+    pub function: AggregateFn,
+    pub from: i64,
+    pub to: i64,
+    /// Most groups returned.
+    pub max_groups: usize,
+}
+
+/// The latest sample of one exact series and statistics over its stored
+/// samples; see [`MetricStorageBackend::series`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeriesSummary {
+    pub latest: MetricPoint,
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Where the next page of a range read starts. `position` breaks ties
+/// between samples with the same timestamp in a backend-specific way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeCursor {
+    pub(crate) timestamp: i64,
+    pub(crate) position: i64,
+}
+
+/// A label set as it keys the `series` table: its JSON, with the keys in
+/// the sorted order [`Labels`] keeps them in.
+#[cfg(any(feature = "duckdb-storage", feature = "sqlite-storage"))]
+pub(crate) fn canonical_labels(labels: &Labels) -> String {
+    serde_json::to_string(labels).unwrap()
+}
+
+/// Raft state a node persists next to the metrics it applies, encoded by
+/// the node, so it can rejoin its group after a restart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RaftLogState {
+    pub hard_state: Option<Vec<u8>>,
+    /// Log entries as index and encoded entry, in index order.
+    pub entries: Vec<(u64, Vec<u8>)>,
+}
+
+/// One write of a [`MetricStorageBackend::write_batch`].
+#[derive(Debug, Clone)]
+pub enum StagedWrite<'a> {
+    /// As [`MetricStorageBackend::insert_sample`], with `summary` being
+    /// what the sample adds to its aggregate: itself as many times as it
+    /// is weighed, or every sample a pre-aggregated one stands for.
+    Sample { name: &'a str, sample: MetricPoint, labels: &'a Labels, seq: u64, summary: SampleSummary, aggregate: Option<MetricAggregate> },
+    /// As [`MetricStorageBackend::put_aggregate`].
+    Aggregate { name: &'a str, aggregate: MetricAggregate },
+    /// As [`MetricStorageBackend::quarantine`].
+    Quarantine { name: &'a str, sample: QuarantinedSample },
+    /// As [`MetricStorageBackend::delete`].
+    Delete { name: &'a str, deleted_at: i64 },
+    /// Raises the store's [`MetricStorageBackend::applied_seq`] to `seq`,
+    /// the sequence of the operation the rest of the batch applies.
+    Applied { seq: u64 },
+}
+
+/// Durable storage behind [`super::MetricsRegistry`]. The registry keeps
+/// its in-memory layer and serializes writes per metric; a backend only
+/// has to persist and read back what it is given. Methods that change
+/// more than one table must do so atomically.
+#[async_trait]
+pub trait MetricStorageBackend: Send + Sync {
+    /// Short name for logs, e.g. `duckdb`.
+    fn name(&self) -> &'static str;
+
+    /// Index of the last raft entry applied to this store, if any. This
+    /// and the other synchronous reads run once, while the registry is
+    /// constructed.
+    fn applied_index(&self) -> Result<Option<u64>>;
+    async fn set_applied_index(&self, index: u64) -> Result<()>;
+    /// Highest sequence written with [`StagedWrite::Applied`], if any. It
+    /// commits with the operation's writes, so a replay of an entry the
+    /// store only partly holds can skip what it already has.
+    fn applied_seq(&self) -> Result<Option<u64>>;
+
+    /// The persisted raft hard state and log entries from index `from` on.
+    fn raft_log(&self, from: u64) -> Result<RaftLogState>;
+    /// Stores log entries, replacing every stored entry at or after the
+    /// first one's index, since a new leader may have overwritten them.
+    async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()>;
+    async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()>;
+    /// Drops log entries before `index`.
+    async fn compact_raft_log(&self, index: u64) -> Result<()>;
+
+    /// Every tombstone, as metric name and deletion time.
+    fn tombstones(&self) -> Result<Vec<(String, i64)>>;
+    /// Every hook, as id and serialized config.
+    fn hooks(&self) -> Result<Vec<(String, String)>>;
+    async fn put_hook(&self, id: &str, config: &str) -> Result<()>;
+    /// Every transform set through raft, by metric name.
+    fn transforms(&self) -> Result<Vec<(String, IngestTransform)>>;
+    async fn put_transform(&self, name: &str, transform: &IngestTransform) -> Result<()>;
+    /// Every state metric, as metric name and serialized definition and
+    /// progress.
+    fn state_metrics(&self) -> Result<Vec<(String, String)>>;
+    async fn put_state_metric(&self, name: &str, config: &str) -> Result<()>;
+    /// Every histogram metric, as metric name and serialized layout and
+    /// counts.
+    fn histograms(&self) -> Result<Vec<(String, String)>>;
+    async fn put_histogram(&self, name: &str, config: &str) -> Result<()>;
+    /// Every applied [`super::MetricOperation::Keyed`] key, with the Unix
+    /// second it expires.
+    fn applied_keys(&self) -> Result<Vec<(String, i64)>>;
+    async fn put_applied_key(&self, key: &str, expires_at: i64) -> Result<()>;
+    /// Drops the applied keys expiring at or before `watermark`.
+    async fn prune_applied_keys(&self, watermark: i64) -> Result<()>;
+
+    /// Appends a sample with its labels, committed at sequence `seq` and
+    /// counted once in its aggregate, and replaces the metric's aggregate
+    /// with `aggregate`, if given.
+    async fn insert_sample(
+        &self,
+        name: &str,
+        sample: MetricPoint,
+        labels: &Labels,
+        seq: u64,
+        aggregate: Option<&MetricAggregate>,
+    ) -> Result<()>;
+    /// Replaces `name`'s aggregate without storing a sample.
+    async fn put_aggregate(&self, name: &str, aggregate: &MetricAggregate) -> Result<()>;
+    /// Makes every write, in order, or none of them.
+    async fn write_batch(&self, writes: &[StagedWrite<'_>]) -> Result<()>;
+
+    /// Latest sample of `name` written after `deleted_at`: the greatest
+    /// timestamp, ties going to the later sequence.
+    async fn latest(&self, name: &str, deleted_at: i64) -> Result<Option<MetricPoint>>;
+    /// Latest live sample of each of `names` that has one, in one statement.
+    async fn latest_many(&self, names: &[String]) -> Result<Vec<(String, MetricPoint)>>;
+    async fn aggregate(&self, name: &str) -> Result<Option<MetricAggregate>>;
+    /// Up to `limit` samples with `timestamp >= from`, newest first: the
+    /// greatest timestamp, ties going to the later sequence.
+    async fn recent(&self, name: &str, from: i64, limit: usize) -> Result<Vec<MetricPoint>>;
+    /// Earliest and latest samples with `from <= timestamp <= to`.
+    async fn range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>>;
+    /// Up to `limit` samples in `range` in timestamp order after `after`,
+    /// with the cursor for the next page when the page was full.
+    async fn range_page(
+        &self,
+        name: &str,
+        range: RangeQuery,
+        after: Option<RangeCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)>;
+
+    /// Statistics of the samples with `from <= timestamp <= to` per
+    /// `step`-second bucket, buckets aligned to the epoch, in bucket order.
+    /// Buckets without samples are left out.
+    async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>>;
+
+    /// `query.function` over the samples with `query.from <= timestamp <=
+    /// query.to`, per value of `query.label`, in label value order. Samples
+    /// without the label fall under [`NO_LABEL_GROUP`]. The flag is set
+    /// when more than `query.max_groups` groups existed.
+    async fn group_by(&self, name: &str, query: &GroupByQuery) -> Result<(Vec<(String, f64)>, bool)>;
+    /// The series of `name` whose label set is exactly `labels`, over its
+    /// samples written after `deleted_at`; `None` when it has none.
+    async fn series(&self, name: &str, labels: &Labels, deleted_at: i64) -> Result<Option<SeriesSummary>>;
+
+    /// Holds `sample` of `name` back from the metric; see
+    /// [`super::outliers::OutlierPolicy`].
+    async fn quarantine(&self, name: &str, sample: &QuarantinedSample) -> Result<()>;
+    /// `name`'s quarantined samples in sequence order.
+    async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>>;
+    async fn drop_quarantined(&self, name: &str) -> Result<()>;
+
+    /// Records a tombstone at `deleted_at` and drops the metric's aggregate
+    /// and quarantined samples.
+    async fn delete(&self, name: &str, deleted_at: i64) -> Result<()>;
+    /// Removes the samples covered by each tombstone and the tombstones.
+    async fn purge(&self, expired: &[(String, i64)]) -> Result<()>;
+    /// Removes up to `limit` samples of `name` older than `before` that are
+    /// not the latest of their `interval`-second bucket, in one
+    /// transaction, returning how many went. Fewer than `limit` means the
+    /// metric is fully thinned.
+    async fn compact(&self, name: &str, before: i64, interval: i64, limit: usize) -> Result<usize>;
+    /// Aggregates rebuilt from the live samples alone, each counted as it
+    /// was when written, of up to `limit` metrics named after `after` and starting with
+    /// `prefix`, in name order, in one grouped pass. `first_seen` and
+    /// `last_seen` are the sample timestamps. Nothing is written.
+    async fn recomputed_aggregates(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>>;
+    /// Removes every sample, aggregate, tombstone and quarantined sample.
+    async fn clear(&self) -> Result<()>;
+    async fn stats(&self) -> Result<StorageStats>;
+
+    /// Up to `limit` aggregates with names after `after`, in name order.
+    async fn aggregates_page(&self, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>>;
+    /// Up to `limit` metrics last written before `cutoff` with names after
+    /// `after`, in name order.
+    async fn stale_page(&self, cutoff: i64, after: &str, limit: usize) -> Result<Vec<StaleMetric>>;
+    /// The latest live sample and highest sequence of every metric named
+    /// in `(after, last]`.
+    async fn latest_between(&self, after: &str, last: &str) -> Result<Vec<(String, MetricPoint, u64)>>;
+    /// The latest live value of every metric.
+    async fn all_latest(&self) -> Result<Vec<(String, f64)>>;
+    /// Up to `limit` aggregates from the `offset`th name on, in name order,
+    /// read in one statement.
+    async fn aggregates_slice(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>>;
+
+    /// Runs a statement already checked by [`super::query::validate`],
+    /// returning at most `max_rows` rows. `metrics` shows live samples only.
+    /// Work still running at `deadline` is interrupted or abandoned.
+    async fn query(&self, _sql: &str, _max_rows: usize, _deadline: std::time::Instant) -> Result<QueryResult> {
+        Err(RaftMetricsError::InvalidRequest(format!(
+            "SQL queries are not supported by the {} backend", self.name()
+        )))
+    }
+}
+
+/// Which [`MetricStorageBackend`] a worker stores metrics in, from
+/// `STORAGE_BACKEND`. DuckDB when the `duckdb-storage` feature is built in,
+/// else SQLite when `sqlite-storage` is, memory otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageBackendKind {
+    #[cfg(feature = "duckdb-storage")]
+    #[default]
+    DuckDb,
+    #[cfg(feature = "sqlite-storage")]
+    #[cfg_attr(not(feature = "duckdb-storage"), default)]
+    Sqlite,
+    #[cfg_attr(not(any(feature = "duckdb-storage", feature = "sqlite-storage")), default)]
+    Memory,
+}
+
+impl StorageBackendKind {
+    /// Every backend compiled into this build.
+    pub const ALL: &'static [Self] = &[
+        #[cfg(feature = "duckdb-storage")]
+        StorageBackendKind::DuckDb,
+        #[cfg(feature = "sqlite-storage")]
+        StorageBackendKind::Sqlite,
+        StorageBackendKind::Memory,
+    ];
+
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        match lookup("STORAGE_BACKEND").as_deref() {
+            None => Ok(StorageBackendKind::default()),
+            #[cfg(feature = "duckdb-storage")]
+            Some("duckdb") => Ok(StorageBackendKind::DuckDb),
+            #[cfg(feature = "sqlite-storage")]
+            Some("sqlite") => Ok(StorageBackendKind::Sqlite),
+            Some("memory") => Ok(StorageBackendKind::Memory),
+            Some(other) => Err(RaftMetricsError::InvalidConfig(vec![format!(
+                "STORAGE_BACKEND '{}' is not available in this build (supported: {})",
+                other,
+                Self::ALL.iter().map(|kind| kind.name()).collect::<Vec<_>>().join(", ")
+            )])),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "duckdb-storage")]
+            StorageBackendKind::DuckDb => "duckdb",
+            #[cfg(feature = "sqlite-storage")]
+            StorageBackendKind::Sqlite => "sqlite",
+            StorageBackendKind::Memory => "memory",
+        }
+    }
+
+    /// The schema version of the existing store at `path` and the latest
+    /// this build migrates to, read without migrating or writing to it. The
+    /// memory backend has nothing on disk and reports `(0, 0)`.
+    #[cfg_attr(not(any(feature = "duckdb-storage", feature = "sqlite-storage")), allow(unused_variables))]
+    pub fn stored_schema(self, path: &str) -> Result<(u32, u32)> {
+        match self {
+            #[cfg(feature = "duckdb-storage")]
+            StorageBackendKind::DuckDb => Ok((DuckDbBackend::stored_schema_version(path)?, super::migrations::latest_version())),
+            #[cfg(feature = "sqlite-storage")]
+            StorageBackendKind::Sqlite => Ok((SqliteBackend::stored_schema_version(path)?, sqlite_backend::SCHEMA_VERSION)),
+            StorageBackendKind::Memory => Ok((0, 0)),
+        }
+    }
+
+    /// Opens the existing store at `path` for reads only, refusing one not
+    /// at the latest schema. The memory backend opens empty.
+    #[cfg_attr(not(any(feature = "duckdb-storage", feature = "sqlite-storage")), allow(unused_variables))]
+    pub fn open_read_only(self, path: &str) -> Result<Box<dyn MetricStorageBackend>> {
+        match self {
+            #[cfg(feature = "duckdb-storage")]
+            StorageBackendKind::DuckDb => Ok(Box::new(DuckDbBackend::open_read_only(path)?)),
+            #[cfg(feature = "sqlite-storage")]
+            StorageBackendKind::Sqlite => Ok(Box::new(SqliteBackend::open_read_only(path)?)),
+            StorageBackendKind::Memory => Ok(Box::new(MemoryBackend::from_env())),
+        }
+    }
+
+    /// Opens the store at `path`, or an in-memory one. The memory backend
+    /// never touches `path`.
+    pub fn open(self, path: Option<&str>) -> Result<Box<dyn MetricStorageBackend>> {
+        match self {
+            #[cfg(feature = "duckdb-storage")]
+            StorageBackendKind::DuckDb => Ok(Box::new(match path {
+                Some(path) => DuckDbBackend::open(path)?,
+                None => DuckDbBackend::open_in_memory()?,
+            })),
+            #[cfg(feature = "sqlite-storage")]
+            StorageBackendKind::Sqlite => Ok(Box::new(match path {
+                Some(path) => SqliteBackend::open(path)?,
+                None => SqliteBackend::open_in_memory()?,
+            })),
+            StorageBackendKind::Memory => {
+                if let Some(path) = path {
+                    tracing::warn!("Memory storage keeps nothing on disk; ignoring DB_PATH {}", path);
+                }
+                Ok(Box::new(MemoryBackend::from_env()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_selection() {
+        let lookup = |value: Option<&'static str>| move |_: &str| value.map(String::from);
+        assert_eq!(StorageBackendKind::from_lookup(lookup(None)).unwrap(), StorageBackendKind::default());
+        #[cfg(feature = "duckdb-storage")]
+        assert_eq!(StorageBackendKind::from_lookup(lookup(Some("duckdb"))).unwrap(), StorageBackendKind::DuckDb);
+        #[cfg(feature = "sqlite-storage")]
+        assert_eq!(StorageBackendKind::from_lookup(lookup(Some("sqlite"))).unwrap(), StorageBackendKind::Sqlite);
+        assert_eq!(StorageBackendKind::from_lookup(lookup(Some("memory"))).unwrap(), StorageBackendKind::Memory);
+        assert!(matches!(
+            StorageBackendKind::from_lookup(lookup(Some("postgres"))),
+            Err(RaftMetricsError::InvalidConfig(_))
+        ));
+    }
+    #[cfg(feature = "duckdb-storage")]
+    #[tokio::test]
+    async fn test_cached_statements_stay_correct_across_calls() {
+        let backend = DuckDbBackend::open_in_memory().unwrap();
+        let labels: Labels = [("host".to_string(), "a".to_string())].into_iter().collect();
+        let mut aggregate = MetricAggregate::default();
+        for seq in 1..=500u64 {
+            let name = if seq % 2 == 0 { "cpu" } else { "mem" };
+            let sample = MetricPoint { value: seq as f64, timestamp: seq as i64 };
+            if name == "cpu" {
+                aggregate.add(sample.value, crate::metrics::Summation::Naive);
+            }
+            backend.insert_sample(name, sample, &labels, seq, Some(&aggregate)).await.unwrap();
+
+            let latest = backend.latest(name, 0).await.unwrap().unwrap();
+            assert_eq!((latest.value, latest.timestamp), (seq as f64, seq as i64));
+            let stored = backend.aggregate("cpu").await.unwrap().unwrap_or_default();
+            assert_eq!(stored.count, aggregate.count);
+            assert_eq!(stored.sum, aggregate.total());
+        }
+
+        let range = RangeQuery { from: 0, to: 500, step: None, function: AggregateFn::Avg };
+        let (page, _) = backend.range_page("cpu", range, None, 1000).await.unwrap();
+        assert_eq!(page.len(), 250);
+        let stepped = RangeQuery { step: Some(100), ..range };
+        for _ in 0..3 {
+            let (buckets, _) = backend.range_page("cpu", stepped, None, 10).await.unwrap();
+            assert_eq!(buckets.iter().map(|point| point.timestamp).collect::<Vec<_>>(), vec![0, 100, 200, 300, 400, 500]);
+        }
+        assert_eq!(backend.stats().await.unwrap().sample_rows, 500);
+    }
+
+    #[cfg(feature = "duckdb-storage")]
+    #[tokio::test]
+    async fn test_series_dictionary_stores_each_label_set_once() {
+        let duckdb = DuckDbBackend::open_in_memory().unwrap();
+        let memory = MemoryBackend::new();
+        let mut per_sample_labels = 0;
+        for seq in 1..=1_000u64 {
+            let mut labels: Labels =
+                [("host".to_string(), format!("h{}", seq % 8)), ("dc".to_string(), format!("dc{}", seq % 2))].into_iter().collect();
+            if seq % 5 == 0 {
+                labels.clear();
+            }
+            per_sample_labels += labels.len();
+            let sample = MetricPoint { value: seq as f64, timestamp: seq as i64 };
+            for backend in [&duckdb as &dyn MetricStorageBackend, &memory] {
+                backend.insert_sample("cpu", sample, &labels, seq, None).await.unwrap();
+            }
+        }
+
+        // Eight label sets and the unlabeled series, rather than two label rows per labeled sample
+        let label_rows = duckdb.query("SELECT COUNT(*) FROM series_labels", 1, far_deadline()).await.unwrap().rows[0][0].clone();
+        assert_eq!((per_sample_labels, label_rows), (1_600, serde_json::json!(16)));
+        let (stats, expected) = (duckdb.stats().await.unwrap(), memory.stats().await.unwrap());
+        assert_eq!((stats.series, stats.sample_rows), (9, 1_000));
+        assert_eq!((expected.series, expected.sample_rows), (9, 1_000));
+
+        for label in ["host", "dc", "missing"] {
+            let query = GroupByQuery { label: label.to_string(), function: AggregateFn::Sum, from: 0, to: 1_000, max_groups: 20 };
+            assert_eq!(duckdb.group_by("cpu", &query).await.unwrap(), memory.group_by("cpu", &query).await.unwrap(), "{}", label);
+        }
+
+        // Purging the samples drops their series, and new ones get fresh ids
+        duckdb.delete("cpu", 1_000).await.unwrap();
+        duckdb.purge(&[("cpu".to_string(), 1_000)]).await.unwrap();
+        assert_eq!(duckdb.stats().await.unwrap().series, 0);
+        let labels: Labels = [("host".to_string(), "h1".to_string())].into_iter().collect();
+        duckdb.insert_sample("cpu", MetricPoint { value: 1.0, timestamp: 1_001 }, &labels, 1_001, None).await.unwrap();
+        let query = GroupByQuery { label: "host".to_string(), function: AggregateFn::Count, from: 0, to: 2_000, max_groups: 20 };
+        assert_eq!(duckdb.group_by("cpu", &query).await.unwrap(), (vec![("h1".to_string(), 1.0)], false));
+        assert_eq!(duckdb.stats().await.unwrap().series, 1);
+    }
+
+    #[tokio::test]
+    async fn test_recompute_counts_weighted_and_summarized_samples_as_written() {
+        let labels = Labels::new();
+        // A sample kept at weight 3, three downsampled to one, and a plain one
+        let mut downsampled = SampleSummary::new(2.0);
+        downsampled.add(6.0, false);
+        downsampled.add(4.0, true);
+        let summaries = [SampleSummary::new(1.0).weighted(3), downsampled, SampleSummary::new(10.0)];
+        let writes: Vec<_> = summaries
+            .into_iter()
+            .zip(1..)
+            .map(|(summary, seq)| {
+                let sample = MetricPoint { value: summary.last, timestamp: seq as i64 * 100 };
+                StagedWrite::Sample { name: "cpu", sample, labels: &labels, seq, summary, aggregate: None }
+            })
+            .collect();
+
+        // 1, 1, 1, 2, 6, 4, 10
+        let m2 = 159.0 - 25.0 * 25.0 / 7.0;
+        for kind in StorageBackendKind::ALL {
+            let backend = kind.open(None).unwrap();
+            backend.write_batch(&writes).await.unwrap();
+            let recomputed = backend.recomputed_aggregates("", "", 10).await.unwrap();
+            let [(name, rebuilt)] = recomputed.as_slice() else { panic!("{:?}", recomputed) };
+            assert_eq!((name.as_str(), rebuilt.count, rebuilt.sum), ("cpu", 7, 25.0), "{}", backend.name());
+            assert_eq!((rebuilt.min, rebuilt.max, rebuilt.first_seen, rebuilt.last_seen), (1.0, 10.0, 100, 300), "{}", backend.name());
+            assert!((rebuilt.m2 - m2).abs() < 1e-9, "{}: {}", backend.name(), rebuilt.m2);
+        }
+    }
+
+    #[cfg(feature = "sqlite-storage")]
+    #[tokio::test]
+    async fn test_sqlite_store_keeps_its_data_and_refuses_a_newer_schema() {
+        let path = std::env::temp_dir().join(format!("store-{}.sqlite", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        {
+            let backend = SqliteBackend::open(&path).unwrap();
+            backend.insert_sample("cpu", MetricPoint { value: 2.0, timestamp: 100 }, &Labels::new(), 1, None).await.unwrap();
+            backend.append_raft_entries(&[(1, vec![1]), (2, vec![2])]).await.unwrap();
+            backend.append_raft_entries(&[(2, vec![3])]).await.unwrap();
+            backend.set_applied_index(2).await.unwrap();
+        }
+
+        let backend = SqliteBackend::open(&path).unwrap();
+        assert_eq!(backend.latest("cpu", 0).await.unwrap(), Some(MetricPoint { value: 2.0, timestamp: 100 }));
+        assert_eq!(backend.raft_log(0).unwrap().entries, vec![(1, vec![1]), (2, vec![3])]);
+        assert_eq!(backend.applied_index().unwrap(), Some(2));
+        drop(backend);
+
+        // A store from before applied sequences were kept is upgraded in place
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch("ALTER TABLE raft_applied_index DROP COLUMN applied_seq; PRAGMA user_version = 1;")
+            .unwrap();
+        let backend = SqliteBackend::open(&path).unwrap();
+        assert_eq!(backend.applied_seq().unwrap(), None);
+        backend.write_batch(&[StagedWrite::Applied { seq: 7 }, StagedWrite::Applied { seq: 5 }]).await.unwrap();
+        assert_eq!((backend.applied_index().unwrap(), backend.applied_seq().unwrap()), (Some(2), Some(7)));
+        drop(backend);
+
+        rusqlite::Connection::open(&path).unwrap().pragma_update(None, "user_version", sqlite_backend::SCHEMA_VERSION + 1).unwrap();
+        assert!(matches!(SqliteBackend::open(&path), Err(RaftMetricsError::Migration(_))));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    #[cfg(feature = "duckdb-storage")]
+    fn far_deadline() -> std::time::Instant {
+        std::time::Instant::now() + std::time::Duration::from_secs(30)
+    }
+}
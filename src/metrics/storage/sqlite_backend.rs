@@ -0,0 +1,920 @@
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+
+use crate::{RaftMetricsError, Result};
+use crate::metrics::{transform::IngestTransform, Labels, MetricAggregate, MetricPoint, QuarantinedSample, SampleSummary, StaleMetric, StorageStats};
+use super::{canonical_labels, BucketStats, GroupByQuery, MetricStorageBackend, RaftLogState, RangeCursor, RangeQuery, SeriesSummary, StagedWrite, NO_LABEL_GROUP};
+
+/// Prepared statements kept per connection; see the DuckDB backend's.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// How long a write waits for another process holding the file's lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version of the schema [`SCHEMA`] creates, kept in `PRAGMA user_version`.
+/// Later changes add steps that upgrade from the version before them.
+pub(super) const SCHEMA_VERSION: u32 = 2;
+
+/// The tables of the DuckDB store at its latest migration, keyed where
+/// SQLite can enforce it.
+const SCHEMA: &str = "
+    CREATE TABLE metrics (
+        name TEXT NOT NULL,
+        value REAL NOT NULL,
+        timestamp INTEGER NOT NULL,
+        seq INTEGER NOT NULL,
+        series_id INTEGER NOT NULL,
+        sample_count INTEGER NOT NULL,
+        sample_sum REAL NOT NULL,
+        sample_min REAL NOT NULL,
+        sample_max REAL NOT NULL,
+        sample_m2 REAL NOT NULL
+    );
+    CREATE INDEX idx_metrics_name_ts ON metrics (name, timestamp);
+    CREATE TABLE series (
+        series_id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        labels TEXT NOT NULL,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        samples INTEGER NOT NULL,
+        UNIQUE (name, labels)
+    );
+    CREATE TABLE series_labels (
+        series_id INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (series_id, key)
+    );
+    CREATE TABLE metric_aggregates (
+        name TEXT PRIMARY KEY,
+        count INTEGER NOT NULL,
+        sum REAL NOT NULL,
+        average REAL NOT NULL,
+        min REAL NOT NULL,
+        max REAL NOT NULL,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        m2 REAL NOT NULL
+    );
+    CREATE TABLE metric_tombstones (name TEXT PRIMARY KEY, deleted_at INTEGER NOT NULL);
+    CREATE TABLE quarantined_samples (
+        name TEXT NOT NULL,
+        value REAL NOT NULL,
+        timestamp INTEGER NOT NULL,
+        seq INTEGER NOT NULL,
+        labels TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        weight INTEGER NOT NULL
+    );
+    CREATE INDEX idx_quarantined_samples_name ON quarantined_samples (name);
+    CREATE TABLE raft_applied_index (id INTEGER PRIMARY KEY CHECK (id = 0), applied_index INTEGER NOT NULL, applied_seq INTEGER);
+    CREATE TABLE raft_hard_state (id INTEGER PRIMARY KEY CHECK (id = 0), state BLOB NOT NULL);
+    CREATE TABLE raft_log (idx INTEGER PRIMARY KEY, entry BLOB NOT NULL);
+    CREATE TABLE metric_hooks (id TEXT PRIMARY KEY, config TEXT NOT NULL);
+    CREATE TABLE metric_transforms (name TEXT PRIMARY KEY, scale REAL NOT NULL, value_offset REAL NOT NULL);
+    CREATE TABLE metric_states (name TEXT PRIMARY KEY, config TEXT NOT NULL);
+    CREATE TABLE metric_histograms (name TEXT PRIMARY KEY, config TEXT NOT NULL);
+    CREATE TABLE applied_keys (key TEXT PRIMARY KEY, expires_at INTEGER NOT NULL);
+";
+
+/// Steps upgrading a database to each version after the first, in order.
+const UPGRADES: &[(u32, &str)] = &[(2, "ALTER TABLE raft_applied_index ADD COLUMN applied_seq INTEGER;")];
+
+/// Creates the schema in a new database, or upgrades an older one,
+/// returning its version. Refuses a database written by a newer binary.
+fn migrate(conn: &mut Connection) -> Result<u32> {
+    let version = stored_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(RaftMetricsError::Migration(format!(
+            "database schema version {} is newer than the latest supported version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    if version == 0 {
+        let tx = conn.transaction()?;
+        tx.execute_batch(SCHEMA)?;
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        return Ok(SCHEMA_VERSION);
+    }
+    for (to, upgrade) in UPGRADES.iter().filter(|(to, _)| *to > version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(upgrade)?;
+        tx.pragma_update(None, "user_version", to)?;
+        tx.commit()?;
+        info!("Upgraded SQLite metrics store to schema version {}", to);
+    }
+    Ok(SCHEMA_VERSION)
+}
+
+/// The schema version recorded in the database, or 0 for a fresh one.
+fn stored_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+fn upsert_aggregate(db: &Connection, name: &str, aggregate: &MetricAggregate) -> Result<()> {
+    db.prepare_cached(
+        "INSERT INTO metric_aggregates (name, count, sum, average, min, max, first_seen, last_seen, m2) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT (name) DO UPDATE SET count = excluded.count, sum = excluded.sum, average = excluded.average, \
+            min = excluded.min, max = excluded.max, first_seen = excluded.first_seen, last_seen = excluded.last_seen, m2 = excluded.m2",
+    )?
+    .execute(params![
+        name, aggregate.count, aggregate.total(), aggregate.average, aggregate.min, aggregate.max,
+        aggregate.first_seen, aggregate.last_seen, aggregate.m2,
+    ])?;
+    Ok(())
+}
+
+/// Series ids by metric name and canonical label set, as in the DuckDB
+/// backend. SQLite numbers new series itself.
+#[derive(Debug, Default)]
+struct SeriesCache {
+    ids: HashMap<String, HashMap<String, i64>>,
+    /// Series created by the open transaction, to forget if it rolls back.
+    created: Vec<(String, String)>,
+}
+
+impl SeriesCache {
+    fn forget_created(&mut self) {
+        for (name, labels) in self.created.drain(..) {
+            if let Some(ids) = self.ids.get_mut(&name) {
+                ids.remove(&labels);
+            }
+        }
+    }
+}
+
+/// Id of `name`'s series with `labels`, created on its first sample, with
+/// a sample at `timestamp` counted against it.
+fn intern_series(db: &Connection, series: &mut SeriesCache, name: &str, labels: &Labels, timestamp: i64) -> Result<i64> {
+    let key = canonical_labels(labels);
+    let id = match series.ids.get(name).and_then(|ids| ids.get(&key)) {
+        Some(&id) => id,
+        None => {
+            let stored = db
+                .prepare_cached("SELECT series_id FROM series WHERE name = ? AND labels = ?")?
+                .query_row(params![name, key], |row| row.get(0))
+                .optional()?;
+            let id = match stored {
+                Some(id) => id,
+                None => {
+                    db.prepare_cached(
+                        "INSERT INTO series (name, labels, first_seen, last_seen, samples) VALUES (?, ?, ?, ?, 0)",
+                    )?
+                    .execute(params![name, key, timestamp, timestamp])?;
+                    let id = db.last_insert_rowid();
+                    let mut insert_label = db.prepare_cached("INSERT INTO series_labels (series_id, key, value) VALUES (?, ?, ?)")?;
+                    for (label, value) in labels {
+                        insert_label.execute(params![id, label, value])?;
+                    }
+                    series.created.push((name.to_string(), key.clone()));
+                    id
+                }
+            };
+            series.ids.entry(name.to_string()).or_default().insert(key, id);
+            id
+        }
+    };
+    db.prepare_cached(
+        "UPDATE series SET first_seen = min(first_seen, ?), last_seen = max(last_seen, ?), samples = samples + 1 \
+         WHERE series_id = ?",
+    )?
+    .execute(params![timestamp, timestamp, id])?;
+    Ok(id)
+}
+
+/// Drops `name`'s series left without samples and recounts the rest.
+fn prune_series(db: &Connection, series: &mut SeriesCache, name: &str) -> Result<()> {
+    db.prepare_cached(
+        "DELETE FROM series_labels WHERE series_id IN ( \
+            SELECT series_id FROM series WHERE name = ?1 AND series_id NOT IN (SELECT series_id FROM metrics WHERE name = ?1))",
+    )?
+    .execute([name])?;
+    db.prepare_cached("DELETE FROM series WHERE name = ?1 AND series_id NOT IN (SELECT series_id FROM metrics WHERE name = ?1)")?
+        .execute([name])?;
+    db.prepare_cached("UPDATE series SET samples = (SELECT COUNT(*) FROM metrics m WHERE m.series_id = series.series_id) WHERE name = ?")?
+        .execute([name])?;
+    series.ids.remove(name);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_sample_rows(
+    db: &Connection,
+    series: &mut SeriesCache,
+    name: &str,
+    sample: MetricPoint,
+    labels: &Labels,
+    seq: u64,
+    summary: &SampleSummary,
+    aggregate: Option<&MetricAggregate>,
+) -> Result<()> {
+    let series_id = intern_series(db, series, name, labels, sample.timestamp)?;
+    db.prepare_cached(
+        "INSERT INTO metrics (name, value, timestamp, seq, series_id, sample_count, sample_sum, sample_min, sample_max, sample_m2) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )?
+    .execute(params![
+        name, sample.value, sample.timestamp, seq, series_id, summary.count, summary.sum, summary.min, summary.max, summary.m2
+    ])?;
+    match aggregate {
+        Some(aggregate) => upsert_aggregate(db, name, aggregate),
+        None => Ok(()),
+    }
+}
+
+fn tombstone(db: &Connection, name: &str, deleted_at: i64) -> Result<()> {
+    db.execute("INSERT OR REPLACE INTO metric_tombstones (name, deleted_at) VALUES (?, ?)", params![name, deleted_at])?;
+    db.execute("DELETE FROM metric_aggregates WHERE name = ?", [name])?;
+    db.execute("DELETE FROM quarantined_samples WHERE name = ?", [name])?;
+    Ok(())
+}
+
+/// Raises the stored applied sequence to `seq`, creating the row at
+/// applied index 0 if no entry has been applied yet.
+fn raise_applied_seq(db: &Connection, seq: u64) -> Result<()> {
+    db.prepare_cached(
+        "INSERT INTO raft_applied_index (id, applied_index, applied_seq) VALUES (0, 0, ?1)
+         ON CONFLICT (id) DO UPDATE SET applied_seq = max(coalesce(applied_seq, 0), excluded.applied_seq)",
+    )?
+    .execute([seq])?;
+    Ok(())
+}
+
+fn insert_quarantined(db: &Connection, name: &str, sample: &QuarantinedSample) -> Result<()> {
+    let labels = serde_json::to_string(&sample.labels)
+        .map_err(|e| RaftMetricsError::Internal(format!("Failed to encode labels: {}", e)))?;
+    db.prepare_cached(
+        "INSERT INTO quarantined_samples (name, value, timestamp, seq, labels, reason, weight) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )?
+    .execute(params![name, sample.value, sample.timestamp, sample.seq, labels, sample.reason, sample.weight])?;
+    Ok(())
+}
+
+fn aggregate_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<MetricAggregate> {
+    Ok(MetricAggregate {
+        count: row.get(first)?,
+        sum: row.get(first + 1)?,
+        average: row.get(first + 2)?,
+        min: row.get(first + 3)?,
+        max: row.get(first + 4)?,
+        first_seen: row.get(first + 5)?,
+        last_seen: row.get(first + 6)?,
+        m2: row.get(first + 7)?,
+        ..Default::default()
+    })
+}
+
+/// Stores metrics in a SQLite database, for builds that cannot link DuckDB.
+/// The library is compiled in, so nothing native is needed at runtime. A
+/// file is kept in WAL mode. Statements run on the caller's task under a
+/// lock never held across an await. SQL queries are not supported.
+pub struct SqliteBackend {
+    db: Mutex<Connection>,
+    /// Only locked while holding `db`.
+    series: Mutex<SeriesCache>,
+}
+
+impl SqliteBackend {
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    /// Opens (or creates) the SQLite file at `path`.
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Self::with_connection(conn)
+    }
+
+    /// Opens the SQLite file at `path` without creating, migrating or
+    /// writing to it. Refuses one not at the latest schema.
+    pub fn open_read_only(path: &str) -> Result<Self> {
+        let conn = Self::connect_read_only(path)?;
+        let version = stored_version(&conn)?;
+        if version != SCHEMA_VERSION {
+            return Err(RaftMetricsError::InvalidRequest(format!(
+                "{} is at schema version {} rather than {}; start a worker on it once to migrate it",
+                path, version, SCHEMA_VERSION
+            )));
+        }
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(Self { db: Mutex::new(conn), series: Mutex::default() })
+    }
+
+    /// Schema version of the SQLite file at `path`, read without migrating
+    /// or writing to it.
+    pub fn stored_schema_version(path: &str) -> Result<u32> {
+        stored_version(&Self::connect_read_only(path)?)
+    }
+
+    fn connect_read_only(path: &str) -> Result<Connection> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        // With no log left beside it everything is in the file itself, which
+        // is then opened immutable so SQLite creates no `-wal` or `-shm`
+        let conn = if Path::new(&format!("{}-wal", path)).exists() {
+            Connection::open_with_flags(path, flags)?
+        } else {
+            let escaped = path.replace('%', "%25").replace('?', "%3f").replace('#', "%23");
+            Connection::open_with_flags(format!("file:{}?immutable=1", escaped), flags | OpenFlags::SQLITE_OPEN_URI)?
+        };
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self> {
+        let version = migrate(&mut conn)?;
+        info!("SQLite metrics store ready at schema version {}", version);
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(Self { db: Mutex::new(conn), series: Mutex::default() })
+    }
+
+    /// Runs `write` in a transaction, forgetting the series it created if
+    /// the transaction does not commit.
+    fn write<T>(&self, write: impl FnOnce(&Transaction, &mut SeriesCache) -> Result<T>) -> Result<T> {
+        let mut db = self.db.lock().unwrap();
+        let mut series = self.series.lock().unwrap();
+        // Dropped without a commit on the first failure, which rolls it back
+        let tx = db.transaction()?;
+        let written = write(&tx, &mut series).and_then(|value| {
+            tx.commit()?;
+            Ok(value)
+        });
+        match written {
+            Ok(_) => series.created.clear(),
+            Err(_) => series.forget_created(),
+        }
+        written
+    }
+}
+
+#[async_trait]
+impl MetricStorageBackend for SqliteBackend {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn applied_index(&self) -> Result<Option<u64>> {
+        let applied_index = self.db.lock().unwrap()
+            .query_row("SELECT applied_index FROM raft_applied_index", [], |row| row.get(0))
+            .optional()?;
+        Ok(applied_index)
+    }
+
+    async fn set_applied_index(&self, index: u64) -> Result<()> {
+        self.db.lock().unwrap()
+            .prepare_cached(
+                "INSERT INTO raft_applied_index (id, applied_index) VALUES (0, ?)
+                 ON CONFLICT (id) DO UPDATE SET applied_index = excluded.applied_index",
+            )?
+            .execute([index])?;
+        Ok(())
+    }
+
+    fn applied_seq(&self) -> Result<Option<u64>> {
+        let applied_seq = self.db.lock().unwrap()
+            .query_row("SELECT applied_seq FROM raft_applied_index", [], |row| row.get(0))
+            .optional()?
+            .flatten();
+        Ok(applied_seq)
+    }
+
+    fn raft_log(&self, from: u64) -> Result<RaftLogState> {
+        let db = self.db.lock().unwrap();
+        let hard_state = db
+            .query_row("SELECT state FROM raft_hard_state", [], |row| row.get(0))
+            .optional()?;
+        let mut stmt = db.prepare("SELECT idx, entry FROM raft_log WHERE idx >= ? ORDER BY idx")?;
+        let rows = stmt.query_map([from], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(RaftLogState { hard_state, entries: rows.collect::<rusqlite::Result<_>>()? })
+    }
+
+    async fn append_raft_entries(&self, entries: &[(u64, Vec<u8>)]) -> Result<()> {
+        let Some((first, _)) = entries.first() else {
+            return Ok(());
+        };
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.prepare_cached("DELETE FROM raft_log WHERE idx >= ?")?.execute([first])?;
+        let mut insert = tx.prepare_cached("INSERT INTO raft_log (idx, entry) VALUES (?, ?)")?;
+        for (index, entry) in entries {
+            insert.execute(params![index, entry])?;
+        }
+        drop(insert);
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn set_raft_hard_state(&self, hard_state: &[u8]) -> Result<()> {
+        self.db.lock().unwrap()
+            .prepare_cached("INSERT OR REPLACE INTO raft_hard_state (id, state) VALUES (0, ?)")?
+            .execute([hard_state])?;
+        Ok(())
+    }
+
+    async fn compact_raft_log(&self, index: u64) -> Result<()> {
+        self.db.lock().unwrap().prepare_cached("DELETE FROM raft_log WHERE idx < ?")?.execute([index])?;
+        Ok(())
+    }
+
+    fn tombstones(&self) -> Result<Vec<(String, i64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, deleted_at FROM metric_tombstones")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn hooks(&self) -> Result<Vec<(String, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT id, config FROM metric_hooks")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn put_hook(&self, id: &str, config: &str) -> Result<()> {
+        self.db.lock().unwrap().execute("INSERT OR REPLACE INTO metric_hooks (id, config) VALUES (?, ?)", params![id, config])?;
+        Ok(())
+    }
+
+    fn transforms(&self) -> Result<Vec<(String, IngestTransform)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, scale, value_offset FROM metric_transforms")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, IngestTransform { scale: row.get(1)?, offset: row.get(2)? })))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn put_transform(&self, name: &str, transform: &IngestTransform) -> Result<()> {
+        self.db.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO metric_transforms (name, scale, value_offset) VALUES (?, ?, ?)",
+            params![name, transform.scale, transform.offset],
+        )?;
+        Ok(())
+    }
+
+    fn state_metrics(&self) -> Result<Vec<(String, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, config FROM metric_states")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn put_state_metric(&self, name: &str, config: &str) -> Result<()> {
+        self.db.lock().unwrap().execute("INSERT OR REPLACE INTO metric_states (name, config) VALUES (?, ?)", params![name, config])?;
+        Ok(())
+    }
+
+    fn histograms(&self) -> Result<Vec<(String, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT name, config FROM metric_histograms")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn put_histogram(&self, name: &str, config: &str) -> Result<()> {
+        self.db.lock().unwrap().execute("INSERT OR REPLACE INTO metric_histograms (name, config) VALUES (?, ?)", params![name, config])?;
+        Ok(())
+    }
+
+    fn applied_keys(&self) -> Result<Vec<(String, i64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("SELECT key, expires_at FROM applied_keys")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn put_applied_key(&self, key: &str, expires_at: i64) -> Result<()> {
+        self.db.lock().unwrap().execute("INSERT OR REPLACE INTO applied_keys (key, expires_at) VALUES (?, ?)", params![key, expires_at])?;
+        Ok(())
+    }
+
+    async fn prune_applied_keys(&self, watermark: i64) -> Result<()> {
+        self.db.lock().unwrap().execute("DELETE FROM applied_keys WHERE expires_at <= ?", [watermark])?;
+        Ok(())
+    }
+
+    async fn insert_sample(
+        &self,
+        name: &str,
+        sample: MetricPoint,
+        labels: &Labels,
+        seq: u64,
+        aggregate: Option<&MetricAggregate>,
+    ) -> Result<()> {
+        self.write(|tx, series| insert_sample_rows(tx, series, name, sample, labels, seq, &SampleSummary::new(sample.value), aggregate))
+    }
+
+    async fn put_aggregate(&self, name: &str, aggregate: &MetricAggregate) -> Result<()> {
+        upsert_aggregate(&self.db.lock().unwrap(), name, aggregate)
+    }
+
+    async fn recomputed_aggregates(&self, prefix: &str, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let db = self.db.lock().unwrap();
+        // As in the DuckDB backend, squaring by hand since SQLite may be
+        // built without its math functions
+        let mut stmt = db.prepare(
+            "SELECT name, SUM(sample_count), SUM(sample_sum), SUM(sample_sum) / SUM(sample_count),
+                    MIN(sample_min), MAX(sample_max), MIN(timestamp), MAX(timestamp),
+                    SUM(sample_m2 + sample_count * (sample_mean - mean) * (sample_mean - mean))
+             FROM (
+                SELECT m.name, m.timestamp, m.sample_count, m.sample_sum, m.sample_min, m.sample_max, m.sample_m2,
+                       m.sample_sum / m.sample_count AS sample_mean,
+                       SUM(m.sample_sum) OVER (PARTITION BY m.name) / SUM(m.sample_count) OVER (PARTITION BY m.name) AS mean
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE (t.deleted_at IS NULL OR m.timestamp > t.deleted_at) AND m.name > ?1 AND substr(m.name, 1, length(?2)) = ?2
+             )
+             GROUP BY name ORDER BY name LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![after, prefix, limit as i64], |row| Ok((row.get(0)?, aggregate_from_row(row, 1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn write_batch(&self, writes: &[StagedWrite<'_>]) -> Result<()> {
+        self.write(|tx, series| {
+            for write in writes {
+                match write {
+                    StagedWrite::Sample { name, sample, labels, seq, summary, aggregate } => {
+                        insert_sample_rows(tx, series, name, *sample, labels, *seq, summary, aggregate.as_ref())?;
+                    }
+                    StagedWrite::Aggregate { name, aggregate } => upsert_aggregate(tx, name, aggregate)?,
+                    StagedWrite::Quarantine { name, sample } => insert_quarantined(tx, name, sample)?,
+                    StagedWrite::Delete { name, deleted_at } => tombstone(tx, name, *deleted_at)?,
+                    StagedWrite::Applied { seq } => raise_applied_seq(tx, *seq)?,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn latest(&self, name: &str, deleted_at: i64) -> Result<Option<MetricPoint>> {
+        let latest = self.db.lock().unwrap()
+            .prepare_cached(
+                "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp > ? \
+                 ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT 1",
+            )?
+            .query_row(params![name, deleted_at], |row| {
+                Ok(MetricPoint { value: row.get(0)?, timestamp: row.get(1)? })
+            })
+            .optional()?;
+        Ok(latest)
+    }
+
+    async fn latest_many(&self, names: &[String]) -> Result<Vec<(String, MetricPoint)>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; names.len()].join(", ");
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(&format!(
+            "SELECT name, value, timestamp FROM (
+                SELECT m.name, m.value, m.timestamp,
+                       row_number() OVER (PARTITION BY m.name ORDER BY m.timestamp DESC, m.seq DESC, m.rowid DESC) AS rn
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE m.name IN ({})
+                  AND (t.deleted_at IS NULL OR m.timestamp > t.deleted_at)
+             ) WHERE rn = 1",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(names), |row| {
+            Ok((row.get(0)?, MetricPoint { value: row.get(1)?, timestamp: row.get(2)? }))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn aggregate(&self, name: &str) -> Result<Option<MetricAggregate>> {
+        let aggregate = self.db.lock().unwrap()
+            .prepare_cached("SELECT count, sum, average, min, max, first_seen, last_seen, m2 FROM metric_aggregates WHERE name = ?")?
+            .query_row([name], |row| aggregate_from_row(row, 0))
+            .optional()?;
+        Ok(aggregate)
+    }
+
+    async fn recent(&self, name: &str, from: i64, limit: usize) -> Result<Vec<MetricPoint>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare_cached(
+            "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp >= ? \
+             ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![name, from, limit as i64], |row| {
+            Ok(MetricPoint { value: row.get(0)?, timestamp: row.get(1)? })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn range_bounds(&self, name: &str, from: i64, to: i64) -> Result<Option<(MetricPoint, MetricPoint)>> {
+        let db = self.db.lock().unwrap();
+        let bound = |order: &str| {
+            db.prepare_cached(&format!(
+                "SELECT value, timestamp FROM metrics WHERE name = ? AND timestamp BETWEEN ? AND ? \
+                 ORDER BY timestamp {order}, rowid {order} LIMIT 1"
+            ))?
+            .query_row(params![name, from, to], |row| {
+                Ok(MetricPoint { value: row.get(0)?, timestamp: row.get(1)? })
+            })
+            .optional()
+        };
+
+        match (bound("ASC")?, bound("DESC")?) {
+            (Some(earliest), Some(latest)) => Ok(Some((earliest, latest))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn range_page(
+        &self,
+        name: &str,
+        range: RangeQuery,
+        after: Option<RangeCursor>,
+        limit: usize,
+    ) -> Result<(Vec<MetricPoint>, Option<RangeCursor>)> {
+        let after = after.unwrap_or(RangeCursor { timestamp: i64::MIN, position: i64::MIN });
+
+        let db = self.db.lock().unwrap();
+        let rows: Vec<(MetricPoint, i64)> = match range.step {
+            Some(step) => {
+                // Offsets from `range.from` are never negative, so truncating division floors them
+                let mut stmt = db.prepare_cached(&format!(
+                    "SELECT {}, ? + (timestamp - ?) / ? * ? AS bucket FROM metrics \
+                     WHERE name = ? AND timestamp BETWEEN ? AND ? \
+                     GROUP BY bucket HAVING bucket > ? ORDER BY bucket LIMIT ?",
+                    range.function.sql()
+                ))?;
+                let rows = stmt.query_map(
+                    params![range.from, range.from, step, step, name, range.from, range.to, after.timestamp, limit as i64],
+                    |row| Ok((MetricPoint { value: row.get(0)?, timestamp: row.get(1)? }, i64::MAX)),
+                )?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+            None => {
+                let mut stmt = db.prepare_cached(
+                    "SELECT value, timestamp, rowid FROM metrics \
+                     WHERE name = ? AND timestamp BETWEEN ? AND ? \
+                     AND (timestamp > ? OR (timestamp = ? AND rowid > ?)) \
+                     ORDER BY timestamp, rowid LIMIT ?",
+                )?;
+                let rows = stmt.query_map(
+                    params![name, range.from, range.to, after.timestamp, after.timestamp, after.position, limit as i64],
+                    |row| Ok((MetricPoint { value: row.get(0)?, timestamp: row.get(1)? }, row.get(2)?)),
+                )?;
+                rows.collect::<rusqlite::Result<_>>()?
+            }
+        };
+
+        let next = match rows.last() {
+            Some(&(last, position)) if rows.len() == limit => Some(RangeCursor { timestamp: last.timestamp, position }),
+            _ => None,
+        };
+        Ok((rows.into_iter().map(|(point, _)| point).collect(), next))
+    }
+
+    async fn buckets(&self, name: &str, from: i64, to: i64, step: i64) -> Result<Vec<BucketStats>> {
+        let db = self.db.lock().unwrap();
+        // `%` keeps the dividend's sign, so the second one floors pre-epoch timestamps
+        let mut stmt = db.prepare_cached(
+            "SELECT timestamp - (timestamp % ?1 + ?1) % ?1 AS bucket, count(value), sum(value), min(value), max(value) \
+             FROM metrics WHERE name = ?2 AND timestamp BETWEEN ?3 AND ?4 \
+             GROUP BY bucket ORDER BY bucket",
+        )?;
+        let rows = stmt.query_map(params![step, name, from, to], |row| {
+            Ok(BucketStats {
+                start: row.get(0)?,
+                count: row.get::<_, i64>(1)? as u64,
+                sum: row.get(2)?,
+                min: row.get(3)?,
+                max: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn group_by(&self, name: &str, query: &GroupByQuery) -> Result<(Vec<(String, f64)>, bool)> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare_cached(&format!(
+            "SELECT grp, {} FROM ( \
+                SELECT COALESCE(l.value, ?) AS grp, m.value AS value FROM metrics m \
+                LEFT JOIN series_labels l ON l.series_id = m.series_id AND l.key = ? \
+                WHERE m.name = ? AND m.timestamp BETWEEN ? AND ? \
+             ) GROUP BY grp ORDER BY grp LIMIT ?",
+            query.function.sql()
+        ))?;
+        let rows = stmt.query_map(
+            params![NO_LABEL_GROUP, query.label, name, query.from, query.to, query.max_groups as i64 + 1],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let mut groups: Vec<(String, f64)> = rows.collect::<rusqlite::Result<_>>()?;
+        let truncated = groups.len() > query.max_groups;
+        groups.truncate(query.max_groups);
+        Ok((groups, truncated))
+    }
+
+    async fn series(&self, name: &str, labels: &Labels, deleted_at: i64) -> Result<Option<SeriesSummary>> {
+        let db = self.db.lock().unwrap();
+        // The window totals cover every row the filter keeps, not just the
+        // one left by the limit
+        let series = db.prepare_cached(
+            "SELECT value, timestamp, count(*) OVER (), sum(value) OVER (), min(value) OVER (), max(value) OVER () \
+             FROM metrics WHERE name = ?1 AND timestamp > ?2 \
+               AND series_id = (SELECT series_id FROM series WHERE name = ?1 AND labels = ?3) \
+             ORDER BY timestamp DESC, seq DESC, rowid DESC LIMIT 1",
+        )?
+        .query_row(params![name, deleted_at, canonical_labels(labels)], |row| {
+            Ok(SeriesSummary {
+                latest: MetricPoint { value: row.get(0)?, timestamp: row.get(1)? },
+                count: row.get::<_, i64>(2)? as u64,
+                sum: row.get(3)?,
+                min: row.get(4)?,
+                max: row.get(5)?,
+            })
+        })
+        .optional()?;
+        Ok(series)
+    }
+
+    async fn quarantine(&self, name: &str, sample: &QuarantinedSample) -> Result<()> {
+        insert_quarantined(&self.db.lock().unwrap(), name, sample)
+    }
+
+    async fn quarantined(&self, name: &str) -> Result<Vec<QuarantinedSample>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare_cached(
+            "SELECT value, timestamp, seq, labels, reason, weight FROM quarantined_samples WHERE name = ? ORDER BY seq, rowid",
+        )?;
+        let rows = stmt.query_map([name], |row| {
+            Ok((
+                row.get::<_, f64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, u64>(5)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (value, timestamp, seq, labels, reason, weight) = row?;
+            let labels = serde_json::from_str(&labels)
+                .map_err(|e| RaftMetricsError::Internal(format!("Failed to decode labels: {}", e)))?;
+            Ok(QuarantinedSample { value, timestamp, labels, seq, reason, weight })
+        })
+        .collect()
+    }
+
+    async fn drop_quarantined(&self, name: &str) -> Result<()> {
+        self.db.lock().unwrap().execute("DELETE FROM quarantined_samples WHERE name = ?", [name])?;
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str, deleted_at: i64) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tombstone(&tx, name, deleted_at)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn purge(&self, expired: &[(String, i64)]) -> Result<()> {
+        self.write(|tx, series| {
+            for (name, deleted_at) in expired {
+                tx.execute("DELETE FROM metrics WHERE name = ? AND timestamp <= ?", params![name, deleted_at])?;
+                prune_series(tx, series, name)?;
+                tx.execute("DELETE FROM metric_tombstones WHERE name = ?", [name])?;
+            }
+            Ok(())
+        })
+    }
+
+    async fn compact(&self, name: &str, before: i64, interval: i64, limit: usize) -> Result<usize> {
+        self.write(|tx, series| {
+            // Buckets start on multiples of the interval, floored like `buckets`
+            let removed = tx
+                .prepare_cached(
+                    "DELETE FROM metrics WHERE rowid IN (
+                        SELECT position FROM (
+                            SELECT rowid AS position, ROW_NUMBER() OVER (
+                                PARTITION BY timestamp - (timestamp % ?1 + ?1) % ?1 ORDER BY timestamp DESC, seq DESC, rowid DESC
+                            ) AS rn
+                            FROM metrics WHERE name = ?2 AND timestamp < ?3
+                        ) WHERE rn > 1 ORDER BY position LIMIT ?4
+                    )",
+                )?
+                .execute(params![interval, name, before, limit as i64])?;
+            if removed > 0 {
+                prune_series(tx, series, name)?;
+            }
+            Ok(removed)
+        })
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.write(|tx, series| {
+            tx.execute_batch(
+                "DELETE FROM metrics;
+                 DELETE FROM series;
+                 DELETE FROM series_labels;
+                 DELETE FROM metric_aggregates;
+                 DELETE FROM metric_tombstones;
+                 DELETE FROM quarantined_samples;",
+            )?;
+            series.ids.clear();
+            Ok(())
+        })
+    }
+
+    async fn stats(&self) -> Result<StorageStats> {
+        let (metric_count, sample_rows, tombstones, series) = self.db.lock().unwrap().query_row(
+            "SELECT (SELECT COUNT(*) FROM metric_aggregates),
+                    (SELECT COUNT(*) FROM metrics),
+                    (SELECT COUNT(*) FROM metric_tombstones),
+                    (SELECT COUNT(*) FROM series)",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)),
+        )?;
+        Ok(StorageStats {
+            metric_count: metric_count as u64,
+            sample_rows: sample_rows as u64,
+            tombstones: tombstones as u64,
+            series: series as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn aggregates_page(&self, after: &str, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, count, sum, average, min, max, first_seen, last_seen, m2 FROM metric_aggregates
+             WHERE name > ? ORDER BY name LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![after, limit as i64], |row| Ok((row.get(0)?, aggregate_from_row(row, 1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn stale_page(&self, cutoff: i64, after: &str, limit: usize) -> Result<Vec<StaleMetric>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, first_seen, last_seen FROM metric_aggregates
+             WHERE last_seen < ? AND name > ? ORDER BY name LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![cutoff, after, limit as i64], |row| {
+            Ok(StaleMetric { name: row.get(0)?, first_seen: row.get(1)?, last_seen: row.get(2)? })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn latest_between(&self, after: &str, last: &str) -> Result<Vec<(String, MetricPoint, u64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, value, timestamp, max_seq FROM (
+                SELECT m.name, m.value, m.timestamp,
+                       max(m.seq) OVER (PARTITION BY m.name) AS max_seq,
+                       row_number() OVER (PARTITION BY m.name ORDER BY m.timestamp DESC, m.seq DESC, m.rowid DESC) AS rn
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE m.name > ? AND m.name <= ?
+                  AND (t.deleted_at IS NULL OR m.timestamp > t.deleted_at)
+             ) WHERE rn = 1",
+        )?;
+        let rows = stmt.query_map(params![after, last], |row| {
+            Ok((
+                row.get(0)?,
+                MetricPoint { value: row.get(1)?, timestamp: row.get(2)? },
+                row.get(3)?,
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn all_latest(&self) -> Result<Vec<(String, f64)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, value FROM (
+                SELECT m.name, m.value,
+                       row_number() OVER (PARTITION BY m.name ORDER BY m.timestamp DESC, m.seq DESC, m.rowid DESC) AS rn
+                FROM metrics m
+                LEFT JOIN metric_tombstones t ON t.name = m.name
+                WHERE t.deleted_at IS NULL OR m.timestamp > t.deleted_at
+             ) WHERE rn = 1",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn aggregates_slice(&self, offset: usize, limit: usize) -> Result<Vec<(String, MetricAggregate)>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT name, count, sum, average, min, max, first_seen, last_seen, m2 FROM metric_aggregates
+             ORDER BY name LIMIT ? OFFSET ?",
+        )?;
+        let limit = limit.min(i64::MAX as usize) as i64;
+        let rows = stmt.query_map(params![limit, offset as i64], |row| Ok((row.get(0)?, aggregate_from_row(row, 1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
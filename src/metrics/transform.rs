@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+use super::SampleSummary;
+use crate::{RaftMetricsError, Result};
+
+fn one() -> f64 {
+    1.0
+}
+
+/// `value * scale + offset`, applied to a metric's samples as they are
+/// recorded so every read and aggregate sees the normalized value, e.g. a
+/// `scale` of `0.001` to record nanoseconds as microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IngestTransform {
+    #[serde(default = "one")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl Default for IngestTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl IngestTransform {
+    /// Records values as they are sent.
+    pub const IDENTITY: Self = Self { scale: 1.0, offset: 0.0 };
+
+    pub fn new(scale: f64, offset: f64) -> Result<Self> {
+        if !scale.is_finite() || !offset.is_finite() {
+            return Err(RaftMetricsError::InvalidRequest("scale and offset must be finite".to_string()));
+        }
+        if scale == 0.0 {
+            return Err(RaftMetricsError::InvalidRequest("scale must not be 0".to_string()));
+        }
+        Ok(Self { scale, offset })
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+
+    /// `summary` as if each of its samples had been transformed.
+    pub fn apply_summary(&self, summary: &SampleSummary) -> SampleSummary {
+        if *self == Self::IDENTITY {
+            return *summary;
+        }
+        let (min, max) = (self.apply(summary.min), self.apply(summary.max));
+        SampleSummary {
+            last: self.apply(summary.last),
+            count: summary.count,
+            sum: summary.sum * self.scale + self.offset * summary.count as f64,
+            min: min.min(max),
+            max: min.max(max),
+            m2: summary.m2 * self.scale * self.scale,
+        }
+    }
+}
+
+/// Per-metric [`IngestTransform`]s from `INGEST_TRANSFORMS`. Transforms
+/// set through `PUT /metrics/:name/transform` take precedence; see
+/// [`super::MetricsRegistry::transform`].
+#[derive(Debug, Default)]
+pub struct IngestTransforms {
+    configured: HashMap<String, IngestTransform>,
+}
+
+impl IngestTransforms {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads `INGEST_TRANSFORMS`, a comma-separated list of `name=scale`
+    /// or `name=scale:offset` pairs. Malformed entries are skipped with a
+    /// warning. Every replica of a partition must be given the same list.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut configured = HashMap::new();
+        for entry in lookup("INGEST_TRANSFORMS").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(name, transform)| {
+                let (scale, offset) = transform.split_once(':').unwrap_or((transform, "0"));
+                let transform = IngestTransform::new(scale.trim().parse().ok()?, offset.trim().parse().ok()?).ok()?;
+                Some((name.trim(), transform))
+            });
+            match parsed {
+                Some((name, transform)) if !name.is_empty() => {
+                    configured.insert(name.to_string(), transform);
+                }
+                _ => warn!("Ignoring INGEST_TRANSFORMS entry '{}'", entry),
+            }
+        }
+        Self { configured }
+    }
+
+    pub fn get(&self, name: &str) -> Option<IngestTransform> {
+        self.configured.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transforms_from_env_and_validation() {
+        let transforms = IngestTransforms::from_lookup(|_| Some("latency_ns=0.001, temp_f = 0.5:-16,bad=0,noeq,=2".to_string()));
+        assert_eq!(transforms.get("latency_ns").unwrap().apply(2500.0), 2.5);
+        assert_eq!(transforms.get("temp_f").unwrap().apply(100.0), 34.0);
+        assert_eq!(transforms.get("bad"), None);
+        assert_eq!(transforms.get("other"), None);
+        assert!(IngestTransform::new(f64::NAN, 0.0).is_err());
+        assert!(IngestTransform::new(0.0, 1.0).is_err());
+    }
+}
@@ -1,6 +1,9 @@
+use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
+use crate::metrics::Labels;
+
 /// Get the partition number for a given metric name.
 /// Uses consistent hashing to ensure the same metric always goes to the same worker.
 /// 
@@ -11,16 +14,33 @@ use std::hash::{Hash, Hasher};
 /// # Returns
 /// Partition number in range [0, num_partitions)
 pub fn get_partition(metric_name: &str, num_partitions: usize) -> usize {
+    jump_hash(hash_key(metric_name), num_partitions)
+}
+
+/// Like [`get_partition`], but hashes the name together with `seed` using
+/// [`seeded_hash`], so the assignment is the same on every build.
+pub fn get_seeded_partition(metric_name: &str, num_partitions: usize, seed: u64) -> usize {
+    jump_hash(seeded_hash(seed, metric_name), num_partitions)
+}
+
+/// 64-bit FNV-1a over `seed`'s little-endian bytes followed by `key`. Unlike
+/// `DefaultHasher`, whose algorithm may change between Rust releases, its
+/// output is fixed.
+pub fn seeded_hash(seed: u64, key: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    seed.to_le_bytes()
+        .iter()
+        .chain(key.as_bytes())
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(PRIME))
+}
+
+/// Jump consistent hashing of `key` into [0, num_partitions).
+fn jump_hash(mut key: u64, num_partitions: usize) -> usize {
     if num_partitions == 0 {
         return 0;
     }
 
-    let mut hasher = DefaultHasher::new();
-    metric_name.hash(&mut hasher);
-    let hash = hasher.finish();
-    
-    // Use jump consistent hashing for better distribution
-    let mut key = hash;
     let mut b = -1i64;
     let mut j = 0i64;
 
@@ -32,6 +52,220 @@ pub fn get_partition(metric_name: &str, num_partitions: usize) -> usize {
     b as usize
 }
 
+/// Label keys whose values join a metric's name in the key it is routed
+/// by, from `PARTITION_LABEL_KEYS`. Series sharing the name and those
+/// values stay on one worker however many other labels they carry, so a
+/// high-cardinality label cannot scatter them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartitionKey {
+    label_keys: Vec<String>,
+}
+
+impl PartitionKey {
+    pub fn new(label_keys: Vec<String>) -> Self {
+        let mut unique = Vec::with_capacity(label_keys.len());
+        for key in label_keys {
+            if !key.is_empty() && !unique.contains(&key) {
+                unique.push(key);
+            }
+        }
+        Self { label_keys: unique }
+    }
+
+    /// Reads `PARTITION_LABEL_KEYS`, a comma-separated list of label keys.
+    /// Unset routes by the metric name alone.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let keys = lookup("PARTITION_LABEL_KEYS").unwrap_or_default();
+        Self::new(keys.split(',').map(|key| key.trim().to_string()).collect())
+    }
+
+    pub fn label_keys(&self) -> &[String] {
+        &self.label_keys
+    }
+
+    /// What a sample of `name` carrying `labels` is routed by: the name
+    /// alone when it has none of the partition labels, otherwise the name
+    /// followed by their values in configured order, e.g.
+    /// `requests{tenant=acme,service=api}`. Other labels never change it.
+    pub fn key<'a>(&self, name: &'a str, labels: &Labels) -> Cow<'a, str> {
+        let pairs: Vec<String> = self
+            .label_keys
+            .iter()
+            .filter_map(|key| Some(format!("{}={}", key, labels.get(key)?)))
+            .collect();
+        if pairs.is_empty() {
+            return Cow::Borrowed(name);
+        }
+        Cow::Owned(format!("{}{{{}}}", name, pairs.join(",")))
+    }
+}
+
+/// Maps a metric name to the index of the worker that owns it.
+pub trait Router: Send + Sync {
+    fn route(&self, metric_name: &str) -> usize;
+    fn worker_count(&self) -> usize;
+}
+
+/// Routes with [`get_partition`], or [`get_seeded_partition`] when seeded;
+/// every worker gets an equal share. Skipped workers keep their slot and
+/// their metrics are hashed again onto the others, so no other worker's
+/// metrics move.
+#[derive(Debug, Clone)]
+pub struct JumpHashRouter {
+    num_partitions: usize,
+    seed: Option<u64>,
+    skipped: Vec<bool>,
+}
+
+impl JumpHashRouter {
+    pub fn new(num_partitions: usize) -> Self {
+        Self { num_partitions, seed: None, skipped: Vec::new() }
+    }
+
+    pub fn seeded(num_partitions: usize, seed: u64) -> Self {
+        Self { num_partitions, seed: Some(seed), skipped: Vec::new() }
+    }
+
+    /// Routes nothing to the workers at `indexes`, unless that would leave
+    /// no worker at all.
+    pub fn skipping(mut self, indexes: &[usize]) -> Self {
+        let skipped: Vec<bool> = (0..self.num_partitions).map(|index| indexes.contains(&index)).collect();
+        if skipped.contains(&false) {
+            self.skipped = skipped;
+        }
+        self
+    }
+}
+
+impl Router for JumpHashRouter {
+    fn route(&self, metric_name: &str) -> usize {
+        let mut key = match self.seed {
+            Some(seed) => seeded_hash(seed, metric_name),
+            None => hash_key(metric_name),
+        };
+        loop {
+            let partition = jump_hash(key, self.num_partitions);
+            if !self.skipped.get(partition).copied().unwrap_or(false) {
+                return partition;
+            }
+            key = key.wrapping_mul(0x9e37_79b9_7f4a_7c15).wrapping_add(1);
+        }
+    }
+
+    fn worker_count(&self) -> usize {
+        self.num_partitions
+    }
+}
+
+/// Virtual nodes placed on the ring per unit of worker weight.
+pub const VNODES_PER_WEIGHT: u32 = 160;
+
+/// Consistent-hash ring where each worker owns virtual nodes in proportion
+/// to its weight. Keys route to the next virtual node clockwise.
+///
+/// Virtual nodes are derived from the worker's identity (its URL), not its
+/// position, so removing or reweighting one worker only moves keys to or
+/// from that worker.
+#[derive(Debug, Clone)]
+pub struct WeightedRingRouter {
+    ring: Vec<(u64, usize)>,
+    worker_count: usize,
+    seed: Option<u64>,
+}
+
+impl WeightedRingRouter {
+    /// # Arguments
+    /// * `workers` - `(worker identity, weight)` pairs; the route result is an index into this slice
+    pub fn new(workers: &[(String, u32)]) -> Self {
+        Self::build(workers, None)
+    }
+
+    /// Like [`WeightedRingRouter::new`], placing virtual nodes and keys with
+    /// [`seeded_hash`].
+    pub fn seeded(workers: &[(String, u32)], seed: u64) -> Self {
+        Self::build(workers, Some(seed))
+    }
+
+    fn build(workers: &[(String, u32)], seed: Option<u64>) -> Self {
+        let mut router = Self { ring: Vec::new(), worker_count: workers.len(), seed };
+        for (index, (worker, weight)) in workers.iter().enumerate() {
+            for vnode in 0..weight * VNODES_PER_WEIGHT {
+                router.ring.push((router.hash(&format!("{}#{}", worker, vnode)), index));
+            }
+        }
+        router.ring.sort_unstable();
+        router
+    }
+
+    fn hash(&self, key: &str) -> u64 {
+        match self.seed {
+            Some(seed) => seeded_hash(seed, key),
+            None => hash_key(key),
+        }
+    }
+}
+
+impl Router for WeightedRingRouter {
+    fn route(&self, metric_name: &str) -> usize {
+        if self.ring.is_empty() {
+            return 0;
+        }
+
+        let hash = self.hash(metric_name);
+        let pos = self.ring.partition_point(|(vnode, _)| *vnode < hash);
+        self.ring[pos % self.ring.len()].1
+    }
+
+    fn worker_count(&self) -> usize {
+        self.worker_count
+    }
+}
+
+/// Sends everything to the worker with the lowest load score, ignoring the
+/// name. Only for work any worker can do, such as stateless aggregation;
+/// partitioned metrics must stay with a hashing router. Workers without a
+/// score are only picked when none has one.
+#[derive(Debug, Clone)]
+pub struct LeastLoadedRouter {
+    scores: Vec<Option<f64>>,
+}
+
+impl LeastLoadedRouter {
+    /// # Arguments
+    /// * `scores` - Load score per worker index, lower meaning less loaded
+    pub fn new(scores: Vec<Option<f64>>) -> Self {
+        Self { scores }
+    }
+}
+
+impl Router for LeastLoadedRouter {
+    fn route(&self, _metric_name: &str) -> usize {
+        self.scores
+            .iter()
+            .enumerate()
+            .filter_map(|(index, score)| Some((index, (*score)?)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn worker_count(&self) -> usize {
+        self.scores.len()
+    }
+}
+
+fn hash_key(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Parses `WORKER_WEIGHTS`-style input (`"2,1,1"`) for `worker_count` workers.
+/// Missing or unparsable entries default to weight 1.
+pub fn parse_weights(raw: &str, worker_count: usize) -> Vec<u32> {
+    let parsed: Vec<u32> = raw.split(',').map(|w| w.trim().parse().unwrap_or(1)).collect();
+    (0..worker_count).map(|i| parsed.get(i).copied().unwrap_or(1)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,4 +317,155 @@ mod tests {
         let partition = get_partition("", 2);
         assert!(partition < 2);
     }
+
+    fn workers(weights: &[(&str, u32)]) -> Vec<(String, u32)> {
+        weights.iter().map(|(w, n)| (w.to_string(), *n)).collect()
+    }
+
+    fn keys() -> Vec<String> {
+        (0..20_000).map(|i| format!("metric_{}", i)).collect()
+    }
+
+    #[test]
+    fn test_weighted_distribution_matches_weights() {
+        let router = WeightedRingRouter::new(&workers(&[("w1", 1), ("w2", 2), ("w3", 1)]));
+        let keys = keys();
+
+        let mut counts = [0usize; 3];
+        for key in &keys {
+            counts[router.route(key)] += 1;
+        }
+
+        let expected = [0.25, 0.5, 0.25];
+        for (count, share) in counts.iter().zip(expected) {
+            let actual = *count as f64 / keys.len() as f64;
+            assert!((actual - share).abs() < 0.05, "share {} too far from {}", actual, share);
+        }
+    }
+
+    #[test]
+    fn test_reweight_moves_only_affected_keys() {
+        let before = WeightedRingRouter::new(&workers(&[("w1", 1), ("w2", 1), ("w3", 1)]));
+        let after = WeightedRingRouter::new(&workers(&[("w1", 1), ("w2", 2), ("w3", 1)]));
+        let keys = keys();
+
+        let mut moved = 0;
+        for key in &keys {
+            let (old, new) = (before.route(key), after.route(key));
+            if old != new {
+                // Extra vnodes can only attract keys toward the reweighted worker
+                assert_eq!(new, 1);
+                moved += 1;
+            }
+        }
+
+        // Ideal movement is 1/2 - 1/3 of the keyspace
+        let fraction = moved as f64 / keys.len() as f64;
+        assert!(fraction > 0.10 && fraction < 0.25, "moved fraction {}", fraction);
+    }
+
+    #[test]
+    fn test_removing_worker_moves_only_its_keys() {
+        let before = WeightedRingRouter::new(&workers(&[("w1", 1), ("w2", 1), ("w3", 1)]));
+        let after = WeightedRingRouter::new(&workers(&[("w1", 1), ("w3", 1)]));
+
+        for key in keys() {
+            let old = before.route(&key);
+            if old != 1 {
+                let expected = if old == 0 { 0 } else { 1 };
+                assert_eq!(after.route(&key), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipping_a_worker_moves_only_its_keys() {
+        let before = JumpHashRouter::seeded(3, 7);
+        let after = JumpHashRouter::seeded(3, 7).skipping(&[1]);
+        let mut moved = [0usize; 3];
+        for key in keys() {
+            let (old, new) = (before.route(&key), after.route(&key));
+            assert_ne!(new, 1);
+            if old != 1 {
+                assert_eq!(new, old);
+            } else {
+                moved[new] += 1;
+            }
+        }
+        assert!(moved[0] > 0 && moved[2] > 0, "skipped keys all went one way: {:?}", moved);
+
+        // Skipping everyone would route nowhere, so it skips no one
+        let all = JumpHashRouter::new(2).skipping(&[0, 1]);
+        assert!(keys().iter().all(|key| all.route(key) == get_partition(key, 2)));
+    }
+
+    #[test]
+    fn test_seeded_partitions_are_reproducible() {
+        let names = ["cpu_usage", "memory_usage", "disk_usage", "network_in", "network_out"];
+        let route = |seed| {
+            let router = JumpHashRouter::seeded(16, seed);
+            names.iter().map(|name| router.route(name)).collect::<Vec<_>>()
+        };
+
+        // Pinned so a change to the hash or jump step is caught, not just
+        // a change between two routers built in the same process
+        assert_eq!(route(7), [10, 11, 5, 11, 12]);
+        assert_eq!(route(42), [13, 3, 1, 11, 9]);
+        assert_eq!(seeded_hash(42, "cpu"), 0x3dfc_7d87_4358_234b);
+        for name in names {
+            assert_eq!(get_seeded_partition(name, 16, 42), JumpHashRouter::seeded(16, 42).route(name));
+        }
+
+        let ring = workers(&[("w1", 1), ("w2", 2)]);
+        let (first, second) = (WeightedRingRouter::seeded(&ring, 42), WeightedRingRouter::seeded(&ring, 42));
+        assert!(keys().iter().all(|key| first.route(key) == second.route(key)));
+        let reseeded = WeightedRingRouter::seeded(&ring, 43);
+        assert!(keys().iter().any(|key| first.route(key) != reseeded.route(key)));
+    }
+
+    #[test]
+    fn test_least_loaded_router_picks_the_lowest_score() {
+        let router = LeastLoadedRouter::new(vec![Some(3.5), None, Some(0.25), Some(0.25)]);
+        // Ties go to the first worker
+        assert_eq!(router.route("anything"), 2);
+        assert_eq!(router.route("else"), 2);
+        assert_eq!(router.worker_count(), 4);
+
+        assert_eq!(LeastLoadedRouter::new(vec![None, None]).route("x"), 0);
+    }
+
+    #[test]
+    fn test_partition_labels_alone_pick_the_worker() {
+        let key = PartitionKey::from_lookup(|_| Some("tenant, service,,tenant".to_string()));
+        assert_eq!(key.label_keys(), ["tenant", "service"]);
+        let router = JumpHashRouter::seeded(16, 42);
+        let labels = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Labels>();
+        let route = |pairs: &[(&str, &str)]| router.route(&key.key("requests", &labels(pairs)));
+
+        // Differing only in a label outside the key, series stay together
+        let base = route(&[("tenant", "acme"), ("service", "api"), ("request_id", "1")]);
+        for request_id in 2..200 {
+            let id = request_id.to_string();
+            assert_eq!(route(&[("tenant", "acme"), ("service", "api"), ("request_id", &id)]), base);
+        }
+        assert_eq!(key.key("requests", &labels(&[("service", "api"), ("tenant", "acme")])), "requests{tenant=acme,service=api}");
+        assert_eq!(key.key("requests", &labels(&[("host", "a")])), "requests");
+
+        // Differing in a partition label, they spread out
+        let tenants: std::collections::HashSet<usize> =
+            (0..50).map(|i| route(&[("tenant", &format!("tenant_{}", i)), ("service", "api")])).collect();
+        assert!(tenants.len() > 1, "every tenant routed to {:?}", tenants);
+        let services: std::collections::HashSet<usize> =
+            (0..50).map(|i| route(&[("tenant", "acme"), ("service", &format!("service_{}", i))])).collect();
+        assert!(services.len() > 1, "every service routed to {:?}", services);
+
+        // Without partition labels nothing changes
+        assert_eq!(PartitionKey::default().key("requests", &labels(&[("tenant", "acme")])), "requests");
+    }
+
+    #[test]
+    fn test_parse_weights() {
+        assert_eq!(parse_weights("2,1", 3), vec![2, 1, 1]);
+        assert_eq!(parse_weights("x,3", 2), vec![1, 3]);
+    }
 }
@@ -1,2 +1,3 @@
+pub mod network;
 pub mod node;
 pub mod storage;
@@ -0,0 +1,115 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use prost::Message as _;
+use raft::eraftpb::Message;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+#[cfg(feature = "chaos")]
+use crate::api::chaos::ChaosState;
+
+/// Carries raft messages between nodes of one group running in this process,
+/// and to members in other processes over HTTP when given their URLs.
+/// Messages to or from a node that is not connected, or is cut off, are
+/// dropped, as they would be by a real network to a node that is down.
+#[derive(Clone, Default)]
+pub struct LocalNetwork {
+    inboxes: Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<Message>>>>,
+    cut_off: Arc<Mutex<HashSet<u64>>>,
+    /// Members in other processes, by raft id, with their URLs.
+    remotes: Arc<HashMap<u64, String>>,
+    client: reqwest::Client,
+    /// Sent as a bearer token to remote members, which need `ADMIN_TOKEN`.
+    token: Option<Arc<str>>,
+    /// Faults each node injects into its outbound messages.
+    #[cfg(feature = "chaos")]
+    chaos: Arc<Mutex<HashMap<u64, Arc<ChaosState>>>>,
+}
+
+impl LocalNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A network whose messages to `remotes` are posted to their
+    /// `/raft/message` route, with `token` when one is set.
+    pub fn with_remotes<'a>(remotes: impl IntoIterator<Item = (u64, &'a str)>, token: Option<Arc<str>>) -> Self {
+        Self {
+            remotes: Arc::new(remotes.into_iter().map(|(id, url)| (id, url.to_string())).collect()),
+            token,
+            ..Self::default()
+        }
+    }
+
+    /// Hands a message a remote member sent to the local node it is for.
+    pub fn deliver(&self, msg: Message) {
+        match self.inboxes.lock().unwrap().get(&msg.to) {
+            Some(inbox) if !self.cut_off.lock().unwrap().contains(&msg.to) => {
+                let _ = inbox.send(msg);
+            }
+            _ => debug!("Dropping Raft message from {} to {}: not connected", msg.from, msg.to),
+        }
+    }
+
+    /// Connects node `id`, returning the inbox its messages arrive on.
+    pub fn connect(&self, id: u64) -> mpsc::UnboundedReceiver<Message> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.inboxes.lock().unwrap().insert(id, tx);
+        rx
+    }
+
+    /// Cuts node `id` off from the rest of the group.
+    pub fn disconnect(&self, id: u64) {
+        self.cut_off.lock().unwrap().insert(id);
+    }
+
+    /// Heals a [`disconnect`](Self::disconnect); the node keeps its inbox.
+    pub fn reconnect(&self, id: u64) {
+        self.cut_off.lock().unwrap().remove(&id);
+    }
+
+    /// Lets `chaos` drop messages node `id` sends.
+    #[cfg(feature = "chaos")]
+    pub fn inject_chaos(&self, id: u64, chaos: Arc<ChaosState>) {
+        self.chaos.lock().unwrap().insert(id, chaos);
+    }
+
+    pub fn send(&self, msg: Message) {
+        #[cfg(feature = "chaos")]
+        if self.chaos.lock().unwrap().get(&msg.from).is_some_and(|chaos| chaos.drops_raft_message()) {
+            debug!("Chaos: dropping Raft message from {} to {}", msg.from, msg.to);
+            return;
+        }
+        if let Some(url) = self.remotes.get(&msg.to) {
+            return self.post(url, msg);
+        }
+        let inboxes = self.inboxes.lock().unwrap();
+        let cut_off = self.cut_off.lock().unwrap();
+        match inboxes.get(&msg.to) {
+            Some(inbox) if inboxes.contains_key(&msg.from) && !cut_off.contains(&msg.from) && !cut_off.contains(&msg.to) => {
+                let _ = inbox.send(msg);
+            }
+            _ => debug!("Dropping Raft message from {} to {}: not connected", msg.from, msg.to),
+        }
+    }
+
+    /// Posts `msg` to the remote member at `url` without waiting on it; a
+    /// message that does not arrive is recovered by raft's retransmission.
+    fn post(&self, url: &str, msg: Message) {
+        if self.cut_off.lock().unwrap().contains(&msg.from) {
+            return;
+        }
+        let mut request = self.client.post(format!("{}/raft/message", url)).body(msg.encode_to_vec());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let (from, to) = (msg.from, msg.to);
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => warn!("Raft message from {} to {} refused: {}", from, to, response.status()),
+                Err(e) => debug!("Raft message from {} to {} not sent: {}", from, to, e),
+            }
+        });
+    }
+}
@@ -1,46 +1,393 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
 use raft::{
-    eraftpb::Message,
+    eraftpb::{EntryType, Message},
     storage::MemStorage,
-    Config, RawNode,
+    Config, LightReady, RawNode, StateRole, INVALID_ID,
     prelude::*,
 };
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
 use slog::{Logger, o};
-use tracing::{info, warn};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn, Instrument, Span};
 
-use crate::{Result, RaftMetricsError};
+use crate::{
+    Result,
+    RaftMetricsError,
+    config::{RaftConfig, RaftHealthConfig, RAFT_MAX_SIZE_PER_MSG},
+    metrics::{
+        codec::RaftCodec, halts_apply, MetricOperation, MetricsRegistry, RaftLogState, RAFT_APPLIED_INDEX, RAFT_APPLY_LAG_ENTRIES, RAFT_BATCH_SIZE,
+        RAFT_COMMIT_INDEX, RAFT_FORCED_STEPDOWNS_TOTAL, RAFT_LAST_APPLY_TIMESTAMP, RAFT_LAST_TICK_TIMESTAMP, RAFT_PROPOSALS_TOTAL,
+        RAFT_PROPOSAL_DURATION, PROPOSAL_QUEUE_DEPTH, PROPOSAL_QUEUE_HIGH_WATER,
+    },
+    raft::network::LocalNetwork,
+};
+
+/// Applied entries between compactions of the persisted log.
+const LOG_COMPACTION_INTERVAL: u64 = 1024;
+
+/// First and longest waits before applying an entry again after a local
+/// failure halted it.
+const APPLY_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(50);
+const APPLY_RETRY_MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(5);
+/// Failed attempts at applying one entry after which a leader steps down.
+const STEP_DOWN_AFTER_APPLY_FAILURES: u32 = 3;
+
+/// An operation waiting to be committed, with the channel its caller is
+/// waiting on for the apply outcome.
+pub struct Proposal {
+    pub operation: MetricOperation,
+    pub respond_to: oneshot::Sender<Result<()>>,
+    /// Signalled when the operation is in this node's log but did not
+    /// commit in the same round, so its caller can stop waiting early.
+    pub appended: Option<oneshot::Sender<()>>,
+    /// Span the entry's apply is traced under; none by default.
+    pub span: Span,
+}
+
+impl Proposal {
+    pub fn new(operation: MetricOperation) -> (Self, oneshot::Receiver<Result<()>>) {
+        let (respond_to, rx) = oneshot::channel();
+        (Self { operation, respond_to, appended: None, span: Span::none() }, rx)
+    }
+
+    /// Like [`Proposal::new`], also returning the `appended` signal.
+    pub fn tracked(operation: MetricOperation) -> (Self, oneshot::Receiver<Result<()>>, oneshot::Receiver<()>) {
+        let (respond_to, rx) = oneshot::channel();
+        let (appended, appended_rx) = oneshot::channel();
+        (Self { operation, respond_to, appended: Some(appended), span: Span::none() }, rx, appended_rx)
+    }
+}
+
+/// Callers waiting on the operations of one log entry.
+type Waiters = Vec<(oneshot::Sender<Result<()>>, Option<oneshot::Sender<()>>)>;
+
+/// A log entry this node proposed that has not been applied yet.
+struct InFlight {
+    /// Term the entry was proposed in.
+    term: u64,
+    /// Whether this node led the group when it proposed the entry, rather
+    /// than forwarding it to the leader.
+    as_leader: bool,
+    /// Index and term of the entry once it is in this node's log.
+    appended_at: Option<(u64, u64)>,
+    waiters: Waiters,
+    /// Spans of the proposals in the entry, in the same order.
+    spans: Vec<Span>,
+}
+
+/// In-flight entries by batch id.
+type Pending = HashMap<u64, InFlight>;
+
+/// A raft node's role in its group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RaftRole {
+    Follower,
+    Candidate,
+    PreCandidate,
+    Leader,
+}
+
+impl From<StateRole> for RaftRole {
+    fn from(role: StateRole) -> Self {
+        match role {
+            StateRole::Follower => RaftRole::Follower,
+            StateRole::Candidate => RaftRole::Candidate,
+            StateRole::PreCandidate => RaftRole::PreCandidate,
+            StateRole::Leader => RaftRole::Leader,
+        }
+    }
+}
+
+/// How a worker's raft loop is keeping up, worst first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    #[default]
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RaftHealth {
+    pub status: HealthStatus,
+    /// Why the status is not healthy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// How far applying trails the log, served in `/raft/status`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RaftLag {
+    pub commit_index: u64,
+    /// Committed entries not yet applied.
+    pub apply_lag_entries: u64,
+    /// How long applying has trailed the commit index; 0 while caught up.
+    pub apply_lag_ms: u64,
+    pub last_apply_age_ms: u64,
+    pub last_tick_age_ms: u64,
+    /// Index of a snapshot without data this node refused, which leaves it
+    /// unable to catch up; see [`step_message`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refused_snapshot: Option<u64>,
+    pub health: RaftHealth,
+}
+
+/// Progress counters and role published by the raft loop.
+#[derive(Debug, Default)]
+pub struct RaftStatus {
+    /// The raft node id, as the `worker` label of the queue gauges.
+    worker: String,
+    pub applied_index: AtomicU64,
+    pub proposed_entries: AtomicU64,
+    role: AtomicU8,
+    /// Current leader's id, or 0 when none is known.
+    pub leader_id: AtomicU64,
+    pub term: AtomicU64,
+    pub commit_index: AtomicU64,
+    /// Unix milliseconds of the last apply, or of the loop starting.
+    last_apply_ms: AtomicI64,
+    /// Unix milliseconds of the last tick of the loop.
+    last_tick_ms: AtomicI64,
+    /// Unix milliseconds since applying fell behind the commit index, or 0
+    /// while caught up.
+    behind_since_ms: AtomicI64,
+    /// Proposals waiting in the channel to the loop, as last published.
+    proposal_queue_depth: AtomicU64,
+    /// Most proposals seen waiting at once.
+    proposal_queue_high_water: AtomicU64,
+    /// Index of the last snapshot refused for carrying no data, or 0.
+    pub(crate) refused_snapshot: AtomicU64,
+}
+
+impl RaftStatus {
+    pub fn new(node_id: u64) -> Self {
+        Self { worker: node_id.to_string(), ..Default::default() }
+    }
+
+    pub fn applied_index(&self) -> u64 {
+        self.applied_index.load(Ordering::Relaxed)
+    }
+
+    pub fn proposed_entries(&self) -> u64 {
+        self.proposed_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn role(&self) -> RaftRole {
+        match self.role.load(Ordering::Relaxed) {
+            1 => RaftRole::Candidate,
+            2 => RaftRole::PreCandidate,
+            3 => RaftRole::Leader,
+            _ => RaftRole::Follower,
+        }
+    }
+
+    pub fn leader_id(&self) -> u64 {
+        self.leader_id.load(Ordering::Relaxed)
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term.load(Ordering::Relaxed)
+    }
+
+    pub fn commit_index(&self) -> u64 {
+        self.commit_index.load(Ordering::Relaxed)
+    }
+
+    pub fn proposal_queue_depth(&self) -> u64 {
+        self.proposal_queue_depth.load(Ordering::Relaxed)
+    }
+
+    pub fn proposal_queue_high_water(&self) -> u64 {
+        self.proposal_queue_high_water.load(Ordering::Relaxed)
+    }
+
+    /// Index of a snapshot this node refused for carrying no data, if it
+    /// refused one; its store then misses the writes up to that index.
+    pub fn refused_snapshot(&self) -> Option<u64> {
+        Some(self.refused_snapshot.load(Ordering::Relaxed)).filter(|&index| index > 0)
+    }
+
+    /// Publishes how many proposals wait in the channel to the loop,
+    /// raising the high-water mark when it is the most seen. Called by
+    /// senders after queueing and by the loop after taking a batch.
+    pub fn publish_queue_depth(&self, depth: usize) {
+        let depth = depth as u64;
+        self.proposal_queue_depth.store(depth, Ordering::Relaxed);
+        let high_water = self.proposal_queue_high_water.fetch_max(depth, Ordering::Relaxed).max(depth);
+        PROPOSAL_QUEUE_DEPTH.with_label_values(&[&self.worker]).set(depth as i64);
+        PROPOSAL_QUEUE_HIGH_WATER.with_label_values(&[&self.worker]).set(high_water as i64);
+    }
+
+    /// Apply lag and loop liveness as of now.
+    pub fn lag(&self, limits: &RaftHealthConfig) -> RaftLag {
+        let now = chrono::Utc::now().timestamp_millis();
+        // A status no loop has started reports no age rather than decades
+        let age = |at: &AtomicI64| match at.load(Ordering::Relaxed) {
+            0 => 0,
+            at => now.saturating_sub(at).max(0) as u64,
+        };
+        let behind_since = self.behind_since_ms.load(Ordering::Relaxed);
+        let mut lag = RaftLag {
+            commit_index: self.commit_index(),
+            apply_lag_entries: self.commit_index().saturating_sub(self.applied_index()),
+            apply_lag_ms: if behind_since == 0 { 0 } else { now.saturating_sub(behind_since).max(0) as u64 },
+            last_apply_age_ms: age(&self.last_apply_ms),
+            last_tick_age_ms: age(&self.last_tick_ms),
+            refused_snapshot: self.refused_snapshot(),
+            health: RaftHealth::default(),
+        };
+        lag.health = lag.evaluate(limits);
+        lag
+    }
+
+    /// Publishes the commit index and the start of any apply lag, before
+    /// the newly committed entries are applied.
+    fn publish_commit(&self, node: &RaftNode) {
+        let commit = node.node.raft.raft_log.committed;
+        self.commit_index.store(commit, Ordering::Relaxed);
+        if commit > self.applied_index() && self.behind_since_ms.load(Ordering::Relaxed) == 0 {
+            self.behind_since_ms.store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+        }
+        RAFT_COMMIT_INDEX.set(commit as i64);
+        RAFT_APPLY_LAG_ENTRIES.set(commit.saturating_sub(self.applied_index()) as i64);
+    }
+
+    fn publish_applied(&self, index: u64) {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.applied_index.store(index, Ordering::Relaxed);
+        self.last_apply_ms.store(now, Ordering::Relaxed);
+        if index >= self.commit_index() {
+            self.behind_since_ms.store(0, Ordering::Relaxed);
+        }
+        RAFT_APPLIED_INDEX.set(index as i64);
+        RAFT_APPLY_LAG_ENTRIES.set(self.commit_index().saturating_sub(index) as i64);
+        RAFT_LAST_APPLY_TIMESTAMP.set(now as f64 / 1000.0);
+    }
+
+    fn publish_tick(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.last_tick_ms.store(now, Ordering::Relaxed);
+        RAFT_LAST_TICK_TIMESTAMP.set(now as f64 / 1000.0);
+    }
+
+    fn publish_role(&self, node: &RaftNode) {
+        let raft = &node.node.raft;
+        let role = match RaftRole::from(raft.state) {
+            RaftRole::Follower => 0,
+            RaftRole::Candidate => 1,
+            RaftRole::PreCandidate => 2,
+            RaftRole::Leader => 3,
+        };
+        self.role.store(role, Ordering::Relaxed);
+        self.leader_id.store(raft.leader_id, Ordering::Relaxed);
+        self.term.store(raft.term, Ordering::Relaxed);
+    }
+}
+
+impl RaftLag {
+    /// Unhealthy once the loop has not ticked for `max_tick_gap`; degraded
+    /// while applying trails the commit index by more than either limit.
+    fn evaluate(&self, limits: &RaftHealthConfig) -> RaftHealth {
+        let (status, reason) = if self.last_tick_age_ms > limits.max_tick_gap.as_millis() as u64 {
+            (HealthStatus::Unhealthy, format!("raft loop has not ticked for {}ms", self.last_tick_age_ms))
+        } else if let Some(index) = self.refused_snapshot {
+            (HealthStatus::Degraded, format!("refused a snapshot at index {} without data; seed this worker from a copy of a current store", index))
+        } else if self.apply_lag_entries > limits.max_apply_lag_entries {
+            (HealthStatus::Degraded, format!("{} committed entries are not yet applied", self.apply_lag_entries))
+        } else if self.apply_lag_ms > limits.max_apply_lag.as_millis() as u64 {
+            (HealthStatus::Degraded, format!("applying has trailed the commit index for {}ms", self.apply_lag_ms))
+        } else {
+            return RaftHealth::default();
+        };
+        RaftHealth { status, reason: Some(reason) }
+    }
+}
 
 pub struct RaftNode {
     id: u64,
     node: RawNode<MemStorage>,
+    /// First batch id this node hands out, past any in its restored log.
+    first_batch_id: u64,
 }
 
 impl RaftNode {
-    pub fn new(id: u64, peers: Vec<u64>) -> Result<Self> {
+    /// Creates a node whose log resumes after `applied`, the last index the
+    /// state machine has already applied (0 for a fresh store). `persisted`
+    /// is the hard state and log the node wrote before a restart; entries
+    /// past `applied` are restored, and those already committed come out
+    /// of the first ready to be applied. `learners` receive the log but
+    /// neither vote nor count toward a quorum.
+    pub fn new(id: u64, peers: Vec<u64>, learners: Vec<u64>, applied: u64, persisted: RaftLogState) -> Result<Self> {
         let storage = MemStorage::new();
         let config = Config {
             id,
             election_tick: 10,
             heartbeat_tick: 3,
-            max_size_per_msg: 1024 * 1024,
+            max_size_per_msg: RAFT_MAX_SIZE_PER_MSG,
             max_inflight_msgs: 256,
-            applied: 0,
+            applied,
             max_uncommitted_size: 1024 * 1024,
             ..Default::default()
         };
 
         // Create a logger for Raft
         let logger = Logger::root(slog::Discard, o!());
-        
+
+        let corrupt = |e: prost::DecodeError| RaftMetricsError::Internal(format!("Persisted raft log is corrupt: {}", e));
+        let mut entries = persisted.entries
+            .iter()
+            .map(|(_, entry)| Entry::decode(entry.as_slice()).map_err(corrupt))
+            .collect::<Result<Vec<_>>>()?;
+        let applied_term = entries.iter().find(|entry| entry.index == applied).map(|entry| entry.term);
+        // Only an unbroken run of entries after `applied` can be restored
+        entries.retain(|entry| entry.index > applied);
+        let unbroken = entries.iter().zip(applied + 1..).take_while(|(entry, index)| entry.index == *index).count();
+        entries.truncate(unbroken);
+
         // Initialize storage with configuration
         let s = storage;
-        let peers_clone = peers.clone();
-        s.wl().set_conf_state(ConfState::from((peers_clone, vec![])));
-        
+        let conf_state = ConfState::from((peers.clone(), learners.clone()));
+        if applied > 0 {
+            // Everything up to `applied` is already reflected in the store,
+            // so start from a snapshot at that index instead of an empty log
+            let mut snapshot = Snapshot::default();
+            snapshot.mut_metadata().index = applied;
+            snapshot.mut_metadata().term = applied_term.unwrap_or(1);
+            snapshot.mut_metadata().set_conf_state(conf_state);
+            s.wl().apply_snapshot(snapshot)?;
+        } else {
+            s.wl().set_conf_state(conf_state);
+        }
+        if !entries.is_empty() {
+            s.wl().append(&entries)?;
+        }
+        if let Some(hard_state) = persisted.hard_state {
+            let mut hard_state = HardState::decode(hard_state.as_slice()).map_err(corrupt)?;
+            let last = entries.last().map_or(applied, |entry| entry.index);
+            hard_state.commit = hard_state.commit.clamp(applied, last);
+            hard_state.term = hard_state.term.max(s.rl().hard_state().term);
+            s.wl().set_hardstate(hard_state);
+        }
+
+        let first_batch_id = entries
+            .iter()
+            .filter_map(|entry| batch_id(&entry.context))
+            .filter(|batch| batch >> 48 == id)
+            .max()
+            .map_or(id << 48, |batch| batch + 1);
         let node = RawNode::new(&config, s, &logger)?;
-        info!("Initialized Raft node {} with peers {:?}", id, peers);
+        info!("Initialized Raft node {} with peers {:?} and learners {:?}", id, peers, learners);
+        if !entries.is_empty() {
+            info!("Raft node {} restored {} log entries after applied index {}", id, entries.len(), applied);
+        }
 
-        Ok(Self { id, node })
+        Ok(Self { id, node, first_batch_id })
     }
 
     pub fn get_id(&self) -> u64 {
@@ -58,6 +405,10 @@ impl RaftNode {
         self.node.tick();
     }
 
+    pub fn campaign(&mut self) -> Result<()> {
+        Ok(self.node.campaign()?)
+    }
+
     pub fn has_ready(&self) -> bool {
         self.node.has_ready()
     }
@@ -66,26 +417,124 @@ impl RaftNode {
         self.node.ready()
     }
 
-    pub fn advance(&mut self, ready: Ready) {
-        self.node.advance(ready);
+    pub fn advance(&mut self, ready: Ready) -> LightReady {
+        self.node.advance(ready)
+    }
+
+    pub fn advance_apply(&mut self) {
+        self.node.advance_apply();
+    }
+
+    pub fn storage(&self) -> &MemStorage {
+        self.node.store()
     }
 
     pub fn propose(&mut self, data: Vec<u8>) -> Result<()> {
-        self.node.propose(vec![], data).map_err(|e| {
+        self.propose_with_context(vec![], data)
+    }
+
+    /// Proposes an entry, recording how long the append took in
+    /// [`RAFT_PROPOSAL_DURATION`] and its outcome in [`RAFT_PROPOSALS_TOTAL`].
+    pub fn propose_with_context(&mut self, context: Vec<u8>, data: Vec<u8>) -> Result<()> {
+        let timer = RAFT_PROPOSAL_DURATION.start_timer();
+        let proposed = self.node.propose(context, data);
+        timer.observe_duration();
+        let outcome = if proposed.is_ok() { "ok" } else { "error" };
+        RAFT_PROPOSALS_TOTAL.with_label_values(&[outcome]).inc();
+        proposed.map_err(|e| {
             warn!("Failed to propose data: {}", e);
             RaftMetricsError::Internal(format!("Failed to propose data: {}", e))
         })
     }
 }
 
-pub async fn run_raft_node(mut node: RaftNode) {
-    let tick_interval = Duration::from_millis(100);
-    let mut tick_timer = tokio::time::interval(tick_interval);
+/// Creates a raft node, elects it immediately when it is the only voter,
+/// and spawns its event loop. Returns the proposal sender and the status
+/// published by the loop.
+pub fn start_raft_node(
+    id: u64,
+    peers: Vec<u64>,
+    registry: Arc<MetricsRegistry>,
+    config: RaftConfig,
+) -> Result<(mpsc::Sender<Proposal>, Arc<RaftStatus>)> {
+    spawn_raft_node(id, peers, vec![], registry, config, None)
+}
+
+/// Like [`start_raft_node`], for a node whose peers run in this process and
+/// reach it over `network`. The group elects a leader on its own from
+/// `peers`; `learners` only follow the log, and `id` may be one of them.
+pub fn start_networked_raft_node(
+    id: u64,
+    peers: Vec<u64>,
+    learners: Vec<u64>,
+    registry: Arc<MetricsRegistry>,
+    config: RaftConfig,
+    network: LocalNetwork,
+) -> Result<(mpsc::Sender<Proposal>, Arc<RaftStatus>)> {
+    spawn_raft_node(id, peers, learners, registry, config, Some(network))
+}
+
+fn spawn_raft_node(
+    id: u64,
+    peers: Vec<u64>,
+    learners: Vec<u64>,
+    registry: Arc<MetricsRegistry>,
+    config: RaftConfig,
+    network: Option<LocalNetwork>,
+) -> Result<(mpsc::Sender<Proposal>, Arc<RaftStatus>)> {
+    let single_voter = peers == [id];
+    let applied = registry.applied_index();
+    let mut node = RaftNode::new(id, peers, learners, applied, registry.raft_log(applied)?)?;
+    if single_voter {
+        node.campaign()?;
+    }
+
+    let (proposal_tx, proposal_rx) = mpsc::channel(config.proposal_channel_cap.max(1));
+    let status = Arc::new(RaftStatus::new(id));
+    status.commit_index.store(node.node.raft.raft_log.committed, Ordering::Relaxed);
+    status.publish_applied(applied);
+    status.publish_tick();
+    status.publish_role(&node);
+    tokio::spawn(run_raft_node(node, registry, proposal_rx, config, status.clone(), network));
+
+    Ok((proposal_tx, status))
+}
+
+pub async fn run_raft_node(
+    mut node: RaftNode,
+    registry: Arc<MetricsRegistry>,
+    mut proposals: mpsc::Receiver<Proposal>,
+    config: RaftConfig,
+    status: Arc<RaftStatus>,
+    network: Option<LocalNetwork>,
+) {
+    let mut next_tick = tokio::time::Instant::now() + config.jittered_tick(random_unit());
+    let mut pending: Pending = HashMap::new();
+    // Batch ids carry the node id in their top bits, so an entry forwarded
+    // by a peer never resolves this node's proposals
+    let mut next_batch_id: u64 = node.first_batch_id;
+    let mut inbox = network.as_ref().map(|network| network.connect(node.get_id()));
 
     loop {
         tokio::select! {
-            _ = tick_timer.tick() => {
+            _ = tokio::time::sleep_until(next_tick) => {
                 node.tick();
+                status.publish_tick();
+                next_tick = tokio::time::Instant::now() + config.jittered_tick(random_unit());
+            }
+            message = next_message(&mut inbox) => {
+                if let Some(message) = message {
+                    step_message(&mut node, message, &registry, &status);
+                }
+            }
+            proposal = proposals.recv() => {
+                let Some(first) = proposal else {
+                    info!("Proposal channel closed, shutting down Raft node {}", node.get_id());
+                    break;
+                };
+                let batch = collect_batch(first, &mut proposals, &config).await;
+                status.publish_queue_depth(proposals.len());
+                propose_batch(&mut node, batch, config.codec, &mut pending, &mut next_batch_id, &status);
             }
             _ = tokio::signal::ctrl_c() => {
                 warn!("Received ctrl-c signal, shutting down Raft node {}", node.get_id());
@@ -94,21 +543,964 @@ pub async fn run_raft_node(mut node: RaftNode) {
         }
 
         if node.has_ready() {
-            let ready = node.ready();
-            
-            // Handle messages
-            for msg in ready.messages() {
-                info!("Processing Raft message: {:?}", msg);
-            }
-            
-            // Handle committed entries
-            for entry in ready.committed_entries() {
-                if !entry.data.is_empty() {
-                    info!("Applying committed entry: {:?}", entry);
+            handle_ready(&mut node, &registry, &mut pending, &status, network.as_ref()).await;
+            status.publish_role(&node);
+        }
+    }
+}
+
+/// A random fraction in [0, 1), drawn from the standard library's
+/// randomly keyed hasher to spare a dependency.
+fn random_unit() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let bits = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The next message from the network, or never when the node has none.
+async fn next_message(inbox: &mut Option<mpsc::UnboundedReceiver<Message>>) -> Option<Message> {
+    match inbox {
+        Some(rx) => {
+            let message = rx.recv().await;
+            if message.is_none() {
+                *inbox = None;
+            }
+            message
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Drains further proposals after `first` until the batch is full by count
+/// or bytes, or the batch window elapses.
+async fn collect_batch(
+    first: Proposal,
+    proposals: &mut mpsc::Receiver<Proposal>,
+    config: &RaftConfig,
+) -> Vec<Proposal> {
+    let mut bytes = MetricsRegistry::encoded_len(&first.operation, config.codec);
+    let mut batch = vec![first];
+    let deadline = tokio::time::Instant::now() + config.batch_window;
+
+    while batch.len() < config.max_batch_size && bytes < config.max_batch_bytes {
+        match tokio::time::timeout_at(deadline, proposals.recv()).await {
+            Ok(Some(proposal)) => {
+                bytes += MetricsRegistry::encoded_len(&proposal.operation, config.codec);
+                batch.push(proposal);
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    batch
+}
+
+/// Proposes `batch` as one or more log entries, splitting so no entry
+/// exceeds the raft message size limit.
+fn propose_batch(
+    node: &mut RaftNode,
+    batch: Vec<Proposal>,
+    codec: RaftCodec,
+    pending: &mut Pending,
+    next_batch_id: &mut u64,
+    status: &RaftStatus,
+) {
+    let limit = (RAFT_MAX_SIZE_PER_MSG / 2) as usize;
+    let mut chunk: Vec<Proposal> = Vec::new();
+    let mut chunk_bytes = 0;
+
+    for proposal in batch {
+        let len = MetricsRegistry::encoded_len(&proposal.operation, codec);
+        if !chunk.is_empty() && chunk_bytes + len > limit {
+            propose_chunk(node, std::mem::take(&mut chunk), codec, pending, next_batch_id, status);
+            chunk_bytes = 0;
+        }
+        chunk_bytes += len;
+        chunk.push(proposal);
+    }
+    if !chunk.is_empty() {
+        propose_chunk(node, chunk, codec, pending, next_batch_id, status);
+    }
+}
+
+fn propose_chunk(
+    node: &mut RaftNode,
+    chunk: Vec<Proposal>,
+    codec: RaftCodec,
+    pending: &mut Pending,
+    next_batch_id: &mut u64,
+    status: &RaftStatus,
+) {
+    let mut spans = Vec::with_capacity(chunk.len());
+    let (operations, callbacks): (Vec<_>, Waiters) = chunk
+        .into_iter()
+        .map(|p| {
+            spans.push(p.span);
+            (p.operation, (p.respond_to, p.appended))
+        })
+        .unzip();
+
+    let data = match MetricsRegistry::serialize_batch(&operations, codec) {
+        Ok(data) => data,
+        Err(e) => {
+            fail_all(callbacks, &e.to_string());
+            return;
+        }
+    };
+
+    let batch_id = *next_batch_id;
+    *next_batch_id += 1;
+    let term = node.node.raft.term;
+    let as_leader = node.node.raft.state == StateRole::Leader;
+
+    match node.propose_with_context(batch_id.to_be_bytes().to_vec(), data) {
+        Ok(()) => {
+            RAFT_BATCH_SIZE.observe(operations.len() as f64);
+            status.proposed_entries.fetch_add(1, Ordering::Relaxed);
+            pending.insert(batch_id, InFlight { term, as_leader, appended_at: None, waiters: callbacks, spans });
+        }
+        Err(e) => fail_all(callbacks, &e.to_string()),
+    }
+}
+
+fn fail_all(callbacks: Waiters, reason: &str) {
+    for (callback, _) in callbacks {
+        let _ = callback.send(Err(RaftMetricsError::Internal(reason.to_string())));
+    }
+}
+
+/// Steps a message from a peer into `node`, except a snapshot that carries
+/// no data past the index the store has applied. A leader restarted over
+/// its store starts from such a snapshot in place of the entries it had
+/// already applied and compacted, and sends it to a peer that needs them;
+/// installing it would move raft past writes this store never received,
+/// and a standby promoted afterwards would serve without them. The node
+/// stays behind instead, reporting the snapshot as refused, until it is
+/// given a copy of a current store.
+fn step_message(node: &mut RaftNode, message: Message, registry: &MetricsRegistry, status: &RaftStatus) {
+    if message.get_msg_type() == MessageType::MsgSnapshot {
+        let snapshot = message.get_snapshot();
+        let index = snapshot.get_metadata().index;
+        if snapshot.data.is_empty() && index > registry.applied_index() {
+            if status.refused_snapshot.swap(index, Ordering::Relaxed) != index {
+                error!(
+                    "Raft node {} refused a snapshot at index {} from node {} without data; its store has only applied up to {}",
+                    node.get_id(), index, message.from, registry.applied_index(),
+                );
+            }
+            return;
+        }
+    }
+    let _ = node.step(message);
+}
+
+fn send_messages(network: Option<&LocalNetwork>, messages: Vec<Message>) {
+    for msg in messages {
+        match network {
+            Some(network) => network.send(msg),
+            None => debug!("Dropping Raft message without transport: {:?}", msg.get_msg_type()),
+        }
+    }
+}
+
+async fn handle_ready(
+    node: &mut RaftNode,
+    registry: &MetricsRegistry,
+    pending: &mut Pending,
+    status: &RaftStatus,
+    network: Option<&LocalNetwork>,
+) {
+    let mut ready = node.ready();
+    // A new role or term may mean entries in flight will never commit
+    let leadership_changed = ready.ss().is_some() || ready.hs().is_some();
+    status.publish_commit(node);
+    send_messages(network, ready.take_messages());
+
+    if !ready.snapshot().is_empty() {
+        if let Err(e) = node.storage().wl().apply_snapshot(ready.snapshot().clone()) {
+            warn!("Failed to apply snapshot: {}", e);
+        }
+    }
+
+    apply_committed(ready.take_committed_entries(), node, registry, pending, status).await;
+
+    let mut appended = Vec::new();
+    if !ready.entries().is_empty() {
+        match node.storage().wl().append(ready.entries()) {
+            Ok(()) => appended.extend(ready.entries().iter().filter_map(|entry| Some((batch_id(&entry.context)?, entry.index, entry.term)))),
+            Err(e) => warn!("Failed to append entries: {}", e),
+        }
+        let entries: Vec<_> = ready.entries().iter().map(|entry| (entry.index, entry.encode_to_vec())).collect();
+        if let Err(e) = registry.append_raft_entries(&entries).await {
+            warn!("Failed to persist raft entries: {}", e);
+        }
+    }
+    if let Some(hs) = ready.hs() {
+        node.storage().wl().set_hardstate(hs.clone());
+        persist_hard_state(node, registry).await;
+    }
+    send_messages(network, ready.take_persisted_messages());
+
+    let mut light = node.advance(ready);
+    if let Some(commit) = light.commit_index() {
+        node.storage().wl().mut_hard_state().set_commit(commit);
+        persist_hard_state(node, registry).await;
+    }
+    status.publish_commit(node);
+    send_messages(network, light.take_messages());
+    apply_committed(light.take_committed_entries(), node, registry, pending, status).await;
+    node.advance_apply();
+
+    // Entries applied above already answered their callers
+    for (id, index, term) in appended {
+        let Some(in_flight) = pending.get_mut(&id) else { continue };
+        in_flight.appended_at = Some((index, term));
+        for (_, appended) in &mut in_flight.waiters {
+            if let Some(appended) = appended.take() {
+                let _ = appended.send(());
+            }
+        }
+    }
+    if leadership_changed {
+        fence_in_flight(node, pending);
+    }
+    fail_overwritten(node, pending);
+}
+
+/// Fails entries this node proposed as leader under a leadership it no
+/// longer holds before they reached its log: nothing else has them, so
+/// they can never commit and their callers are told to retry now instead
+/// of waiting out their deadline. Entries already in the log stay pending,
+/// as a later leader may still commit them, until they apply or are
+/// overwritten; see [`fail_overwritten`]. So do entries it forwarded while
+/// anyone waits on them, as a later leader may still append them.
+fn fence_in_flight(node: &RaftNode, pending: &mut Pending) {
+    let raft = &node.node.raft;
+    let leading = raft.state == StateRole::Leader;
+    let leader_id = (raft.leader_id != 0).then_some(raft.leader_id);
+    let mut failed = 0;
+    pending.retain(|_, in_flight| {
+        if !in_flight.as_leader || in_flight.appended_at.is_some() {
+            return in_flight.waiters.iter().any(|(callback, _)| !callback.is_closed());
+        }
+        if in_flight.term == raft.term && leading {
+            return true;
+        }
+        for (callback, _) in in_flight.waiters.drain(..) {
+            let _ = callback.send(Err(RaftMetricsError::LeadershipLost { term: raft.term, leader_id }));
+        }
+        failed += 1;
+        false
+    });
+    if failed > 0 {
+        warn!(
+            "Raft node {} failed {} in-flight entries after losing leadership (term {}, leader {:?})",
+            node.get_id(), failed, raft.term, leader_id,
+        );
+    }
+}
+
+/// Fails entries this node appended whose place in its log a newer
+/// leader has since taken, or cut off. Raft never brings an overwritten
+/// entry back, so their callers can retry safely. Entries already
+/// compacted out of the log are left to apply.
+fn fail_overwritten(node: &RaftNode, pending: &mut Pending) {
+    let raft_log = &node.node.raft.raft_log;
+    let leader_id = (node.node.raft.leader_id != 0).then_some(node.node.raft.leader_id);
+    let first_index = raft_log.first_index();
+    let mut failed = 0;
+    pending.retain(|_, in_flight| {
+        let Some((index, term)) = in_flight.appended_at else { return true };
+        if index < first_index || raft_log.term(index).is_ok_and(|now| now == term) {
+            return true;
+        }
+        for (callback, _) in in_flight.waiters.drain(..) {
+            let _ = callback.send(Err(RaftMetricsError::LeadershipLost { term: node.node.raft.term, leader_id }));
+        }
+        failed += 1;
+        false
+    });
+    if failed > 0 {
+        warn!("Raft node {} failed {} in-flight entries a newer leader overwrote", node.get_id(), failed);
+    }
+}
+
+async fn apply_committed(
+    entries: Vec<Entry>,
+    node: &mut RaftNode,
+    registry: &MetricsRegistry,
+    pending: &mut Pending,
+    status: &RaftStatus,
+) {
+    for entry in entries {
+        if !entry.data.is_empty() && entry.get_entry_type() == EntryType::EntryNormal {
+            let in_flight = batch_id(&entry.context).and_then(|id| pending.remove(&id));
+            let span = apply_span(&entry, in_flight.as_ref(), registry);
+            let applied = apply_until_settled(&entry, node, registry, pending, status).instrument(span.clone()).await;
+            if let Ok(results) = &applied {
+                span.record("batch.size", results.len());
+            }
+            // Advance the applied index before acking so a writer's version
+            // token is already readable when the write returns.
+            advance_applied(entry.index, registry, status).await;
+            if let Err(e) = &applied {
+                warn!("Failed to apply entry {}: {}", entry.index, e);
+            }
+            // The batch id names this node's own proposal, so its callers hear
+            // how it applied even when it committed under a later term than
+            // it was proposed in; failing it would have their retry write it
+            // twice
+            let Some(in_flight) = in_flight else { continue };
+            match applied {
+                Ok(results) => {
+                    for ((callback, _), result) in in_flight.waiters.into_iter().zip(results) {
+                        let _ = callback.send(result);
+                    }
+                }
+                Err(e) => fail_all(in_flight.waiters, &e.to_string()),
+            }
+        } else {
+            advance_applied(entry.index, registry, status).await;
+        }
+    }
+}
+
+/// Span for applying `entry`: a child of the first proposal in it that
+/// this node made, following from the others, which may belong to other
+/// traces. Entries proposed elsewhere start a trace of their own.
+fn apply_span(entry: &Entry, in_flight: Option<&InFlight>, registry: &MetricsRegistry) -> Span {
+    let spans = in_flight.map_or(&[][..], |in_flight| &in_flight.spans);
+    let span = tracing::info_span!(
+        parent: spans.first().and_then(Span::id),
+        "raft.apply",
+        raft.index = entry.index,
+        batch.size = tracing::field::Empty,
+        storage.backend = registry.backend_name(),
+    );
+    for proposal in spans.iter().skip(1) {
+        span.follows_from(proposal);
+    }
+    span
+}
+
+/// Applies `entry`, applying it again with backoff while a local failure
+/// halts it: nothing after it may apply until it has, or this replica's
+/// state would part from the others'. A leader that keeps failing steps
+/// down; see [`step_down`].
+async fn apply_until_settled(
+    entry: &Entry,
+    node: &mut RaftNode,
+    registry: &MetricsRegistry,
+    pending: &mut Pending,
+    status: &RaftStatus,
+) -> Result<Vec<Result<()>>> {
+    let mut backoff = APPLY_RETRY_BACKOFF;
+    let mut failures = 0;
+    loop {
+        match registry.apply_raft_batch(entry.index, &entry.data).await {
+            Err(e) if halts_apply(&e) => {
+                warn!("Apply halted at entry {}, retrying in {:?}: {}", entry.index, backoff, e);
+                failures += 1;
+                if failures == STEP_DOWN_AFTER_APPLY_FAILURES {
+                    step_down(node, pending, status);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(APPLY_RETRY_MAX_BACKOFF);
+            }
+            applied => return applied,
+        }
+    }
+}
+
+/// Gives up leadership while this node cannot apply what its group
+/// commits, so it stops accepting writes it cannot store and a replica
+/// whose store works can be elected. Until the entry applies the loop
+/// neither ticks nor votes; once it does, the node campaigns again like
+/// any follower whose leader has gone quiet.
+fn step_down(node: &mut RaftNode, pending: &mut Pending, status: &RaftStatus) {
+    let raft = &mut node.node.raft;
+    if raft.state != StateRole::Leader {
+        return;
+    }
+    let term = raft.term;
+    raft.become_follower(term, INVALID_ID);
+    RAFT_FORCED_STEPDOWNS_TOTAL.inc();
+    warn!("Raft node {} stepped down in term {} after repeated apply failures", node.get_id(), term);
+    status.publish_role(node);
+    fence_in_flight(node, pending);
+}
+
+async fn persist_hard_state(node: &RaftNode, registry: &MetricsRegistry) {
+    let hard_state = node.storage().rl().hard_state().encode_to_vec();
+    if let Err(e) = registry.set_raft_hard_state(&hard_state).await {
+        warn!("Failed to persist raft hard state: {}", e);
+    }
+}
+
+async fn advance_applied(index: u64, registry: &MetricsRegistry, status: &RaftStatus) {
+    if let Err(e) = registry.set_applied_index(index).await {
+        warn!("Failed to persist applied index {}: {}", index, e);
+    }
+    // The entry at the applied index stays, as a restart reads its term
+    if index.is_multiple_of(LOG_COMPACTION_INTERVAL) {
+        if let Err(e) = registry.compact_raft_log(index).await {
+            warn!("Failed to compact the persisted raft log: {}", e);
+        }
+    }
+    status.publish_applied(index);
+}
+
+fn batch_id(context: &[u8]) -> Option<u64> {
+    context.try_into().ok().map(u64::from_be_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Labels;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_proposals_share_log_entries() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let config = RaftConfig { batch_window: Duration::from_millis(20), ..RaftConfig::default() };
+        let (proposal_tx, status) = start_raft_node(1, vec![1], registry.clone(), config).unwrap();
+
+        let mut waiters = Vec::new();
+        for i in 0..100 {
+            let (proposal, rx) = Proposal::new(MetricOperation::record(format!("metric_{}", i % 10), i as f64, 1_000 + i, Labels::new()));
+            proposal_tx.send(proposal).await.unwrap();
+            waiters.push(rx);
+        }
+
+        for rx in waiters {
+            rx.await.unwrap().unwrap();
+        }
+
+        assert!(status.proposed_entries() < 20, "expected batching, got {} entries", status.proposed_entries());
+        let aggregate = registry.get_metric_aggregate("metric_3").await.unwrap().unwrap();
+        assert_eq!(aggregate.count, 10);
+    }
+
+    #[tokio::test]
+    async fn test_entry_committed_under_a_later_term_reports_its_apply() {
+        let registry = MetricsRegistry::new().unwrap();
+        let mut node = RaftNode::new(1, vec![1], vec![], 0, RaftLogState::default()).unwrap();
+        let status = RaftStatus::new(1);
+        let operation = MetricOperation::record("cpu", 1.0, 100, Labels::new());
+        let (proposal, rx) = Proposal::new(operation.clone());
+        let in_flight = InFlight { term: 1, as_leader: false, appended_at: None, waiters: vec![(proposal.respond_to, None)], spans: Vec::new() };
+        let mut pending = Pending::from([(7, in_flight)]);
+
+        // Forwarded in term 1, appended by a leader of term 2
+        let entry = Entry {
+            index: 1,
+            term: 2,
+            context: 7u64.to_be_bytes().to_vec(),
+            data: RaftCodec::default().encode(&[operation]).unwrap(),
+            ..Default::default()
+        };
+        apply_committed(vec![entry], &mut node, &registry, &mut pending, &status).await;
+        rx.await.unwrap().unwrap();
+        assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(1.0));
+    }
+
+    /// A node driven by hand rather than by its event loop.
+    struct ManualNode {
+        node: RaftNode,
+        registry: MetricsRegistry,
+        pending: Pending,
+        status: RaftStatus,
+        inbox: mpsc::UnboundedReceiver<Message>,
+    }
+
+    /// Steps every queued message into its node and handles every ready
+    /// until the group is quiet, returning the messages `hold` kept back.
+    async fn settle(nodes: &mut [ManualNode], network: &LocalNetwork, hold: impl Fn(&Message) -> bool) -> Vec<Message> {
+        let mut held = Vec::new();
+        loop {
+            let mut progressed = false;
+            for n in nodes.iter_mut() {
+                while let Ok(msg) = n.inbox.try_recv() {
+                    if hold(&msg) {
+                        held.push(msg);
+                    } else {
+                        step_message(&mut n.node, msg, &n.registry, &n.status);
+                        progressed = true;
+                    }
+                }
+                if n.node.has_ready() {
+                    handle_ready(&mut n.node, &n.registry, &mut n.pending, &n.status, Some(network)).await;
+                    progressed = true;
+                }
+            }
+            if !progressed {
+                return held;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_entry_survives_a_term_change_and_reports_its_apply() {
+        let network = LocalNetwork::new();
+        let mut nodes: Vec<_> = (1..=3)
+            .map(|id| ManualNode {
+                node: RaftNode::new(id, vec![1, 2, 3], vec![], 0, RaftLogState::default()).unwrap(),
+                registry: MetricsRegistry::new().unwrap(),
+                pending: Pending::new(),
+                status: RaftStatus::new(id),
+                inbox: network.connect(id),
+            })
+            .collect();
+        nodes[0].node.campaign().unwrap();
+        settle(&mut nodes, &network, |_| false).await;
+        assert_eq!(nodes[1].node.node.raft.leader_id, 1);
+
+        // Node 2 forwards a write to the leader of term 1, which only gets
+        // it once node 3 leads term 2
+        let (proposal, rx) = Proposal::new(MetricOperation::record("cpu", 1.0, 100, Labels::new()));
+        let follower = &mut nodes[1];
+        propose_batch(&mut follower.node, vec![proposal], RaftCodec::default(), &mut follower.pending, &mut 1, &follower.status);
+        let held = settle(&mut nodes, &network, |msg| msg.get_msg_type() == MessageType::MsgPropose).await;
+        assert_eq!(held.len(), 1);
+        nodes[2].node.campaign().unwrap();
+        settle(&mut nodes, &network, |_| false).await;
+        assert_eq!((nodes[1].node.node.raft.term, nodes[1].node.node.raft.leader_id), (2, 3));
+        assert_eq!(nodes[1].pending.len(), 1);
+
+        // Node 1 now follows node 3 and passes the write on
+        for msg in held {
+            network.send(msg);
+        }
+        settle(&mut nodes, &network, |_| false).await;
+        rx.await.unwrap().unwrap();
+        for n in &nodes {
+            assert_eq!(n.registry.get_metric("cpu").await.unwrap(), Some(1.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deposed_leader_waits_for_entries_in_its_log_until_they_commit_or_are_overwritten() {
+        let network = LocalNetwork::new();
+        let mut nodes: Vec<_> = (1..=3)
+            .map(|id| ManualNode {
+                node: RaftNode::new(id, vec![1, 2, 3], vec![], 0, RaftLogState::default()).unwrap(),
+                registry: MetricsRegistry::new().unwrap(),
+                pending: Pending::new(),
+                status: RaftStatus::new(id),
+                inbox: network.connect(id),
+            })
+            .collect();
+        nodes[0].node.campaign().unwrap();
+        settle(&mut nodes, &network, |_| false).await;
+        let mut next_batch_id = 1;
+        let mut propose = |nodes: &mut [ManualNode], name: &str| {
+            let (proposal, rx) = Proposal::new(MetricOperation::record(name, 1.0, 100, Labels::new()));
+            let leader = &mut nodes[0];
+            propose_batch(&mut leader.node, vec![proposal], RaftCodec::default(), &mut leader.pending, &mut next_batch_id, &leader.status);
+            rx
+        };
+
+        // The followers have the entry, but the leader never hears so
+        // before node 2 takes over; node 2 commits it in its own term
+        let replicated = propose(&mut nodes, "replicated");
+        settle(&mut nodes, &network, |msg| msg.get_msg_type() == MessageType::MsgAppendResponse && msg.to == 1).await;
+        nodes[1].node.campaign().unwrap();
+        settle(&mut nodes, &network, |_| false).await;
+        assert_eq!(nodes[0].node.node.raft.leader_id, 2);
+        replicated.await.unwrap().unwrap();
+
+        // Node 1 leads again but its entry reaches no one before node 3
+        // takes over, whose own entry takes its place
+        nodes[0].node.campaign().unwrap();
+        settle(&mut nodes, &network, |_| false).await;
+        let overwritten = propose(&mut nodes, "overwritten");
+        settle(&mut nodes, &network, |msg| msg.from == 1).await;
+        assert_eq!(nodes[0].pending.len(), 1);
+        nodes[2].node.campaign().unwrap();
+        settle(&mut nodes, &network, |msg| msg.from == 1 && msg.get_msg_type() == MessageType::MsgRequestVoteResponse).await;
+        settle(&mut nodes, &network, |_| false).await;
+        assert_eq!(nodes[0].node.node.raft.leader_id, 3);
+        assert!(matches!(overwritten.await.unwrap(), Err(RaftMetricsError::LeadershipLost { .. })));
+        for n in &nodes {
+            assert_eq!(n.registry.get_metric("replicated").await.unwrap(), Some(1.0));
+            assert_eq!(n.registry.get_metric("overwritten").await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_standby_refuses_the_snapshot_of_a_restarted_leader() {
+        // The leader's store applied entries up to 5 before a restart that
+        // left none of them in its log
+        let leader = MetricsRegistry::new().unwrap();
+        leader.record_metric_with_timestamp("cpu", 1.0, 100).await.unwrap();
+        leader.set_applied_index(5).await.unwrap();
+
+        let network = LocalNetwork::new();
+        let mut nodes = vec![
+            ManualNode {
+                node: RaftNode::new(1, vec![1], vec![2], 5, RaftLogState::default()).unwrap(),
+                registry: leader,
+                pending: Pending::new(),
+                status: RaftStatus::new(1),
+                inbox: network.connect(1),
+            },
+            ManualNode {
+                node: RaftNode::new(2, vec![1], vec![2], 0, RaftLogState::default()).unwrap(),
+                registry: MetricsRegistry::new().unwrap(),
+                pending: Pending::new(),
+                status: RaftStatus::new(2),
+                inbox: network.connect(2),
+            },
+        ];
+        nodes[0].node.campaign().unwrap();
+        settle(&mut nodes, &network, |_| false).await;
+
+        // The standby is offered the leader's snapshot but stays empty and behind
+        let standby = &nodes[1];
+        assert!(standby.status.refused_snapshot().is_some_and(|index| index >= 5), "{:?}", standby.status.refused_snapshot());
+        assert_eq!(standby.node.storage().rl().hard_state().commit, 0);
+        assert_eq!(standby.registry.applied_index(), 0);
+        assert_eq!(standby.registry.get_metric("cpu").await.unwrap(), None);
+        let health = standby.status.lag(&RaftHealthConfig::default()).health;
+        assert_eq!(health.status, HealthStatus::Degraded, "{:?}", health);
+        assert!(nodes[0].status.refused_snapshot().is_none());
+    }
+
+    #[test]
+    fn test_tick_interval_varies_within_the_jitter() {
+        let config = RaftConfig::default();
+        let ticks: Vec<Duration> = (0..200).map(|_| config.jittered_tick(random_unit())).collect();
+        let (low, high) = (config.tick_interval - config.tick_jitter, config.tick_interval + config.tick_jitter);
+        assert!(ticks.iter().all(|tick| (low..=high).contains(tick)), "{:?}", ticks);
+        let distinct: std::collections::HashSet<_> = ticks.iter().collect();
+        assert!(distinct.len() > 10, "ticks barely vary: {:?}", distinct);
+    }
+
+    #[test]
+    fn test_election_timeouts_are_randomized() {
+        let timeouts: std::collections::HashSet<usize> = (0..50)
+            .map(|_| RaftNode::new(1, vec![1, 2, 3], vec![], 0, RaftLogState::default()).unwrap())
+            .map(|node| node.node.raft.randomized_election_timeout())
+            .collect();
+        // raft draws each timeout from [election_tick, 2 * election_tick)
+        assert!(timeouts.iter().all(|timeout| (10..20).contains(timeout)), "{:?}", timeouts);
+        assert!(timeouts.len() > 1, "every node drew {:?}", timeouts);
+    }
+
+    #[tokio::test]
+    async fn test_proposals_are_timed_and_counted() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let (proposal_tx, _) = start_raft_node(1, vec![1], registry, RaftConfig::default()).unwrap();
+        let samples = RAFT_PROPOSAL_DURATION.get_sample_count();
+        let proposed = RAFT_PROPOSALS_TOTAL.with_label_values(&["ok"]).get();
+
+        let (proposal, rx) = Proposal::new(MetricOperation::record("timed", 1.0, 1, Labels::new()));
+        proposal_tx.send(proposal).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        // Other tests propose concurrently, so only a lower bound holds
+        assert!(RAFT_PROPOSAL_DURATION.get_sample_count() > samples);
+        assert!(RAFT_PROPOSALS_TOTAL.with_label_values(&["ok"]).get() > proposed);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_batch_is_split() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let (proposal_tx, status) = start_raft_node(1, vec![1], registry.clone(), RaftConfig::default()).unwrap();
+
+        // Each name is ~100KiB, so a handful exceed the per-entry limit
+        let mut waiters = Vec::new();
+        for i in 0..12 {
+            let (proposal, rx) = Proposal::new(MetricOperation::record(format!("{}{}", "x".repeat(100 * 1024), i), 1.0, 1, Labels::new()));
+            proposal_tx.send(proposal).await.unwrap();
+            waiters.push(rx);
+        }
+        for rx in waiters {
+            rx.await.unwrap().unwrap();
+        }
+
+        assert!(status.proposed_entries() >= 3);
+    }
+
+    #[tokio::test]
+    async fn test_restart_resumes_after_persisted_applied_index() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let record = |value: f64| MetricOperation::record("cpu", value, value as i64, Labels::new());
+
+        let (proposal_tx, status) = start_raft_node(1, vec![1], registry.clone(), RaftConfig::default()).unwrap();
+        let (proposal, rx) = Proposal::new(record(1.0));
+        proposal_tx.send(proposal).await.unwrap();
+        rx.await.unwrap().unwrap();
+        let applied = status.applied_index();
+        assert!(applied > 0);
+        assert_eq!(registry.applied_index(), applied);
+        drop(proposal_tx);
+
+        // A new node over the same store continues the log past `applied`
+        let (proposal_tx, status) = start_raft_node(1, vec![1], registry.clone(), RaftConfig::default()).unwrap();
+        assert_eq!(status.applied_index(), applied);
+        let (proposal, rx) = Proposal::new(record(2.0));
+        proposal_tx.send(proposal).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        assert!(status.applied_index() > applied);
+        assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_restarted_node_replays_its_persisted_log() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let record = |value: f64| MetricOperation::record("cpu", value, value as i64, Labels::new());
+
+        let (proposal_tx, status) = start_raft_node(1, vec![1], registry.clone(), RaftConfig::default()).unwrap();
+        for value in [1.0, 2.0, 3.0] {
+            let (proposal, rx) = Proposal::new(record(value));
+            proposal_tx.send(proposal).await.unwrap();
+            rx.await.unwrap().unwrap();
+        }
+        let applied = status.applied_index();
+        drop(proposal_tx);
+
+        // A store holding only the raft log, as after a crash between
+        // committing entries and applying them
+        let restored = Arc::new(MetricsRegistry::new().unwrap());
+        let log = registry.raft_log(0).unwrap();
+        restored.append_raft_entries(&log.entries).await.unwrap();
+        restored.set_raft_hard_state(log.hard_state.as_deref().unwrap()).await.unwrap();
+        assert_eq!(restored.applied_index(), 0);
+
+        let (proposal_tx, status) = start_raft_node(1, vec![1], restored.clone(), RaftConfig::default()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), restored.wait_for_applied(applied)).await.unwrap();
+        let aggregate = restored.get_metric_aggregate("cpu").await.unwrap().unwrap();
+        assert_eq!((aggregate.count, aggregate.total()), (3, 6.0));
+        assert_eq!(restored.get_metric("cpu").await.unwrap(), Some(3.0));
+
+        // New writes continue the restored log
+        let (proposal, rx) = Proposal::new(record(4.0));
+        proposal_tx.send(proposal).await.unwrap();
+        rx.await.unwrap().unwrap();
+        assert!(status.applied_index() > applied);
+        assert_eq!(restored.get_metric("cpu").await.unwrap(), Some(4.0));
+    }
+
+    #[tokio::test]
+    async fn test_later_commit_wins_timestamp_ties() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let (proposal_tx, _status) = start_raft_node(1, vec![1], registry.clone(), RaftConfig::default()).unwrap();
+
+        // Equal timestamps go to the later commit; an older timestamp
+        // committed last still loses
+        let (first, first_rx) = Proposal::new(MetricOperation::record("cpu", 1.0, 500, Labels::new()));
+        let (second, second_rx) = Proposal::new(MetricOperation::record("cpu", 2.0, 500, Labels::new()));
+        let (third, third_rx) = Proposal::new(MetricOperation::record("cpu", 3.0, 400, Labels::new()));
+        proposal_tx.send(first).await.unwrap();
+        proposal_tx.send(second).await.unwrap();
+        proposal_tx.send(third).await.unwrap();
+        first_rx.await.unwrap().unwrap();
+        second_rx.await.unwrap().unwrap();
+        third_rx.await.unwrap().unwrap();
+
+        assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+        registry.recover().await.unwrap();
+        assert_eq!(registry.get_metric("cpu").await.unwrap(), Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_deposed_leader_fails_writes_in_flight() {
+        let network = LocalNetwork::new();
+        let nodes: Vec<_> = (1..=3)
+            .map(|id| {
+                let registry = Arc::new(MetricsRegistry::new().unwrap());
+                let (proposal_tx, status) =
+                    start_networked_raft_node(id, vec![1, 2, 3], vec![], registry.clone(), RaftConfig::default(), network.clone()).unwrap();
+                (proposal_tx, status, registry)
+            })
+            .collect();
+        let leader_other_than = |excluded: Option<usize>| {
+            nodes.iter().enumerate().position(|(i, (_, status, _))| Some(i) != excluded && status.role() == RaftRole::Leader)
+        };
+        let record = |name: &str, value: f64| MetricOperation::record(name, value, 1, Labels::new());
+        let mut old = None;
+        for _ in 0..100 {
+            old = leader_other_than(None);
+            if old.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let old = old.expect("no leader elected");
+
+        // Cut off, the old leader keeps appending writes it cannot commit
+        network.disconnect(old as u64 + 1);
+        let mut waiters = Vec::new();
+        for i in 0..5 {
+            let (proposal, rx) = Proposal::new(record("fenced", i as f64));
+            nodes[old].0.send(proposal).await.unwrap();
+            waiters.push(rx);
+        }
+        let mut new = None;
+        for _ in 0..100 {
+            new = leader_other_than(Some(old));
+            if new.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let new = new.expect("no new leader elected");
+        let (proposal, rx) = Proposal::new(record("moved_on", 1.0));
+        nodes[new].0.send(proposal).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        // Back in the group it steps down and fails every write in flight
+        network.reconnect(old as u64 + 1);
+        for rx in waiters {
+            let outcome = tokio::time::timeout(Duration::from_secs(5), rx).await.expect("write hung past the leadership change");
+            assert!(matches!(outcome.unwrap(), Err(RaftMetricsError::LeadershipLost { .. })));
+        }
+        assert_ne!(nodes[old].1.role(), RaftRole::Leader);
+        for _ in 0..50 {
+            if nodes[old].2.get_metric("moved_on").await.unwrap().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        for (_, _, registry) in &nodes {
+            assert_eq!(registry.get_metric("moved_on").await.unwrap(), Some(1.0));
+            assert_eq!(registry.get_metric("fenced").await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_leader_steps_down_while_it_cannot_apply() {
+        let registry = Arc::new(MetricsRegistry::new().unwrap());
+        let (proposal_tx, status) = start_raft_node(1, vec![1], registry.clone(), RaftConfig::default()).unwrap();
+        let record = |value: f64| MetricOperation::record("disk", value, value as i64, Labels::new());
+        let until_leading = |leading: bool| {
+            let status = status.clone();
+            async move {
+                for _ in 0..100 {
+                    if (status.role() == RaftRole::Leader) == leading {
+                        return true;
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                false
+            }
+        };
+        assert_eq!(status.role(), RaftRole::Leader);
+        let stepdowns = RAFT_FORCED_STEPDOWNS_TOTAL.get();
+
+        // The third of four failed attempts steps the leader down
+        registry.fail_next_applies(4);
+        let (proposal, rx) = Proposal::new(record(1.0));
+        proposal_tx.send(proposal).await.unwrap();
+        assert!(until_leading(false).await, "the leader kept leading while it could not apply");
+        assert!(RAFT_FORCED_STEPDOWNS_TOTAL.get() > stepdowns);
+
+        // Once storage works the entry applies and the node is elected again
+        tokio::time::timeout(Duration::from_secs(5), rx).await.unwrap().unwrap().unwrap();
+        assert_eq!(registry.get_metric("disk").await.unwrap(), Some(1.0));
+        assert!(until_leading(true).await, "the node was not elected again after recovering");
+        let (proposal, rx) = Proposal::new(record(2.0));
+        proposal_tx.send(proposal).await.unwrap();
+        rx.await.unwrap().unwrap();
+        assert_eq!(registry.get_metric("disk").await.unwrap(), Some(2.0));
+    }
+
+    /// Everything a replica serves for `names`, encoded to compare replicas
+    /// byte for byte.
+    async fn replica_state(registry: &MetricsRegistry, names: &[&str]) -> Vec<u8> {
+        let mut state = Vec::new();
+        for name in names {
+            let recent = registry.get_recent(name, 100, None).await.unwrap();
+            let aggregate = registry.get_metric_aggregate(name).await.unwrap().map(|a| (a.count, a.total(), a.min, a.max));
+            state.push(serde_json::json!({ "name": name, "recent": recent, "aggregate": aggregate }));
+        }
+        state.push(serde_json::to_value(registry.storage_stats().await.unwrap()).unwrap());
+        serde_json::to_vec(&state).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_batch_apply_isolates_rejections_and_replicas_agree() {
+        let network = LocalNetwork::new();
+        let config = RaftConfig { batch_window: Duration::from_millis(50), ..RaftConfig::default() };
+        let nodes: Vec<_> = (1..=2)
+            .map(|id| {
+                let registry = Arc::new(MetricsRegistry::new().unwrap().with_max_distinct_metrics(Some(3)));
+                let (proposal_tx, status) =
+                    start_networked_raft_node(id, vec![1, 2], vec![], registry.clone(), config.clone(), network.clone()).unwrap();
+                (proposal_tx, status, registry)
+            })
+            .collect();
+        let mut leader = None;
+        for _ in 0..100 {
+            leader = nodes.iter().position(|(_, status, _)| status.role() == RaftRole::Leader);
+            if leader.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let leader = leader.expect("no leader elected");
+
+        let record = |name: &str, value: f64, timestamp: i64| {
+            MetricOperation::record(name, value, timestamp, Labels::new())
+        };
+        let operations = vec![
+            record("a", 1.0, 1),
+            // A transaction may only hold records, resets and deletes
+            MetricOperation::Transaction(vec![record("b", 1.0, 1), MetricOperation::Clear]),
+            record("b", 2.0, 1),
+            // Nor write a metric twice
+            MetricOperation::Transaction(vec![record("c", 1.0, 1), record("c", 2.0, 2)]),
+            record("c", 3.0, 1),
+            // A fourth metric is past MAX_DISTINCT_METRICS on every replica
+            record("d", 4.0, 1),
+            record("a", 5.0, 2),
+        ];
+        let mut waiters = Vec::new();
+        for operation in operations {
+            let (proposal, rx) = Proposal::new(operation);
+            nodes[leader].0.send(proposal).await.unwrap();
+            waiters.push(rx);
+        }
+        let mut outcomes = Vec::new();
+        for rx in waiters {
+            let outcome = tokio::time::timeout(Duration::from_secs(5), rx).await.unwrap().unwrap();
+            outcomes.push(outcome.map_err(|e| e.to_string()));
+        }
+        let accepted: Vec<bool> = outcomes.iter().map(|outcome| outcome.is_ok()).collect();
+        assert_eq!(accepted, vec![true, false, true, false, true, false, true], "{:?}", outcomes);
+
+        let applied = nodes[leader].2.applied_index();
+        for (_, status, _) in &nodes {
+            for _ in 0..100 {
+                if status.applied_index() >= applied {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }
+        let names = ["a", "b", "c", "d"];
+        let expected = replica_state(&nodes[leader].2, &names).await;
+        for (_, _, registry) in &nodes {
+            assert_eq!(replica_state(registry, &names).await, expected);
+            assert_eq!(registry.get_metric("a").await.unwrap(), Some(5.0));
+            assert_eq!(registry.get_metric("d").await.unwrap(), None);
+        }
+
+        // Replayed on every replica, applied writes are skipped as duplicates
+        // and rejected ones are rejected again, for the same reason
+        for (_, _, registry) in &nodes {
+            let mut replayed = Vec::new();
+            for (_, encoded) in registry.raft_log(0).unwrap().entries {
+                let entry = Entry::decode(&encoded[..]).unwrap();
+                if entry.get_entry_type() == EntryType::EntryNormal && !entry.data.is_empty() {
+                    let results = registry.apply_raft_batch(entry.index, &entry.data).await.unwrap();
+                    replayed.extend(results.into_iter().map(|result| result.map_err(|e| e.to_string())));
                 }
             }
-            
-            node.advance(ready);
+            assert_eq!(replayed, outcomes);
+            assert_eq!(replica_state(registry, &names).await, expected);
         }
     }
 }
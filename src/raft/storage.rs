@@ -27,6 +27,12 @@ impl MemStorage {
     }
 }
 
+impl Default for MemStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Storage for MemStorage {
     fn initial_state(&self) -> raft::Result<RaftState> {
         let hs = self.hard_state.lock().map_err(|e| 
@@ -0,0 +1,340 @@
+//! Offline replay of a worker's persisted raft log into a fresh registry,
+//! for finding out how a metric came to hold its value.
+
+use prost::Message as _;
+use raft::eraftpb::{Entry, EntryType, HardState};
+use serde::Serialize;
+#[cfg(feature = "duckdb-storage")]
+use std::io::Write;
+
+use crate::{
+    metrics::{MetricAggregate, MetricOperation, MetricsRegistry, RaftLogState},
+    RaftMetricsError, Result,
+};
+
+pub const USAGE: &str = "usage: replay <DB_PATH> [--to INDEX] [--trace METRIC] [--out DUCKDB_FILE]";
+
+/// Aggregates read per page when dumping the replayed state.
+const DUMP_PAGE: usize = 1000;
+
+/// What `replay` was asked to do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayOptions {
+    /// The worker's DuckDB store, which holds its raft log.
+    pub store: String,
+    /// Last index applied; the commit index when unset.
+    pub target: Option<u64>,
+    /// Prints each entry touching this metric instead of the final state.
+    pub trace: Option<String>,
+    /// Replays into this DuckDB file instead of memory.
+    pub output: Option<String>,
+}
+
+impl ReplayOptions {
+    /// Reads the arguments after `replay`.
+    pub fn parse(args: &[String]) -> std::result::Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| args.next().cloned().ok_or_else(|| format!("{} needs a value", flag));
+            match arg.as_str() {
+                "--to" => {
+                    let index = value("--to")?;
+                    options.target = Some(index.parse().map_err(|_| format!("--to '{}' is not a log index", index))?);
+                }
+                "--trace" => options.trace = Some(value("--trace")?),
+                "--out" => options.output = Some(value("--out")?),
+                flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+                store if options.store.is_empty() => options.store = store.to_string(),
+                extra => return Err(format!("unexpected argument '{}'", extra)),
+            }
+        }
+        if options.store.is_empty() {
+            return Err("the store to replay is required".to_string());
+        }
+        if options.output.as_ref() == Some(&options.store) {
+            return Err("--out must not be the store being replayed".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// What a replay went through.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReplaySummary {
+    /// First index in the log; earlier entries were compacted away and
+    /// are missing from the replayed state.
+    pub first_index: u64,
+    pub commit_index: u64,
+    /// Last index applied.
+    pub applied_index: u64,
+    pub entries: u64,
+    pub operations: u64,
+    /// Operations the state machine refused, as the live node did.
+    pub rejected: u64,
+}
+
+/// A metric's aggregate after replay. `first_seen` and `last_seen` are
+/// left out, as they come from the applying node's clock.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayedMetric {
+    pub name: String,
+    pub count: u64,
+    pub sum: f64,
+    pub average: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ReplayedMetric {
+    pub fn new(name: String, aggregate: &MetricAggregate) -> Self {
+        Self {
+            name,
+            count: aggregate.count,
+            sum: aggregate.total(),
+            average: aggregate.average,
+            min: aggregate.min,
+            max: aggregate.max,
+        }
+    }
+}
+
+/// An operation on the traced metric and how it was applied.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TracedOperation {
+    pub operation: MetricOperation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An entry touching the traced metric, with its aggregate either side.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceStep {
+    pub index: u64,
+    pub operations: Vec<TracedOperation>,
+    pub before: Option<ReplayedMetric>,
+    pub after: Option<ReplayedMetric>,
+}
+
+/// Whether `operation` changes `name`: directly, in a transaction, or by
+/// clearing every metric.
+fn touches(operation: &MetricOperation, name: &str) -> bool {
+    match operation {
+        MetricOperation::Transaction(operations) => operations.iter().any(|operation| touches(operation, name)),
+        MetricOperation::Keyed { operation, .. } => touches(operation, name),
+        MetricOperation::Clear => true,
+        operation => operation.name() == Some(name),
+    }
+}
+
+async fn replayed(registry: &MetricsRegistry, name: &str) -> Result<Option<ReplayedMetric>> {
+    let aggregate = registry.get_metric_aggregate(name).await?;
+    Ok(aggregate.map(|aggregate| ReplayedMetric::new(name.to_string(), &aggregate)))
+}
+
+/// Applies the committed entries of `log` up to `target` to `registry`,
+/// through [`MetricsRegistry::apply_raft_batch`] as the live raft loop
+/// does, handing each entry touching `trace` to `on_step`. A failure that
+/// would halt the live node ends the replay.
+pub async fn replay_log(
+    log: &RaftLogState,
+    registry: &MetricsRegistry,
+    target: Option<u64>,
+    trace: Option<&str>,
+    mut on_step: impl FnMut(TraceStep),
+) -> Result<ReplaySummary> {
+    let invalid = |message: String| Err(RaftMetricsError::InvalidRequest(message));
+    let hard_state = match &log.hard_state {
+        Some(encoded) => HardState::decode(&encoded[..])
+            .map_err(|e| RaftMetricsError::Internal(format!("Unreadable raft hard state: {}", e)))?,
+        None => HardState::default(),
+    };
+    let mut summary = ReplaySummary {
+        first_index: log.entries.first().map_or(0, |(index, _)| *index),
+        commit_index: hard_state.commit,
+        ..ReplaySummary::default()
+    };
+    let target = target.unwrap_or(summary.commit_index);
+    if target > summary.commit_index {
+        return invalid(format!("index {} is not committed; the log is committed up to {}", target, summary.commit_index));
+    }
+    if target < summary.first_index {
+        return invalid(format!("index {} was compacted away; the log starts at {}", target, summary.first_index));
+    }
+
+    for (index, encoded) in log.entries.iter().take_while(|(index, _)| *index <= target) {
+        let entry = Entry::decode(&encoded[..])
+            .map_err(|e| RaftMetricsError::Internal(format!("Unreadable raft entry {}: {}", index, e)))?;
+        summary.entries += 1;
+        summary.applied_index = entry.index;
+        if entry.data.is_empty() || entry.get_entry_type() != EntryType::EntryNormal {
+            registry.set_applied_index(entry.index).await?;
+            continue;
+        }
+
+        let operations = MetricsRegistry::deserialize_batch(&entry.data)?;
+        let traced = trace.filter(|name| operations.iter().any(|operation| touches(operation, name)));
+        let before = match traced {
+            Some(name) => replayed(registry, name).await?,
+            None => None,
+        };
+        let results = registry.apply_raft_batch(entry.index, &entry.data).await?;
+        registry.set_applied_index(entry.index).await?;
+        summary.operations += results.len() as u64;
+        summary.rejected += results.iter().filter(|result| result.is_err()).count() as u64;
+
+        if let Some(name) = traced {
+            let operations = operations
+                .into_iter()
+                .zip(results)
+                .filter(|(operation, _)| touches(operation, name))
+                .map(|(operation, result)| TracedOperation { operation, error: result.err().map(|e| e.to_string()) })
+                .collect();
+            on_step(TraceStep { index: entry.index, operations, before, after: replayed(registry, name).await? });
+        }
+    }
+    Ok(summary)
+}
+
+/// Every metric in `registry`, in name order.
+pub async fn dump(registry: &MetricsRegistry) -> Result<Vec<ReplayedMetric>> {
+    let mut metrics = Vec::new();
+    loop {
+        let page = registry.get_all_aggregates(metrics.len(), DUMP_PAGE).await?;
+        let done = page.len() < DUMP_PAGE;
+        metrics.extend(page.into_iter().map(|(name, aggregate)| ReplayedMetric::new(name, &aggregate)));
+        if done {
+            return Ok(metrics);
+        }
+    }
+}
+
+#[cfg(feature = "duckdb-storage")]
+fn print_line(out: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    let line = serde_json::to_string(value).map_err(|e| RaftMetricsError::Internal(e.to_string()))?;
+    writeln!(out, "{}", line).map_err(|e| RaftMetricsError::Internal(format!("Failed to write output: {}", e)))
+}
+
+/// Replays the store `options` names into a registry configured like a
+/// worker from the environment, printing JSON lines to `out`: the trace
+/// steps with `--trace`, otherwise every replayed metric unless `--out`
+/// keeps them. Refused while a worker has the store open.
+#[cfg(feature = "duckdb-storage")]
+pub async fn run(options: &ReplayOptions, out: &mut impl Write) -> Result<ReplaySummary> {
+    use crate::metrics::storage::{DuckDbBackend, MetricStorageBackend as _, StorageBackendKind};
+
+    let log = DuckDbBackend::open_read_only(&options.store)?.raft_log(0)?;
+    let backend = match &options.output {
+        Some(path) => StorageBackendKind::DuckDb.open(Some(path))?,
+        None => StorageBackendKind::Memory.open(None)?,
+    };
+    let registry = crate::api::worker::configure_from_env(MetricsRegistry::with_backend(backend)?);
+
+    let mut steps = Vec::new();
+    let summary = replay_log(&log, &registry, options.target, options.trace.as_deref(), |step| steps.push(step)).await?;
+    registry.flush_aggregates().await?;
+    if options.trace.is_some() {
+        for step in &steps {
+            print_line(out, step)?;
+        }
+    } else if options.output.is_none() {
+        for metric in dump(&registry).await? {
+            print_line(out, &metric)?;
+        }
+    }
+    Ok(summary)
+}
+
+/// Runs `replay` with the arguments after it, returning the exit code.
+pub async fn main(args: &[String]) -> i32 {
+    let options = match ReplayOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    #[cfg(feature = "duckdb-storage")]
+    let replayed = run(&options, &mut std::io::stdout().lock()).await;
+    #[cfg(not(feature = "duckdb-storage"))]
+    let replayed: Result<ReplaySummary> = Err(RaftMetricsError::InvalidRequest(
+        "only DuckDB stores keep their raft log; build with the duckdb-storage feature".to_string(),
+    ));
+    match replayed {
+        Ok(summary) => {
+            if summary.first_index > 1 {
+                eprintln!("The log starts at index {}; state written before it is not replayed", summary.first_index);
+            }
+            eprintln!(
+                "Replayed {} entries ({} operations, {} rejected) up to index {} of {} committed",
+                summary.entries, summary.operations, summary.rejected, summary.applied_index, summary.commit_index
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Replay of {} failed: {}", options.store, e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::RaftConfig, metrics::Labels, raft::node::{start_raft_node, Proposal}};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_options_parse() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let options = ReplayOptions::parse(&args(&["/data/worker.db", "--to", "40", "--trace", "cpu"])).unwrap();
+        assert_eq!(options, ReplayOptions {
+            store: "/data/worker.db".to_string(),
+            target: Some(40),
+            trace: Some("cpu".to_string()),
+            output: None,
+        });
+        assert!(ReplayOptions::parse(&args(&[])).is_err());
+        assert!(ReplayOptions::parse(&args(&["a.db", "--to", "last"])).is_err());
+        assert!(ReplayOptions::parse(&args(&["a.db", "--out", "a.db"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_matches_the_live_worker() {
+        let live = Arc::new(MetricsRegistry::new().unwrap());
+        let (proposal_tx, status) = start_raft_node(1, vec![1], live.clone(), RaftConfig::default()).unwrap();
+        let operations = [
+            MetricOperation::record("cpu", 1.0, 100, Labels::new()),
+            MetricOperation::record("mem", 5.0, 100, Labels::new()),
+            MetricOperation::record("cpu", 3.0, 200, Labels::new()),
+            MetricOperation::Delete { name: "mem".into(), deleted_at: 150 },
+            MetricOperation::record("cpu", 2.0, 300, Labels::new()),
+        ];
+        for operation in operations {
+            let (proposal, rx) = Proposal::new(operation);
+            proposal_tx.send(proposal).await.unwrap();
+            rx.await.unwrap().unwrap();
+        }
+        let log = live.raft_log(0).unwrap();
+
+        let replica = MetricsRegistry::new().unwrap();
+        let mut steps = Vec::new();
+        let summary = replay_log(&log, &replica, None, Some("cpu"), |step| steps.push(step)).await.unwrap();
+        assert_eq!(summary.applied_index, status.applied_index());
+        assert_eq!(summary.operations, 5);
+        assert_eq!(dump(&replica).await.unwrap(), dump(&live).await.unwrap());
+        assert_eq!(replica.get_metric("cpu").await.unwrap(), Some(2.0));
+
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].before, None);
+        let after = steps[1].after.as_ref().unwrap();
+        assert_eq!((after.count, after.sum, after.max), (2, 4.0, 3.0));
+        assert_eq!(steps[2].before.as_ref(), Some(after));
+
+        // Stopping at the second cpu write leaves the third out
+        let partial = MetricsRegistry::new().unwrap();
+        replay_log(&log, &partial, Some(steps[1].index), None, |_| {}).await.unwrap();
+        assert_eq!(partial.get_metric_aggregate("cpu").await.unwrap().unwrap().count, 2);
+        assert!(replay_log(&log, &partial, Some(summary.commit_index + 1), None, |_| {}).await.is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use axum::http::HeaderMap;
+use tracing::Span;
+
+/// OTLP collector traces are exported to. Unset, nothing is exported.
+pub const ENDPOINT_KEY: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+/// Share of new traces sampled, from 0 to 1. Requests arriving with a
+/// `traceparent` follow the caller's decision instead.
+pub const SAMPLE_RATIO_KEY: &str = "OTEL_TRACES_SAMPLER_ARG";
+/// Name traces are reported under; defaults to `raftmetrics-<node type>`.
+pub const SERVICE_NAME_KEY: &str = "OTEL_SERVICE_NAME";
+/// Longest metric name put on a span as is; longer ones are cut short so
+/// generated names cannot flood the trace backend with distinct values.
+pub const MAX_METRIC_ATTR: usize = 64;
+
+/// Where and how much a node traces. Only used with the `otel` feature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryConfig {
+    pub endpoint: Option<String>,
+    pub sample_ratio: f64,
+    pub service_name: String,
+}
+
+impl TelemetryConfig {
+    pub fn from_env(node_type: &str) -> Self {
+        Self::from_lookup(node_type, |key| std::env::var(key).ok())
+    }
+
+    /// A ratio outside 0 to 1 is refused at startup; see
+    /// [`crate::config::validate_startup_lookup`].
+    pub fn from_lookup(node_type: &str, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            endpoint: lookup(ENDPOINT_KEY).filter(|endpoint| !endpoint.trim().is_empty()),
+            sample_ratio: lookup(SAMPLE_RATIO_KEY)
+                .and_then(|ratio| ratio.parse::<f64>().ok())
+                .map_or(1.0, |ratio| ratio.clamp(0.0, 1.0)),
+            service_name: lookup(SERVICE_NAME_KEY).unwrap_or_else(|| format!("raftmetrics-{}", node_type)),
+        }
+    }
+}
+
+/// `name` as a span attribute, cut to [`MAX_METRIC_ATTR`] bytes.
+pub fn metric_attr(name: &str) -> &str {
+    if name.len() <= MAX_METRIC_ATTR {
+        return name;
+    }
+    let mut end = MAX_METRIC_ATTR;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use super::*;
+    use opentelemetry::{global, propagation::Extractor, KeyValue};
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::Sampler, Resource};
+    use std::collections::HashMap;
+    use tracing::{warn, Level, Subscriber};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{filter::Targets, registry::LookupSpan, Layer};
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+
+    /// Exports this crate's spans over OTLP when an endpoint is configured,
+    /// and reads and writes W3C `traceparent` headers from then on.
+    pub fn layer<S>(config: &TelemetryConfig) -> Option<impl Layer<S>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let endpoint = config.endpoint.as_ref()?;
+        let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+            .with_trace_config(
+                opentelemetry_sdk::trace::config()
+                    .with_sampler(sampler)
+                    .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())])),
+            )
+            .install_batch(opentelemetry_sdk::runtime::Tokio);
+        let tracer = match tracer {
+            Ok(tracer) => tracer,
+            Err(e) => {
+                warn!("Tracing to {} is disabled: {}", endpoint, e);
+                return None;
+            }
+        };
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let targets = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::INFO);
+        Some(tracing_opentelemetry::layer().with_tracer(tracer).with_filter(targets))
+    }
+
+    pub fn set_parent(span: &Span, headers: &HeaderMap) {
+        let context = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+        span.set_parent(context);
+    }
+
+    pub fn inject(mut request: reqwest::RequestBuilder, span: &Span) -> reqwest::RequestBuilder {
+        let mut carrier = HashMap::new();
+        global::get_text_map_propagator(|propagator| propagator.inject_context(&span.context(), &mut carrier));
+        for (key, value) in carrier {
+            request = request.header(key, value);
+        }
+        request
+    }
+
+    pub fn shutdown() {
+        global::shutdown_tracer_provider();
+    }
+}
+
+#[cfg(feature = "otel")]
+pub use otel::layer;
+
+/// Continues the trace named by the `traceparent` in `headers`, if any, in
+/// `span`. A no-op without the `otel` feature.
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    #[cfg(feature = "otel")]
+    otel::set_parent(span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
+
+/// Adds a `traceparent` naming `span` to `request`, so the receiving node
+/// continues the trace. A no-op without the `otel` feature.
+pub fn inject(request: reqwest::RequestBuilder, span: &Span) -> reqwest::RequestBuilder {
+    #[cfg(feature = "otel")]
+    let request = otel::inject(request, span);
+    #[cfg(not(feature = "otel"))]
+    let _ = span;
+    request
+}
+
+/// Flushes spans not yet exported. A no-op without the `otel` feature.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    otel::shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_defaults_and_metric_names_are_cut_short() {
+        let config = TelemetryConfig::from_lookup("worker", |_| None);
+        assert_eq!(config.endpoint, None);
+        assert_eq!(config.sample_ratio, 1.0);
+        assert_eq!(config.service_name, "raftmetrics-worker");
+
+        let config = TelemetryConfig::from_lookup("control", |key| match key {
+            ENDPOINT_KEY => Some("http://collector:4317".to_string()),
+            SAMPLE_RATIO_KEY => Some("0.25".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.endpoint.as_deref(), Some("http://collector:4317"));
+        assert_eq!(config.sample_ratio, 0.25);
+
+        assert_eq!(metric_attr("cpu"), "cpu");
+        let long = "é".repeat(MAX_METRIC_ATTR);
+        assert!(metric_attr(&long).len() <= MAX_METRIC_ATTR);
+        assert!(long.starts_with(metric_attr(&long)));
+    }
+}
@@ -0,0 +1,127 @@
+//! In-process clusters for integration tests: a control node and workers
+//! served on ephemeral ports of the calling runtime, with a client pointed
+//! at the control node. Enabled by the `test-util` feature.
+
+use axum::Router;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::{
+    api::{
+        control::{control_router, ControlState},
+        serve::serve_split,
+        worker::{worker_router, WorkerState},
+    },
+    config::{ControlConfig, RaftConfig},
+    metrics::MetricsRegistry,
+    RaftMetricsError, Result,
+};
+
+pub use crate::client::RaftMetricsClient;
+
+/// How long [`TestCluster::start`] waits for the control node to answer.
+const READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A node serving in the background until its shutdown signal fires.
+struct Server {
+    stop: oneshot::Sender<()>,
+    served: JoinHandle<std::io::Result<()>>,
+}
+
+impl Server {
+    /// Serves `router` on an ephemeral local port, returning its base URL.
+    async fn start(router: Router) -> Result<(String, Self)> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| RaftMetricsError::Internal(format!("Failed to bind a test listener: {}", e)))?;
+        let url = format!("http://{}", listener.local_addr().map_err(|e| RaftMetricsError::Internal(e.to_string()))?);
+        let (stop, stopped) = oneshot::channel();
+        let served = tokio::spawn(serve_split(listener, None, router, Router::new(), async {
+            let _ = stopped.await;
+        }));
+        Ok((url, Self { stop, served }))
+    }
+
+    async fn shutdown(self) -> Result<()> {
+        let _ = self.stop.send(());
+        self.served
+            .await
+            .map_err(|e| RaftMetricsError::Internal(format!("Test server panicked: {}", e)))?
+            .map_err(|e| RaftMetricsError::Internal(format!("Test server failed: {}", e)))
+    }
+}
+
+/// A control node and its workers, each a single-voter raft group over an
+/// in-memory store.
+pub struct TestCluster {
+    pub control_url: String,
+    pub worker_urls: Vec<String>,
+    pub control: ControlState,
+    pub workers: Vec<WorkerState>,
+    pub client: RaftMetricsClient,
+    servers: Vec<Server>,
+}
+
+impl TestCluster {
+    /// Starts `workers` workers and a control node partitioning over them,
+    /// returning once the control node answers `/health`.
+    pub async fn start(workers: usize) -> Result<Self> {
+        let mut servers = Vec::with_capacity(workers + 1);
+        let mut worker_states = Vec::with_capacity(workers);
+        let mut worker_urls = Vec::with_capacity(workers);
+        for worker_id in 1..=workers {
+            let state = WorkerState::new(worker_id, Arc::new(MetricsRegistry::new()?), RaftConfig::default())?;
+            let (url, server) = Server::start(worker_router(state.clone())).await?;
+            worker_states.push(state);
+            worker_urls.push(url);
+            servers.push(server);
+        }
+
+        let mut config = ControlConfig::from_lookup(|_| None);
+        config.worker_urls = worker_urls.clone();
+        let control = ControlState::new(config)?;
+        let (control_url, server) = Server::start(control_router(control.clone())).await?;
+        servers.push(server);
+
+        let client = RaftMetricsClient::new(&control_url);
+        let cluster = Self { control_url, worker_urls, control, workers: worker_states, client, servers };
+        cluster.client.wait_until_healthy(READY_TIMEOUT).await?;
+        Ok(cluster)
+    }
+
+    /// Stops every node after the requests it is serving, control node
+    /// first so nothing is forwarded to a worker that has stopped.
+    pub async fn shutdown(mut self) -> Result<()> {
+        while let Some(server) = self.servers.pop() {
+            server.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_three_worker_cluster_records_reads_and_shuts_down() {
+        let cluster = TestCluster::start(3).await.unwrap();
+        assert_eq!(cluster.worker_urls.len(), 3);
+
+        let written = cluster.client.record("testkit_cpu", 42.0).await.unwrap();
+        assert!(written.success, "{}", written.message);
+        let metric = cluster.client.get_metric("testkit_cpu").await.unwrap();
+        assert_eq!(metric.value, 42.0);
+        let owner = cluster.workers.iter().find(|worker| worker.worker_id == metric.worker_id).expect("no worker owns the metric");
+        assert_eq!(owner.metrics.get_metric("testkit_cpu").await.unwrap(), Some(42.0));
+        assert_eq!(cluster.client.aggregate("testkit_cpu").await.unwrap().count, 1);
+        assert!(cluster.client.get_metric("testkit_absent").await.is_err());
+
+        let client = cluster.client.clone();
+        cluster.shutdown().await.unwrap();
+        assert!(client.get_metric("testkit_cpu").await.is_err());
+    }
+}
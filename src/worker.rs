@@ -1,9 +1,10 @@
 use std::env;
-use tracing::info;
-use distributed_analytics_system::api::worker;
+use tracing::{error, info};
+use distributed_analytics_system::api::{info::{self, NodeInfo}, worker};
 
 #[tokio::main]
 async fn main() {
+    info::mark_started();
     // Initialize logging
     tracing_subscriber::fmt::init();
 
@@ -13,6 +14,16 @@ async fn main() {
         .parse()
         .unwrap_or(1);
 
+    if env::args().skip(1).any(|arg| arg == "--check") {
+        print!("{}", NodeInfo { node_id: worker_id, ..NodeInfo::from_env("worker") }.render());
+        let report = worker::preflight_worker_node().await;
+        print!("{}", report.render());
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
     info!("Starting worker node {}", worker_id);
-    worker::start_worker_node(worker_id).await;
+    if let Err(e) = worker::start_worker_node(worker_id).await {
+        error!("Worker node {} failed to start: {}", worker_id, e);
+        std::process::exit(1);
+    }
 }